@@ -1,11 +1,21 @@
 //! Authentication API endpoints.
 
-use axum::{extract::State, Extension, Json};
+use std::net::SocketAddr;
+
+use axum::{
+    extract::{ConnectInfo, Path, State},
+    http::{header, HeaderMap},
+    Extension, Json,
+};
 use serde::{Deserialize, Serialize};
 
-use crate::db::models::UserRole;
+use crate::db::models::{Session, UserRole};
 use crate::error::{AppError, Result};
+use crate::middleware::client_ip;
+use crate::services::activity::{ActivityBuilder, EventType};
+use crate::services::auth::{hash_session_id, revoke_session, revoke_user_sessions};
 use crate::services::Claims;
+use crate::services::{totp, two_factor};
 use crate::AppState;
 
 /// Dummy hash for timing attack prevention.
@@ -25,6 +35,55 @@ pub struct LoginRequest {
 pub struct LoginResponse {
     pub token: String,
     pub user: UserInfo,
+    /// Set for admins who must enrol in 2FA before using the API
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub two_factor_setup_required: bool,
+}
+
+/// Returned instead of a token when the password was right but the user has
+/// 2FA enabled. The challenge token is exchanged at `/api/auth/login/2fa`.
+#[derive(Debug, Serialize)]
+pub struct TwoFactorChallenge {
+    pub two_factor_required: bool,
+    pub challenge_token: String,
+}
+
+/// Outcome of the password step of a login.
+#[derive(Debug, Serialize)]
+#[serde(untagged)]
+pub enum LoginResult {
+    Authenticated(LoginResponse),
+    TwoFactorRequired(TwoFactorChallenge),
+}
+
+/// Second login step request body.
+#[derive(Debug, Deserialize)]
+pub struct TwoFactorLoginRequest {
+    pub challenge_token: String,
+    /// TOTP code or recovery code
+    pub code: String,
+}
+
+/// Request body carrying a second-factor code.
+#[derive(Debug, Deserialize)]
+pub struct TwoFactorCodeRequest {
+    /// TOTP code, or a recovery code where noted
+    pub code: String,
+}
+
+/// A new TOTP secret to add to an authenticator app.
+#[derive(Debug, Serialize)]
+pub struct TwoFactorSetupResponse {
+    pub secret: String,
+    pub otpauth_uri: String,
+    /// The otpauth URI as an SVG QR code
+    pub qr_svg: Option<String>,
+}
+
+/// Newly issued recovery codes, only shown once.
+#[derive(Debug, Serialize)]
+pub struct RecoveryCodesResponse {
+    pub recovery_codes: Vec<String>,
 }
 
 /// User information returned in responses (without password hash).
@@ -41,13 +100,27 @@ pub struct SuccessResponse {
     pub message: String,
 }
 
+/// A login session of the current user.
+#[derive(Debug, Serialize)]
+pub struct SessionInfo {
+    #[serde(flatten)]
+    pub session: Session,
+    /// Whether this is the session making the request.
+    pub current: bool,
+}
+
 /// POST /api/auth/login
 ///
-/// Authenticates a user and returns a JWT token.
+/// Authenticates a user and returns a JWT token, or a challenge for the
+/// second step if the user has 2FA enabled.
+///
+/// Repeated failures are throttled per client IP and username.
 pub async fn login(
     State(state): State<AppState>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
     Json(body): Json<LoginRequest>,
-) -> Result<Json<LoginResponse>> {
+) -> Result<Json<LoginResult>> {
     // Validate input
     if body.username.is_empty() || body.password.is_empty() {
         return Err(AppError::BadRequest(
@@ -55,6 +128,14 @@ pub async fn login(
         ));
     }
 
+    let ip = client_ip(
+        connect_info.map(|ConnectInfo(addr)| addr.ip()),
+        &headers,
+        &state.config.server.trusted_proxies,
+    );
+    let throttle = state.login_throttle();
+    let attempt = throttle.begin_attempt(ip, &body.username)?;
+
     let db = state.db.lock().await;
     let auth_service = state.auth_service();
 
@@ -86,35 +167,124 @@ pub async fn login(
         Err(rusqlite::Error::QueryReturnedNoRows) => {
             // Perform dummy verification to prevent timing attack
             let _ = auth_service.verify_password(&body.password, DUMMY_HASH);
+            throttle.login_failed(&db, ip, &body.username, attempt);
             return Err(AppError::Unauthorized);
         }
         Err(e) => return Err(AppError::Sqlite(e)),
     };
 
     if !authenticated {
+        throttle.login_failed(&db, ip, &body.username, attempt);
+        return Err(AppError::Unauthorized);
+    }
+
+    // The attempt stays counted until the second factor is verified too
+    if two_factor::is_enabled(&db, user_id)? {
+        tracing::debug!(
+            user_id = user_id,
+            "Password accepted, second factor required"
+        );
+        return Ok(Json(LoginResult::TwoFactorRequired(TwoFactorChallenge {
+            two_factor_required: true,
+            challenge_token: auth_service.create_challenge(user_id)?,
+        })));
+    }
+
+    throttle.record_success(ip, &body.username);
+
+    let response = issue_token(&state, &db, &headers, user_id, username, role)?;
+    Ok(Json(LoginResult::Authenticated(response)))
+}
+
+/// POST /api/auth/login/2fa
+///
+/// Second login step: exchanges a challenge and a TOTP or recovery code for a
+/// JWT token. Failures count towards login throttling.
+pub async fn login_two_factor(
+    State(state): State<AppState>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+    Json(body): Json<TwoFactorLoginRequest>,
+) -> Result<Json<LoginResponse>> {
+    let auth_service = state.auth_service();
+    let user_id = auth_service.verify_challenge(&body.challenge_token)?;
+
+    let db = state.db.lock().await;
+    let (username, role): (String, String) = db
+        .query_row(
+            "SELECT username, role FROM users WHERE id = ?1",
+            [user_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .map_err(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => AppError::Unauthorized,
+            _ => AppError::Sqlite(e),
+        })?;
+
+    let ip = client_ip(
+        connect_info.map(|ConnectInfo(addr)| addr.ip()),
+        &headers,
+        &state.config.server.trusted_proxies,
+    );
+    let throttle = state.login_throttle();
+    let attempt = throttle.begin_attempt(ip, &username)?;
+
+    if !two_factor::verify_code(&db, user_id, &body.code, two_factor::unix_now())? {
+        throttle.login_failed(&db, ip, &username, attempt);
         return Err(AppError::Unauthorized);
     }
 
-    // Create JWT token (stateless - no session storage needed)
-    let token = auth_service.create_token(user_id, &role.to_string())?;
+    throttle.record_success(ip, &username);
+
+    let role = match role.as_str() {
+        "admin" => UserRole::Admin,
+        _ => UserRole::User,
+    };
+    Ok(Json(issue_token(
+        &state, &db, &headers, user_id, username, role,
+    )?))
+}
+
+/// Creates the token and session of a user who passed every login step.
+fn issue_token(
+    state: &AppState,
+    db: &rusqlite::Connection,
+    headers: &HeaderMap,
+    user_id: i64,
+    username: String,
+    role: UserRole,
+) -> Result<LoginResponse> {
+    let user_agent = headers
+        .get(header::USER_AGENT)
+        .and_then(|v| v.to_str().ok());
+    let token = state
+        .auth_service()
+        .create_token(db, user_id, &role.to_string(), user_agent)?;
+    let two_factor_setup_required = two_factor::setup_required(db, user_id, &role.to_string())?;
 
     tracing::info!(user_id = user_id, username = %username, "User logged in");
 
-    Ok(Json(LoginResponse {
+    Ok(LoginResponse {
         token,
         user: UserInfo {
             id: user_id,
             username,
             role,
         },
-    }))
+        two_factor_setup_required,
+    })
 }
 
 /// POST /api/auth/logout
 ///
-/// Logs out the user. Since we use stateless JWT tokens, this is a no-op
-/// on the server side. The client should discard the token.
-pub async fn logout(Extension(claims): Extension<Claims>) -> Result<Json<SuccessResponse>> {
+/// Logs out the user by revoking the session of the token used.
+pub async fn logout(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+) -> Result<Json<SuccessResponse>> {
+    let db = state.db.lock().await;
+    revoke_session(&db, &claims.jti)?;
+
     tracing::info!(user_id = claims.sub, "User logged out");
 
     Ok(Json(SuccessResponse {
@@ -122,6 +292,99 @@ pub async fn logout(Extension(claims): Extension<Claims>) -> Result<Json<Success
     }))
 }
 
+/// POST /api/auth/logout-all
+///
+/// Logs out the user everywhere by revoking all of their sessions, including
+/// the one making the request.
+pub async fn logout_all(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+) -> Result<Json<SuccessResponse>> {
+    let db = state.db.lock().await;
+    let revoked = revoke_user_sessions(&db, claims.sub)?;
+
+    tracing::info!(
+        user_id = claims.sub,
+        sessions = revoked,
+        "User logged out everywhere"
+    );
+
+    Ok(Json(SuccessResponse {
+        message: format!("Logged out of {} sessions", revoked),
+    }))
+}
+
+/// GET /api/auth/sessions
+///
+/// Lists the current user's active sessions, most recently used first.
+pub async fn list_sessions(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+) -> Result<Json<Vec<SessionInfo>>> {
+    let db = state.db.lock().await;
+    let current_hash = hash_session_id(&claims.jti);
+
+    let mut stmt = db.prepare(
+        r#"
+        SELECT id, user_id, token_hash, expires_at, created_at, user_agent, last_used_at
+        FROM sessions
+        WHERE user_id = ?1 AND expires_at > datetime('now')
+        ORDER BY COALESCE(last_used_at, created_at) DESC, id DESC
+        "#,
+    )?;
+
+    let sessions = stmt
+        .query_map([claims.sub], |row| {
+            Ok(Session {
+                id: row.get(0)?,
+                user_id: row.get(1)?,
+                token_hash: row.get(2)?,
+                expires_at: row.get(3)?,
+                created_at: row.get(4)?,
+                user_agent: row.get(5)?,
+                last_used_at: row.get(6)?,
+            })
+        })?
+        .map(|session| {
+            session.map(|session| SessionInfo {
+                current: session.token_hash == current_hash,
+                session,
+            })
+        })
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+
+    Ok(Json(sessions))
+}
+
+/// DELETE /api/auth/sessions/:id
+///
+/// Revokes one of the current user's sessions.
+pub async fn delete_session(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Path(session_id): Path<i64>,
+) -> Result<Json<SuccessResponse>> {
+    let db = state.db.lock().await;
+
+    let deleted = db.execute(
+        "DELETE FROM sessions WHERE id = ?1 AND user_id = ?2",
+        rusqlite::params![session_id, claims.sub],
+    )?;
+    if deleted == 0 {
+        return Err(AppError::NotFound("Session not found".to_string()));
+    }
+
+    tracing::info!(
+        user_id = claims.sub,
+        session_id = session_id,
+        "Session revoked"
+    );
+
+    Ok(Json(SuccessResponse {
+        message: "Session revoked".to_string(),
+    }))
+}
+
 /// GET /api/auth/me
 ///
 /// Returns the current authenticated user's information.
@@ -157,3 +420,131 @@ pub async fn me(
 
     Ok(Json(user))
 }
+
+/// GET /api/auth/2fa
+///
+/// Returns the current user's 2FA state.
+pub async fn two_factor_status(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+) -> Result<Json<two_factor::TwoFactorStatus>> {
+    let db = state.db.lock().await;
+    Ok(Json(two_factor::status(&db, claims.sub)?))
+}
+
+/// POST /api/auth/2fa/setup
+///
+/// Starts 2FA enrolment with a new secret. 2FA is not enabled until a code
+/// from it is confirmed at `/api/auth/2fa/enable`.
+pub async fn two_factor_setup(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+) -> Result<Json<TwoFactorSetupResponse>> {
+    let db = state.db.lock().await;
+    let secret = two_factor::begin_enrolment(&db, claims.sub)?;
+    let username: String = db.query_row(
+        "SELECT username FROM users WHERE id = ?1",
+        [claims.sub],
+        |row| row.get(0),
+    )?;
+
+    let otpauth_uri = totp::otpauth_uri("LCARS", &username, &secret);
+    Ok(Json(TwoFactorSetupResponse {
+        qr_svg: totp::qr_svg(&otpauth_uri),
+        secret,
+        otpauth_uri,
+    }))
+}
+
+/// POST /api/auth/2fa/enable
+///
+/// Confirms enrolment with a TOTP code and returns the recovery codes.
+pub async fn two_factor_enable(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Json(body): Json<TwoFactorCodeRequest>,
+) -> Result<Json<RecoveryCodesResponse>> {
+    let db = state.db.lock().await;
+    let recovery_codes =
+        two_factor::confirm_enrolment(&db, claims.sub, &body.code, two_factor::unix_now())?;
+
+    ActivityBuilder::new(
+        EventType::TwoFactorEnabled,
+        "Two-factor authentication enabled",
+    )
+    .user(claims.sub)
+    .log_sync(&db);
+    tracing::info!(user_id = claims.sub, "Two-factor authentication enabled");
+
+    Ok(Json(RecoveryCodesResponse { recovery_codes }))
+}
+
+/// POST /api/auth/2fa/disable
+///
+/// Turns 2FA off after checking a TOTP or recovery code. Not allowed when the
+/// user's role requires 2FA.
+pub async fn two_factor_disable(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Json(body): Json<TwoFactorCodeRequest>,
+) -> Result<Json<SuccessResponse>> {
+    let db = state.db.lock().await;
+
+    let status = two_factor::status(&db, claims.sub)?;
+    if !status.enabled {
+        return Err(AppError::BadRequest(
+            "Two-factor authentication is not enabled".to_string(),
+        ));
+    }
+    if status.required {
+        return Err(AppError::BadRequest(
+            "Two-factor authentication is required for admins".to_string(),
+        ));
+    }
+    if !two_factor::verify_code(&db, claims.sub, &body.code, two_factor::unix_now())? {
+        return Err(AppError::BadRequest(
+            "Invalid verification code".to_string(),
+        ));
+    }
+
+    two_factor::disable(&db, claims.sub)?;
+
+    ActivityBuilder::new(
+        EventType::TwoFactorDisabled,
+        "Two-factor authentication disabled",
+    )
+    .user(claims.sub)
+    .log_sync(&db);
+    tracing::info!(user_id = claims.sub, "Two-factor authentication disabled");
+
+    Ok(Json(SuccessResponse {
+        message: "Two-factor authentication disabled".to_string(),
+    }))
+}
+
+/// POST /api/auth/2fa/recovery-codes
+///
+/// Replaces the recovery codes after checking a TOTP or recovery code.
+pub async fn regenerate_recovery_codes(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Json(body): Json<TwoFactorCodeRequest>,
+) -> Result<Json<RecoveryCodesResponse>> {
+    let db = state.db.lock().await;
+
+    if !two_factor::is_enabled(&db, claims.sub)? {
+        return Err(AppError::BadRequest(
+            "Two-factor authentication is not enabled".to_string(),
+        ));
+    }
+    if !two_factor::verify_code(&db, claims.sub, &body.code, two_factor::unix_now())? {
+        return Err(AppError::BadRequest(
+            "Invalid verification code".to_string(),
+        ));
+    }
+
+    let recovery_codes = two_factor::regenerate_recovery_codes(&db, claims.sub)?;
+    tracing::info!(user_id = claims.sub, "Recovery codes regenerated");
+
+    Ok(Json(RecoveryCodesResponse { recovery_codes }))
+}
@@ -0,0 +1,70 @@
+//! Release blocklist API endpoints.
+//!
+//! The blocklist is listed to every user, and admins can remove entries so a
+//! release may be grabbed again. Entries are added when a download fails,
+//! stalls, or is deleted with `blocklist=true`.
+
+use axum::{
+    extract::{Path, State},
+    response::Json,
+};
+use serde::Serialize;
+
+use crate::error::{AppError, Result};
+use crate::services::indexer::BlocklistEntry;
+use crate::AppState;
+
+/// Generic success response.
+#[derive(Debug, Serialize)]
+pub struct SuccessResponse {
+    pub success: bool,
+    pub message: Option<String>,
+}
+
+/// GET /api/system/blocklist
+///
+/// List blocklisted releases, newest first.
+pub async fn list_blocklist(State(state): State<AppState>) -> Result<Json<Vec<BlocklistEntry>>> {
+    let db = state.db.lock().await;
+    Ok(Json(BlocklistEntry::load_all(&db)?))
+}
+
+/// DELETE /api/system/blocklist/:id
+///
+/// Remove a release from the blocklist.
+pub async fn delete_blocklist_entry(
+    State(state): State<AppState>,
+    Path(entry_id): Path<i64>,
+) -> Result<Json<SuccessResponse>> {
+    let db = state.db.lock().await;
+
+    let rows_affected = db.execute("DELETE FROM blocklist WHERE id = ?1", [entry_id])?;
+    if rows_affected == 0 {
+        return Err(AppError::NotFound("Blocklist entry not found".to_string()));
+    }
+    state.indexer_manager().reload_blocklist(&db)?;
+
+    tracing::info!(entry_id = entry_id, "Removed release from blocklist");
+
+    Ok(Json(SuccessResponse {
+        success: true,
+        message: Some("Release removed from blocklist".to_string()),
+    }))
+}
+
+/// DELETE /api/system/blocklist
+///
+/// Clear the blocklist.
+pub async fn clear_blocklist(State(state): State<AppState>) -> Result<Json<SuccessResponse>> {
+    let db = state.db.lock().await;
+
+    let removed = db.execute("DELETE FROM blocklist", [])?;
+    state.indexer_manager().reload_blocklist(&db)?;
+
+    tracing::info!(removed = removed, "Cleared blocklist");
+
+    Ok(Json(SuccessResponse {
+        success: true,
+        message: Some(format!("Removed {} releases from blocklist", removed)),
+    }))
+}
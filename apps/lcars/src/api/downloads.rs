@@ -3,15 +3,20 @@
 use axum::{
     extract::{Path, Query, State},
     middleware as axum_mw,
-    routing::{get, post},
+    routing::{get, post, put},
     Json, Router,
 };
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
 use serde::{Deserialize, Serialize};
 
 use crate::db::models::{Download, DownloadSource, DownloadStatus, MediaType};
 use crate::error::{AppError, Result};
 use crate::middleware;
-use crate::services::torrent::MediaRef;
+use crate::services::bandwidth::SpeedLimits;
+use crate::services::download_queue;
+use crate::services::indexer::blocklist::{block_download, BlocklistReason};
+use crate::services::torrent::TorrentListing;
+use crate::services::torrent_source::TorrentSource;
 use crate::AppState;
 
 // =============================================================================
@@ -32,6 +37,79 @@ pub struct ListDownloadsQuery {
 pub struct DeleteDownloadQuery {
     /// Whether to delete downloaded files (default: false).
     pub delete_files: Option<bool>,
+    /// Whether to blocklist the release, so it is never grabbed again
+    /// (default: false). Only supported for torrent downloads.
+    pub blocklist: Option<bool>,
+}
+
+/// Torrent given in a request: a magnet link, the URL of a .torrent file, or
+/// the base64-encoded contents of a .torrent file. Exactly one must be set.
+#[derive(Debug, Default, Deserialize)]
+pub struct TorrentSourceRequest {
+    /// Magnet link.
+    pub magnet: Option<String>,
+    /// HTTP(S) URL of a .torrent file.
+    pub torrent_url: Option<String>,
+    /// Base64-encoded .torrent file.
+    pub torrent_file: Option<String>,
+}
+
+impl TorrentSourceRequest {
+    /// Whether no source was given.
+    pub fn is_empty(&self) -> bool {
+        self.magnet.is_none() && self.torrent_url.is_none() && self.torrent_file.is_none()
+    }
+
+    /// Validate the request and turn it into a torrent source.
+    ///
+    /// # Errors
+    ///
+    /// Returns a bad request error if zero or several sources are given, or
+    /// if the given one is malformed.
+    pub fn to_source(&self) -> Result<TorrentSource> {
+        match (&self.magnet, &self.torrent_url, &self.torrent_file) {
+            (Some(magnet), None, None) => {
+                if !magnet.starts_with("magnet:?") {
+                    return Err(AppError::BadRequest(
+                        "Invalid magnet link format".to_string(),
+                    ));
+                }
+                Ok(TorrentSource::Magnet(magnet.clone()))
+            }
+            (None, Some(url), None) => match TorrentSource::from_link(url) {
+                Some(source @ TorrentSource::Url(_)) => Ok(source),
+                _ => Err(AppError::BadRequest(
+                    "Invalid .torrent URL: must be http(s)".to_string(),
+                )),
+            },
+            (None, None, Some(file)) => {
+                let bytes = BASE64.decode(file.trim()).map_err(|e| {
+                    AppError::BadRequest(format!("Invalid base64 .torrent file: {}", e))
+                })?;
+                Ok(TorrentSource::File(bytes.into()))
+            }
+            _ => Err(AppError::BadRequest(
+                "Exactly one of magnet, torrent_url or torrent_file is required".to_string(),
+            )),
+        }
+    }
+}
+
+/// Request body for setting a download's own speed limits.
+///
+/// Caps are in KiB/s and apply on top of the global limits; a missing, null or
+/// zero value removes the cap.
+#[derive(Debug, Deserialize)]
+pub struct DownloadLimitsRequest {
+    pub download_limit: Option<u64>,
+    pub upload_limit: Option<u64>,
+}
+
+/// Request body for moving a queued download.
+#[derive(Debug, Deserialize)]
+pub struct QueuePositionRequest {
+    /// New 1-based position in the queue, past the end moves it last.
+    pub position: usize,
 }
 
 /// Success response for operations without specific data.
@@ -50,10 +128,14 @@ pub struct SuccessResponse {
 pub fn router(state: AppState) -> Router<AppState> {
     Router::new()
         .route("/", get(list_downloads))
+        .route("/files", post(list_torrent_files))
+        .route("/queue", get(list_queue))
         .route("/{id}", get(get_download).delete(delete_download))
         .route("/{id}/pause", post(pause_download))
         .route("/{id}/resume", post(resume_download))
         .route("/{id}/retry", post(retry_download))
+        .route("/{id}/limits", put(set_download_limits))
+        .route("/{id}/position", put(move_download))
         .layer(axum_mw::from_fn_with_state(
             state,
             middleware::auth_middleware,
@@ -106,7 +188,8 @@ pub async fn list_downloads(
                    progress, download_speed, upload_speed, size_bytes,
                    downloaded_bytes, uploaded_bytes, ratio, peers, error_message,
                    added_at, started_at, completed_at,
-                   soulseek_username, soulseek_filename, queue_position
+                   soulseek_username, soulseek_filename, queue_position, selected_files,
+               download_limit, upload_limit, queue_order
             FROM downloads
             WHERE (?1 IS NULL OR status = ?1)
               AND (?2 IS NULL OR source_type = ?2)
@@ -153,6 +236,32 @@ pub async fn list_downloads(
     Ok(Json(downloads))
 }
 
+/// POST /api/downloads/files
+///
+/// Resolves a torrent's metadata (from a magnet link, a .torrent URL or an
+/// uploaded .torrent file) and lists its files without downloading anything. The file indexes can be passed to the download endpoints to
+/// fetch only some files.
+pub async fn list_torrent_files(
+    State(state): State<AppState>,
+    Json(body): Json<TorrentSourceRequest>,
+) -> Result<Json<TorrentListing>> {
+    let source = body.to_source()?;
+
+    let torrent_engine = state
+        .torrent_engine()
+        .ok_or_else(|| AppError::Internal("Torrent engine not available".to_string()))?;
+
+    let listing = torrent_engine.list_files(&source).await?;
+
+    tracing::debug!(
+        info_hash = %listing.info_hash,
+        files = listing.files.len(),
+        "Listed torrent files"
+    );
+
+    Ok(Json(listing))
+}
+
 /// GET /api/downloads/:id
 ///
 /// Gets a single download by ID with real-time stats.
@@ -169,7 +278,8 @@ pub async fn get_download(
                    progress, download_speed, upload_speed, size_bytes,
                    downloaded_bytes, uploaded_bytes, ratio, peers, error_message,
                    added_at, started_at, completed_at,
-                   soulseek_username, soulseek_filename, queue_position
+                   soulseek_username, soulseek_filename, queue_position, selected_files,
+               download_limit, upload_limit, queue_order
             FROM downloads WHERE id = ?1
             "#,
             [download_id],
@@ -210,7 +320,8 @@ pub async fn get_download(
 
 /// DELETE /api/downloads/:id
 ///
-/// Removes a download from the appropriate engine and database.
+/// Removes a download from the appropriate engine and database, optionally
+/// blocklisting its release.
 pub async fn delete_download(
     State(state): State<AppState>,
     Path(download_id): Path<i64>,
@@ -253,11 +364,28 @@ pub async fn delete_download(
 
     drop(db); // Release lock before async operations
 
-    // Remove from appropriate engine
+    let blocklist = query.blocklist.unwrap_or(false);
+    if blocklist && source_type != DownloadSource::Torrent {
+        return Err(AppError::BadRequest(
+            "Only torrent downloads can be blocklisted".to_string(),
+        ));
+    }
+
+    // Remove from appropriate engine, queued downloads never reached it
     let delete_files = query.delete_files.unwrap_or(false);
+    let queued = download_queue::is_queued_source_id(&source_id);
+    let mut torrent_name = None;
     match source_type {
+        DownloadSource::Torrent if queued => {}
         DownloadSource::Torrent => {
             if let Some(torrent_engine) = state.torrent_engine() {
+                if blocklist {
+                    torrent_name = torrent_engine
+                        .get_status(&source_id)
+                        .await
+                        .ok()
+                        .map(|s| s.name);
+                }
                 if let Err(e) = torrent_engine.remove(&source_id, delete_files).await {
                     tracing::debug!(
                         source_id = %source_id,
@@ -267,6 +395,7 @@ pub async fn delete_download(
                 }
             }
         }
+        DownloadSource::Soulseek if queued => {}
         DownloadSource::Soulseek => {
             // TODO: Cancel Soulseek download when SoulseekEngine has download tracking
             tracing::debug!(
@@ -279,31 +408,32 @@ pub async fn delete_download(
     // Delete from database and revert media status
     let db = state.db.lock().await;
 
+    if blocklist {
+        block_download(
+            &db,
+            download_id,
+            torrent_name.as_deref(),
+            BlocklistReason::Manual,
+            None,
+        )?;
+        state.indexer_manager().reload_blocklist(&db)?;
+    }
+
     db.execute("DELETE FROM downloads WHERE id = ?1", [download_id])?;
 
-    // Revert media status to 'missing'
-    let update_query = match media_type {
-        MediaType::Movie => {
-            "UPDATE movies SET status = 'missing', updated_at = datetime('now') WHERE id = ?1"
-        }
-        MediaType::Episode => {
-            "UPDATE episodes SET status = 'missing', updated_at = datetime('now') WHERE id = ?1"
-        }
-        MediaType::Album => {
-            "UPDATE albums SET status = 'missing', updated_at = datetime('now') WHERE id = ?1"
-        }
-        MediaType::Track => {
-            "UPDATE tracks SET status = 'missing', updated_at = datetime('now') WHERE id = ?1"
-        }
-    };
+    // Revert media status, to 'missing' unless it still has a file
+    download_queue::reset_media_status(&db, media_type, media_id)?;
+    drop(db);
 
-    db.execute(update_query, [media_id])?;
+    // A slot may have freed up
+    state.download_queue().notify();
 
     tracing::info!(
         download_id = download_id,
         source_type = %source_type,
         source_id = %source_id,
         delete_files = delete_files,
+        blocklist = blocklist,
         "Download deleted"
     );
 
@@ -373,7 +503,8 @@ pub async fn pause_download(
                progress, download_speed, upload_speed, size_bytes,
                downloaded_bytes, uploaded_bytes, ratio, peers, error_message,
                added_at, started_at, completed_at,
-               soulseek_username, soulseek_filename, queue_position
+               soulseek_username, soulseek_filename, queue_position, selected_files,
+               download_limit, upload_limit, queue_order
         FROM downloads WHERE id = ?1
         "#,
         [download_id],
@@ -449,7 +580,8 @@ pub async fn resume_download(
                progress, download_speed, upload_speed, size_bytes,
                downloaded_bytes, uploaded_bytes, ratio, peers, error_message,
                added_at, started_at, completed_at,
-               soulseek_username, soulseek_filename, queue_position
+               soulseek_username, soulseek_filename, queue_position, selected_files,
+               download_limit, upload_limit, queue_order
         FROM downloads WHERE id = ?1
         "#,
         [download_id],
@@ -467,7 +599,7 @@ pub async fn resume_download(
 
 /// POST /api/downloads/:id/retry
 ///
-/// Retries a failed download by re-adding it to the appropriate engine.
+/// Retries a failed download by putting it back in the download queue.
 pub async fn retry_download(
     State(state): State<AppState>,
     Path(download_id): Path<i64>,
@@ -475,11 +607,11 @@ pub async fn retry_download(
     let db = state.db.lock().await;
 
     // Get download info
-    let (source_type_str, source_id, source_uri, media_type_str, media_id): (String, String, String, String, i64) = db
+    let (source_type_str, source_id): (String, String) = db
         .query_row(
-            "SELECT source_type, source_id, source_uri, media_type, media_id FROM downloads WHERE id = ?1",
+            "SELECT source_type, source_id FROM downloads WHERE id = ?1",
             [download_id],
-            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?, row.get(4)?)),
+            |row| Ok((row.get(0)?, row.get(1)?)),
         )
         .map_err(|e| match e {
             rusqlite::Error::QueryReturnedNoRows => {
@@ -495,53 +627,126 @@ pub async fn retry_download(
 
     drop(db); // Release lock before async operations
 
-    // Parse media type
-    let media_type = match media_type_str.as_str() {
-        "movie" => MediaType::Movie,
-        "episode" => MediaType::Episode,
-        "album" => MediaType::Album,
-        "track" => MediaType::Track,
-        _ => return Err(AppError::Internal("Invalid media type".to_string())),
-    };
-
-    // Retry in appropriate engine
-    let new_source_id = match source_type {
+    // Take the download out of its engine, the queue starts it again
+    match source_type {
         DownloadSource::Torrent => {
             let torrent_engine = state
                 .torrent_engine()
                 .ok_or_else(|| AppError::Internal("Torrent engine not available".to_string()))?;
 
             // Remove the failed torrent if it exists
-            let _ = torrent_engine.remove(&source_id, false).await;
+            if !download_queue::is_queued_source_id(&source_id) {
+                let _ = torrent_engine.remove(&source_id, false).await;
+            }
+        }
+        DownloadSource::Soulseek => {
+            if state.soulseek_engine().is_none() {
+                return Err(AppError::ServiceUnavailable(
+                    "Soulseek not configured".to_string(),
+                ));
+            }
+        }
+    }
 
-            // Re-add the magnet
-            let media_ref = MediaRef {
-                media_type,
-                media_id,
-            };
+    // The file selection and speed limits stay on the row
+    state.download_queue().requeue(download_id).await?;
 
-            torrent_engine.add_magnet(&source_uri, media_ref).await?
+    // Fetch updated download
+    let db = state.db.lock().await;
+    let download = db.query_row(
+        r#"
+        SELECT id, source_type, source_id, name, media_type, media_id, source_uri, status,
+               progress, download_speed, upload_speed, size_bytes,
+               downloaded_bytes, uploaded_bytes, ratio, peers, error_message,
+               added_at, started_at, completed_at,
+               soulseek_username, soulseek_filename, queue_position, selected_files,
+               download_limit, upload_limit, queue_order
+        FROM downloads WHERE id = ?1
+        "#,
+        [download_id],
+        map_download_row,
+    )?;
+
+    tracing::info!(
+        download_id = download_id,
+        old_source_id = %source_id,
+        status = %download.status,
+        "Download retried"
+    );
+
+    Ok(Json(download))
+}
+
+/// PUT /api/downloads/:id/limits
+///
+/// Sets a download's own speed limits. Only supported for torrent downloads.
+/// A running torrent is briefly taken out of the engine and added back to
+/// apply them.
+pub async fn set_download_limits(
+    State(state): State<AppState>,
+    Path(download_id): Path<i64>,
+    Json(body): Json<DownloadLimitsRequest>,
+) -> Result<Json<Download>> {
+    let limits = SpeedLimits {
+        download: body.download_limit.filter(|&l| l > 0),
+        upload: body.upload_limit.filter(|&l| l > 0),
+    };
+    if [limits.download, limits.upload]
+        .into_iter()
+        .flatten()
+        .any(|l| l > i64::MAX as u64)
+    {
+        return Err(AppError::BadRequest("Speed limit is too large".to_string()));
+    }
+
+    let db = state.db.lock().await;
+
+    // Get download info
+    let (source_type_str, source_id, removed): (String, String, bool) = db
+        .query_row(
+            "SELECT source_type, source_id, removed_at IS NOT NULL FROM downloads WHERE id = ?1",
+            [download_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .map_err(|e| match e {
+            rusqlite::Error::QueryReturnedNoRows => {
+                AppError::NotFound("Download not found".to_string())
+            }
+            _ => AppError::Sqlite(e),
+        })?;
+
+    // Parse source type
+    let source_type: DownloadSource = source_type_str
+        .parse()
+        .map_err(|e: String| AppError::Internal(e))?;
+
+    drop(db); // Release lock before async operations
+
+    match source_type {
+        DownloadSource::Torrent => {
+            // Torrents no longer in the engine pick the limits up when retried
+            if let Some(torrent_engine) = state.torrent_engine().filter(|_| !removed) {
+                match torrent_engine.set_torrent_limits(&source_id, limits).await {
+                    Ok(()) | Err(AppError::NotFound(_)) => {}
+                    Err(e) => return Err(e),
+                }
+            }
         }
         DownloadSource::Soulseek => {
             return Err(AppError::BadRequest(
-                "Retry is not yet supported for Soulseek downloads".to_string(),
+                "Per-download speed limits are not supported for Soulseek downloads".to_string(),
             ));
         }
-    };
+    }
 
-    // Update database
     let db = state.db.lock().await;
-
     db.execute(
-        r#"
-        UPDATE downloads
-        SET source_id = ?1, status = 'downloading', error_message = NULL,
-            progress = 0, download_speed = 0, upload_speed = 0,
-            downloaded_bytes = 0, uploaded_bytes = 0, ratio = 0, peers = 0,
-            started_at = datetime('now')
-        WHERE id = ?2
-        "#,
-        rusqlite::params![new_source_id, download_id],
+        "UPDATE downloads SET download_limit = ?1, upload_limit = ?2 WHERE id = ?3",
+        rusqlite::params![
+            limits.download.map(|l| l as i64),
+            limits.upload.map(|l| l as i64),
+            download_id
+        ],
     )?;
 
     // Fetch updated download
@@ -551,7 +756,8 @@ pub async fn retry_download(
                progress, download_speed, upload_speed, size_bytes,
                downloaded_bytes, uploaded_bytes, ratio, peers, error_message,
                added_at, started_at, completed_at,
-               soulseek_username, soulseek_filename, queue_position
+               soulseek_username, soulseek_filename, queue_position, selected_files,
+               download_limit, upload_limit, queue_order
         FROM downloads WHERE id = ?1
         "#,
         [download_id],
@@ -560,25 +766,86 @@ pub async fn retry_download(
 
     tracing::info!(
         download_id = download_id,
-        old_source_id = %source_id,
-        new_source_id = %new_source_id,
-        "Download retried"
+        source_id = %source_id,
+        download_kib = ?limits.download,
+        upload_kib = ?limits.upload,
+        "Download speed limits set"
     );
 
     Ok(Json(download))
 }
 
+/// GET /api/downloads/queue
+///
+/// Lists the downloads waiting for a free slot, in the order they will start.
+pub async fn list_queue(State(state): State<AppState>) -> Result<Json<Vec<Download>>> {
+    let db = state.db.lock().await;
+    Ok(Json(queued_downloads(&db)?))
+}
+
+/// PUT /api/downloads/:id/position
+///
+/// Moves a queued download to another position in the queue. Returns the
+/// reordered queue.
+pub async fn move_download(
+    State(state): State<AppState>,
+    Path(download_id): Path<i64>,
+    Json(body): Json<QueuePositionRequest>,
+) -> Result<Json<Vec<Download>>> {
+    if body.position == 0 {
+        return Err(AppError::BadRequest(
+            "Queue positions start at 1".to_string(),
+        ));
+    }
+
+    let db = state.db.lock().await;
+    download_queue::move_in_queue(&db, download_id, body.position)?;
+    let queue = queued_downloads(&db)?;
+    drop(db);
+
+    tracing::info!(
+        download_id = download_id,
+        position = body.position,
+        "Download moved in queue"
+    );
+
+    Ok(Json(queue))
+}
+
 // =============================================================================
 // Helpers
 // =============================================================================
 
+/// Queued downloads, first to start first.
+fn queued_downloads(conn: &rusqlite::Connection) -> Result<Vec<Download>> {
+    let mut stmt = conn.prepare(
+        r#"
+        SELECT id, source_type, source_id, name, media_type, media_id, source_uri, status,
+               progress, download_speed, upload_speed, size_bytes,
+               downloaded_bytes, uploaded_bytes, ratio, peers, error_message,
+               added_at, started_at, completed_at,
+               soulseek_username, soulseek_filename, queue_position, selected_files,
+               download_limit, upload_limit, queue_order
+        FROM downloads
+        WHERE status = 'queued'
+        ORDER BY queue_order IS NULL, queue_order, added_at, id
+        "#,
+    )?;
+
+    let downloads = stmt
+        .query_map([], map_download_row)?
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    Ok(downloads)
+}
+
 /// Maps a database row to a Download struct.
 /// Column order:
 ///   0: id, 1: source_type, 2: source_id, 3: name, 4: media_type, 5: media_id,
 ///   6: source_uri, 7: status, 8: progress, 9: download_speed, 10: upload_speed,
 ///   11: size_bytes, 12: downloaded_bytes, 13: uploaded_bytes, 14: ratio, 15: peers,
 ///   16: error_message, 17: added_at, 18: started_at, 19: completed_at,
-///   20: soulseek_username, 21: soulseek_filename, 22: queue_position
+///   20: soulseek_username, 21: soulseek_filename, 22: queue_position,
+///   23: selected_files, 24: download_limit, 25: upload_limit, 26: queue_order
 fn map_download_row(row: &rusqlite::Row) -> rusqlite::Result<Download> {
     let source_type_str: String = row.get(1)?;
     let source_type = match source_type_str.as_str() {
@@ -631,5 +898,11 @@ fn map_download_row(row: &rusqlite::Row) -> rusqlite::Result<Download> {
         soulseek_username: row.get(20)?,
         soulseek_filename: row.get(21)?,
         queue_position: row.get(22)?,
+        selected_files: row
+            .get::<_, Option<String>>(23)?
+            .and_then(|f| serde_json::from_str(&f).ok()),
+        download_limit: row.get(24)?,
+        upload_limit: row.get(25)?,
+        queue_order: row.get(26)?,
     })
 }
@@ -1,9 +1,11 @@
 //! API endpoint handlers for the LCARS backend.
 
 pub mod auth;
+pub mod blocklist;
 pub mod downloads;
 pub mod movies;
 pub mod music;
+pub mod quality_profiles;
 pub mod search;
 pub mod soulseek;
 pub mod system;
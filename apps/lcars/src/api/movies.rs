@@ -6,9 +6,14 @@ use axum::{
 };
 use serde::{Deserialize, Serialize};
 
-use crate::db::models::{MediaStatus, MediaType, Movie};
+use crate::api::downloads::TorrentSourceRequest;
+use crate::api::quality_profiles::validate_profile_reference;
+use crate::db::models::{map_movie_row, MediaStatus, MediaType, Movie};
 use crate::error::{AppError, Result};
-use crate::services::indexer::{MediaSearchType, Release, SearchQuery as IndexerSearchQuery};
+use crate::services::download_queue::{QueuePriority, QueueRequest, QueuedSource};
+use crate::services::indexer::{
+    MediaSearchType, ProfileMediaType, Release, SearchQuery as IndexerSearchQuery,
+};
 use crate::services::Claims;
 use crate::AppState;
 
@@ -53,6 +58,8 @@ pub struct AddMovieRequest {
     pub monitored: Option<bool>,
     /// Quality limit for downloads (default: "1080p").
     pub quality_limit: Option<String>,
+    /// Quality profile to use instead of the quality limit.
+    pub quality_profile_id: Option<i64>,
 }
 
 /// Request body for updating a movie.
@@ -62,6 +69,11 @@ pub struct UpdateMovieRequest {
     pub monitored: Option<bool>,
     /// Quality limit for downloads.
     pub quality_limit: Option<String>,
+    /// Quality profile to use instead of the quality limit.
+    pub quality_profile_id: Option<i64>,
+    /// Remove the quality profile, going back to the quality limit.
+    #[serde(default)]
+    pub clear_quality_profile: bool,
 }
 
 /// Query parameters for deleting a movie.
@@ -74,8 +86,12 @@ pub struct DeleteMovieQuery {
 /// Request body for downloading a release.
 #[derive(Debug, Deserialize)]
 pub struct DownloadRequest {
-    /// Direct magnet link.
-    pub magnet: String,
+    /// Torrent to download.
+    #[serde(flatten)]
+    pub source: TorrentSourceRequest,
+    /// Indexes of the torrent files to download (default: every file).
+    /// See `POST /api/downloads/files`.
+    pub files: Option<Vec<usize>>,
 }
 
 /// Success response for operations without specific data.
@@ -141,7 +157,7 @@ pub async fn list_movies(
             SELECT m.id, m.tmdb_id, m.imdb_id, m.title, m.original_title, m.year,
                    m.overview, m.poster_path, m.backdrop_path, m.runtime_minutes,
                    m.genres, m.status, m.monitored, m.quality_limit, m.file_path,
-                   m.file_size, m.added_at, m.updated_at, m.added_by
+                   m.file_size, m.added_at, m.updated_at, m.added_by, m.quality_profile_id
             FROM movies m
             JOIN movies_fts fts ON m.id = fts.rowid
             WHERE movies_fts MATCH ?1
@@ -186,7 +202,7 @@ pub async fn list_movies(
             SELECT id, tmdb_id, imdb_id, title, original_title, year,
                    overview, poster_path, backdrop_path, runtime_minutes,
                    genres, status, monitored, quality_limit, file_path,
-                   file_size, added_at, updated_at, added_by
+                   file_size, added_at, updated_at, added_by, quality_profile_id
             FROM movies
             WHERE (?1 IS NULL OR status = ?1)
               AND (?2 IS NULL OR monitored = ?2)
@@ -278,14 +294,18 @@ pub async fn add_movie(
         )));
     }
 
+    if let Some(profile_id) = body.quality_profile_id {
+        validate_profile_reference(&db, profile_id, ProfileMediaType::Video)?;
+    }
+
     // Insert the movie
     db.execute(
         r#"
         INSERT INTO movies (
             tmdb_id, imdb_id, title, original_title, year, overview,
             poster_path, backdrop_path, runtime_minutes, genres,
-            status, monitored, quality_limit, added_by
-        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, 'missing', ?11, ?12, ?13)
+            status, monitored, quality_limit, added_by, quality_profile_id
+        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, 'missing', ?11, ?12, ?13, ?14)
         "#,
         rusqlite::params![
             body.tmdb_id,
@@ -301,6 +321,7 @@ pub async fn add_movie(
             monitored,
             quality_limit,
             claims.sub,
+            body.quality_profile_id,
         ],
     )?;
 
@@ -312,7 +333,7 @@ pub async fn add_movie(
         SELECT id, tmdb_id, imdb_id, title, original_title, year,
                overview, poster_path, backdrop_path, runtime_minutes,
                genres, status, monitored, quality_limit, file_path,
-               file_size, added_at, updated_at, added_by
+               file_size, added_at, updated_at, added_by, quality_profile_id
         FROM movies WHERE id = ?1
         "#,
         [movie_id],
@@ -345,7 +366,7 @@ pub async fn get_movie(
             SELECT id, tmdb_id, imdb_id, title, original_title, year,
                    overview, poster_path, backdrop_path, runtime_minutes,
                    genres, status, monitored, quality_limit, file_path,
-                   file_size, added_at, updated_at, added_by
+                   file_size, added_at, updated_at, added_by, quality_profile_id
             FROM movies WHERE id = ?1
             "#,
             [movie_id],
@@ -397,6 +418,14 @@ pub async fn update_movie(
         params.push(Box::new(quality_limit.clone()));
     }
 
+    if let Some(profile_id) = body.quality_profile_id {
+        validate_profile_reference(&db, profile_id, ProfileMediaType::Video)?;
+        updates.push("quality_profile_id = ?");
+        params.push(Box::new(profile_id));
+    } else if body.clear_quality_profile {
+        updates.push("quality_profile_id = NULL");
+    }
+
     if updates.is_empty() {
         return Err(AppError::BadRequest("No fields to update".to_string()));
     }
@@ -414,7 +443,7 @@ pub async fn update_movie(
         SELECT id, tmdb_id, imdb_id, title, original_title, year,
                overview, poster_path, backdrop_path, runtime_minutes,
                genres, status, monitored, quality_limit, file_path,
-               file_size, added_at, updated_at, added_by
+               file_size, added_at, updated_at, added_by, quality_profile_id
         FROM movies WHERE id = ?1
         "#,
         [movie_id],
@@ -504,7 +533,7 @@ pub async fn search_releases(
             SELECT id, tmdb_id, imdb_id, title, original_title, year,
                    overview, poster_path, backdrop_path, runtime_minutes,
                    genres, status, monitored, quality_limit, file_path,
-                   file_size, added_at, updated_at, added_by
+                   file_size, added_at, updated_at, added_by, quality_profile_id
             FROM movies WHERE id = ?1
             "#,
             [movie_id],
@@ -528,6 +557,10 @@ pub async fn search_releases(
         query = query.imdb_id(imdb_id);
     }
 
+    if let Ok(tmdb_id) = i32::try_from(movie.tmdb_id) {
+        query = query.tmdb_id(tmdb_id);
+    }
+
     // Search indexers
     let indexer_manager = state.indexer_manager();
     let releases = indexer_manager.search(&query).await?;
@@ -544,24 +577,22 @@ pub async fn search_releases(
 
 /// POST /api/movies/:id/download
 ///
-/// Starts downloading a release for this movie.
+/// Queues a release for this movie, starting it once a download slot is free.
 pub async fn download_release(
     State(state): State<AppState>,
     Path(movie_id): Path<i64>,
     Json(body): Json<DownloadRequest>,
 ) -> Result<Json<DownloadInfo>> {
-    // Validate magnet link format
-    if !body.magnet.starts_with("magnet:?") {
-        return Err(AppError::BadRequest(
-            "Invalid magnet link format".to_string(),
+    // Validate the torrent source
+    let source = body.source.to_source()?;
+
+    // Downloads need the torrent engine
+    if state.torrent_engine().is_none() {
+        return Err(AppError::Internal(
+            "Torrent engine not available".to_string(),
         ));
     }
 
-    // Get torrent engine
-    let torrent_engine = state
-        .torrent_engine()
-        .ok_or_else(|| AppError::Internal("Torrent engine not available".to_string()))?;
-
     let db = state.db.lock().await;
 
     // Check movie exists and get title
@@ -580,45 +611,38 @@ pub async fn download_release(
 
     drop(db); // Release lock before async operation
 
-    // Add magnet to torrent engine
+    // Queue the download, it starts once a slot is free
     let media_ref = crate::services::torrent::MediaRef {
         media_type: MediaType::Movie,
         media_id: movie_id,
     };
 
-    let info_hash = torrent_engine.add_magnet(&body.magnet, media_ref).await?;
-
-    // Create download record and update movie status
-    let db = state.db.lock().await;
-
-    db.execute(
-        r#"
-        INSERT INTO downloads (source_type, source_id, name, media_type, media_id, source_uri, status)
-        VALUES ('torrent', ?1, ?2, 'movie', ?3, ?4, 'downloading')
-        "#,
-        rusqlite::params![info_hash, title, movie_id, body.magnet],
-    )?;
-
-    let download_id = db.last_insert_rowid();
-
-    // Update movie status
-    db.execute(
-        "UPDATE movies SET status = 'downloading', updated_at = datetime('now') WHERE id = ?1",
-        [movie_id],
-    )?;
+    let queued = state
+        .download_queue()
+        .enqueue(QueueRequest {
+            name: title.clone(),
+            release_title: None,
+            media: media_ref,
+            source: QueuedSource::Torrent {
+                source,
+                files: body.files,
+            },
+            priority: QueuePriority::Manual,
+        })
+        .await?;
 
     tracing::info!(
         movie_id = movie_id,
-        info_hash = %info_hash,
-        download_id = download_id,
-        "Started movie download"
+        download_id = queued.id,
+        status = %queued.status,
+        "Queued movie download"
     );
 
     Ok(Json(DownloadInfo {
-        id: download_id,
-        info_hash,
+        id: queued.id,
+        info_hash: queued.source_id,
         name: title,
-        status: "downloading".to_string(),
+        status: queued.status.to_string(),
     }))
 }
 
@@ -711,7 +735,7 @@ pub async fn refresh_metadata(
         SELECT id, tmdb_id, imdb_id, title, original_title, year,
                overview, poster_path, backdrop_path, runtime_minutes,
                genres, status, monitored, quality_limit, file_path,
-               file_size, added_at, updated_at, added_by
+               file_size, added_at, updated_at, added_by, quality_profile_id
         FROM movies WHERE id = ?1
         "#,
         [movie_id],
@@ -726,42 +750,3 @@ pub async fn refresh_metadata(
 
     Ok(Json(movie))
 }
-
-// =============================================================================
-// Helpers
-// =============================================================================
-
-/// Maps a database row to a Movie struct.
-fn map_movie_row(row: &rusqlite::Row) -> rusqlite::Result<Movie> {
-    let status_str: String = row.get(11)?;
-    let status = match status_str.as_str() {
-        "missing" => MediaStatus::Missing,
-        "searching" => MediaStatus::Searching,
-        "downloading" => MediaStatus::Downloading,
-        "processing" => MediaStatus::Processing,
-        "available" => MediaStatus::Available,
-        _ => MediaStatus::Missing,
-    };
-
-    Ok(Movie {
-        id: row.get(0)?,
-        tmdb_id: row.get(1)?,
-        imdb_id: row.get(2)?,
-        title: row.get(3)?,
-        original_title: row.get(4)?,
-        year: row.get(5)?,
-        overview: row.get(6)?,
-        poster_path: row.get(7)?,
-        backdrop_path: row.get(8)?,
-        runtime_minutes: row.get(9)?,
-        genres: row.get(10)?,
-        status,
-        monitored: row.get(12)?,
-        quality_limit: row.get(13)?,
-        file_path: row.get(14)?,
-        file_size: row.get(15)?,
-        added_at: row.get(16)?,
-        updated_at: row.get(17)?,
-        added_by: row.get(18)?,
-    })
-}
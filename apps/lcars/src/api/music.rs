@@ -9,11 +9,19 @@ use axum::{
 };
 use serde::{Deserialize, Serialize};
 
+use crate::api::downloads::TorrentSourceRequest;
+use crate::api::quality_profiles::validate_profile_reference;
 use crate::config::MusicQualityConfig;
-use crate::db::models::{Album, AlbumStatus, Artist, MediaStatus, MediaType, Track};
+use crate::db::models::{
+    map_album_row, map_artist_row, map_track_row, Album, AlbumStatus, Artist, MediaStatus,
+    MediaType, Track,
+};
 use crate::error::{AppError, Result};
 use crate::middleware;
-use crate::services::indexer::{MediaSearchType, Release, SearchQuery as IndexerSearchQuery};
+use crate::services::download_queue::{QueuePriority, QueueRequest, QueuedDownload, QueuedSource};
+use crate::services::indexer::{
+    MediaSearchType, ProfileMediaType, Release, SearchQuery as IndexerSearchQuery,
+};
 use crate::services::soulseek::{
     FileResult as SoulseekFileResultType, SearchResult as SoulseekSearchResult,
 };
@@ -89,6 +97,8 @@ pub struct AddArtistRequest {
     pub monitored: Option<bool>,
     /// Quality limit for downloads (default: "flac").
     pub quality_limit: Option<String>,
+    /// Quality profile to use instead of the quality limit.
+    pub quality_profile_id: Option<i64>,
 }
 
 /// Request body for updating an artist.
@@ -98,6 +108,11 @@ pub struct UpdateArtistRequest {
     pub monitored: Option<bool>,
     /// Quality limit for downloads.
     pub quality_limit: Option<String>,
+    /// Quality profile to use instead of the quality limit.
+    pub quality_profile_id: Option<i64>,
+    /// Remove the quality profile, going back to the quality limit.
+    #[serde(default)]
+    pub clear_quality_profile: bool,
 }
 
 /// Request body for updating an album.
@@ -133,8 +148,12 @@ pub struct DeleteAlbumQuery {
 /// Request body for downloading a release (torrent).
 #[derive(Debug, Deserialize)]
 pub struct DownloadRequest {
-    /// Direct magnet link.
-    pub magnet: String,
+    /// Torrent to download.
+    #[serde(flatten)]
+    pub source: TorrentSourceRequest,
+    /// Indexes of the torrent files to download (default: every file).
+    /// See `POST /api/downloads/files`.
+    pub files: Option<Vec<usize>>,
 }
 
 /// Request body for searching releases with multiple sources.
@@ -153,8 +172,11 @@ pub struct UnifiedDownloadRequest {
     /// Download source: "torrent" or "soulseek".
     #[serde(default = "default_source")]
     pub source: String,
-    /// Magnet link (required for torrent downloads).
-    pub magnet: Option<String>,
+    /// Torrent to download (required for torrent downloads).
+    #[serde(flatten)]
+    pub torrent: TorrentSourceRequest,
+    /// Indexes of the torrent files to download (default: every file).
+    pub torrent_files: Option<Vec<usize>>,
     /// Soulseek username (required for soulseek downloads).
     pub username: Option<String>,
     /// Soulseek files to download (required for soulseek downloads).
@@ -361,7 +383,7 @@ pub async fn list_artists(
             r#"
             SELECT a.id, a.mbid, a.name, a.sort_name, a.disambiguation, a.artist_type,
                    a.country, a.begin_date, a.end_date, a.overview, a.image_path,
-                   a.monitored, a.quality_limit, a.added_at, a.updated_at, a.added_by,
+                   a.monitored, a.quality_limit, a.added_at, a.updated_at, a.added_by, a.quality_profile_id,
                    (SELECT COUNT(*) FROM albums WHERE artist_id = a.id) as album_count
             FROM artists a
             JOIN artists_fts fts ON a.id = fts.rowid
@@ -395,7 +417,7 @@ pub async fn list_artists(
             r#"
             SELECT a.id, a.mbid, a.name, a.sort_name, a.disambiguation, a.artist_type,
                    a.country, a.begin_date, a.end_date, a.overview, a.image_path,
-                   a.monitored, a.quality_limit, a.added_at, a.updated_at, a.added_by,
+                   a.monitored, a.quality_limit, a.added_at, a.updated_at, a.added_by, a.quality_profile_id,
                    (SELECT COUNT(*) FROM albums WHERE artist_id = a.id) as album_count
             FROM artists a
             WHERE (?1 IS NULL OR a.monitored = ?1)
@@ -474,6 +496,10 @@ pub async fn add_artist(
         )));
     }
 
+    if let Some(profile_id) = body.quality_profile_id {
+        validate_profile_reference(&db, profile_id, ProfileMediaType::Music)?;
+    }
+
     // Extract life span dates
     let begin_date = mb_artist.life_span.as_ref().and_then(|ls| ls.begin.clone());
     let end_date = mb_artist.life_span.as_ref().and_then(|ls| ls.end.clone());
@@ -483,8 +509,8 @@ pub async fn add_artist(
         r#"
         INSERT INTO artists (
             mbid, name, sort_name, disambiguation, artist_type, country,
-            begin_date, end_date, monitored, quality_limit, added_by
-        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
+            begin_date, end_date, monitored, quality_limit, added_by, quality_profile_id
+        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)
         "#,
         rusqlite::params![
             mbid,
@@ -498,6 +524,7 @@ pub async fn add_artist(
             monitored,
             quality_limit,
             claims.sub,
+            body.quality_profile_id,
         ],
     )?;
 
@@ -553,7 +580,7 @@ pub async fn add_artist(
         r#"
         SELECT id, mbid, name, sort_name, disambiguation, artist_type, country,
                begin_date, end_date, overview, image_path, monitored, quality_limit,
-               added_at, updated_at, added_by
+               added_at, updated_at, added_by, quality_profile_id
         FROM artists WHERE id = ?1
         "#,
         [artist_id],
@@ -586,7 +613,7 @@ pub async fn get_artist(
             r#"
             SELECT id, mbid, name, sort_name, disambiguation, artist_type, country,
                    begin_date, end_date, overview, image_path, monitored, quality_limit,
-                   added_at, updated_at, added_by
+                   added_at, updated_at, added_by, quality_profile_id
             FROM artists WHERE id = ?1
             "#,
             [artist_id],
@@ -654,6 +681,14 @@ pub async fn update_artist(
         params.push(Box::new(quality_limit.clone()));
     }
 
+    if let Some(profile_id) = body.quality_profile_id {
+        validate_profile_reference(&db, profile_id, ProfileMediaType::Music)?;
+        updates.push("quality_profile_id = ?");
+        params.push(Box::new(profile_id));
+    } else if body.clear_quality_profile {
+        updates.push("quality_profile_id = NULL");
+    }
+
     if updates.is_empty() {
         return Err(AppError::BadRequest("No fields to update".to_string()));
     }
@@ -670,7 +705,7 @@ pub async fn update_artist(
         r#"
         SELECT id, mbid, name, sort_name, disambiguation, artist_type, country,
                begin_date, end_date, overview, image_path, monitored, quality_limit,
-               added_at, updated_at, added_by
+               added_at, updated_at, added_by, quality_profile_id
         FROM artists WHERE id = ?1
         "#,
         [artist_id],
@@ -869,7 +904,7 @@ pub async fn refresh_artist(
         r#"
         SELECT id, mbid, name, sort_name, disambiguation, artist_type, country,
                begin_date, end_date, overview, image_path, monitored, quality_limit,
-               added_at, updated_at, added_by
+               added_at, updated_at, added_by, quality_profile_id
         FROM artists WHERE id = ?1
         "#,
         [artist_id],
@@ -1242,7 +1277,9 @@ pub async fn search_album_releases(
 
     // Build search query with artist and album
     let search_term = format!("{} {}", artist_name, album_title);
-    let query = IndexerSearchQuery::new(&search_term).media_type(MediaSearchType::MusicAlbum);
+    let query = IndexerSearchQuery::new(&search_term)
+        .media_type(MediaSearchType::MusicAlbum)
+        .album(&artist_name, &album_title);
 
     // Search indexers
     let indexer_manager = state.indexer_manager();
@@ -1296,6 +1333,7 @@ pub async fn unified_search_album(
         .unwrap_or_else(|| state.config.music.search_sources.clone());
 
     // Build search query
+    let custom_query = body.query.is_some();
     let search_term = body
         .query
         .unwrap_or_else(|| format!("{} {}", artist_name, album_title));
@@ -1305,7 +1343,11 @@ pub async fn unified_search_album(
 
     // Search indexers if requested
     if sources.iter().any(|s| s == "indexers" || s == "all") {
-        let query = IndexerSearchQuery::new(&search_term).media_type(MediaSearchType::MusicAlbum);
+        let mut query =
+            IndexerSearchQuery::new(&search_term).media_type(MediaSearchType::MusicAlbum);
+        if !custom_query {
+            query = query.album(&artist_name, &album_title);
+        }
         let indexer_manager = state.indexer_manager();
         match indexer_manager.search(&query).await {
             Ok(releases) => {
@@ -1368,7 +1410,7 @@ pub async fn unified_search_album(
 
 /// POST /api/music/albums/:id/unified-download
 ///
-/// Starts downloading a release for this album from either torrent or Soulseek.
+/// Queues a release for this album from either torrent or Soulseek.
 pub async fn unified_download_album(
     State(state): State<AppState>,
     Path(album_id): Path<i64>,
@@ -1376,21 +1418,20 @@ pub async fn unified_download_album(
 ) -> Result<Json<DownloadInfo>> {
     match body.source.as_str() {
         "torrent" => {
-            // Validate magnet link
-            let magnet = body.magnet.ok_or_else(|| {
-                AppError::BadRequest("Magnet link required for torrent downloads".to_string())
-            })?;
-
-            if !magnet.starts_with("magnet:?") {
+            // Validate the torrent source
+            if body.torrent.is_empty() {
                 return Err(AppError::BadRequest(
-                    "Invalid magnet link format".to_string(),
+                    "Magnet link or .torrent file required for torrent downloads".to_string(),
                 ));
             }
+            let source = body.torrent.to_source()?;
 
-            // Get torrent engine
-            let torrent_engine = state
-                .torrent_engine()
-                .ok_or_else(|| AppError::Internal("Torrent engine not available".to_string()))?;
+            // Downloads need the torrent engine
+            if state.torrent_engine().is_none() {
+                return Err(AppError::Internal(
+                    "Torrent engine not available".to_string(),
+                ));
+            }
 
             let db = state.db.lock().await;
 
@@ -1410,45 +1451,39 @@ pub async fn unified_download_album(
 
             drop(db);
 
-            // Add magnet to torrent engine
+            // Queue the download, it starts once a slot is free
             let media_ref = crate::services::torrent::MediaRef {
                 media_type: MediaType::Album,
                 media_id: album_id,
             };
 
-            let info_hash = torrent_engine.add_magnet(&magnet, media_ref).await?;
-
-            // Create download record and update album status
-            let db = state.db.lock().await;
-
-            db.execute(
-                r#"
-                INSERT INTO downloads (source_type, source_id, name, media_type, media_id, source_uri, status)
-                VALUES ('torrent', ?1, ?2, 'album', ?3, ?4, 'downloading')
-                "#,
-                rusqlite::params![info_hash, title, album_id, magnet],
-            )?;
-
-            let download_id = db.last_insert_rowid();
-
-            db.execute(
-                "UPDATE albums SET status = 'downloading', updated_at = datetime('now') WHERE id = ?1",
-                [album_id],
-            )?;
+            let queued = state
+                .download_queue()
+                .enqueue(QueueRequest {
+                    name: title.clone(),
+                    release_title: None,
+                    media: media_ref,
+                    source: QueuedSource::Torrent {
+                        source,
+                        files: body.torrent_files,
+                    },
+                    priority: QueuePriority::Manual,
+                })
+                .await?;
 
             tracing::info!(
                 album_id = album_id,
-                info_hash = %info_hash,
-                download_id = download_id,
+                download_id = queued.id,
+                status = %queued.status,
                 source = "torrent",
-                "Started album download via unified endpoint"
+                "Queued album download via unified endpoint"
             );
 
             Ok(Json(DownloadInfo {
-                id: download_id,
-                info_hash,
+                id: queued.id,
+                info_hash: queued.source_id,
                 name: title,
-                status: "downloading".to_string(),
+                status: queued.status.to_string(),
             }))
         }
         "soulseek" => {
@@ -1467,14 +1502,10 @@ pub async fn unified_download_album(
                 ));
             }
 
-            // Get Soulseek engine
-            let engine = state.soulseek_engine().ok_or_else(|| {
-                AppError::ServiceUnavailable("Soulseek not configured".to_string())
-            })?;
-
-            if !engine.is_connected().await {
+            // Downloads need the Soulseek engine, they start once it is connected
+            if state.soulseek_engine().is_none() {
                 return Err(AppError::ServiceUnavailable(
-                    "Soulseek is not connected".to_string(),
+                    "Soulseek not configured".to_string(),
                 ));
             }
 
@@ -1496,71 +1527,50 @@ pub async fn unified_download_album(
 
             drop(db);
 
-            // Start downloads for each file
-            let mut first_download_id = String::new();
-            for (i, file) in files.iter().enumerate() {
-                let request = crate::services::soulseek::DownloadRequest {
-                    username: username.clone(),
-                    filename: file.filename.clone(),
-                    size: file.size,
-                    media_type: Some("album".to_string()),
-                    media_id: Some(album_id),
-                };
-
-                match engine.download(request).await {
-                    Ok(id) => {
-                        if i == 0 {
-                            first_download_id = id.clone();
-                        }
-
-                        // Create download record in database
-                        let db = state.db.lock().await;
-                        let source_uri = format!("soulseek://{}/{}", username, file.filename);
-                        let file_name = file
-                            .filename
-                            .rsplit(['/', '\\'])
-                            .next()
-                            .unwrap_or(&file.filename);
-
-                        db.execute(
-                            r#"
-                            INSERT INTO downloads (source_type, source_id, name, media_type, media_id, source_uri, status, size_bytes, soulseek_username, soulseek_filename)
-                            VALUES ('soulseek', ?1, ?2, 'album', ?3, ?4, 'downloading', ?5, ?6, ?7)
-                            "#,
-                            rusqlite::params![id, file_name, album_id, source_uri, file.size, username, file.filename],
-                        )?;
-                    }
-                    Err(e) => {
-                        tracing::warn!(
-                            username = %username,
-                            filename = %file.filename,
-                            error = %e,
-                            "Failed to start Soulseek download for file"
-                        );
-                    }
-                }
+            // Queue a download for each file
+            let mut first: Option<QueuedDownload> = None;
+            for file in &files {
+                let file_name = file
+                    .filename
+                    .rsplit(['/', '\\'])
+                    .next()
+                    .unwrap_or(&file.filename);
+
+                let queued = state
+                    .download_queue()
+                    .enqueue(QueueRequest {
+                        name: file_name.to_string(),
+                        release_title: None,
+                        media: crate::services::torrent::MediaRef {
+                            media_type: MediaType::Album,
+                            media_id: album_id,
+                        },
+                        source: QueuedSource::Soulseek {
+                            username: username.clone(),
+                            filename: file.filename.clone(),
+                            size: file.size,
+                        },
+                        priority: QueuePriority::Manual,
+                    })
+                    .await?;
+                first.get_or_insert(queued);
             }
 
-            // Update album status
-            let db = state.db.lock().await;
-            db.execute(
-                "UPDATE albums SET status = 'downloading', updated_at = datetime('now') WHERE id = ?1",
-                [album_id],
-            )?;
-
             tracing::info!(
                 album_id = album_id,
                 username = %username,
                 files = files.len(),
                 source = "soulseek",
-                "Started album download via unified endpoint"
+                "Queued album download via unified endpoint"
             );
 
+            // Files are separate downloads, report the first one
+            let first = first.expect("at least one file is queued");
             Ok(Json(DownloadInfo {
-                id: 0, // Soulseek downloads don't have a single DB ID
-                info_hash: first_download_id,
+                id: first.id,
+                info_hash: first.source_id,
                 name: title,
-                status: "downloading".to_string(),
+                status: first.status.to_string(),
             }))
         }
         _ => Err(AppError::BadRequest(format!(
@@ -1572,24 +1582,22 @@ pub async fn unified_download_album(
 
 /// POST /api/music/albums/:id/download
 ///
-/// Starts downloading a release for this album.
+/// Queues a release for this album, starting it once a download slot is free.
 pub async fn download_album(
     State(state): State<AppState>,
     Path(album_id): Path<i64>,
     Json(body): Json<DownloadRequest>,
 ) -> Result<Json<DownloadInfo>> {
-    // Validate magnet link format
-    if !body.magnet.starts_with("magnet:?") {
-        return Err(AppError::BadRequest(
-            "Invalid magnet link format".to_string(),
+    // Validate the torrent source
+    let source = body.source.to_source()?;
+
+    // Downloads need the torrent engine
+    if state.torrent_engine().is_none() {
+        return Err(AppError::Internal(
+            "Torrent engine not available".to_string(),
         ));
     }
 
-    // Get torrent engine
-    let torrent_engine = state
-        .torrent_engine()
-        .ok_or_else(|| AppError::Internal("Torrent engine not available".to_string()))?;
-
     let db = state.db.lock().await;
 
     // Get album info
@@ -1608,45 +1616,38 @@ pub async fn download_album(
 
     drop(db); // Release lock before async operation
 
-    // Add magnet to torrent engine
+    // Queue the download, it starts once a slot is free
     let media_ref = crate::services::torrent::MediaRef {
         media_type: MediaType::Album,
         media_id: album_id,
     };
 
-    let info_hash = torrent_engine.add_magnet(&body.magnet, media_ref).await?;
-
-    // Create download record and update album status
-    let db = state.db.lock().await;
-
-    db.execute(
-        r#"
-        INSERT INTO downloads (source_type, source_id, name, media_type, media_id, source_uri, status)
-        VALUES ('torrent', ?1, ?2, 'album', ?3, ?4, 'downloading')
-        "#,
-        rusqlite::params![info_hash, title, album_id, body.magnet],
-    )?;
-
-    let download_id = db.last_insert_rowid();
-
-    // Update album status
-    db.execute(
-        "UPDATE albums SET status = 'downloading', updated_at = datetime('now') WHERE id = ?1",
-        [album_id],
-    )?;
+    let queued = state
+        .download_queue()
+        .enqueue(QueueRequest {
+            name: title.clone(),
+            release_title: None,
+            media: media_ref,
+            source: QueuedSource::Torrent {
+                source,
+                files: body.files,
+            },
+            priority: QueuePriority::Manual,
+        })
+        .await?;
 
     tracing::info!(
         album_id = album_id,
-        info_hash = %info_hash,
-        download_id = download_id,
-        "Started album download"
+        download_id = queued.id,
+        status = %queued.status,
+        "Queued album download"
     );
 
     Ok(Json(DownloadInfo {
-        id: download_id,
-        info_hash,
+        id: queued.id,
+        info_hash: queued.source_id,
         name: title,
-        status: "downloading".to_string(),
+        status: queued.status.to_string(),
     }))
 }
 
@@ -1965,24 +1966,22 @@ pub async fn search_track_releases(
 
 /// POST /api/music/tracks/:id/download
 ///
-/// Starts downloading a release for this track.
+/// Queues a release for this track, starting it once a download slot is free.
 pub async fn download_track(
     State(state): State<AppState>,
     Path(track_id): Path<i64>,
     Json(body): Json<DownloadRequest>,
 ) -> Result<Json<DownloadInfo>> {
-    // Validate magnet link format
-    if !body.magnet.starts_with("magnet:?") {
-        return Err(AppError::BadRequest(
-            "Invalid magnet link format".to_string(),
+    // Validate the torrent source
+    let source = body.source.to_source()?;
+
+    // Downloads need the torrent engine
+    if state.torrent_engine().is_none() {
+        return Err(AppError::Internal(
+            "Torrent engine not available".to_string(),
         ));
     }
 
-    // Get torrent engine
-    let torrent_engine = state
-        .torrent_engine()
-        .ok_or_else(|| AppError::Internal("Torrent engine not available".to_string()))?;
-
     let db = state.db.lock().await;
 
     // Get track info
@@ -2001,45 +2000,38 @@ pub async fn download_track(
 
     drop(db); // Release lock before async operation
 
-    // Add magnet to torrent engine
+    // Queue the download, it starts once a slot is free
     let media_ref = crate::services::torrent::MediaRef {
         media_type: MediaType::Track,
         media_id: track_id,
     };
 
-    let info_hash = torrent_engine.add_magnet(&body.magnet, media_ref).await?;
-
-    // Create download record and update track status
-    let db = state.db.lock().await;
-
-    db.execute(
-        r#"
-        INSERT INTO downloads (source_type, source_id, name, media_type, media_id, source_uri, status)
-        VALUES ('torrent', ?1, ?2, 'track', ?3, ?4, 'downloading')
-        "#,
-        rusqlite::params![info_hash, title, track_id, body.magnet],
-    )?;
-
-    let download_id = db.last_insert_rowid();
-
-    // Update track status
-    db.execute(
-        "UPDATE tracks SET status = 'downloading', updated_at = datetime('now') WHERE id = ?1",
-        [track_id],
-    )?;
+    let queued = state
+        .download_queue()
+        .enqueue(QueueRequest {
+            name: title.clone(),
+            release_title: None,
+            media: media_ref,
+            source: QueuedSource::Torrent {
+                source,
+                files: body.files,
+            },
+            priority: QueuePriority::Manual,
+        })
+        .await?;
 
     tracing::info!(
         track_id = track_id,
-        info_hash = %info_hash,
-        download_id = download_id,
-        "Started track download"
+        download_id = queued.id,
+        status = %queued.status,
+        "Queued track download"
     );
 
     Ok(Json(DownloadInfo {
-        id: download_id,
-        info_hash,
+        id: queued.id,
+        info_hash: queued.source_id,
         name: title,
-        status: "downloading".to_string(),
+        status: queued.status.to_string(),
     }))
 }
 
@@ -2154,7 +2146,7 @@ fn group_and_score_soulseek_results(
         .collect();
 
     // Sort by score (highest first)
-    album_results.sort_by(|a, b| b.score.cmp(&a.score));
+    album_results.sort_by_key(|r| std::cmp::Reverse(r.score));
 
     album_results
 }
@@ -2232,28 +2224,6 @@ fn score_soulseek_result(
     score
 }
 
-/// Maps a database row to an Artist struct.
-fn map_artist_row(row: &rusqlite::Row) -> rusqlite::Result<Artist> {
-    Ok(Artist {
-        id: row.get(0)?,
-        mbid: row.get(1)?,
-        name: row.get(2)?,
-        sort_name: row.get(3)?,
-        disambiguation: row.get(4)?,
-        artist_type: row.get(5)?,
-        country: row.get(6)?,
-        begin_date: row.get(7)?,
-        end_date: row.get(8)?,
-        overview: row.get(9)?,
-        image_path: row.get(10)?,
-        monitored: row.get(11)?,
-        quality_limit: row.get(12)?,
-        added_at: row.get(13)?,
-        updated_at: row.get(14)?,
-        added_by: row.get(15)?,
-    })
-}
-
 /// Maps a database row to an ArtistWithStats struct.
 fn map_artist_with_stats_row(row: &rusqlite::Row) -> rusqlite::Result<ArtistWithStats> {
     Ok(ArtistWithStats {
@@ -2274,102 +2244,12 @@ fn map_artist_with_stats_row(row: &rusqlite::Row) -> rusqlite::Result<ArtistWith
             added_at: row.get(13)?,
             updated_at: row.get(14)?,
             added_by: row.get(15)?,
+            quality_profile_id: row.get(16)?,
         },
-        album_count: row.get(16)?,
-    })
-}
-
-/// Maps a database row to an Album struct.
-fn map_album_row(row: &rusqlite::Row) -> rusqlite::Result<Album> {
-    let status_str: String = row.get(9)?;
-    let status = match status_str.as_str() {
-        "missing" => AlbumStatus::Missing,
-        "searching" => AlbumStatus::Searching,
-        "downloading" => AlbumStatus::Downloading,
-        "processing" => AlbumStatus::Processing,
-        "partial" => AlbumStatus::Partial,
-        "available" => AlbumStatus::Available,
-        _ => AlbumStatus::Missing,
-    };
-
-    Ok(Album {
-        id: row.get(0)?,
-        mbid: row.get(1)?,
-        artist_id: row.get(2)?,
-        title: row.get(3)?,
-        album_type: row.get(4)?,
-        release_date: row.get(5)?,
-        overview: row.get(6)?,
-        cover_path: row.get(7)?,
-        total_tracks: row.get(8)?,
-        status,
-        monitored: row.get(10)?,
-        quality_limit: row.get(11)?,
-        added_at: row.get(12)?,
-        updated_at: row.get(13)?,
+        album_count: row.get(17)?,
     })
 }
 
-/// Maps a database row to a Track struct.
-fn map_track_row(row: &rusqlite::Row) -> rusqlite::Result<Track> {
-    let status_str: String = row.get(8)?;
-    let status = match status_str.as_str() {
-        "missing" => MediaStatus::Missing,
-        "searching" => MediaStatus::Searching,
-        "downloading" => MediaStatus::Downloading,
-        "processing" => MediaStatus::Processing,
-        "available" => MediaStatus::Available,
-        _ => MediaStatus::Missing,
-    };
-
-    Ok(Track {
-        id: row.get(0)?,
-        mbid: row.get(1)?,
-        album_id: row.get(2)?,
-        artist_id: row.get(3)?,
-        title: row.get(4)?,
-        track_number: row.get(5)?,
-        disc_number: row.get(6)?,
-        duration_ms: row.get(7)?,
-        status,
-        monitored: row.get(9)?,
-        file_path: row.get(10)?,
-        file_size: row.get(11)?,
-        audio_format: row.get(12)?,
-        bitrate: row.get(13)?,
-        sample_rate: row.get(14)?,
-        bit_depth: row.get(15)?,
-        created_at: row.get(16)?,
-        updated_at: row.get(17)?,
-    })
-}
-
-/// Calculate album status based on track statuses.
-///
-/// TODO: Use this function to automatically update album status when:
-/// - A track download completes (status changes to Available)
-/// - Track status is updated via API
-/// - Album refresh discovers new tracks
-#[allow(dead_code)]
-fn calculate_album_status(tracks: &[Track]) -> AlbumStatus {
-    if tracks.is_empty() {
-        return AlbumStatus::Missing;
-    }
-
-    let available = tracks
-        .iter()
-        .filter(|t| matches!(t.status, MediaStatus::Available))
-        .count();
-
-    if available == 0 {
-        AlbumStatus::Missing
-    } else if available == tracks.len() {
-        AlbumStatus::Available
-    } else {
-        AlbumStatus::Partial
-    }
-}
-
 /// Validate that a string is a valid UUID format.
 ///
 /// UUID format: xxxxxxxx-xxxx-xxxx-xxxx-xxxxxxxxxxxx (36 characters with 4 dashes)
@@ -0,0 +1,266 @@
+//! Quality profile API endpoints.
+//!
+//! Profiles are listed to every user so they can be picked when adding
+//! media, and managed by admins.
+
+use axum::{
+    extract::{Path, State},
+    response::Json,
+};
+use rusqlite::Connection;
+use serde::{Deserialize, Serialize};
+
+use crate::error::{AppError, Result};
+use crate::services::indexer::{ProfileMediaType, QualityItem, QualityProfile};
+use crate::AppState;
+
+// =============================================================================
+// Request/Response Types
+// =============================================================================
+
+/// Request to create a quality profile.
+#[derive(Debug, Deserialize)]
+pub struct CreateQualityProfileRequest {
+    pub name: String,
+    pub media_type: ProfileMediaType,
+    /// Allowed qualities, best first
+    pub allowed: Vec<String>,
+    pub cutoff: String,
+    #[serde(default)]
+    pub preferred_words: Vec<String>,
+    #[serde(default)]
+    pub rejected_words: Vec<String>,
+    pub min_size_mb_per_minute: Option<f64>,
+    pub max_size_mb_per_minute: Option<f64>,
+}
+
+/// Request to update a quality profile. The media type cannot change, as
+/// media already using the profile depend on it.
+#[derive(Debug, Deserialize)]
+pub struct UpdateQualityProfileRequest {
+    pub name: Option<String>,
+    pub allowed: Option<Vec<String>>,
+    pub cutoff: Option<String>,
+    pub preferred_words: Option<Vec<String>>,
+    pub rejected_words: Option<Vec<String>>,
+    pub min_size_mb_per_minute: Option<f64>,
+    pub max_size_mb_per_minute: Option<f64>,
+    /// Remove the size limits
+    #[serde(default)]
+    pub clear_sizes: bool,
+}
+
+/// Generic success response.
+#[derive(Debug, Serialize)]
+pub struct SuccessResponse {
+    pub success: bool,
+    pub message: Option<String>,
+}
+
+fn parse_item(s: &str) -> Result<QualityItem> {
+    QualityItem::parse(s).ok_or_else(|| AppError::BadRequest(format!("Unknown quality '{}'", s)))
+}
+
+fn parse_items(items: &[String]) -> Result<Vec<QualityItem>> {
+    items.iter().map(|s| parse_item(s)).collect()
+}
+
+/// Reject a name used by another profile.
+fn check_name_free(conn: &Connection, name: &str, id: Option<i64>) -> Result<()> {
+    let taken: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM quality_profiles WHERE name = ?1 AND id IS NOT ?2)",
+        rusqlite::params![name, id],
+        |row| row.get(0),
+    )?;
+    if taken {
+        return Err(AppError::Conflict(format!(
+            "A quality profile named '{}' already exists",
+            name
+        )));
+    }
+    Ok(())
+}
+
+/// Check that a profile exists and applies to the given media.
+pub fn validate_profile_reference(
+    conn: &Connection,
+    profile_id: i64,
+    media_type: ProfileMediaType,
+) -> Result<()> {
+    let profile = QualityProfile::load(conn, profile_id)?
+        .ok_or_else(|| AppError::BadRequest("Quality profile not found".to_string()))?;
+    if profile.media_type != media_type {
+        return Err(AppError::BadRequest(format!(
+            "Quality profile '{}' is for {}, not {}",
+            profile.name, profile.media_type, media_type
+        )));
+    }
+    Ok(())
+}
+
+// =============================================================================
+// Handlers
+// =============================================================================
+
+/// GET /api/system/quality-profiles
+///
+/// List all quality profiles.
+pub async fn list_quality_profiles(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<QualityProfile>>> {
+    let db = state.db.lock().await;
+    Ok(Json(QualityProfile::load_all(&db)?))
+}
+
+/// GET /api/system/quality-profiles/:id
+///
+/// Get a quality profile.
+pub async fn get_quality_profile(
+    State(state): State<AppState>,
+    Path(profile_id): Path<i64>,
+) -> Result<Json<QualityProfile>> {
+    let db = state.db.lock().await;
+    QualityProfile::load(&db, profile_id)?
+        .map(Json)
+        .ok_or_else(|| AppError::NotFound("Quality profile not found".to_string()))
+}
+
+/// POST /api/system/quality-profiles
+///
+/// Create a quality profile.
+pub async fn create_quality_profile(
+    State(state): State<AppState>,
+    Json(req): Json<CreateQualityProfileRequest>,
+) -> Result<Json<QualityProfile>> {
+    let profile = QualityProfile {
+        id: 0,
+        name: req.name.trim().to_string(),
+        media_type: req.media_type,
+        allowed: parse_items(&req.allowed)?,
+        cutoff: parse_item(&req.cutoff)?,
+        preferred_words: req.preferred_words,
+        rejected_words: req.rejected_words,
+        min_size_mb_per_minute: req.min_size_mb_per_minute,
+        max_size_mb_per_minute: req.max_size_mb_per_minute,
+        created_at: String::new(),
+        updated_at: String::new(),
+    };
+    profile.validate()?;
+
+    let db = state.db.lock().await;
+    check_name_free(&db, &profile.name, None)?;
+
+    db.execute(
+        r#"
+        INSERT INTO quality_profiles (name, media_type, allowed, cutoff, preferred_words,
+                                      rejected_words, min_size_mb_per_minute, max_size_mb_per_minute)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+        "#,
+        rusqlite::params![
+            profile.name,
+            profile.media_type.to_string(),
+            serde_json::json!(profile.allowed).to_string(),
+            profile.cutoff.to_string(),
+            serde_json::json!(profile.preferred_words).to_string(),
+            serde_json::json!(profile.rejected_words).to_string(),
+            profile.min_size_mb_per_minute,
+            profile.max_size_mb_per_minute,
+        ],
+    )?;
+    let id = db.last_insert_rowid();
+
+    tracing::info!(profile_id = id, name = %profile.name, "Created quality profile");
+
+    QualityProfile::load(&db, id)?
+        .map(Json)
+        .ok_or_else(|| AppError::Internal("Failed to load created quality profile".to_string()))
+}
+
+/// PUT /api/system/quality-profiles/:id
+///
+/// Update a quality profile.
+pub async fn update_quality_profile(
+    State(state): State<AppState>,
+    Path(profile_id): Path<i64>,
+    Json(req): Json<UpdateQualityProfileRequest>,
+) -> Result<Json<QualityProfile>> {
+    let db = state.db.lock().await;
+
+    let mut profile = QualityProfile::load(&db, profile_id)?
+        .ok_or_else(|| AppError::NotFound("Quality profile not found".to_string()))?;
+
+    if let Some(name) = req.name {
+        profile.name = name.trim().to_string();
+    }
+    if let Some(allowed) = req.allowed {
+        profile.allowed = parse_items(&allowed)?;
+    }
+    if let Some(cutoff) = req.cutoff {
+        profile.cutoff = parse_item(&cutoff)?;
+    }
+    if let Some(words) = req.preferred_words {
+        profile.preferred_words = words;
+    }
+    if let Some(words) = req.rejected_words {
+        profile.rejected_words = words;
+    }
+    if req.clear_sizes {
+        profile.min_size_mb_per_minute = None;
+        profile.max_size_mb_per_minute = None;
+    }
+    if let Some(size) = req.min_size_mb_per_minute {
+        profile.min_size_mb_per_minute = Some(size);
+    }
+    if let Some(size) = req.max_size_mb_per_minute {
+        profile.max_size_mb_per_minute = Some(size);
+    }
+    profile.validate()?;
+    check_name_free(&db, &profile.name, Some(profile_id))?;
+
+    db.execute(
+        r#"
+        UPDATE quality_profiles
+        SET name = ?1, allowed = ?2, cutoff = ?3, preferred_words = ?4, rejected_words = ?5,
+            min_size_mb_per_minute = ?6, max_size_mb_per_minute = ?7, updated_at = datetime('now')
+        WHERE id = ?8
+        "#,
+        rusqlite::params![
+            profile.name,
+            serde_json::json!(profile.allowed).to_string(),
+            profile.cutoff.to_string(),
+            serde_json::json!(profile.preferred_words).to_string(),
+            serde_json::json!(profile.rejected_words).to_string(),
+            profile.min_size_mb_per_minute,
+            profile.max_size_mb_per_minute,
+            profile_id,
+        ],
+    )?;
+
+    tracing::info!(profile_id = profile_id, "Updated quality profile");
+
+    QualityProfile::load(&db, profile_id)?
+        .map(Json)
+        .ok_or_else(|| AppError::NotFound("Quality profile not found".to_string()))
+}
+
+/// DELETE /api/system/quality-profiles/:id
+///
+/// Delete a quality profile. Media using it fall back to their quality limit.
+pub async fn delete_quality_profile(
+    State(state): State<AppState>,
+    Path(profile_id): Path<i64>,
+) -> Result<Json<SuccessResponse>> {
+    let db = state.db.lock().await;
+
+    let rows_affected = db.execute("DELETE FROM quality_profiles WHERE id = ?1", [profile_id])?;
+    if rows_affected == 0 {
+        return Err(AppError::NotFound("Quality profile not found".to_string()));
+    }
+
+    tracing::info!(profile_id = profile_id, "Deleted quality profile");
+
+    Ok(Json(SuccessResponse {
+        success: true,
+        message: Some("Quality profile deleted successfully".to_string()),
+    }))
+}
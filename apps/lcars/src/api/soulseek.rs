@@ -455,16 +455,16 @@ pub async fn get_search_results(
     if let Some(ref sort_by) = query.sort_by {
         match sort_by.as_str() {
             "bitrate" => {
-                all_files.sort_by(|a, b| b.bitrate.cmp(&a.bitrate));
+                all_files.sort_by_key(|f| std::cmp::Reverse(f.bitrate));
             }
             "size" => {
-                all_files.sort_by(|a, b| b.size.cmp(&a.size));
+                all_files.sort_by_key(|f| std::cmp::Reverse(f.size));
             }
             "speed" => {
-                all_files.sort_by(|a, b| b.upload_speed.cmp(&a.upload_speed));
+                all_files.sort_by_key(|f| std::cmp::Reverse(f.upload_speed));
             }
             "queue" => {
-                all_files.sort_by(|a, b| a.queue_length.cmp(&b.queue_length));
+                all_files.sort_by_key(|f| f.queue_length);
             }
             _ => {
                 // Invalid sort parameter, ignore
@@ -3,15 +3,20 @@
 use axum::{
     extract::{Path, Query, State},
     response::Json,
+    Extension,
 };
 use serde::{Deserialize, Serialize};
 
 use crate::db::models::{Activity, Indexer};
 use crate::error::{AppError, Result};
+use crate::services::activity::{ActivityBuilder, EventType};
+use crate::services::indexer::providers::INDEXER_TYPES;
 use crate::services::scheduler::{
     run_check_new_episodes_job, run_check_new_releases_job, run_cleanup_completed_job,
-    run_refresh_metadata_job, run_search_missing_job,
+    run_refresh_metadata_job, run_rss_sync_job, run_search_missing_job,
 };
+use crate::services::two_factor;
+use crate::services::Claims;
 use crate::AppState;
 
 // =============================================================================
@@ -41,6 +46,7 @@ pub struct SystemStatus {
     pub database_size_bytes: u64,
     pub downloads: DownloadStats,
     pub vpn: VpnStatus,
+    pub bandwidth: BandwidthStatus,
 }
 
 /// Download statistics.
@@ -54,6 +60,14 @@ pub struct DownloadStats {
     pub total_upload_speed: u64,
 }
 
+/// Global bandwidth limits in effect, in KiB/s (null = unlimited).
+#[derive(Debug, Serialize)]
+pub struct BandwidthStatus {
+    pub alt_speed: bool,
+    pub download_limit: Option<u64>,
+    pub upload_limit: Option<u64>,
+}
+
 /// VPN connection status.
 #[derive(Debug, Serialize)]
 pub struct VpnStatus {
@@ -125,32 +139,60 @@ impl CreateIndexerRequest {
             ));
         }
 
-        // Type validation
-        const VALID_TYPES: &[&str] = &["public", "private", "torznab", "newznab"];
-        if !VALID_TYPES.contains(&self.indexer_type.as_str()) {
-            return Err(AppError::BadRequest(format!(
-                "Invalid indexer type. Must be one of: {}",
-                VALID_TYPES.join(", ")
-            )));
-        }
+        validate_indexer_type(&self.indexer_type)?;
 
         // URL validation
         reqwest::Url::parse(&self.url)
             .map_err(|_| AppError::BadRequest("Invalid URL format".to_string()))?;
 
-        // Priority bounds
         if let Some(priority) = self.priority {
-            if !(0..=100).contains(&priority) {
-                return Err(AppError::BadRequest(
-                    "Priority must be between 0 and 100".to_string(),
-                ));
-            }
+            validate_indexer_priority(priority)?;
+        }
+        if let Some(ref categories) = self.categories {
+            validate_indexer_categories(categories)?;
         }
 
         Ok(())
     }
 }
 
+/// Indexer types must have a provider to be searched.
+fn validate_indexer_type(indexer_type: &str) -> Result<()> {
+    if !INDEXER_TYPES.contains(&indexer_type) {
+        return Err(AppError::BadRequest(format!(
+            "Invalid indexer type. Must be one of: {}",
+            INDEXER_TYPES.join(", ")
+        )));
+    }
+    Ok(())
+}
+
+fn validate_indexer_priority(priority: i32) -> Result<()> {
+    if !(0..=100).contains(&priority) {
+        return Err(AppError::BadRequest(
+            "Priority must be between 0 and 100".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Categories are a JSON list of `movies`, `tv` and `music`.
+fn validate_indexer_categories(categories: &str) -> Result<()> {
+    const VALID_CATEGORIES: &[&str] = &["movies", "tv", "music"];
+
+    let invalid = || {
+        AppError::BadRequest(format!(
+            "Categories must be a JSON list of: {}",
+            VALID_CATEGORIES.join(", ")
+        ))
+    };
+    let list: Vec<String> = serde_json::from_str(categories).map_err(|_| invalid())?;
+    if list.iter().any(|c| !VALID_CATEGORIES.contains(&c.as_str())) {
+        return Err(invalid());
+    }
+    Ok(())
+}
+
 /// Request to update an indexer.
 #[derive(Debug, Deserialize)]
 pub struct UpdateIndexerRequest {
@@ -190,6 +232,13 @@ pub struct MountTestResponse {
     pub error: Option<String>,
 }
 
+/// Instance-wide security settings.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SecuritySettings {
+    /// Admins must enrol in 2FA before they can use the API
+    pub require_admin_two_factor: bool,
+}
+
 /// Success response for operations without specific data.
 #[derive(Debug, Serialize)]
 pub struct SuccessResponse {
@@ -207,6 +256,7 @@ pub enum JobName {
     CheckNewEpisodes,
     CheckNewReleases,
     CleanupCompleted,
+    RssSync,
 }
 
 impl std::fmt::Display for JobName {
@@ -217,6 +267,7 @@ impl std::fmt::Display for JobName {
             JobName::CheckNewEpisodes => write!(f, "check_new_episodes"),
             JobName::CheckNewReleases => write!(f, "check_new_releases"),
             JobName::CleanupCompleted => write!(f, "cleanup_completed"),
+            JobName::RssSync => write!(f, "rss_sync"),
         }
     }
 }
@@ -250,6 +301,10 @@ pub async fn list_jobs() -> Json<Vec<JobInfo>> {
             name: "cleanup_completed".to_string(),
             description: "Clean up torrents that have met seeding requirements".to_string(),
         },
+        JobInfo {
+            name: "rss_sync".to_string(),
+            description: "Grab wanted media from the newest releases of each indexer".to_string(),
+        },
     ])
 }
 
@@ -293,6 +348,12 @@ pub async fn trigger_job(
             });
             JobName::CleanupCompleted
         }
+        "rss_sync" => {
+            tokio::spawn(async move {
+                run_rss_sync_job(&ctx).await;
+            });
+            JobName::RssSync
+        }
         _ => {
             return Err(AppError::NotFound(format!("Job '{}' not found", job_name)));
         }
@@ -385,12 +446,20 @@ pub async fn get_system_status(State(state): State<AppState>) -> Result<Json<Sys
     // Check VPN status
     let vpn = check_vpn_status();
 
+    let limits = state.bandwidth().limits();
+    let bandwidth = BandwidthStatus {
+        alt_speed: state.bandwidth().alt_speed_active(),
+        download_limit: limits.download,
+        upload_limit: limits.upload,
+    };
+
     Ok(Json(SystemStatus {
         version: env!("CARGO_PKG_VERSION").to_string(),
         uptime_seconds: uptime,
         database_size_bytes,
         downloads,
         vpn,
+        bandwidth,
     }))
 }
 
@@ -602,6 +671,7 @@ pub async fn create_indexer(
     )?;
 
     tracing::info!(indexer_id = id, name = %req.name, "Created indexer");
+    state.indexer_manager().reload(&db)?;
 
     Ok(Json(IndexerResponse::from(indexer)))
 }
@@ -629,6 +699,20 @@ pub async fn update_indexer(
         return Err(AppError::NotFound("Indexer not found".to_string()));
     }
 
+    if let Some(ref indexer_type) = req.indexer_type {
+        validate_indexer_type(indexer_type)?;
+    }
+    if let Some(ref url) = req.url {
+        reqwest::Url::parse(url)
+            .map_err(|_| AppError::BadRequest("Invalid URL format".to_string()))?;
+    }
+    if let Some(priority) = req.priority {
+        validate_indexer_priority(priority)?;
+    }
+    if let Some(ref categories) = req.categories {
+        validate_indexer_categories(categories)?;
+    }
+
     // Build dynamic update query
     let mut updates = Vec::new();
     let mut params: Vec<Box<dyn rusqlite::ToSql>> = Vec::new();
@@ -685,6 +769,7 @@ pub async fn update_indexer(
     )?;
 
     tracing::info!(indexer_id = indexer_id, "Updated indexer");
+    state.indexer_manager().reload(&db)?;
 
     Ok(Json(IndexerResponse::from(indexer)))
 }
@@ -705,6 +790,7 @@ pub async fn delete_indexer(
     }
 
     tracing::info!(indexer_id = indexer_id, "Deleted indexer");
+    state.indexer_manager().reload(&db)?;
 
     Ok(Json(SuccessResponse {
         success: true,
@@ -977,6 +1063,52 @@ pub async fn test_mount(
     }
 }
 
+// =============================================================================
+// Security Handlers
+// =============================================================================
+
+/// GET /api/system/security
+///
+/// Get the instance-wide security settings.
+pub async fn get_security(State(state): State<AppState>) -> Result<Json<SecuritySettings>> {
+    let db = state.db.lock().await;
+    Ok(Json(SecuritySettings {
+        require_admin_two_factor: two_factor::admin_two_factor_required(&db)?,
+    }))
+}
+
+/// PUT /api/system/security
+///
+/// Update the instance-wide security settings.
+pub async fn update_security(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Json(body): Json<SecuritySettings>,
+) -> Result<Json<SecuritySettings>> {
+    let db = state.db.lock().await;
+    two_factor::set_admin_two_factor_required(&db, body.require_admin_two_factor)?;
+
+    ActivityBuilder::new(
+        EventType::ConfigChanged,
+        if body.require_admin_two_factor {
+            "Two-factor authentication required for admins"
+        } else {
+            "Two-factor authentication no longer required for admins"
+        },
+    )
+    .user(claims.sub)
+    .metadata(&body)
+    .log_sync(&db);
+
+    tracing::info!(
+        require_admin_two_factor = body.require_admin_two_factor,
+        changed_by = claims.sub,
+        "Security settings updated"
+    );
+
+    Ok(Json(body))
+}
+
 /// Get disk space for a path.
 #[cfg(unix)]
 fn get_disk_space(path: &std::path::Path) -> (Option<u64>, Option<u64>) {
@@ -9,10 +9,17 @@ use futures::future::join_all;
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
 
-use crate::db::models::{Episode, MediaStatus, MediaType, ShowStatus, TvShow};
+use crate::api::downloads::TorrentSourceRequest;
+use crate::api::quality_profiles::validate_profile_reference;
+use crate::db::models::{
+    map_episode_row, map_show_row, Episode, MediaStatus, MediaType, ShowStatus, TvShow,
+};
 use crate::error::{AppError, Result};
 use crate::middleware;
-use crate::services::indexer::{MediaSearchType, Release, SearchQuery as IndexerSearchQuery};
+use crate::services::download_queue::{QueuePriority, QueueRequest, QueuedSource};
+use crate::services::indexer::{
+    MediaSearchType, ProfileMediaType, Release, SearchQuery as IndexerSearchQuery,
+};
 use crate::services::tmdb::TmdbSeason;
 use crate::services::Claims;
 use crate::AppState;
@@ -58,6 +65,8 @@ pub struct AddShowRequest {
     pub monitored: Option<bool>,
     /// Quality limit for downloads (default: "1080p").
     pub quality_limit: Option<String>,
+    /// Quality profile to use instead of the quality limit.
+    pub quality_profile_id: Option<i64>,
 }
 
 /// Request body for updating a TV show.
@@ -67,6 +76,11 @@ pub struct UpdateShowRequest {
     pub monitored: Option<bool>,
     /// Quality limit for downloads.
     pub quality_limit: Option<String>,
+    /// Quality profile to use instead of the quality limit.
+    pub quality_profile_id: Option<i64>,
+    /// Remove the quality profile, going back to the quality limit.
+    #[serde(default)]
+    pub clear_quality_profile: bool,
 }
 
 /// Request body for updating a season (batch update all episodes).
@@ -93,8 +107,12 @@ pub struct DeleteShowQuery {
 /// Request body for downloading a release.
 #[derive(Debug, Deserialize)]
 pub struct DownloadRequest {
-    /// Direct magnet link.
-    pub magnet: String,
+    /// Torrent to download.
+    #[serde(flatten)]
+    pub source: TorrentSourceRequest,
+    /// Indexes of the torrent files to download (default: every file).
+    /// See `POST /api/downloads/files`.
+    pub files: Option<Vec<usize>>,
 }
 
 /// Success response for operations without specific data.
@@ -207,7 +225,8 @@ pub async fn list_shows(
             r#"
             SELECT s.id, s.tmdb_id, s.imdb_id, s.title, s.original_title, s.year_start,
                    s.year_end, s.overview, s.poster_path, s.backdrop_path, s.status,
-                   s.monitored, s.quality_limit, s.added_at, s.updated_at, s.added_by
+                   s.monitored, s.quality_limit, s.added_at, s.updated_at, s.added_by, s.quality_profile_id,
+                   s.tvdb_id
             FROM tv_shows s
             JOIN tv_shows_fts fts ON s.id = fts.rowid
             WHERE tv_shows_fts MATCH ?1
@@ -251,7 +270,8 @@ pub async fn list_shows(
             r#"
             SELECT id, tmdb_id, imdb_id, title, original_title, year_start,
                    year_end, overview, poster_path, backdrop_path, status,
-                   monitored, quality_limit, added_at, updated_at, added_by
+                   monitored, quality_limit, added_at, updated_at, added_by, quality_profile_id,
+                   tvdb_id
             FROM tv_shows
             WHERE (?1 IS NULL OR status = ?1)
               AND (?2 IS NULL OR monitored = ?2)
@@ -320,13 +340,14 @@ pub async fn add_show(
         .and_then(|y| y.parse::<i32>().ok());
 
     // Parse show status from TMDB
-    let show_status = parse_tmdb_status(tmdb_show.status.as_deref());
+    let show_status = ShowStatus::from_tmdb(tmdb_show.status.as_deref());
 
-    // Get IMDB ID from external IDs
+    // Get IMDB and TVDB IDs from external IDs
     let imdb_id = tmdb_show
         .external_ids
         .as_ref()
         .and_then(|e| e.imdb_id.clone());
+    let tvdb_id = tmdb_show.external_ids.as_ref().and_then(|e| e.tvdb_id);
 
     let monitored = body.monitored.unwrap_or(true);
     let quality_limit = body.quality_limit.unwrap_or_else(|| "1080p".to_string());
@@ -347,17 +368,23 @@ pub async fn add_show(
         )));
     }
 
+    if let Some(profile_id) = body.quality_profile_id {
+        validate_profile_reference(&db, profile_id, ProfileMediaType::Video)?;
+    }
+
     // Insert the show
     db.execute(
         r#"
         INSERT INTO tv_shows (
-            tmdb_id, imdb_id, title, original_title, year_start, year_end,
-            overview, poster_path, backdrop_path, status, monitored, quality_limit, added_by
-        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)
+            tmdb_id, imdb_id, tvdb_id, title, original_title, year_start, year_end,
+            overview, poster_path, backdrop_path, status, monitored, quality_limit, added_by,
+            quality_profile_id
+        ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)
         "#,
         rusqlite::params![
             body.tmdb_id,
             imdb_id,
+            tvdb_id,
             tmdb_show.name,
             tmdb_show.original_name,
             year_start,
@@ -369,6 +396,7 @@ pub async fn add_show(
             monitored,
             quality_limit,
             claims.sub,
+            body.quality_profile_id,
         ],
     )?;
 
@@ -464,7 +492,8 @@ pub async fn add_show(
         r#"
         SELECT id, tmdb_id, imdb_id, title, original_title, year_start,
                year_end, overview, poster_path, backdrop_path, status,
-               monitored, quality_limit, added_at, updated_at, added_by
+               monitored, quality_limit, added_at, updated_at, added_by, quality_profile_id,
+               tvdb_id
         FROM tv_shows WHERE id = ?1
         "#,
         [show_id],
@@ -500,7 +529,8 @@ pub async fn get_show(
             r#"
             SELECT id, tmdb_id, imdb_id, title, original_title, year_start,
                    year_end, overview, poster_path, backdrop_path, status,
-                   monitored, quality_limit, added_at, updated_at, added_by
+                   monitored, quality_limit, added_at, updated_at, added_by, quality_profile_id,
+                   tvdb_id
             FROM tv_shows WHERE id = ?1
             "#,
             [show_id],
@@ -570,6 +600,14 @@ pub async fn update_show(
         params.push(Box::new(quality_limit.clone()));
     }
 
+    if let Some(profile_id) = body.quality_profile_id {
+        validate_profile_reference(&db, profile_id, ProfileMediaType::Video)?;
+        updates.push("quality_profile_id = ?");
+        params.push(Box::new(profile_id));
+    } else if body.clear_quality_profile {
+        updates.push("quality_profile_id = NULL");
+    }
+
     if updates.is_empty() {
         return Err(AppError::BadRequest("No fields to update".to_string()));
     }
@@ -586,7 +624,8 @@ pub async fn update_show(
         r#"
         SELECT id, tmdb_id, imdb_id, title, original_title, year_start,
                year_end, overview, poster_path, backdrop_path, status,
-               monitored, quality_limit, added_at, updated_at, added_by
+               monitored, quality_limit, added_at, updated_at, added_by, quality_profile_id,
+               tvdb_id
         FROM tv_shows WHERE id = ?1
         "#,
         [show_id],
@@ -728,11 +767,12 @@ pub async fn refresh_metadata(
         .and_then(|d| d.split('-').next())
         .and_then(|y| y.parse::<i32>().ok());
 
-    let show_status = parse_tmdb_status(tmdb_show.status.as_deref());
+    let show_status = ShowStatus::from_tmdb(tmdb_show.status.as_deref());
     let imdb_id = tmdb_show
         .external_ids
         .as_ref()
         .and_then(|e| e.imdb_id.clone());
+    let tvdb_id = tmdb_show.external_ids.as_ref().and_then(|e| e.tvdb_id);
 
     let db = state.db.lock().await;
 
@@ -749,8 +789,9 @@ pub async fn refresh_metadata(
             poster_path = ?7,
             backdrop_path = ?8,
             status = ?9,
+            tvdb_id = ?10,
             updated_at = datetime('now')
-        WHERE id = ?10
+        WHERE id = ?11
         "#,
         rusqlite::params![
             imdb_id,
@@ -762,6 +803,7 @@ pub async fn refresh_metadata(
             tmdb_show.poster_path,
             tmdb_show.backdrop_path,
             show_status.to_string(),
+            tvdb_id,
             show_id,
         ],
     )?;
@@ -866,7 +908,8 @@ pub async fn refresh_metadata(
         r#"
         SELECT id, tmdb_id, imdb_id, title, original_title, year_start,
                year_end, overview, poster_path, backdrop_path, status,
-               monitored, quality_limit, added_at, updated_at, added_by
+               monitored, quality_limit, added_at, updated_at, added_by, quality_profile_id,
+               tvdb_id
         FROM tv_shows WHERE id = ?1
         "#,
         [show_id],
@@ -1114,12 +1157,17 @@ pub async fn search_episode(
 ) -> Result<Json<Vec<Release>>> {
     let db = state.db.lock().await;
 
-    // Get show title and verify episode exists
-    let (show_title,): (String,) = db
+    // Get show title and IDs, and verify episode exists
+    let (show_title, imdb_id, tmdb_id, tvdb_id): (
+        String,
+        Option<String>,
+        Option<i32>,
+        Option<i32>,
+    ) = db
         .query_row(
-            "SELECT title FROM tv_shows WHERE id = ?1",
+            "SELECT title, imdb_id, tmdb_id, tvdb_id FROM tv_shows WHERE id = ?1",
             [show_id],
-            |row| Ok((row.get(0)?,)),
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
         )
         .map_err(|e| match e {
             rusqlite::Error::QueryReturnedNoRows => {
@@ -1148,10 +1196,22 @@ pub async fn search_episode(
     drop(db); // Release the lock before async operations
 
     // Build search query
-    let query = IndexerSearchQuery::new(&show_title)
+    let mut query = IndexerSearchQuery::new(&show_title)
         .media_type(MediaSearchType::TvEpisode)
         .episode(season_number, episode_number);
 
+    if let Some(ref imdb_id) = imdb_id {
+        query = query.imdb_id(imdb_id);
+    }
+
+    if let Some(tmdb_id) = tmdb_id {
+        query = query.tmdb_id(tmdb_id);
+    }
+
+    if let Some(tvdb_id) = tvdb_id {
+        query = query.tvdb_id(tvdb_id);
+    }
+
     // Search indexers
     let indexer_manager = state.indexer_manager();
     let releases = indexer_manager.search(&query).await?;
@@ -1170,24 +1230,22 @@ pub async fn search_episode(
 
 /// POST /api/tv/:id/season/:s/episode/:e/download
 ///
-/// Starts downloading a release for this episode.
+/// Queues a release for this episode, starting it once a download slot is free.
 pub async fn download_episode(
     State(state): State<AppState>,
     Path((show_id, season_number, episode_number)): Path<(i64, i32, i32)>,
     Json(body): Json<DownloadRequest>,
 ) -> Result<Json<DownloadInfo>> {
-    // Validate magnet link format
-    if !body.magnet.starts_with("magnet:?") {
-        return Err(AppError::BadRequest(
-            "Invalid magnet link format".to_string(),
+    // Validate the torrent source
+    let source = body.source.to_source()?;
+
+    // Downloads need the torrent engine
+    if state.torrent_engine().is_none() {
+        return Err(AppError::Internal(
+            "Torrent engine not available".to_string(),
         ));
     }
 
-    // Get torrent engine
-    let torrent_engine = state
-        .torrent_engine()
-        .ok_or_else(|| AppError::Internal("Torrent engine not available".to_string()))?;
-
     let db = state.db.lock().await;
 
     // Get episode info
@@ -1213,45 +1271,38 @@ pub async fn download_episode(
 
     drop(db); // Release lock before async operation
 
-    // Add magnet to torrent engine
+    // Queue the download, it starts once a slot is free
     let media_ref = crate::services::torrent::MediaRef {
         media_type: MediaType::Episode,
         media_id: episode_id,
     };
 
-    let info_hash = torrent_engine.add_magnet(&body.magnet, media_ref).await?;
-
-    // Create download record and update episode status
-    let db = state.db.lock().await;
-
-    db.execute(
-        r#"
-        INSERT INTO downloads (source_type, source_id, name, media_type, media_id, source_uri, status)
-        VALUES ('torrent', ?1, ?2, 'episode', ?3, ?4, 'downloading')
-        "#,
-        rusqlite::params![info_hash, download_name, episode_id, body.magnet],
-    )?;
-
-    let download_id = db.last_insert_rowid();
-
-    // Update episode status
-    db.execute(
-        "UPDATE episodes SET status = 'downloading', updated_at = datetime('now') WHERE id = ?1",
-        [episode_id],
-    )?;
+    let queued = state
+        .download_queue()
+        .enqueue(QueueRequest {
+            name: download_name.clone(),
+            release_title: None,
+            media: media_ref,
+            source: QueuedSource::Torrent {
+                source,
+                files: body.files,
+            },
+            priority: QueuePriority::Manual,
+        })
+        .await?;
 
     tracing::info!(
         episode_id = episode_id,
-        info_hash = %info_hash,
-        download_id = download_id,
-        "Started episode download"
+        download_id = queued.id,
+        status = %queued.status,
+        "Queued episode download"
     );
 
     Ok(Json(DownloadInfo {
-        id: download_id,
-        info_hash,
+        id: queued.id,
+        info_hash: queued.source_id,
         name: download_name,
-        status: "downloading".to_string(),
+        status: queued.status.to_string(),
     }))
 }
 
@@ -1259,69 +1310,6 @@ pub async fn download_episode(
 // Helpers
 // =============================================================================
 
-/// Maps a database row to a TvShow struct.
-fn map_show_row(row: &rusqlite::Row) -> rusqlite::Result<TvShow> {
-    let status_str: String = row.get(10)?;
-    let status = match status_str.as_str() {
-        "continuing" => ShowStatus::Continuing,
-        "ended" => ShowStatus::Ended,
-        "canceled" => ShowStatus::Canceled,
-        "upcoming" => ShowStatus::Upcoming,
-        _ => ShowStatus::Continuing,
-    };
-
-    Ok(TvShow {
-        id: row.get(0)?,
-        tmdb_id: row.get(1)?,
-        imdb_id: row.get(2)?,
-        title: row.get(3)?,
-        original_title: row.get(4)?,
-        year_start: row.get(5)?,
-        year_end: row.get(6)?,
-        overview: row.get(7)?,
-        poster_path: row.get(8)?,
-        backdrop_path: row.get(9)?,
-        status,
-        monitored: row.get(11)?,
-        quality_limit: row.get(12)?,
-        added_at: row.get(13)?,
-        updated_at: row.get(14)?,
-        added_by: row.get(15)?,
-    })
-}
-
-/// Maps a database row to an Episode struct.
-fn map_episode_row(row: &rusqlite::Row) -> rusqlite::Result<Episode> {
-    let status_str: String = row.get(10)?;
-    let status = match status_str.as_str() {
-        "missing" => MediaStatus::Missing,
-        "searching" => MediaStatus::Searching,
-        "downloading" => MediaStatus::Downloading,
-        "processing" => MediaStatus::Processing,
-        "available" => MediaStatus::Available,
-        _ => MediaStatus::Missing,
-    };
-
-    Ok(Episode {
-        id: row.get(0)?,
-        show_id: row.get(1)?,
-        tmdb_id: row.get(2)?,
-        season_number: row.get(3)?,
-        episode_number: row.get(4)?,
-        title: row.get(5)?,
-        overview: row.get(6)?,
-        air_date: row.get(7)?,
-        runtime_minutes: row.get(8)?,
-        still_path: row.get(9)?,
-        status,
-        monitored: row.get(11)?,
-        file_path: row.get(12)?,
-        file_size: row.get(13)?,
-        created_at: row.get(14)?,
-        updated_at: row.get(15)?,
-    })
-}
-
 /// Groups episodes by season number.
 fn group_episodes_by_season(episodes: Vec<Episode>) -> Vec<SeasonWithEpisodes> {
     let mut seasons: BTreeMap<i32, Vec<Episode>> = BTreeMap::new();
@@ -1348,14 +1336,3 @@ fn group_episodes_by_season(episodes: Vec<Episode>) -> Vec<SeasonWithEpisodes> {
         })
         .collect()
 }
-
-/// Convert TMDB show status to our ShowStatus enum.
-fn parse_tmdb_status(status: Option<&str>) -> ShowStatus {
-    match status {
-        Some("Returning Series") => ShowStatus::Continuing,
-        Some("Ended") => ShowStatus::Ended,
-        Some("Canceled") => ShowStatus::Canceled,
-        Some("In Production") | Some("Planned") => ShowStatus::Upcoming,
-        _ => ShowStatus::Continuing,
-    }
-}
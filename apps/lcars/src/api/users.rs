@@ -4,11 +4,16 @@ use axum::{
     extract::{Path, State},
     Extension, Json,
 };
+use rusqlite::OptionalExtension;
 use serde::{Deserialize, Serialize};
 
 use crate::api::auth::SuccessResponse;
-use crate::db::models::UserRole;
+use crate::db::models::{ApiKey, ApiKeyScope, UserRole};
 use crate::error::{AppError, Result};
+use crate::services::activity::{ActivityBuilder, EventType};
+use crate::services::api_keys::{create_api_key, delete_api_key, list_api_keys};
+use crate::services::auth::revoke_user_sessions;
+use crate::services::two_factor;
 use crate::services::Claims;
 use crate::AppState;
 
@@ -28,6 +33,26 @@ pub struct UpdateUserRequest {
     pub role: Option<UserRole>,
 }
 
+/// Create API key request body.
+#[derive(Debug, Deserialize)]
+pub struct CreateApiKeyRequest {
+    pub name: String,
+    #[serde(default = "default_api_key_scope")]
+    pub scope: ApiKeyScope,
+}
+
+fn default_api_key_scope() -> ApiKeyScope {
+    ApiKeyScope::Read
+}
+
+/// A newly created API key, the only time the key itself is returned.
+#[derive(Debug, Serialize)]
+pub struct CreatedApiKeyResponse {
+    #[serde(flatten)]
+    pub api_key: ApiKey,
+    pub key: String,
+}
+
 /// User response with timestamps.
 #[derive(Debug, Serialize)]
 pub struct UserResponse {
@@ -224,6 +249,19 @@ pub async fn update_user(
     let param_refs: Vec<&dyn rusqlite::ToSql> = params.iter().map(|p| p.as_ref()).collect();
     db.execute(&query, param_refs.as_slice())?;
 
+    // Tokens carry the role, so a role change logs the user out everywhere
+    if body
+        .role
+        .is_some_and(|role| role.to_string() != current_role)
+    {
+        let revoked = revoke_user_sessions(&db, user_id)?;
+        tracing::info!(
+            user_id = user_id,
+            sessions = revoked,
+            "Sessions revoked after role change"
+        );
+    }
+
     let user = db.query_row(
         "SELECT id, username, role, created_at, updated_at FROM users WHERE id = ?1",
         [user_id],
@@ -292,8 +330,8 @@ pub async fn delete_user(
         }
     }
 
-    // Delete associated sessions first
-    db.execute("DELETE FROM sessions WHERE user_id = ?1", [user_id])?;
+    // Log the user out everywhere first
+    revoke_user_sessions(&db, user_id)?;
 
     // Delete the user
     db.execute("DELETE FROM users WHERE id = ?1", [user_id])?;
@@ -304,3 +342,179 @@ pub async fn delete_user(
         message: "User deleted successfully".to_string(),
     }))
 }
+
+/// DELETE /api/users/:id/sessions
+///
+/// Logs a user out everywhere by revoking all of their sessions (admin only).
+pub async fn delete_user_sessions(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Path(user_id): Path<i64>,
+) -> Result<Json<SuccessResponse>> {
+    let db = state.db.lock().await;
+
+    let exists: bool = db.query_row(
+        "SELECT EXISTS(SELECT 1 FROM users WHERE id = ?1)",
+        [user_id],
+        |row| row.get(0),
+    )?;
+    if !exists {
+        return Err(AppError::NotFound("User not found".to_string()));
+    }
+
+    let revoked = revoke_user_sessions(&db, user_id)?;
+
+    tracing::info!(
+        user_id = user_id,
+        sessions = revoked,
+        revoked_by = claims.sub,
+        "User logged out by admin"
+    );
+
+    Ok(Json(SuccessResponse {
+        message: format!("Logged out of {} sessions", revoked),
+    }))
+}
+
+/// Returns NotFound unless the user exists.
+fn ensure_user_exists(db: &rusqlite::Connection, user_id: i64) -> Result<()> {
+    let exists: bool = db.query_row(
+        "SELECT EXISTS(SELECT 1 FROM users WHERE id = ?1)",
+        [user_id],
+        |row| row.get(0),
+    )?;
+    if !exists {
+        return Err(AppError::NotFound("User not found".to_string()));
+    }
+    Ok(())
+}
+
+/// GET /api/users/:id/api-keys
+///
+/// Lists a user's API keys (admin only).
+pub async fn list_user_api_keys(
+    State(state): State<AppState>,
+    Path(user_id): Path<i64>,
+) -> Result<Json<Vec<ApiKey>>> {
+    let db = state.db.lock().await;
+    ensure_user_exists(&db, user_id)?;
+
+    Ok(Json(list_api_keys(&db, user_id)?))
+}
+
+/// POST /api/users/:id/api-keys
+///
+/// Creates an API key for a user (admin only). The key is only returned here.
+pub async fn create_user_api_key(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Path(user_id): Path<i64>,
+    Json(body): Json<CreateApiKeyRequest>,
+) -> Result<Json<CreatedApiKeyResponse>> {
+    let db = state.db.lock().await;
+    ensure_user_exists(&db, user_id)?;
+
+    let (api_key, key) = create_api_key(&db, user_id, &body.name, body.scope)?;
+
+    ActivityBuilder::new(
+        EventType::ApiKeyCreated,
+        format!("API key '{}' created", api_key.name),
+    )
+    .user(user_id)
+    .metadata(&serde_json::json!({
+        "api_key_id": api_key.id,
+        "api_key": api_key.name,
+        "scope": api_key.scope,
+        "created_by": claims.sub,
+    }))
+    .log_sync(&db);
+
+    tracing::info!(
+        user_id = user_id,
+        key_id = api_key.id,
+        created_by = claims.sub,
+        "API key created"
+    );
+
+    Ok(Json(CreatedApiKeyResponse { api_key, key }))
+}
+
+/// DELETE /api/users/:id/api-keys/:key_id
+///
+/// Revokes one of a user's API keys (admin only).
+pub async fn delete_user_api_key(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Path((user_id, key_id)): Path<(i64, i64)>,
+) -> Result<Json<SuccessResponse>> {
+    let db = state.db.lock().await;
+
+    let name: Option<String> = db
+        .query_row(
+            "SELECT name FROM api_keys WHERE id = ?1 AND user_id = ?2",
+            [key_id, user_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    let Some(name) = name else {
+        return Err(AppError::NotFound("API key not found".to_string()));
+    };
+
+    delete_api_key(&db, user_id, key_id)?;
+
+    ActivityBuilder::new(
+        EventType::ApiKeyDeleted,
+        format!("API key '{}' deleted", name),
+    )
+    .user(user_id)
+    .metadata(&serde_json::json!({
+        "api_key_id": key_id,
+        "api_key": name,
+        "deleted_by": claims.sub,
+    }))
+    .log_sync(&db);
+
+    tracing::info!(
+        user_id = user_id,
+        key_id = key_id,
+        deleted_by = claims.sub,
+        "API key deleted"
+    );
+
+    Ok(Json(SuccessResponse {
+        message: "API key deleted".to_string(),
+    }))
+}
+
+/// DELETE /api/users/:id/two-factor
+///
+/// Turns off a user's 2FA, for when they lost their authenticator and
+/// recovery codes (admin only).
+pub async fn delete_user_two_factor(
+    State(state): State<AppState>,
+    Extension(claims): Extension<Claims>,
+    Path(user_id): Path<i64>,
+) -> Result<Json<SuccessResponse>> {
+    let db = state.db.lock().await;
+    ensure_user_exists(&db, user_id)?;
+
+    two_factor::disable(&db, user_id)?;
+
+    ActivityBuilder::new(
+        EventType::TwoFactorDisabled,
+        "Two-factor authentication reset by an admin",
+    )
+    .user(user_id)
+    .metadata(&serde_json::json!({ "reset_by": claims.sub }))
+    .log_sync(&db);
+
+    tracing::info!(
+        user_id = user_id,
+        reset_by = claims.sub,
+        "Two-factor authentication reset"
+    );
+
+    Ok(Json(SuccessResponse {
+        message: "Two-factor authentication disabled".to_string(),
+    }))
+}
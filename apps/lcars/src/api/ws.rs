@@ -12,7 +12,7 @@ use serde::{Deserialize, Serialize};
 use tokio::sync::broadcast;
 
 use crate::error::Result;
-use crate::services::torrent::TorrentEvent;
+use crate::services::torrent::{stall_message, TorrentEvent};
 use crate::AppState;
 
 // =============================================================================
@@ -89,8 +89,11 @@ pub async fn ws_handler(
     State(state): State<AppState>,
     Query(query): Query<WsQuery>,
 ) -> Result<Response> {
-    // Validate JWT token
-    let _claims = state.auth_service().verify_token(&query.token)?;
+    // Validate JWT token and its session
+    let _claims = {
+        let db = state.db.lock().await;
+        state.auth_service().verify_session(&db, &query.token)?
+    };
 
     tracing::debug!("WebSocket connection authenticated");
 
@@ -219,6 +222,12 @@ fn convert_torrent_event(event: TorrentEvent) -> WsMessage {
             error_message: Some(message),
         },
 
+        TorrentEvent::Stalled { info_hash, minutes } => WsMessage::DownloadStatus {
+            info_hash,
+            status: "failed".to_string(),
+            error_message: Some(stall_message(minutes)),
+        },
+
         TorrentEvent::Removed { info_hash } => WsMessage::DownloadRemoved { info_hash },
 
         TorrentEvent::Paused { info_hash } => WsMessage::DownloadStatus {
@@ -7,6 +7,8 @@ use serde::Deserialize;
 use std::path::PathBuf;
 
 use crate::error::AppError;
+use crate::middleware::TrustedProxy;
+use crate::services::bandwidth::parse_schedule;
 
 /// Main application configuration
 #[derive(Debug, Clone, Deserialize)]
@@ -24,10 +26,16 @@ pub struct Config {
     #[serde(default)]
     pub soulseek: SoulseekConfig,
     #[serde(default)]
+    pub bandwidth: BandwidthConfig,
+    #[serde(default)]
+    pub login: LoginConfig,
+    #[serde(default)]
     pub storage: StorageConfig,
     #[serde(default)]
     pub scheduler: SchedulerConfig,
     #[serde(default)]
+    pub search: SearchConfig,
+    #[serde(default)]
     pub music: MusicConfig,
     #[serde(default)]
     pub wireguard: Option<WireGuardConfig>,
@@ -47,6 +55,9 @@ pub struct ServerConfig {
     /// Allowed CORS origins (empty means same-origin only)
     #[serde(default)]
     pub cors_origins: Vec<String>,
+    /// Reverse proxies (addresses or CIDR networks) whose X-Forwarded-For is believed
+    #[serde(default)]
+    pub trusted_proxies: Vec<TrustedProxy>,
 }
 
 // Custom Debug implementation to avoid exposing jwt_secret
@@ -71,6 +82,7 @@ impl Default for ServerConfig {
             jwt_secret: None,
             secure_cookies: false,
             cors_origins: Vec::new(),
+            trusted_proxies: Vec::new(),
         }
     }
 }
@@ -145,6 +157,13 @@ pub struct TorrentConfig {
     pub bind_interface: String,
     #[serde(default = "default_max_connections")]
     pub max_connections: u32,
+    /// Torrents downloading at once, others wait in the download queue (0 = unlimited)
+    #[serde(default = "default_max_active_downloads")]
+    pub max_active_downloads: usize,
+    /// Minutes a download may go without progress before it is failed and
+    /// its release blocklisted (0 = never)
+    #[serde(default = "default_stall_timeout_minutes")]
+    pub stall_timeout_minutes: u64,
     #[serde(default = "default_port_range")]
     pub port_range: (u16, u16),
     #[serde(default)]
@@ -157,6 +176,8 @@ impl Default for TorrentConfig {
             download_dir: default_download_dir(),
             bind_interface: String::new(),
             max_connections: default_max_connections(),
+            max_active_downloads: default_max_active_downloads(),
+            stall_timeout_minutes: default_stall_timeout_minutes(),
             port_range: default_port_range(),
             seeding: SeedingConfig::default(),
         }
@@ -171,6 +192,14 @@ fn default_max_connections() -> u32 {
     100
 }
 
+fn default_max_active_downloads() -> usize {
+    5
+}
+
+fn default_stall_timeout_minutes() -> u64 {
+    60
+}
+
 fn default_port_range() -> (u16, u16) {
     (6881, 6889)
 }
@@ -208,6 +237,68 @@ fn default_time_limit() -> u64 {
     48
 }
 
+/// Bandwidth limits shared by torrent and Soulseek transfers, in KiB/s (0 = unlimited)
+#[derive(Debug, Clone, Deserialize, Default)]
+pub struct BandwidthConfig {
+    #[serde(default)]
+    pub download_limit: u64,
+    #[serde(default)]
+    pub upload_limit: u64,
+    #[serde(default)]
+    pub alt_speed: AltSpeedConfig,
+}
+
+/// Alternate ("turtle mode") limits, turned on and off on a cron schedule
+#[derive(Debug, Clone, Deserialize, Default)]
+pub struct AltSpeedConfig {
+    #[serde(default)]
+    pub download_limit: u64,
+    #[serde(default)]
+    pub upload_limit: u64,
+    /// Cron expression turning the alternate limits on (empty = never)
+    #[serde(default)]
+    pub start: String,
+    /// Cron expression turning the alternate limits off
+    #[serde(default)]
+    pub end: String,
+}
+
+/// Failed login throttling, tracked per client IP and per username
+#[derive(Debug, Clone, Deserialize)]
+pub struct LoginConfig {
+    /// Failures allowed before delays kick in
+    #[serde(default = "default_login_free_attempts")]
+    pub free_attempts: u32,
+    /// Failures that lock logins out; delays double on each failure before that
+    #[serde(default = "default_login_max_attempts")]
+    pub max_attempts: u32,
+    /// How long a lockout lasts, and how long failures are remembered
+    #[serde(default = "default_login_lockout_minutes")]
+    pub lockout_minutes: u64,
+}
+
+impl Default for LoginConfig {
+    fn default() -> Self {
+        Self {
+            free_attempts: default_login_free_attempts(),
+            max_attempts: default_login_max_attempts(),
+            lockout_minutes: default_login_lockout_minutes(),
+        }
+    }
+}
+
+fn default_login_free_attempts() -> u32 {
+    3
+}
+
+fn default_login_max_attempts() -> u32 {
+    10
+}
+
+fn default_login_lockout_minutes() -> u64 {
+    15
+}
+
 /// Soulseek client configuration
 #[derive(Clone, Deserialize)]
 pub struct SoulseekConfig {
@@ -230,6 +321,7 @@ pub struct SoulseekConfig {
     pub share_hidden: bool,
     #[serde(default = "default_upload_slots")]
     pub upload_slots: u32,
+    /// Upload speed cap in KiB/s, on top of the global bandwidth limits
     #[serde(default)]
     pub upload_speed_limit: Option<u64>,
     #[serde(default)]
@@ -587,6 +679,8 @@ pub struct SchedulerConfig {
     pub check_new_releases: String,
     #[serde(default = "default_cleanup_completed")]
     pub cleanup_completed: String,
+    #[serde(default = "default_rss_sync")]
+    pub rss_sync: String,
 }
 
 impl Default for SchedulerConfig {
@@ -597,6 +691,7 @@ impl Default for SchedulerConfig {
             check_new_episodes: default_check_new_episodes(),
             check_new_releases: default_check_new_releases(),
             cleanup_completed: default_cleanup_completed(),
+            rss_sync: default_rss_sync(),
         }
     }
 }
@@ -621,6 +716,54 @@ fn default_cleanup_completed() -> String {
     "0 0 * * * *".to_string()
 }
 
+fn default_rss_sync() -> String {
+    "0 */15 * * * *".to_string()
+}
+
+/// Automatic search and release selection configuration
+#[derive(Debug, Clone, Deserialize)]
+pub struct SearchConfig {
+    /// Minimum number of seeders for a release to be grabbed automatically
+    #[serde(default = "default_min_seeders")]
+    pub min_seeders: u32,
+    /// Acceptable movie release size in MB (min, max)
+    #[serde(default = "default_movie_size_mb")]
+    pub movie_size_mb: (u64, u64),
+    /// Acceptable episode release size in MB (min, max)
+    #[serde(default = "default_episode_size_mb")]
+    pub episode_size_mb: (u64, u64),
+    /// Acceptable album release size in MB (min, max)
+    #[serde(default = "default_album_size_mb")]
+    pub album_size_mb: (u64, u64),
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        Self {
+            min_seeders: default_min_seeders(),
+            movie_size_mb: default_movie_size_mb(),
+            episode_size_mb: default_episode_size_mb(),
+            album_size_mb: default_album_size_mb(),
+        }
+    }
+}
+
+fn default_min_seeders() -> u32 {
+    3
+}
+
+fn default_movie_size_mb() -> (u64, u64) {
+    (300, 40_000)
+}
+
+fn default_episode_size_mb() -> (u64, u64) {
+    (50, 8_000)
+}
+
+fn default_album_size_mb() -> (u64, u64) {
+    (20, 5_000)
+}
+
 /// Music acquisition configuration
 #[derive(Debug, Clone, Deserialize)]
 pub struct MusicConfig {
@@ -633,6 +776,9 @@ pub struct MusicConfig {
     /// Quality preferences for search result matching
     #[serde(default)]
     pub quality: MusicQualityConfig,
+    /// Filters for albums discovered by the check_new_releases job
+    #[serde(default)]
+    pub releases: MusicReleaseConfig,
 }
 
 impl Default for MusicConfig {
@@ -641,6 +787,7 @@ impl Default for MusicConfig {
             search_sources: default_search_sources(),
             auto_download_source: default_auto_download_source(),
             quality: MusicQualityConfig::default(),
+            releases: MusicReleaseConfig::default(),
         }
     }
 }
@@ -689,6 +836,46 @@ fn default_prefer_complete_albums() -> bool {
     true
 }
 
+/// Filters applied to new release groups of monitored artists
+#[derive(Debug, Clone, Deserialize)]
+pub struct MusicReleaseConfig {
+    /// MusicBrainz primary types to add (e.g., "album", "ep", "single")
+    #[serde(default = "default_release_primary_types")]
+    pub primary_types: Vec<String>,
+    /// MusicBrainz secondary types to skip (e.g., "live", "compilation", "remix")
+    #[serde(default = "default_release_excluded_secondary_types")]
+    pub exclude_secondary_types: Vec<String>,
+    /// Only add announced releases due within this many days
+    #[serde(default = "default_release_future_cutoff_days")]
+    pub future_cutoff_days: u32,
+}
+
+impl Default for MusicReleaseConfig {
+    fn default() -> Self {
+        Self {
+            primary_types: default_release_primary_types(),
+            exclude_secondary_types: default_release_excluded_secondary_types(),
+            future_cutoff_days: default_release_future_cutoff_days(),
+        }
+    }
+}
+
+fn default_release_primary_types() -> Vec<String> {
+    vec!["album".to_string(), "ep".to_string()]
+}
+
+fn default_release_excluded_secondary_types() -> Vec<String> {
+    vec![
+        "live".to_string(),
+        "compilation".to_string(),
+        "remix".to_string(),
+    ]
+}
+
+fn default_release_future_cutoff_days() -> u32 {
+    30
+}
+
 impl Config {
     /// Load configuration from file and environment variables.
     ///
@@ -714,9 +901,13 @@ impl Config {
             .set_default("musicbrainz.rate_limit_ms", 1000)?
             .set_default("torrent.download_dir", "./downloads")?
             .set_default("torrent.max_connections", 100)?
+            .set_default("torrent.max_active_downloads", 5)?
             .set_default("torrent.seeding.enabled", true)?
             .set_default("torrent.seeding.ratio_limit", 1.0)?
             .set_default("torrent.seeding.time_limit_hours", 48)?
+            .set_default("login.free_attempts", 3)?
+            .set_default("login.max_attempts", 10)?
+            .set_default("login.lockout_minutes", 15)?
             // Add config file (optional)
             .add_source(File::with_name(config_path).required(false))
             // Override with environment variables
@@ -748,6 +939,29 @@ impl Config {
             tracing::warn!("TMDB API key not configured - movie/TV metadata lookups will fail");
         }
 
+        let alt_speed = &self.bandwidth.alt_speed;
+        if alt_speed.start.is_empty() != alt_speed.end.is_empty() {
+            return Err(AppError::Config(config::ConfigError::Message(
+                "bandwidth.alt_speed needs both a start and an end schedule".to_string(),
+            )));
+        }
+        for cron in [&alt_speed.start, &alt_speed.end] {
+            if !cron.is_empty() {
+                parse_schedule(cron).map_err(|e| {
+                    AppError::Config(config::ConfigError::Message(format!(
+                        "Invalid bandwidth.alt_speed schedule: {}",
+                        e
+                    )))
+                })?;
+            }
+        }
+
+        if self.login.max_attempts <= self.login.free_attempts {
+            return Err(AppError::Config(config::ConfigError::Message(
+                "login.max_attempts must be greater than login.free_attempts".to_string(),
+            )));
+        }
+
         Ok(())
     }
 
@@ -786,8 +1000,74 @@ mod tests {
     fn test_torrent_defaults() {
         let config = Config::load_from("nonexistent.toml").unwrap();
         assert_eq!(config.torrent.max_connections, 100);
+        assert_eq!(config.torrent.max_active_downloads, 5);
         assert!(config.torrent.seeding.enabled);
         assert_eq!(config.torrent.seeding.ratio_limit, 1.0);
         assert_eq!(config.torrent.seeding.time_limit_hours, 48);
     }
+
+    #[test]
+    fn test_search_defaults() {
+        let config = Config::load_from("nonexistent.toml").unwrap();
+        assert_eq!(config.search.min_seeders, 3);
+        assert_eq!(config.search.movie_size_mb, (300, 40_000));
+        assert_eq!(config.search.episode_size_mb, (50, 8_000));
+        assert_eq!(config.search.album_size_mb, (20, 5_000));
+    }
+
+    #[test]
+    fn test_music_release_defaults() {
+        let config = Config::load_from("nonexistent.toml").unwrap();
+        assert_eq!(config.music.releases.primary_types, vec!["album", "ep"]);
+        assert_eq!(
+            config.music.releases.exclude_secondary_types,
+            vec!["live", "compilation", "remix"]
+        );
+        assert_eq!(config.music.releases.future_cutoff_days, 30);
+    }
+
+    #[test]
+    fn test_bandwidth_alt_speed_validation() {
+        let dir = tempfile::tempdir().unwrap();
+        let load = |alt_speed: &str| {
+            let path = dir.path().join("config.toml");
+            std::fs::write(&path, format!("[bandwidth.alt_speed]\n{}", alt_speed)).unwrap();
+            Config::load_from(path.to_str().unwrap())
+        };
+
+        let config =
+            load("download_limit = 100\nstart = \"0 0 18 * * *\"\nend = \"0 0 23 * * *\"").unwrap();
+        assert_eq!(config.bandwidth.download_limit, 0);
+        assert_eq!(config.bandwidth.alt_speed.download_limit, 100);
+
+        // Both ends of the window are required, and must be valid cron expressions
+        assert!(load("start = \"0 0 18 * * *\"").is_err());
+        assert!(load("start = \"at six\"\nend = \"0 0 23 * * *\"").is_err());
+    }
+
+    #[test]
+    fn test_login_and_proxy_config() {
+        let config = Config::load_from("nonexistent.toml").unwrap();
+        assert_eq!(config.login.free_attempts, 3);
+        assert_eq!(config.login.max_attempts, 10);
+        assert_eq!(config.login.lockout_minutes, 15);
+        assert!(config.server.trusted_proxies.is_empty());
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(
+            &path,
+            "[server]\ntrusted_proxies = [\"127.0.0.1\", \"172.16.0.0/12\"]\n",
+        )
+        .unwrap();
+        let config = Config::load_from(path.to_str().unwrap()).unwrap();
+        assert_eq!(config.server.trusted_proxies.len(), 2);
+        assert!(config.server.trusted_proxies[1].contains("172.20.1.1".parse().unwrap()));
+
+        std::fs::write(&path, "[server]\ntrusted_proxies = [\"proxy.local\"]\n").unwrap();
+        assert!(Config::load_from(path.to_str().unwrap()).is_err());
+
+        std::fs::write(&path, "[login]\nfree_attempts = 5\nmax_attempts = 5\n").unwrap();
+        assert!(Config::load_from(path.to_str().unwrap()).is_err());
+    }
 }
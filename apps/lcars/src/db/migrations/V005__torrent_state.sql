@@ -0,0 +1,12 @@
+-- Torrent state persistence
+-- Keeps what the torrent engine needs to pick torrents back up after a restart
+
+-- When the torrent finished downloading and started seeding, for time-based seeding limits
+ALTER TABLE downloads ADD COLUMN seeding_started_at TEXT;
+
+-- When the torrent left the engine; torrents without it are restored on startup
+ALTER TABLE downloads ADD COLUMN removed_at TEXT;
+
+-- Torrents that completed before this migration are assumed to be gone from the engine
+UPDATE downloads SET removed_at = completed_at
+WHERE source_type = 'torrent' AND status = 'completed';
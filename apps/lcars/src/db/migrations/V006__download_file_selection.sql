@@ -0,0 +1,4 @@
+-- Selective file download
+-- Indexes of the files to download from a multi-file torrent, as a JSON array.
+-- NULL downloads every file.
+ALTER TABLE downloads ADD COLUMN selected_files TEXT;
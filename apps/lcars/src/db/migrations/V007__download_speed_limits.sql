@@ -0,0 +1,4 @@
+-- Per-download speed limits
+-- Caps in KiB/s applied on top of the global bandwidth limits. NULL = no cap.
+ALTER TABLE downloads ADD COLUMN download_limit INTEGER;
+ALTER TABLE downloads ADD COLUMN upload_limit INTEGER;
@@ -0,0 +1,13 @@
+-- Download queue
+-- Downloads wait as 'queued' until a slot frees up, starting in queue_order (1 first).
+-- priority is 1 for downloads requested by a user and 0 for automatic grabs;
+-- wanted_since is when the media started being wanted, older goes first.
+ALTER TABLE downloads ADD COLUMN priority INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE downloads ADD COLUMN wanted_since TEXT;
+ALTER TABLE downloads ADD COLUMN queue_order INTEGER;
+
+CREATE INDEX idx_downloads_queue ON downloads(status, queue_order);
+
+-- Torrents the engine was still initializing used to be recorded as queued
+UPDATE downloads SET status = 'downloading'
+WHERE source_type = 'torrent' AND status = 'queued';
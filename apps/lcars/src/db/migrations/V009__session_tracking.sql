@@ -0,0 +1,5 @@
+-- Session tracking
+-- Every issued token has a row in sessions keyed by the SHA-256 of its id (jti);
+-- tokens without a row are rejected, so deleting rows logs users out.
+ALTER TABLE sessions ADD COLUMN user_agent TEXT;
+ALTER TABLE sessions ADD COLUMN last_used_at TEXT;
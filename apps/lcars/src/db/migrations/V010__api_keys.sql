@@ -0,0 +1,16 @@
+-- Per-user API keys
+-- Keys are shown once when created and stored as SHA-256 hashes. A key acts as
+-- its user; read-scoped keys may only make GET requests.
+CREATE TABLE api_keys (
+    id INTEGER PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    key_hash TEXT NOT NULL UNIQUE,
+    key_prefix TEXT NOT NULL,
+    scope TEXT NOT NULL DEFAULT 'read' CHECK (scope IN ('read', 'full')),
+    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+    last_used_at TEXT,
+    UNIQUE (user_id, name)
+);
+
+CREATE INDEX idx_api_keys_user ON api_keys(user_id);
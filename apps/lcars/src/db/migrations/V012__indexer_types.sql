@@ -0,0 +1,6 @@
+-- Indexers are loaded from this table, so each row names the provider that
+-- handles it. The built-in sites were all stored as 'public'.
+UPDATE indexers SET indexer_type = '1337x' WHERE name = '1337x' AND indexer_type = 'public';
+UPDATE indexers SET indexer_type = 'eztv' WHERE name = 'EZTV' AND indexer_type = 'public';
+UPDATE indexers SET indexer_type = 'yts' WHERE name = 'YTS' AND indexer_type = 'public';
+UPDATE indexers SET indexer_type = 'rutracker' WHERE name = 'Rutracker' AND indexer_type = 'public';
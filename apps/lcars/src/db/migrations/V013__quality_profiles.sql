@@ -0,0 +1,39 @@
+-- Quality profiles
+-- `allowed` is a JSON list of qualities, best first: "<resolution> <source>"
+-- or just "<resolution>" for video (e.g. "1080p BluRay", "720p"), and audio
+-- formats for music (e.g. "FLAC"). Files below `cutoff` are upgraded when a
+-- better allowed release appears. Sizes are in MB per minute of runtime.
+CREATE TABLE quality_profiles (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL UNIQUE,
+    media_type TEXT NOT NULL CHECK (media_type IN ('video', 'music')),
+    allowed TEXT NOT NULL,
+    cutoff TEXT NOT NULL,
+    preferred_words TEXT NOT NULL DEFAULT '[]',
+    rejected_words TEXT NOT NULL DEFAULT '[]',
+    min_size_mb_per_minute REAL,
+    max_size_mb_per_minute REAL,
+    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+    updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+);
+
+-- Media without a profile keep using quality_limit
+ALTER TABLE movies ADD COLUMN quality_profile_id INTEGER REFERENCES quality_profiles(id) ON DELETE SET NULL;
+ALTER TABLE tv_shows ADD COLUMN quality_profile_id INTEGER REFERENCES quality_profiles(id) ON DELETE SET NULL;
+ALTER TABLE artists ADD COLUMN quality_profile_id INTEGER REFERENCES quality_profiles(id) ON DELETE SET NULL;
+
+-- Quality of the file in the library, compared against the cutoff
+ALTER TABLE movies ADD COLUMN file_quality TEXT;
+ALTER TABLE episodes ADD COLUMN file_quality TEXT;
+ALTER TABLE albums ADD COLUMN file_quality TEXT;
+
+INSERT INTO quality_profiles (name, media_type, allowed, cutoff, min_size_mb_per_minute, max_size_mb_per_minute) VALUES
+    ('HD', 'video',
+     '["1080p BluRay", "1080p WEB-DL", "1080p WebRip", "1080p HDTV", "720p BluRay", "720p WEB-DL", "720p WebRip", "720p HDTV"]',
+     '1080p WEB-DL', 5, 100),
+    ('Ultra HD', 'video',
+     '["2160p BluRay", "2160p WEB-DL", "2160p WebRip", "1080p BluRay", "1080p WEB-DL"]',
+     '2160p WEB-DL', 15, 400),
+    ('Lossless', 'music',
+     '["FLAC", "ALAC", "MP3", "AAC"]',
+     'FLAC', NULL, NULL);
@@ -0,0 +1,10 @@
+-- Feed releases already processed by the rss_sync job, so each is only
+-- matched once. `guid` is the release id; old rows are pruned by the job.
+CREATE TABLE rss_seen (
+    guid TEXT PRIMARY KEY,
+    indexer TEXT NOT NULL,
+    title TEXT NOT NULL,
+    seen_at TEXT NOT NULL DEFAULT (datetime('now'))
+);
+
+CREATE INDEX idx_rss_seen_seen_at ON rss_seen(seen_at);
@@ -0,0 +1,19 @@
+-- Releases never to grab again: they failed, stalled, or a user removed them.
+-- A release is blocked when its info hash matches, or for indexers that only
+-- link a .torrent file, when its title does. `reason` is 'failed', 'stalled'
+-- or 'manual'.
+CREATE TABLE blocklist (
+    id INTEGER PRIMARY KEY,
+    info_hash TEXT,
+    title TEXT NOT NULL,
+    media_type TEXT,
+    media_id INTEGER,
+    reason TEXT NOT NULL CHECK (reason IN ('failed', 'stalled', 'manual')),
+    message TEXT,
+    created_at TEXT NOT NULL DEFAULT (datetime('now'))
+);
+
+CREATE INDEX idx_blocklist_info_hash ON blocklist(info_hash);
+
+-- Title of the indexer release a download was grabbed from, when known
+ALTER TABLE downloads ADD COLUMN release_title TEXT;
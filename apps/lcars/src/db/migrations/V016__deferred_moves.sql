@@ -0,0 +1,5 @@
+-- Torrents keep seeding from their files after import, so move rules copy
+-- them instead. Set on downloads whose files are deleted when the torrent is
+-- removed after seeding, which completes the move.
+
+ALTER TABLE downloads ADD COLUMN delete_files_on_removal INTEGER NOT NULL DEFAULT 0;
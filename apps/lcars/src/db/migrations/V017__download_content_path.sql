@@ -0,0 +1,10 @@
+-- Where a torrent's files were when they were imported. Completed torrents
+-- are only restored on startup while their files are still there, otherwise
+-- the engine would download the whole release again.
+
+ALTER TABLE downloads ADD COLUMN content_path TEXT;
+
+-- Torrents imported before the path was recorded may have had their files
+-- moved into the library, they are not restored
+UPDATE downloads SET removed_at = datetime('now')
+WHERE source_type = 'torrent' AND status = 'completed' AND removed_at IS NULL;
@@ -0,0 +1,4 @@
+-- TheTVDB id of a show, sent to indexers that search TV by tvdbid. Filled in
+-- from TMDB's external ids when a show is added or refreshed.
+
+ALTER TABLE tv_shows ADD COLUMN tvdb_id INTEGER;
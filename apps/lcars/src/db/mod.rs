@@ -92,6 +92,22 @@ mod tests {
         assert_eq!(count, 4);
     }
 
+    #[test]
+    fn test_default_indexer_types() {
+        let conn = init_db_memory().expect("Failed to initialize in-memory database");
+
+        let types: Vec<String> = conn
+            .prepare("SELECT indexer_type FROM indexers ORDER BY id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .filter_map(|r| r.ok())
+            .collect();
+
+        // V012 gives the built-in sites their own types
+        assert_eq!(types, vec!["1337x", "eztv", "yts", "rutracker"]);
+    }
+
     #[test]
     fn test_foreign_keys_enabled() {
         let conn = init_db_memory().expect("Failed to initialize in-memory database");
@@ -145,6 +161,143 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_downloads_table_has_torrent_state_columns() {
+        let conn = init_db_memory().expect("Failed to initialize in-memory database");
+
+        let mut stmt = conn
+            .prepare("PRAGMA table_info(downloads)")
+            .expect("Failed to prepare statement");
+
+        let columns: Vec<String> = stmt
+            .query_map([], |row| row.get::<_, String>(1))
+            .unwrap()
+            .filter_map(|r| r.ok())
+            .collect();
+
+        // V005 migration columns
+        assert!(
+            columns.contains(&"seeding_started_at".to_string()),
+            "downloads should have seeding_started_at column"
+        );
+        assert!(
+            columns.contains(&"removed_at".to_string()),
+            "downloads should have removed_at column"
+        );
+    }
+
+    #[test]
+    fn test_downloads_table_has_selected_files_column() {
+        let conn = init_db_memory().expect("Failed to initialize in-memory database");
+
+        let has_column: bool = conn
+            .query_row(
+                "SELECT COUNT(*) > 0 FROM pragma_table_info('downloads') WHERE name = 'selected_files'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+
+        // V006 migration column
+        assert!(has_column, "downloads should have selected_files column");
+    }
+
+    #[test]
+    fn test_downloads_table_has_speed_limit_columns() {
+        let conn = init_db_memory().expect("Failed to initialize in-memory database");
+
+        let count: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM pragma_table_info('downloads') WHERE name IN ('download_limit', 'upload_limit')",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+
+        // V007 migration columns
+        assert_eq!(count, 2, "downloads should have speed limit columns");
+    }
+
+    #[test]
+    fn test_downloads_table_has_queue_columns() {
+        let conn = init_db_memory().expect("Failed to initialize in-memory database");
+
+        let count: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM pragma_table_info('downloads') WHERE name IN ('priority', 'wanted_since', 'queue_order')",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+
+        // V008 migration columns
+        assert_eq!(count, 3, "downloads should have download queue columns");
+    }
+
+    #[test]
+    fn test_sessions_table_has_tracking_columns() {
+        let conn = init_db_memory().expect("Failed to initialize in-memory database");
+
+        let count: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM pragma_table_info('sessions') WHERE name IN ('user_agent', 'last_used_at')",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+
+        // V009 migration columns
+        assert_eq!(count, 2, "sessions should have tracking columns");
+    }
+
+    #[test]
+    fn test_api_keys_table_exists() {
+        let conn = init_db_memory().expect("Failed to initialize in-memory database");
+
+        let count: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM pragma_table_info('api_keys') WHERE name IN ('user_id', 'name', 'key_hash', 'key_prefix', 'scope', 'last_used_at')",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+
+        // V010 migration columns
+        assert_eq!(count, 6, "api_keys table should exist with its columns");
+    }
+
+    #[test]
+    fn test_two_factor_tables_exist() {
+        let conn = init_db_memory().expect("Failed to initialize in-memory database");
+
+        let user_columns: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM pragma_table_info('users') WHERE name IN ('totp_secret', 'totp_enabled', 'totp_last_step')",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        let recovery_columns: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM pragma_table_info('recovery_codes') WHERE name IN ('user_id', 'code_hash', 'used_at')",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        let settings_columns: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM pragma_table_info('settings') WHERE name IN ('key', 'value', 'updated_at')",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+
+        // V011 migration columns
+        assert_eq!(user_columns, 3, "users should have TOTP columns");
+        assert_eq!(recovery_columns, 3, "recovery_codes table should exist");
+        assert_eq!(settings_columns, 3, "settings table should exist");
+    }
+
     #[test]
     fn test_downloads_source_type_index_exists() {
         let conn = init_db_memory().expect("Failed to initialize in-memory database");
@@ -88,6 +88,19 @@ impl std::fmt::Display for ShowStatus {
     }
 }
 
+impl ShowStatus {
+    /// Convert a TMDB show status to a ShowStatus.
+    pub fn from_tmdb(status: Option<&str>) -> Self {
+        match status {
+            Some("Returning Series") => ShowStatus::Continuing,
+            Some("Ended") => ShowStatus::Ended,
+            Some("Canceled") => ShowStatus::Canceled,
+            Some("In Production") | Some("Planned") => ShowStatus::Upcoming,
+            _ => ShowStatus::Continuing,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
 pub enum DownloadStatus {
@@ -195,6 +208,7 @@ pub struct Movie {
     pub added_at: String,
     pub updated_at: String,
     pub added_by: Option<i64>,
+    pub quality_profile_id: Option<i64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -202,6 +216,7 @@ pub struct TvShow {
     pub id: i64,
     pub tmdb_id: i64,
     pub imdb_id: Option<String>,
+    pub tvdb_id: Option<i32>,
     pub title: String,
     pub original_title: Option<String>,
     pub year_start: Option<i32>,
@@ -215,6 +230,7 @@ pub struct TvShow {
     pub added_at: String,
     pub updated_at: String,
     pub added_by: Option<i64>,
+    pub quality_profile_id: Option<i64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -255,6 +271,7 @@ pub struct Artist {
     pub added_at: String,
     pub updated_at: String,
     pub added_by: Option<i64>,
+    pub quality_profile_id: Option<i64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -347,6 +364,19 @@ pub struct Download {
     /// Position in the remote user's download queue
     #[serde(skip_serializing_if = "Option::is_none")]
     pub queue_position: Option<i32>,
+    // Torrent-specific fields
+    /// Indexes of the torrent files being downloaded (None for every file)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub selected_files: Option<Vec<usize>>,
+    /// Download speed cap in KiB/s, on top of the global limits
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub download_limit: Option<i64>,
+    /// Upload speed cap in KiB/s, on top of the global limits
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upload_limit: Option<i64>,
+    /// Position in the download queue while queued (1 starts first)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub queue_order: Option<i32>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -366,7 +396,261 @@ pub struct Activity {
 pub struct Session {
     pub id: i64,
     pub user_id: i64,
+    #[serde(skip_serializing)]
     pub token_hash: String,
     pub expires_at: String,
     pub created_at: String,
+    /// User agent of the client that logged in
+    pub user_agent: Option<String>,
+    /// Last authenticated request, updated at most once a minute
+    pub last_used_at: Option<String>,
+}
+
+/// What an API key is allowed to do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ApiKeyScope {
+    /// GET and HEAD requests only
+    Read,
+    /// Everything the owning user can do
+    Full,
+}
+
+impl std::fmt::Display for ApiKeyScope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ApiKeyScope::Read => write!(f, "read"),
+            ApiKeyScope::Full => write!(f, "full"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApiKey {
+    pub id: i64,
+    pub user_id: i64,
+    pub name: String,
+    /// Start of the key, to tell keys apart without storing them
+    pub key_prefix: String,
+    pub scope: ApiKeyScope,
+    pub created_at: String,
+    /// Last authenticated request, updated at most once a minute
+    pub last_used_at: Option<String>,
+}
+
+// =============================================================================
+// Row mapping
+// =============================================================================
+
+/// Maps a database row to a Movie struct.
+pub fn map_movie_row(row: &rusqlite::Row) -> rusqlite::Result<Movie> {
+    let status_str: String = row.get(11)?;
+    let status = match status_str.as_str() {
+        "missing" => MediaStatus::Missing,
+        "searching" => MediaStatus::Searching,
+        "downloading" => MediaStatus::Downloading,
+        "processing" => MediaStatus::Processing,
+        "available" => MediaStatus::Available,
+        _ => MediaStatus::Missing,
+    };
+
+    Ok(Movie {
+        id: row.get(0)?,
+        tmdb_id: row.get(1)?,
+        imdb_id: row.get(2)?,
+        title: row.get(3)?,
+        original_title: row.get(4)?,
+        year: row.get(5)?,
+        overview: row.get(6)?,
+        poster_path: row.get(7)?,
+        backdrop_path: row.get(8)?,
+        runtime_minutes: row.get(9)?,
+        genres: row.get(10)?,
+        status,
+        monitored: row.get(12)?,
+        quality_limit: row.get(13)?,
+        file_path: row.get(14)?,
+        file_size: row.get(15)?,
+        added_at: row.get(16)?,
+        updated_at: row.get(17)?,
+        added_by: row.get(18)?,
+        quality_profile_id: row.get(19)?,
+    })
+}
+
+/// Maps a database row to a TvShow struct.
+pub fn map_show_row(row: &rusqlite::Row) -> rusqlite::Result<TvShow> {
+    let status_str: String = row.get(10)?;
+    let status = match status_str.as_str() {
+        "continuing" => ShowStatus::Continuing,
+        "ended" => ShowStatus::Ended,
+        "canceled" => ShowStatus::Canceled,
+        "upcoming" => ShowStatus::Upcoming,
+        _ => ShowStatus::Continuing,
+    };
+
+    Ok(TvShow {
+        id: row.get(0)?,
+        tmdb_id: row.get(1)?,
+        imdb_id: row.get(2)?,
+        tvdb_id: row.get(17)?,
+        title: row.get(3)?,
+        original_title: row.get(4)?,
+        year_start: row.get(5)?,
+        year_end: row.get(6)?,
+        overview: row.get(7)?,
+        poster_path: row.get(8)?,
+        backdrop_path: row.get(9)?,
+        status,
+        monitored: row.get(11)?,
+        quality_limit: row.get(12)?,
+        added_at: row.get(13)?,
+        updated_at: row.get(14)?,
+        added_by: row.get(15)?,
+        quality_profile_id: row.get(16)?,
+    })
+}
+
+/// Maps a database row to an Episode struct.
+pub fn map_episode_row(row: &rusqlite::Row) -> rusqlite::Result<Episode> {
+    let status_str: String = row.get(10)?;
+    let status = match status_str.as_str() {
+        "missing" => MediaStatus::Missing,
+        "searching" => MediaStatus::Searching,
+        "downloading" => MediaStatus::Downloading,
+        "processing" => MediaStatus::Processing,
+        "available" => MediaStatus::Available,
+        _ => MediaStatus::Missing,
+    };
+
+    Ok(Episode {
+        id: row.get(0)?,
+        show_id: row.get(1)?,
+        tmdb_id: row.get(2)?,
+        season_number: row.get(3)?,
+        episode_number: row.get(4)?,
+        title: row.get(5)?,
+        overview: row.get(6)?,
+        air_date: row.get(7)?,
+        runtime_minutes: row.get(8)?,
+        still_path: row.get(9)?,
+        status,
+        monitored: row.get(11)?,
+        file_path: row.get(12)?,
+        file_size: row.get(13)?,
+        created_at: row.get(14)?,
+        updated_at: row.get(15)?,
+    })
+}
+
+/// Maps a database row to an Artist struct.
+pub fn map_artist_row(row: &rusqlite::Row) -> rusqlite::Result<Artist> {
+    Ok(Artist {
+        id: row.get(0)?,
+        mbid: row.get(1)?,
+        name: row.get(2)?,
+        sort_name: row.get(3)?,
+        disambiguation: row.get(4)?,
+        artist_type: row.get(5)?,
+        country: row.get(6)?,
+        begin_date: row.get(7)?,
+        end_date: row.get(8)?,
+        overview: row.get(9)?,
+        image_path: row.get(10)?,
+        monitored: row.get(11)?,
+        quality_limit: row.get(12)?,
+        added_at: row.get(13)?,
+        updated_at: row.get(14)?,
+        added_by: row.get(15)?,
+        quality_profile_id: row.get(16)?,
+    })
+}
+
+/// Maps a database row to an Album struct.
+pub fn map_album_row(row: &rusqlite::Row) -> rusqlite::Result<Album> {
+    let status_str: String = row.get(9)?;
+    let status = match status_str.as_str() {
+        "missing" => AlbumStatus::Missing,
+        "searching" => AlbumStatus::Searching,
+        "downloading" => AlbumStatus::Downloading,
+        "processing" => AlbumStatus::Processing,
+        "partial" => AlbumStatus::Partial,
+        "available" => AlbumStatus::Available,
+        _ => AlbumStatus::Missing,
+    };
+
+    Ok(Album {
+        id: row.get(0)?,
+        mbid: row.get(1)?,
+        artist_id: row.get(2)?,
+        title: row.get(3)?,
+        album_type: row.get(4)?,
+        release_date: row.get(5)?,
+        overview: row.get(6)?,
+        cover_path: row.get(7)?,
+        total_tracks: row.get(8)?,
+        status,
+        monitored: row.get(10)?,
+        quality_limit: row.get(11)?,
+        added_at: row.get(12)?,
+        updated_at: row.get(13)?,
+    })
+}
+
+/// Maps a database row to a Track struct.
+pub fn map_track_row(row: &rusqlite::Row) -> rusqlite::Result<Track> {
+    let status_str: String = row.get(8)?;
+    let status = match status_str.as_str() {
+        "missing" => MediaStatus::Missing,
+        "searching" => MediaStatus::Searching,
+        "downloading" => MediaStatus::Downloading,
+        "processing" => MediaStatus::Processing,
+        "available" => MediaStatus::Available,
+        _ => MediaStatus::Missing,
+    };
+
+    Ok(Track {
+        id: row.get(0)?,
+        mbid: row.get(1)?,
+        album_id: row.get(2)?,
+        artist_id: row.get(3)?,
+        title: row.get(4)?,
+        track_number: row.get(5)?,
+        disc_number: row.get(6)?,
+        duration_ms: row.get(7)?,
+        status,
+        monitored: row.get(9)?,
+        file_path: row.get(10)?,
+        file_size: row.get(11)?,
+        audio_format: row.get(12)?,
+        bitrate: row.get(13)?,
+        sample_rate: row.get(14)?,
+        bit_depth: row.get(15)?,
+        created_at: row.get(16)?,
+        updated_at: row.get(17)?,
+    })
+}
+
+/// Calculate album status based on track statuses.
+///
+/// TODO: Also use this function to update album status when:
+/// - Track status is updated via API
+/// - Album refresh discovers new tracks
+pub fn calculate_album_status(tracks: &[Track]) -> AlbumStatus {
+    if tracks.is_empty() {
+        return AlbumStatus::Missing;
+    }
+
+    let available = tracks
+        .iter()
+        .filter(|t| matches!(t.status, MediaStatus::Available))
+        .count();
+
+    if available == 0 {
+        AlbumStatus::Missing
+    } else if available == tracks.len() {
+        AlbumStatus::Available
+    } else {
+        AlbumStatus::Partial
+    }
 }
@@ -207,6 +207,15 @@ impl IntoResponse for AppError {
             message,
         };
 
+        if let AppError::RateLimited(retry_after) = self {
+            return (
+                status,
+                [(axum::http::header::RETRY_AFTER, retry_after.to_string())],
+                Json(body),
+            )
+                .into_response();
+        }
+
         (status, Json(body)).into_response()
     }
 }
@@ -258,6 +267,7 @@ mod tests {
         let error = AppError::RateLimited(60);
         let response = error.into_response();
         assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()["retry-after"], "60");
     }
 
     #[test]
@@ -21,8 +21,8 @@ pub mod views;
 
 use config::Config;
 use services::{
-    AuthService, IndexerManager, MusicBrainzClient, Scheduler, SoulseekEngine, StorageManager,
-    TmdbClient, TorrentEngine, WireGuardService,
+    AuthService, BandwidthManager, DownloadQueue, IndexerManager, LoginThrottle, MusicBrainzClient,
+    Scheduler, SoulseekEngine, StorageManager, TmdbClient, TorrentEngine, WireGuardService,
 };
 
 /// Application state shared across handlers
@@ -35,6 +35,9 @@ pub struct AppState {
     pub musicbrainz_client: Option<Arc<MusicBrainzClient>>,
     pub indexer_manager: Arc<IndexerManager>,
     pub torrent_engine: Option<Arc<TorrentEngine>>,
+    pub bandwidth: Arc<BandwidthManager>,
+    pub download_queue: Arc<DownloadQueue>,
+    pub login_throttle: Arc<LoginThrottle>,
     pub soulseek_engine: Option<Arc<SoulseekEngine>>,
     pub scheduler: Option<Arc<Scheduler>>,
     pub start_time: std::time::Instant,
@@ -63,6 +66,21 @@ impl AppState {
         &self.indexer_manager
     }
 
+    /// Get a reference to the bandwidth limits manager.
+    pub fn bandwidth(&self) -> &BandwidthManager {
+        &self.bandwidth
+    }
+
+    /// Get a reference to the download queue.
+    pub fn download_queue(&self) -> &DownloadQueue {
+        &self.download_queue
+    }
+
+    /// Get a reference to the failed login throttle.
+    pub fn login_throttle(&self) -> &LoginThrottle {
+        &self.login_throttle
+    }
+
     /// Get a reference to the torrent engine, if initialized.
     pub fn torrent_engine(&self) -> Option<&TorrentEngine> {
         self.torrent_engine.as_deref()
@@ -96,11 +114,14 @@ impl AppState {
     /// Create a job context for manual job execution.
     pub fn job_context(&self) -> services::JobContext {
         services::JobContext {
+            config: Arc::clone(&self.config),
             db: Arc::clone(&self.db),
             tmdb_client: self.tmdb_client.clone(),
             musicbrainz_client: self.musicbrainz_client.clone(),
             indexer_manager: Arc::clone(&self.indexer_manager),
             torrent_engine: self.torrent_engine.clone(),
+            bandwidth: Arc::clone(&self.bandwidth),
+            download_queue: Arc::clone(&self.download_queue),
         }
     }
 }
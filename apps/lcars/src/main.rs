@@ -1,7 +1,7 @@
 use axum::{
     http::{header, Method},
     middleware as axum_mw,
-    routing::{get, post, put},
+    routing::{delete, get, post, put},
     Router,
 };
 use rand::Rng;
@@ -15,8 +15,9 @@ use lcars::{api, config, db, middleware, services, static_files, views, AppState
 
 use config::Config;
 use services::{
-    AuthService, IndexerManager, JobContext, MusicBrainzClient, Scheduler, SoulseekEngine,
-    StorageManager, TmdbClient, TorrentEngine, WireGuardService,
+    AuthService, BandwidthManager, DownloadQueue, IndexerManager, JobContext, MusicBrainzClient,
+    PostProcessor, Scheduler, SoulseekEngine, StorageManager, TmdbClient, TorrentEngine,
+    TorrentSync, WireGuardService,
 };
 
 fn init_tracing() {
@@ -190,8 +191,14 @@ async fn main() {
         }
     };
 
-    // Create indexer manager
+    // Create indexer manager from the indexers table
     let indexer_manager = IndexerManager::new_shared();
+    if let Err(e) = indexer_manager.reload(&conn) {
+        tracing::error!("Failed to load indexers: {}", e);
+    }
+    if let Err(e) = indexer_manager.reload_blocklist(&conn) {
+        tracing::error!("Failed to load blocklist: {}", e);
+    }
     tracing::info!(
         "Indexer manager initialized with {} providers",
         indexer_manager.providers().len()
@@ -235,6 +242,9 @@ async fn main() {
         }
     };
 
+    // Global bandwidth limits, shared by the torrent and Soulseek engines
+    let bandwidth = BandwidthManager::new_shared(config.bandwidth.clone());
+
     // Create torrent engine
     let torrent_engine = match TorrentEngine::new_shared(config.torrent.clone()).await {
         Ok(engine) => {
@@ -244,6 +254,15 @@ async fn main() {
             );
             Some(engine)
         }
+        Err(e) if !config.torrent.bind_interface.is_empty() => {
+            // Running unbound would leak torrent traffic outside the interface
+            tracing::error!("Failed to create torrent engine: {}", e);
+            tracing::error!(
+                interface = %config.torrent.bind_interface,
+                "Refusing to start without binding torrent traffic to the configured interface"
+            );
+            std::process::exit(1);
+        }
         Err(e) => {
             tracing::error!("Failed to create torrent engine: {}", e);
             tracing::warn!("Downloads will be unavailable until torrent engine is fixed");
@@ -251,6 +270,10 @@ async fn main() {
         }
     };
 
+    if let Some(ref torrent) = torrent_engine {
+        torrent.apply_bandwidth_limits(&bandwidth);
+    }
+
     // Enable VPN kill switch if both services are available and kill switch is configured
     if let (Some(ref torrent), Some(ref wg)) = (&torrent_engine, &wireguard_service) {
         if config.wireguard.as_ref().is_some_and(|c| c.kill_switch) {
@@ -278,6 +301,11 @@ async fn main() {
                             tracing::info!("Auto-reconnect is enabled, will retry in background");
                         }
                     }
+                    engine.apply_bandwidth_limits(&bandwidth);
+                    // Accept inbound peer connections (file transfers, shares)
+                    if let Err(e) = engine.start_listener().await {
+                        tracing::warn!("Failed to start Soulseek peer listener: {}", e);
+                    }
                     Some(engine)
                 }
                 Err(e) => {
@@ -291,13 +319,25 @@ async fn main() {
             None
         };
 
+    // Downloads wait here until the engines have a free slot
+    let db = Arc::new(Mutex::new(conn));
+    let download_queue = DownloadQueue::new_shared(
+        Arc::clone(&db),
+        &config,
+        torrent_engine.clone(),
+        soulseek_engine.clone(),
+    );
+
     // Create job context for scheduler
     let job_ctx = JobContext {
-        db: Arc::new(Mutex::new(conn)),
+        config: Arc::new(config.clone()),
+        db,
         tmdb_client: tmdb_client.clone(),
         musicbrainz_client: musicbrainz_client.clone(),
         indexer_manager: indexer_manager.clone(),
         torrent_engine: torrent_engine.clone(),
+        bandwidth: Arc::clone(&bandwidth),
+        download_queue: Arc::clone(&download_queue),
     };
 
     // Create and start scheduler
@@ -318,6 +358,9 @@ async fn main() {
         }
     };
 
+    // Blocklist failed torrents and grab another release for their media
+    services::scheduler::watch_failed_downloads(&job_ctx);
+
     // Create storage manager
     let storage_manager = match StorageManager::new(config.storage.clone()) {
         Ok(manager) => {
@@ -334,15 +377,44 @@ async fn main() {
         }
     };
 
+    // Import completed downloads into the library
+    if let Some(ref storage) = storage_manager {
+        let processor = PostProcessor::new_shared(Arc::clone(&job_ctx.db), Arc::clone(storage));
+        if let Some(ref torrent) = torrent_engine {
+            processor.watch_torrents(Arc::clone(torrent));
+        }
+        if let Some(ref soulseek) = soulseek_engine {
+            processor.watch_soulseek(Arc::clone(soulseek));
+        }
+    }
+
+    // Keep torrent state in the database and pick up torrents from the last run
+    if let Some(ref torrent) = torrent_engine {
+        let sync = TorrentSync::new_shared(Arc::clone(&job_ctx.db), Arc::clone(torrent));
+        sync.watch();
+        if let Err(e) = sync.rehydrate().await {
+            tracing::error!("Failed to restore torrents: {}", e);
+        }
+    }
+
+    // Start downloads queued before the last shutdown
+    if let Err(e) = download_queue.requeue_interrupted().await {
+        tracing::error!("Failed to requeue interrupted downloads: {}", e);
+    }
+    download_queue.watch();
+
     // Create application state
     let state = AppState {
-        config: Arc::new(config.clone()),
+        config: job_ctx.config,
         db: job_ctx.db,
         auth_service: Arc::new(auth_service),
         tmdb_client,
         musicbrainz_client,
         indexer_manager,
         torrent_engine,
+        bandwidth,
+        download_queue,
+        login_throttle: services::LoginThrottle::new_shared(config.login.clone()),
         soulseek_engine,
         scheduler,
         start_time: std::time::Instant::now(),
@@ -350,25 +422,34 @@ async fn main() {
         wireguard_service,
     };
 
-    // Build auth routes (public)
+    // Build auth routes: login is public, the rest manage the user's own
+    // credentials and are not open to API keys
     let auth_routes = Router::new()
         .route("/login", post(api::auth::login))
-        .route(
-            "/logout",
-            post(api::auth::logout).layer(axum_mw::from_fn_with_state(
-                state.clone(),
-                middleware::auth_middleware,
-            )),
-        )
-        .route(
-            "/me",
-            get(api::auth::me).layer(axum_mw::from_fn_with_state(
-                state.clone(),
-                middleware::auth_middleware,
-            )),
+        .route("/login/2fa", post(api::auth::login_two_factor))
+        .merge(
+            Router::new()
+                .route("/logout", post(api::auth::logout))
+                .route("/me", get(api::auth::me))
+                .route("/logout-all", post(api::auth::logout_all))
+                .route("/sessions", get(api::auth::list_sessions))
+                .route("/sessions/{id}", delete(api::auth::delete_session))
+                .route("/2fa", get(api::auth::two_factor_status))
+                .route("/2fa/setup", post(api::auth::two_factor_setup))
+                .route("/2fa/enable", post(api::auth::two_factor_enable))
+                .route("/2fa/disable", post(api::auth::two_factor_disable))
+                .route(
+                    "/2fa/recovery-codes",
+                    post(api::auth::regenerate_recovery_codes),
+                )
+                .layer(axum_mw::from_fn(middleware::reject_api_keys))
+                .layer(axum_mw::from_fn_with_state(
+                    state.clone(),
+                    middleware::auth_middleware,
+                )),
         );
 
-    // Build user routes (admin only)
+    // Build user routes (admin only, not open to API keys)
     let user_routes = Router::new()
         .route(
             "/",
@@ -378,7 +459,21 @@ async fn main() {
             "/{id}",
             put(api::users::update_user).delete(api::users::delete_user),
         )
+        .route("/{id}/sessions", delete(api::users::delete_user_sessions))
+        .route(
+            "/{id}/api-keys",
+            get(api::users::list_user_api_keys).post(api::users::create_user_api_key),
+        )
+        .route(
+            "/{id}/api-keys/{key_id}",
+            delete(api::users::delete_user_api_key),
+        )
+        .route(
+            "/{id}/two-factor",
+            delete(api::users::delete_user_two_factor),
+        )
         .layer(axum_mw::from_fn(middleware::require_admin))
+        .layer(axum_mw::from_fn(middleware::reject_api_keys))
         .layer(axum_mw::from_fn_with_state(
             state.clone(),
             middleware::auth_middleware,
@@ -388,6 +483,15 @@ async fn main() {
     let system_auth_routes = Router::new()
         .route("/status", get(api::system::get_system_status))
         .route("/activity", get(api::system::get_activity))
+        .route(
+            "/quality-profiles",
+            get(api::quality_profiles::list_quality_profiles),
+        )
+        .route(
+            "/quality-profiles/{id}",
+            get(api::quality_profiles::get_quality_profile),
+        )
+        .route("/blocklist", get(api::blocklist::list_blocklist))
         .layer(axum_mw::from_fn_with_state(
             state.clone(),
             middleware::auth_middleware,
@@ -405,6 +509,24 @@ async fn main() {
             put(api::system::update_indexer).delete(api::system::delete_indexer),
         )
         .route("/indexers/{id}/test", post(api::system::test_indexer))
+        .route(
+            "/quality-profiles",
+            post(api::quality_profiles::create_quality_profile),
+        )
+        .route(
+            "/quality-profiles/{id}",
+            put(api::quality_profiles::update_quality_profile)
+                .delete(api::quality_profiles::delete_quality_profile),
+        )
+        .route("/blocklist", delete(api::blocklist::clear_blocklist))
+        .route(
+            "/blocklist/{id}",
+            delete(api::blocklist::delete_blocklist_entry),
+        )
+        .route(
+            "/security",
+            get(api::system::get_security).put(api::system::update_security),
+        )
         .route("/storage/mounts", get(api::system::list_mounts))
         .route("/storage/mounts/{name}/test", post(api::system::test_mount))
         .layer(axum_mw::from_fn(middleware::require_admin))
@@ -518,7 +640,7 @@ async fn main() {
     let vpn_routes = Router::new().merge(vpn_auth_routes).merge(vpn_admin_routes);
 
     // Build HTML views routes for HTMX frontend
-    let html_routes = views::routes();
+    let html_routes = views::routes(state.clone());
 
     // Build main router with state
     let app = Router::new()
@@ -549,5 +671,10 @@ async fn main() {
     tracing::info!("LCARS Backend listening on {}", addr);
 
     let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    axum::serve(
+        listener,
+        app.into_make_service_with_connect_info::<std::net::SocketAddr>(),
+    )
+    .await
+    .unwrap();
 }
@@ -1,19 +1,32 @@
 //! Authentication middleware for LCARS.
 //!
-//! Provides JWT validation and role-based access control.
+//! Provides JWT and API key validation and role-based access control.
+
+use std::collections::HashMap;
 
 use axum::{
     body::Body,
-    extract::State,
-    http::{header::AUTHORIZATION, Request},
+    extract::{OriginalUri, Query, State},
+    http::{header::AUTHORIZATION, HeaderName, Method, Request},
     middleware::Next,
     response::Response,
 };
+use serde_json::json;
 
+use crate::db::models::ApiKey;
 use crate::error::{AppError, Result};
+use crate::services::activity::{ActivityBuilder, EventType};
+use crate::services::api_keys::{authenticate_api_key, scope_allows};
 use crate::services::auth::Claims;
+use crate::services::two_factor;
 use crate::AppState;
 
+/// Header carrying an API key.
+const API_KEY_HEADER: HeaderName = HeaderName::from_static("x-api-key");
+
+/// Query parameter carrying an API key, for clients that cannot set headers.
+const API_KEY_PARAM: &str = "apikey";
+
 /// Extracts the Bearer token from the Authorization header.
 fn extract_bearer_token(request: &Request<Body>) -> Option<&str> {
     request
@@ -24,19 +37,88 @@ fn extract_bearer_token(request: &Request<Body>) -> Option<&str> {
         .strip_prefix("Bearer ")
 }
 
-/// Authentication middleware that validates JWT tokens.
+/// Full path of the request, also inside nested routers which see a stripped one.
+fn original_path(request: &Request<Body>) -> &str {
+    request
+        .extensions()
+        .get::<OriginalUri>()
+        .map_or(request.uri().path(), |uri| uri.path())
+}
+
+/// Extracts an API key from the `X-Api-Key` header or the `apikey` query parameter.
+fn extract_api_key(request: &Request<Body>) -> Option<String> {
+    if let Some(key) = request.headers().get(API_KEY_HEADER) {
+        return key.to_str().ok().map(str::to_string);
+    }
+
+    Query::<HashMap<String, String>>::try_from_uri(request.uri())
+        .ok()?
+        .0
+        .remove(API_KEY_PARAM)
+}
+
+/// Authentication middleware that validates JWT tokens and API keys.
 ///
-/// Extracts the Bearer token from the Authorization header, validates it,
-/// and adds the claims to the request extensions.
+/// Uses the Bearer token from the Authorization header if present, otherwise
+/// an API key. Adds the claims to the request extensions; requests made with
+/// an API key also carry its [`ApiKey`](crate::db::models::ApiKey).
 pub async fn auth_middleware(
     State(state): State<AppState>,
     mut request: Request<Body>,
     next: Next,
 ) -> Result<Response> {
-    let token = extract_bearer_token(&request).ok_or(AppError::Unauthorized)?;
-
-    let auth_service = state.auth_service();
-    let claims = auth_service.verify_token(token)?;
+    let claims = if let Some(token) = extract_bearer_token(&request) {
+        let db = state.db.lock().await;
+        state.auth_service().verify_session(&db, token)?
+    } else if let Some(secret) = extract_api_key(&request) {
+        let db = state.db.lock().await;
+        let auth = authenticate_api_key(&db, &secret)?;
+
+        if !scope_allows(auth.key.scope, request.method()) {
+            tracing::debug!(key_id = auth.key.id, method = %request.method(), "Read-only API key used for a write");
+            return Err(AppError::Forbidden);
+        }
+
+        // Writes are always logged, reads once per hour of use
+        let is_read = matches!(*request.method(), Method::GET | Method::HEAD);
+        if !is_read || auth.idle {
+            let path = original_path(&request);
+
+            ActivityBuilder::new(
+                EventType::ApiKeyUsed,
+                format!(
+                    "API key '{}' used: {} {}",
+                    auth.key.name,
+                    request.method(),
+                    path
+                ),
+            )
+            .user(auth.key.user_id)
+            .metadata(&json!({
+                "api_key_id": auth.key.id,
+                "api_key": auth.key.name,
+                "method": request.method().as_str(),
+                "path": path,
+            }))
+            .log_sync(&db);
+        }
+
+        let claims = auth.claims();
+        request.extensions_mut().insert(auth.key);
+        claims
+    } else {
+        return Err(AppError::Unauthorized);
+    };
+
+    // Admins who must use 2FA but have not enrolled can only reach the auth
+    // endpoints, which is where they enrol
+    if claims.role == "admin" && !original_path(&request).starts_with("/api/auth/") {
+        let db = state.db.lock().await;
+        if two_factor::setup_required(&db, claims.sub, &claims.role)? {
+            tracing::debug!(user_id = claims.sub, "Admin has not set up required 2FA");
+            return Err(AppError::Forbidden);
+        }
+    }
 
     // Add claims to request extensions for downstream handlers
     request.extensions_mut().insert(claims);
@@ -60,6 +142,24 @@ pub async fn require_admin(request: Request<Body>, next: Next) -> Result<Respons
     Ok(next.run(request).await)
 }
 
+/// Middleware that turns away requests made with an API key.
+///
+/// Guards the routes that manage credentials (sessions, 2FA, API keys,
+/// passwords and roles), so a leaked key cannot be turned into lasting
+/// control of an account. Must be used after `auth_middleware`.
+pub async fn reject_api_keys(request: Request<Body>, next: Next) -> Result<Response> {
+    if let Some(key) = request.extensions().get::<ApiKey>() {
+        tracing::debug!(
+            key_id = key.id,
+            path = original_path(&request),
+            "API key used on a credential management route"
+        );
+        return Err(AppError::Forbidden);
+    }
+
+    Ok(next.run(request).await)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -105,4 +205,35 @@ mod tests {
         let token = extract_bearer_token(&request);
         assert_eq!(token, Some(""));
     }
+
+    #[test]
+    fn test_extract_api_key_header() {
+        let request = Request::builder()
+            .uri("/api/movies?apikey=from-query")
+            .header("X-Api-Key", "from-header")
+            .body(Body::empty())
+            .unwrap();
+
+        assert_eq!(extract_api_key(&request), Some("from-header".to_string()));
+    }
+
+    #[test]
+    fn test_extract_api_key_query() {
+        let request = Request::builder()
+            .uri("/api/movies?page=2&apikey=lcars_abc")
+            .body(Body::empty())
+            .unwrap();
+
+        assert_eq!(extract_api_key(&request), Some("lcars_abc".to_string()));
+    }
+
+    #[test]
+    fn test_extract_api_key_missing() {
+        let request = Request::builder()
+            .uri("/api/movies?page=2")
+            .body(Body::empty())
+            .unwrap();
+
+        assert_eq!(extract_api_key(&request), None);
+    }
 }
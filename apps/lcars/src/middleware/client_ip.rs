@@ -0,0 +1,219 @@
+//! Client IP resolution behind reverse proxies.
+//!
+//! `X-Forwarded-For` is only believed when the connection comes from a
+//! trusted proxy, and then read from the right, skipping trusted hops, so a
+//! client cannot pick its own address by sending the header itself.
+
+use std::net::IpAddr;
+use std::str::FromStr;
+
+use axum::http::HeaderMap;
+use serde::{Deserialize, Deserializer};
+
+/// A trusted proxy address or network, e.g. `10.0.0.1` or `172.16.0.0/12`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrustedProxy {
+    network: IpAddr,
+    prefix_len: u8,
+}
+
+impl TrustedProxy {
+    /// Whether `ip` is this address or inside this network.
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.network, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX
+                    .checked_shl(32 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX
+                    .checked_shl(128 - self.prefix_len as u32)
+                    .unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+impl FromStr for TrustedProxy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (addr, prefix) = match s.trim().split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s.trim(), None),
+        };
+
+        let network: IpAddr = addr
+            .parse()
+            .map_err(|_| format!("invalid proxy address '{}'", s))?;
+        let max_len = if network.is_ipv4() { 32 } else { 128 };
+        let prefix_len = match prefix {
+            Some(prefix) => prefix
+                .parse::<u8>()
+                .ok()
+                .filter(|len| *len <= max_len)
+                .ok_or_else(|| format!("invalid prefix length in '{}'", s))?,
+            None => max_len,
+        };
+
+        Ok(Self {
+            network: network.to_canonical(),
+            prefix_len,
+        })
+    }
+}
+
+impl<'de> Deserialize<'de> for TrustedProxy {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+/// Resolves the address of the client behind any trusted proxies.
+///
+/// `peer` is the address of the connection, if known.
+pub fn client_ip(
+    peer: Option<IpAddr>,
+    headers: &HeaderMap,
+    trusted_proxies: &[TrustedProxy],
+) -> Option<IpAddr> {
+    let peer = peer?.to_canonical();
+    let is_trusted = |ip: IpAddr| trusted_proxies.iter().any(|proxy| proxy.contains(ip));
+
+    if !is_trusted(peer) {
+        return Some(peer);
+    }
+
+    // Hops in the order they were appended, across repeated headers
+    let hops: Vec<&str> = headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .collect();
+
+    // Walk back from the nearest hop to the first one not added by a trusted
+    // proxy. Anything left of an unparsable entry cannot be relied on.
+    let mut client = peer;
+    for hop in hops.iter().rev() {
+        let Ok(ip) = hop.parse::<IpAddr>() else {
+            break;
+        };
+        client = ip.to_canonical();
+        if !is_trusted(client) {
+            break;
+        }
+    }
+
+    Some(client)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn proxies(list: &[&str]) -> Vec<TrustedProxy> {
+        list.iter().map(|p| p.parse().unwrap()).collect()
+    }
+
+    fn forwarded(values: &[&str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append("x-forwarded-for", HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_parse_trusted_proxy() {
+        let proxy: TrustedProxy = "10.0.0.0/8".parse().unwrap();
+        assert!(proxy.contains(ip("10.1.2.3")));
+        assert!(!proxy.contains(ip("11.0.0.1")));
+
+        let single: TrustedProxy = "192.168.1.10".parse().unwrap();
+        assert!(single.contains(ip("192.168.1.10")));
+        assert!(!single.contains(ip("192.168.1.11")));
+
+        let v6: TrustedProxy = "fd00::/8".parse().unwrap();
+        assert!(v6.contains(ip("fd12::1")));
+        assert!(!v6.contains(ip("10.0.0.1")));
+
+        // IPv4-mapped addresses match IPv4 networks
+        assert!(proxy.contains(ip("::ffff:10.0.0.1")));
+
+        assert!("0.0.0.0/0"
+            .parse::<TrustedProxy>()
+            .unwrap()
+            .contains(ip("8.8.8.8")));
+        assert!("10.0.0.0/33".parse::<TrustedProxy>().is_err());
+        assert!("proxy.local".parse::<TrustedProxy>().is_err());
+    }
+
+    #[test]
+    fn test_untrusted_peer_ignores_header() {
+        let headers = forwarded(&["1.2.3.4"]);
+        assert_eq!(
+            client_ip(Some(ip("203.0.113.9")), &headers, &proxies(&["10.0.0.0/8"])),
+            Some(ip("203.0.113.9"))
+        );
+        assert_eq!(
+            client_ip(Some(ip("10.0.0.2")), &headers, &[]),
+            Some(ip("10.0.0.2"))
+        );
+    }
+
+    #[test]
+    fn test_trusted_peer_uses_header() {
+        let trusted = proxies(&["10.0.0.0/8"]);
+
+        // Spoofed entries to the left of the real client are ignored
+        let headers = forwarded(&["6.6.6.6, 203.0.113.9, 10.0.0.5"]);
+        assert_eq!(
+            client_ip(Some(ip("10.0.0.2")), &headers, &trusted),
+            Some(ip("203.0.113.9"))
+        );
+
+        // Repeated headers are read as one list
+        let headers = forwarded(&["6.6.6.6", "203.0.113.9"]);
+        assert_eq!(
+            client_ip(Some(ip("10.0.0.2")), &headers, &trusted),
+            Some(ip("203.0.113.9"))
+        );
+
+        // Only proxies in the chain
+        let headers = forwarded(&["10.0.0.7, 10.0.0.5"]);
+        assert_eq!(
+            client_ip(Some(ip("10.0.0.2")), &headers, &trusted),
+            Some(ip("10.0.0.7"))
+        );
+
+        // Garbage before the real client does not matter
+        let headers = forwarded(&["unknown, 203.0.113.9"]);
+        assert_eq!(
+            client_ip(Some(ip("10.0.0.2")), &headers, &trusted),
+            Some(ip("203.0.113.9"))
+        );
+
+        // No header
+        assert_eq!(
+            client_ip(Some(ip("10.0.0.2")), &HeaderMap::new(), &trusted),
+            Some(ip("10.0.0.2"))
+        );
+    }
+
+    #[test]
+    fn test_unknown_peer() {
+        assert_eq!(client_ip(None, &forwarded(&["1.2.3.4"]), &[]), None);
+    }
+}
@@ -1,5 +1,7 @@
 //! Middleware components for the LCARS backend.
 
 mod auth;
+mod client_ip;
 
-pub use auth::{auth_middleware, require_admin};
+pub use auth::{auth_middleware, reject_api_keys, require_admin};
+pub use client_ip::{client_ip, TrustedProxy};
@@ -31,8 +31,14 @@ pub enum EventType {
     // User events
     UserLogin,
     UserLogout,
+    LoginLockedOut,
+    TwoFactorEnabled,
+    TwoFactorDisabled,
     UserCreated,
     UserDeleted,
+    ApiKeyCreated,
+    ApiKeyDeleted,
+    ApiKeyUsed,
 
     // System events
     SystemStarted,
@@ -57,8 +63,14 @@ impl EventType {
             EventType::JobFailed => "job_failed",
             EventType::UserLogin => "user_login",
             EventType::UserLogout => "user_logout",
+            EventType::LoginLockedOut => "login_locked_out",
+            EventType::TwoFactorEnabled => "two_factor_enabled",
+            EventType::TwoFactorDisabled => "two_factor_disabled",
             EventType::UserCreated => "user_created",
             EventType::UserDeleted => "user_deleted",
+            EventType::ApiKeyCreated => "api_key_created",
+            EventType::ApiKeyDeleted => "api_key_deleted",
+            EventType::ApiKeyUsed => "api_key_used",
             EventType::SystemStarted => "system_started",
             EventType::ConfigChanged => "config_changed",
         }
@@ -0,0 +1,310 @@
+//! API keys for scripts and integrations.
+//!
+//! A key is a random token shown once when it is created and stored as its
+//! SHA-256, like session ids. Requests made with a key act as the key's user
+//! with that user's current role; `read` keys are limited to GET and HEAD.
+
+use axum::http::Method;
+use rand::{distributions::Alphanumeric, Rng};
+use rusqlite::{Connection, OptionalExtension};
+
+use crate::db::models::{ApiKey, ApiKeyScope};
+use crate::error::{AppError, Result};
+use crate::services::auth::{hash_session_id, Claims};
+
+/// Prefix of every generated key, so leaked keys are easy to recognise.
+pub const KEY_PREFIX: &str = "lcars_";
+
+/// Number of random characters after the prefix.
+const KEY_RANDOM_LEN: usize = 40;
+
+/// Characters of the key kept in clear for display.
+const DISPLAY_PREFIX_LEN: usize = KEY_PREFIX.len() + 6;
+
+/// A request authenticated with an API key.
+#[derive(Debug, Clone)]
+pub struct ApiKeyAuth {
+    pub key: ApiKey,
+    /// Current role of the key's user
+    pub role: String,
+    /// Whether the key had not been used for an hour before this request
+    pub idle: bool,
+}
+
+impl ApiKeyAuth {
+    /// Claims for handlers, as if the user had logged in.
+    ///
+    /// The session id is empty since key requests have no session.
+    pub fn claims(&self) -> Claims {
+        Claims {
+            sub: self.key.user_id,
+            role: self.role.clone(),
+            exp: 0,
+            iat: 0,
+            jti: String::new(),
+        }
+    }
+}
+
+/// Whether a key with the given scope may make a request with `method`.
+pub fn scope_allows(scope: ApiKeyScope, method: &Method) -> bool {
+    match scope {
+        ApiKeyScope::Full => true,
+        ApiKeyScope::Read => matches!(*method, Method::GET | Method::HEAD),
+    }
+}
+
+fn generate_key() -> String {
+    let random: String = rand::thread_rng()
+        .sample_iter(&Alphanumeric)
+        .take(KEY_RANDOM_LEN)
+        .map(char::from)
+        .collect();
+    format!("{}{}", KEY_PREFIX, random)
+}
+
+fn row_to_api_key(row: &rusqlite::Row) -> rusqlite::Result<ApiKey> {
+    let scope: String = row.get(4)?;
+    Ok(ApiKey {
+        id: row.get(0)?,
+        user_id: row.get(1)?,
+        name: row.get(2)?,
+        key_prefix: row.get(3)?,
+        scope: match scope.as_str() {
+            "full" => ApiKeyScope::Full,
+            _ => ApiKeyScope::Read,
+        },
+        created_at: row.get(5)?,
+        last_used_at: row.get(6)?,
+    })
+}
+
+const API_KEY_COLUMNS: &str = "id, user_id, name, key_prefix, scope, created_at, last_used_at";
+
+/// Creates a key for a user.
+///
+/// Returns the stored key and the secret, which cannot be recovered later.
+pub fn create_api_key(
+    conn: &Connection,
+    user_id: i64,
+    name: &str,
+    scope: ApiKeyScope,
+) -> Result<(ApiKey, String)> {
+    let name = name.trim();
+    if name.is_empty() {
+        return Err(AppError::BadRequest("API key name is required".to_string()));
+    }
+
+    let exists: bool = conn.query_row(
+        "SELECT EXISTS(SELECT 1 FROM api_keys WHERE user_id = ?1 AND name = ?2)",
+        rusqlite::params![user_id, name],
+        |row| row.get(0),
+    )?;
+    if exists {
+        return Err(AppError::Conflict(format!(
+            "An API key named '{}' already exists",
+            name
+        )));
+    }
+
+    let secret = generate_key();
+    conn.execute(
+        "INSERT INTO api_keys (user_id, name, key_hash, key_prefix, scope) VALUES (?1, ?2, ?3, ?4, ?5)",
+        rusqlite::params![
+            user_id,
+            name,
+            hash_session_id(&secret),
+            &secret[..DISPLAY_PREFIX_LEN],
+            scope.to_string()
+        ],
+    )?;
+
+    let key = conn.query_row(
+        &format!("SELECT {} FROM api_keys WHERE id = ?1", API_KEY_COLUMNS),
+        [conn.last_insert_rowid()],
+        row_to_api_key,
+    )?;
+
+    Ok((key, secret))
+}
+
+/// Lists a user's keys, newest first.
+pub fn list_api_keys(conn: &Connection, user_id: i64) -> Result<Vec<ApiKey>> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {} FROM api_keys WHERE user_id = ?1 ORDER BY created_at DESC, id DESC",
+        API_KEY_COLUMNS
+    ))?;
+    let keys = stmt
+        .query_map([user_id], row_to_api_key)?
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    Ok(keys)
+}
+
+/// Deletes one of a user's keys. Returns whether it existed.
+pub fn delete_api_key(conn: &Connection, user_id: i64, key_id: i64) -> Result<bool> {
+    let deleted = conn.execute(
+        "DELETE FROM api_keys WHERE id = ?1 AND user_id = ?2",
+        rusqlite::params![key_id, user_id],
+    )?;
+    Ok(deleted > 0)
+}
+
+/// Looks up the key for a secret and the current role of its user.
+///
+/// Also records when the key was last used, at most once a minute.
+pub fn authenticate_api_key(conn: &Connection, secret: &str) -> Result<ApiKeyAuth> {
+    if !secret.starts_with(KEY_PREFIX) {
+        return Err(AppError::Unauthorized);
+    }
+
+    let found = conn
+        .query_row(
+            r#"
+            SELECT k.id, k.user_id, k.name, k.key_prefix, k.scope, k.created_at, k.last_used_at,
+                   u.role,
+                   k.last_used_at IS NULL OR k.last_used_at < datetime('now', '-1 hour'),
+                   k.last_used_at IS NULL OR k.last_used_at < datetime('now', '-1 minute')
+            FROM api_keys k
+            JOIN users u ON u.id = k.user_id
+            WHERE k.key_hash = ?1
+            "#,
+            [hash_session_id(secret)],
+            |row| {
+                Ok((
+                    row_to_api_key(row)?,
+                    row.get::<_, String>(7)?,
+                    row.get::<_, bool>(8)?,
+                    row.get::<_, bool>(9)?,
+                ))
+            },
+        )
+        .optional()?;
+
+    let Some((key, role, idle, stale)) = found else {
+        tracing::debug!("Unknown API key");
+        return Err(AppError::Unauthorized);
+    };
+
+    if stale {
+        conn.execute(
+            "UPDATE api_keys SET last_used_at = datetime('now') WHERE id = ?1",
+            [key.id],
+        )?;
+    }
+
+    Ok(ApiKeyAuth { key, role, idle })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_db() -> (Connection, i64) {
+        let conn = crate::db::init_db_memory().unwrap();
+        conn.execute(
+            "INSERT INTO users (username, password_hash, role) VALUES ('alice', 'x', 'user')",
+            [],
+        )
+        .unwrap();
+        let user_id = conn.last_insert_rowid();
+        (conn, user_id)
+    }
+
+    #[test]
+    fn test_create_and_authenticate() {
+        let (conn, user_id) = test_db();
+
+        let (key, secret) = create_api_key(&conn, user_id, "sonarr", ApiKeyScope::Full).unwrap();
+        assert!(secret.starts_with(KEY_PREFIX));
+        assert!(secret.starts_with(&key.key_prefix));
+        assert_eq!(key.scope, ApiKeyScope::Full);
+
+        // Only the hash is stored
+        let stored: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM api_keys WHERE key_hash = ?1",
+                [&secret],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(stored, 0);
+
+        let auth = authenticate_api_key(&conn, &secret).unwrap();
+        assert_eq!(auth.key.id, key.id);
+        assert_eq!(auth.role, "user");
+        assert!(auth.idle);
+
+        let claims = auth.claims();
+        assert_eq!(claims.sub, user_id);
+        assert_eq!(claims.role, "user");
+
+        // Used just now, so no longer idle
+        let auth = authenticate_api_key(&conn, &secret).unwrap();
+        assert!(!auth.idle);
+        assert!(auth.key.last_used_at.is_some());
+    }
+
+    #[test]
+    fn test_authenticate_unknown_key() {
+        let (conn, _) = test_db();
+
+        assert!(matches!(
+            authenticate_api_key(&conn, "lcars_nope"),
+            Err(AppError::Unauthorized)
+        ));
+        assert!(matches!(
+            authenticate_api_key(&conn, "not-a-key"),
+            Err(AppError::Unauthorized)
+        ));
+    }
+
+    #[test]
+    fn test_key_follows_user_role() {
+        let (conn, user_id) = test_db();
+        let (_, secret) = create_api_key(&conn, user_id, "script", ApiKeyScope::Read).unwrap();
+
+        conn.execute("UPDATE users SET role = 'admin' WHERE id = ?1", [user_id])
+            .unwrap();
+
+        assert_eq!(authenticate_api_key(&conn, &secret).unwrap().role, "admin");
+    }
+
+    #[test]
+    fn test_create_validates_name() {
+        let (conn, user_id) = test_db();
+
+        assert!(matches!(
+            create_api_key(&conn, user_id, "  ", ApiKeyScope::Read),
+            Err(AppError::BadRequest(_))
+        ));
+
+        create_api_key(&conn, user_id, "radarr", ApiKeyScope::Read).unwrap();
+        assert!(matches!(
+            create_api_key(&conn, user_id, "radarr", ApiKeyScope::Full),
+            Err(AppError::Conflict(_))
+        ));
+    }
+
+    #[test]
+    fn test_delete_revokes_key() {
+        let (conn, user_id) = test_db();
+        let (key, secret) = create_api_key(&conn, user_id, "script", ApiKeyScope::Read).unwrap();
+
+        // Keys of other users cannot be deleted
+        assert!(!delete_api_key(&conn, user_id + 1, key.id).unwrap());
+        assert_eq!(list_api_keys(&conn, user_id).unwrap().len(), 1);
+
+        assert!(delete_api_key(&conn, user_id, key.id).unwrap());
+        assert!(list_api_keys(&conn, user_id).unwrap().is_empty());
+        assert!(authenticate_api_key(&conn, &secret).is_err());
+    }
+
+    #[test]
+    fn test_scope_allows() {
+        assert!(scope_allows(ApiKeyScope::Read, &Method::GET));
+        assert!(scope_allows(ApiKeyScope::Read, &Method::HEAD));
+        assert!(!scope_allows(ApiKeyScope::Read, &Method::POST));
+        assert!(!scope_allows(ApiKeyScope::Read, &Method::DELETE));
+        assert!(scope_allows(ApiKeyScope::Full, &Method::DELETE));
+    }
+}
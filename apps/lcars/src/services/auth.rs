@@ -1,13 +1,20 @@
 //! Authentication service for LCARS.
 //!
 //! Provides password hashing with Argon2 and JWT token management.
+//!
+//! Every token is a session recorded in the `sessions` table under the SHA-256
+//! of its `jti`. Tokens whose session is gone are rejected, which is how users
+//! are logged out before their token expires.
 
 use argon2::{
     password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
     Argon2,
 };
 use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use rusqlite::{Connection, OptionalExtension};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
 
 use crate::error::{AppError, Result};
 
@@ -22,11 +29,38 @@ pub struct Claims {
     pub exp: usize,
     /// Issued at timestamp (Unix time)
     pub iat: usize,
+    /// Session ID, empty for requests made with an API key
+    pub jti: String,
 }
 
 /// Token expiration duration in seconds (24 hours).
 const TOKEN_EXPIRATION_SECS: usize = 24 * 60 * 60;
 
+/// Claims of a login challenge, issued instead of a token when the password
+/// was right but a second factor is still needed.
+///
+/// These lack `role` and `jti`, so they never verify as [`Claims`].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ChallengeClaims {
+    sub: i64,
+    exp: usize,
+    iat: usize,
+    purpose: String,
+}
+
+/// Purpose of login challenge tokens.
+const CHALLENGE_PURPOSE: &str = "two_factor";
+
+/// Time allowed to enter the second factor, in seconds.
+const CHALLENGE_EXPIRATION_SECS: usize = 5 * 60;
+
+fn unix_now() -> Result<usize> {
+    Ok(std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_err(|e| AppError::Internal(format!("System time error: {}", e)))?
+        .as_secs() as usize)
+}
+
 /// Authentication service handling password hashing and JWT tokens.
 pub struct AuthService {
     jwt_secret: String,
@@ -67,18 +101,95 @@ impl AuthService {
             .is_ok())
     }
 
-    /// Creates a JWT token for the given user.
-    pub fn create_token(&self, user_id: i64, role: &str) -> Result<String> {
-        let now = std::time::SystemTime::now()
-            .duration_since(std::time::UNIX_EPOCH)
-            .map_err(|e| AppError::Internal(format!("System time error: {}", e)))?
-            .as_secs() as usize;
+    /// Creates a JWT token for the given user and records its session.
+    ///
+    /// `user_agent` is kept to tell sessions apart when listing them.
+    pub fn create_token(
+        &self,
+        conn: &Connection,
+        user_id: i64,
+        role: &str,
+        user_agent: Option<&str>,
+    ) -> Result<String> {
+        let now = unix_now()?;
 
         let claims = Claims {
             sub: user_id,
             role: role.to_string(),
             exp: now + TOKEN_EXPIRATION_SECS,
             iat: now,
+            jti: Uuid::new_v4().to_string(),
+        };
+
+        let token = encode(
+            &Header::default(),
+            &claims,
+            &EncodingKey::from_secret(self.jwt_secret.as_bytes()),
+        )
+        .map_err(|e| AppError::Internal(format!("Token creation failed: {}", e)))?;
+
+        conn.execute(
+            r#"
+            INSERT INTO sessions (user_id, token_hash, expires_at, user_agent, last_used_at)
+            VALUES (?1, ?2, datetime(?3, 'unixepoch'), ?4, datetime('now'))
+            "#,
+            rusqlite::params![
+                user_id,
+                hash_session_id(&claims.jti),
+                claims.exp as i64,
+                user_agent
+            ],
+        )?;
+
+        Ok(token)
+    }
+
+    /// Verifies a JWT token and checks that its session was not revoked.
+    ///
+    /// Also records when the session was last used, at most once a minute.
+    pub fn verify_session(&self, conn: &Connection, token: &str) -> Result<Claims> {
+        let claims = self.verify_token(token)?;
+
+        let token_hash = hash_session_id(&claims.jti);
+
+        // Whether last_used_at is due for an update, None if the session is gone
+        let stale: Option<bool> = conn
+            .query_row(
+                r#"
+                SELECT last_used_at IS NULL OR last_used_at < datetime('now', '-1 minute')
+                FROM sessions
+                WHERE token_hash = ?1 AND user_id = ?2 AND expires_at > datetime('now')
+                "#,
+                rusqlite::params![token_hash, claims.sub],
+                |row| row.get(0),
+            )
+            .optional()?;
+
+        match stale {
+            None => {
+                tracing::debug!(user_id = claims.sub, "Token session revoked or unknown");
+                return Err(AppError::Unauthorized);
+            }
+            Some(true) => {
+                conn.execute(
+                    "UPDATE sessions SET last_used_at = datetime('now') WHERE token_hash = ?1",
+                    [&token_hash],
+                )?;
+            }
+            Some(false) => {}
+        }
+
+        Ok(claims)
+    }
+
+    /// Creates a short-lived challenge for the second login step of a user.
+    pub fn create_challenge(&self, user_id: i64) -> Result<String> {
+        let now = unix_now()?;
+        let claims = ChallengeClaims {
+            sub: user_id,
+            exp: now + CHALLENGE_EXPIRATION_SECS,
+            iat: now,
+            purpose: CHALLENGE_PURPOSE.to_string(),
         };
 
         encode(
@@ -86,10 +197,32 @@ impl AuthService {
             &claims,
             &EncodingKey::from_secret(self.jwt_secret.as_bytes()),
         )
-        .map_err(|e| AppError::Internal(format!("Token creation failed: {}", e)))
+        .map_err(|e| AppError::Internal(format!("Challenge creation failed: {}", e)))
     }
 
-    /// Verifies a JWT token and returns the claims.
+    /// Verifies a login challenge and returns the user it was issued to.
+    pub fn verify_challenge(&self, token: &str) -> Result<i64> {
+        let claims = decode::<ChallengeClaims>(
+            token,
+            &DecodingKey::from_secret(self.jwt_secret.as_bytes()),
+            &Validation::default(),
+        )
+        .map_err(|e| {
+            tracing::debug!("Challenge verification failed: {}", e);
+            AppError::Unauthorized
+        })?
+        .claims;
+
+        if claims.purpose != CHALLENGE_PURPOSE {
+            return Err(AppError::Unauthorized);
+        }
+        Ok(claims.sub)
+    }
+
+    /// Verifies a JWT token's signature and expiry and returns the claims.
+    ///
+    /// Does not check the session, use [`AuthService::verify_session`] to
+    /// authenticate requests.
     pub fn verify_token(&self, token: &str) -> Result<Claims> {
         let token_data = decode::<Claims>(
             token,
@@ -105,6 +238,28 @@ impl AuthService {
     }
 }
 
+/// Revokes a single session. Returns whether it existed.
+pub fn revoke_session(conn: &Connection, jti: &str) -> Result<bool> {
+    let deleted = conn.execute(
+        "DELETE FROM sessions WHERE token_hash = ?1",
+        [hash_session_id(jti)],
+    )?;
+    Ok(deleted > 0)
+}
+
+/// Revokes every session of a user, logging them out everywhere.
+///
+/// Returns the number of sessions revoked.
+pub fn revoke_user_sessions(conn: &Connection, user_id: i64) -> Result<usize> {
+    let deleted = conn.execute("DELETE FROM sessions WHERE user_id = ?1", [user_id])?;
+    Ok(deleted)
+}
+
+/// Hash under which a session is stored, so the table holds no usable token data.
+pub fn hash_session_id(jti: &str) -> String {
+    hex::encode(Sha256::digest(jti.as_bytes()))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -144,15 +299,74 @@ mod tests {
         assert_ne!(hash1, hash3);
     }
 
+    fn test_db() -> (Connection, i64) {
+        let conn = crate::db::init_db_memory().unwrap();
+        conn.execute(
+            "INSERT INTO users (username, password_hash, role) VALUES ('alice', 'x', 'admin')",
+            [],
+        )
+        .unwrap();
+        let user_id = conn.last_insert_rowid();
+        (conn, user_id)
+    }
+
     #[test]
     fn test_token_create_and_verify() {
         let service = test_service();
+        let (conn, user_id) = test_db();
 
-        let token = service.create_token(42, "admin").unwrap();
-        let claims = service.verify_token(&token).unwrap();
+        let token = service
+            .create_token(&conn, user_id, "admin", Some("curl/8.0"))
+            .unwrap();
+        let claims = service.verify_session(&conn, &token).unwrap();
 
-        assert_eq!(claims.sub, 42);
+        assert_eq!(claims.sub, user_id);
         assert_eq!(claims.role, "admin");
+
+        let (token_hash, user_agent): (String, Option<String>) = conn
+            .query_row(
+                "SELECT token_hash, user_agent FROM sessions WHERE user_id = ?1",
+                [user_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(token_hash, hash_session_id(&claims.jti));
+        assert_eq!(user_agent.as_deref(), Some("curl/8.0"));
+    }
+
+    #[test]
+    fn test_revoked_session_rejected() {
+        let service = test_service();
+        let (conn, user_id) = test_db();
+
+        let first = service.create_token(&conn, user_id, "admin", None).unwrap();
+        let second = service.create_token(&conn, user_id, "admin", None).unwrap();
+
+        let claims = service.verify_token(&first).unwrap();
+        assert!(revoke_session(&conn, &claims.jti).unwrap());
+        assert!(matches!(
+            service.verify_session(&conn, &first),
+            Err(AppError::Unauthorized)
+        ));
+        assert!(service.verify_session(&conn, &second).is_ok());
+
+        assert_eq!(revoke_user_sessions(&conn, user_id).unwrap(), 1);
+        assert!(service.verify_session(&conn, &second).is_err());
+    }
+
+    #[test]
+    fn test_expired_session_rejected() {
+        let service = test_service();
+        let (conn, user_id) = test_db();
+
+        let token = service.create_token(&conn, user_id, "admin", None).unwrap();
+        conn.execute(
+            "UPDATE sessions SET expires_at = datetime('now', '-1 minute')",
+            [],
+        )
+        .unwrap();
+
+        assert!(service.verify_session(&conn, &token).is_err());
     }
 
     #[test]
@@ -168,9 +382,24 @@ mod tests {
         let service1 = AuthService::new("secret1".to_string());
         let service2 = AuthService::new("secret2".to_string());
 
-        let token = service1.create_token(1, "user").unwrap();
-        let result = service2.verify_token(&token);
+        let (conn, user_id) = test_db();
+        let token = service1.create_token(&conn, user_id, "user", None).unwrap();
+        let result = service2.verify_session(&conn, &token);
 
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_challenge_is_not_a_token() {
+        let service = test_service();
+        let (conn, user_id) = test_db();
+
+        let challenge = service.create_challenge(user_id).unwrap();
+        assert_eq!(service.verify_challenge(&challenge).unwrap(), user_id);
+        assert!(service.verify_token(&challenge).is_err());
+
+        // And tokens are not challenges
+        let token = service.create_token(&conn, user_id, "admin", None).unwrap();
+        assert!(service.verify_challenge(&token).is_err());
+    }
 }
@@ -0,0 +1,312 @@
+//! Bandwidth limits shared by torrent and Soulseek transfers.
+//!
+//! [`BandwidthManager`] holds the global limits in effect: the configured ones,
+//! or the alternate ("turtle mode") ones while the alt-speed schedule is on.
+//! Engines subscribe to it and apply changes as they happen. librqbit throttles
+//! torrents itself; Soulseek transfers go through a [`RateLimiter`].
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use croner::Cron;
+use serde::{Deserialize, Serialize};
+use tokio::sync::{watch, Mutex};
+use tokio::time::Instant;
+
+use crate::config::BandwidthConfig;
+use crate::error::{AppError, Result};
+
+/// Transfer speed caps in KiB/s. `None` means unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SpeedLimits {
+    pub download: Option<u64>,
+    pub upload: Option<u64>,
+}
+
+impl SpeedLimits {
+    /// Limits from configuration values, where 0 means unlimited.
+    pub fn from_config(download: u64, upload: u64) -> Self {
+        Self {
+            download: (download > 0).then_some(download),
+            upload: (upload > 0).then_some(upload),
+        }
+    }
+
+    /// Whether no cap is set.
+    pub fn is_unlimited(&self) -> bool {
+        self.download.is_none() && self.upload.is_none()
+    }
+}
+
+/// Convert a KiB/s cap to bytes per second.
+pub fn kib_to_bytes(kib: u64) -> u64 {
+    kib.saturating_mul(1024)
+}
+
+/// Parse a cron expression the way the scheduler does (seconds required, UTC).
+///
+/// # Errors
+///
+/// Returns an error if the expression is invalid.
+pub fn parse_schedule(cron: &str) -> Result<Cron> {
+    Cron::new(cron)
+        .with_seconds_required()
+        .with_dom_and_dow()
+        .parse()
+        .map_err(|e| AppError::BadRequest(format!("Invalid cron expression '{}': {}", cron, e)))
+}
+
+/// Whether `now` falls between a `start` and the following `end` tick.
+///
+/// That is the case when the window closes before it next opens.
+///
+/// # Errors
+///
+/// Returns an error if either expression is invalid.
+pub fn in_schedule_window(start: &str, end: &str, now: DateTime<Utc>) -> Result<bool> {
+    let next = |cron: &str| -> Result<Option<DateTime<Utc>>> {
+        Ok(parse_schedule(cron)?.find_next_occurrence(&now, false).ok())
+    };
+
+    Ok(match (next(start)?, next(end)?) {
+        (Some(start), Some(end)) => end < start,
+        (None, Some(_)) => true,
+        _ => false,
+    })
+}
+
+/// Global bandwidth limits, switching between the normal and alternate set.
+pub struct BandwidthManager {
+    config: BandwidthConfig,
+    alt_speed: AtomicBool,
+    limits_tx: watch::Sender<SpeedLimits>,
+}
+
+impl BandwidthManager {
+    /// Create a manager applying the normal limits.
+    pub fn new(config: BandwidthConfig) -> Self {
+        let (limits_tx, _) = watch::channel(SpeedLimits::from_config(
+            config.download_limit,
+            config.upload_limit,
+        ));
+
+        Self {
+            config,
+            alt_speed: AtomicBool::new(false),
+            limits_tx,
+        }
+    }
+
+    /// Create a manager wrapped in Arc for shared access.
+    pub fn new_shared(config: BandwidthConfig) -> Arc<Self> {
+        Arc::new(Self::new(config))
+    }
+
+    /// Limits currently in effect.
+    pub fn limits(&self) -> SpeedLimits {
+        *self.limits_tx.borrow()
+    }
+
+    /// Whether the alternate limits are in effect.
+    pub fn alt_speed_active(&self) -> bool {
+        self.alt_speed.load(Ordering::SeqCst)
+    }
+
+    /// Turn the alternate limits on or off.
+    pub fn set_alt_speed(&self, active: bool) {
+        if self.alt_speed.swap(active, Ordering::SeqCst) == active {
+            return;
+        }
+
+        let limits = if active {
+            SpeedLimits::from_config(
+                self.config.alt_speed.download_limit,
+                self.config.alt_speed.upload_limit,
+            )
+        } else {
+            SpeedLimits::from_config(self.config.download_limit, self.config.upload_limit)
+        };
+
+        tracing::info!(
+            alt_speed = active,
+            download_kib = ?limits.download,
+            upload_kib = ?limits.upload,
+            "Bandwidth limits changed"
+        );
+        self.limits_tx.send_replace(limits);
+    }
+
+    /// Cron expressions turning the alternate limits on and off, if scheduled.
+    pub fn alt_speed_schedule(&self) -> Option<(&str, &str)> {
+        let alt_speed = &self.config.alt_speed;
+        if alt_speed.start.is_empty() || alt_speed.end.is_empty() {
+            None
+        } else {
+            Some((alt_speed.start.as_str(), alt_speed.end.as_str()))
+        }
+    }
+
+    /// Watch the limits in effect.
+    pub fn subscribe(&self) -> watch::Receiver<SpeedLimits> {
+        self.limits_tx.subscribe()
+    }
+}
+
+/// Throttles a byte stream to a rate that can change at any time.
+///
+/// Each caller reserves the time its bytes take at the current rate, so
+/// transfers sharing a limiter share its bandwidth.
+pub struct RateLimiter {
+    /// Bytes per second, 0 for unlimited.
+    rate: AtomicU64,
+    /// When the bytes reserved so far will have gone through.
+    next_free: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    /// Create a limiter with a cap in KiB/s.
+    pub fn new(limit_kib: Option<u64>) -> Self {
+        let limiter = Self {
+            rate: AtomicU64::new(0),
+            next_free: Mutex::new(Instant::now()),
+        };
+        limiter.set_limit(limit_kib);
+        limiter
+    }
+
+    /// Change the cap, in KiB/s.
+    pub fn set_limit(&self, limit_kib: Option<u64>) {
+        self.rate
+            .store(limit_kib.map(kib_to_bytes).unwrap_or(0), Ordering::SeqCst);
+    }
+
+    /// Cap in KiB/s.
+    pub fn limit(&self) -> Option<u64> {
+        match self.rate.load(Ordering::SeqCst) {
+            0 => None,
+            rate => Some(rate / 1024),
+        }
+    }
+
+    /// Wait until `bytes` may be transferred.
+    pub async fn acquire(&self, bytes: usize) {
+        let rate = self.rate.load(Ordering::SeqCst);
+        if rate == 0 {
+            return;
+        }
+
+        let start = {
+            let mut next_free = self.next_free.lock().await;
+            let now = Instant::now();
+            let start = (*next_free).max(now);
+            *next_free = start + Duration::from_secs_f64(bytes as f64 / rate as f64);
+            start
+        };
+
+        tokio::time::sleep_until(start).await;
+    }
+}
+
+/// The smaller of two optional caps.
+pub fn min_limit(a: Option<u64>, b: Option<u64>) -> Option<u64> {
+    match (a, b) {
+        (Some(a), Some(b)) => Some(a.min(b)),
+        (a, b) => a.or(b),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::AltSpeedConfig;
+    use chrono::TimeZone;
+
+    fn config() -> BandwidthConfig {
+        BandwidthConfig {
+            download_limit: 1000,
+            upload_limit: 0,
+            alt_speed: AltSpeedConfig {
+                download_limit: 100,
+                upload_limit: 20,
+                start: "0 0 18 * * *".to_string(),
+                end: "0 0 23 * * *".to_string(),
+            },
+        }
+    }
+
+    #[test]
+    fn test_alt_speed_switches_limits() {
+        let manager = BandwidthManager::new(config());
+        let mut rx = manager.subscribe();
+        assert_eq!(
+            manager.limits(),
+            SpeedLimits {
+                download: Some(1000),
+                upload: None
+            }
+        );
+
+        manager.set_alt_speed(true);
+        assert!(manager.alt_speed_active());
+        assert!(rx.has_changed().unwrap());
+        assert_eq!(
+            *rx.borrow_and_update(),
+            SpeedLimits {
+                download: Some(100),
+                upload: Some(20)
+            }
+        );
+
+        // Setting the same state again is a no-op
+        manager.set_alt_speed(true);
+        assert!(!rx.has_changed().unwrap());
+
+        manager.set_alt_speed(false);
+        assert_eq!(manager.limits().download, Some(1000));
+    }
+
+    #[test]
+    fn test_in_schedule_window() {
+        let (start, end) = ("0 0 18 * * *", "0 0 23 * * *");
+        let at = |hour| Utc.with_ymd_and_hms(2026, 3, 1, hour, 30, 0).unwrap();
+
+        assert!(!in_schedule_window(start, end, at(12)).unwrap());
+        assert!(in_schedule_window(start, end, at(19)).unwrap());
+        assert!(!in_schedule_window(start, end, at(23)).unwrap());
+
+        // Windows spanning midnight
+        let (start, end) = ("0 0 22 * * *", "0 0 6 * * *");
+        assert!(in_schedule_window(start, end, at(2)).unwrap());
+        assert!(!in_schedule_window(start, end, at(12)).unwrap());
+
+        assert!(in_schedule_window("not a cron", end, at(2)).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_rate_limiter_throttles() {
+        let limiter = RateLimiter::new(Some(100));
+        assert_eq!(limiter.limit(), Some(100));
+
+        // 30 KiB at 100 KiB/s: the first 10 KiB go at once, the rest take 200ms
+        let started = std::time::Instant::now();
+        for _ in 0..3 {
+            limiter.acquire(10 * 1024).await;
+        }
+        assert!(started.elapsed() >= Duration::from_millis(190));
+
+        limiter.set_limit(None);
+        let started = std::time::Instant::now();
+        limiter.acquire(usize::MAX).await;
+        assert!(started.elapsed() < Duration::from_millis(50));
+    }
+
+    #[test]
+    fn test_min_limit() {
+        assert_eq!(min_limit(Some(5), Some(3)), Some(3));
+        assert_eq!(min_limit(None, Some(3)), Some(3));
+        assert_eq!(min_limit(Some(5), None), Some(5));
+        assert_eq!(min_limit(None, None), None);
+    }
+}
@@ -0,0 +1,1124 @@
+//! Download queue in front of the torrent and Soulseek engines.
+//!
+//! Downloads are not handed to an engine right away: they are recorded as
+//! `queued` in the `downloads` table and [`DownloadQueue`] starts them as slots
+//! free up, so that at most `torrent.max_active_downloads` torrents and
+//! `soulseek.max_concurrent_downloads` Soulseek transfers run at once.
+//!
+//! Queued downloads start in `queue_order`. A new download goes before every
+//! download it outranks: downloads requested by a user outrank automatic
+//! grabs, then media wanted for longer outranks newer media. Users can move
+//! downloads around the queue afterwards. The queue lives in the database, so
+//! it survives restarts.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use rusqlite::{Connection, OptionalExtension};
+use tokio::sync::{broadcast, Mutex, Notify};
+use uuid::Uuid;
+
+use crate::config::Config;
+use crate::db::models::{DownloadSource, DownloadStatus, MediaType};
+use crate::error::{AppError, Result};
+use crate::services::bandwidth::SpeedLimits;
+use crate::services::soulseek::{
+    DownloadRequest as SoulseekDownloadRequest, DownloadStatus as SoulseekDownloadStatus,
+    SoulseekEvent,
+};
+use crate::services::torrent::{MediaRef, TorrentEvent};
+use crate::services::torrent_source::TorrentSource;
+use crate::services::{SoulseekEngine, TorrentEngine};
+
+/// How often the queue is checked besides engine events.
+const CHECK_INTERVAL_SECS: u64 = 30;
+
+/// Prefix of the `source_id` of downloads that have not started yet.
+const QUEUED_SOURCE_PREFIX: &str = "queued:";
+
+/// Who asked for a download, deciding where it enters the queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueuePriority {
+    /// Grabbed by a background job.
+    Automatic,
+    /// Requested by a user.
+    Manual,
+}
+
+impl QueuePriority {
+    fn value(self) -> i64 {
+        match self {
+            QueuePriority::Automatic => 0,
+            QueuePriority::Manual => 1,
+        }
+    }
+}
+
+/// What a queued download fetches.
+#[derive(Debug, Clone)]
+pub enum QueuedSource {
+    /// A torrent, limited to the files at the given indexes if any.
+    Torrent {
+        source: TorrentSource,
+        files: Option<Vec<usize>>,
+    },
+    /// A file shared by a Soulseek user.
+    Soulseek {
+        username: String,
+        filename: String,
+        size: u64,
+    },
+}
+
+/// A download to add to the queue.
+#[derive(Debug, Clone)]
+pub struct QueueRequest {
+    /// Name shown for the download.
+    pub name: String,
+    /// Title of the indexer release, for the blocklist.
+    pub release_title: Option<String>,
+    /// Media the download is for.
+    pub media: MediaRef,
+    pub source: QueuedSource,
+    pub priority: QueuePriority,
+}
+
+/// A download added to the queue, as it stands once the queue had a chance to start it.
+#[derive(Debug, Clone)]
+pub struct QueuedDownload {
+    pub id: i64,
+    /// Info hash or transfer id once started, a placeholder while queued.
+    pub source_id: String,
+    pub status: DownloadStatus,
+}
+
+/// A queued download, as needed to start it.
+#[derive(Debug, Clone)]
+struct QueuedRow {
+    id: i64,
+    source_uri: String,
+    media: MediaRef,
+    selected_files: Option<Vec<usize>>,
+    limits: SpeedLimits,
+    soulseek_username: Option<String>,
+    soulseek_filename: Option<String>,
+    size_bytes: Option<i64>,
+}
+
+/// Starts queued downloads as engine slots free up.
+pub struct DownloadQueue {
+    db: Arc<Mutex<Connection>>,
+    torrent_engine: Option<Arc<TorrentEngine>>,
+    soulseek_engine: Option<Arc<SoulseekEngine>>,
+    /// Torrents downloading at once (0 = unlimited).
+    max_torrents: usize,
+    /// Soulseek transfers running at once (0 = unlimited).
+    max_soulseek: usize,
+    /// Held while starting downloads, so none is started twice.
+    starting: Mutex<()>,
+    wake: Notify,
+}
+
+impl DownloadQueue {
+    /// Create a queue feeding the given engines.
+    pub fn new(
+        db: Arc<Mutex<Connection>>,
+        config: &Config,
+        torrent_engine: Option<Arc<TorrentEngine>>,
+        soulseek_engine: Option<Arc<SoulseekEngine>>,
+    ) -> Self {
+        Self {
+            db,
+            torrent_engine,
+            soulseek_engine,
+            max_torrents: config.torrent.max_active_downloads,
+            max_soulseek: config.soulseek.max_concurrent_downloads,
+            starting: Mutex::new(()),
+            wake: Notify::new(),
+        }
+    }
+
+    /// Create a queue wrapped in Arc for shared access.
+    pub fn new_shared(
+        db: Arc<Mutex<Connection>>,
+        config: &Config,
+        torrent_engine: Option<Arc<TorrentEngine>>,
+        soulseek_engine: Option<Arc<SoulseekEngine>>,
+    ) -> Arc<Self> {
+        Arc::new(Self::new(db, config, torrent_engine, soulseek_engine))
+    }
+
+    /// Record a download as queued and wake the queue to start it.
+    ///
+    /// The download is started by the [`watch`](Self::watch) task once a slot
+    /// is free, not by the caller. The media is marked as downloading right
+    /// away, so background jobs do not grab it again while it waits.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the engine for the source is not available, the
+    /// file selection is empty, or an uploaded .torrent file cannot be kept.
+    pub async fn enqueue(&self, request: QueueRequest) -> Result<QueuedDownload> {
+        let (source_type, source_uri) = match &request.source {
+            QueuedSource::Torrent { source, files } => {
+                let engine = self.torrent_engine.as_ref().ok_or_else(|| {
+                    AppError::Internal("Torrent engine not available".to_string())
+                })?;
+                if files.as_ref().is_some_and(|f| f.is_empty()) {
+                    return Err(AppError::BadRequest(
+                        "At least one file must be selected".to_string(),
+                    ));
+                }
+                let source_uri = match source {
+                    TorrentSource::Magnet(uri) | TorrentSource::Url(uri) => uri.clone(),
+                    TorrentSource::File(bytes) => engine.save_torrent_file(bytes).await?,
+                };
+                (DownloadSource::Torrent, source_uri)
+            }
+            QueuedSource::Soulseek {
+                username, filename, ..
+            } => {
+                if self.soulseek_engine.is_none() {
+                    return Err(AppError::ServiceUnavailable(
+                        "Soulseek not configured".to_string(),
+                    ));
+                }
+                (
+                    DownloadSource::Soulseek,
+                    format!("soulseek://{}/{}", username, filename),
+                )
+            }
+        };
+
+        let id = {
+            let db = self.db.lock().await;
+            insert_queued(&db, &request, source_type, &source_uri)?
+        };
+
+        tracing::info!(
+            download_id = id,
+            source_type = %source_type,
+            media_type = %request.media.media_type,
+            media_id = request.media.media_id,
+            priority = ?request.priority,
+            "Download queued"
+        );
+
+        self.notify();
+
+        let db = self.db.lock().await;
+        let (source_id, status): (String, String) = db.query_row(
+            "SELECT source_id, status FROM downloads WHERE id = ?1",
+            [id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+
+        Ok(QueuedDownload {
+            id,
+            source_id,
+            status: parse_status(&status),
+        })
+    }
+
+    /// Put a download back in the queue, as if it was just added.
+    ///
+    /// Used to retry failed downloads. The caller takes it out of its engine first.
+    ///
+    /// # Errors
+    ///
+    /// Returns a not found error if the download does not exist.
+    pub async fn requeue(&self, download_id: i64) -> Result<()> {
+        {
+            let db = self.db.lock().await;
+            requeue(&db, download_id)?;
+        }
+        self.notify();
+        Ok(())
+    }
+
+    /// Put Soulseek transfers interrupted by a restart back in the queue.
+    ///
+    /// Soulseek transfers only live in memory, so ones recorded as running
+    /// when LCARS stopped start over. Returns how many were requeued.
+    pub async fn requeue_interrupted(&self) -> Result<usize> {
+        let db = self.db.lock().await;
+        let ids: Vec<i64> = {
+            let mut stmt = db.prepare(
+                "SELECT id FROM downloads WHERE source_type = 'soulseek' AND status = 'downloading' ORDER BY added_at",
+            )?;
+            let ids = stmt
+                .query_map([], |row| row.get(0))?
+                .collect::<std::result::Result<_, _>>()?;
+            ids
+        };
+
+        for &id in &ids {
+            requeue(&db, id)?;
+        }
+
+        if !ids.is_empty() {
+            tracing::info!(count = ids.len(), "Requeued interrupted Soulseek downloads");
+        }
+        Ok(ids.len())
+    }
+
+    /// Ask the queue to start downloads if slots are free.
+    pub fn notify(&self) {
+        self.wake.notify_one();
+    }
+
+    /// Start queued downloads whenever a slot may have freed up.
+    ///
+    /// The queue is checked when an engine reports a download finished,
+    /// failed or went away, and every [`CHECK_INTERVAL_SECS`] seconds. Failed
+    /// Soulseek transfers are recorded here, as nothing else tracks them.
+    pub fn watch(self: &Arc<Self>) {
+        let queue = Arc::clone(self);
+        tokio::spawn(async move {
+            tracing::info!(
+                max_torrents = queue.max_torrents,
+                max_soulseek = queue.max_soulseek,
+                "Download queue enabled"
+            );
+
+            let mut interval = tokio::time::interval(Duration::from_secs(CHECK_INTERVAL_SECS));
+            loop {
+                tokio::select! {
+                    _ = queue.wake.notified() => {}
+                    _ = interval.tick() => {}
+                }
+                queue.process().await;
+            }
+        });
+
+        if let Some(engine) = &self.torrent_engine {
+            let queue = Arc::clone(self);
+            let mut rx = engine.subscribe();
+            tokio::spawn(async move {
+                loop {
+                    match rx.recv().await {
+                        Ok(
+                            TorrentEvent::Completed { .. }
+                            | TorrentEvent::Error { .. }
+                            | TorrentEvent::Stalled { .. }
+                            | TorrentEvent::Removed { .. }
+                            | TorrentEvent::Paused { .. },
+                        ) => queue.notify(),
+                        Ok(_) => {}
+                        Err(broadcast::error::RecvError::Lagged(n)) => {
+                            tracing::warn!(
+                                missed = n,
+                                "Torrent event receiver lagged, missed events"
+                            );
+                        }
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    }
+                }
+            });
+        }
+
+        if let Some(engine) = &self.soulseek_engine {
+            let queue = Arc::clone(self);
+            let mut rx = engine.subscribe();
+            tokio::spawn(async move {
+                loop {
+                    match rx.recv().await {
+                        Ok(SoulseekEvent::DownloadFailed { id, error }) => {
+                            let db = queue.db.lock().await;
+                            if let Err(e) = mark_failed(&db, DownloadSource::Soulseek, &id, &error)
+                            {
+                                tracing::error!(id = %id, error = %e, "Failed to record Soulseek download failure");
+                            }
+                            drop(db);
+                            queue.notify();
+                        }
+                        Ok(
+                            SoulseekEvent::DownloadComplete { .. }
+                            | SoulseekEvent::Connected { .. },
+                        ) => queue.notify(),
+                        Ok(_) => {}
+                        Err(broadcast::error::RecvError::Lagged(n)) => {
+                            tracing::warn!(
+                                missed = n,
+                                "Soulseek event receiver lagged, missed events"
+                            );
+                        }
+                        Err(broadcast::error::RecvError::Closed) => break,
+                    }
+                }
+            });
+        }
+    }
+
+    /// Start as many queued downloads as there are free slots.
+    pub async fn process(&self) {
+        let _starting = self.starting.lock().await;
+
+        if let Some(engine) = &self.torrent_engine {
+            if let Err(e) = self.start_torrents(engine).await {
+                tracing::error!(error = %e, "Failed to start queued torrents");
+            }
+        }
+
+        if let Some(engine) = &self.soulseek_engine {
+            if let Err(e) = self.start_soulseek(engine).await {
+                tracing::error!(error = %e, "Failed to start queued Soulseek downloads");
+            }
+        }
+    }
+
+    /// Start queued torrents up to the torrent limit.
+    ///
+    /// Active torrents are counted from the database, which also knows about
+    /// torrents still being restored after a restart.
+    async fn start_torrents(&self, engine: &TorrentEngine) -> Result<()> {
+        let rows = {
+            let db = self.db.lock().await;
+            let active = count_active_torrents(&db)?;
+            let free = free_slots(self.max_torrents, active);
+            if free == 0 {
+                return Ok(());
+            }
+            next_queued(&db, DownloadSource::Torrent, free)?
+        };
+
+        for row in rows {
+            let result = engine
+                .restore(
+                    &row.source_uri,
+                    row.media.clone(),
+                    row.selected_files.clone(),
+                    None,
+                    false,
+                    row.limits,
+                )
+                .await;
+
+            let db = self.db.lock().await;
+            match result.and_then(|info_hash| {
+                mark_started(&db, row.id, &info_hash)?;
+                Ok(info_hash)
+            }) {
+                Ok(info_hash) => {
+                    tracing::info!(download_id = row.id, info_hash = %info_hash, "Started queued torrent");
+                }
+                Err(e) => {
+                    tracing::error!(download_id = row.id, error = %e, "Failed to start queued torrent");
+                    mark_start_failed(&db, &row, &e.to_string())?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Start queued Soulseek transfers up to the Soulseek limit.
+    ///
+    /// Active transfers are counted from the engine, which knows when they end.
+    async fn start_soulseek(&self, engine: &SoulseekEngine) -> Result<()> {
+        if !engine.is_connected().await {
+            return Ok(());
+        }
+
+        let active = engine
+            .get_downloads()
+            .await
+            .iter()
+            .filter(|d| {
+                matches!(
+                    d.status,
+                    SoulseekDownloadStatus::Connecting
+                        | SoulseekDownloadStatus::Queued
+                        | SoulseekDownloadStatus::Downloading
+                )
+            })
+            .count();
+        let free = free_slots(self.max_soulseek, active);
+        if free == 0 {
+            return Ok(());
+        }
+
+        let rows = {
+            let db = self.db.lock().await;
+            next_queued(&db, DownloadSource::Soulseek, free)?
+        };
+
+        for row in rows {
+            let (Some(username), Some(filename)) =
+                (row.soulseek_username.clone(), row.soulseek_filename.clone())
+            else {
+                let db = self.db.lock().await;
+                mark_start_failed(&db, &row, "Missing Soulseek user or file")?;
+                continue;
+            };
+
+            let result = engine
+                .download(SoulseekDownloadRequest {
+                    username,
+                    filename,
+                    size: row.size_bytes.unwrap_or(0).max(0) as u64,
+                    media_type: Some(row.media.media_type.to_string()),
+                    media_id: Some(row.media.media_id),
+                })
+                .await;
+
+            let db = self.db.lock().await;
+            match result {
+                Ok(transfer_id) => {
+                    mark_started(&db, row.id, &transfer_id)?;
+                    tracing::info!(download_id = row.id, transfer_id = %transfer_id, "Started queued Soulseek download");
+                }
+                // Lost the server connection, try again later
+                Err(AppError::ServiceUnavailable(_)) => break,
+                Err(e) => {
+                    tracing::error!(download_id = row.id, error = %e, "Failed to start queued Soulseek download");
+                    mark_start_failed(&db, &row, &e.to_string())?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Whether a download has not been handed to an engine yet.
+pub fn is_queued_source_id(source_id: &str) -> bool {
+    source_id.starts_with(QUEUED_SOURCE_PREFIX)
+}
+
+/// Move a queued download to a 1-based position in the queue.
+///
+/// Positions past the end move the download last.
+///
+/// # Errors
+///
+/// Returns a not found error if the download does not exist, and a conflict
+/// error if it is not queued.
+pub fn move_in_queue(conn: &Connection, download_id: i64, position: usize) -> Result<()> {
+    let status: String = conn
+        .query_row(
+            "SELECT status FROM downloads WHERE id = ?1",
+            [download_id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .ok_or_else(|| AppError::NotFound("Download not found".to_string()))?;
+    if status != "queued" {
+        return Err(AppError::Conflict("Download is not queued".to_string()));
+    }
+
+    let mut ids = queued_ids(conn)?;
+    ids.retain(|&id| id != download_id);
+    let index = position.saturating_sub(1).min(ids.len());
+    ids.insert(index, download_id);
+
+    for (i, id) in ids.iter().enumerate() {
+        conn.execute(
+            "UPDATE downloads SET queue_order = ?1 WHERE id = ?2",
+            rusqlite::params![i as i64 + 1, id],
+        )?;
+    }
+    Ok(())
+}
+
+/// Ids of the queued downloads, first to start first.
+fn queued_ids(conn: &Connection) -> Result<Vec<i64>> {
+    let mut stmt = conn.prepare(
+        "SELECT id FROM downloads WHERE status = 'queued' ORDER BY queue_order IS NULL, queue_order, added_at, id",
+    )?;
+    let ids = stmt
+        .query_map([], |row| row.get(0))?
+        .collect::<std::result::Result<_, _>>()?;
+    Ok(ids)
+}
+
+/// Free slots given a limit (0 = unlimited) and the active count.
+fn free_slots(limit: usize, active: usize) -> usize {
+    if limit == 0 {
+        usize::MAX
+    } else {
+        limit.saturating_sub(active)
+    }
+}
+
+/// Torrents downloading, or about to once restored.
+fn count_active_torrents(conn: &Connection) -> Result<usize> {
+    let count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM downloads WHERE source_type = 'torrent' AND status = 'downloading' AND removed_at IS NULL",
+        [],
+        |row| row.get(0),
+    )?;
+    Ok(count as usize)
+}
+
+/// Table holding a media type.
+fn media_table(media_type: MediaType) -> &'static str {
+    match media_type {
+        MediaType::Movie => "movies",
+        MediaType::Episode => "episodes",
+        MediaType::Album => "albums",
+        MediaType::Track => "tracks",
+    }
+}
+
+/// Put media back once its download is gone: `available` when it still has a
+/// file, as after a failed upgrade, otherwise `missing`. Albums take the
+/// status of their tracks.
+pub(crate) fn reset_media_status(
+    conn: &Connection,
+    media_type: MediaType,
+    media_id: i64,
+) -> Result<()> {
+    let sql = match media_type {
+        MediaType::Album => r#"
+            UPDATE albums SET status = CASE
+                WHEN NOT EXISTS (SELECT 1 FROM tracks WHERE album_id = albums.id AND status = 'available')
+                    THEN 'missing'
+                WHEN EXISTS (SELECT 1 FROM tracks WHERE album_id = albums.id AND status != 'available')
+                    THEN 'partial'
+                ELSE 'available'
+            END, updated_at = datetime('now')
+            WHERE id = ?1
+            "#
+        .to_string(),
+        _ => format!(
+            r#"
+            UPDATE {} SET status = CASE WHEN file_path IS NULL THEN 'missing' ELSE 'available' END,
+                   updated_at = datetime('now')
+            WHERE id = ?1
+            "#,
+            media_table(media_type)
+        ),
+    };
+    conn.execute(&sql, [media_id])?;
+    Ok(())
+}
+
+/// When a media item started being wanted: when it was added, or for
+/// episodes, when it aired.
+fn wanted_since(conn: &Connection, media: &MediaRef) -> Result<Option<String>> {
+    let column = match media.media_type {
+        MediaType::Movie | MediaType::Album => "added_at",
+        MediaType::Episode => "COALESCE(air_date, created_at)",
+        MediaType::Track => "created_at",
+    };
+    let since = conn
+        .query_row(
+            &format!(
+                "SELECT {} FROM {} WHERE id = ?1",
+                column,
+                media_table(media.media_type)
+            ),
+            [media.media_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(since)
+}
+
+/// Make room for a download in the queue, returning its position.
+///
+/// The download goes before the first queued download it outranks, or last.
+fn reserve_position(conn: &Connection, priority: i64, wanted_since: Option<&str>) -> Result<i64> {
+    let before: Option<i64> = conn.query_row(
+        r#"
+        SELECT MIN(queue_order) FROM downloads
+        WHERE status = 'queued' AND queue_order IS NOT NULL
+          AND (priority < ?1
+               OR (priority = ?1 AND ?2 IS NOT NULL
+                   AND COALESCE(wanted_since, added_at) > ?2))
+        "#,
+        rusqlite::params![priority, wanted_since],
+        |row| row.get(0),
+    )?;
+
+    match before {
+        Some(position) => {
+            conn.execute(
+                "UPDATE downloads SET queue_order = queue_order + 1 WHERE status = 'queued' AND queue_order >= ?1",
+                [position],
+            )?;
+            Ok(position)
+        }
+        None => Ok(conn.query_row(
+            "SELECT COALESCE(MAX(queue_order), 0) + 1 FROM downloads WHERE status = 'queued'",
+            [],
+            |row| row.get(0),
+        )?),
+    }
+}
+
+/// Insert a queued download and flip the media status to 'downloading'.
+///
+/// Returns the id of the new download.
+fn insert_queued(
+    conn: &Connection,
+    request: &QueueRequest,
+    source_type: DownloadSource,
+    source_uri: &str,
+) -> Result<i64> {
+    let priority = request.priority.value();
+    let wanted_since = wanted_since(conn, &request.media)?;
+    let position = reserve_position(conn, priority, wanted_since.as_deref())?;
+
+    let (selected_files, size, username, filename) = match &request.source {
+        QueuedSource::Torrent { files, .. } => (
+            files.as_ref().map(|f| serde_json::json!(f).to_string()),
+            None,
+            None,
+            None,
+        ),
+        QueuedSource::Soulseek {
+            username,
+            filename,
+            size,
+        } => (
+            None,
+            Some(*size as i64),
+            Some(username.as_str()),
+            Some(filename.as_str()),
+        ),
+    };
+
+    conn.execute(
+        r#"
+        INSERT INTO downloads (source_type, source_id, name, media_type, media_id, source_uri, status,
+                               selected_files, size_bytes, soulseek_username, soulseek_filename,
+                               priority, wanted_since, queue_order, release_title)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, 'queued', ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)
+        "#,
+        rusqlite::params![
+            source_type.to_string(),
+            queued_source_id(),
+            request.name,
+            request.media.media_type.to_string(),
+            request.media.media_id,
+            source_uri,
+            selected_files,
+            size,
+            username,
+            filename,
+            priority,
+            wanted_since,
+            position,
+            request.release_title,
+        ],
+    )?;
+    let download_id = conn.last_insert_rowid();
+
+    conn.execute(
+        &format!(
+            "UPDATE {} SET status = 'downloading', updated_at = datetime('now') WHERE id = ?1",
+            media_table(request.media.media_type)
+        ),
+        [request.media.media_id],
+    )?;
+
+    Ok(download_id)
+}
+
+/// Reset a download to queued, placing it by its priority.
+fn requeue(conn: &Connection, download_id: i64) -> Result<()> {
+    let (priority, wanted_since, media_type, media_id): (i64, Option<String>, String, i64) = conn
+        .query_row(
+            "SELECT priority, wanted_since, media_type, media_id FROM downloads WHERE id = ?1",
+            [download_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )
+        .optional()?
+        .ok_or_else(|| AppError::NotFound("Download not found".to_string()))?;
+
+    // Leave the queue first, so the download is not counted when placing it
+    conn.execute(
+        "UPDATE downloads SET status = 'failed', queue_order = NULL WHERE id = ?1",
+        [download_id],
+    )?;
+    let position = reserve_position(conn, priority, wanted_since.as_deref())?;
+
+    conn.execute(
+        r#"
+        UPDATE downloads
+        SET source_id = ?1, status = 'queued', queue_order = ?2, error_message = NULL,
+            progress = 0, download_speed = 0, upload_speed = 0,
+            downloaded_bytes = 0, uploaded_bytes = 0, ratio = 0, peers = 0,
+            started_at = NULL, completed_at = NULL, seeding_started_at = NULL, removed_at = NULL
+        WHERE id = ?3
+        "#,
+        rusqlite::params![queued_source_id(), position, download_id],
+    )?;
+
+    if let Some(table) = match media_type.as_str() {
+        "movie" => Some("movies"),
+        "episode" => Some("episodes"),
+        "album" => Some("albums"),
+        "track" => Some("tracks"),
+        _ => None,
+    } {
+        conn.execute(
+            &format!(
+                "UPDATE {} SET status = 'downloading', updated_at = datetime('now') WHERE id = ?1",
+                table
+            ),
+            [media_id],
+        )?;
+    }
+
+    Ok(())
+}
+
+/// The first queued downloads of a source, in queue order.
+fn next_queued(conn: &Connection, source: DownloadSource, limit: usize) -> Result<Vec<QueuedRow>> {
+    let mut stmt = conn.prepare(
+        r#"
+        SELECT id, source_uri, media_type, media_id, selected_files, download_limit, upload_limit,
+               soulseek_username, soulseek_filename, size_bytes
+        FROM downloads
+        WHERE status = 'queued' AND source_type = ?1
+        ORDER BY queue_order IS NULL, queue_order, added_at, id
+        LIMIT ?2
+        "#,
+    )?;
+
+    let rows = stmt
+        .query_map(
+            rusqlite::params![source.to_string(), limit.min(i64::MAX as usize) as i64],
+            |row| {
+                Ok(QueuedRow {
+                    id: row.get(0)?,
+                    source_uri: row.get(1)?,
+                    media: MediaRef {
+                        media_type: parse_media_type(&row.get::<_, String>(2)?),
+                        media_id: row.get(3)?,
+                    },
+                    selected_files: row
+                        .get::<_, Option<String>>(4)?
+                        .and_then(|f| serde_json::from_str(&f).ok()),
+                    limits: SpeedLimits {
+                        download: row.get::<_, Option<i64>>(5)?.map(|l| l as u64),
+                        upload: row.get::<_, Option<i64>>(6)?.map(|l| l as u64),
+                    },
+                    soulseek_username: row.get(7)?,
+                    soulseek_filename: row.get(8)?,
+                    size_bytes: row.get(9)?,
+                })
+            },
+        )?
+        .collect::<std::result::Result<_, _>>()?;
+    Ok(rows)
+}
+
+/// Record that an engine took a queued download.
+fn mark_started(conn: &Connection, download_id: i64, source_id: &str) -> Result<()> {
+    conn.execute(
+        r#"
+        UPDATE downloads
+        SET source_id = ?1, status = 'downloading', queue_order = NULL,
+            started_at = datetime('now'), removed_at = NULL
+        WHERE id = ?2
+        "#,
+        rusqlite::params![source_id, download_id],
+    )?;
+    Ok(())
+}
+
+/// Record that a queued download could not be started, and reset its media.
+fn mark_start_failed(conn: &Connection, row: &QueuedRow, error: &str) -> Result<()> {
+    conn.execute(
+        "UPDATE downloads SET status = 'failed', error_message = ?1, queue_order = NULL WHERE id = ?2",
+        rusqlite::params![error, row.id],
+    )?;
+    reset_media_status(conn, row.media.media_type, row.media.media_id)
+}
+
+/// Record that a running download failed, and reset its media.
+fn mark_failed(
+    conn: &Connection,
+    source: DownloadSource,
+    source_id: &str,
+    error: &str,
+) -> Result<()> {
+    let download: Option<(i64, String, i64)> = conn
+        .query_row(
+            r#"
+            SELECT id, media_type, media_id FROM downloads
+            WHERE source_type = ?1 AND source_id = ?2 AND status = 'downloading'
+            "#,
+            rusqlite::params![source.to_string(), source_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()?;
+    let Some((id, media_type, media_id)) = download else {
+        return Ok(());
+    };
+
+    conn.execute(
+        "UPDATE downloads SET status = 'failed', error_message = ?1 WHERE id = ?2",
+        rusqlite::params![error, id],
+    )?;
+    reset_media_status(conn, parse_media_type(&media_type), media_id)
+}
+
+/// A unique placeholder `source_id` for a download that has not started.
+fn queued_source_id() -> String {
+    format!("{}{}", QUEUED_SOURCE_PREFIX, Uuid::new_v4())
+}
+
+fn parse_media_type(media_type: &str) -> MediaType {
+    match media_type {
+        "episode" => MediaType::Episode,
+        "album" => MediaType::Album,
+        "track" => MediaType::Track,
+        _ => MediaType::Movie,
+    }
+}
+
+fn parse_status(status: &str) -> DownloadStatus {
+    match status {
+        "downloading" => DownloadStatus::Downloading,
+        "seeding" => DownloadStatus::Seeding,
+        "processing" => DownloadStatus::Processing,
+        "completed" => DownloadStatus::Completed,
+        "failed" => DownloadStatus::Failed,
+        "paused" => DownloadStatus::Paused,
+        _ => DownloadStatus::Queued,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insert_movie(conn: &Connection, tmdb_id: i64, added_at: &str) -> MediaRef {
+        conn.execute(
+            "INSERT INTO movies (tmdb_id, title, year, added_at) VALUES (?1, 'Movie', 2024, ?2)",
+            rusqlite::params![tmdb_id, added_at],
+        )
+        .unwrap();
+        MediaRef {
+            media_type: MediaType::Movie,
+            media_id: conn.last_insert_rowid(),
+        }
+    }
+
+    fn request(media: MediaRef, priority: QueuePriority) -> QueueRequest {
+        QueueRequest {
+            name: "Movie".to_string(),
+            release_title: None,
+            media,
+            source: QueuedSource::Torrent {
+                source: TorrentSource::Magnet("magnet:?xt=urn:btih:abc".to_string()),
+                files: Some(vec![2, 5]),
+            },
+            priority,
+        }
+    }
+
+    fn enqueue(conn: &Connection, media: MediaRef, priority: QueuePriority) -> i64 {
+        insert_queued(
+            conn,
+            &request(media, priority),
+            DownloadSource::Torrent,
+            "magnet:?xt=urn:btih:abc",
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_insert_queued_flips_media_status() {
+        let conn = crate::db::init_db_memory().unwrap();
+        let media = insert_movie(&conn, 1, "2024-01-01 00:00:00");
+
+        let id = enqueue(&conn, media.clone(), QueuePriority::Manual);
+
+        let (status, source_id, files, order): (String, String, String, i64) = conn
+            .query_row(
+                "SELECT status, source_id, selected_files, queue_order FROM downloads WHERE id = ?1",
+                [id],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .unwrap();
+        assert_eq!(status, "queued");
+        assert!(is_queued_source_id(&source_id));
+        assert_eq!(files, "[2,5]");
+        assert_eq!(order, 1);
+
+        let movie_status: String = conn
+            .query_row(
+                "SELECT status FROM movies WHERE id = ?1",
+                [media.media_id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(movie_status, "downloading");
+    }
+
+    #[test]
+    fn test_queue_order_follows_priority() {
+        let conn = crate::db::init_db_memory().unwrap();
+        let old = insert_movie(&conn, 1, "2020-01-01 00:00:00");
+        let new = insert_movie(&conn, 2, "2024-01-01 00:00:00");
+        let older = insert_movie(&conn, 3, "2019-01-01 00:00:00");
+        let wanted = insert_movie(&conn, 4, "2024-06-01 00:00:00");
+
+        let auto_new = enqueue(&conn, new, QueuePriority::Automatic);
+        let auto_old = enqueue(&conn, old, QueuePriority::Automatic);
+        let manual = enqueue(&conn, wanted, QueuePriority::Manual);
+        let auto_older = enqueue(&conn, older, QueuePriority::Automatic);
+
+        assert_eq!(
+            queued_ids(&conn).unwrap(),
+            [manual, auto_older, auto_old, auto_new]
+        );
+    }
+
+    #[test]
+    fn test_move_in_queue() {
+        let conn = crate::db::init_db_memory().unwrap();
+        let ids: Vec<i64> = (1..=3)
+            .map(|i| {
+                let media = insert_movie(&conn, i, "2024-01-01 00:00:00");
+                enqueue(&conn, media, QueuePriority::Automatic)
+            })
+            .collect();
+
+        move_in_queue(&conn, ids[2], 1).unwrap();
+        assert_eq!(queued_ids(&conn).unwrap(), [ids[2], ids[0], ids[1]]);
+
+        move_in_queue(&conn, ids[2], 99).unwrap();
+        assert_eq!(queued_ids(&conn).unwrap(), [ids[0], ids[1], ids[2]]);
+
+        conn.execute(
+            "UPDATE downloads SET status = 'downloading' WHERE id = ?1",
+            [ids[0]],
+        )
+        .unwrap();
+        assert!(matches!(
+            move_in_queue(&conn, ids[0], 1),
+            Err(AppError::Conflict(_))
+        ));
+        assert!(matches!(
+            move_in_queue(&conn, 999, 1),
+            Err(AppError::NotFound(_))
+        ));
+    }
+
+    #[test]
+    fn test_next_queued_and_start() {
+        let conn = crate::db::init_db_memory().unwrap();
+        let first = enqueue(
+            &conn,
+            insert_movie(&conn, 1, "2024-01-01 00:00:00"),
+            QueuePriority::Manual,
+        );
+        enqueue(
+            &conn,
+            insert_movie(&conn, 2, "2024-01-01 00:00:00"),
+            QueuePriority::Automatic,
+        );
+
+        let rows = next_queued(&conn, DownloadSource::Torrent, 1).unwrap();
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].id, first);
+        assert_eq!(rows[0].selected_files, Some(vec![2, 5]));
+        assert!(next_queued(&conn, DownloadSource::Soulseek, 5)
+            .unwrap()
+            .is_empty());
+
+        mark_started(&conn, first, "abc123").unwrap();
+        assert_eq!(count_active_torrents(&conn).unwrap(), 1);
+        assert_eq!(
+            next_queued(&conn, DownloadSource::Torrent, 5)
+                .unwrap()
+                .len(),
+            1
+        );
+    }
+
+    #[test]
+    fn test_requeue_resets_download() {
+        let conn = crate::db::init_db_memory().unwrap();
+        let media = insert_movie(&conn, 1, "2024-01-01 00:00:00");
+        let id = enqueue(&conn, media.clone(), QueuePriority::Manual);
+        mark_started(&conn, id, "abc123").unwrap();
+        let row = next_queued(&conn, DownloadSource::Torrent, 1).unwrap();
+        assert!(row.is_empty());
+        conn.execute(
+            "UPDATE downloads SET status = 'failed', error_message = 'boom', progress = 40 WHERE id = ?1",
+            [id],
+        )
+        .unwrap();
+
+        requeue(&conn, id).unwrap();
+
+        let (status, source_id, error, progress): (String, String, Option<String>, f64) = conn
+            .query_row(
+                "SELECT status, source_id, error_message, progress FROM downloads WHERE id = ?1",
+                [id],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .unwrap();
+        assert_eq!(status, "queued");
+        assert!(is_queued_source_id(&source_id));
+        assert!(error.is_none());
+        assert_eq!(progress, 0.0);
+        assert_eq!(queued_ids(&conn).unwrap(), [id]);
+    }
+
+    #[tokio::test]
+    async fn test_enqueue_leaves_starting_to_the_queue() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config: Config = serde_json::from_str("{}").unwrap();
+        config.torrent.download_dir = dir.path().to_path_buf();
+        let engine = TorrentEngine::new_shared(config.torrent.clone())
+            .await
+            .unwrap();
+
+        let conn = crate::db::init_db_memory().unwrap();
+        let media = insert_movie(&conn, 1, "2024-01-01 00:00:00");
+        let queue =
+            DownloadQueue::new_shared(Arc::new(Mutex::new(conn)), &config, Some(engine), None);
+
+        // A slot is free, but the watch task starts the download, not the caller
+        let queued = queue
+            .enqueue(request(media, QueuePriority::Manual))
+            .await
+            .unwrap();
+        assert_eq!(queued.status, DownloadStatus::Queued);
+        assert!(is_queued_source_id(&queued.source_id));
+    }
+
+    #[test]
+    fn test_mark_failed_resets_media() {
+        let conn = crate::db::init_db_memory().unwrap();
+        let media = insert_movie(&conn, 1, "2024-01-01 00:00:00");
+        let id = enqueue(&conn, media.clone(), QueuePriority::Manual);
+        mark_started(&conn, id, "abc123").unwrap();
+
+        mark_failed(&conn, DownloadSource::Torrent, "abc123", "Peer went away").unwrap();
+
+        let (status, error): (String, Option<String>) = conn
+            .query_row(
+                "SELECT status, error_message FROM downloads WHERE id = ?1",
+                [id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(status, "failed");
+        assert_eq!(error.as_deref(), Some("Peer went away"));
+        let media_status: String = conn
+            .query_row(
+                "SELECT status FROM movies WHERE id = ?1",
+                [media.media_id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(media_status, "missing");
+    }
+
+    #[test]
+    fn test_free_slots() {
+        assert_eq!(free_slots(0, 10), usize::MAX);
+        assert_eq!(free_slots(3, 1), 2);
+        assert_eq!(free_slots(3, 5), 0);
+    }
+}
@@ -0,0 +1,392 @@
+//! Release blocklist.
+//!
+//! Releases that failed to download, stalled, or that a user removed are
+//! never grabbed again. A release is blocked by its info hash, or when the
+//! indexer only links a .torrent file, by its title. [`IndexerManager`]
+//! keeps the blocklist in memory and drops blocked releases from results.
+//!
+//! [`IndexerManager`]: super::IndexerManager
+
+use std::collections::HashSet;
+
+use rusqlite::{Connection, OptionalExtension};
+use serde::Serialize;
+
+use crate::error::Result;
+use crate::services::download_queue::is_queued_source_id;
+use crate::services::torrent_source::magnet_info_hash;
+
+use super::Release;
+
+/// Columns selected for [`BlocklistEntry::from_row`].
+const ENTRY_COLUMNS: &str =
+    "id, info_hash, title, media_type, media_id, reason, message, created_at";
+
+/// Why a release was blocklisted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BlocklistReason {
+    /// The torrent reported an error
+    Failed,
+    /// The torrent made no progress for too long
+    Stalled,
+    /// A user removed the download and blocklisted it
+    Manual,
+}
+
+impl BlocklistReason {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "failed" => Some(BlocklistReason::Failed),
+            "stalled" => Some(BlocklistReason::Stalled),
+            "manual" => Some(BlocklistReason::Manual),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for BlocklistReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BlocklistReason::Failed => write!(f, "failed"),
+            BlocklistReason::Stalled => write!(f, "stalled"),
+            BlocklistReason::Manual => write!(f, "manual"),
+        }
+    }
+}
+
+/// A blocklisted release.
+#[derive(Debug, Clone, Serialize)]
+pub struct BlocklistEntry {
+    pub id: i64,
+    /// Info hash, unknown for .torrent links that never started
+    pub info_hash: Option<String>,
+    pub title: String,
+    /// Media the release was grabbed for
+    pub media_type: Option<String>,
+    pub media_id: Option<i64>,
+    pub reason: BlocklistReason,
+    /// Error reported by the torrent engine
+    pub message: Option<String>,
+    pub created_at: String,
+}
+
+impl BlocklistEntry {
+    /// Load an entry by id.
+    pub fn load(conn: &Connection, id: i64) -> Result<Option<Self>> {
+        Ok(conn
+            .query_row(
+                &format!("SELECT {} FROM blocklist WHERE id = ?1", ENTRY_COLUMNS),
+                [id],
+                Self::from_row,
+            )
+            .optional()?)
+    }
+
+    /// Load every entry, newest first.
+    pub fn load_all(conn: &Connection) -> Result<Vec<Self>> {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM blocklist ORDER BY created_at DESC, id DESC",
+            ENTRY_COLUMNS
+        ))?;
+        let entries = stmt
+            .query_map([], Self::from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(entries)
+    }
+
+    /// Map a row selected with the entry columns.
+    fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        let reason: String = row.get(5)?;
+        let reason = BlocklistReason::parse(&reason).ok_or_else(|| {
+            rusqlite::Error::FromSqlConversionFailure(
+                5,
+                rusqlite::types::Type::Text,
+                format!("Unknown blocklist reason '{}'", reason).into(),
+            )
+        })?;
+
+        Ok(Self {
+            id: row.get(0)?,
+            info_hash: row.get(1)?,
+            title: row.get(2)?,
+            media_type: row.get(3)?,
+            media_id: row.get(4)?,
+            reason,
+            message: row.get(6)?,
+            created_at: row.get(7)?,
+        })
+    }
+}
+
+/// Blocklist a torrent download's release.
+///
+/// The release is named by the title it was grabbed under, or the torrent's
+/// own name, or the download's. Downloads that are already blocklisted keep
+/// their entry. Returns the id of the entry, or `None` for downloads that
+/// do not exist or are not torrents.
+pub fn block_download(
+    conn: &Connection,
+    download_id: i64,
+    torrent_name: Option<&str>,
+    reason: BlocklistReason,
+    message: Option<&str>,
+) -> Result<Option<i64>> {
+    let download = conn
+        .query_row(
+            r#"
+            SELECT source_type, source_id, source_uri, name, release_title, media_type, media_id
+            FROM downloads WHERE id = ?1
+            "#,
+            [download_id],
+            |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, String>(3)?,
+                    row.get::<_, Option<String>>(4)?,
+                    row.get::<_, String>(5)?,
+                    row.get::<_, i64>(6)?,
+                ))
+            },
+        )
+        .optional()?;
+    let Some((source_type, source_id, source_uri, name, release_title, media_type, media_id)) =
+        download
+    else {
+        return Ok(None);
+    };
+    if source_type != "torrent" {
+        return Ok(None);
+    }
+
+    // Queued downloads only know the info hash their magnet link gives
+    let info_hash = if is_queued_source_id(&source_id) {
+        magnet_info_hash(&source_uri)
+    } else {
+        Some(source_id.to_lowercase())
+    };
+    let title = release_title
+        .or_else(|| torrent_name.map(str::to_string))
+        .unwrap_or(name);
+
+    let existing: Option<i64> = conn
+        .query_row(
+            r#"
+            SELECT id FROM blocklist
+            WHERE (?1 IS NOT NULL AND info_hash = ?1) OR (?1 IS NULL AND info_hash IS NULL AND title = ?2)
+            "#,
+            rusqlite::params![info_hash, title],
+            |row| row.get(0),
+        )
+        .optional()?;
+    if existing.is_some() {
+        return Ok(existing);
+    }
+
+    conn.execute(
+        r#"
+        INSERT INTO blocklist (info_hash, title, media_type, media_id, reason, message)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+        "#,
+        rusqlite::params![
+            info_hash,
+            title,
+            media_type,
+            media_id,
+            reason.to_string(),
+            message
+        ],
+    )?;
+    let id = conn.last_insert_rowid();
+
+    tracing::info!(
+        download_id = download_id,
+        blocklist_id = id,
+        title = %title,
+        reason = %reason,
+        "Blocklisted release"
+    );
+
+    Ok(Some(id))
+}
+
+/// Blocked info hashes and titles, to check releases against.
+#[derive(Debug, Clone, Default)]
+pub struct Blocklist {
+    info_hashes: HashSet<String>,
+    titles: HashSet<String>,
+    entries: usize,
+}
+
+impl Blocklist {
+    /// Load the `blocklist` table.
+    pub fn load(conn: &Connection) -> Result<Self> {
+        let mut blocklist = Self::default();
+        let mut stmt = conn.prepare("SELECT info_hash, title FROM blocklist")?;
+        let rows = stmt.query_map([], |row| {
+            Ok((row.get::<_, Option<String>>(0)?, row.get::<_, String>(1)?))
+        })?;
+        for row in rows {
+            let (info_hash, title) = row?;
+            if let Some(info_hash) = info_hash {
+                blocklist.info_hashes.insert(info_hash.to_lowercase());
+            }
+            blocklist.titles.insert(normalize_title(&title));
+            blocklist.entries += 1;
+        }
+        Ok(blocklist)
+    }
+
+    /// Number of blocked releases.
+    pub fn len(&self) -> usize {
+        self.entries
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries == 0
+    }
+
+    /// Whether a release is blocked, by info hash or by title.
+    pub fn contains(&self, release: &Release) -> bool {
+        let info_hash = release
+            .info_hash
+            .clone()
+            .or_else(|| magnet_info_hash(&release.magnet));
+        if info_hash.is_some_and(|h| self.info_hashes.contains(&h)) {
+            return true;
+        }
+        self.titles.contains(&normalize_title(&release.title))
+    }
+}
+
+fn normalize_title(title: &str) -> String {
+    title.trim().to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::indexer::{Quality, Source};
+
+    const HASH: &str = "c12fe1c06bba254a9dc9f519b335aa7c1367a88a";
+
+    fn release(title: &str, magnet: &str) -> Release {
+        Release {
+            id: Release::generate_id("Test", title, magnet),
+            title: title.to_string(),
+            indexer: "Test".to_string(),
+            magnet: magnet.to_string(),
+            info_hash: magnet_info_hash(magnet),
+            size_bytes: 0,
+            seeders: 10,
+            leechers: 0,
+            quality: Quality::Unknown,
+            source: Source::Unknown,
+            codec: None,
+            audio: None,
+            group: None,
+            proper: false,
+            repack: false,
+            uploaded_at: None,
+        }
+    }
+
+    fn insert_download(conn: &Connection, source_id: &str, source_uri: &str) -> i64 {
+        conn.execute(
+            "INSERT INTO movies (tmdb_id, title, year, status) VALUES (603, 'The Matrix', 1999, 'downloading')",
+            [],
+        )
+        .unwrap();
+        let movie_id = conn.last_insert_rowid();
+        conn.execute(
+            "INSERT INTO downloads (source_type, source_id, name, media_type, media_id, source_uri, status) VALUES ('torrent', ?1, 'The Matrix', 'movie', ?2, ?3, 'downloading')",
+            rusqlite::params![source_id, movie_id, source_uri],
+        )
+        .unwrap();
+        conn.last_insert_rowid()
+    }
+
+    #[test]
+    fn test_block_download() {
+        let conn = crate::db::init_db_memory().unwrap();
+        let id = insert_download(&conn, HASH, &format!("magnet:?xt=urn:btih:{}", HASH));
+        conn.execute(
+            "UPDATE downloads SET release_title = 'The.Matrix.1999.1080p.BluRay.x264' WHERE id = ?1",
+            [id],
+        )
+        .unwrap();
+
+        let entry_id = block_download(
+            &conn,
+            id,
+            Some("The Matrix (1999)"),
+            BlocklistReason::Failed,
+            Some("tracker error"),
+        )
+        .unwrap()
+        .unwrap();
+
+        let entry = BlocklistEntry::load(&conn, entry_id).unwrap().unwrap();
+        assert_eq!(entry.info_hash.as_deref(), Some(HASH));
+        assert_eq!(entry.title, "The.Matrix.1999.1080p.BluRay.x264");
+        assert_eq!(entry.media_type.as_deref(), Some("movie"));
+        assert_eq!(entry.reason, BlocklistReason::Failed);
+        assert_eq!(entry.message.as_deref(), Some("tracker error"));
+
+        // Blocking it again keeps the first entry
+        let again = block_download(&conn, id, None, BlocklistReason::Manual, None).unwrap();
+        assert_eq!(again, Some(entry_id));
+        assert_eq!(BlocklistEntry::load_all(&conn).unwrap().len(), 1);
+
+        assert_eq!(
+            block_download(&conn, 999, None, BlocklistReason::Manual, None).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_block_queued_download() {
+        let conn = crate::db::init_db_memory().unwrap();
+        let id = insert_download(&conn, "queued:1234", "https://tracker.example/dl/1.torrent");
+
+        let entry_id = block_download(&conn, id, None, BlocklistReason::Manual, None)
+            .unwrap()
+            .unwrap();
+
+        // Without a magnet link only the title is known
+        let entry = BlocklistEntry::load(&conn, entry_id).unwrap().unwrap();
+        assert_eq!(entry.info_hash, None);
+        assert_eq!(entry.title, "The Matrix");
+    }
+
+    #[test]
+    fn test_blocklist_contains() {
+        let conn = crate::db::init_db_memory().unwrap();
+        conn.execute(
+            "INSERT INTO blocklist (info_hash, title, reason) VALUES (?1, 'Blocked.By.Hash.1080p', 'failed')",
+            [HASH],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO blocklist (title, reason) VALUES ('Blocked.By.Title.720p', 'manual')",
+            [],
+        )
+        .unwrap();
+
+        let blocklist = Blocklist::load(&conn).unwrap();
+        assert_eq!(blocklist.len(), 2);
+
+        let magnet = format!("magnet:?xt=urn:btih:{}", HASH.to_uppercase());
+        assert!(blocklist.contains(&release("Renamed.Upload", &magnet)));
+        assert!(blocklist.contains(&release(
+            "blocked.by.title.720p",
+            "https://tracker.example/dl/2.torrent"
+        )));
+        assert!(!blocklist.contains(&release(
+            "Other.Release.1080p",
+            "magnet:?xt=urn:btih:0000000000000000000000000000000000000000"
+        )));
+    }
+}
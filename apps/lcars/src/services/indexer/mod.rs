@@ -1,21 +1,29 @@
 //! Torrent indexer service for searching multiple torrent providers.
 //!
 //! Provides a unified interface for searching torrents across multiple indexer sites
-//! and aggregating results.
+//! and aggregating results. The providers come from the `indexers` table and are
+//! reloaded whenever it changes, and so is the release blocklist.
 
+pub mod blocklist;
 pub mod parser;
+pub mod profile;
 pub mod providers;
+pub mod selection;
 
 use async_trait::async_trait;
 use futures::future::join_all;
+use rusqlite::Connection;
 use serde::Serialize;
 use sha2::{Digest, Sha256};
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
 
-use crate::error::Result;
+use crate::db::models::Indexer;
+use crate::error::{AppError, Result};
+pub use blocklist::{Blocklist, BlocklistEntry, BlocklistReason};
 pub use parser::{parse_music_release, parse_release_name, Quality, Source};
-use providers::{EztvProvider, LeetxProvider, RutrackerProvider, YtsProvider};
+pub use profile::{ProfileMediaType, QualityItem, QualityProfile};
+pub use selection::{select_best_release, SelectionCriteria};
 
 /// Type of media to search for.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -36,6 +44,8 @@ pub struct SearchQuery {
     pub imdb_id: Option<String>,
     /// TMDB ID for movies/TV
     pub tmdb_id: Option<i32>,
+    /// TVDB ID for TV
+    pub tvdb_id: Option<i32>,
     /// MusicBrainz ID for music
     pub mbid: Option<String>,
     /// Release year filter
@@ -95,6 +105,25 @@ impl SearchQuery {
         self
     }
 
+    /// Set TMDB ID for precise matching.
+    pub fn tmdb_id(mut self, tmdb_id: i32) -> Self {
+        self.tmdb_id = Some(tmdb_id);
+        self
+    }
+
+    /// Set TVDB ID for precise matching of TV shows.
+    pub fn tvdb_id(mut self, tvdb_id: i32) -> Self {
+        self.tvdb_id = Some(tvdb_id);
+        self
+    }
+
+    /// Set artist and album for music searches.
+    pub fn album(mut self, artist: impl Into<String>, album: impl Into<String>) -> Self {
+        self.artist = Some(artist.into());
+        self.album = Some(album.into());
+        self
+    }
+
     /// Build a search query string suitable for indexers.
     pub fn build_query_string(&self) -> String {
         let mut parts = vec![self.query.clone()];
@@ -135,6 +164,8 @@ pub struct Release {
     pub indexer: String,
     /// Magnet link or torrent URL
     pub magnet: String,
+    /// Info hash (lowercase hex), when known without fetching the .torrent
+    pub info_hash: Option<String>,
     /// File size in bytes
     pub size_bytes: u64,
     /// Number of seeders
@@ -181,6 +212,14 @@ impl Release {
     }
 }
 
+/// A release from an indexer's feed.
+#[derive(Debug, Clone)]
+pub struct FeedRelease {
+    pub release: Release,
+    /// Media the indexer is searched for, and so may be matched against
+    pub media_types: Vec<MediaSearchType>,
+}
+
 /// Trait for implementing torrent indexer providers.
 #[async_trait]
 pub trait IndexerProvider: Send + Sync {
@@ -199,31 +238,102 @@ pub trait IndexerProvider: Send + Sync {
     /// Search for releases matching the given query.
     async fn search(&self, query: &SearchQuery) -> Result<Vec<Release>>;
 
+    /// Newest releases, for RSS sync. Indexers without a feed return none.
+    async fn latest(&self) -> Result<Vec<Release>> {
+        Ok(Vec::new())
+    }
+
     /// Test the indexer connection and functionality.
     async fn test(&self) -> Result<IndexerTestResult>;
 }
 
+/// A provider with the settings of the `indexers` row it was built from.
+#[derive(Clone)]
+pub struct ConfiguredProvider {
+    pub provider: Arc<dyn IndexerProvider>,
+    /// Indexer priority (0-100), weighted into the ranking of its releases
+    pub priority: i32,
+    /// Categories (`movies`, `tv`, `music`) to search this indexer for.
+    /// `None` searches it for everything it supports.
+    pub categories: Option<Vec<String>>,
+}
+
+impl ConfiguredProvider {
+    /// Wrap a provider with no priority and no category restriction.
+    pub fn new(provider: Arc<dyn IndexerProvider>) -> Self {
+        Self {
+            provider,
+            priority: 0,
+            categories: None,
+        }
+    }
+
+    /// Build the provider for an `indexers` row.
+    pub fn from_indexer(indexer: &Indexer) -> Result<Self> {
+        let categories = match indexer.categories.as_deref().map(str::trim) {
+            None | Some("") => None,
+            Some(json) => Some(serde_json::from_str::<Vec<String>>(json).map_err(|_| {
+                AppError::BadRequest(format!(
+                    "Invalid categories for indexer '{}': expected a JSON list",
+                    indexer.name
+                ))
+            })?),
+        };
+
+        Ok(Self {
+            provider: providers::from_indexer(indexer)?,
+            priority: indexer.priority,
+            categories,
+        })
+    }
+
+    /// Whether this indexer should be searched for the given media type.
+    fn handles(&self, media_type: Option<MediaSearchType>) -> bool {
+        let (category, supported) = match media_type {
+            Some(MediaSearchType::Movie) => ("movies", self.provider.supports_movies()),
+            Some(MediaSearchType::TvEpisode) => ("tv", self.provider.supports_tv()),
+            Some(MediaSearchType::MusicAlbum) => ("music", self.provider.supports_music()),
+            None => return true, // Search all if no type specified
+        };
+
+        supported
+            && self.categories.as_ref().is_none_or(|categories| {
+                categories.iter().any(|c| c.eq_ignore_ascii_case(category))
+            })
+    }
+
+    /// Ranking bonus for this indexer's releases. Kept below one quality step
+    /// so priority only decides between otherwise similar releases.
+    fn weight(&self) -> u32 {
+        self.priority.clamp(0, 100) as u32 / 4
+    }
+}
+
 /// Manager for coordinating searches across multiple indexer providers.
 pub struct IndexerManager {
-    providers: Vec<Arc<dyn IndexerProvider>>,
+    providers: RwLock<Vec<ConfiguredProvider>>,
+    /// Releases left out of search and feed results
+    blocklist: RwLock<Blocklist>,
 }
 
 impl IndexerManager {
-    /// Create a new indexer manager with default providers.
+    /// Create an indexer manager with no providers. Call [`reload`](Self::reload)
+    /// to load them from the database.
     pub fn new() -> Self {
-        Self {
-            providers: vec![
-                Arc::new(LeetxProvider::new()),
-                Arc::new(EztvProvider::new()),
-                Arc::new(YtsProvider::new()),
-                Arc::new(RutrackerProvider::new()),
-            ],
-        }
+        Self::with_configured(Vec::new())
     }
 
     /// Create an indexer manager with custom providers.
     pub fn with_providers(providers: Vec<Arc<dyn IndexerProvider>>) -> Self {
-        Self { providers }
+        Self::with_configured(providers.into_iter().map(ConfiguredProvider::new).collect())
+    }
+
+    /// Create an indexer manager with custom providers and their settings.
+    pub fn with_configured(providers: Vec<ConfiguredProvider>) -> Self {
+        Self {
+            providers: RwLock::new(providers),
+            blocklist: RwLock::new(Blocklist::default()),
+        }
     }
 
     /// Create an indexer manager wrapped in Arc for shared access.
@@ -232,38 +342,123 @@ impl IndexerManager {
     }
 
     /// Get all registered providers.
-    pub fn providers(&self) -> &[Arc<dyn IndexerProvider>] {
-        &self.providers
+    pub fn providers(&self) -> Vec<Arc<dyn IndexerProvider>> {
+        self.snapshot().into_iter().map(|p| p.provider).collect()
+    }
+
+    fn snapshot(&self) -> Vec<ConfiguredProvider> {
+        self.providers.read().unwrap().clone()
+    }
+
+    /// Replace the providers with the enabled rows of the `indexers` table.
+    ///
+    /// Rows that cannot be turned into a provider are skipped with a warning.
+    /// Returns the number of providers loaded.
+    pub fn reload(&self, conn: &Connection) -> Result<usize> {
+        let mut stmt = conn.prepare(
+            r#"
+            SELECT id, name, indexer_type, url, api_key, enabled, priority, categories, last_check, last_error, created_at
+            FROM indexers
+            WHERE enabled = 1
+            ORDER BY priority DESC, id
+            "#,
+        )?;
+        let indexers = stmt
+            .query_map([], |row| {
+                Ok(Indexer {
+                    id: row.get(0)?,
+                    name: row.get(1)?,
+                    indexer_type: row.get(2)?,
+                    url: row.get(3)?,
+                    api_key: row.get(4)?,
+                    enabled: row.get(5)?,
+                    priority: row.get(6)?,
+                    categories: row.get(7)?,
+                    last_check: row.get(8)?,
+                    last_error: row.get(9)?,
+                    created_at: row.get(10)?,
+                })
+            })?
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        let providers: Vec<ConfiguredProvider> = indexers
+            .iter()
+            .filter_map(|indexer| match ConfiguredProvider::from_indexer(indexer) {
+                Ok(provider) => Some(provider),
+                Err(e) => {
+                    tracing::warn!(
+                        indexer_id = indexer.id,
+                        indexer = %indexer.name,
+                        error = %e,
+                        "Skipping indexer"
+                    );
+                    None
+                }
+            })
+            .collect();
+
+        let count = providers.len();
+        *self.providers.write().unwrap() = providers;
+        tracing::info!(providers = count, "Indexers loaded");
+
+        Ok(count)
+    }
+
+    /// Replace the blocklist with the `blocklist` table.
+    ///
+    /// Returns the number of blocked releases.
+    pub fn reload_blocklist(&self, conn: &Connection) -> Result<usize> {
+        let blocklist = Blocklist::load(conn)?;
+        let count = blocklist.len();
+        *self.blocklist.write().unwrap() = blocklist;
+        tracing::debug!(releases = count, "Blocklist loaded");
+        Ok(count)
+    }
+
+    /// Drop blocklisted releases.
+    fn unblocked<T>(&self, items: Vec<T>, release: impl Fn(&T) -> &Release) -> Vec<T> {
+        let blocklist = self.blocklist.read().unwrap();
+        if blocklist.is_empty() {
+            return items;
+        }
+        let total = items.len();
+        let items: Vec<T> = items
+            .into_iter()
+            .filter(|item| !blocklist.contains(release(item)))
+            .collect();
+        if items.len() < total {
+            tracing::debug!(
+                blocked = total - items.len(),
+                "Dropped blocklisted releases"
+            );
+        }
+        items
     }
 
     /// Search all appropriate providers for releases matching the query.
     ///
-    /// Results are aggregated, deduplicated, and sorted by quality/seeders.
+    /// Results are aggregated, deduplicated, and sorted by quality/seeders,
+    /// weighted by indexer priority. Blocklisted releases are left out.
     pub async fn search(&self, query: &SearchQuery) -> Result<Vec<Release>> {
-        // Filter providers based on media type
+        // Filter providers based on media type and indexer categories
         let suitable_providers: Vec<_> = self
-            .providers
-            .iter()
-            .filter(|p| match query.media_type {
-                Some(MediaSearchType::Movie) => p.supports_movies(),
-                Some(MediaSearchType::TvEpisode) => p.supports_tv(),
-                Some(MediaSearchType::MusicAlbum) => p.supports_music(),
-                None => true, // Search all if no type specified
-            })
+            .snapshot()
+            .into_iter()
+            .filter(|p| p.handles(query.media_type))
             .collect();
 
         // Search all providers in parallel
         let search_futures: Vec<_> = suitable_providers
-            .iter()
-            .map(|provider| {
-                let provider = Arc::clone(provider);
+            .into_iter()
+            .map(|configured| {
                 let query = query.clone();
                 async move {
-                    match provider.search(&query).await {
-                        Ok(results) => results,
+                    let weight = configured.weight();
+                    match configured.provider.search(&query).await {
+                        Ok(results) => results.into_iter().map(|r| (r, weight)).collect(),
                         Err(e) => {
                             tracing::warn!(
-                                indexer = %provider.name(),
+                                indexer = %configured.provider.name(),
                                 error = %e,
                                 "Indexer search failed"
                             );
@@ -274,51 +469,95 @@ impl IndexerManager {
             })
             .collect();
 
-        let all_results: Vec<Vec<Release>> = join_all(search_futures).await;
+        let all_results: Vec<Vec<(Release, u32)>> = join_all(search_futures).await;
 
-        // Flatten and deduplicate results
-        let mut releases: Vec<Release> = all_results.into_iter().flatten().collect();
+        // Flatten, drop blocklisted releases and deduplicate results
+        let mut releases = self.unblocked(all_results.into_iter().flatten().collect(), |(r, _)| r);
 
-        // Deduplicate by magnet link (keep the one with more seeders)
+        // Deduplicate by info hash, or the full link when the hash is unknown
+        // (keep the one with more seeders)
         let mut seen: HashMap<String, usize> = HashMap::new();
-        let mut unique_releases: Vec<Release> = Vec::new();
-
-        for release in releases.drain(..) {
-            let magnet_hash = &release.magnet[..release.magnet.len().min(60)];
-            if let Some(&idx) = seen.get(magnet_hash) {
-                // Keep the one with more seeders
-                if release.seeders > unique_releases[idx].seeders {
-                    unique_releases[idx] = release;
+        let mut unique_releases: Vec<(Release, u32)> = Vec::new();
+
+        for (release, weight) in releases.drain(..) {
+            let key = release
+                .info_hash
+                .clone()
+                .unwrap_or_else(|| release.magnet.clone());
+            if let Some(&idx) = seen.get(&key) {
+                // Keep the one with more seeders, then the higher priority indexer
+                let (kept, kept_weight) = &unique_releases[idx];
+                if (release.seeders, weight) > (kept.seeders, *kept_weight) {
+                    unique_releases[idx] = (release, weight);
                 }
             } else {
-                seen.insert(magnet_hash.to_string(), unique_releases.len());
-                unique_releases.push(release);
+                seen.insert(key, unique_releases.len());
+                unique_releases.push((release, weight));
             }
         }
 
-        // Sort by score (quality + seeders)
-        unique_releases.sort_by_key(|r| std::cmp::Reverse(r.score()));
+        // Sort by score (quality + seeders) plus indexer priority
+        unique_releases.sort_by_key(|(r, weight)| std::cmp::Reverse(r.score() + weight));
 
-        Ok(unique_releases)
+        Ok(unique_releases.into_iter().map(|(r, _)| r).collect())
+    }
+
+    /// Fetch the newest releases of every indexer, for RSS sync.
+    ///
+    /// Indexers that fail are skipped with a warning. Blocklisted releases
+    /// are left out.
+    pub async fn latest(&self) -> Vec<FeedRelease> {
+        let feed_futures: Vec<_> = self
+            .snapshot()
+            .into_iter()
+            .map(|configured| async move {
+                let media_types: Vec<MediaSearchType> = [
+                    MediaSearchType::Movie,
+                    MediaSearchType::TvEpisode,
+                    MediaSearchType::MusicAlbum,
+                ]
+                .into_iter()
+                .filter(|media_type| configured.handles(Some(*media_type)))
+                .collect();
+
+                match configured.provider.latest().await {
+                    Ok(releases) => releases
+                        .into_iter()
+                        .map(|release| FeedRelease {
+                            release,
+                            media_types: media_types.clone(),
+                        })
+                        .collect(),
+                    Err(e) => {
+                        tracing::warn!(
+                            indexer = %configured.provider.name(),
+                            error = %e,
+                            "Indexer feed failed"
+                        );
+                        Vec::new()
+                    }
+                }
+            })
+            .collect();
+
+        let releases = join_all(feed_futures).await.into_iter().flatten().collect();
+        self.unblocked(releases, |f: &FeedRelease| &f.release)
     }
 
     /// Test all providers and return their status.
     pub async fn test_all(&self) -> Vec<IndexerTestResult> {
         let test_futures: Vec<_> = self
-            .providers
-            .iter()
-            .map(|provider| {
-                let provider = Arc::clone(provider);
-                async move {
-                    match provider.test().await {
-                        Ok(result) => result,
-                        Err(e) => IndexerTestResult {
-                            name: provider.name().to_string(),
-                            success: false,
-                            response_time_ms: 0,
-                            error: Some(e.to_string()),
-                        },
-                    }
+            .providers()
+            .into_iter()
+            .map(|provider| async move {
+                match provider.test().await {
+                    Ok(result) => result,
+                    Err(e) => IndexerTestResult {
+                        name: provider.name().to_string(),
+                        success: false,
+                        response_time_ms: 0,
+                        error: Some(e.to_string()),
+                    },
                 }
             })
             .collect();
@@ -336,6 +575,7 @@ impl Default for IndexerManager {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::services::torrent_source::magnet_info_hash;
 
     #[test]
     fn test_search_query_builder() {
@@ -368,6 +608,7 @@ mod tests {
             title: "test".to_string(),
             indexer: "test".to_string(),
             magnet: "magnet:test".to_string(),
+            info_hash: None,
             size_bytes: 0,
             seeders: 100,
             leechers: 10,
@@ -390,4 +631,286 @@ mod tests {
 
         assert!(high_quality.score() > low_quality.score());
     }
+
+    /// Provider returning fixed releases, for every media type.
+    struct MockProvider {
+        name: &'static str,
+        releases: Vec<Release>,
+    }
+
+    #[async_trait]
+    impl IndexerProvider for MockProvider {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn supports_movies(&self) -> bool {
+            true
+        }
+
+        fn supports_tv(&self) -> bool {
+            true
+        }
+
+        fn supports_music(&self) -> bool {
+            true
+        }
+
+        async fn search(&self, _query: &SearchQuery) -> Result<Vec<Release>> {
+            Ok(self.releases.clone())
+        }
+
+        async fn latest(&self) -> Result<Vec<Release>> {
+            Ok(self.releases.clone())
+        }
+
+        async fn test(&self) -> Result<IndexerTestResult> {
+            Ok(IndexerTestResult {
+                name: self.name.to_string(),
+                success: true,
+                response_time_ms: 0,
+                error: None,
+            })
+        }
+    }
+
+    fn release(indexer: &str, magnet: &str, seeders: u32) -> Release {
+        Release {
+            id: Release::generate_id(indexer, "Movie.2024.1080p.BluRay", magnet),
+            title: "Movie.2024.1080p.BluRay".to_string(),
+            indexer: indexer.to_string(),
+            magnet: magnet.to_string(),
+            info_hash: magnet_info_hash(magnet),
+            size_bytes: 0,
+            seeders,
+            leechers: 0,
+            quality: Quality::P1080,
+            source: Source::BluRay,
+            codec: None,
+            audio: None,
+            group: None,
+            proper: false,
+            repack: false,
+            uploaded_at: None,
+        }
+    }
+
+    fn configured(
+        name: &'static str,
+        releases: Vec<Release>,
+        priority: i32,
+        categories: Option<&[&str]>,
+    ) -> ConfiguredProvider {
+        ConfiguredProvider {
+            provider: Arc::new(MockProvider { name, releases }),
+            priority,
+            categories: categories.map(|c| c.iter().map(|s| s.to_string()).collect()),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_search_honours_categories() {
+        let manager = IndexerManager::with_configured(vec![
+            configured(
+                "movies-only",
+                vec![release("movies-only", "magnet:?xt=urn:btih:aaa", 10)],
+                50,
+                Some(&["movies"]),
+            ),
+            configured(
+                "music-only",
+                vec![release("music-only", "magnet:?xt=urn:btih:bbb", 10)],
+                50,
+                Some(&["music"]),
+            ),
+        ]);
+
+        let query = SearchQuery::new("Movie").media_type(MediaSearchType::Movie);
+        let results = manager.search(&query).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].indexer, "movies-only");
+
+        // Untyped searches go to every indexer
+        let results = manager.search(&SearchQuery::new("Movie")).await.unwrap();
+        assert_eq!(results.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_search_weights_priority() {
+        let manager = IndexerManager::with_configured(vec![
+            configured(
+                "low",
+                vec![release("low", "magnet:?xt=urn:btih:aaa", 12)],
+                0,
+                None,
+            ),
+            configured(
+                "high",
+                vec![release("high", "magnet:?xt=urn:btih:bbb", 10)],
+                100,
+                None,
+            ),
+        ]);
+
+        let results = manager.search(&SearchQuery::new("Movie")).await.unwrap();
+        assert_eq!(results[0].indexer, "high");
+        assert_eq!(results[1].indexer, "low");
+    }
+
+    #[tokio::test]
+    async fn test_latest_keeps_indexer_media_types() {
+        let manager = IndexerManager::with_configured(vec![
+            configured(
+                "tv-only",
+                vec![release("tv-only", "magnet:?xt=urn:btih:aaa", 10)],
+                0,
+                Some(&["tv"]),
+            ),
+            configured(
+                "all",
+                vec![release("all", "magnet:?xt=urn:btih:aaa", 10)],
+                0,
+                None,
+            ),
+        ]);
+
+        let feed = manager.latest().await;
+        assert_eq!(feed.len(), 2);
+        assert_eq!(feed[0].release.indexer, "tv-only");
+        assert_eq!(feed[0].media_types, vec![MediaSearchType::TvEpisode]);
+        assert_eq!(feed[1].media_types.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_duplicate_kept_from_higher_priority() {
+        let magnet = "magnet:?xt=urn:btih:aaa";
+        let manager = IndexerManager::with_configured(vec![
+            configured("low", vec![release("low", magnet, 10)], 10, None),
+            configured("high", vec![release("high", magnet, 10)], 90, None),
+        ]);
+
+        let results = manager.search(&SearchQuery::new("Movie")).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].indexer, "high");
+    }
+
+    #[tokio::test]
+    async fn test_duplicates_matched_by_info_hash() {
+        let hash = "c12fe1c06bba254a9dc9f519b335aa7c1367a88a";
+        // Jackett download links only differ past their long common prefix
+        let jackett = |path: &str| {
+            format!(
+                "http://jackett:9117/dl/tracker/?jackett_apikey=0123456789abcdef&path={}",
+                path
+            )
+        };
+        let mut torrent_link = release("jackett", &jackett("one"), 30);
+        torrent_link.info_hash = Some(hash.to_string());
+        let manager = IndexerManager::with_configured(vec![
+            configured(
+                "jackett",
+                vec![torrent_link, release("jackett", &jackett("two"), 5)],
+                0,
+                None,
+            ),
+            configured(
+                "magnets",
+                vec![release(
+                    "magnets",
+                    &format!("magnet:?xt=urn:btih:{}", hash),
+                    10,
+                )],
+                0,
+                None,
+            ),
+        ]);
+
+        let results = manager.search(&SearchQuery::new("Movie")).await.unwrap();
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].magnet, jackett("one"));
+        assert_eq!(results[1].magnet, jackett("two"));
+    }
+
+    #[tokio::test]
+    async fn test_blocklisted_releases_are_left_out() {
+        let blocked = "magnet:?xt=urn:btih:c12fe1c06bba254a9dc9f519b335aa7c1367a88a";
+        let allowed = "magnet:?xt=urn:btih:0000000000000000000000000000000000000000";
+        let manager = IndexerManager::with_configured(vec![configured(
+            "mock",
+            vec![release("mock", blocked, 50), release("mock", allowed, 10)],
+            0,
+            None,
+        )]);
+
+        let conn = crate::db::init_db_memory().unwrap();
+        conn.execute(
+            "INSERT INTO blocklist (info_hash, title, reason) VALUES ('c12fe1c06bba254a9dc9f519b335aa7c1367a88a', 'Upload.Under.Another.Name', 'failed')",
+            [],
+        )
+        .unwrap();
+        assert_eq!(manager.reload_blocklist(&conn).unwrap(), 1);
+
+        let results = manager.search(&SearchQuery::new("Movie")).await.unwrap();
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].magnet, allowed);
+
+        let feed = manager.latest().await;
+        assert_eq!(feed.len(), 1);
+        assert_eq!(feed[0].release.magnet, allowed);
+    }
+
+    #[test]
+    fn test_reload_from_db() {
+        let conn = crate::db::init_db_memory().unwrap();
+        let manager = IndexerManager::new();
+        assert!(manager.providers().is_empty());
+
+        // The default indexers
+        assert_eq!(manager.reload(&conn).unwrap(), 4);
+
+        conn.execute("UPDATE indexers SET enabled = 0 WHERE name = 'YTS'", [])
+            .unwrap();
+        // Unknown types are skipped
+        conn.execute(
+            "INSERT INTO indexers (name, indexer_type, url) VALUES ('Old', 'public', 'https://example.com')",
+            [],
+        )
+        .unwrap();
+        assert_eq!(manager.reload(&conn).unwrap(), 3);
+
+        // Highest priority first
+        let names: Vec<String> = manager
+            .providers()
+            .iter()
+            .map(|p| p.name().to_string())
+            .collect();
+        assert_eq!(names, vec!["Rutracker", "EZTV", "1337x"]);
+    }
+
+    #[test]
+    fn test_from_indexer_categories() {
+        let indexer = Indexer {
+            id: 1,
+            name: "1337x mirror".to_string(),
+            indexer_type: "1337x".to_string(),
+            url: "https://1337x.example/".to_string(),
+            api_key: None,
+            enabled: true,
+            priority: 50,
+            categories: Some(r#"["tv"]"#.to_string()),
+            last_check: None,
+            last_error: None,
+            created_at: String::new(),
+        };
+
+        let provider = ConfiguredProvider::from_indexer(&indexer).unwrap();
+        assert!(provider.handles(Some(MediaSearchType::TvEpisode)));
+        assert!(!provider.handles(Some(MediaSearchType::Movie)));
+
+        let invalid = Indexer {
+            categories: Some("tv".to_string()),
+            ..indexer
+        };
+        assert!(ConfiguredProvider::from_indexer(&invalid).is_err());
+    }
 }
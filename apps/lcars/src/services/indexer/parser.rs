@@ -12,6 +12,7 @@ lazy_static! {
     static ref QUALITY_RE: Regex = Regex::new(r"(?i)(2160p|1080p|720p|480p)").unwrap();
     static ref SOURCE_RE: Regex = Regex::new(r"(?i)(BluRay|Blu-Ray|BDRip|BRRip|WEB-DL|WEBDL|WEBRip|WEB|HDTV|DVDRip|DVD|CAM|TS|TELESYNC|HDCAM|SCR|SCREENER)").unwrap();
     static ref SEASON_EP_RE: Regex = Regex::new(r"(?i)S(\d{1,2})E(\d{1,2})").unwrap();
+    static ref SEASON_RE: Regex = Regex::new(r"(?i)\b(?:S|Season[ ._-]?)(\d{1,2})\b").unwrap();
     static ref YEAR_RE: Regex = Regex::new(r"[.\s\(\[](\d{4})[.\s\)\]]").unwrap();
     static ref CODEC_RE: Regex = Regex::new(r"(?i)(x264|x265|HEVC|H\.?264|H\.?265|AVC|XviD|DivX)").unwrap();
     static ref AUDIO_RE: Regex = Regex::new(r"(?i)(AAC|AC3|DD5\.?1|DTS|DTS-HD|Atmos|TrueHD|FLAC|MP3|EAC3|E-AC-3)").unwrap();
@@ -184,6 +185,22 @@ impl AudioFormat {
     }
 }
 
+impl std::fmt::Display for AudioFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AudioFormat::Flac => write!(f, "FLAC"),
+            AudioFormat::Mp3 => write!(f, "MP3"),
+            AudioFormat::Aac => write!(f, "AAC"),
+            AudioFormat::Alac => write!(f, "ALAC"),
+            AudioFormat::Wav => write!(f, "WAV"),
+            AudioFormat::Ogg => write!(f, "OGG"),
+            AudioFormat::Opus => write!(f, "OPUS"),
+            AudioFormat::Ape => write!(f, "APE"),
+            AudioFormat::Unknown => write!(f, "unknown"),
+        }
+    }
+}
+
 /// Music source type.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
 pub enum MusicSource {
@@ -316,6 +333,9 @@ pub fn parse_release_name(name: &str) -> ParsedRelease {
     if let Some(caps) = SEASON_EP_RE.captures(name) {
         result.season = caps[1].parse().ok();
         result.episode = caps[2].parse().ok();
+    } else if let Some(caps) = SEASON_RE.captures(name) {
+        // Season pack
+        result.season = caps[1].parse().ok();
     }
 
     // Extract quality
@@ -480,6 +500,20 @@ mod tests {
         assert_eq!(parsed.audio, Some("AAC".to_string()));
     }
 
+    #[test]
+    fn test_parse_season_pack() {
+        let parsed = parse_release_name("Show.Name.S02.1080p.WEB-DL-GROUP");
+        assert_eq!(parsed.season, Some(2));
+        assert_eq!(parsed.episode, None);
+
+        let parsed = parse_release_name("Show Name Season 3 Complete 720p");
+        assert_eq!(parsed.season, Some(3));
+        assert_eq!(parsed.episode, None);
+
+        let parsed = parse_release_name("Movie.2024.1080p.BluRay.x264-GROUP");
+        assert_eq!(parsed.season, None);
+    }
+
     #[test]
     fn test_parse_4k_release() {
         let parsed = parse_release_name("Movie.2023.2160p.WEB-DL.x265.HEVC.DTS-GROUP");
@@ -0,0 +1,538 @@
+//! Quality profiles.
+//!
+//! A profile lists the qualities a media item may be grabbed in, best first,
+//! and a cutoff: once the file in the library is at or above the cutoff, no
+//! more upgrades are searched for. Preferred words rank releases of the same
+//! quality, rejected words or groups rule releases out, and size limits scale
+//! with the runtime.
+
+use rusqlite::{Connection, OptionalExtension};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::error::{AppError, Result};
+
+use super::parser::{parse_music_release, parse_release_name, AudioFormat, Quality, Source};
+use super::selection::contains_title;
+
+const BYTES_PER_MB: f64 = 1024.0 * 1024.0;
+
+/// Columns selected for [`QualityProfile::from_row`].
+const PROFILE_COLUMNS: &str = "id, name, media_type, allowed, cutoff, preferred_words, \
+     rejected_words, min_size_mb_per_minute, max_size_mb_per_minute, created_at, updated_at";
+
+/// Kind of media a profile applies to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ProfileMediaType {
+    /// Movies and TV shows
+    Video,
+    /// Artists and albums
+    Music,
+}
+
+impl ProfileMediaType {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "video" => Some(ProfileMediaType::Video),
+            "music" => Some(ProfileMediaType::Music),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for ProfileMediaType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ProfileMediaType::Video => write!(f, "video"),
+            ProfileMediaType::Music => write!(f, "music"),
+        }
+    }
+}
+
+/// One entry of a profile's allowed list.
+///
+/// Written as `"1080p BluRay"`, `"720p"` (any source) or `"FLAC"`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QualityItem {
+    /// A resolution, from one source or from any
+    Video {
+        quality: Quality,
+        source: Option<Source>,
+    },
+    /// An audio format
+    Audio(AudioFormat),
+}
+
+impl QualityItem {
+    /// Parse an item, returning `None` for unknown resolutions, sources or formats.
+    pub fn parse(s: &str) -> Option<Self> {
+        let parts: Vec<&str> = s.split_whitespace().collect();
+
+        if let [format] = parts.as_slice() {
+            let format = AudioFormat::parse(format);
+            if format != AudioFormat::Unknown {
+                return Some(QualityItem::Audio(format));
+            }
+        }
+
+        let (quality, source) = match parts.as_slice() {
+            [quality] => (*quality, None),
+            [quality, source] => (*quality, Some(*source)),
+            _ => return None,
+        };
+
+        let quality = match Quality::parse(quality) {
+            Quality::Unknown if !quality.eq_ignore_ascii_case("unknown") => return None,
+            quality => quality,
+        };
+        let source = match source.map(|raw| (raw, Source::parse(raw))) {
+            Some((raw, Source::Unknown)) if !raw.eq_ignore_ascii_case("unknown") => return None,
+            source => source.map(|(_, source)| source),
+        };
+
+        Some(QualityItem::Video { quality, source })
+    }
+
+    /// Quality of a release, from its title.
+    pub fn of_release(title: &str, media_type: ProfileMediaType) -> Option<Self> {
+        match media_type {
+            ProfileMediaType::Video => {
+                let parsed = parse_release_name(title);
+                Some(QualityItem::Video {
+                    quality: parsed.quality,
+                    source: Some(parsed.source),
+                })
+            }
+            ProfileMediaType::Music => parse_music_release(title)
+                .audio_format
+                .map(QualityItem::Audio),
+        }
+    }
+
+    /// The kind of media this item describes.
+    pub fn media_type(&self) -> ProfileMediaType {
+        match self {
+            QualityItem::Video { .. } => ProfileMediaType::Video,
+            QualityItem::Audio(_) => ProfileMediaType::Music,
+        }
+    }
+
+    /// Whether a release or file quality falls under this item.
+    fn matches(&self, other: &QualityItem) -> bool {
+        match (self, other) {
+            (
+                QualityItem::Video { quality, source },
+                QualityItem::Video {
+                    quality: other_quality,
+                    source: other_source,
+                },
+            ) => quality == other_quality && (source.is_none() || source == other_source),
+            (QualityItem::Audio(format), QualityItem::Audio(other)) => format == other,
+            _ => false,
+        }
+    }
+}
+
+impl std::fmt::Display for QualityItem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            QualityItem::Video {
+                quality,
+                source: Some(source),
+            } => write!(f, "{} {}", quality, source),
+            QualityItem::Video {
+                quality,
+                source: None,
+            } => write!(f, "{}", quality),
+            QualityItem::Audio(format) => write!(f, "{}", format),
+        }
+    }
+}
+
+impl Serialize for QualityItem {
+    fn serialize<S: Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for QualityItem {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        QualityItem::parse(&s)
+            .ok_or_else(|| serde::de::Error::custom(format!("unknown quality '{}'", s)))
+    }
+}
+
+/// A named set of quality rules that movies, shows and artists can use.
+#[derive(Debug, Clone, Serialize)]
+pub struct QualityProfile {
+    pub id: i64,
+    pub name: String,
+    pub media_type: ProfileMediaType,
+    /// Allowed qualities, best first
+    pub allowed: Vec<QualityItem>,
+    /// Quality at which upgrades stop
+    pub cutoff: QualityItem,
+    /// Words or groups that rank a release above others of the same quality
+    pub preferred_words: Vec<String>,
+    /// Words or groups that rule a release out
+    pub rejected_words: Vec<String>,
+    pub min_size_mb_per_minute: Option<f64>,
+    pub max_size_mb_per_minute: Option<f64>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl QualityProfile {
+    /// Position of a quality in the allowed list (0 is best), or `None` if
+    /// it is not allowed.
+    pub fn rank(&self, item: &QualityItem) -> Option<usize> {
+        self.allowed
+            .iter()
+            .position(|allowed| allowed.matches(item))
+    }
+
+    /// Position of a release's quality in the allowed list.
+    pub fn release_rank(&self, title: &str) -> Option<usize> {
+        QualityItem::of_release(title, self.media_type).and_then(|item| self.rank(&item))
+    }
+
+    /// Whether a file of this quality needs no upgrade.
+    pub fn meets_cutoff(&self, item: &QualityItem) -> bool {
+        match (self.rank(item), self.rank(&self.cutoff)) {
+            (Some(rank), Some(cutoff)) => rank <= cutoff,
+            (Some(_), None) => true,
+            (None, _) => false,
+        }
+    }
+
+    /// Whether a release contains a rejected word or group.
+    pub fn is_rejected(&self, title: &str) -> bool {
+        self.rejected_words
+            .iter()
+            .any(|word| contains_title(title, word))
+    }
+
+    /// Number of preferred words or groups a release contains.
+    pub fn preferred_count(&self, title: &str) -> usize {
+        self.preferred_words
+            .iter()
+            .filter(|word| contains_title(title, word))
+            .count()
+    }
+
+    /// Size limits in bytes for a runtime, where the profile sets them.
+    pub fn size_range(&self, runtime_minutes: u32) -> (Option<u64>, Option<u64>) {
+        let bytes =
+            |mb_per_minute: f64| (mb_per_minute * runtime_minutes as f64 * BYTES_PER_MB) as u64;
+        (
+            self.min_size_mb_per_minute.map(bytes),
+            self.max_size_mb_per_minute.map(bytes),
+        )
+    }
+
+    /// Check the profile is consistent.
+    pub fn validate(&self) -> Result<()> {
+        if self.name.trim().is_empty() || self.name.len() > 100 {
+            return Err(AppError::BadRequest(
+                "Name must be between 1 and 100 characters".to_string(),
+            ));
+        }
+
+        if self.allowed.is_empty() {
+            return Err(AppError::BadRequest(
+                "At least one quality must be allowed".to_string(),
+            ));
+        }
+        for (i, item) in self.allowed.iter().enumerate() {
+            if item.media_type() != self.media_type {
+                return Err(AppError::BadRequest(format!(
+                    "Quality '{}' does not apply to {} profiles",
+                    item, self.media_type
+                )));
+            }
+            if self.allowed[..i].contains(item) {
+                return Err(AppError::BadRequest(format!(
+                    "Quality '{}' is listed twice",
+                    item
+                )));
+            }
+        }
+
+        if !self.allowed.contains(&self.cutoff) {
+            return Err(AppError::BadRequest(format!(
+                "Cutoff '{}' must be one of the allowed qualities",
+                self.cutoff
+            )));
+        }
+
+        if self
+            .preferred_words
+            .iter()
+            .chain(&self.rejected_words)
+            .any(|word| word.trim().is_empty())
+        {
+            return Err(AppError::BadRequest(
+                "Preferred and rejected words must not be empty".to_string(),
+            ));
+        }
+
+        let sizes = [self.min_size_mb_per_minute, self.max_size_mb_per_minute];
+        if sizes
+            .iter()
+            .flatten()
+            .any(|size| !size.is_finite() || *size < 0.0)
+        {
+            return Err(AppError::BadRequest(
+                "Sizes per minute must be positive numbers".to_string(),
+            ));
+        }
+        if let [Some(min), Some(max)] = sizes {
+            if min > max {
+                return Err(AppError::BadRequest(
+                    "Minimum size must not exceed the maximum size".to_string(),
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Load a profile by id.
+    pub fn load(conn: &Connection, id: i64) -> Result<Option<Self>> {
+        Ok(conn
+            .query_row(
+                &format!(
+                    "SELECT {} FROM quality_profiles WHERE id = ?1",
+                    PROFILE_COLUMNS
+                ),
+                [id],
+                Self::from_row,
+            )
+            .optional()?)
+    }
+
+    /// Load every profile, ordered by name.
+    pub fn load_all(conn: &Connection) -> Result<Vec<Self>> {
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM quality_profiles ORDER BY name",
+            PROFILE_COLUMNS
+        ))?;
+        let profiles = stmt
+            .query_map([], Self::from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(profiles)
+    }
+
+    /// Map a row selected with the profile columns.
+    fn from_row(row: &rusqlite::Row) -> rusqlite::Result<Self> {
+        fn json<T: serde::de::DeserializeOwned>(
+            row: &rusqlite::Row,
+            idx: usize,
+        ) -> rusqlite::Result<T> {
+            let raw: String = row.get(idx)?;
+            serde_json::from_str(&raw).map_err(|e| {
+                rusqlite::Error::FromSqlConversionFailure(
+                    idx,
+                    rusqlite::types::Type::Text,
+                    Box::new(e),
+                )
+            })
+        }
+
+        let media_type: String = row.get(2)?;
+        let media_type = ProfileMediaType::parse(&media_type).ok_or_else(|| {
+            rusqlite::Error::FromSqlConversionFailure(
+                2,
+                rusqlite::types::Type::Text,
+                format!("unknown media type '{}'", media_type).into(),
+            )
+        })?;
+        let cutoff: String = row.get(4)?;
+        let cutoff = QualityItem::parse(&cutoff).ok_or_else(|| {
+            rusqlite::Error::FromSqlConversionFailure(
+                4,
+                rusqlite::types::Type::Text,
+                format!("unknown quality '{}'", cutoff).into(),
+            )
+        })?;
+
+        Ok(Self {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            media_type,
+            allowed: json(row, 3)?,
+            cutoff,
+            preferred_words: json(row, 5)?,
+            rejected_words: json(row, 6)?,
+            min_size_mb_per_minute: row.get(7)?,
+            max_size_mb_per_minute: row.get(8)?,
+            created_at: row.get(9)?,
+            updated_at: row.get(10)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(s: &str) -> QualityItem {
+        QualityItem::parse(s).unwrap()
+    }
+
+    fn profile(allowed: &[&str], cutoff: &str) -> QualityProfile {
+        let allowed: Vec<QualityItem> = allowed.iter().map(|s| item(s)).collect();
+        QualityProfile {
+            id: 1,
+            name: "Test".to_string(),
+            media_type: allowed[0].media_type(),
+            allowed,
+            cutoff: item(cutoff),
+            preferred_words: Vec::new(),
+            rejected_words: Vec::new(),
+            min_size_mb_per_minute: None,
+            max_size_mb_per_minute: None,
+            created_at: String::new(),
+            updated_at: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_parse_items() {
+        assert_eq!(
+            item("1080p BluRay"),
+            QualityItem::Video {
+                quality: Quality::P1080,
+                source: Some(Source::BluRay)
+            }
+        );
+        assert_eq!(
+            item("720p"),
+            QualityItem::Video {
+                quality: Quality::P720,
+                source: None
+            }
+        );
+        assert_eq!(item("flac"), QualityItem::Audio(AudioFormat::Flac));
+        assert_eq!(item("1080p WEB-DL").to_string(), "1080p WEB-DL");
+        assert_eq!(item("FLAC").to_string(), "FLAC");
+
+        assert!(QualityItem::parse("1440p").is_none());
+        assert!(QualityItem::parse("1080p Laserdisc").is_none());
+        assert!(QualityItem::parse("1080p WEB DL").is_none());
+        assert!(QualityItem::parse("").is_none());
+    }
+
+    #[test]
+    fn test_rank_and_cutoff() {
+        let profile = profile(&["1080p BluRay", "1080p WEB-DL", "720p"], "1080p WEB-DL");
+
+        assert_eq!(
+            profile.release_rank("Movie.2024.1080p.BluRay.x264-GRP"),
+            Some(0)
+        );
+        assert_eq!(
+            profile.release_rank("Movie.2024.1080p.WEB-DL.x264-GRP"),
+            Some(1)
+        );
+        assert_eq!(
+            profile.release_rank("Movie.2024.720p.HDTV.x264-GRP"),
+            Some(2)
+        );
+        assert_eq!(
+            profile.release_rank("Movie.2024.2160p.WEB-DL.x265-GRP"),
+            None
+        );
+
+        assert!(profile.meets_cutoff(&item("1080p BluRay")));
+        assert!(profile.meets_cutoff(&item("1080p WEB-DL")));
+        assert!(!profile.meets_cutoff(&item("720p HDTV")));
+        assert!(!profile.meets_cutoff(&item("480p DVDRip")));
+    }
+
+    #[test]
+    fn test_music_rank() {
+        let profile = profile(&["FLAC", "MP3"], "FLAC");
+
+        assert_eq!(
+            profile.release_rank("Artist - Album (2024) [FLAC]"),
+            Some(0)
+        );
+        assert_eq!(
+            profile.release_rank("Artist - Album (2024) [MP3 320]"),
+            Some(1)
+        );
+        assert_eq!(profile.release_rank("Artist - Album (2024) [OGG]"), None);
+        assert_eq!(profile.release_rank("Artist - Album (2024)"), None);
+    }
+
+    #[test]
+    fn test_words() {
+        let mut profile = profile(&["1080p"], "1080p");
+        profile.preferred_words = vec!["REMUX".to_string(), "Atmos".to_string()];
+        profile.rejected_words = vec!["HC".to_string(), "BADGRP".to_string()];
+
+        assert!(profile.is_rejected("Movie.2024.1080p.HC.WEB-DL"));
+        assert!(profile.is_rejected("Movie.2024.1080p.WEB-DL-BADGRP"));
+        assert!(!profile.is_rejected("Movie.2024.1080p.WEB-DL-GOODGRP"));
+        assert_eq!(
+            profile.preferred_count("Movie.2024.1080p.BluRay.REMUX.Atmos"),
+            2
+        );
+        assert_eq!(profile.preferred_count("Movie.2024.1080p.BluRay"), 0);
+    }
+
+    #[test]
+    fn test_size_range() {
+        let mut profile = profile(&["1080p"], "1080p");
+        assert_eq!(profile.size_range(100), (None, None));
+
+        profile.min_size_mb_per_minute = Some(5.0);
+        profile.max_size_mb_per_minute = Some(50.0);
+        assert_eq!(
+            profile.size_range(100),
+            (Some(500 * 1024 * 1024), Some(5_000 * 1024 * 1024))
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        assert!(profile(&["1080p BluRay", "720p"], "720p")
+            .validate()
+            .is_ok());
+
+        // Cutoff must be allowed
+        assert!(profile(&["1080p BluRay"], "720p").validate().is_err());
+
+        // No mixing video and music
+        assert!(profile(&["1080p", "FLAC"], "1080p").validate().is_err());
+
+        // No duplicates
+        assert!(profile(&["1080p", "1080p"], "1080p").validate().is_err());
+
+        let mut bad_sizes = profile(&["1080p"], "1080p");
+        bad_sizes.min_size_mb_per_minute = Some(10.0);
+        bad_sizes.max_size_mb_per_minute = Some(5.0);
+        assert!(bad_sizes.validate().is_err());
+    }
+
+    #[test]
+    fn test_load_default_profiles() {
+        let conn = crate::db::init_db_memory().unwrap();
+        let profiles = QualityProfile::load_all(&conn).unwrap();
+
+        let names: Vec<&str> = profiles.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["HD", "Lossless", "Ultra HD"]);
+        for profile in &profiles {
+            profile.validate().unwrap();
+        }
+
+        let hd = QualityProfile::load(&conn, profiles[0].id)
+            .unwrap()
+            .unwrap();
+        assert_eq!(hd.media_type, ProfileMediaType::Video);
+        assert_eq!(hd.cutoff, item("1080p WEB-DL"));
+        assert!(QualityProfile::load(&conn, 999).unwrap().is_none());
+    }
+}
@@ -11,6 +11,7 @@ use crate::error::{AppError, Result};
 use crate::services::indexer::{
     parse_release_name, IndexerProvider, IndexerTestResult, Release, SearchQuery,
 };
+use crate::services::torrent_source::magnet_info_hash;
 
 const EZTV_BASE_URL: &str = "https://eztv.re";
 const EZTV_API_URL: &str = "https://eztv.re/api/get-torrents";
@@ -47,6 +48,53 @@ impl EztvProvider {
             api_url,
         }
     }
+
+    /// Fetch torrents from the API.
+    async fn fetch(&self, url: &str) -> Result<Vec<EztvTorrent>> {
+        let response = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .map_err(|e| AppError::Internal(format!("EZTV search request failed: {}", e)))?;
+
+        if !response.status().is_success() {
+            return Err(AppError::Internal(format!(
+                "EZTV returned status: {}",
+                response.status()
+            )));
+        }
+
+        let api_response: EztvApiResponse = response
+            .json()
+            .await
+            .map_err(|e| AppError::Internal(format!("Failed to parse EZTV response: {}", e)))?;
+
+        Ok(api_response.torrents.unwrap_or_default())
+    }
+
+    fn to_release(&self, torrent: EztvTorrent) -> Release {
+        let parsed = parse_release_name(&torrent.title);
+
+        Release {
+            id: Release::generate_id(self.name(), &torrent.title, &torrent.magnet_url),
+            title: torrent.title,
+            indexer: self.name().to_string(),
+            info_hash: magnet_info_hash(&torrent.magnet_url),
+            magnet: torrent.magnet_url,
+            size_bytes: torrent.size_bytes.parse().unwrap_or(0),
+            seeders: torrent.seeds,
+            leechers: torrent.peers.saturating_sub(torrent.seeds),
+            quality: parsed.quality,
+            source: parsed.source,
+            codec: parsed.codec,
+            audio: parsed.audio,
+            group: parsed.group,
+            proper: parsed.proper,
+            repack: parsed.repack,
+            uploaded_at: Some(torrent.date_released_unix.to_string()),
+        }
+    }
 }
 
 impl Default for EztvProvider {
@@ -90,28 +138,9 @@ impl IndexerProvider for EztvProvider {
 
         tracing::debug!(url = %url, "Searching EZTV");
 
-        let response = self
-            .client
-            .get(&url)
-            .send()
-            .await
-            .map_err(|e| AppError::Internal(format!("EZTV search request failed: {}", e)))?;
-
-        if !response.status().is_success() {
-            return Err(AppError::Internal(format!(
-                "EZTV returned status: {}",
-                response.status()
-            )));
-        }
-
-        let api_response: EztvApiResponse = response
-            .json()
-            .await
-            .map_err(|e| AppError::Internal(format!("Failed to parse EZTV response: {}", e)))?;
-
-        let releases = api_response
-            .torrents
-            .unwrap_or_default()
+        let releases = self
+            .fetch(&url)
+            .await?
             .into_iter()
             .filter(|t| {
                 // Filter by season/episode if specified
@@ -122,32 +151,19 @@ impl IndexerProvider for EztvProvider {
                     true
                 }
             })
-            .map(|torrent| {
-                let parsed = parse_release_name(&torrent.title);
-
-                Release {
-                    id: Release::generate_id(self.name(), &torrent.title, &torrent.magnet_url),
-                    title: torrent.title,
-                    indexer: self.name().to_string(),
-                    magnet: torrent.magnet_url,
-                    size_bytes: torrent.size_bytes.parse().unwrap_or(0),
-                    seeders: torrent.seeds,
-                    leechers: torrent.peers.saturating_sub(torrent.seeds),
-                    quality: parsed.quality,
-                    source: parsed.source,
-                    codec: parsed.codec,
-                    audio: parsed.audio,
-                    group: parsed.group,
-                    proper: parsed.proper,
-                    repack: parsed.repack,
-                    uploaded_at: Some(torrent.date_released_unix.to_string()),
-                }
-            })
+            .map(|torrent| self.to_release(torrent))
             .collect();
 
         Ok(releases)
     }
 
+    /// Without a query the API lists the newest torrents first.
+    async fn latest(&self) -> Result<Vec<Release>> {
+        let url = format!("{}?limit=100&page=1", self.api_url);
+        let torrents = self.fetch(&url).await?;
+        Ok(torrents.into_iter().map(|t| self.to_release(t)).collect())
+    }
+
     async fn test(&self) -> Result<IndexerTestResult> {
         let start = Instant::now();
 
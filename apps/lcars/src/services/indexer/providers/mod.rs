@@ -3,9 +3,55 @@
 mod eztv;
 mod leetx;
 mod rutracker;
+mod torznab;
 mod yts;
 
 pub use eztv::EztvProvider;
 pub use leetx::LeetxProvider;
 pub use rutracker::RutrackerProvider;
+pub use torznab::TorznabProvider;
 pub use yts::YtsProvider;
+
+use std::sync::Arc;
+
+use crate::db::models::Indexer;
+use crate::error::{AppError, Result};
+use crate::services::indexer::IndexerProvider;
+
+/// Indexer types with a provider, as stored in `indexers.indexer_type`.
+pub const INDEXER_TYPES: &[&str] = &["1337x", "eztv", "yts", "rutracker", "torznab"];
+
+/// Build the provider for an `indexers` row.
+///
+/// The row's URL replaces the site's default, so mirrors can be used. For
+/// `torznab` rows it is the Torznab endpoint and the API key is sent with
+/// every request.
+pub fn from_indexer(indexer: &Indexer) -> Result<Arc<dyn IndexerProvider>> {
+    let base_url = indexer.url.trim_end_matches('/').to_string();
+
+    let provider: Arc<dyn IndexerProvider> = match indexer.indexer_type.as_str() {
+        "1337x" => Arc::new(LeetxProvider::with_base_url(base_url)),
+        "eztv" => {
+            let api_url = format!("{}/api/get-torrents", base_url);
+            Arc::new(EztvProvider::with_urls(base_url, api_url))
+        }
+        "yts" => {
+            let api_url = format!("{}/api/v2/list_movies.json", base_url);
+            Arc::new(YtsProvider::with_urls(base_url, api_url))
+        }
+        "rutracker" => Arc::new(RutrackerProvider::with_base_url(base_url)),
+        "torznab" => Arc::new(TorznabProvider::new(
+            indexer.name.clone(),
+            &base_url,
+            indexer.api_key.clone(),
+        )),
+        other => {
+            return Err(AppError::BadRequest(format!(
+                "Unsupported indexer type '{}'",
+                other
+            )))
+        }
+    };
+
+    Ok(provider)
+}
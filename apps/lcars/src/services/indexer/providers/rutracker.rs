@@ -11,6 +11,7 @@ use crate::error::{AppError, Result};
 use crate::services::indexer::{
     parse_music_release, IndexerProvider, IndexerTestResult, Quality, Release, SearchQuery, Source,
 };
+use crate::services::torrent_source::magnet_info_hash;
 
 const RUTRACKER_BASE_URL: &str = "https://rutracker.org";
 const REQUEST_TIMEOUT_SECS: u64 = 30;
@@ -206,6 +207,7 @@ impl IndexerProvider for RutrackerProvider {
                     id: Release::generate_id(self.name(), &partial.title, &magnet),
                     title: partial.title,
                     indexer: self.name().to_string(),
+                    info_hash: magnet_info_hash(&magnet),
                     magnet,
                     size_bytes: partial.size_bytes,
                     seeders: partial.seeders,
@@ -0,0 +1,854 @@
+//! Torznab indexer provider.
+//!
+//! Speaks the Torznab API (the torrent flavour of Newznab) served by Jackett,
+//! Prowlarr and many private trackers. The indexer's capabilities are read
+//! from `t=caps` once and decide which search modes and ID parameters are used.
+
+use async_trait::async_trait;
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use reqwest::Client;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use crate::error::{AppError, Result};
+use crate::services::indexer::{
+    parse_release_name, IndexerProvider, IndexerTestResult, MediaSearchType, Release, SearchQuery,
+};
+use crate::services::torrent_source::magnet_info_hash;
+
+const REQUEST_TIMEOUT_SECS: u64 = 30;
+const USER_AGENT: &str = concat!("LCARS/", env!("CARGO_PKG_VERSION"));
+
+/// Standard Newznab top-level categories.
+const MOVIE_CATEGORY: u32 = 2000;
+const AUDIO_CATEGORY: u32 = 3000;
+const TV_CATEGORY: u32 = 5000;
+
+/// A search mode advertised in the capabilities.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchMode {
+    /// Supported parameters, e.g. `q`, `imdbid`, `season`
+    pub params: Vec<String>,
+}
+
+impl SearchMode {
+    pub fn supports(&self, param: &str) -> bool {
+        self.params.iter().any(|p| p == param)
+    }
+}
+
+/// Capabilities of a Torznab indexer (`t=caps`).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct TorznabCaps {
+    pub search: Option<SearchMode>,
+    pub tv_search: Option<SearchMode>,
+    pub movie_search: Option<SearchMode>,
+    pub music_search: Option<SearchMode>,
+    /// Category and subcategory ids
+    pub categories: Vec<u32>,
+}
+
+impl TorznabCaps {
+    /// Whether any category falls under the given top-level category.
+    fn has_category(&self, top_level: u32) -> bool {
+        self.categories.is_empty()
+            || self
+                .categories
+                .iter()
+                .any(|id| (top_level..top_level + 1000).contains(id))
+    }
+}
+
+/// Torznab indexer provider.
+///
+/// Configured by an `indexers` row of type `torznab`, whose URL points at the
+/// Torznab endpoint (with or without the trailing `/api`).
+pub struct TorznabProvider {
+    client: Client,
+    name: String,
+    api_url: String,
+    api_key: Option<String>,
+    caps: OnceLock<TorznabCaps>,
+}
+
+impl TorznabProvider {
+    /// Create a provider for a Torznab endpoint.
+    pub fn new(name: String, url: &str, api_key: Option<String>) -> Self {
+        let client = Client::builder()
+            .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS))
+            .user_agent(USER_AGENT)
+            .build()
+            .unwrap_or_else(|_| Client::new());
+
+        let url = url.trim_end_matches('/');
+        let api_url = if url.ends_with("/api") {
+            url.to_string()
+        } else {
+            format!("{}/api", url)
+        };
+
+        Self {
+            client,
+            name,
+            api_url,
+            api_key,
+            caps: OnceLock::new(),
+        }
+    }
+
+    /// Send an API request and return the response body.
+    async fn request(&self, params: &[(&str, String)]) -> Result<String> {
+        tracing::debug!(indexer = %self.name, params = ?params, "Torznab request");
+
+        let mut request = self.client.get(&self.api_url).query(params);
+        if let Some(ref key) = self.api_key {
+            request = request.query(&[("apikey", key)]);
+        }
+
+        let response = request.send().await.map_err(|e| {
+            AppError::Internal(format!("{} request failed: {}", self.name, e.without_url()))
+        })?;
+
+        if !response.status().is_success() {
+            return Err(AppError::Internal(format!(
+                "{} returned status: {}",
+                self.name,
+                response.status()
+            )));
+        }
+
+        response.text().await.map_err(|e| {
+            AppError::Internal(format!("Failed to read {} response: {}", self.name, e))
+        })
+    }
+
+    /// Fetch the capabilities, caching them after the first success.
+    pub async fn caps(&self) -> Result<&TorznabCaps> {
+        if let Some(caps) = self.caps.get() {
+            return Ok(caps);
+        }
+
+        let xml = self.request(&[("t", "caps".to_string())]).await?;
+        let caps = parse_caps(&xml)?;
+        Ok(self.caps.get_or_init(|| caps))
+    }
+
+    /// Whether the indexer can be searched for a top-level category. Assumed
+    /// until the capabilities have been fetched.
+    fn supports(&self, mode: fn(&TorznabCaps) -> &Option<SearchMode>, category: u32) -> bool {
+        self.caps.get().is_none_or(|caps| {
+            (mode(caps).is_some() || caps.search.is_some()) && caps.has_category(category)
+        })
+    }
+}
+
+/// Build the request parameters for a query.
+///
+/// Uses the dedicated search mode for the media type when the indexer has it,
+/// passing IDs where supported instead of the title, and falls back to a plain
+/// text search otherwise.
+pub fn search_params(caps: &TorznabCaps, query: &SearchQuery) -> Vec<(&'static str, String)> {
+    let mut params = Vec::new();
+
+    let (mode, function, category) = match query.media_type {
+        Some(MediaSearchType::Movie) => (&caps.movie_search, "movie", Some(MOVIE_CATEGORY)),
+        Some(MediaSearchType::TvEpisode) => (&caps.tv_search, "tvsearch", Some(TV_CATEGORY)),
+        Some(MediaSearchType::MusicAlbum) => (&caps.music_search, "music", Some(AUDIO_CATEGORY)),
+        None => (&None, "search", None),
+    };
+
+    match mode {
+        Some(mode) => {
+            params.push(("t", function.to_string()));
+            let mut has_id = false;
+            let mut add = |name: &'static str, value: Option<String>| match value {
+                Some(value) if mode.supports(name) => {
+                    params.push((name, value));
+                    true
+                }
+                _ => false,
+            };
+
+            // Torznab IMDB ids are the number without the `tt` prefix
+            let imdb_id = query
+                .imdb_id
+                .as_deref()
+                .map(|id| id.trim_start_matches("tt").to_string());
+            has_id |= add("imdbid", imdb_id);
+            has_id |= add("tmdbid", query.tmdb_id.map(|id| id.to_string()));
+            if function == "tvsearch" {
+                has_id |= add("tvdbid", query.tvdb_id.map(|id| id.to_string()));
+            }
+
+            let mut text = query.query.clone();
+            match function {
+                "movie" => {
+                    let year = add("year", query.year.map(|y| y.to_string()));
+                    if let (false, Some(y)) = (year, query.year) {
+                        text = format!("{} {}", text, y);
+                    }
+                }
+                "tvsearch" => {
+                    let season = add("season", query.season.map(|s| s.to_string()));
+                    let episode = add("ep", query.episode.map(|e| e.to_string()));
+                    if let (false, false, Some(s), Some(e)) =
+                        (season, episode, query.season, query.episode)
+                    {
+                        text = format!("{} S{:02}E{:02}", text, s, e);
+                    }
+                }
+                "music" => {
+                    has_id |= add("artist", query.artist.clone());
+                    has_id |= add("album", query.album.clone());
+                }
+                _ => {}
+            }
+
+            if !has_id && !text.trim().is_empty() {
+                params.push(("q", text.trim().to_string()));
+            }
+        }
+        None => {
+            params.push(("t", "search".to_string()));
+            let text = query.build_query_string();
+            if !text.trim().is_empty() {
+                params.push(("q", text.trim().to_string()));
+            }
+        }
+    }
+
+    if let Some(category) = category.filter(|c| caps.has_category(*c)) {
+        params.push(("cat", category.to_string()));
+    }
+    params.push(("extended", "1".to_string()));
+
+    params
+}
+
+#[async_trait]
+impl IndexerProvider for TorznabProvider {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn supports_movies(&self) -> bool {
+        self.supports(|caps| &caps.movie_search, MOVIE_CATEGORY)
+    }
+
+    fn supports_tv(&self) -> bool {
+        self.supports(|caps| &caps.tv_search, TV_CATEGORY)
+    }
+
+    fn supports_music(&self) -> bool {
+        self.supports(|caps| &caps.music_search, AUDIO_CATEGORY)
+    }
+
+    async fn search(&self, query: &SearchQuery) -> Result<Vec<Release>> {
+        let caps = match self.caps().await {
+            Ok(caps) => caps.clone(),
+            Err(e) => {
+                // Plain text search works on every indexer
+                tracing::debug!(indexer = %self.name, error = %e, "Capabilities unavailable");
+                TorznabCaps::default()
+            }
+        };
+
+        let xml = self.request(&search_params(&caps, query)).await?;
+        parse_results(&xml, &self.name)
+    }
+
+    /// `t=search` without a query lists the newest releases.
+    async fn latest(&self) -> Result<Vec<Release>> {
+        let xml = self
+            .request(&[("t", "search".to_string()), ("extended", "1".to_string())])
+            .await?;
+        parse_results(&xml, &self.name)
+    }
+
+    async fn test(&self) -> Result<IndexerTestResult> {
+        let start = Instant::now();
+        let result = self.request(&[("t", "caps".to_string())]).await;
+        let elapsed = start.elapsed().as_millis() as u64;
+
+        let error = match result.and_then(|xml| parse_caps(&xml)) {
+            Ok(caps) => {
+                let _ = self.caps.set(caps);
+                None
+            }
+            Err(e) => Some(e.to_string()),
+        };
+
+        Ok(IndexerTestResult {
+            name: self.name.clone(),
+            success: error.is_none(),
+            response_time_ms: elapsed,
+            error,
+        })
+    }
+}
+
+fn xml_error(e: impl std::fmt::Display) -> AppError {
+    AppError::Internal(format!("Invalid Torznab response: {}", e))
+}
+
+/// Attribute value of an element, unescaped.
+fn attribute(element: &BytesStart, name: &str) -> Option<String> {
+    element
+        .attributes()
+        .flatten()
+        .find(|a| a.key.local_name().as_ref() == name.as_bytes())
+        .and_then(|a| a.unescape_value().ok())
+        .map(|v| v.into_owned())
+}
+
+/// Turn an `<error code=".." description=".."/>` response into an error.
+fn check_error(element: &BytesStart) -> Result<()> {
+    if element.local_name().as_ref() == b"error" {
+        let code = attribute(element, "code").unwrap_or_default();
+        let description = attribute(element, "description").unwrap_or_default();
+        return Err(AppError::Internal(format!(
+            "Torznab error {}: {}",
+            code, description
+        )));
+    }
+    Ok(())
+}
+
+/// Parse a `t=caps` response.
+pub fn parse_caps(xml: &str) -> Result<TorznabCaps> {
+    let mut reader = Reader::from_str(xml);
+    let mut caps = TorznabCaps::default();
+    let mut seen_caps = false;
+
+    loop {
+        match reader.read_event().map_err(xml_error)? {
+            Event::Start(e) | Event::Empty(e) => {
+                check_error(&e)?;
+                match e.local_name().as_ref() {
+                    b"caps" => seen_caps = true,
+                    name @ (b"search" | b"tv-search" | b"movie-search" | b"music-search"
+                    | b"audio-search") => {
+                        let available = attribute(&e, "available").as_deref() == Some("yes");
+                        let mode = available.then(|| SearchMode {
+                            params: attribute(&e, "supportedParams")
+                                .unwrap_or_else(|| "q".to_string())
+                                .split(',')
+                                .map(|p| p.trim().to_string())
+                                .filter(|p| !p.is_empty())
+                                .collect(),
+                        });
+                        match name {
+                            b"search" => caps.search = mode,
+                            b"tv-search" => caps.tv_search = mode,
+                            b"movie-search" => caps.movie_search = mode,
+                            // Older indexers call music search `audio-search`
+                            _ => {
+                                if caps.music_search.is_none() {
+                                    caps.music_search = mode;
+                                }
+                            }
+                        }
+                    }
+                    b"category" | b"subcat" => {
+                        if let Some(id) = attribute(&e, "id").and_then(|id| id.parse().ok()) {
+                            caps.categories.push(id);
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    if !seen_caps {
+        return Err(xml_error("missing <caps> element"));
+    }
+    Ok(caps)
+}
+
+/// Fields of an `<item>` while it is being read.
+#[derive(Default)]
+struct Item {
+    title: String,
+    link: Option<String>,
+    enclosure: Option<String>,
+    size: Option<u64>,
+    pub_date: Option<String>,
+    seeders: Option<u32>,
+    peers: Option<u32>,
+    leechers: Option<u32>,
+    infohash: Option<String>,
+    magnet: Option<String>,
+}
+
+impl Item {
+    /// Record a `torznab:attr` / `newznab:attr` element.
+    fn attr(&mut self, element: &BytesStart) {
+        let (Some(name), Some(value)) = (attribute(element, "name"), attribute(element, "value"))
+        else {
+            return;
+        };
+        match name.as_str() {
+            "seeders" => self.seeders = value.parse().ok(),
+            "peers" => self.peers = value.parse().ok(),
+            "leechers" => self.leechers = value.parse().ok(),
+            "size" => self.size = value.parse().ok().or(self.size),
+            "infohash" => self.infohash = Some(value),
+            "magneturl" => self.magnet = Some(value),
+            _ => {}
+        }
+    }
+
+    fn into_release(self, indexer: &str) -> Option<Release> {
+        let title = self.title.trim().to_string();
+        if title.is_empty() {
+            return None;
+        }
+
+        // The info hash attribute is kept for deduplication and the blocklist
+        let info_hash = self
+            .infohash
+            .as_deref()
+            .and_then(|hash| magnet_info_hash(&format!("magnet:?xt=urn:btih:{}", hash.trim())));
+
+        // Prefer the indexer's magnet, then the .torrent download (which keeps
+        // the trackers), and only build a trackerless magnet from the info
+        // hash as a last resort
+        let magnet = self.magnet.or(self.enclosure).or(self.link).or_else(|| {
+            info_hash.as_ref().map(|hash| {
+                format!(
+                    "magnet:?xt=urn:btih:{}&dn={}",
+                    hash,
+                    urlencoding::encode(&title)
+                )
+            })
+        })?;
+        let info_hash = info_hash.or_else(|| magnet_info_hash(&magnet));
+
+        let seeders = self.seeders.unwrap_or(0);
+        let leechers = self
+            .leechers
+            .or(self.peers.map(|peers| peers.saturating_sub(seeders)))
+            .unwrap_or(0);
+        let uploaded_at = self.pub_date.map(|date| {
+            chrono::DateTime::parse_from_rfc2822(&date)
+                .map(|d| d.to_rfc3339())
+                .unwrap_or(date)
+        });
+        let parsed = parse_release_name(&title);
+
+        Some(Release {
+            id: Release::generate_id(indexer, &title, &magnet),
+            title,
+            indexer: indexer.to_string(),
+            magnet,
+            info_hash,
+            size_bytes: self.size.unwrap_or(0),
+            seeders,
+            leechers,
+            quality: parsed.quality,
+            source: parsed.source,
+            codec: parsed.codec,
+            audio: parsed.audio,
+            group: parsed.group,
+            proper: parsed.proper,
+            repack: parsed.repack,
+            uploaded_at,
+        })
+    }
+}
+
+/// Parse a search response (an RSS feed with Torznab attributes).
+pub fn parse_results(xml: &str, indexer: &str) -> Result<Vec<Release>> {
+    let mut reader = Reader::from_str(xml);
+    let mut releases = Vec::new();
+    let mut item: Option<Item> = None;
+    // Element whose text is being read inside an item
+    let mut field: Option<Vec<u8>> = None;
+
+    loop {
+        match reader.read_event().map_err(xml_error)? {
+            Event::Start(e) => {
+                check_error(&e)?;
+                match (e.local_name().as_ref(), item.as_mut()) {
+                    (b"item", _) => item = Some(Item::default()),
+                    (b"attr", Some(current)) => current.attr(&e),
+                    (b"enclosure", Some(current)) => {
+                        current.enclosure = attribute(&e, "url");
+                        current.size = current
+                            .size
+                            .or_else(|| attribute(&e, "length").and_then(|l| l.parse().ok()));
+                    }
+                    (name, Some(_)) => field = Some(name.to_vec()),
+                    _ => {}
+                }
+            }
+            Event::Empty(e) => {
+                check_error(&e)?;
+                if let Some(current) = item.as_mut() {
+                    match e.local_name().as_ref() {
+                        b"attr" => current.attr(&e),
+                        b"enclosure" => {
+                            current.enclosure = attribute(&e, "url");
+                            current.size = current
+                                .size
+                                .or_else(|| attribute(&e, "length").and_then(|l| l.parse().ok()));
+                        }
+                        _ => {}
+                    }
+                }
+            }
+            Event::Text(text) => {
+                if let (Some(current), Some(name)) = (item.as_mut(), field.as_deref()) {
+                    let text = text.unescape().map_err(xml_error)?.into_owned();
+                    set_field(current, name, text);
+                }
+            }
+            Event::CData(data) => {
+                if let (Some(current), Some(name)) = (item.as_mut(), field.as_deref()) {
+                    let text = String::from_utf8_lossy(&data.into_inner()).into_owned();
+                    set_field(current, name, text);
+                }
+            }
+            Event::End(e) => {
+                if e.local_name().as_ref() == b"item" {
+                    if let Some(release) = item.take().and_then(|i| i.into_release(indexer)) {
+                        releases.push(release);
+                    }
+                }
+                field = None;
+            }
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+
+    Ok(releases)
+}
+
+fn set_field(item: &mut Item, name: &[u8], text: String) {
+    match name {
+        b"title" => item.title.push_str(&text),
+        b"link" => item.link = Some(text.trim().to_string()),
+        b"size" => item.size = item.size.or(text.trim().parse().ok()),
+        b"pubDate" => item.pub_date = Some(text.trim().to_string()),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::indexer::{Quality, Source};
+    use axum::{extract::Query, routing::get, Router};
+    use std::collections::HashMap;
+    use std::sync::{Arc, Mutex};
+
+    const CAPS: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<caps>
+  <server version="1.0" title="Fixture"/>
+  <limits max="100" default="50"/>
+  <searching>
+    <search available="yes" supportedParams="q"/>
+    <tv-search available="yes" supportedParams="q,season,ep,imdbid,tvdbid"/>
+    <movie-search available="yes" supportedParams="q,imdbid"/>
+    <music-search available="yes" supportedParams="q,artist,album"/>
+    <book-search available="no" supportedParams="q"/>
+  </searching>
+  <categories>
+    <category id="2000" name="Movies">
+      <subcat id="2040" name="Movies/HD"/>
+    </category>
+    <category id="3000" name="Audio"/>
+    <category id="5000" name="TV"/>
+  </categories>
+</caps>"#;
+
+    const RESULTS: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0" xmlns:atom="http://www.w3.org/2005/Atom" xmlns:torznab="http://torznab.com/schemas/2015/feed">
+  <channel>
+    <title>Fixture</title>
+    <item>
+      <title>Fight.Club.1999.1080p.BluRay.x264-GROUP</title>
+      <guid>https://tracker.example/details/1</guid>
+      <link>https://tracker.example/download/1.torrent</link>
+      <size>8589934592</size>
+      <pubDate>Sat, 17 Oct 2026 09:30:00 +0000</pubDate>
+      <enclosure url="https://tracker.example/download/1.torrent" length="8589934592" type="application/x-bittorrent"/>
+      <torznab:attr name="seeders" value="42"/>
+      <torznab:attr name="peers" value="50"/>
+      <torznab:attr name="infohash" value="0123456789abcdef0123456789abcdef01234567"/>
+    </item>
+    <item>
+      <title><![CDATA[Fight Club 1999 720p WEB-DL & Extras]]></title>
+      <guid>https://tracker.example/details/2</guid>
+      <link>https://tracker.example/download/2.torrent</link>
+      <enclosure url="https://tracker.example/download/2.torrent" length="2147483648" type="application/x-bittorrent"/>
+      <torznab:attr name="seeders" value="3"/>
+      <torznab:attr name="peers" value="4"/>
+      <torznab:attr name="magneturl" value="magnet:?xt=urn:btih:fedcba9876543210fedcba9876543210fedcba98"/>
+    </item>
+    <item>
+      <title>No.Link.At.All</title>
+    </item>
+  </channel>
+</rss>"#;
+
+    /// Serves the fixtures and records the query of each request.
+    async fn fixture_server() -> (String, Arc<Mutex<Vec<HashMap<String, String>>>>) {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&requests);
+
+        let app = Router::new().route(
+            "/api",
+            get(move |Query(params): Query<HashMap<String, String>>| {
+                let recorded = Arc::clone(&recorded);
+                async move {
+                    let body = match params.get("t").map(String::as_str) {
+                        Some("caps") => CAPS,
+                        _ => RESULTS,
+                    };
+                    recorded.lock().unwrap().push(params);
+                    body
+                }
+            }),
+        );
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            axum::serve(listener, app).await.unwrap();
+        });
+
+        (format!("http://{}", addr), requests)
+    }
+
+    fn param<'a>(params: &'a [(&str, String)], name: &str) -> Option<&'a str> {
+        params
+            .iter()
+            .find(|(k, _)| *k == name)
+            .map(|(_, v)| v.as_str())
+    }
+
+    #[test]
+    fn test_parse_caps() {
+        let caps = parse_caps(CAPS).unwrap();
+
+        assert!(caps.search.is_some());
+        assert!(caps.tv_search.as_ref().unwrap().supports("tvdbid"));
+        assert!(caps.movie_search.as_ref().unwrap().supports("imdbid"));
+        assert!(!caps.movie_search.as_ref().unwrap().supports("tmdbid"));
+        assert!(caps.music_search.as_ref().unwrap().supports("album"));
+        assert_eq!(caps.categories, vec![2000, 2040, 3000, 5000]);
+    }
+
+    #[test]
+    fn test_parse_error_response() {
+        let xml =
+            r#"<?xml version="1.0"?><error code="100" description="Incorrect user credentials"/>"#;
+        let err = parse_caps(xml).unwrap_err();
+        assert!(err.to_string().contains("Incorrect user credentials"));
+        assert!(parse_results(xml, "Fixture").is_err());
+    }
+
+    #[test]
+    fn test_parse_results() {
+        let releases = parse_results(RESULTS, "Fixture").unwrap();
+        assert_eq!(releases.len(), 2);
+
+        let first = &releases[0];
+        assert_eq!(first.indexer, "Fixture");
+        assert_eq!(first.seeders, 42);
+        assert_eq!(first.leechers, 8);
+        assert_eq!(first.size_bytes, 8589934592);
+        // The .torrent link keeps the trackers, the hash is kept for dedupe
+        assert_eq!(first.magnet, "https://tracker.example/download/1.torrent");
+        assert_eq!(
+            first.info_hash.as_deref(),
+            Some("0123456789abcdef0123456789abcdef01234567")
+        );
+        assert_eq!(first.quality, Quality::P1080);
+        assert_eq!(first.source, Source::BluRay);
+        assert_eq!(
+            first.uploaded_at.as_deref(),
+            Some("2026-10-17T09:30:00+00:00")
+        );
+
+        let second = &releases[1];
+        assert_eq!(second.title, "Fight Club 1999 720p WEB-DL & Extras");
+        assert_eq!(
+            second.magnet,
+            "magnet:?xt=urn:btih:fedcba9876543210fedcba9876543210fedcba98"
+        );
+        assert_eq!(
+            second.info_hash.as_deref(),
+            Some("fedcba9876543210fedcba9876543210fedcba98")
+        );
+        assert_eq!(second.size_bytes, 2147483648);
+        assert_eq!(second.leechers, 1);
+    }
+
+    #[test]
+    fn test_magnet_built_from_info_hash_without_link() {
+        let xml = r#"<rss><channel><item>
+            <title>Hash.Only.2024.1080p</title>
+            <torznab:attr name="infohash" value="0123456789ABCDEF0123456789ABCDEF01234567"/>
+        </item></channel></rss>"#;
+        let releases = parse_results(xml, "Fixture").unwrap();
+        assert_eq!(releases.len(), 1);
+        assert!(releases[0]
+            .magnet
+            .starts_with("magnet:?xt=urn:btih:0123456789abcdef0123456789abcdef01234567"));
+        assert_eq!(
+            releases[0].info_hash.as_deref(),
+            Some("0123456789abcdef0123456789abcdef01234567")
+        );
+    }
+
+    #[test]
+    fn test_search_params_use_ids() {
+        let caps = parse_caps(CAPS).unwrap();
+
+        let movie = SearchQuery::new("Fight Club")
+            .media_type(MediaSearchType::Movie)
+            .year(1999)
+            .imdb_id("tt0137523");
+        let params = search_params(&caps, &movie);
+        assert_eq!(param(&params, "t"), Some("movie"));
+        assert_eq!(param(&params, "imdbid"), Some("0137523"));
+        assert_eq!(param(&params, "q"), None);
+        assert_eq!(param(&params, "cat"), Some("2000"));
+
+        let episode = SearchQuery::new("Breaking Bad")
+            .media_type(MediaSearchType::TvEpisode)
+            .episode(1, 2)
+            .tvdb_id(81189);
+        let params = search_params(&caps, &episode);
+        assert_eq!(param(&params, "t"), Some("tvsearch"));
+        assert_eq!(param(&params, "tvdbid"), Some("81189"));
+        assert_eq!(param(&params, "season"), Some("1"));
+        assert_eq!(param(&params, "ep"), Some("2"));
+        assert_eq!(param(&params, "cat"), Some("5000"));
+
+        let album = SearchQuery::new("Daft Punk Discovery")
+            .media_type(MediaSearchType::MusicAlbum)
+            .album("Daft Punk", "Discovery");
+        let params = search_params(&caps, &album);
+        assert_eq!(param(&params, "t"), Some("music"));
+        assert_eq!(param(&params, "artist"), Some("Daft Punk"));
+        assert_eq!(param(&params, "album"), Some("Discovery"));
+        assert_eq!(param(&params, "cat"), Some("3000"));
+    }
+
+    #[test]
+    fn test_search_params_fall_back_to_text() {
+        let caps = TorznabCaps {
+            search: Some(SearchMode {
+                params: vec!["q".to_string()],
+            }),
+            tv_search: Some(SearchMode {
+                params: vec!["q".to_string()],
+            }),
+            ..Default::default()
+        };
+
+        // No movie search mode
+        let movie = SearchQuery::new("Fight Club")
+            .media_type(MediaSearchType::Movie)
+            .year(1999)
+            .imdb_id("tt0137523");
+        let params = search_params(&caps, &movie);
+        assert_eq!(param(&params, "t"), Some("search"));
+        assert_eq!(param(&params, "q"), Some("Fight Club 1999"));
+        assert_eq!(param(&params, "imdbid"), None);
+
+        // TV search without season and episode parameters
+        let episode = SearchQuery::new("Breaking Bad")
+            .media_type(MediaSearchType::TvEpisode)
+            .episode(1, 2);
+        let params = search_params(&caps, &episode);
+        assert_eq!(param(&params, "t"), Some("tvsearch"));
+        assert_eq!(param(&params, "q"), Some("Breaking Bad S01E02"));
+
+        // Latest releases
+        let params = search_params(&caps, &SearchQuery::default());
+        assert_eq!(param(&params, "t"), Some("search"));
+        assert_eq!(param(&params, "q"), None);
+    }
+
+    #[test]
+    fn test_api_url() {
+        let provider = TorznabProvider::new(
+            "Jackett".to_string(),
+            "http://jackett:9117/api/v2.0/indexers/all/results/torznab/",
+            None,
+        );
+        assert_eq!(
+            provider.api_url,
+            "http://jackett:9117/api/v2.0/indexers/all/results/torznab/api"
+        );
+
+        let provider =
+            TorznabProvider::new("Prowlarr".to_string(), "http://prowlarr:9696/1/api", None);
+        assert_eq!(provider.api_url, "http://prowlarr:9696/1/api");
+    }
+
+    #[tokio::test]
+    async fn test_latest_against_fixture_server() {
+        let (url, requests) = fixture_server().await;
+        let provider = TorznabProvider::new("Fixture".to_string(), &url, None);
+
+        let releases = provider.latest().await.unwrap();
+        assert_eq!(releases.len(), 2);
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0]["t"], "search");
+        assert!(!requests[0].contains_key("q"));
+    }
+
+    #[tokio::test]
+    async fn test_search_against_fixture_server() {
+        let (url, requests) = fixture_server().await;
+        let provider =
+            TorznabProvider::new("Fixture".to_string(), &url, Some("secret".to_string()));
+
+        // Assumed capable until the capabilities are known
+        assert!(provider.supports_movies());
+
+        let query = SearchQuery::new("Fight Club")
+            .media_type(MediaSearchType::Movie)
+            .imdb_id("tt0137523");
+        let releases = provider.search(&query).await.unwrap();
+        assert_eq!(releases.len(), 2);
+        assert_eq!(releases[0].seeders, 42);
+
+        // Caps are fetched once
+        provider.search(&query).await.unwrap();
+        let requests = requests.lock().unwrap();
+        let kinds: Vec<&str> = requests.iter().map(|r| r["t"].as_str()).collect();
+        assert_eq!(kinds, vec!["caps", "movie", "movie"]);
+        assert_eq!(requests[1]["imdbid"], "0137523");
+        assert!(requests.iter().all(|r| r["apikey"] == "secret"));
+
+        assert!(provider.supports_movies());
+        assert!(provider.supports_tv());
+        assert!(provider.supports_music());
+    }
+
+    #[tokio::test]
+    async fn test_connection_test() {
+        let (url, _) = fixture_server().await;
+        let provider = TorznabProvider::new("Fixture".to_string(), &url, None);
+        let result = provider.test().await.unwrap();
+        assert!(result.success);
+
+        let provider = TorznabProvider::new("Down".to_string(), "http://127.0.0.1:1", None);
+        let result = provider.test().await.unwrap();
+        assert!(!result.success);
+        assert!(result.error.is_some());
+    }
+}
@@ -11,6 +11,7 @@ use crate::error::{AppError, Result};
 use crate::services::indexer::{
     IndexerProvider, IndexerTestResult, Quality, Release, SearchQuery, Source,
 };
+use crate::services::torrent_source::magnet_info_hash;
 
 const YTS_BASE_URL: &str = "https://yts.mx";
 const YTS_API_URL: &str = "https://yts.mx/api/v2/list_movies.json";
@@ -67,58 +68,12 @@ impl YtsProvider {
             _ => Quality::Unknown,
         }
     }
-}
-
-impl Default for YtsProvider {
-    fn default() -> Self {
-        Self::new()
-    }
-}
-
-#[async_trait]
-impl IndexerProvider for YtsProvider {
-    fn name(&self) -> &str {
-        "YTS"
-    }
-
-    fn supports_movies(&self) -> bool {
-        true
-    }
-
-    fn supports_tv(&self) -> bool {
-        false
-    }
-
-    fn supports_music(&self) -> bool {
-        false
-    }
-
-    async fn search(&self, query: &SearchQuery) -> Result<Vec<Release>> {
-        // Build URL with query parameters
-        let mut url = format!("{}?limit=50", self.api_url);
-
-        // Add search query
-        if !query.query.is_empty() {
-            url.push_str(&format!(
-                "&query_term={}",
-                urlencoding::encode(&query.query)
-            ));
-        }
-
-        // Add year filter if specified
-        if let Some(year) = query.year {
-            // YTS doesn't have exact year filter, but we can use it as part of query
-            url.push_str(&format!("&query_term={}", year));
-        }
-
-        // Sort by seeds for best results
-        url.push_str("&sort_by=seeds");
-
-        tracing::debug!(url = %url, "Searching YTS");
 
+    /// Fetch movies from the API, one release per torrent.
+    async fn fetch(&self, url: &str) -> Result<Vec<Release>> {
         let response = self
             .client
-            .get(&url)
+            .get(url)
             .send()
             .await
             .map_err(|e| AppError::Internal(format!("YTS search request failed: {}", e)))?;
@@ -165,6 +120,7 @@ impl IndexerProvider for YtsProvider {
                         id: Release::generate_id("YTS", &title, &magnet),
                         title,
                         indexer: "YTS".to_string(),
+                        info_hash: magnet_info_hash(&magnet),
                         magnet,
                         size_bytes: torrent.size_bytes,
                         seeders: torrent.seeds,
@@ -184,6 +140,63 @@ impl IndexerProvider for YtsProvider {
 
         Ok(releases)
     }
+}
+
+impl Default for YtsProvider {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl IndexerProvider for YtsProvider {
+    fn name(&self) -> &str {
+        "YTS"
+    }
+
+    fn supports_movies(&self) -> bool {
+        true
+    }
+
+    fn supports_tv(&self) -> bool {
+        false
+    }
+
+    fn supports_music(&self) -> bool {
+        false
+    }
+
+    async fn search(&self, query: &SearchQuery) -> Result<Vec<Release>> {
+        // Build URL with query parameters
+        let mut url = format!("{}?limit=50", self.api_url);
+
+        // Add search query
+        if !query.query.is_empty() {
+            url.push_str(&format!(
+                "&query_term={}",
+                urlencoding::encode(&query.query)
+            ));
+        }
+
+        // Add year filter if specified
+        if let Some(year) = query.year {
+            // YTS doesn't have exact year filter, but we can use it as part of query
+            url.push_str(&format!("&query_term={}", year));
+        }
+
+        // Sort by seeds for best results
+        url.push_str("&sort_by=seeds");
+
+        tracing::debug!(url = %url, "Searching YTS");
+
+        self.fetch(&url).await
+    }
+
+    /// Movies by upload date are the newest releases.
+    async fn latest(&self) -> Result<Vec<Release>> {
+        let url = format!("{}?limit=50&sort_by=date_added", self.api_url);
+        self.fetch(&url).await
+    }
 
     async fn test(&self) -> Result<IndexerTestResult> {
         let start = Instant::now();
@@ -0,0 +1,592 @@
+//! Automatic release selection for monitored media.
+//!
+//! Filters indexer results against a media item's quality profile (or its
+//! older quality limit), the configured minimum seeders and size bounds, then
+//! picks the best candidate. With a profile, the position of a release's
+//! quality in the allowed list ranks first, then preferred words, then the
+//! release score. Upgrades only accept releases better than the current file.
+//! Season packs and discographies are accepted too: [`SelectionCriteria::select_files`]
+//! then picks the files of the wanted episode or album.
+
+use std::cmp::Reverse;
+use std::path::Path;
+
+use crate::config::SearchConfig;
+use crate::services::torrent::TorrentFile;
+use crate::services::torrent_source::TorrentSource;
+
+use super::parser::{parse_music_release, parse_release_name, AudioFormat, Quality};
+use super::profile::{QualityItem, QualityProfile};
+use super::{MediaSearchType, Release};
+
+const BYTES_PER_MB: u64 = 1024 * 1024;
+
+/// Criteria a release must satisfy to be grabbed automatically.
+#[derive(Debug, Clone)]
+pub struct SelectionCriteria {
+    /// Type of media being selected for
+    pub media_type: MediaSearchType,
+    /// Quality limit of the media item (e.g. "1080p" or "flac")
+    pub quality_limit: Option<String>,
+    /// Minimum number of seeders
+    pub min_seeders: u32,
+    /// Acceptable size range in bytes (min, max)
+    pub size_range: (u64, u64),
+    /// Wanted season and episode (for TV)
+    pub episode: Option<(i32, i32)>,
+    /// Wanted album title (for music)
+    pub album: Option<String>,
+    /// Quality profile of the media item, replacing the quality limit
+    pub profile: Option<QualityProfile>,
+    /// Runtime in minutes, for the profile's size limits
+    pub runtime_minutes: Option<u32>,
+    /// Quality of the file being upgraded
+    pub upgrade_from: Option<QualityItem>,
+}
+
+impl SelectionCriteria {
+    /// Create selection criteria for a media type using the configured bounds.
+    pub fn new(media_type: MediaSearchType, config: &SearchConfig) -> Self {
+        let (min_mb, max_mb) = match media_type {
+            MediaSearchType::Movie => config.movie_size_mb,
+            MediaSearchType::TvEpisode => config.episode_size_mb,
+            MediaSearchType::MusicAlbum => config.album_size_mb,
+        };
+
+        Self {
+            media_type,
+            quality_limit: None,
+            min_seeders: config.min_seeders,
+            size_range: (min_mb * BYTES_PER_MB, max_mb * BYTES_PER_MB),
+            episode: None,
+            album: None,
+            profile: None,
+            runtime_minutes: None,
+            upgrade_from: None,
+        }
+    }
+
+    /// Set the quality limit of the media item.
+    pub fn quality_limit(mut self, quality_limit: impl Into<String>) -> Self {
+        self.quality_limit = Some(quality_limit.into());
+        self
+    }
+
+    /// Require the release to match the given season and episode.
+    pub fn episode(mut self, season: i32, episode: i32) -> Self {
+        self.episode = Some((season, episode));
+        self
+    }
+
+    /// Set the title of the wanted album.
+    pub fn album(mut self, title: impl Into<String>) -> Self {
+        self.album = Some(title.into());
+        self
+    }
+
+    /// Use a quality profile instead of the quality limit.
+    pub fn profile(mut self, profile: QualityProfile) -> Self {
+        self.profile = Some(profile);
+        self
+    }
+
+    /// Set the runtime, so the profile's per-minute sizes can apply.
+    pub fn runtime(mut self, minutes: u32) -> Self {
+        self.runtime_minutes = Some(minutes);
+        self
+    }
+
+    /// Only accept releases the profile ranks above the current file.
+    pub fn upgrade_from(mut self, current: QualityItem) -> Self {
+        self.upgrade_from = Some(current);
+        self
+    }
+
+    /// Check whether a release satisfies these criteria.
+    pub fn accepts(&self, release: &Release) -> bool {
+        // Only magnet links and .torrent URLs can be handed to the torrent engine
+        if TorrentSource::from_link(&release.magnet).is_none() {
+            return false;
+        }
+
+        if release.seeders < self.min_seeders {
+            return false;
+        }
+
+        // A size of 0 means the indexer did not report it. Packs are bigger
+        // than a single item, only the wanted files of those get downloaded.
+        let (min_size, max_size) = self.size_bounds();
+        let max_size = if self.needs_file_selection(release) {
+            u64::MAX
+        } else {
+            max_size
+        };
+        if release.size_bytes > 0
+            && (release.size_bytes < min_size || release.size_bytes > max_size)
+        {
+            return false;
+        }
+
+        if let Some(ref profile) = self.profile {
+            if !self.accepts_profile(profile, release) {
+                return false;
+            }
+        }
+
+        match self.media_type {
+            MediaSearchType::Movie => self.accepts_video_quality(release),
+            MediaSearchType::TvEpisode => {
+                if let Some((season, episode)) = self.episode {
+                    let parsed = parse_release_name(&release.title);
+                    // Season packs have no episode number
+                    if parsed.season != Some(season) || parsed.episode.is_some_and(|e| e != episode)
+                    {
+                        return false;
+                    }
+                }
+                self.accepts_video_quality(release)
+            }
+            MediaSearchType::MusicAlbum => self.accepts_audio_format(release),
+        }
+    }
+
+    /// Whether a release may hold more than the wanted item, so that only
+    /// some of its files should be downloaded.
+    ///
+    /// True for season packs, and for music releases whose title is not the
+    /// wanted album (e.g. discographies).
+    pub fn needs_file_selection(&self, release: &Release) -> bool {
+        match self.media_type {
+            MediaSearchType::Movie => false,
+            MediaSearchType::TvEpisode => {
+                self.episode.is_some() && parse_release_name(&release.title).episode.is_none()
+            }
+            MediaSearchType::MusicAlbum => self
+                .album
+                .as_deref()
+                .is_some_and(|album| !contains_title(&release.title, album)),
+        }
+    }
+
+    /// Pick the files of the wanted episode or album from a release.
+    ///
+    /// Episode files are matched on the season and episode numbers in their
+    /// name, album files on the name of a folder they are in. Returns `None` when no file
+    /// matches.
+    pub fn select_files(&self, files: &[TorrentFile]) -> Option<Vec<usize>> {
+        let selected: Vec<usize> = files
+            .iter()
+            .filter(|file| self.wants_file(Path::new(&file.path)))
+            .map(|file| file.index)
+            .collect();
+
+        if selected.is_empty() {
+            None
+        } else {
+            Some(selected)
+        }
+    }
+
+    /// Check whether a file of a release belongs to the wanted item.
+    fn wants_file(&self, path: &Path) -> bool {
+        match self.media_type {
+            MediaSearchType::Movie => true,
+            MediaSearchType::TvEpisode => {
+                let Some((season, episode)) = self.episode else {
+                    return true;
+                };
+                let Some(name) = path.file_name().and_then(|n| n.to_str()) else {
+                    return false;
+                };
+                let parsed = parse_release_name(name);
+                parsed.season == Some(season) && parsed.episode == Some(episode)
+            }
+            MediaSearchType::MusicAlbum => {
+                let Some(album) = self.album.as_deref() else {
+                    return true;
+                };
+                let Some(parent) = path.parent() else {
+                    return false;
+                };
+                parent
+                    .components()
+                    .filter_map(|c| c.as_os_str().to_str())
+                    .any(|folder| contains_title(folder, album))
+            }
+        }
+    }
+
+    /// Size range in bytes, from the profile when it sets sizes per minute
+    /// and the runtime is known, otherwise from the configuration.
+    fn size_bounds(&self) -> (u64, u64) {
+        let (min, max) = match (&self.profile, self.runtime_minutes) {
+            (Some(profile), Some(runtime)) => profile.size_range(runtime),
+            _ => (None, None),
+        };
+        (
+            min.unwrap_or(self.size_range.0),
+            max.unwrap_or(self.size_range.1),
+        )
+    }
+
+    /// Require an allowed quality without rejected words, and when upgrading,
+    /// a better quality than the current file.
+    fn accepts_profile(&self, profile: &QualityProfile, release: &Release) -> bool {
+        if profile.is_rejected(&release.title) {
+            return false;
+        }
+
+        let Some(rank) = profile.release_rank(&release.title) else {
+            return false;
+        };
+
+        match self.upgrade_from {
+            Some(current) => match (profile.rank(&current), current) {
+                (Some(current), _) => rank < current,
+                // Anything allowed beats a quality that is not, but never
+                // at a lower resolution
+                (None, QualityItem::Video { quality, .. }) => {
+                    release.quality.score() >= quality.score()
+                }
+                (None, QualityItem::Audio(_)) => true,
+            },
+            None => true,
+        }
+    }
+
+    /// Reject video releases above the quality limit.
+    fn accepts_video_quality(&self, release: &Release) -> bool {
+        if self.profile.is_some() {
+            return true;
+        }
+        match self.quality_limit.as_deref().map(Quality::parse) {
+            Some(Quality::Unknown) | None => true,
+            Some(limit) => release.quality.score() <= limit.score(),
+        }
+    }
+
+    /// Require music releases to be in the limited audio format.
+    fn accepts_audio_format(&self, release: &Release) -> bool {
+        if self.profile.is_some() {
+            return true;
+        }
+        match self.quality_limit.as_deref().map(AudioFormat::parse) {
+            Some(AudioFormat::Unknown) | None => true,
+            Some(limit) => parse_music_release(&release.title).audio_format == Some(limit),
+        }
+    }
+}
+
+/// Pick the best release satisfying the criteria.
+///
+/// Releases holding only the wanted item are preferred over packs. With a
+/// quality profile, candidates are then ranked by their position in the
+/// allowed list and the number of preferred words. Last comes
+/// [`Release::score`]; the first of equally ranked releases wins, so indexer
+/// result order is preserved for ties.
+pub fn select_best_release<'a>(
+    releases: &'a [Release],
+    criteria: &SelectionCriteria,
+) -> Option<&'a Release> {
+    let rank = |r: &Release| {
+        let (quality, preferred) = match criteria.profile {
+            Some(ref profile) => (
+                profile.release_rank(&r.title).unwrap_or(usize::MAX),
+                profile.preferred_count(&r.title),
+            ),
+            None => (0, 0),
+        };
+        (
+            !criteria.needs_file_selection(r),
+            Reverse(quality),
+            preferred,
+            r.score(),
+        )
+    };
+
+    releases
+        .iter()
+        .filter(|r| criteria.accepts(r))
+        .fold(None, |best: Option<&Release>, r| match best {
+            Some(b) if rank(b) >= rank(r) => Some(b),
+            _ => Some(r),
+        })
+}
+
+/// Check whether a name contains all words of a title, in order.
+///
+/// Case and punctuation are ignored, so "2004 - Second Album [FLAC]"
+/// contains "Second Album".
+pub(crate) fn contains_title(name: &str, title: &str) -> bool {
+    let name = title_words(name);
+    let title = title_words(title);
+    !title.is_empty() && name.windows(title.len()).any(|w| w == title.as_slice())
+}
+
+/// Check whether two titles have the same words, ignoring case and
+/// punctuation, so "Show.Name" is the same title as "Show Name".
+pub(crate) fn same_title(a: &str, b: &str) -> bool {
+    let a = title_words(a);
+    !a.is_empty() && a == title_words(b)
+}
+
+fn title_words(s: &str) -> Vec<String> {
+    s.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::indexer::{ProfileMediaType, Source};
+
+    fn release(title: &str, quality: Quality, seeders: u32, size_mb: u64) -> Release {
+        Release {
+            id: title.to_string(),
+            title: title.to_string(),
+            indexer: "test".to_string(),
+            magnet: format!("magnet:?xt=urn:btih:{}", title),
+            info_hash: None,
+            size_bytes: size_mb * BYTES_PER_MB,
+            seeders,
+            leechers: 0,
+            quality,
+            source: Source::WebDl,
+            codec: None,
+            audio: None,
+            group: None,
+            proper: false,
+            repack: false,
+            uploaded_at: None,
+        }
+    }
+
+    #[test]
+    fn test_respects_quality_limit() {
+        let criteria = SelectionCriteria::new(MediaSearchType::Movie, &SearchConfig::default())
+            .quality_limit("1080p");
+        let releases = vec![
+            release("Movie.2024.2160p.WEB-DL", Quality::P2160, 50, 15_000),
+            release("Movie.2024.1080p.WEB-DL", Quality::P1080, 60, 4_000),
+            release("Movie.2024.720p.WEB-DL", Quality::P720, 80, 1_500),
+        ];
+
+        let best = select_best_release(&releases, &criteria).unwrap();
+        assert_eq!(best.title, "Movie.2024.1080p.WEB-DL");
+    }
+
+    #[test]
+    fn test_rejects_low_seeders_and_bad_sizes() {
+        let criteria = SelectionCriteria::new(MediaSearchType::Movie, &SearchConfig::default());
+        let releases = vec![
+            release("Movie.2024.1080p.fake", Quality::P1080, 100, 10),
+            release("Movie.2024.1080p.dead", Quality::P1080, 1, 4_000),
+            release("Movie.2024.720p.ok", Quality::P720, 10, 1_500),
+        ];
+
+        let best = select_best_release(&releases, &criteria).unwrap();
+        assert_eq!(best.title, "Movie.2024.720p.ok");
+    }
+
+    #[test]
+    fn test_unknown_size_is_not_rejected() {
+        let criteria = SelectionCriteria::new(MediaSearchType::Movie, &SearchConfig::default());
+        let releases = vec![release("Movie.2024.1080p", Quality::P1080, 10, 0)];
+
+        assert!(select_best_release(&releases, &criteria).is_some());
+    }
+
+    #[test]
+    fn test_rejects_unsupported_links() {
+        let criteria = SelectionCriteria::new(MediaSearchType::Movie, &SearchConfig::default());
+        let mut r = release("Movie.2024.1080p", Quality::P1080, 10, 2_000);
+        r.magnet = "ftp://example.com/file.torrent".to_string();
+
+        assert!(select_best_release(&[r], &criteria).is_none());
+    }
+
+    #[test]
+    fn test_accepts_torrent_urls() {
+        let criteria = SelectionCriteria::new(MediaSearchType::Movie, &SearchConfig::default());
+        let mut r = release("Movie.2024.1080p", Quality::P1080, 10, 2_000);
+        r.magnet = "https://example.com/file.torrent".to_string();
+
+        assert!(select_best_release(&[r], &criteria).is_some());
+    }
+
+    #[test]
+    fn test_episode_must_match() {
+        let criteria = SelectionCriteria::new(MediaSearchType::TvEpisode, &SearchConfig::default())
+            .episode(1, 5);
+        let releases = vec![
+            release("Show.S01E04.1080p.WEB-DL", Quality::P1080, 90, 1_000),
+            release("Show.S01E05.720p.HDTV", Quality::P720, 10, 500),
+        ];
+
+        let best = select_best_release(&releases, &criteria).unwrap();
+        assert_eq!(best.title, "Show.S01E05.720p.HDTV");
+    }
+
+    #[test]
+    fn test_album_format_limit() {
+        let criteria =
+            SelectionCriteria::new(MediaSearchType::MusicAlbum, &SearchConfig::default())
+                .quality_limit("flac");
+        let releases = vec![
+            release("Artist - Album (2024) [MP3 320]", Quality::Unknown, 90, 120),
+            release("Artist - Album (2024) [FLAC]", Quality::Unknown, 10, 400),
+        ];
+
+        let best = select_best_release(&releases, &criteria).unwrap();
+        assert_eq!(best.title, "Artist - Album (2024) [FLAC]");
+    }
+
+    fn file(index: usize, path: &str) -> TorrentFile {
+        TorrentFile {
+            index,
+            path: path.to_string(),
+            size: 1_000,
+        }
+    }
+
+    #[test]
+    fn test_prefers_episode_over_season_pack() {
+        let criteria = SelectionCriteria::new(MediaSearchType::TvEpisode, &SearchConfig::default())
+            .episode(1, 5);
+        let releases = vec![
+            release("Show.S01.1080p.WEB-DL", Quality::P1080, 90, 12_000),
+            release("Show.S02.1080p.WEB-DL", Quality::P1080, 90, 12_000),
+        ];
+
+        let best = select_best_release(&releases, &criteria).unwrap();
+        assert_eq!(best.title, "Show.S01.1080p.WEB-DL");
+        assert!(criteria.needs_file_selection(best));
+
+        let mut releases = releases;
+        releases.push(release("Show.S01E05.720p.HDTV", Quality::P720, 10, 500));
+        let best = select_best_release(&releases, &criteria).unwrap();
+        assert_eq!(best.title, "Show.S01E05.720p.HDTV");
+        assert!(!criteria.needs_file_selection(best));
+    }
+
+    #[test]
+    fn test_selects_episode_files() {
+        let criteria = SelectionCriteria::new(MediaSearchType::TvEpisode, &SearchConfig::default())
+            .episode(1, 5);
+        let files = vec![
+            file(0, "Show.S01E04.1080p.mkv"),
+            file(1, "Show.S01E05.1080p.mkv"),
+            file(2, "Subs/Show.S01E05.en.srt"),
+            file(3, "Show.S02E05.1080p.mkv"),
+            file(4, "Sample/sample.mkv"),
+        ];
+
+        assert_eq!(criteria.select_files(&files), Some(vec![1, 2]));
+        assert_eq!(criteria.select_files(&files[3..]), None);
+    }
+
+    #[test]
+    fn test_selects_album_files() {
+        let criteria =
+            SelectionCriteria::new(MediaSearchType::MusicAlbum, &SearchConfig::default())
+                .album("Second Album");
+        let files = vec![
+            file(0, "2001 - First Album/01 - Intro.flac"),
+            file(1, "2004 - Second Album [FLAC]/01 - Opening.flac"),
+            file(2, "2004 - Second Album [FLAC]/cover.jpg"),
+            file(3, "Second Album/Bonus/01 - Demo.flac"),
+            file(4, "01 - Second Album.flac"),
+        ];
+
+        assert_eq!(criteria.select_files(&files), Some(vec![1, 2, 3]));
+
+        let discography = release(
+            "Artist - Discography (2001-2010) [FLAC]",
+            Quality::Unknown,
+            10,
+            400,
+        );
+        let album = release(
+            "Artist - Second Album (2004) [FLAC]",
+            Quality::Unknown,
+            10,
+            400,
+        );
+        assert!(criteria.needs_file_selection(&discography));
+        assert!(!criteria.needs_file_selection(&album));
+    }
+
+    fn hd_profile() -> QualityProfile {
+        QualityProfile {
+            id: 1,
+            name: "HD".to_string(),
+            media_type: ProfileMediaType::Video,
+            allowed: ["1080p BluRay", "1080p WEB-DL", "720p WEB-DL"]
+                .iter()
+                .map(|s| QualityItem::parse(s).unwrap())
+                .collect(),
+            cutoff: QualityItem::parse("1080p BluRay").unwrap(),
+            preferred_words: vec!["GOOD".to_string()],
+            rejected_words: vec!["BADGRP".to_string()],
+            min_size_mb_per_minute: Some(5.0),
+            max_size_mb_per_minute: Some(50.0),
+            created_at: String::new(),
+            updated_at: String::new(),
+        }
+    }
+
+    #[test]
+    fn test_profile_ranks_quality_before_seeders() {
+        let criteria = SelectionCriteria::new(MediaSearchType::Movie, &SearchConfig::default())
+            .profile(hd_profile());
+        let releases = vec![
+            release("Movie.2024.720p.WEB-DL", Quality::P720, 500, 2_000),
+            release("Movie.2024.1080p.WEB-DL", Quality::P1080, 20, 4_000),
+            release("Movie.2024.1080p.WEB-DL-GOOD", Quality::P1080, 10, 4_000),
+            release("Movie.2024.2160p.WEB-DL", Quality::P2160, 900, 4_000),
+            release("Movie.2024.1080p.BluRay-BADGRP", Quality::P1080, 900, 4_000),
+        ];
+
+        let best = select_best_release(&releases, &criteria).unwrap();
+        assert_eq!(best.title, "Movie.2024.1080p.WEB-DL-GOOD");
+    }
+
+    #[test]
+    fn test_profile_sizes_scale_with_runtime() {
+        let criteria = SelectionCriteria::new(MediaSearchType::Movie, &SearchConfig::default())
+            .profile(hd_profile())
+            .runtime(100);
+        // 500 MB to 5000 MB for 100 minutes
+        let too_small = release("Movie.2024.1080p.WEB-DL", Quality::P1080, 50, 400);
+        let too_large = release("Movie.2024.1080p.WEB-DL", Quality::P1080, 50, 6_000);
+        let fits = release("Movie.2024.1080p.WEB-DL", Quality::P1080, 50, 3_000);
+
+        assert!(!criteria.accepts(&too_small));
+        assert!(!criteria.accepts(&too_large));
+        assert!(criteria.accepts(&fits));
+    }
+
+    #[test]
+    fn test_upgrade_requires_better_quality() {
+        let criteria = SelectionCriteria::new(MediaSearchType::Movie, &SearchConfig::default())
+            .profile(hd_profile())
+            .upgrade_from(QualityItem::parse("1080p WEB-DL").unwrap());
+
+        let same = release("Movie.2024.1080p.WEB-DL", Quality::P1080, 50, 4_000);
+        let worse = release("Movie.2024.720p.WEB-DL", Quality::P720, 50, 2_000);
+        let better = release("Movie.2024.1080p.BluRay", Quality::P1080, 50, 8_000);
+        assert!(!criteria.accepts(&same));
+        assert!(!criteria.accepts(&worse));
+        assert!(criteria.accepts(&better));
+
+        // A file of a quality the profile does not list is never replaced by
+        // a lower resolution
+        let criteria = SelectionCriteria::new(MediaSearchType::Movie, &SearchConfig::default())
+            .profile(hd_profile())
+            .upgrade_from(QualityItem::parse("1080p HDTV").unwrap());
+        assert!(!criteria.accepts(&worse));
+        assert!(criteria.accepts(&same));
+    }
+}
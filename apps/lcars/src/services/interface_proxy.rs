@@ -0,0 +1,435 @@
+//! Interface-bound SOCKS5 proxy for torrent traffic.
+//!
+//! librqbit cannot bind its sockets to a network interface, but it can send
+//! peer connections and tracker requests through a SOCKS5 proxy. This proxy
+//! listens on localhost and opens every outgoing connection with
+//! `SO_BINDTODEVICE`, so that traffic can only leave through the configured
+//! interface (e.g. a WireGuard tunnel). If the interface goes away, connections
+//! fail instead of falling back to the default route. Domain name targets are
+//! refused, since the system resolver is not bound to the interface.
+//!
+//! The sockets librqbit opens itself (DHT, UDP trackers and the incoming
+//! listener) cannot be bound, so [`check_default_route`] makes sure the
+//! routing table already sends them through the interface.
+
+use std::io;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, SocketAddrV4, SocketAddrV6};
+use std::sync::Arc;
+use std::time::Duration;
+
+use network_interface::{NetworkInterface, NetworkInterfaceConfig};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpSocket, TcpStream, UdpSocket};
+use tokio::task::JoinHandle;
+
+use crate::error::{AppError, Result};
+
+/// Timeout for connecting to the requested destination.
+const CONNECT_TIMEOUT_SECS: u64 = 10;
+
+/// Public addresses whose route is checked, one per address family.
+const ROUTE_PROBES: [SocketAddr; 2] = [
+    SocketAddr::V4(SocketAddrV4::new(Ipv4Addr::new(1, 1, 1, 1), 53)),
+    SocketAddr::V6(SocketAddrV6::new(
+        Ipv6Addr::new(0x2606, 0x4700, 0x4700, 0, 0, 0, 0, 0x1111),
+        53,
+        0,
+        0,
+    )),
+];
+
+const SOCKS_VERSION: u8 = 5;
+const AUTH_NONE: u8 = 0x00;
+const AUTH_UNACCEPTABLE: u8 = 0xff;
+const CMD_CONNECT: u8 = 0x01;
+const ATYP_IPV4: u8 = 0x01;
+const ATYP_DOMAIN: u8 = 0x03;
+const ATYP_IPV6: u8 = 0x04;
+const REPLY_SUCCEEDED: u8 = 0x00;
+const REPLY_GENERAL_FAILURE: u8 = 0x01;
+const REPLY_COMMAND_NOT_SUPPORTED: u8 = 0x07;
+const REPLY_ADDRESS_NOT_SUPPORTED: u8 = 0x08;
+
+/// A running SOCKS5 proxy whose outgoing connections are bound to an interface.
+///
+/// The proxy stops when dropped.
+pub struct InterfaceProxy {
+    interface: Arc<str>,
+    addr: SocketAddr,
+    handle: JoinHandle<()>,
+}
+
+impl InterfaceProxy {
+    /// Start a proxy for `interface`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if sockets cannot be bound to the interface, e.g.
+    /// because it does not exist or the platform does not support it.
+    pub async fn start(interface: &str) -> Result<Self> {
+        // Fail now rather than on the first connection
+        let probe = TcpSocket::new_v4()
+            .map_err(|e| AppError::Internal(format!("Failed to create socket: {}", e)))?;
+        bind_to_interface(&probe, interface).map_err(|e| {
+            AppError::Vpn(format!(
+                "Cannot bind sockets to interface {}: {}",
+                interface, e
+            ))
+        })?;
+
+        let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+            .await
+            .map_err(|e| AppError::Internal(format!("Failed to start interface proxy: {}", e)))?;
+        let addr = listener
+            .local_addr()
+            .map_err(|e| AppError::Internal(format!("Failed to start interface proxy: {}", e)))?;
+
+        let interface: Arc<str> = Arc::from(interface);
+        let handle = tokio::spawn(accept_loop(listener, Arc::clone(&interface)));
+
+        tracing::info!(addr = %addr, interface = %interface, "Interface proxy started");
+
+        Ok(Self {
+            interface,
+            addr,
+            handle,
+        })
+    }
+
+    /// Proxy URL to hand to librqbit.
+    pub fn url(&self) -> String {
+        format!("socks5://{}", self.addr)
+    }
+
+    /// Interface outgoing connections are bound to.
+    pub fn interface(&self) -> &str {
+        &self.interface
+    }
+}
+
+impl Drop for InterfaceProxy {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+/// Check that traffic to the internet leaves through `interface`.
+///
+/// Sockets that are not bound to the interface follow the routing table
+/// (policy rules included), so the route to public addresses must go through
+/// it. An address family without any route cannot leak and is accepted.
+///
+/// # Errors
+///
+/// Returns an error if public traffic would use another interface.
+pub async fn check_default_route(interface: &str) -> Result<()> {
+    check_routes(interface, &ROUTE_PROBES).await
+}
+
+async fn check_routes(interface: &str, targets: &[SocketAddr]) -> Result<()> {
+    let interfaces = NetworkInterface::show()
+        .map_err(|e| AppError::Internal(format!("Failed to list network interfaces: {}", e)))?;
+
+    for target in targets {
+        let Some(source) = source_address(*target).await else {
+            continue;
+        };
+        let routed = interfaces
+            .iter()
+            .any(|i| i.name == interface && i.addr.iter().any(|a| a.ip() == source));
+        if !routed {
+            return Err(AppError::Vpn(format!(
+                "Traffic to {} is not routed through interface {}",
+                target.ip(),
+                interface
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Local address the kernel picks to reach `target`, if it has a route.
+///
+/// Connecting a UDP socket only selects the route, nothing is sent.
+async fn source_address(target: SocketAddr) -> Option<IpAddr> {
+    let local = if target.is_ipv4() {
+        SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0))
+    } else {
+        SocketAddr::from((Ipv6Addr::UNSPECIFIED, 0))
+    };
+    let socket = UdpSocket::bind(local).await.ok()?;
+    socket.connect(target).await.ok()?;
+    socket.local_addr().ok().map(|addr| addr.ip())
+}
+
+/// Accept proxy clients until the proxy is dropped.
+async fn accept_loop(listener: TcpListener, interface: Arc<str>) {
+    loop {
+        let (stream, _) = match listener.accept().await {
+            Ok(accepted) => accepted,
+            Err(e) => {
+                tracing::warn!(error = %e, "Failed to accept proxy connection");
+                continue;
+            }
+        };
+
+        let interface = Arc::clone(&interface);
+        tokio::spawn(async move {
+            if let Err(e) = serve(stream, &interface).await {
+                tracing::debug!(interface = %interface, error = %e, "Proxy connection failed");
+            }
+        });
+    }
+}
+
+/// Handle one SOCKS5 client: negotiate, connect and relay.
+async fn serve(mut client: TcpStream, interface: &str) -> io::Result<()> {
+    // Greeting: VER NMETHODS METHODS
+    if client.read_u8().await? != SOCKS_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "unsupported SOCKS version",
+        ));
+    }
+    let mut methods = vec![0; client.read_u8().await? as usize];
+    client.read_exact(&mut methods).await?;
+    if !methods.contains(&AUTH_NONE) {
+        client
+            .write_all(&[SOCKS_VERSION, AUTH_UNACCEPTABLE])
+            .await?;
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            "client requires authentication",
+        ));
+    }
+    client.write_all(&[SOCKS_VERSION, AUTH_NONE]).await?;
+
+    // Request: VER CMD RSV ATYP DST.ADDR DST.PORT
+    let mut header = [0u8; 4];
+    client.read_exact(&mut header).await?;
+    if header[1] != CMD_CONNECT {
+        reply(&mut client, REPLY_COMMAND_NOT_SUPPORTED, None).await?;
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("unsupported SOCKS command {}", header[1]),
+        ));
+    }
+
+    let targets: Vec<SocketAddr> = match header[3] {
+        ATYP_IPV4 => {
+            let mut ip = [0u8; 4];
+            client.read_exact(&mut ip).await?;
+            let port = client.read_u16().await?;
+            vec![SocketAddr::from((Ipv4Addr::from(ip), port))]
+        }
+        ATYP_IPV6 => {
+            let mut ip = [0u8; 16];
+            client.read_exact(&mut ip).await?;
+            let port = client.read_u16().await?;
+            vec![SocketAddr::from((Ipv6Addr::from(ip), port))]
+        }
+        ATYP_DOMAIN => {
+            // The system resolver is not bound to the interface, so looking
+            // the name up here would leak it; clients must resolve themselves
+            reply(&mut client, REPLY_ADDRESS_NOT_SUPPORTED, None).await?;
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "domain targets are not resolved through the interface",
+            ));
+        }
+        other => {
+            reply(&mut client, REPLY_ADDRESS_NOT_SUPPORTED, None).await?;
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("unsupported SOCKS address type {}", other),
+            ));
+        }
+    };
+
+    let mut upstream = match connect(&targets, interface).await {
+        Ok(stream) => stream,
+        Err(e) => {
+            reply(&mut client, REPLY_GENERAL_FAILURE, None).await?;
+            return Err(e);
+        }
+    };
+    reply(&mut client, REPLY_SUCCEEDED, upstream.local_addr().ok()).await?;
+
+    tokio::io::copy_bidirectional(&mut client, &mut upstream).await?;
+    Ok(())
+}
+
+/// Connect to the first reachable address through the interface.
+async fn connect(targets: &[SocketAddr], interface: &str) -> io::Result<TcpStream> {
+    let mut last_error = io::Error::new(io::ErrorKind::NotFound, "no address to connect to");
+
+    for target in targets {
+        let socket = if target.is_ipv4() {
+            TcpSocket::new_v4()?
+        } else {
+            TcpSocket::new_v6()?
+        };
+        bind_to_interface(&socket, interface)?;
+
+        match tokio::time::timeout(
+            Duration::from_secs(CONNECT_TIMEOUT_SECS),
+            socket.connect(*target),
+        )
+        .await
+        {
+            Ok(Ok(stream)) => return Ok(stream),
+            Ok(Err(e)) => last_error = e,
+            Err(_) => last_error = io::Error::new(io::ErrorKind::TimedOut, "connection timed out"),
+        }
+    }
+
+    Err(last_error)
+}
+
+/// Send a reply to the client, with the address we connected from on success.
+async fn reply(client: &mut TcpStream, code: u8, bound: Option<SocketAddr>) -> io::Result<()> {
+    let mut message = vec![SOCKS_VERSION, code, 0];
+    match bound.unwrap_or_else(|| SocketAddr::from((Ipv4Addr::UNSPECIFIED, 0))) {
+        SocketAddr::V4(addr) => {
+            message.push(ATYP_IPV4);
+            message.extend_from_slice(&addr.ip().octets());
+            message.extend_from_slice(&addr.port().to_be_bytes());
+        }
+        SocketAddr::V6(addr) => {
+            message.push(ATYP_IPV6);
+            message.extend_from_slice(&addr.ip().octets());
+            message.extend_from_slice(&addr.port().to_be_bytes());
+        }
+    }
+    client.write_all(&message).await
+}
+
+#[cfg(any(target_os = "linux", target_os = "android", target_os = "fuchsia"))]
+fn bind_to_interface(socket: &TcpSocket, interface: &str) -> io::Result<()> {
+    socket.bind_device(Some(interface.as_bytes()))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "android", target_os = "fuchsia")))]
+fn bind_to_interface(_socket: &TcpSocket, _interface: &str) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "binding to an interface is only supported on Linux",
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Open a SOCKS5 CONNECT through the proxy to `target`.
+    async fn socks_connect(proxy: &InterfaceProxy, target: SocketAddr) -> (TcpStream, u8) {
+        let mut stream = TcpStream::connect(proxy.addr).await.unwrap();
+        stream
+            .write_all(&[SOCKS_VERSION, 1, AUTH_NONE])
+            .await
+            .unwrap();
+        let mut choice = [0u8; 2];
+        stream.read_exact(&mut choice).await.unwrap();
+        assert_eq!(choice, [SOCKS_VERSION, AUTH_NONE]);
+
+        let SocketAddr::V4(target) = target else {
+            panic!("test targets are IPv4");
+        };
+        let mut request = vec![SOCKS_VERSION, CMD_CONNECT, 0, ATYP_IPV4];
+        request.extend_from_slice(&target.ip().octets());
+        request.extend_from_slice(&target.port().to_be_bytes());
+        stream.write_all(&request).await.unwrap();
+
+        let mut reply = [0u8; 10];
+        stream.read_exact(&mut reply).await.unwrap();
+        (stream, reply[1])
+    }
+
+    #[tokio::test]
+    async fn test_relays_through_interface() {
+        let proxy = InterfaceProxy::start("lo").await.unwrap();
+        assert_eq!(proxy.interface(), "lo");
+        assert!(proxy.url().starts_with("socks5://127.0.0.1:"));
+
+        let server = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let target = server.local_addr().unwrap();
+        let echo = tokio::spawn(async move {
+            let (mut stream, _) = server.accept().await.unwrap();
+            let mut buf = [0u8; 4];
+            stream.read_exact(&mut buf).await.unwrap();
+            stream.write_all(&buf).await.unwrap();
+        });
+
+        let (mut stream, code) = socks_connect(&proxy, target).await;
+        assert_eq!(code, REPLY_SUCCEEDED);
+
+        stream.write_all(b"ping").await.unwrap();
+        let mut buf = [0u8; 4];
+        stream.read_exact(&mut buf).await.unwrap();
+        assert_eq!(&buf, b"ping");
+        echo.await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_reports_unreachable_destination() {
+        let proxy = InterfaceProxy::start("lo").await.unwrap();
+        let target = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap()
+        };
+
+        let (_, code) = socks_connect(&proxy, target).await;
+        assert_eq!(code, REPLY_GENERAL_FAILURE);
+    }
+
+    #[tokio::test]
+    async fn test_rejects_domain_targets() {
+        let proxy = InterfaceProxy::start("lo").await.unwrap();
+        let mut stream = TcpStream::connect(proxy.addr).await.unwrap();
+        stream
+            .write_all(&[SOCKS_VERSION, 1, AUTH_NONE])
+            .await
+            .unwrap();
+        let mut choice = [0u8; 2];
+        stream.read_exact(&mut choice).await.unwrap();
+
+        let domain = b"tracker.example";
+        let mut request = vec![
+            SOCKS_VERSION,
+            CMD_CONNECT,
+            0,
+            ATYP_DOMAIN,
+            domain.len() as u8,
+        ];
+        request.extend_from_slice(domain);
+        request.extend_from_slice(&80u16.to_be_bytes());
+        stream.write_all(&request).await.unwrap();
+
+        let mut reply = [0u8; 10];
+        stream.read_exact(&mut reply).await.unwrap();
+        assert_eq!(reply[1], REPLY_ADDRESS_NOT_SUPPORTED);
+    }
+
+    #[tokio::test]
+    async fn test_route_through_interface_is_accepted() {
+        let target = SocketAddr::from((Ipv4Addr::LOCALHOST, 9));
+        check_routes("lo", &[target]).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_route_elsewhere_is_rejected() {
+        // Public addresses are never routed through loopback
+        for target in ROUTE_PROBES {
+            if source_address(target).await.is_some() {
+                let err = check_routes("lo", &[target]).await.unwrap_err();
+                assert!(matches!(err, AppError::Vpn(_)));
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_missing_interface_is_rejected() {
+        let err = InterfaceProxy::start("lcars-missing0").await.err().unwrap();
+        assert!(matches!(err, AppError::Vpn(_)));
+    }
+}
@@ -0,0 +1,380 @@
+//! Failed login throttling.
+//!
+//! Failures are counted per client IP and per username. After a few free
+//! attempts each failure doubles the wait before the next attempt, and
+//! reaching the maximum locks logins out for the lockout period. Counters are
+//! forgotten once a lockout period passes without failures, or on success.
+//!
+//! Each attempt is counted as a failure when it begins, in the same step as
+//! the check, so parallel requests cannot all pass the check before the
+//! first failure is recorded.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use rusqlite::Connection;
+use serde_json::json;
+
+use crate::config::LoginConfig;
+use crate::error::{AppError, Result};
+use crate::services::activity::{ActivityBuilder, EventType};
+
+/// Entries kept before expired ones are pruned.
+const PRUNE_THRESHOLD: usize = 10_000;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+enum AttemptKey {
+    Ip(IpAddr),
+    Username(String),
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Attempts {
+    failures: u32,
+    last_failure: Instant,
+    blocked_until: Instant,
+}
+
+/// A login attempt that was let through, counted as a failure until it succeeds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LoginAttempt {
+    /// Most failures counted against the IP or the username
+    pub failures: u32,
+    /// Whether this attempt starts a lockout if it fails
+    pub locked_out: bool,
+}
+
+/// Tracks failed logins and decides when to refuse attempts.
+pub struct LoginThrottle {
+    config: LoginConfig,
+    attempts: Mutex<HashMap<AttemptKey, Attempts>>,
+}
+
+impl LoginThrottle {
+    pub fn new(config: LoginConfig) -> Self {
+        Self {
+            config,
+            attempts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn new_shared(config: LoginConfig) -> Arc<Self> {
+        Arc::new(Self::new(config))
+    }
+
+    /// Lets a login attempt through and counts it against the IP and the
+    /// username until `record_success` clears it.
+    ///
+    /// Returns `RateLimited` with the seconds to wait if the IP or username
+    /// may not attempt a login yet.
+    pub fn begin_attempt(&self, ip: Option<IpAddr>, username: &str) -> Result<LoginAttempt> {
+        self.begin_attempt_at(ip, username, Instant::now())
+    }
+
+    /// Writes an activity entry when a failed attempt started a lockout.
+    pub fn login_failed(
+        &self,
+        conn: &Connection,
+        ip: Option<IpAddr>,
+        username: &str,
+        failed: LoginAttempt,
+    ) {
+        if failed.locked_out {
+            let from = ip.map_or_else(|| "unknown address".to_string(), |ip| ip.to_string());
+            tracing::warn!(username = %username, ip = %from, failures = failed.failures, "Login locked out");
+            ActivityBuilder::new(
+                EventType::LoginLockedOut,
+                format!(
+                    "Logins for '{}' from {} locked out for {} minutes after {} failed attempts",
+                    username, from, self.config.lockout_minutes, failed.failures
+                ),
+            )
+            .metadata(&json!({
+                "username": username,
+                "ip": ip,
+                "failures": failed.failures,
+                "lockout_minutes": self.config.lockout_minutes,
+            }))
+            .log_sync(conn);
+        }
+    }
+
+    /// Clears the counters of the IP and the username after a successful login.
+    pub fn record_success(&self, ip: Option<IpAddr>, username: &str) {
+        let mut attempts = self.attempts.lock().unwrap();
+        for key in keys(ip, username) {
+            attempts.remove(&key);
+        }
+    }
+
+    /// How long a lockout lasts.
+    pub fn lockout(&self) -> Duration {
+        Duration::from_secs(self.config.lockout_minutes * 60)
+    }
+
+    /// Wait before the next attempt after `failures` failures.
+    fn delay(&self, failures: u32) -> Duration {
+        if failures >= self.config.max_attempts {
+            return self.lockout();
+        }
+        match failures.checked_sub(self.config.free_attempts + 1) {
+            Some(doublings) => Duration::from_secs(1u64 << doublings.min(31)).min(self.lockout()),
+            None => Duration::ZERO,
+        }
+    }
+
+    fn is_expired(&self, entry: &Attempts, now: Instant) -> bool {
+        now >= entry.blocked_until && now.duration_since(entry.last_failure) >= self.lockout()
+    }
+
+    fn begin_attempt_at(
+        &self,
+        ip: Option<IpAddr>,
+        username: &str,
+        now: Instant,
+    ) -> Result<LoginAttempt> {
+        let mut attempts = self.attempts.lock().unwrap();
+        let keys = keys(ip, username);
+
+        let wait = wait_for(&attempts, &keys, now);
+        if !wait.is_zero() {
+            // Round up so clients never retry a moment too early
+            let secs = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+            return Err(AppError::RateLimited(secs.min(u32::MAX as u64) as u32));
+        }
+
+        if attempts.len() > PRUNE_THRESHOLD {
+            attempts.retain(|_, entry| !self.is_expired(entry, now));
+        }
+
+        let mut outcome = LoginAttempt {
+            failures: 0,
+            locked_out: false,
+        };
+
+        for key in keys {
+            let entry = attempts.entry(key).or_insert(Attempts {
+                failures: 0,
+                last_failure: now,
+                blocked_until: now,
+            });
+            if self.is_expired(entry, now) {
+                entry.failures = 0;
+            }
+
+            entry.failures += 1;
+            entry.last_failure = now;
+            entry.blocked_until = now + self.delay(entry.failures);
+
+            outcome.failures = outcome.failures.max(entry.failures);
+            outcome.locked_out |= entry.failures == self.config.max_attempts;
+        }
+
+        Ok(outcome)
+    }
+}
+
+/// Longest wait before any of `keys` may attempt a login.
+fn wait_for(
+    attempts: &HashMap<AttemptKey, Attempts>,
+    keys: &[AttemptKey],
+    now: Instant,
+) -> Duration {
+    keys.iter()
+        .filter_map(|key| attempts.get(key))
+        .map(|entry| entry.blocked_until.saturating_duration_since(now))
+        .max()
+        .unwrap_or_default()
+}
+
+fn keys(ip: Option<IpAddr>, username: &str) -> Vec<AttemptKey> {
+    ip.map(AttemptKey::Ip)
+        .into_iter()
+        .chain(std::iter::once(AttemptKey::Username(username.to_string())))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn throttle() -> LoginThrottle {
+        LoginThrottle::new(LoginConfig {
+            free_attempts: 3,
+            max_attempts: 6,
+            lockout_minutes: 15,
+        })
+    }
+
+    fn ip(s: &str) -> Option<IpAddr> {
+        Some(s.parse().unwrap())
+    }
+
+    fn wait(throttle: &LoginThrottle, ip: Option<IpAddr>, username: &str, now: Instant) -> u64 {
+        let attempts = throttle.attempts.lock().unwrap();
+        wait_for(&attempts, &keys(ip, username), now).as_secs()
+    }
+
+    /// Begins an attempt that is expected to be let through.
+    fn fail(
+        throttle: &LoginThrottle,
+        ip: Option<IpAddr>,
+        username: &str,
+        now: Instant,
+    ) -> LoginAttempt {
+        throttle.begin_attempt_at(ip, username, now).unwrap()
+    }
+
+    #[test]
+    fn test_backoff_doubles_then_locks_out() {
+        let throttle = throttle();
+        let mut now = Instant::now();
+        let client = ip("203.0.113.9");
+
+        let waits: Vec<u64> = (0..6)
+            .map(|_| {
+                fail(&throttle, client, "alice", now);
+                let wait = wait(&throttle, client, "alice", now);
+                now += Duration::from_secs(wait);
+                wait
+            })
+            .collect();
+        assert_eq!(waits, vec![0, 0, 0, 1, 2, 15 * 60]);
+    }
+
+    #[test]
+    fn test_rate_limited_reports_wait() {
+        let throttle = throttle();
+        let now = Instant::now();
+
+        for _ in 0..4 {
+            fail(&throttle, None, "alice", now);
+        }
+        assert!(matches!(
+            throttle.begin_attempt_at(None, "alice", now),
+            Err(AppError::RateLimited(1))
+        ));
+    }
+
+    #[test]
+    fn test_concurrent_attempts_are_counted() {
+        let throttle = Arc::new(throttle());
+
+        // All threads race for the check; only the attempts the backoff
+        // allows without waiting get through
+        let handles: Vec<_> = (0..20)
+            .map(|_| {
+                let throttle = throttle.clone();
+                std::thread::spawn(move || {
+                    throttle.begin_attempt(ip("203.0.113.9"), "alice").is_ok()
+                })
+            })
+            .collect();
+        let allowed = handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .filter(|&ok| ok)
+            .count();
+        assert_eq!(allowed, 4);
+    }
+
+    #[test]
+    fn test_lockout_reported_once() {
+        let throttle = throttle();
+        let mut now = Instant::now();
+
+        let outcomes: Vec<LoginAttempt> = (0..6)
+            .map(|_| {
+                now += Duration::from_secs(wait(&throttle, None, "alice", now));
+                fail(&throttle, None, "alice", now)
+            })
+            .collect();
+        assert!(outcomes[..5].iter().all(|o| !o.locked_out));
+        assert!(outcomes[5].locked_out);
+        assert_eq!(outcomes[5].failures, 6);
+
+        // No further attempts until the lockout passes
+        assert!(matches!(
+            throttle.begin_attempt_at(None, "alice", now),
+            Err(AppError::RateLimited(secs)) if secs == 15 * 60
+        ));
+    }
+
+    #[test]
+    fn test_tracks_ip_and_username_separately() {
+        let throttle = throttle();
+        let now = Instant::now();
+        let attacker = ip("198.51.100.1");
+
+        // One IP guessing many usernames
+        for i in 0..4 {
+            fail(&throttle, attacker, &format!("user{}", i), now);
+        }
+        assert!(wait(&throttle, attacker, "someone", now) > 0);
+        assert_eq!(wait(&throttle, ip("203.0.113.9"), "someone", now), 0);
+
+        // Many IPs guessing one username
+        for i in 0..4 {
+            fail(&throttle, ip(&format!("192.0.2.{}", i)), "bob", now);
+        }
+        assert!(wait(&throttle, ip("203.0.113.9"), "bob", now) > 0);
+    }
+
+    #[test]
+    fn test_lockout_expires() {
+        let throttle = throttle();
+        let mut now = Instant::now();
+
+        for _ in 0..6 {
+            now += Duration::from_secs(wait(&throttle, None, "alice", now));
+            fail(&throttle, None, "alice", now);
+        }
+        let later = now + throttle.lockout();
+        assert_eq!(wait(&throttle, None, "alice", later), 0);
+
+        // Counting starts over
+        let outcome = fail(&throttle, None, "alice", later);
+        assert_eq!(outcome.failures, 1);
+        assert_eq!(wait(&throttle, None, "alice", later), 0);
+    }
+
+    #[test]
+    fn test_lockout_is_logged() {
+        let throttle = throttle();
+        let conn = crate::db::init_db_memory().unwrap();
+        let mut now = Instant::now();
+
+        for _ in 0..6 {
+            now += Duration::from_secs(wait(&throttle, ip("203.0.113.9"), "alice", now));
+            let attempt = fail(&throttle, ip("203.0.113.9"), "alice", now);
+            throttle.login_failed(&conn, ip("203.0.113.9"), "alice", attempt);
+        }
+
+        let message: String = conn
+            .query_row(
+                "SELECT message FROM activity WHERE event_type = 'login_locked_out'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert!(message.contains("'alice'"));
+        assert!(message.contains("203.0.113.9"));
+    }
+
+    #[test]
+    fn test_success_clears_counters() {
+        let throttle = throttle();
+        let now = Instant::now();
+        let client = ip("203.0.113.9");
+
+        for _ in 0..4 {
+            fail(&throttle, client, "alice", now);
+        }
+        throttle.record_success(client, "alice");
+
+        assert_eq!(wait(&throttle, client, "alice", now), 0);
+        assert_eq!(fail(&throttle, client, "alice", now).failures, 1);
+    }
+}
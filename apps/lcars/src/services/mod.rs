@@ -1,25 +1,40 @@
 //! Application services for the LCARS backend.
 
 pub mod activity;
+pub mod api_keys;
 pub mod auth;
+pub mod bandwidth;
 pub mod dns;
+pub mod download_queue;
 pub mod indexer;
+pub mod interface_proxy;
+pub mod login_throttle;
 pub mod musicbrainz;
+pub mod postprocess;
 pub mod scheduler;
 pub mod soulseek;
 pub mod storage;
 pub mod tmdb;
 pub mod torrent;
+pub mod torrent_source;
+pub mod torrent_sync;
+pub mod totp;
+pub mod two_factor;
 pub mod wireguard;
 
 pub use auth::{AuthService, Claims};
+pub use bandwidth::BandwidthManager;
 pub use dns::DnsManager;
+pub use download_queue::DownloadQueue;
 pub use indexer::IndexerManager;
+pub use login_throttle::LoginThrottle;
 pub use musicbrainz::MusicBrainzClient;
+pub use postprocess::PostProcessor;
 pub use scheduler::{JobContext, Scheduler};
 pub use soulseek::SoulseekEngine;
 #[allow(unused_imports)]
 pub use storage::{LocalMount, MediaInfo, Mount, NamingEngine, ProcessedFile, StorageManager};
 pub use tmdb::TmdbClient;
 pub use torrent::TorrentEngine;
+pub use torrent_sync::TorrentSync;
 pub use wireguard::WireGuardService;
@@ -54,6 +54,7 @@ impl RateLimiter {
 pub struct MusicBrainzClient {
     client: Client,
     rate_limiter: RateLimiter,
+    base_url: String,
 }
 
 impl MusicBrainzClient {
@@ -122,9 +123,16 @@ impl MusicBrainzClient {
         Ok(Self {
             client,
             rate_limiter: RateLimiter::new(Duration::from_millis(rate_limit_ms)),
+            base_url: MB_BASE_URL.to_string(),
         })
     }
 
+    /// Use a different API base URL (e.g. a local stub in tests).
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into().trim_end_matches('/').to_string();
+        self
+    }
+
     /// Create a new MusicBrainz client wrapped in Arc for shared access.
     pub fn new_shared(
         app_name: &str,
@@ -311,7 +319,7 @@ impl MusicBrainzClient {
         // Enforce rate limit
         self.rate_limiter.wait().await;
 
-        let url = format!("{}{}", MB_BASE_URL, path);
+        let url = format!("{}{}", self.base_url, path);
 
         let response = self
             .client
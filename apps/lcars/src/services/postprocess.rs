@@ -0,0 +1,1127 @@
+//! Post-download processing pipeline.
+//!
+//! Listens for completion events from the torrent and Soulseek engines, hands the
+//! downloaded files to the [`StorageManager`] and records the final location of each
+//! file on the matching movie, episode or track row. When the download upgrades
+//! media that already had a file, the old file is deleted from storage.
+//!
+//! Torrents keep seeding from their files after they are imported, so move
+//! rules copy them and the download is flagged to delete its files when the
+//! torrent is removed after seeding.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use lazy_static::lazy_static;
+use regex::Regex;
+use rusqlite::{Connection, OptionalExtension};
+use tokio::sync::{broadcast, Mutex};
+
+use crate::db::models::{
+    calculate_album_status, map_album_row, map_artist_row, map_episode_row, map_movie_row,
+    map_show_row, map_track_row, Album, Artist, MediaType, Track,
+};
+use crate::error::{AppError, Result};
+use crate::services::activity::{ActivityBuilder, EventType};
+use crate::services::download_queue::reset_media_status;
+use crate::services::indexer::{parse_release_name, ProfileMediaType, QualityItem};
+use crate::services::soulseek::SoulseekEvent;
+use crate::services::storage::{find_media_files, MediaInfo, ProcessedFile, StorageManager};
+use crate::services::torrent::TorrentEvent;
+use crate::services::{SoulseekEngine, TorrentEngine};
+
+lazy_static! {
+    /// Leading disc/track number of an audio file name, e.g. "01 - Title" or "2-05 Title".
+    static ref TRACK_NUMBER_REGEX: Regex =
+        Regex::new(r"^(?:(\d{1,2})[-.])?(\d{1,3})(?:\D|$)").unwrap();
+}
+
+/// A download row that is ready for post-processing.
+#[derive(Debug, Clone)]
+struct PendingDownload {
+    id: i64,
+    media_type: MediaType,
+    media_id: i64,
+}
+
+/// Media loaded from the database for a pending download.
+enum Target {
+    /// A single media item (movie, episode or track).
+    Single(MediaInfo),
+    /// Every track of an album, matched by file name.
+    Album {
+        artist: Box<Artist>,
+        album: Box<Album>,
+        tracks: Vec<Track>,
+    },
+}
+
+/// Processes completed downloads into the media library.
+pub struct PostProcessor {
+    db: Arc<Mutex<Connection>>,
+    storage: Arc<StorageManager>,
+}
+
+impl PostProcessor {
+    /// Create a new post-processor.
+    pub fn new(db: Arc<Mutex<Connection>>, storage: Arc<StorageManager>) -> Self {
+        Self { db, storage }
+    }
+
+    /// Create a new post-processor wrapped in Arc for shared access.
+    pub fn new_shared(db: Arc<Mutex<Connection>>, storage: Arc<StorageManager>) -> Arc<Self> {
+        Arc::new(Self::new(db, storage))
+    }
+
+    /// Process torrents as soon as the torrent engine reports them completed.
+    pub fn watch_torrents(self: &Arc<Self>, engine: Arc<TorrentEngine>) {
+        let processor = Arc::clone(self);
+        let mut rx = engine.subscribe();
+
+        tokio::spawn(async move {
+            tracing::info!("Post-processing enabled for torrent downloads");
+
+            loop {
+                match rx.recv().await {
+                    Ok(TorrentEvent::Completed { info_hash }) => {
+                        let path = match engine.content_path(&info_hash) {
+                            Ok(path) => path,
+                            Err(e) => {
+                                tracing::error!(
+                                    info_hash = %info_hash,
+                                    error = %e,
+                                    "Cannot locate completed torrent content"
+                                );
+                                continue;
+                            }
+                        };
+
+                        // Copying files can take a while, don't hold up the event loop
+                        let processor = Arc::clone(&processor);
+                        tokio::spawn(async move {
+                            if let Err(e) = processor.process("torrent", &info_hash, &path).await {
+                                tracing::debug!(info_hash = %info_hash, error = %e, "Torrent not imported");
+                            }
+                        });
+                    }
+                    Ok(_) => {}
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        tracing::warn!(missed = n, "Torrent event receiver lagged, missed events");
+                    }
+                    Err(broadcast::error::RecvError::Closed) => {
+                        tracing::debug!("Torrent event channel closed, stopping post-processing");
+                        break;
+                    }
+                }
+            }
+        });
+    }
+
+    /// Process Soulseek files as soon as their transfer completes.
+    pub fn watch_soulseek(self: &Arc<Self>, engine: Arc<SoulseekEngine>) {
+        let processor = Arc::clone(self);
+        let mut rx = engine.subscribe();
+
+        tokio::spawn(async move {
+            tracing::info!("Post-processing enabled for Soulseek downloads");
+
+            loop {
+                match rx.recv().await {
+                    Ok(SoulseekEvent::DownloadComplete { id, path }) => {
+                        let processor = Arc::clone(&processor);
+                        tokio::spawn(async move {
+                            if let Err(e) = processor.process("soulseek", &id, &path).await {
+                                tracing::debug!(id = %id, error = %e, "Soulseek download not imported");
+                            }
+                        });
+                    }
+                    Ok(_) => {}
+                    Err(broadcast::error::RecvError::Lagged(n)) => {
+                        tracing::warn!(missed = n, "Soulseek event receiver lagged, missed events");
+                    }
+                    Err(broadcast::error::RecvError::Closed) => {
+                        tracing::debug!("Soulseek event channel closed, stopping post-processing");
+                        break;
+                    }
+                }
+            }
+        });
+    }
+
+    /// Run the storage rules for a completed download and record the results.
+    ///
+    /// `source_type` and `source_id` identify the row in the `downloads` table
+    /// (info hash for torrents, transfer id for Soulseek). On failure the download
+    /// is marked `failed` and the media goes back to `missing`.
+    pub async fn process(
+        &self,
+        source_type: &str,
+        source_id: &str,
+        path: &Path,
+    ) -> Result<Vec<ProcessedFile>> {
+        let (download, target, previous) = {
+            let db = self.db.lock().await;
+            let download = find_download(&db, source_type, source_id)?;
+            let target = match load_target(&db, &download, path) {
+                Ok(target) => target,
+                Err(e) => {
+                    record_failure(&db, &download, &e.to_string());
+                    return Err(e);
+                }
+            };
+            let previous = current_files(&db, &download)?;
+            set_download_status(&db, download.id, "processing")?;
+            set_media_status(&db, &download, "processing")?;
+            (download, target, previous)
+        };
+
+        tracing::info!(
+            download_id = download.id,
+            media_type = %download.media_type,
+            media_id = download.media_id,
+            path = ?path,
+            "Post-processing completed download"
+        );
+
+        let keep_sources = source_type == "torrent";
+        let result = self.store(path, &target, keep_sources).await;
+        let quality = path
+            .file_name()
+            .and_then(|n| n.to_str())
+            .and_then(|name| file_quality(download.media_type, name));
+
+        let db = self.db.lock().await;
+        match result {
+            Ok(files) => {
+                if let Err(e) = record_success(&db, &download, path, &files, quality) {
+                    tracing::error!(download_id = download.id, error = %e, "Failed to record processed files");
+                    record_failure(&db, &download, &e.to_string());
+                    return Err(e);
+                }
+
+                // Files of the same item that the new ones replace
+                let replaced: Vec<PathBuf> = previous
+                    .into_iter()
+                    .filter(|(track_id, old)| {
+                        files
+                            .iter()
+                            .any(|(id, f)| id == track_id && f.destination != *old)
+                    })
+                    .map(|(_, old)| old)
+                    .collect();
+
+                let processed: Vec<_> = files.iter().map(|(_, f)| f).collect();
+                ActivityBuilder::new(
+                    EventType::DownloadCompleted,
+                    format!("Imported {} file(s)", processed.len()),
+                )
+                .media(&download.media_type.to_string(), download.media_id)
+                .download(download.id)
+                .metadata(&serde_json::json!({
+                    "files": processed
+                        .iter()
+                        .map(|f| serde_json::json!({
+                            "destination": f.destination,
+                            "mount": f.mount_name,
+                            "size": f.size,
+                        }))
+                        .collect::<Vec<_>>(),
+                    "quality": quality,
+                    "replaced": replaced,
+                }))
+                .log_sync(&db);
+                drop(db);
+
+                tracing::info!(
+                    download_id = download.id,
+                    files = processed.len(),
+                    "Download imported"
+                );
+
+                self.remove_replaced(&replaced).await;
+
+                Ok(files.into_iter().map(|(_, f)| f).collect())
+            }
+            Err(e) => {
+                tracing::error!(download_id = download.id, error = %e, "Post-processing failed");
+                record_failure(&db, &download, &e.to_string());
+                Err(e)
+            }
+        }
+    }
+
+    /// Delete library files that an upgrade replaced.
+    async fn remove_replaced(&self, replaced: &[PathBuf]) {
+        for path in replaced {
+            match self.storage.delete_library_file(path).await {
+                Ok(true) => tracing::info!(path = ?path, "Deleted replaced file"),
+                Ok(false) => {
+                    tracing::debug!(path = ?path, "Replaced file is outside storage, leaving it")
+                }
+                Err(e) => {
+                    tracing::warn!(path = ?path, error = %e, "Failed to delete replaced file")
+                }
+            }
+        }
+    }
+
+    /// Move or copy the downloaded files, returning each file with the track it belongs to.
+    async fn store(
+        &self,
+        path: &Path,
+        target: &Target,
+        keep_sources: bool,
+    ) -> Result<Vec<(Option<i64>, ProcessedFile)>> {
+        match target {
+            Target::Single(media_info) => {
+                let files = self.store_files(path, media_info, keep_sources).await?;
+                Ok(files.into_iter().map(|f| (None, f)).collect())
+            }
+            Target::Album {
+                artist,
+                album,
+                tracks,
+            } => {
+                let mut processed = Vec::new();
+
+                for file in find_media_files(path, MediaType::Album).await? {
+                    let Some(track) = match_track(&file, tracks) else {
+                        tracing::warn!(file = ?file, album_id = album.id, "No track matches file, skipping");
+                        continue;
+                    };
+
+                    let media_info = MediaInfo::Track {
+                        artist: artist.clone(),
+                        album: album.clone(),
+                        track: Box::new(track.clone()),
+                    };
+                    for f in self.store_files(&file, &media_info, keep_sources).await? {
+                        processed.push((Some(track.id), f));
+                    }
+                }
+
+                if processed.is_empty() {
+                    return Err(AppError::NotFound(format!(
+                        "No files in {:?} match a track of album {}",
+                        path, album.id
+                    )));
+                }
+
+                Ok(processed)
+            }
+        }
+    }
+
+    /// Apply the storage rules to a path, leaving files in place when no rule applies.
+    async fn store_files(
+        &self,
+        path: &Path,
+        media_info: &MediaInfo,
+        keep_sources: bool,
+    ) -> Result<Vec<ProcessedFile>> {
+        let processed = self
+            .storage
+            .process_completed_download(path, media_info, keep_sources)
+            .await?;
+        if !processed.is_empty() {
+            return Ok(processed);
+        }
+
+        let media_type = match media_info {
+            MediaInfo::Movie { .. } => MediaType::Movie,
+            MediaInfo::Episode { .. } => MediaType::Episode,
+            MediaInfo::Album { .. } => MediaType::Album,
+            MediaInfo::Track { .. } => MediaType::Track,
+        };
+
+        let mut files = Vec::new();
+        for file in find_media_files(path, media_type).await? {
+            let size = tokio::fs::metadata(&file)
+                .await
+                .map(|m| m.len())
+                .unwrap_or(0);
+            files.push(ProcessedFile {
+                source: file.clone(),
+                destination: file,
+                mount_name: String::new(),
+                size,
+                move_pending: false,
+            });
+        }
+        Ok(files)
+    }
+}
+
+/// Find the download row for an engine's source id.
+fn find_download(conn: &Connection, source_type: &str, source_id: &str) -> Result<PendingDownload> {
+    let row: Option<(i64, Option<String>, Option<i64>, String)> = conn
+        .query_row(
+            "SELECT id, media_type, media_id, status FROM downloads WHERE source_type = ?1 AND source_id = ?2",
+            [source_type, source_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )
+        .optional()?;
+
+    let (id, media_type, media_id, status) = row.ok_or_else(|| {
+        AppError::NotFound(format!("No {} download with id {}", source_type, source_id))
+    })?;
+
+    // Torrents restored after a restart report completion again
+    if status == "completed" {
+        return Err(AppError::Conflict(format!(
+            "Download {} was already imported",
+            id
+        )));
+    }
+    if status == "processing" {
+        return Err(AppError::Conflict(format!(
+            "Download {} is already being imported",
+            id
+        )));
+    }
+
+    let media_type = match media_type.as_deref() {
+        Some("movie") => MediaType::Movie,
+        Some("episode") => MediaType::Episode,
+        Some("album") => MediaType::Album,
+        Some("track") => MediaType::Track,
+        _ => {
+            return Err(AppError::BadRequest(format!(
+                "Download {} is not linked to any media",
+                id
+            )))
+        }
+    };
+    let media_id = media_id.ok_or_else(|| {
+        AppError::BadRequest(format!("Download {} is not linked to any media", id))
+    })?;
+
+    Ok(PendingDownload {
+        id,
+        media_type,
+        media_id,
+    })
+}
+
+/// Load everything the naming engine needs for a download.
+fn load_target(conn: &Connection, download: &PendingDownload, path: &Path) -> Result<Target> {
+    // The torrent or file name carries the release quality
+    let quality = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .map(|n| parse_release_name(n).quality.to_string())
+        .unwrap_or_else(|| "unknown".to_string());
+
+    match download.media_type {
+        MediaType::Movie => {
+            let movie = conn.query_row(
+                r#"
+                SELECT id, tmdb_id, imdb_id, title, original_title, year,
+                       overview, poster_path, backdrop_path, runtime_minutes,
+                       genres, status, monitored, quality_limit, file_path,
+                       file_size, added_at, updated_at, added_by, quality_profile_id
+                FROM movies WHERE id = ?1
+                "#,
+                [download.media_id],
+                map_movie_row,
+            )?;
+            Ok(Target::Single(MediaInfo::Movie {
+                movie: Box::new(movie),
+                quality,
+            }))
+        }
+        MediaType::Episode => {
+            let episode = conn.query_row(
+                r#"
+                SELECT id, show_id, tmdb_id, season_number, episode_number, title,
+                       overview, air_date, runtime_minutes, still_path, status,
+                       monitored, file_path, file_size, created_at, updated_at
+                FROM episodes WHERE id = ?1
+                "#,
+                [download.media_id],
+                map_episode_row,
+            )?;
+            let show = conn.query_row(
+                r#"
+                SELECT id, tmdb_id, imdb_id, title, original_title, year_start,
+                       year_end, overview, poster_path, backdrop_path, status,
+                       monitored, quality_limit, added_at, updated_at, added_by, quality_profile_id,
+                       tvdb_id
+                FROM tv_shows WHERE id = ?1
+                "#,
+                [episode.show_id],
+                map_show_row,
+            )?;
+            Ok(Target::Single(MediaInfo::Episode {
+                show: Box::new(show),
+                episode: Box::new(episode),
+                quality,
+            }))
+        }
+        MediaType::Album => {
+            let album = load_album(conn, download.media_id)?;
+            let artist = load_artist(conn, album.artist_id)?;
+            let tracks = load_tracks(conn, album.id)?;
+            Ok(Target::Album {
+                artist: Box::new(artist),
+                album: Box::new(album),
+                tracks,
+            })
+        }
+        MediaType::Track => {
+            let track = conn.query_row(
+                r#"
+                SELECT id, mbid, album_id, artist_id, title, track_number, disc_number,
+                       duration_ms, status, monitored, file_path, file_size, audio_format,
+                       bitrate, sample_rate, bit_depth, created_at, updated_at
+                FROM tracks WHERE id = ?1
+                "#,
+                [download.media_id],
+                map_track_row,
+            )?;
+            let album = load_album(conn, track.album_id)?;
+            let artist = load_artist(conn, track.artist_id.unwrap_or(album.artist_id))?;
+            Ok(Target::Single(MediaInfo::Track {
+                artist: Box::new(artist),
+                album: Box::new(album),
+                track: Box::new(track),
+            }))
+        }
+    }
+}
+
+fn load_album(conn: &Connection, album_id: i64) -> Result<Album> {
+    Ok(conn.query_row(
+        r#"
+        SELECT id, mbid, artist_id, title, album_type, release_date, overview,
+               cover_path, total_tracks, status, monitored, quality_limit,
+               added_at, updated_at
+        FROM albums WHERE id = ?1
+        "#,
+        [album_id],
+        map_album_row,
+    )?)
+}
+
+fn load_artist(conn: &Connection, artist_id: i64) -> Result<Artist> {
+    Ok(conn.query_row(
+        r#"
+        SELECT id, mbid, name, sort_name, disambiguation, artist_type, country,
+               begin_date, end_date, overview, image_path, monitored, quality_limit,
+               added_at, updated_at, added_by, quality_profile_id
+        FROM artists WHERE id = ?1
+        "#,
+        [artist_id],
+        map_artist_row,
+    )?)
+}
+
+fn load_tracks(conn: &Connection, album_id: i64) -> Result<Vec<Track>> {
+    let mut stmt = conn.prepare(
+        r#"
+        SELECT id, mbid, album_id, artist_id, title, track_number, disc_number,
+               duration_ms, status, monitored, file_path, file_size, audio_format,
+               bitrate, sample_rate, bit_depth, created_at, updated_at
+        FROM tracks WHERE album_id = ?1
+        ORDER BY disc_number, track_number
+        "#,
+    )?;
+    let tracks = stmt
+        .query_map([album_id], map_track_row)?
+        .collect::<std::result::Result<Vec<_>, _>>()?;
+    Ok(tracks)
+}
+
+/// Match an audio file to a track by its leading number, falling back to the title.
+fn match_track<'a>(file: &Path, tracks: &'a [Track]) -> Option<&'a Track> {
+    let stem = file.file_stem()?.to_str()?.trim();
+
+    if let Some(caps) = TRACK_NUMBER_REGEX.captures(stem) {
+        let disc = caps.get(1).and_then(|m| m.as_str().parse::<i32>().ok());
+        if let Some(number) = caps.get(2).and_then(|m| m.as_str().parse::<i32>().ok()) {
+            let by_number = tracks
+                .iter()
+                .find(|t| t.track_number == number && disc.is_none_or(|d| t.disc_number == d));
+            if by_number.is_some() {
+                return by_number;
+            }
+        }
+    }
+
+    // Prefer the longest title so "Intro" doesn't shadow "Intro (Reprise)"
+    let stem = stem.to_lowercase();
+    tracks
+        .iter()
+        .filter(|t| !t.title.is_empty() && stem.contains(&t.title.to_lowercase()))
+        .max_by_key(|t| t.title.len())
+}
+
+/// Quality of a download, from its torrent or file name.
+fn file_quality(media_type: MediaType, name: &str) -> Option<QualityItem> {
+    let profile_type = match media_type {
+        MediaType::Movie | MediaType::Episode => ProfileMediaType::Video,
+        MediaType::Album | MediaType::Track => ProfileMediaType::Music,
+    };
+    QualityItem::of_release(name, profile_type)
+}
+
+/// Files already in the library for a download's media, with the track each
+/// belongs to for albums.
+fn current_files(
+    conn: &Connection,
+    download: &PendingDownload,
+) -> Result<Vec<(Option<i64>, PathBuf)>> {
+    let files: Vec<(Option<i64>, Option<String>)> = match download.media_type {
+        MediaType::Album => load_tracks(conn, download.media_id)?
+            .into_iter()
+            .map(|track| (Some(track.id), track.file_path))
+            .collect(),
+        media_type => {
+            let path = conn.query_row(
+                &format!(
+                    "SELECT file_path FROM {} WHERE id = ?1",
+                    media_table(media_type)
+                ),
+                [download.media_id],
+                |row| row.get(0),
+            )?;
+            // Stored like the processed files of a single item, without a track
+            vec![(None, path)]
+        }
+    };
+
+    Ok(files
+        .into_iter()
+        .filter_map(|(track_id, path)| path.map(|p| (track_id, PathBuf::from(p))))
+        .collect())
+}
+
+fn set_download_status(conn: &Connection, download_id: i64, status: &str) -> Result<()> {
+    conn.execute(
+        "UPDATE downloads SET status = ?1 WHERE id = ?2",
+        rusqlite::params![status, download_id],
+    )?;
+    Ok(())
+}
+
+fn media_table(media_type: MediaType) -> &'static str {
+    match media_type {
+        MediaType::Movie => "movies",
+        MediaType::Episode => "episodes",
+        MediaType::Album => "albums",
+        MediaType::Track => "tracks",
+    }
+}
+
+fn set_media_status(conn: &Connection, download: &PendingDownload, status: &str) -> Result<()> {
+    conn.execute(
+        &format!(
+            "UPDATE {} SET status = ?1, updated_at = datetime('now') WHERE id = ?2",
+            media_table(download.media_type)
+        ),
+        rusqlite::params![status, download.media_id],
+    )?;
+    Ok(())
+}
+
+/// Recompute an album's status from its tracks.
+fn refresh_album_status(conn: &Connection, album_id: i64) -> Result<()> {
+    let status = calculate_album_status(&load_tracks(conn, album_id)?);
+    conn.execute(
+        "UPDATE albums SET status = ?1, updated_at = datetime('now') WHERE id = ?2",
+        rusqlite::params![status.to_string(), album_id],
+    )?;
+    Ok(())
+}
+
+/// Record processed files and their quality on the media rows and complete
+/// the download.
+fn record_success(
+    conn: &Connection,
+    download: &PendingDownload,
+    path: &Path,
+    files: &[(Option<i64>, ProcessedFile)],
+    quality: Option<QualityItem>,
+) -> Result<()> {
+    let quality = quality.map(|q| q.to_string());
+
+    for (track_id, file) in files {
+        let destination = file.destination.to_string_lossy();
+        let (table, id) = match track_id {
+            Some(track_id) => ("tracks", *track_id),
+            None => (media_table(download.media_type), download.media_id),
+        };
+
+        if table == "tracks" {
+            let format = file
+                .destination
+                .extension()
+                .and_then(|e| e.to_str())
+                .map(|e| e.to_lowercase());
+            conn.execute(
+                r#"
+                UPDATE tracks SET file_path = ?1, file_size = ?2, audio_format = ?3,
+                       status = 'available', updated_at = datetime('now')
+                WHERE id = ?4
+                "#,
+                rusqlite::params![destination, file.size as i64, format, id],
+            )?;
+        } else {
+            conn.execute(
+                &format!(
+                    r#"
+                    UPDATE {} SET file_path = ?1, file_size = ?2, file_quality = ?3,
+                           status = 'available', updated_at = datetime('now')
+                    WHERE id = ?4
+                    "#,
+                    table
+                ),
+                rusqlite::params![destination, file.size as i64, quality, id],
+            )?;
+        }
+    }
+
+    match download.media_type {
+        MediaType::Album => {
+            conn.execute(
+                "UPDATE albums SET file_quality = ?1 WHERE id = ?2",
+                rusqlite::params![quality, download.media_id],
+            )?;
+            refresh_album_status(conn, download.media_id)?;
+        }
+        MediaType::Track => {
+            let album_id: i64 = conn.query_row(
+                "SELECT album_id FROM tracks WHERE id = ?1",
+                [download.media_id],
+                |row| row.get(0),
+            )?;
+            refresh_album_status(conn, album_id)?;
+        }
+        MediaType::Movie | MediaType::Episode => {}
+    }
+
+    // Moves that still have to delete the torrent's files
+    let delete_files = files.iter().any(|(_, f)| f.move_pending);
+    conn.execute(
+        r#"
+        UPDATE downloads SET status = 'completed', progress = 100.0,
+               error_message = NULL, completed_at = datetime('now'),
+               delete_files_on_removal = ?2, content_path = ?3
+        WHERE id = ?1
+        "#,
+        rusqlite::params![download.id, delete_files, path.to_string_lossy()],
+    )?;
+
+    Ok(())
+}
+
+/// Mark the download failed and make the media eligible for another search,
+/// keeping any file it already had.
+fn record_failure(conn: &Connection, download: &PendingDownload, error: &str) {
+    if let Err(e) = conn.execute(
+        "UPDATE downloads SET status = 'failed', error_message = ?1 WHERE id = ?2",
+        rusqlite::params![error, download.id],
+    ) {
+        tracing::error!(download_id = download.id, error = %e, "Failed to mark download failed");
+    }
+
+    let reset = match download.media_type {
+        MediaType::Album => refresh_album_status(conn, download.media_id),
+        _ => reset_media_status(conn, download.media_type, download.media_id),
+    };
+    if let Err(e) = reset {
+        tracing::error!(download_id = download.id, error = %e, "Failed to reset media status");
+    }
+
+    ActivityBuilder::new(
+        EventType::DownloadFailed,
+        format!("Post-processing failed: {}", error),
+    )
+    .media(&download.media_type.to_string(), download.media_id)
+    .download(download.id)
+    .log_sync(conn);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{MountConfig, MountType, StorageAction, StorageConfig, StorageRule};
+    use std::fs;
+    use std::path::PathBuf;
+    use tempfile::TempDir;
+
+    fn processor(conn: Connection, library: &Path) -> PostProcessor {
+        let storage = StorageManager::new(StorageConfig {
+            mounts: vec![MountConfig {
+                name: "library".to_string(),
+                mount_type: MountType::Local,
+                path: Some(library.to_path_buf()),
+                host: None,
+                share: None,
+                username: None,
+                password: None,
+                mount_point: None,
+                enabled: true,
+            }],
+            naming: Default::default(),
+            rules: vec![StorageRule {
+                action: StorageAction::Move,
+                destination: "library".to_string(),
+                media_types: Vec::new(),
+            }],
+        })
+        .unwrap();
+
+        PostProcessor::new(Arc::new(Mutex::new(conn)), Arc::new(storage))
+    }
+
+    fn query_status(conn: &Connection, sql: &str, id: i64) -> String {
+        conn.query_row(sql, [id], |row| row.get(0)).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_process_movie_download() {
+        let downloads = TempDir::new().unwrap();
+        let library = TempDir::new().unwrap();
+
+        let conn = crate::db::init_db_memory().unwrap();
+        conn.execute(
+            "INSERT INTO movies (tmdb_id, title, year, status) VALUES (1, 'Test Movie', 2024, 'downloading')",
+            [],
+        )
+        .unwrap();
+        let movie_id = conn.last_insert_rowid();
+        conn.execute(
+            "INSERT INTO downloads (source_type, source_id, name, media_type, media_id, source_uri, status) VALUES ('torrent', 'abc', 'Test Movie', 'movie', ?1, 'magnet:?xt=urn:btih:abc', 'downloading')",
+            [movie_id],
+        )
+        .unwrap();
+
+        let content = downloads.path().join("Test.Movie.2024.1080p.WEB-DL");
+        fs::create_dir_all(&content).unwrap();
+        fs::write(content.join("movie.mkv"), "feature video content").unwrap();
+        fs::write(content.join("sample.mkv"), "sample").unwrap();
+
+        let processor = processor(conn, library.path());
+        let files = processor.process("torrent", "abc", &content).await.unwrap();
+        assert_eq!(files.len(), 1);
+        assert!(files[0].destination.exists());
+        assert!(files[0]
+            .destination
+            .ends_with("movie/Test Movie (2024)/Test Movie (2024) - 1080p.mkv"));
+
+        let db = processor.db.lock().await;
+        let (status, file_path, file_size): (String, String, i64) = db
+            .query_row(
+                "SELECT status, file_path, file_size FROM movies WHERE id = ?1",
+                [movie_id],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(status, "available");
+        assert_eq!(PathBuf::from(file_path), files[0].destination);
+        assert_eq!(file_size, "feature video content".len() as i64);
+
+        let download_status = query_status(
+            &db,
+            "SELECT status FROM downloads WHERE media_id = ?1",
+            movie_id,
+        );
+        assert_eq!(download_status, "completed");
+
+        // The torrent keeps seeding from its file, which goes when the torrent does
+        assert!(content.join("movie.mkv").exists());
+        let (delete_files, content_path): (bool, String) = db
+            .query_row(
+                "SELECT delete_files_on_removal, content_path FROM downloads WHERE media_id = ?1",
+                [movie_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert!(delete_files);
+        assert_eq!(PathBuf::from(content_path), content);
+        drop(db);
+
+        let err = processor
+            .process("torrent", "abc", &content)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AppError::Conflict(_)));
+    }
+
+    #[tokio::test]
+    async fn test_process_skips_download_being_imported() {
+        let conn = crate::db::init_db_memory().unwrap();
+        conn.execute(
+            "INSERT INTO movies (tmdb_id, title, year, status) VALUES (1, 'Test Movie', 2024, 'processing')",
+            [],
+        )
+        .unwrap();
+        let movie_id = conn.last_insert_rowid();
+        conn.execute(
+            "INSERT INTO downloads (source_type, source_id, name, media_type, media_id, source_uri, status) VALUES ('torrent', 'abc', 'Test Movie', 'movie', ?1, 'magnet:?xt=urn:btih:abc', 'processing')",
+            [movie_id],
+        )
+        .unwrap();
+
+        let library = TempDir::new().unwrap();
+        let processor = processor(conn, library.path());
+        let err = processor
+            .process("torrent", "abc", Path::new("/nonexistent"))
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AppError::Conflict(_)));
+
+        // The import in progress is left alone
+        let db = processor.db.lock().await;
+        assert_eq!(
+            query_status(
+                &db,
+                "SELECT status FROM downloads WHERE media_id = ?1",
+                movie_id
+            ),
+            "processing"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_process_soulseek_download_moves_file() {
+        let downloads = TempDir::new().unwrap();
+        let library = TempDir::new().unwrap();
+
+        let conn = crate::db::init_db_memory().unwrap();
+        conn.execute(
+            "INSERT INTO movies (tmdb_id, title, year, status) VALUES (1, 'Test Movie', 2024, 'downloading')",
+            [],
+        )
+        .unwrap();
+        let movie_id = conn.last_insert_rowid();
+        conn.execute(
+            "INSERT INTO downloads (source_type, source_id, name, media_type, media_id, source_uri, status) VALUES ('soulseek', 'transfer-1', 'Test Movie', 'movie', ?1, 'soulseek://user/movie.mkv', 'downloading')",
+            [movie_id],
+        )
+        .unwrap();
+
+        let file = downloads.path().join("movie.mkv");
+        fs::write(&file, "feature video content").unwrap();
+
+        let processor = processor(conn, library.path());
+        let files = processor
+            .process("soulseek", "transfer-1", &file)
+            .await
+            .unwrap();
+        assert!(files[0].destination.exists());
+        assert!(!files[0].move_pending);
+        assert!(!file.exists());
+    }
+
+    #[tokio::test]
+    async fn test_process_album_download_matches_tracks() {
+        let downloads = TempDir::new().unwrap();
+        let library = TempDir::new().unwrap();
+
+        let conn = crate::db::init_db_memory().unwrap();
+        conn.execute(
+            "INSERT INTO artists (mbid, name) VALUES ('artist-mbid', 'Artist')",
+            [],
+        )
+        .unwrap();
+        let artist_id = conn.last_insert_rowid();
+        conn.execute(
+            "INSERT INTO albums (mbid, artist_id, title, status) VALUES ('album-mbid', ?1, 'Album', 'downloading')",
+            [artist_id],
+        )
+        .unwrap();
+        let album_id = conn.last_insert_rowid();
+        for (number, title) in [(1, "First"), (2, "Second"), (3, "Third")] {
+            conn.execute(
+                "INSERT INTO tracks (album_id, artist_id, title, track_number) VALUES (?1, ?2, ?3, ?4)",
+                rusqlite::params![album_id, artist_id, title, number],
+            )
+            .unwrap();
+        }
+        conn.execute(
+            "INSERT INTO downloads (source_type, source_id, name, media_type, media_id, source_uri, status) VALUES ('torrent', 'def', 'Album', 'album', ?1, 'magnet:?xt=urn:btih:def', 'downloading')",
+            [album_id],
+        )
+        .unwrap();
+
+        let content = downloads.path().join("Artist - Album (2024) [FLAC]");
+        fs::create_dir_all(&content).unwrap();
+        fs::write(content.join("01 - First.flac"), "one").unwrap();
+        fs::write(content.join("02 - Second.flac"), "two").unwrap();
+
+        let processor = processor(conn, library.path());
+        let files = processor.process("torrent", "def", &content).await.unwrap();
+        assert_eq!(files.len(), 2);
+        assert!(library
+            .path()
+            .join("music/Artist/Album/Second.flac")
+            .exists());
+
+        let db = processor.db.lock().await;
+        let album_status = query_status(&db, "SELECT status FROM albums WHERE id = ?1", album_id);
+        assert_eq!(album_status, "partial");
+
+        let format: String = db
+            .query_row(
+                "SELECT audio_format FROM tracks WHERE album_id = ?1 AND track_number = 1",
+                [album_id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(format, "flac");
+    }
+
+    #[tokio::test]
+    async fn test_process_failure_marks_download_failed() {
+        let downloads = TempDir::new().unwrap();
+        let library = TempDir::new().unwrap();
+
+        let conn = crate::db::init_db_memory().unwrap();
+        conn.execute(
+            "INSERT INTO movies (tmdb_id, title, year, status) VALUES (1, 'Test Movie', 2024, 'downloading')",
+            [],
+        )
+        .unwrap();
+        let movie_id = conn.last_insert_rowid();
+        conn.execute(
+            "INSERT INTO downloads (source_type, source_id, name, media_type, media_id, source_uri, status) VALUES ('torrent', 'abc', 'Test Movie', 'movie', ?1, 'magnet:?xt=urn:btih:abc', 'downloading')",
+            [movie_id],
+        )
+        .unwrap();
+
+        // No video files in the download
+        let content = downloads.path().join("Test.Movie.2024.1080p");
+        fs::create_dir_all(&content).unwrap();
+        fs::write(content.join("readme.txt"), "nothing to see").unwrap();
+
+        let processor = processor(conn, library.path());
+        assert!(processor.process("torrent", "abc", &content).await.is_err());
+
+        let db = processor.db.lock().await;
+        assert_eq!(
+            query_status(&db, "SELECT status FROM movies WHERE id = ?1", movie_id),
+            "missing"
+        );
+        assert_eq!(
+            query_status(
+                &db,
+                "SELECT status FROM downloads WHERE media_id = ?1",
+                movie_id
+            ),
+            "failed"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_process_upgrade_replaces_file() {
+        let downloads = TempDir::new().unwrap();
+        let library = TempDir::new().unwrap();
+
+        let old_file = library
+            .path()
+            .join("movie/Test Movie (2024)/Test Movie (2024) - 720p.mkv");
+        fs::create_dir_all(old_file.parent().unwrap()).unwrap();
+        fs::write(&old_file, "old video").unwrap();
+
+        let conn = crate::db::init_db_memory().unwrap();
+        conn.execute(
+            "INSERT INTO movies (tmdb_id, title, year, status, file_path, file_quality) VALUES (1, 'Test Movie', 2024, 'downloading', ?1, '720p WEB-DL')",
+            [old_file.to_string_lossy()],
+        )
+        .unwrap();
+        let movie_id = conn.last_insert_rowid();
+        conn.execute(
+            "INSERT INTO downloads (source_type, source_id, name, media_type, media_id, source_uri, status) VALUES ('torrent', 'abc', 'Test Movie', 'movie', ?1, 'magnet:?xt=urn:btih:abc', 'downloading')",
+            [movie_id],
+        )
+        .unwrap();
+
+        let content = downloads.path().join("Test.Movie.2024.1080p.BluRay.x264");
+        fs::create_dir_all(&content).unwrap();
+        fs::write(content.join("movie.mkv"), "better video").unwrap();
+
+        let processor = processor(conn, library.path());
+        let files = processor.process("torrent", "abc", &content).await.unwrap();
+        assert!(files[0].destination.exists());
+        assert!(!old_file.exists());
+
+        let db = processor.db.lock().await;
+        let (status, quality): (String, String) = db
+            .query_row(
+                "SELECT status, file_quality FROM movies WHERE id = ?1",
+                [movie_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(status, "available");
+        assert_eq!(quality, "1080p BluRay");
+    }
+
+    #[tokio::test]
+    async fn test_failed_upgrade_keeps_file_available() {
+        let downloads = TempDir::new().unwrap();
+        let library = TempDir::new().unwrap();
+
+        let conn = crate::db::init_db_memory().unwrap();
+        conn.execute(
+            "INSERT INTO movies (tmdb_id, title, year, status, file_path) VALUES (1, 'Test Movie', 2024, 'downloading', '/library/movie.mkv')",
+            [],
+        )
+        .unwrap();
+        let movie_id = conn.last_insert_rowid();
+        conn.execute(
+            "INSERT INTO downloads (source_type, source_id, name, media_type, media_id, source_uri, status) VALUES ('torrent', 'abc', 'Test Movie', 'movie', ?1, 'magnet:?xt=urn:btih:abc', 'downloading')",
+            [movie_id],
+        )
+        .unwrap();
+
+        let content = downloads.path().join("Test.Movie.2024.1080p");
+        fs::create_dir_all(&content).unwrap();
+
+        let processor = processor(conn, library.path());
+        assert!(processor.process("torrent", "abc", &content).await.is_err());
+
+        let db = processor.db.lock().await;
+        assert_eq!(
+            query_status(&db, "SELECT status FROM movies WHERE id = ?1", movie_id),
+            "available"
+        );
+    }
+
+    #[test]
+    fn test_match_track() {
+        let track = |disc, number, title: &str| Track {
+            id: number as i64 + disc as i64 * 100,
+            mbid: None,
+            album_id: 1,
+            artist_id: None,
+            title: title.to_string(),
+            track_number: number,
+            disc_number: disc,
+            duration_ms: None,
+            status: crate::db::models::MediaStatus::Missing,
+            monitored: true,
+            file_path: None,
+            file_size: None,
+            audio_format: None,
+            bitrate: None,
+            sample_rate: None,
+            bit_depth: None,
+            created_at: String::new(),
+            updated_at: String::new(),
+        };
+        let tracks = vec![
+            track(1, 1, "Intro"),
+            track(1, 2, "Intro (Reprise)"),
+            track(2, 1, "Encore"),
+        ];
+
+        let matched = |name: &str| match_track(Path::new(name), &tracks).map(|t| t.id);
+        assert_eq!(matched("01 - Intro.flac"), Some(101));
+        assert_eq!(matched("2-01 Encore.flac"), Some(201));
+        assert_eq!(matched("Artist - Intro (Reprise).flac"), Some(102));
+        assert_eq!(matched("Bonus.flac"), None);
+    }
+}
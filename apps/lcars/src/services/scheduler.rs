@@ -3,25 +3,48 @@
 //! Manages scheduled tasks like searching for missing media, refreshing metadata,
 //! checking for new episodes/releases, and cleaning up completed downloads.
 
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
-use rusqlite::Connection;
-use tokio::sync::Mutex;
+use rusqlite::{Connection, OptionalExtension};
+use tokio::sync::{broadcast, Mutex};
 use tokio_cron_scheduler::{Job, JobScheduler, JobSchedulerError};
 
-use crate::config::SchedulerConfig;
+use crate::config::{Config, MusicReleaseConfig, SchedulerConfig, SearchConfig};
+use crate::db::models::{MediaType, ShowStatus};
 use crate::error::{AppError, Result};
-use crate::services::indexer::{MediaSearchType, SearchQuery};
-use crate::services::{IndexerManager, MusicBrainzClient, TmdbClient, TorrentEngine};
+use crate::services::activity::{ActivityBuilder, EventType};
+use crate::services::bandwidth::in_schedule_window;
+use crate::services::download_queue::{
+    reset_media_status, QueuePriority, QueueRequest, QueuedSource,
+};
+use crate::services::indexer::blocklist::block_download;
+use crate::services::indexer::parser::ParsedRelease;
+use crate::services::indexer::selection::{contains_title, same_title};
+use crate::services::indexer::{
+    parse_music_release, parse_release_name, select_best_release, BlocklistReason, FeedRelease,
+    MediaSearchType, ProfileMediaType, QualityItem, QualityProfile, Release, SearchQuery,
+    SelectionCriteria,
+};
+use crate::services::musicbrainz::MbReleaseGroup;
+use crate::services::tmdb::TmdbEpisode;
+use crate::services::torrent::{stall_message, MediaRef, TorrentEvent};
+use crate::services::torrent_source::TorrentSource;
+use crate::services::{
+    BandwidthManager, DownloadQueue, IndexerManager, MusicBrainzClient, TmdbClient, TorrentEngine,
+};
 
 /// Job execution context providing access to application services.
 #[derive(Clone)]
 pub struct JobContext {
+    pub config: Arc<Config>,
     pub db: Arc<Mutex<Connection>>,
     pub tmdb_client: Option<Arc<TmdbClient>>,
     pub musicbrainz_client: Option<Arc<MusicBrainzClient>>,
     pub indexer_manager: Arc<IndexerManager>,
     pub torrent_engine: Option<Arc<TorrentEngine>>,
+    pub bandwidth: Arc<BandwidthManager>,
+    pub download_queue: Arc<DownloadQueue>,
 }
 
 /// The scheduler service managing all background jobs.
@@ -48,7 +71,9 @@ impl Scheduler {
             .await?;
         Self::add_check_new_releases_job(&scheduler, &config.check_new_releases, ctx.clone())
             .await?;
-        Self::add_cleanup_completed_job(&scheduler, &config.cleanup_completed, ctx).await?;
+        Self::add_alt_speed_jobs(&scheduler, Arc::clone(&ctx.bandwidth)).await?;
+        Self::add_cleanup_completed_job(&scheduler, &config.cleanup_completed, ctx.clone()).await?;
+        Self::add_rss_sync_job(&scheduler, &config.rss_sync, ctx).await?;
 
         Ok(Self { scheduler })
     }
@@ -163,6 +188,56 @@ impl Scheduler {
         tracing::debug!(cron = cron, "Scheduled cleanup_completed job");
         Ok(())
     }
+
+    /// Add the RSS sync job.
+    async fn add_rss_sync_job(scheduler: &JobScheduler, cron: &str, ctx: JobContext) -> Result<()> {
+        let job = Job::new_async(cron, move |_uuid, _lock| {
+            let ctx = ctx.clone();
+            Box::pin(async move {
+                run_rss_sync_job(&ctx).await;
+            })
+        })
+        .map_err(map_scheduler_error)?;
+
+        scheduler.add(job).await.map_err(map_scheduler_error)?;
+        tracing::debug!(cron = cron, "Scheduled rss_sync job");
+        Ok(())
+    }
+
+    /// Add the jobs turning the alternate speed limits on and off, if scheduled.
+    ///
+    /// When starting inside the window, the alternate limits apply right away.
+    async fn add_alt_speed_jobs(
+        scheduler: &JobScheduler,
+        bandwidth: Arc<BandwidthManager>,
+    ) -> Result<()> {
+        let Some((start, end)) = bandwidth
+            .alt_speed_schedule()
+            .map(|(start, end)| (start.to_string(), end.to_string()))
+        else {
+            return Ok(());
+        };
+
+        for (cron, active) in [(&start, true), (&end, false)] {
+            let bandwidth = Arc::clone(&bandwidth);
+            let job = Job::new_async(cron.as_str(), move |_uuid, _lock| {
+                let bandwidth = Arc::clone(&bandwidth);
+                Box::pin(async move {
+                    bandwidth.set_alt_speed(active);
+                })
+            })
+            .map_err(map_scheduler_error)?;
+
+            scheduler.add(job).await.map_err(map_scheduler_error)?;
+        }
+
+        if in_schedule_window(&start, &end, chrono::Utc::now())? {
+            bandwidth.set_alt_speed(true);
+        }
+
+        tracing::debug!(start = %start, end = %end, "Scheduled alt_speed jobs");
+        Ok(())
+    }
 }
 
 /// Map JobSchedulerError to AppError.
@@ -178,164 +253,1021 @@ fn map_scheduler_error(e: JobSchedulerError) -> AppError {
 pub async fn run_search_missing_job(ctx: &JobContext) {
     tracing::info!("Running search_missing job");
 
+    let Some(engine) = &ctx.torrent_engine else {
+        tracing::warn!("Torrent engine not available, skipping search_missing job");
+        return;
+    };
+
     // Find monitored movies with status 'missing'
-    if let Err(e) = search_missing_movies(ctx).await {
+    if let Err(e) = search_missing_movies(ctx, engine).await {
         tracing::error!(error = %e, "Failed to search missing movies");
     }
 
     // Find monitored episodes with status 'missing'
-    if let Err(e) = search_missing_episodes(ctx).await {
+    if let Err(e) = search_missing_episodes(ctx, engine).await {
         tracing::error!(error = %e, "Failed to search missing episodes");
     }
 
     // Find monitored albums with status 'missing'
-    if let Err(e) = search_missing_albums(ctx).await {
+    if let Err(e) = search_missing_albums(ctx, engine).await {
         tracing::error!(error = %e, "Failed to search missing albums");
     }
 
     tracing::info!("search_missing job completed");
 }
 
-async fn search_missing_movies(ctx: &JobContext) -> Result<()> {
-    let movies: Vec<(i64, String, Option<i32>)> = {
-        let db = ctx.db.lock().await;
-        let mut stmt = db.prepare(
-            "SELECT id, title, year FROM movies WHERE status = 'missing' AND monitored = 1",
-        )?;
-        let result = stmt
-            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
-            .filter_map(|r| r.ok())
-            .collect();
-        result
-    };
+/// Quality settings of a wanted item and the file it already has, if any.
+struct WantedQuality {
+    quality_limit: Option<String>,
+    profile_id: Option<i64>,
+    runtime_minutes: Option<i64>,
+    /// Whether the item has a file, so this is an upgrade search
+    has_file: bool,
+    file_quality: Option<String>,
+    file_path: Option<String>,
+}
 
-    for (id, title, year) in movies {
-        let mut query = SearchQuery::new(&title).media_type(MediaSearchType::Movie);
+impl WantedQuality {
+    /// Read from six consecutive columns starting at `start`: quality limit,
+    /// profile id, runtime in minutes, whether a file exists, file quality
+    /// and file path.
+    fn from_row(row: &rusqlite::Row, start: usize) -> rusqlite::Result<Self> {
+        Ok(Self {
+            quality_limit: row.get(start)?,
+            profile_id: row.get(start + 1)?,
+            runtime_minutes: row.get(start + 2)?,
+            has_file: row.get(start + 3)?,
+            file_quality: row.get(start + 4)?,
+            file_path: row.get(start + 5)?,
+        })
+    }
 
-        if let Some(y) = year {
-            query = query.year(y);
+    /// Quality of the existing file: as recorded on import, or for files
+    /// imported before that was recorded, as named in the file name.
+    fn current(&self, media_type: ProfileMediaType) -> Option<QualityItem> {
+        if let Some(item) = self.file_quality.as_deref().and_then(QualityItem::parse) {
+            return Some(item);
         }
+        let path = std::path::Path::new(self.file_path.as_deref()?);
+        QualityItem::of_release(&path.file_name()?.to_string_lossy(), media_type)
+    }
 
-        tracing::debug!(movie_id = id, query = %title, "Searching for missing movie");
-
-        // Search indexers for this movie
-        match ctx.indexer_manager.search(&query).await {
-            Ok(results) if !results.is_empty() => {
-                tracing::info!(
-                    movie_id = id,
-                    title = %title,
-                    results = results.len(),
-                    "Found releases for missing movie"
-                );
-                // TODO: Implement automatic selection and download queueing
+    /// Apply the item's quality profile, or without one its quality limit.
+    ///
+    /// Returns `None` when the item has a file that needs no upgrade.
+    fn criteria(
+        &self,
+        mut criteria: SelectionCriteria,
+        profiles: &HashMap<i64, QualityProfile>,
+    ) -> Option<SelectionCriteria> {
+        let Some(profile) = self.profile_id.and_then(|id| profiles.get(&id)) else {
+            if self.has_file {
+                return None;
             }
-            Ok(_) => {
-                tracing::debug!(movie_id = id, title = %title, "No releases found");
+            if let Some(limit) = &self.quality_limit {
+                criteria = criteria.quality_limit(limit.clone());
             }
-            Err(e) => {
-                tracing::warn!(movie_id = id, error = %e, "Search failed for movie");
+            return Some(criteria);
+        };
+
+        if self.has_file {
+            let current = self.current(profile.media_type)?;
+            if profile.meets_cutoff(&current) {
+                return None;
             }
+            criteria = criteria.upgrade_from(current);
+        }
+        if let Some(runtime) = self.runtime_minutes.filter(|m| *m > 0) {
+            criteria = criteria.runtime(runtime as u32);
         }
+        Some(criteria.profile(profile.clone()))
+    }
+}
+
+/// Quality profiles by id.
+fn load_profiles(conn: &Connection) -> Result<HashMap<i64, QualityProfile>> {
+    Ok(QualityProfile::load_all(conn)?
+        .into_iter()
+        .map(|p| (p.id, p))
+        .collect())
+}
+
+/// A monitored movie that is missing or may be upgraded.
+struct WantedMovie {
+    id: i64,
+    title: String,
+    year: Option<i32>,
+    imdb_id: Option<String>,
+    tmdb_id: Option<i32>,
+    quality: WantedQuality,
+}
+
+impl WantedMovie {
+    fn criteria(
+        &self,
+        config: &SearchConfig,
+        profiles: &HashMap<i64, QualityProfile>,
+    ) -> Option<SelectionCriteria> {
+        let base = SelectionCriteria::new(MediaSearchType::Movie, config);
+        self.quality.criteria(base, profiles)
+    }
+}
+
+/// A monitored, aired episode that is missing or may be upgraded.
+struct WantedEpisode {
+    id: i64,
+    show_title: String,
+    imdb_id: Option<String>,
+    tmdb_id: Option<i32>,
+    tvdb_id: Option<i32>,
+    season: i32,
+    episode: i32,
+    title: Option<String>,
+    quality: WantedQuality,
+}
+
+impl WantedEpisode {
+    fn criteria(
+        &self,
+        config: &SearchConfig,
+        profiles: &HashMap<i64, QualityProfile>,
+    ) -> Option<SelectionCriteria> {
+        let base = SelectionCriteria::new(MediaSearchType::TvEpisode, config)
+            .episode(self.season, self.episode);
+        self.quality.criteria(base, profiles)
+    }
+
+    /// Name for the download: the episode title, or show and number.
+    fn name(&self) -> String {
+        self.title.clone().unwrap_or_else(|| {
+            format!(
+                "{} S{:02}E{:02}",
+                self.show_title, self.season, self.episode
+            )
+        })
+    }
+}
+
+/// A monitored album that is missing or may be upgraded.
+struct WantedAlbum {
+    id: i64,
+    artist: String,
+    title: String,
+    quality: WantedQuality,
+}
+
+impl WantedAlbum {
+    fn criteria(
+        &self,
+        config: &SearchConfig,
+        profiles: &HashMap<i64, QualityProfile>,
+    ) -> Option<SelectionCriteria> {
+        let base = SelectionCriteria::new(MediaSearchType::MusicAlbum, config).album(&self.title);
+        self.quality.criteria(base, profiles)
+    }
+}
+
+/// Monitored movies that are missing, or available with a quality profile.
+fn wanted_movies(conn: &Connection) -> Result<Vec<WantedMovie>> {
+    let mut stmt = conn.prepare(
+        r#"
+        SELECT id, title, year, imdb_id, tmdb_id,
+               quality_limit, quality_profile_id, runtime_minutes,
+               status = 'available', file_quality, file_path
+        FROM movies
+        WHERE monitored = 1
+          AND (status = 'missing' OR (status = 'available' AND quality_profile_id IS NOT NULL))
+        "#,
+    )?;
+    let movies = stmt
+        .query_map([], |row| {
+            Ok(WantedMovie {
+                id: row.get(0)?,
+                title: row.get(1)?,
+                year: row.get(2)?,
+                imdb_id: row.get(3)?,
+                tmdb_id: row.get(4)?,
+                quality: WantedQuality::from_row(row, 5)?,
+            })
+        })?
+        .filter_map(|r| r.ok())
+        .collect();
+    Ok(movies)
+}
+
+/// Monitored, aired episodes of monitored shows that are missing, or
+/// available and the show has a quality profile.
+fn wanted_episodes(conn: &Connection) -> Result<Vec<WantedEpisode>> {
+    let mut stmt = conn.prepare(
+        r#"
+        SELECT e.id, s.title, s.imdb_id, s.tmdb_id, e.season_number, e.episode_number,
+               e.title, s.quality_limit, s.quality_profile_id, e.runtime_minutes,
+               e.status = 'available', e.file_quality, e.file_path, s.tvdb_id
+        FROM episodes e
+        JOIN tv_shows s ON e.show_id = s.id
+        WHERE (e.status = 'missing'
+               OR (e.status = 'available' AND s.quality_profile_id IS NOT NULL))
+          AND e.monitored = 1 AND s.monitored = 1
+          AND (e.air_date IS NULL OR e.air_date <= date('now'))
+        ORDER BY s.id, e.season_number, e.episode_number
+        "#,
+    )?;
+    let episodes = stmt
+        .query_map([], |row| {
+            Ok(WantedEpisode {
+                id: row.get(0)?,
+                show_title: row.get(1)?,
+                imdb_id: row.get(2)?,
+                tmdb_id: row.get(3)?,
+                tvdb_id: row.get(13)?,
+                season: row.get(4)?,
+                episode: row.get(5)?,
+                title: row.get(6)?,
+                quality: WantedQuality::from_row(row, 7)?,
+            })
+        })?
+        .filter_map(|r| r.ok())
+        .collect();
+    Ok(episodes)
+}
+
+/// Monitored albums of monitored artists that are missing, or available and
+/// the artist has a quality profile.
+fn wanted_albums(conn: &Connection) -> Result<Vec<WantedAlbum>> {
+    // Albums imported before file_quality was recorded fall back to the
+    // format of their tracks
+    let mut stmt = conn.prepare(
+        r#"
+        SELECT al.id, ar.name, al.title, al.quality_limit, ar.quality_profile_id,
+               (SELECT SUM(duration_ms) / 60000 FROM tracks WHERE album_id = al.id),
+               al.status = 'available',
+               COALESCE(al.file_quality,
+                        (SELECT UPPER(audio_format) FROM tracks
+                         WHERE album_id = al.id AND audio_format IS NOT NULL LIMIT 1)),
+               NULL
+        FROM albums al
+        JOIN artists ar ON al.artist_id = ar.id
+        WHERE (al.status = 'missing'
+               OR (al.status = 'available' AND ar.quality_profile_id IS NOT NULL))
+          AND al.monitored = 1 AND ar.monitored = 1
+        "#,
+    )?;
+    let albums = stmt
+        .query_map([], |row| {
+            Ok(WantedAlbum {
+                id: row.get(0)?,
+                artist: row.get(1)?,
+                title: row.get(2)?,
+                quality: WantedQuality::from_row(row, 3)?,
+            })
+        })?
+        .filter_map(|r| r.ok())
+        .collect();
+    Ok(albums)
+}
+
+/// Search for missing movies, and for upgrades of movies whose file is
+/// below their quality profile's cutoff.
+async fn search_missing_movies(ctx: &JobContext, engine: &TorrentEngine) -> Result<()> {
+    let (profiles, movies) = {
+        let db = ctx.db.lock().await;
+        (load_profiles(&db)?, wanted_movies(&db)?)
+    };
+
+    for movie in movies {
+        search_movie(ctx, engine, &movie, &profiles).await;
     }
 
     Ok(())
 }
 
-async fn search_missing_episodes(ctx: &JobContext) -> Result<()> {
-    let episodes: Vec<(i64, String, i32, i32)> = {
+/// Search for a wanted movie and grab the best release.
+async fn search_movie(
+    ctx: &JobContext,
+    engine: &TorrentEngine,
+    movie: &WantedMovie,
+    profiles: &HashMap<i64, QualityProfile>,
+) {
+    let Some(criteria) = movie.criteria(&ctx.config.search, profiles) else {
+        return;
+    };
+    let (id, title) = (movie.id, &movie.title);
+
+    let mut query = SearchQuery::new(title).media_type(MediaSearchType::Movie);
+
+    if let Some(y) = movie.year {
+        query = query.year(y);
+    }
+
+    if let Some(ref imdb_id) = movie.imdb_id {
+        query = query.imdb_id(imdb_id);
+    }
+
+    if let Some(tmdb_id) = movie.tmdb_id {
+        query = query.tmdb_id(tmdb_id);
+    }
+
+    tracing::debug!(movie_id = id, query = %title, "Searching for missing movie");
+
+    // Search indexers for this movie
+    match ctx.indexer_manager.search(&query).await {
+        Ok(results) if !results.is_empty() => {
+            tracing::info!(
+                movie_id = id,
+                title = %title,
+                results = results.len(),
+                "Found releases for missing movie"
+            );
+
+            let media = MediaRef {
+                media_type: MediaType::Movie,
+                media_id: id,
+            };
+            grab_best_release(ctx, engine, media, title, &results, &criteria).await;
+        }
+        Ok(_) => {
+            tracing::debug!(movie_id = id, title = %title, "No releases found");
+        }
+        Err(e) => {
+            tracing::warn!(movie_id = id, error = %e, "Search failed for movie");
+        }
+    }
+}
+
+/// Search for missing episodes, and for upgrades of episodes whose file is
+/// below their show's quality profile cutoff.
+async fn search_missing_episodes(ctx: &JobContext, engine: &TorrentEngine) -> Result<()> {
+    let (profiles, episodes) = {
         let db = ctx.db.lock().await;
-        let mut stmt = db.prepare(
-            r#"
-            SELECT e.id, s.title, e.season_number, e.episode_number
-            FROM episodes e
-            JOIN shows s ON e.show_id = s.id
-            WHERE e.status = 'missing' AND s.monitored = 1
-            ORDER BY s.id, e.season_number, e.episode_number
-            "#,
-        )?;
-        let result = stmt
-            .query_map([], |row| {
-                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
-            })?
-            .filter_map(|r| r.ok())
-            .collect();
-        result
+        (load_profiles(&db)?, wanted_episodes(&db)?)
+    };
+
+    for wanted in episodes {
+        search_episode(ctx, engine, &wanted, &profiles).await;
+    }
+
+    Ok(())
+}
+
+/// Search for a wanted episode and grab the best release.
+async fn search_episode(
+    ctx: &JobContext,
+    engine: &TorrentEngine,
+    wanted: &WantedEpisode,
+    profiles: &HashMap<i64, QualityProfile>,
+) {
+    let Some(criteria) = wanted.criteria(&ctx.config.search, profiles) else {
+        return;
     };
+    let (id, show_title, season, episode) =
+        (wanted.id, &wanted.show_title, wanted.season, wanted.episode);
 
-    for (id, show_title, season, episode) in episodes {
-        let query = SearchQuery::new(&show_title)
-            .media_type(MediaSearchType::TvEpisode)
-            .episode(season, episode);
-
-        tracing::debug!(episode_id = id, show = %show_title, season, episode, "Searching for missing episode");
-
-        match ctx.indexer_manager.search(&query).await {
-            Ok(results) if !results.is_empty() => {
-                tracing::info!(
-                    episode_id = id,
-                    show = %show_title,
-                    season = season,
-                    episode = episode,
-                    results = results.len(),
-                    "Found releases for missing episode"
+    let mut query = SearchQuery::new(show_title)
+        .media_type(MediaSearchType::TvEpisode)
+        .episode(season, episode);
+
+    if let Some(ref imdb_id) = wanted.imdb_id {
+        query = query.imdb_id(imdb_id);
+    }
+
+    if let Some(tmdb_id) = wanted.tmdb_id {
+        query = query.tmdb_id(tmdb_id);
+    }
+
+    if let Some(tvdb_id) = wanted.tvdb_id {
+        query = query.tvdb_id(tvdb_id);
+    }
+
+    tracing::debug!(episode_id = id, show = %show_title, season, episode, "Searching for missing episode");
+
+    match ctx.indexer_manager.search(&query).await {
+        Ok(results) if !results.is_empty() => {
+            tracing::info!(
+                episode_id = id,
+                show = %show_title,
+                season = season,
+                episode = episode,
+                results = results.len(),
+                "Found releases for missing episode"
+            );
+
+            let media = MediaRef {
+                media_type: MediaType::Episode,
+                media_id: id,
+            };
+            grab_best_release(ctx, engine, media, &wanted.name(), &results, &criteria).await;
+        }
+        Ok(_) => {
+            tracing::debug!(episode_id = id, "No releases found");
+        }
+        Err(e) => {
+            tracing::warn!(episode_id = id, error = %e, "Search failed for episode");
+        }
+    }
+}
+
+/// Search for missing albums, and for upgrades of albums whose files are
+/// below their artist's quality profile cutoff.
+async fn search_missing_albums(ctx: &JobContext, engine: &TorrentEngine) -> Result<()> {
+    let (profiles, albums) = {
+        let db = ctx.db.lock().await;
+        (load_profiles(&db)?, wanted_albums(&db)?)
+    };
+
+    for album in albums {
+        search_album(ctx, engine, &album, &profiles).await;
+    }
+
+    Ok(())
+}
+
+/// Search for a wanted album and grab the best release.
+async fn search_album(
+    ctx: &JobContext,
+    engine: &TorrentEngine,
+    album: &WantedAlbum,
+    profiles: &HashMap<i64, QualityProfile>,
+) {
+    let Some(criteria) = album.criteria(&ctx.config.search, profiles) else {
+        return;
+    };
+    let (id, artist, album_title) = (album.id, &album.artist, &album.title);
+
+    // Combine artist and album into search query
+    let query = SearchQuery::new(format!("{} {}", artist, album_title))
+        .media_type(MediaSearchType::MusicAlbum)
+        .album(artist, album_title);
+
+    tracing::debug!(album_id = id, artist = %artist, album = %album_title, "Searching for missing album");
+
+    match ctx.indexer_manager.search(&query).await {
+        Ok(results) if !results.is_empty() => {
+            tracing::info!(
+                album_id = id,
+                artist = %artist,
+                album = %album_title,
+                results = results.len(),
+                "Found releases for missing album"
+            );
+
+            let media = MediaRef {
+                media_type: MediaType::Album,
+                media_id: id,
+            };
+            grab_best_release(ctx, engine, media, album_title, &results, &criteria).await;
+        }
+        Ok(_) => {
+            tracing::debug!(album_id = id, "No releases found");
+        }
+        Err(e) => {
+            tracing::warn!(album_id = id, error = %e, "Search failed for album");
+        }
+    }
+}
+
+/// Select the best release for a media item and queue it for download.
+///
+/// Releases that were already attempted for this item are skipped so a
+/// failed download is not grabbed again on the next run. Of season packs and
+/// discographies, only the files of the wanted episode or album are downloaded.
+async fn grab_best_release(
+    ctx: &JobContext,
+    engine: &TorrentEngine,
+    media: MediaRef,
+    name: &str,
+    releases: &[Release],
+    criteria: &SelectionCriteria,
+) {
+    let attempted: HashSet<String> = {
+        let db = ctx.db.lock().await;
+        match attempted_source_uris(&db, media.media_type, media.media_id) {
+            Ok(uris) => uris,
+            Err(e) => {
+                tracing::error!(error = %e, "Failed to load previous downloads");
+                return;
+            }
+        }
+    };
+
+    let candidates: Vec<Release> = releases
+        .iter()
+        .filter(|r| !attempted.contains(&r.magnet))
+        .cloned()
+        .collect();
+
+    let Some(release) = select_best_release(&candidates, criteria) else {
+        tracing::info!(
+            media_type = %media.media_type,
+            media_id = media.media_id,
+            "No release satisfies selection criteria"
+        );
+        return;
+    };
+
+    tracing::info!(
+        media_type = %media.media_type,
+        media_id = media.media_id,
+        release = %release.title,
+        indexer = %release.indexer,
+        seeders = release.seeders,
+        "Selected release for automatic download"
+    );
+
+    let Some(source) = TorrentSource::from_link(&release.magnet) else {
+        tracing::warn!(release = %release.title, "Release has no usable torrent link");
+        return;
+    };
+
+    let files = if criteria.needs_file_selection(release) {
+        match engine.list_files(&source).await {
+            Ok(listing) => match criteria.select_files(&listing.files) {
+                Some(files) => Some(files),
+                // Folder names of a music release may not name the album
+                None if media.media_type == MediaType::Album => None,
+                None => {
+                    tracing::info!(
+                        media_type = %media.media_type,
+                        media_id = media.media_id,
+                        release = %release.title,
+                        "Release does not contain the wanted episode"
+                    );
+                    return;
+                }
+            },
+            Err(e) => {
+                tracing::warn!(
+                    release = %release.title,
+                    error = %e,
+                    "Failed to list release files"
                 );
-                // TODO: Implement automatic selection and download queueing
+                return;
+            }
+        }
+    } else {
+        None
+    };
+
+    let queued = match ctx
+        .download_queue
+        .enqueue(QueueRequest {
+            name: name.to_string(),
+            release_title: Some(release.title.clone()),
+            media: media.clone(),
+            source: QueuedSource::Torrent {
+                source,
+                files: files.clone(),
+            },
+            priority: QueuePriority::Automatic,
+        })
+        .await
+    {
+        Ok(queued) => queued,
+        Err(e) => {
+            tracing::error!(
+                media_type = %media.media_type,
+                media_id = media.media_id,
+                error = %e,
+                "Failed to queue release"
+            );
+            return;
+        }
+    };
+
+    let db = ctx.db.lock().await;
+    ActivityBuilder::new(
+        EventType::DownloadStarted,
+        format!("Automatically grabbed {}", release.title),
+    )
+    .media(&media.media_type.to_string(), media.media_id)
+    .download(queued.id)
+    .metadata(&serde_json::json!({
+        "indexer": release.indexer,
+        "quality": release.quality,
+        "seeders": release.seeders,
+        "size_bytes": release.size_bytes,
+        "files": files,
+    }))
+    .log_sync(&db);
+
+    tracing::info!(
+        media_type = %media.media_type,
+        media_id = media.media_id,
+        download_id = queued.id,
+        status = %queued.status,
+        "Queued automatic download"
+    );
+}
+
+/// Source URIs of every download previously created for a media item.
+fn attempted_source_uris(
+    conn: &Connection,
+    media_type: MediaType,
+    media_id: i64,
+) -> Result<HashSet<String>> {
+    let mut stmt =
+        conn.prepare("SELECT source_uri FROM downloads WHERE media_type = ?1 AND media_id = ?2")?;
+    let uris = stmt
+        .query_map(rusqlite::params![media_type.to_string(), media_id], |row| {
+            row.get(0)
+        })?
+        .filter_map(|r| r.ok())
+        .collect();
+    Ok(uris)
+}
+
+/// Move failed torrents on to the next-best release.
+///
+/// When a torrent reports an error or stalls, its release is blocklisted,
+/// its media goes back to wanted and is searched for again right away. The
+/// blocklist keeps the failed release out of the results. Each failure is
+/// handled in its own task, so a slow search does not hold up the events.
+pub fn watch_failed_downloads(ctx: &JobContext) {
+    let Some(engine) = ctx.torrent_engine.clone() else {
+        return;
+    };
+    let ctx = ctx.clone();
+    let mut rx = engine.subscribe();
+    // Failures being handled, so a torrent reporting again is not handled twice
+    let handling: Arc<Mutex<HashSet<String>>> = Arc::new(Mutex::new(HashSet::new()));
+
+    tokio::spawn(async move {
+        loop {
+            let (info_hash, reason, message) = match rx.recv().await {
+                Ok(TorrentEvent::Error { info_hash, message }) => {
+                    (info_hash, BlocklistReason::Failed, message)
+                }
+                Ok(TorrentEvent::Stalled { info_hash, minutes }) => {
+                    (info_hash, BlocklistReason::Stalled, stall_message(minutes))
+                }
+                Ok(_) => continue,
+                Err(broadcast::error::RecvError::Lagged(n)) => {
+                    tracing::warn!(missed = n, "Torrent event receiver lagged, missed events");
+                    continue;
+                }
+                Err(broadcast::error::RecvError::Closed) => break,
+            };
+            if !handling.lock().await.insert(info_hash.clone()) {
+                continue;
             }
-            Ok(_) => {
-                tracing::debug!(episode_id = id, "No releases found");
+
+            // Searching again takes a while, keep receiving events meanwhile
+            let (ctx, engine, handling) = (ctx.clone(), Arc::clone(&engine), Arc::clone(&handling));
+            tokio::spawn(async move {
+                handle_failed_download(&ctx, &engine, &info_hash, reason, &message).await;
+                handling.lock().await.remove(&info_hash);
+            });
+        }
+    });
+}
+
+/// Blocklist a failed torrent and search for its media again.
+async fn handle_failed_download(
+    ctx: &JobContext,
+    engine: &TorrentEngine,
+    info_hash: &str,
+    reason: BlocklistReason,
+    message: &str,
+) {
+    let status = engine.get_status(info_hash).await.ok();
+    // Torrents failing once complete, while seeding, need no other release
+    if status.as_ref().is_some_and(|s| s.progress >= 100.0) {
+        return;
+    }
+
+    // A stalled torrent is still downloading, free its slot
+    if reason == BlocklistReason::Stalled {
+        if let Err(e) = engine.remove(info_hash, true).await {
+            tracing::warn!(info_hash = %info_hash, error = %e, "Failed to remove stalled torrent");
+        }
+    }
+
+    let media = {
+        let db = ctx.db.lock().await;
+        let torrent_name = status.as_ref().map(|s| s.name.as_str());
+        match fail_download(&db, info_hash, torrent_name, reason, message) {
+            Ok(Some(media)) => {
+                if let Err(e) = ctx.indexer_manager.reload_blocklist(&db) {
+                    tracing::error!(error = %e, "Failed to reload blocklist");
+                }
+                media
             }
+            Ok(None) => return,
             Err(e) => {
-                tracing::warn!(episode_id = id, error = %e, "Search failed for episode");
+                tracing::error!(info_hash = %info_hash, error = %e, "Failed to record failed download");
+                return;
             }
         }
-    }
+    };
 
-    Ok(())
+    if let Err(e) = search_media(ctx, engine, &media).await {
+        tracing::error!(
+            media_type = %media.media_type,
+            media_id = media.media_id,
+            error = %e,
+            "Failed to search for another release"
+        );
+    }
 }
 
-async fn search_missing_albums(ctx: &JobContext) -> Result<()> {
-    let albums: Vec<(i64, String, String)> = {
-        let db = ctx.db.lock().await;
-        let mut stmt = db.prepare(
+/// Record that a torrent download failed: mark it failed, blocklist its
+/// release and put its media back to wanted.
+///
+/// Returns the media of the download, or `None` when no download of the
+/// torrent is in progress.
+fn fail_download(
+    conn: &Connection,
+    info_hash: &str,
+    torrent_name: Option<&str>,
+    reason: BlocklistReason,
+    message: &str,
+) -> Result<Option<MediaRef>> {
+    // The download may already be marked failed by the torrent state sync
+    let download: Option<(i64, String, String, i64)> = conn
+        .query_row(
             r#"
-            SELECT al.id, ar.name, al.title
-            FROM albums al
-            JOIN artists ar ON al.artist_id = ar.id
-            WHERE al.status = 'missing' AND ar.monitored = 1
+            SELECT id, name, media_type, media_id FROM downloads
+            WHERE source_type = 'torrent' AND source_id = ?1
+              AND status IN ('downloading', 'paused', 'failed')
+            ORDER BY id DESC LIMIT 1
             "#,
-        )?;
-        let result = stmt
-            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
-            .filter_map(|r| r.ok())
-            .collect();
-        result
+            [info_hash],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )
+        .optional()?;
+    let Some((download_id, name, media_type, media_id)) = download else {
+        return Ok(None);
+    };
+    let media_type = match media_type.as_str() {
+        "movie" => MediaType::Movie,
+        "episode" => MediaType::Episode,
+        "album" => MediaType::Album,
+        "track" => MediaType::Track,
+        _ => return Ok(None),
     };
 
-    for (id, artist, album_title) in albums {
-        // Combine artist and album into search query
-        let query = SearchQuery::new(format!("{} {}", artist, album_title))
-            .media_type(MediaSearchType::MusicAlbum);
-
-        tracing::debug!(album_id = id, artist = %artist, album = %album_title, "Searching for missing album");
-
-        match ctx.indexer_manager.search(&query).await {
-            Ok(results) if !results.is_empty() => {
-                tracing::info!(
-                    album_id = id,
-                    artist = %artist,
-                    album = %album_title,
-                    results = results.len(),
-                    "Found releases for missing album"
-                );
-                // TODO: Implement automatic selection and download queueing
+    conn.execute(
+        "UPDATE downloads SET status = 'failed', error_message = ?1 WHERE id = ?2",
+        rusqlite::params![message, download_id],
+    )?;
+    block_download(conn, download_id, torrent_name, reason, Some(message))?;
+    reset_media_status(conn, media_type, media_id)?;
+
+    ActivityBuilder::new(
+        EventType::DownloadFailed,
+        format!(
+            "Download of {} failed and was blocklisted: {}",
+            name, message
+        ),
+    )
+    .media(&media_type.to_string(), media_id)
+    .download(download_id)
+    .metadata(&serde_json::json!({ "reason": reason }))
+    .log_sync(conn);
+
+    Ok(Some(MediaRef {
+        media_type,
+        media_id,
+    }))
+}
+
+/// Search for a media item again if it is still wanted.
+async fn search_media(ctx: &JobContext, engine: &TorrentEngine, media: &MediaRef) -> Result<()> {
+    let db = ctx.db.lock().await;
+    let profiles = load_profiles(&db)?;
+    match media.media_type {
+        MediaType::Movie => {
+            let movie = wanted_movies(&db)?
+                .into_iter()
+                .find(|m| m.id == media.media_id);
+            drop(db);
+            if let Some(movie) = movie {
+                search_movie(ctx, engine, &movie, &profiles).await;
             }
-            Ok(_) => {
-                tracing::debug!(album_id = id, "No releases found");
+        }
+        MediaType::Episode => {
+            let episode = wanted_episodes(&db)?
+                .into_iter()
+                .find(|e| e.id == media.media_id);
+            drop(db);
+            if let Some(episode) = episode {
+                search_episode(ctx, engine, &episode, &profiles).await;
             }
-            Err(e) => {
-                tracing::warn!(album_id = id, error = %e, "Search failed for album");
+        }
+        MediaType::Album => {
+            let album = wanted_albums(&db)?
+                .into_iter()
+                .find(|a| a.id == media.media_id);
+            drop(db);
+            if let Some(album) = album {
+                search_album(ctx, engine, &album, &profiles).await;
             }
         }
+        // Tracks are only grabbed with their album
+        MediaType::Track => {}
+    }
+    Ok(())
+}
+
+/// How long feed releases are remembered as seen.
+const RSS_SEEN_RETENTION_DAYS: u32 = 14;
+
+/// Grab wanted media from the newest releases of each indexer.
+///
+/// Catches new releases between `search_missing` runs without searching for
+/// every wanted item.
+pub async fn run_rss_sync_job(ctx: &JobContext) {
+    tracing::info!("Running rss_sync job");
+
+    let Some(engine) = &ctx.torrent_engine else {
+        tracing::warn!("Torrent engine not available, skipping rss_sync job");
+        return;
+    };
+
+    if let Err(e) = rss_sync(ctx, engine).await {
+        tracing::error!(error = %e, "Failed to sync indexer feeds");
+    }
+
+    tracing::info!("rss_sync job completed");
+}
+
+/// A new feed release with its title parsed as video and as music.
+struct FeedItem {
+    release: Release,
+    media_types: Vec<MediaSearchType>,
+    video: ParsedRelease,
+    music: ParsedRelease,
+}
+
+impl FeedItem {
+    fn new(feed: FeedRelease) -> Self {
+        Self {
+            video: parse_release_name(&feed.release.title),
+            music: parse_music_release(&feed.release.title),
+            release: feed.release,
+            media_types: feed.media_types,
+        }
+    }
+}
+
+/// Releases from indexers used for a media type that match a wanted item.
+fn feed_matches(
+    items: &[FeedItem],
+    media_type: MediaSearchType,
+    is_match: impl Fn(&FeedItem) -> bool,
+) -> Vec<Release> {
+    items
+        .iter()
+        .filter(|item| item.media_types.contains(&media_type) && is_match(item))
+        .map(|item| item.release.clone())
+        .collect()
+}
+
+/// Whether a release is of a movie: the same title, and the same year when
+/// the movie's is known.
+fn matches_movie(parsed: &ParsedRelease, title: &str, year: Option<i32>) -> bool {
+    parsed.season.is_none()
+        && same_title(&parsed.title, title)
+        && (year.is_none() || parsed.year == year)
+}
+
+/// Whether a release is of a single episode of a show. Season packs are
+/// left to `search_missing`, which can pick files out of them.
+fn matches_episode(parsed: &ParsedRelease, show_title: &str, season: i32, episode: i32) -> bool {
+    parsed.season == Some(season)
+        && parsed.episode == Some(episode)
+        && same_title(&parsed.title, show_title)
+}
+
+/// Whether a release is of an album: by the artist, naming the album.
+fn matches_album(parsed: &ParsedRelease, release_title: &str, artist: &str, album: &str) -> bool {
+    parsed
+        .artist
+        .as_deref()
+        .is_some_and(|a| same_title(a, artist))
+        && contains_title(release_title, album)
+}
+
+/// Drop feed releases seen by an earlier run and remember the rest.
+fn unseen_releases(conn: &Connection, feed: Vec<FeedRelease>) -> Result<Vec<FeedRelease>> {
+    conn.execute(
+        "DELETE FROM rss_seen WHERE seen_at < datetime('now', ?1)",
+        [format!("-{} days", RSS_SEEN_RETENTION_DAYS)],
+    )?;
+
+    let mut stmt =
+        conn.prepare("INSERT OR IGNORE INTO rss_seen (guid, indexer, title) VALUES (?1, ?2, ?3)")?;
+    let mut unseen = Vec::new();
+    for item in feed {
+        let inserted = stmt.execute(rusqlite::params![
+            item.release.id,
+            item.release.indexer,
+            item.release.title
+        ])?;
+        if inserted > 0 {
+            unseen.push(item);
+        }
+    }
+    Ok(unseen)
+}
+
+/// Match new feed releases against wanted movies, episodes and albums and
+/// grab the best match for each.
+async fn rss_sync(ctx: &JobContext, engine: &TorrentEngine) -> Result<()> {
+    let feed = ctx.indexer_manager.latest().await;
+
+    let (items, profiles, movies, episodes, albums) = {
+        let db = ctx.db.lock().await;
+        let items: Vec<FeedItem> = unseen_releases(&db, feed)?
+            .into_iter()
+            .map(FeedItem::new)
+            .collect();
+        if items.is_empty() {
+            tracing::debug!("No new releases in indexer feeds");
+            return Ok(());
+        }
+        (
+            items,
+            load_profiles(&db)?,
+            wanted_movies(&db)?,
+            wanted_episodes(&db)?,
+            wanted_albums(&db)?,
+        )
+    };
+
+    tracing::info!(releases = items.len(), "New releases in indexer feeds");
+
+    for movie in movies {
+        let matches = feed_matches(&items, MediaSearchType::Movie, |item| {
+            matches_movie(&item.video, &movie.title, movie.year)
+        });
+        if matches.is_empty() {
+            continue;
+        }
+        let Some(criteria) = movie.criteria(&ctx.config.search, &profiles) else {
+            continue;
+        };
+
+        tracing::info!(movie_id = movie.id, title = %movie.title, releases = matches.len(), "Feed has releases for movie");
+        let media = MediaRef {
+            media_type: MediaType::Movie,
+            media_id: movie.id,
+        };
+        grab_best_release(ctx, engine, media, &movie.title, &matches, &criteria).await;
+    }
+
+    for wanted in episodes {
+        let matches = feed_matches(&items, MediaSearchType::TvEpisode, |item| {
+            matches_episode(
+                &item.video,
+                &wanted.show_title,
+                wanted.season,
+                wanted.episode,
+            )
+        });
+        if matches.is_empty() {
+            continue;
+        }
+        let Some(criteria) = wanted.criteria(&ctx.config.search, &profiles) else {
+            continue;
+        };
+
+        tracing::info!(
+            episode_id = wanted.id,
+            show = %wanted.show_title,
+            season = wanted.season,
+            episode = wanted.episode,
+            releases = matches.len(),
+            "Feed has releases for episode"
+        );
+        let media = MediaRef {
+            media_type: MediaType::Episode,
+            media_id: wanted.id,
+        };
+        grab_best_release(ctx, engine, media, &wanted.name(), &matches, &criteria).await;
+    }
+
+    for album in albums {
+        let matches = feed_matches(&items, MediaSearchType::MusicAlbum, |item| {
+            matches_album(
+                &item.music,
+                &item.release.title,
+                &album.artist,
+                &album.title,
+            )
+        });
+        if matches.is_empty() {
+            continue;
+        }
+        let Some(criteria) = album.criteria(&ctx.config.search, &profiles) else {
+            continue;
+        };
+
+        tracing::info!(album_id = album.id, artist = %album.artist, album = %album.title, releases = matches.len(), "Feed has releases for album");
+        let media = MediaRef {
+            media_type: MediaType::Album,
+            media_id: album.id,
+        };
+        grab_best_release(ctx, engine, media, &album.title, &matches, &criteria).await;
     }
 
     Ok(())
@@ -369,7 +1301,7 @@ pub async fn run_refresh_metadata_job(ctx: &JobContext) {
     tracing::info!("refresh_metadata job completed");
 }
 
-async fn refresh_movie_metadata(ctx: &JobContext, _tmdb: &TmdbClient) -> Result<()> {
+async fn refresh_movie_metadata(ctx: &JobContext, tmdb: &TmdbClient) -> Result<()> {
     let movies: Vec<(i64, i64)> = {
         let db = ctx.db.lock().await;
         let mut stmt = db.prepare(
@@ -382,20 +1314,68 @@ async fn refresh_movie_metadata(ctx: &JobContext, _tmdb: &TmdbClient) -> Result<
         result
     };
 
-    for (id, _tmdb_id) in movies {
+    for (id, tmdb_id) in movies {
         tracing::debug!(movie_id = id, "Refreshing movie metadata");
-        // TODO: Call TMDB API to refresh metadata
-        // Update database with new metadata
+
+        let details = match tmdb.get_movie(tmdb_id as i32).await {
+            Ok(details) => details,
+            Err(e) => {
+                tracing::warn!(movie_id = id, tmdb_id = tmdb_id, error = %e, "Failed to fetch movie from TMDB");
+                continue;
+            }
+        };
+
+        let year = parse_year(details.release_date.as_deref());
+        let genres =
+            serde_json::to_string(&details.genres.iter().map(|g| &g.name).collect::<Vec<_>>()).ok();
+
+        let db = ctx.db.lock().await;
+        db.execute(
+            r#"
+            UPDATE movies SET
+                imdb_id = COALESCE(?1, imdb_id),
+                title = ?2,
+                original_title = ?3,
+                year = COALESCE(?4, year),
+                overview = ?5,
+                poster_path = ?6,
+                backdrop_path = ?7,
+                runtime_minutes = ?8,
+                genres = ?9,
+                updated_at = datetime('now')
+            WHERE id = ?10
+            "#,
+            rusqlite::params![
+                details.imdb_id,
+                details.title,
+                details.original_title,
+                year,
+                details.overview,
+                details.poster_path,
+                details.backdrop_path,
+                details.runtime,
+                genres,
+                id,
+            ],
+        )?;
+
+        ActivityBuilder::new(
+            EventType::MetadataRefreshed,
+            format!("Refreshed metadata for {}", details.title),
+        )
+        .media("movie", id)
+        .metadata(&serde_json::json!({ "source": "tmdb", "tmdb_id": tmdb_id }))
+        .log_sync(&db);
     }
 
     Ok(())
 }
 
-async fn refresh_show_metadata(ctx: &JobContext, _tmdb: &TmdbClient) -> Result<()> {
+async fn refresh_show_metadata(ctx: &JobContext, tmdb: &TmdbClient) -> Result<()> {
     let shows: Vec<(i64, i64)> = {
         let db = ctx.db.lock().await;
         let mut stmt = db.prepare(
-            "SELECT id, tmdb_id FROM shows WHERE tmdb_id IS NOT NULL ORDER BY updated_at ASC LIMIT 50",
+            "SELECT id, tmdb_id FROM tv_shows WHERE tmdb_id IS NOT NULL ORDER BY updated_at ASC LIMIT 50",
         )?;
         let result = stmt
             .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
@@ -404,20 +1384,83 @@ async fn refresh_show_metadata(ctx: &JobContext, _tmdb: &TmdbClient) -> Result<(
         result
     };
 
-    for (id, _tmdb_id) in shows {
+    for (id, tmdb_id) in shows {
         tracing::debug!(show_id = id, "Refreshing show metadata");
-        // TODO: Call TMDB API to refresh metadata
-        // Check for new seasons/episodes
+
+        let details = match tmdb.get_tv(tmdb_id as i32).await {
+            Ok(details) => details,
+            Err(e) => {
+                tracing::warn!(show_id = id, tmdb_id = tmdb_id, error = %e, "Failed to fetch show from TMDB");
+                continue;
+            }
+        };
+
+        let status = ShowStatus::from_tmdb(details.status.as_deref());
+        let imdb_id = details
+            .external_ids
+            .as_ref()
+            .and_then(|e| e.imdb_id.clone());
+        let tvdb_id = details.external_ids.as_ref().and_then(|e| e.tvdb_id);
+
+        let db = ctx.db.lock().await;
+        db.execute(
+            r#"
+            UPDATE tv_shows SET
+                imdb_id = COALESCE(?1, imdb_id),
+                tvdb_id = COALESCE(?11, tvdb_id),
+                title = ?2,
+                original_title = ?3,
+                year_start = COALESCE(?4, year_start),
+                year_end = ?5,
+                overview = ?6,
+                poster_path = ?7,
+                backdrop_path = ?8,
+                status = ?9,
+                updated_at = datetime('now')
+            WHERE id = ?10
+            "#,
+            rusqlite::params![
+                imdb_id,
+                details.name,
+                details.original_name,
+                parse_year(details.first_air_date.as_deref()),
+                // Only finished shows have an end year
+                match status {
+                    ShowStatus::Ended | ShowStatus::Canceled => {
+                        parse_year(details.last_air_date.as_deref())
+                    }
+                    _ => None,
+                },
+                details.overview,
+                details.poster_path,
+                details.backdrop_path,
+                status.to_string(),
+                id,
+                tvdb_id,
+            ],
+        )?;
+
+        ActivityBuilder::new(
+            EventType::MetadataRefreshed,
+            format!("Refreshed metadata for {}", details.name),
+        )
+        .media("show", id)
+        .metadata(&serde_json::json!({
+            "source": "tmdb",
+            "tmdb_id": tmdb_id,
+            "status": status.to_string(),
+        }))
+        .log_sync(&db);
     }
 
     Ok(())
 }
 
-async fn refresh_music_metadata(ctx: &JobContext, _mb: &MusicBrainzClient) -> Result<()> {
+async fn refresh_music_metadata(ctx: &JobContext, mb: &MusicBrainzClient) -> Result<()> {
     let artists: Vec<(i64, String)> = {
         let db = ctx.db.lock().await;
         let mut stmt = db.prepare(
-            "SELECT id, musicbrainz_id FROM artists WHERE musicbrainz_id IS NOT NULL ORDER BY updated_at ASC LIMIT 50",
+            "SELECT id, mbid FROM artists WHERE mbid IS NOT NULL ORDER BY updated_at ASC LIMIT 50",
         )?;
         let result = stmt
             .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
@@ -426,14 +1469,64 @@ async fn refresh_music_metadata(ctx: &JobContext, _mb: &MusicBrainzClient) -> Re
         result
     };
 
-    for (id, _mb_id) in artists {
+    for (id, mbid) in artists {
         tracing::debug!(artist_id = id, "Refreshing artist metadata");
-        // TODO: Call MusicBrainz API to refresh metadata
+
+        let details = match mb.get_artist(&mbid).await {
+            Ok(details) => details,
+            Err(e) => {
+                tracing::warn!(artist_id = id, mbid = %mbid, error = %e, "Failed to fetch artist from MusicBrainz");
+                continue;
+            }
+        };
+
+        let begin_date = details.life_span.as_ref().and_then(|ls| ls.begin.clone());
+        let end_date = details.life_span.as_ref().and_then(|ls| ls.end.clone());
+
+        let db = ctx.db.lock().await;
+        db.execute(
+            r#"
+            UPDATE artists SET
+                name = ?1,
+                sort_name = ?2,
+                disambiguation = ?3,
+                artist_type = ?4,
+                country = ?5,
+                begin_date = ?6,
+                end_date = ?7,
+                updated_at = datetime('now')
+            WHERE id = ?8
+            "#,
+            rusqlite::params![
+                details.name,
+                details.sort_name,
+                details.disambiguation,
+                details.artist_type,
+                details.country,
+                begin_date,
+                end_date,
+                id,
+            ],
+        )?;
+
+        ActivityBuilder::new(
+            EventType::MetadataRefreshed,
+            format!("Refreshed metadata for {}", details.name),
+        )
+        .media("artist", id)
+        .metadata(&serde_json::json!({ "source": "musicbrainz", "mbid": mbid }))
+        .log_sync(&db);
     }
 
     Ok(())
 }
 
+/// Extract the year from a `YYYY-MM-DD` date.
+fn parse_year(date: Option<&str>) -> Option<i32> {
+    date.and_then(|d| d.split('-').next())
+        .and_then(|y| y.parse().ok())
+}
+
 /// Check for new episodes of continuing TV shows.
 pub async fn run_check_new_episodes_job(ctx: &JobContext) {
     tracing::info!("Running check_new_episodes job");
@@ -450,29 +1543,209 @@ pub async fn run_check_new_episodes_job(ctx: &JobContext) {
     tracing::info!("check_new_episodes job completed");
 }
 
-async fn check_new_episodes(ctx: &JobContext, _tmdb: &TmdbClient) -> Result<()> {
-    let shows: Vec<(i64, String, i64)> = {
+async fn check_new_episodes(ctx: &JobContext, tmdb: &TmdbClient) -> Result<()> {
+    #[allow(clippy::type_complexity)]
+    let shows: Vec<(i64, String, i64, bool, Option<i32>)> = {
         let db = ctx.db.lock().await;
         let mut stmt = db.prepare(
-            "SELECT id, title, tmdb_id FROM shows WHERE status = 'continuing' AND monitored = 1 AND tmdb_id IS NOT NULL",
+            r#"
+            SELECT s.id, s.title, s.tmdb_id, s.monitored,
+                   (SELECT MAX(season_number) FROM episodes WHERE show_id = s.id AND season_number > 0)
+            FROM tv_shows s
+            WHERE s.status IN ('continuing', 'upcoming') AND s.monitored = 1 AND s.tmdb_id IS NOT NULL
+            "#,
         )?;
         let result = stmt
-            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .query_map([], |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                ))
+            })?
             .filter_map(|r| r.ok())
             .collect();
         result
     };
 
-    for (id, title, _tmdb_id) in shows {
+    for (id, title, tmdb_id, monitored, latest_season) in shows {
         tracing::debug!(show_id = id, title = %title, "Checking for new episodes");
-        // TODO: Query TMDB for show details
-        // Compare episodes with database
-        // Add new episodes with 'missing' status
+
+        let details = match tmdb.get_tv(tmdb_id as i32).await {
+            Ok(details) => details,
+            Err(e) => {
+                tracing::warn!(show_id = id, tmdb_id = tmdb_id, error = %e, "Failed to fetch show from TMDB");
+                continue;
+            }
+        };
+
+        // Only the latest known season and anything newer can gain episodes
+        let from_season = latest_season.unwrap_or(1);
+        let mut episodes = Vec::new();
+        for season in details
+            .seasons
+            .iter()
+            .filter(|s| s.season_number >= from_season)
+        {
+            match tmdb.get_season(tmdb_id as i32, season.season_number).await {
+                Ok(season) => episodes.extend(season.episodes),
+                Err(e) => {
+                    tracing::warn!(
+                        show_id = id,
+                        season = season.season_number,
+                        error = %e,
+                        "Failed to fetch season from TMDB"
+                    );
+                }
+            }
+        }
+
+        let status = ShowStatus::from_tmdb(details.status.as_deref());
+
+        let db = ctx.db.lock().await;
+        let added = sync_show_episodes(&db, id, monitored, &episodes)?;
+
+        if !added.is_empty() {
+            tracing::info!(show_id = id, title = %title, new_episodes = added.len(), "Found new episodes");
+            ActivityBuilder::new(
+                EventType::MediaAdded,
+                format!("Found {} new episode(s) of {}", added.len(), title),
+            )
+            .media("show", id)
+            .metadata(&serde_json::json!({
+                "episodes": added
+                    .iter()
+                    .map(|(s, e)| format!("S{:02}E{:02}", s, e))
+                    .collect::<Vec<_>>(),
+            }))
+            .log_sync(&db);
+        }
+
+        let ended = matches!(status, ShowStatus::Ended | ShowStatus::Canceled);
+        let changed = db.execute(
+            r#"
+            UPDATE tv_shows SET
+                status = ?1,
+                year_end = COALESCE(?2, year_end),
+                updated_at = datetime('now')
+            WHERE id = ?3 AND status != ?1
+            "#,
+            rusqlite::params![
+                status.to_string(),
+                if ended {
+                    parse_year(details.last_air_date.as_deref())
+                } else {
+                    None
+                },
+                id
+            ],
+        )?;
+
+        if changed > 0 {
+            tracing::info!(show_id = id, title = %title, status = %status, "Show status changed");
+            ActivityBuilder::new(
+                EventType::MediaUpdated,
+                format!("{} is now {}", title, status),
+            )
+            .media("show", id)
+            .metadata(&serde_json::json!({ "status": status.to_string() }))
+            .log_sync(&db);
+        }
     }
 
     Ok(())
 }
 
+/// Diff TMDB episodes against the database for a show.
+///
+/// Existing episodes get their title and air date updated; new ones are
+/// inserted as missing, monitored if their season is (or, for a new season,
+/// if the show is). Returns the (season, episode) numbers that were added.
+fn sync_show_episodes(
+    conn: &Connection,
+    show_id: i64,
+    show_monitored: bool,
+    episodes: &[TmdbEpisode],
+) -> Result<Vec<(i32, i32)>> {
+    let tx = conn.unchecked_transaction()?;
+
+    let existing: HashSet<(i32, i32)> = {
+        let mut stmt =
+            tx.prepare("SELECT season_number, episode_number FROM episodes WHERE show_id = ?1")?;
+        let result = stmt
+            .query_map([show_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<std::result::Result<_, _>>()?;
+        result
+    };
+
+    let season_monitored: HashMap<i32, bool> = {
+        let mut stmt = tx.prepare(
+            "SELECT season_number, MAX(monitored) FROM episodes WHERE show_id = ?1 GROUP BY season_number",
+        )?;
+        let result = stmt
+            .query_map([show_id], |row| Ok((row.get(0)?, row.get(1)?)))?
+            .collect::<std::result::Result<_, _>>()?;
+        result
+    };
+
+    let mut added = Vec::new();
+    for ep in episodes {
+        let key = (ep.season_number, ep.episode_number);
+
+        if existing.contains(&key) {
+            tx.execute(
+                r#"
+                UPDATE episodes SET
+                    title = ?1,
+                    air_date = ?2,
+                    updated_at = datetime('now')
+                WHERE show_id = ?3 AND season_number = ?4 AND episode_number = ?5
+                  AND (title IS NOT ?1 OR air_date IS NOT ?2)
+                "#,
+                rusqlite::params![
+                    ep.name,
+                    ep.air_date,
+                    show_id,
+                    ep.season_number,
+                    ep.episode_number
+                ],
+            )?;
+        } else {
+            let monitored = season_monitored
+                .get(&ep.season_number)
+                .copied()
+                .unwrap_or(show_monitored);
+
+            tx.execute(
+                r#"
+                INSERT INTO episodes (
+                    show_id, tmdb_id, season_number, episode_number, title,
+                    overview, air_date, runtime_minutes, still_path, status, monitored
+                ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, 'missing', ?10)
+                "#,
+                rusqlite::params![
+                    show_id,
+                    ep.id,
+                    ep.season_number,
+                    ep.episode_number,
+                    ep.name,
+                    ep.overview,
+                    ep.air_date,
+                    ep.runtime,
+                    ep.still_path,
+                    monitored,
+                ],
+            )?;
+            added.push(key);
+        }
+    }
+
+    tx.commit()?;
+    Ok(added)
+}
+
 /// Check for new album releases from monitored artists.
 pub async fn run_check_new_releases_job(ctx: &JobContext) {
     tracing::info!("Running check_new_releases job");
@@ -489,29 +1762,139 @@ pub async fn run_check_new_releases_job(ctx: &JobContext) {
     tracing::info!("check_new_releases job completed");
 }
 
-async fn check_new_releases(ctx: &JobContext, _mb: &MusicBrainzClient) -> Result<()> {
-    let artists: Vec<(i64, String, String)> = {
+async fn check_new_releases(ctx: &JobContext, mb: &MusicBrainzClient) -> Result<()> {
+    let filter = &ctx.config.music.releases;
+    let today = chrono::Utc::now().date_naive();
+    let cutoff = (today + chrono::Duration::days(i64::from(filter.future_cutoff_days)))
+        .format("%Y-%m-%d")
+        .to_string();
+
+    #[allow(clippy::type_complexity)]
+    let artists: Vec<(i64, String, String, Option<String>, String)> = {
         let db = ctx.db.lock().await;
         let mut stmt = db.prepare(
-            "SELECT id, name, musicbrainz_id FROM artists WHERE monitored = 1 AND musicbrainz_id IS NOT NULL",
+            "SELECT id, name, mbid, quality_limit, date(added_at) FROM artists WHERE monitored = 1 AND mbid IS NOT NULL",
         )?;
         let result = stmt
-            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))?
+            .query_map([], |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                ))
+            })?
             .filter_map(|r| r.ok())
             .collect();
         result
     };
 
-    for (id, name, _mb_id) in artists {
+    for (id, name, mbid, quality_limit, added_on) in artists {
         tracing::debug!(artist_id = id, name = %name, "Checking for new releases");
-        // TODO: Query MusicBrainz for artist release groups
-        // Compare with database
-        // Add new albums with 'missing' status
+
+        let release_groups = match mb.get_artist_releases(&mbid).await {
+            Ok(groups) => groups,
+            Err(e) => {
+                tracing::warn!(artist_id = id, mbid = %mbid, error = %e, "Failed to fetch artist releases");
+                continue;
+            }
+        };
+
+        let db = ctx.db.lock().await;
+        let existing: HashSet<String> = {
+            let mut stmt = db.prepare("SELECT mbid FROM albums WHERE artist_id = ?1")?;
+            let result = stmt
+                .query_map([id], |row| row.get(0))?
+                .filter_map(|r| r.ok())
+                .collect();
+            result
+        };
+
+        for rg in release_groups {
+            if existing.contains(&rg.id) || !accepts_release_group(filter, &rg, &added_on, &cutoff)
+            {
+                continue;
+            }
+
+            db.execute(
+                r#"
+                INSERT INTO albums (
+                    mbid, artist_id, title, album_type, release_date,
+                    status, monitored, quality_limit
+                ) VALUES (?1, ?2, ?3, ?4, ?5, 'missing', 1, ?6)
+                "#,
+                rusqlite::params![
+                    rg.id,
+                    id,
+                    rg.title,
+                    rg.primary_type,
+                    rg.first_release_date,
+                    quality_limit,
+                ],
+            )?;
+            let album_id = db.last_insert_rowid();
+
+            tracing::info!(artist_id = id, album_id = album_id, title = %rg.title, "Found new release");
+            ActivityBuilder::new(
+                EventType::MediaAdded,
+                format!("New release {} by {}", rg.title, name),
+            )
+            .media("album", album_id)
+            .metadata(&serde_json::json!({
+                "mbid": rg.id,
+                "artist_id": id,
+                "album_type": rg.primary_type,
+                "release_date": rg.first_release_date,
+            }))
+            .log_sync(&db);
+        }
     }
 
     Ok(())
 }
 
+/// Check whether a release group should be added for a monitored artist.
+///
+/// Only release groups dated on or after the day the artist was added are
+/// considered new, so the back catalogue excluded at import time is not
+/// pulled in. Undated and far-off announced releases are left for later runs.
+/// Dates are compared at the precision MusicBrainz provides (YYYY, YYYY-MM or
+/// YYYY-MM-DD).
+fn accepts_release_group(
+    filter: &MusicReleaseConfig,
+    rg: &MbReleaseGroup,
+    added_on: &str,
+    cutoff: &str,
+) -> bool {
+    let Some(primary_type) = rg.primary_type.as_deref() else {
+        return false;
+    };
+    if !filter
+        .primary_types
+        .iter()
+        .any(|t| t.eq_ignore_ascii_case(primary_type))
+    {
+        return false;
+    }
+
+    if rg.secondary_types.iter().any(|secondary| {
+        filter
+            .exclude_secondary_types
+            .iter()
+            .any(|t| t.eq_ignore_ascii_case(secondary))
+    }) {
+        return false;
+    }
+
+    let Some(date) = rg.first_release_date.as_deref().filter(|d| !d.is_empty()) else {
+        return false;
+    };
+    let prefix = |other: &str| other.get(..date.len()).unwrap_or(other).to_string();
+
+    date >= prefix(added_on).as_str() && date <= prefix(cutoff).as_str()
+}
+
 /// Clean up completed downloads that meet seeding requirements.
 pub async fn run_cleanup_completed_job(ctx: &JobContext) {
     tracing::info!("Running cleanup_completed job");
@@ -533,10 +1916,26 @@ async fn cleanup_completed_downloads(ctx: &JobContext, engine: &TorrentEngine) -
     let completed = engine.check_seeding_completion().await;
 
     for info_hash in completed {
-        tracing::info!(info_hash = %info_hash, "Removing completed torrent");
+        // Files imported with a move rule were copied while the torrent seeded,
+        // removing the torrent finishes the move
+        let download: Option<(String, bool)> = {
+            let db = ctx.db.lock().await;
+            db.query_row(
+                "SELECT status, delete_files_on_removal FROM downloads WHERE source_type = 'torrent' AND source_id = ?1",
+                [&info_hash],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?
+        };
+        if matches!(&download, Some((status, _)) if status == "processing") {
+            tracing::debug!(info_hash = %info_hash, "Torrent is still being imported, keeping it");
+            continue;
+        }
+        let delete_files = download.is_some_and(|(_, delete)| delete);
+
+        tracing::info!(info_hash = %info_hash, delete_files = delete_files, "Removing completed torrent");
 
-        // Remove torrent from engine (don't delete files - they've been processed)
-        if let Err(e) = engine.remove(&info_hash, false).await {
+        if let Err(e) = engine.remove(&info_hash, delete_files).await {
             tracing::error!(
                 info_hash = %info_hash,
                 error = %e,
@@ -548,7 +1947,12 @@ async fn cleanup_completed_downloads(ctx: &JobContext, engine: &TorrentEngine) -
         // Update download status in database
         let db = ctx.db.lock().await;
         if let Err(e) = db.execute(
-            "UPDATE downloads SET status = 'completed', completed_at = datetime('now') WHERE info_hash = ?",
+            r#"
+            UPDATE downloads SET status = 'completed',
+                   completed_at = COALESCE(completed_at, datetime('now')),
+                   removed_at = datetime('now')
+            WHERE source_type = 'torrent' AND source_id = ?
+            "#,
             [&info_hash],
         ) {
             tracing::error!(
@@ -566,10 +1970,691 @@ async fn cleanup_completed_downloads(ctx: &JobContext, engine: &TorrentEngine) -
 mod tests {
     use super::*;
 
+    async fn stub_server(router: axum::Router) -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+        format!("http://{}", addr)
+    }
+
+    fn test_context(
+        conn: Connection,
+        tmdb: Option<TmdbClient>,
+        mb: Option<MusicBrainzClient>,
+    ) -> JobContext {
+        let config = Arc::new(Config {
+            server: Default::default(),
+            database: Default::default(),
+            tmdb: Default::default(),
+            musicbrainz: Default::default(),
+            torrent: Default::default(),
+            soulseek: Default::default(),
+            bandwidth: Default::default(),
+            login: Default::default(),
+            storage: Default::default(),
+            scheduler: Default::default(),
+            search: Default::default(),
+            music: Default::default(),
+            wireguard: None,
+        });
+        let db = Arc::new(Mutex::new(conn));
+        let download_queue = DownloadQueue::new_shared(Arc::clone(&db), &config, None, None);
+        JobContext {
+            config,
+            db,
+            tmdb_client: tmdb.map(Arc::new),
+            musicbrainz_client: mb.map(Arc::new),
+            indexer_manager: IndexerManager::new_shared(),
+            torrent_engine: None,
+            bandwidth: BandwidthManager::new_shared(Default::default()),
+            download_queue,
+        }
+    }
+
     #[test]
     fn test_job_context_clone() {
         // JobContext must be Clone for use in async jobs
         fn assert_clone<T: Clone>() {}
         assert_clone::<JobContext>();
     }
+
+    #[tokio::test]
+    async fn test_refresh_metadata_updates_media() {
+        use axum::{extract::Query, routing::get, Json};
+        use serde_json::json;
+
+        let base_url = stub_server(
+            axum::Router::new()
+                .route(
+                    "/movie/603",
+                    get(|| async {
+                        Json(json!({
+                            "id": 603,
+                            "title": "The Matrix",
+                            "original_title": "The Matrix",
+                            "overview": "A hacker learns the truth.",
+                            "release_date": "1999-03-30",
+                            "poster_path": "/matrix.jpg",
+                            "backdrop_path": null,
+                            "vote_average": 8.2,
+                            "runtime": 136,
+                            "genres": [{"id": 28, "name": "Action"}],
+                            "imdb_id": "tt0133093",
+                            "status": "Released"
+                        }))
+                    }),
+                )
+                .route(
+                    "/tv/1399",
+                    get(|Query(params): Query<HashMap<String, String>>| async move {
+                        // External ids are only included when asked for
+                        let external_ids = (params.get("append_to_response").map(String::as_str)
+                            == Some("external_ids"))
+                        .then(|| json!({"imdb_id": "tt0944947", "tvdb_id": 121361}));
+                        Json(json!({
+                            "id": 1399,
+                            "name": "Game of Thrones",
+                            "original_name": "Game of Thrones",
+                            "overview": "Seven noble families fight.",
+                            "first_air_date": "2011-04-17",
+                            "last_air_date": "2019-05-19",
+                            "poster_path": "/got.jpg",
+                            "backdrop_path": null,
+                            "vote_average": 8.4,
+                            "genres": [],
+                            "status": "Ended",
+                            "seasons": [],
+                            "external_ids": external_ids
+                        }))
+                    }),
+                )
+                .route(
+                    "/artist/a74b1b7f",
+                    get(|| async {
+                        Json(json!({
+                            "id": "a74b1b7f",
+                            "name": "Portishead",
+                            "sort-name": "Portishead",
+                            "type": "Group",
+                            "country": "GB",
+                            "life-span": {"begin": "1991", "end": null, "ended": false},
+                            "release-groups": []
+                        }))
+                    }),
+                ),
+        )
+        .await;
+
+        let conn = crate::db::init_db_memory().unwrap();
+        conn.execute_batch(
+            r#"
+            INSERT INTO movies (tmdb_id, title, year) VALUES (603, 'Matrix', 1999);
+            INSERT INTO movies (tmdb_id, title, year) VALUES (404, 'Gone', 2000);
+            INSERT INTO tv_shows (tmdb_id, title, status) VALUES (1399, 'GoT', 'continuing');
+            INSERT INTO artists (mbid, name) VALUES ('a74b1b7f', 'portishead');
+            "#,
+        )
+        .unwrap();
+
+        let tmdb = TmdbClient::new("key".to_string())
+            .unwrap()
+            .with_base_url(&base_url);
+        let mb = MusicBrainzClient::new("lcars", "test", "test@example.com", 0)
+            .unwrap()
+            .with_base_url(&base_url);
+        let ctx = test_context(conn, Some(tmdb), Some(mb));
+
+        run_refresh_metadata_job(&ctx).await;
+
+        let db = ctx.db.lock().await;
+        let (title, runtime, genres, imdb_id): (String, i32, String, String) = db
+            .query_row(
+                "SELECT title, runtime_minutes, genres, imdb_id FROM movies WHERE tmdb_id = 603",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .unwrap();
+        assert_eq!(title, "The Matrix");
+        assert_eq!(runtime, 136);
+        assert_eq!(genres, r#"["Action"]"#);
+        assert_eq!(imdb_id, "tt0133093");
+
+        // Lookup failures leave the row untouched
+        let title: String = db
+            .query_row("SELECT title FROM movies WHERE tmdb_id = 404", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(title, "Gone");
+
+        let (title, status, year_end, tvdb_id): (String, String, i32, i32) = db
+            .query_row(
+                "SELECT title, status, year_end, tvdb_id FROM tv_shows WHERE tmdb_id = 1399",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .unwrap();
+        assert_eq!(title, "Game of Thrones");
+        assert_eq!(status, "ended");
+        assert_eq!(year_end, 2019);
+        assert_eq!(tvdb_id, 121361);
+
+        let (name, country, begin): (String, String, String) = db
+            .query_row(
+                "SELECT name, country, begin_date FROM artists WHERE mbid = 'a74b1b7f'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .unwrap();
+        assert_eq!(name, "Portishead");
+        assert_eq!(country, "GB");
+        assert_eq!(begin, "1991");
+
+        let refreshed: Vec<String> = db
+            .prepare(
+                "SELECT media_type FROM activity WHERE event_type = 'metadata_refreshed' ORDER BY id",
+            )
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<std::result::Result<_, _>>()
+            .unwrap();
+        assert_eq!(refreshed, vec!["movie", "show", "artist"]);
+    }
+
+    #[tokio::test]
+    async fn test_check_new_episodes_inserts_and_updates() {
+        use axum::{routing::get, Json};
+        use serde_json::json;
+
+        fn episode(season: i32, number: i32, name: &str, air_date: &str) -> serde_json::Value {
+            json!({
+                "id": season * 100 + number,
+                "name": name,
+                "overview": null,
+                "air_date": air_date,
+                "episode_number": number,
+                "season_number": season,
+                "still_path": null,
+                "vote_average": 0.0,
+                "runtime": 50
+            })
+        }
+
+        let base_url = stub_server(
+            axum::Router::new()
+                .route(
+                    "/tv/100",
+                    get(|| async {
+                        Json(json!({
+                            "id": 100,
+                            "name": "Severance",
+                            "original_name": "Severance",
+                            "overview": null,
+                            "first_air_date": "2022-02-18",
+                            "last_air_date": "2025-03-21",
+                            "poster_path": null,
+                            "backdrop_path": null,
+                            "vote_average": 8.4,
+                            "genres": [],
+                            "status": "Ended",
+                            "seasons": [
+                                {"id": 1, "name": "Season 1", "overview": null, "air_date": null,
+                                 "episode_count": 2, "poster_path": null, "season_number": 1},
+                                {"id": 2, "name": "Season 2", "overview": null, "air_date": null,
+                                 "episode_count": 1, "poster_path": null, "season_number": 2}
+                            ]
+                        }))
+                    }),
+                )
+                .route(
+                    "/tv/100/season/1",
+                    get(|| async {
+                        Json(json!({
+                            "id": 1, "name": "Season 1", "overview": null, "air_date": null,
+                            "poster_path": null, "season_number": 1,
+                            "episodes": [
+                                episode(1, 1, "Good News About Hell", "2022-02-18"),
+                                episode(1, 2, "Half Loop", "2022-02-18")
+                            ]
+                        }))
+                    }),
+                )
+                .route(
+                    "/tv/100/season/2",
+                    get(|| async {
+                        Json(json!({
+                            "id": 2, "name": "Season 2", "overview": null, "air_date": null,
+                            "poster_path": null, "season_number": 2,
+                            "episodes": [episode(2, 1, "Hello, Ms. Cobel", "2025-01-17")]
+                        }))
+                    }),
+                ),
+        )
+        .await;
+
+        let conn = crate::db::init_db_memory().unwrap();
+        conn.execute_batch(
+            r#"
+            INSERT INTO tv_shows (id, tmdb_id, title, status, monitored)
+                VALUES (1, 100, 'Severance', 'continuing', 1);
+            INSERT INTO episodes (show_id, season_number, episode_number, title, air_date, monitored)
+                VALUES (1, 1, 1, 'Episode 1', NULL, 0);
+            "#,
+        )
+        .unwrap();
+
+        let tmdb = TmdbClient::new("key".to_string())
+            .unwrap()
+            .with_base_url(&base_url);
+        let ctx = test_context(conn, Some(tmdb), None);
+
+        run_check_new_episodes_job(&ctx).await;
+
+        let db = ctx.db.lock().await;
+        let episodes: Vec<(i32, i32, String, String, String, bool)> = db
+            .prepare(
+                "SELECT season_number, episode_number, title, air_date, status, monitored
+                 FROM episodes WHERE show_id = 1 ORDER BY season_number, episode_number",
+            )
+            .unwrap()
+            .query_map([], |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                    row.get(5)?,
+                ))
+            })
+            .unwrap()
+            .collect::<std::result::Result<_, _>>()
+            .unwrap();
+
+        assert_eq!(episodes.len(), 3);
+        // Existing episode picks up the announced title and air date
+        assert_eq!(episodes[0].2, "Good News About Hell");
+        assert_eq!(episodes[0].3, "2022-02-18");
+        // New episode in an unmonitored season stays unmonitored
+        assert_eq!(
+            (episodes[1].1, episodes[1].4.as_str(), episodes[1].5),
+            (2, "missing", false)
+        );
+        // New season inherits the show's monitored flag
+        assert_eq!(
+            (episodes[2].0, episodes[2].4.as_str(), episodes[2].5),
+            (2, "missing", true)
+        );
+
+        let (status, year_end): (String, i32) = db
+            .query_row(
+                "SELECT status, year_end FROM tv_shows WHERE id = 1",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(status, "ended");
+        assert_eq!(year_end, 2025);
+
+        let events: Vec<String> = db
+            .prepare("SELECT event_type FROM activity ORDER BY id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<std::result::Result<_, _>>()
+            .unwrap();
+        assert_eq!(events, vec!["media_added", "media_updated"]);
+    }
+
+    fn release_group(
+        id: &str,
+        primary: Option<&str>,
+        secondary: &[&str],
+        date: Option<&str>,
+    ) -> MbReleaseGroup {
+        MbReleaseGroup {
+            id: id.to_string(),
+            title: id.to_string(),
+            primary_type: primary.map(String::from),
+            secondary_types: secondary.iter().map(|s| s.to_string()).collect(),
+            first_release_date: date.map(String::from),
+            artist_credit: Vec::new(),
+            score: None,
+        }
+    }
+
+    #[test]
+    fn test_wanted_quality_upgrades_below_cutoff() {
+        let conn = crate::db::init_db_memory().unwrap();
+        let profiles = load_profiles(&conn).unwrap();
+        let hd = profiles.values().find(|p| p.name == "HD").unwrap().id;
+        let base = || SelectionCriteria::new(MediaSearchType::Movie, &Default::default());
+        let wanted = |profile_id, has_file, file_quality: Option<&str>, file_path: Option<&str>| {
+            WantedQuality {
+                quality_limit: Some("1080p".to_string()),
+                profile_id,
+                runtime_minutes: Some(100),
+                has_file,
+                file_quality: file_quality.map(str::to_string),
+                file_path: file_path.map(str::to_string),
+            }
+        };
+
+        // Missing items are always searched
+        assert!(wanted(None, false, None, None)
+            .criteria(base(), &profiles)
+            .is_some());
+        assert!(wanted(Some(hd), false, None, None)
+            .criteria(base(), &profiles)
+            .is_some());
+
+        // Without a profile nothing is upgraded
+        let item = wanted(None, true, Some("720p HDTV"), None);
+        assert!(item.criteria(base(), &profiles).is_none());
+
+        // At or above the cutoff nothing is upgraded
+        let item = wanted(Some(hd), true, Some("1080p BluRay"), None);
+        assert!(item.criteria(base(), &profiles).is_none());
+
+        let item = wanted(Some(hd), true, Some("720p HDTV"), None);
+        assert!(item.criteria(base(), &profiles).is_some());
+
+        // Files imported before qualities were recorded use their name
+        let item = wanted(
+            Some(hd),
+            true,
+            None,
+            Some("/library/Movie (2024)/Movie.2024.1080p.BluRay.mkv"),
+        );
+        assert!(item.criteria(base(), &profiles).is_none());
+    }
+
+    #[test]
+    fn test_feed_release_matching() {
+        let movie = parse_release_name("Fight.Club.1999.1080p.BluRay.x264-GRP");
+        assert!(matches_movie(&movie, "Fight Club", Some(1999)));
+        assert!(matches_movie(&movie, "Fight Club", None));
+        assert!(!matches_movie(&movie, "Fight Club", Some(2024)));
+        assert!(!matches_movie(&movie, "Fight", Some(1999)));
+
+        let episode = parse_release_name("The.Expanse.S02E05.720p.WEB-DL");
+        assert!(matches_episode(&episode, "The Expanse", 2, 5));
+        assert!(!matches_episode(&episode, "The Expanse", 2, 6));
+        assert!(!matches_movie(&episode, "The Expanse", None));
+
+        // Season packs are left to search_missing
+        let pack = parse_release_name("The.Expanse.S02.720p.WEB-DL");
+        assert!(!matches_episode(&pack, "The Expanse", 2, 5));
+
+        let title = "Radiohead - OK Computer (1997) [FLAC]";
+        let album = parse_music_release(title);
+        assert!(matches_album(&album, title, "Radiohead", "OK Computer"));
+        assert!(!matches_album(&album, title, "Radiohead", "Kid A"));
+        assert!(!matches_album(&album, title, "Muse", "OK Computer"));
+    }
+
+    #[test]
+    fn test_fail_download_blocklists_release() {
+        let conn = crate::db::init_db_memory().unwrap();
+        conn.execute(
+            "INSERT INTO movies (tmdb_id, title, year, status) VALUES (603, 'The Matrix', 1999, 'downloading')",
+            [],
+        )
+        .unwrap();
+        let movie_id = conn.last_insert_rowid();
+        conn.execute(
+            "INSERT INTO downloads (source_type, source_id, name, media_type, media_id, source_uri, status, release_title) VALUES ('torrent', 'abc', 'The Matrix', 'movie', ?1, 'magnet:?xt=urn:btih:abc', 'downloading', 'The.Matrix.1999.1080p.BluRay')",
+            [movie_id],
+        )
+        .unwrap();
+        conn.execute(
+            "INSERT INTO downloads (source_type, source_id, name, media_type, media_id, source_uri, status) VALUES ('torrent', 'def', 'The Matrix', 'movie', ?1, 'magnet:?xt=urn:btih:def', 'seeding')",
+            [movie_id],
+        )
+        .unwrap();
+
+        let message = stall_message(60);
+        let media = fail_download(&conn, "abc", None, BlocklistReason::Stalled, &message)
+            .unwrap()
+            .unwrap();
+        assert_eq!(media.media_type, MediaType::Movie);
+        assert_eq!(media.media_id, movie_id);
+
+        let (status, error): (String, String) = conn
+            .query_row(
+                "SELECT status, error_message FROM downloads WHERE source_id = 'abc'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(status, "failed");
+        assert_eq!(error, "No progress for 60 minutes");
+
+        let movie_status: String = conn
+            .query_row(
+                "SELECT status FROM movies WHERE id = ?1",
+                [movie_id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(movie_status, "missing");
+
+        let (title, reason): (String, String) = conn
+            .query_row(
+                "SELECT title, reason FROM blocklist WHERE info_hash = 'abc'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(title, "The.Matrix.1999.1080p.BluRay");
+        assert_eq!(reason, "stalled");
+
+        // Finished torrents are left alone
+        assert!(
+            fail_download(&conn, "def", None, BlocklistReason::Failed, "boom")
+                .unwrap()
+                .is_none()
+        );
+        let blocked: i64 = conn
+            .query_row("SELECT COUNT(*) FROM blocklist", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(blocked, 1);
+    }
+
+    #[test]
+    fn test_unseen_releases_are_remembered() {
+        let conn = crate::db::init_db_memory().unwrap();
+        let feed_release = |title: &str| FeedRelease {
+            release: Release {
+                id: Release::generate_id("test", title, "magnet:?xt=urn:btih:abc"),
+                title: title.to_string(),
+                indexer: "test".to_string(),
+                magnet: "magnet:?xt=urn:btih:abc".to_string(),
+                info_hash: None,
+                size_bytes: 0,
+                seeders: 0,
+                leechers: 0,
+                quality: crate::services::indexer::Quality::Unknown,
+                source: crate::services::indexer::Source::Unknown,
+                codec: None,
+                audio: None,
+                group: None,
+                proper: false,
+                repack: false,
+                uploaded_at: None,
+            },
+            media_types: vec![MediaSearchType::Movie],
+        };
+
+        let unseen = unseen_releases(&conn, vec![feed_release("One"), feed_release("Two")]);
+        assert_eq!(unseen.unwrap().len(), 2);
+
+        let unseen = unseen_releases(&conn, vec![feed_release("Two"), feed_release("Three")]);
+        let titles: Vec<String> = unseen
+            .unwrap()
+            .into_iter()
+            .map(|f| f.release.title)
+            .collect();
+        assert_eq!(titles, vec!["Three"]);
+
+        // Old entries are forgotten
+        conn.execute(
+            "UPDATE rss_seen SET seen_at = datetime('now', '-30 days') WHERE title = 'One'",
+            [],
+        )
+        .unwrap();
+        let unseen = unseen_releases(&conn, vec![feed_release("One")]);
+        assert_eq!(unseen.unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_accepts_release_group() {
+        let filter = MusicReleaseConfig::default();
+        let accepts =
+            |rg: MbReleaseGroup| accepts_release_group(&filter, &rg, "2025-06-10", "2025-07-10");
+
+        assert!(accepts(release_group(
+            "a",
+            Some("Album"),
+            &[],
+            Some("2025-06-20")
+        )));
+        assert!(accepts(release_group(
+            "b",
+            Some("EP"),
+            &[],
+            Some("2025-06")
+        )));
+        assert!(accepts(release_group(
+            "c",
+            Some("Album"),
+            &[],
+            Some("2025")
+        )));
+
+        // Filtered types
+        assert!(!accepts(release_group(
+            "d",
+            Some("Single"),
+            &[],
+            Some("2025-06-20")
+        )));
+        assert!(!accepts(release_group(
+            "e",
+            Some("Album"),
+            &["Live"],
+            Some("2025-06-20")
+        )));
+        assert!(!accepts(release_group("f", None, &[], Some("2025-06-20"))));
+
+        // Back catalogue, undated and far-off announcements
+        assert!(!accepts(release_group(
+            "g",
+            Some("Album"),
+            &[],
+            Some("2019-01-01")
+        )));
+        assert!(!accepts(release_group("h", Some("Album"), &[], None)));
+        assert!(!accepts(release_group(
+            "i",
+            Some("Album"),
+            &[],
+            Some("2025-12-01")
+        )));
+
+        let filter = MusicReleaseConfig {
+            primary_types: vec!["single".to_string()],
+            exclude_secondary_types: Vec::new(),
+            future_cutoff_days: 0,
+        };
+        assert!(accepts_release_group(
+            &filter,
+            &release_group("j", Some("Single"), &["Remix"], Some("2025-06-10")),
+            "2025-06-10",
+            "2025-06-10",
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_check_new_releases_inserts_albums() {
+        use axum::{routing::get, Json};
+        use serde_json::json;
+
+        let today = chrono::Utc::now().date_naive();
+        let soon = (today + chrono::Duration::days(7)).to_string();
+        let later = (today + chrono::Duration::days(365)).to_string();
+
+        let base_url = stub_server(axum::Router::new().route(
+            "/release-group",
+            get(move || {
+                let soon = soon.clone();
+                let later = later.clone();
+                async move {
+                    Json(json!({
+                        "release-groups": [
+                            {"id": "known", "title": "Dummy", "primary-type": "Album",
+                             "secondary-types": [], "first-release-date": "1994-08-22"},
+                            {"id": "new", "title": "Fourth", "primary-type": "Album",
+                             "secondary-types": [], "first-release-date": soon},
+                            {"id": "live", "title": "Roseland", "primary-type": "Album",
+                             "secondary-types": ["Live"], "first-release-date": soon},
+                            {"id": "announced", "title": "Fifth", "primary-type": "Album",
+                             "secondary-types": [], "first-release-date": later}
+                        ]
+                    }))
+                }
+            }),
+        ))
+        .await;
+
+        let conn = crate::db::init_db_memory().unwrap();
+        conn.execute_batch(
+            r#"
+            INSERT INTO artists (id, mbid, name, monitored, quality_limit)
+                VALUES (1, 'a74b1b7f', 'Portishead', 1, 'flac');
+            INSERT INTO albums (mbid, artist_id, title) VALUES ('known', 1, 'Dummy');
+            "#,
+        )
+        .unwrap();
+
+        let mb = MusicBrainzClient::new("lcars", "test", "test@example.com", 0)
+            .unwrap()
+            .with_base_url(&base_url);
+        let ctx = test_context(conn, None, Some(mb));
+
+        run_check_new_releases_job(&ctx).await;
+
+        let db = ctx.db.lock().await;
+        let albums: Vec<(String, String, bool, String)> = db
+            .prepare(
+                "SELECT mbid, status, monitored, quality_limit FROM albums WHERE artist_id = 1 ORDER BY id",
+            )
+            .unwrap()
+            .query_map([], |row| {
+                Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+            })
+            .unwrap()
+            .collect::<std::result::Result<_, _>>()
+            .unwrap();
+        assert_eq!(albums.len(), 2);
+        assert_eq!(
+            albums[1],
+            (
+                "new".to_string(),
+                "missing".to_string(),
+                true,
+                "flac".to_string()
+            )
+        );
+
+        let added: i64 = db
+            .query_row(
+                "SELECT COUNT(*) FROM activity WHERE event_type = 'media_added' AND media_type = 'album'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(added, 1);
+    }
 }
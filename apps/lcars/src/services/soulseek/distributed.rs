@@ -0,0 +1,548 @@
+//! Participation in the Soulseek distributed search network.
+//!
+//! The server does not send searches to every client. Clients form a tree in
+//! which the server sends searches to branch roots, and every node forwards
+//! them to its children over distributed (`D`) connections. We join the tree
+//! as a leaf: when the server offers `PossibleParents` we connect to the first
+//! reachable candidate, report our branch level and root to the server, and
+//! answer the searches our parent forwards with matches from our share index.
+//! We never accept children of our own.
+
+use bytes::BytesMut;
+use rand::Rng;
+use soulseek_protocol::{
+    frame::ToBytes,
+    message_common::ConnectionType,
+    peers::{
+        connection::PeerConnectionMessage,
+        distributed::{search::SearchRequest, DistributedMessage},
+        p2p::{request::PeerRequest, search::SearchReply},
+    },
+    server::{peer::Peer, request::ServerRequest},
+    ProtocolMessage,
+};
+use std::io::Cursor;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt, BufWriter};
+use tokio::net::TcpStream;
+use tokio::sync::RwLock;
+
+use crate::error::{AppError, Result};
+
+use super::peer::PeerConnector;
+use super::shares::ShareIndex;
+use super::uploads::UploadQueue;
+
+/// Connection timeout for each parent candidate.
+const PARENT_CONNECT_TIMEOUT_SECS: u64 = 10;
+
+/// Maximum number of files sent in reply to a single distributed search.
+const MAX_SEARCH_RESULTS: usize = 100;
+
+/// Buffer size for reading from the parent connection.
+const READ_BUFFER_SIZE: usize = 65536;
+
+/// Our position in the distributed network.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum ParentState {
+    /// No parent: the server should send us candidates.
+    #[default]
+    None,
+    /// Trying the candidates from the last `PossibleParents`.
+    Connecting,
+    /// Connected to a parent.
+    Connected {
+        username: String,
+        /// Our level in the tree, one below our parent's.
+        branch_level: u32,
+        /// Root of the branch we are in.
+        branch_root: String,
+    },
+}
+
+/// Joins the distributed network and answers the searches it forwards.
+#[derive(Clone)]
+pub struct DistributedClient {
+    /// Opens connections to searching peers, and to the server.
+    pub connector: PeerConnector,
+    /// Files we answer searches from.
+    pub share_index: Arc<RwLock<ShareIndex>>,
+    /// Upload queue, used to report free slots and queue length.
+    pub upload_queue: Arc<RwLock<UploadQueue>>,
+    /// Our position in the distributed network, shared with the engine.
+    pub parent: Arc<RwLock<ParentState>>,
+}
+
+impl DistributedClient {
+    /// Tell the server we are looking for a parent.
+    ///
+    /// Until we have one we are the root of our own branch.
+    pub async fn announce_no_parent(&self) -> Result<()> {
+        self.send_request(ServerRequest::NoParents(true)).await?;
+        self.send_request(ServerRequest::BranchRoot(
+            self.connector.our_username.clone(),
+        ))
+        .await?;
+        self.send_request(ServerRequest::BranchLevel(0)).await
+    }
+
+    /// Connect to the first reachable candidate and serve it until it
+    /// disconnects, then ask the server for new candidates.
+    ///
+    /// This is a long-running task that should be spawned.
+    pub async fn run(self, candidates: Vec<Peer>) {
+        for candidate in candidates {
+            if candidate.username == self.connector.our_username {
+                continue;
+            }
+
+            let stream = match self.connect_parent(&candidate).await {
+                Ok(stream) => stream,
+                Err(e) => {
+                    tracing::debug!(
+                        username = %candidate.username,
+                        error = %e,
+                        "Failed to connect to parent candidate"
+                    );
+                    continue;
+                }
+            };
+
+            match self.serve_parent(&candidate.username, stream).await {
+                Ok(()) => {
+                    tracing::info!(username = %candidate.username, "Distributed parent disconnected")
+                }
+                Err(e) => {
+                    tracing::warn!(username = %candidate.username, error = %e, "Distributed parent connection failed")
+                }
+            }
+            break;
+        }
+
+        *self.parent.write().await = ParentState::None;
+        if let Err(e) = self.announce_no_parent().await {
+            tracing::debug!(error = %e, "Failed to ask the server for new parents");
+        }
+    }
+
+    /// Open a distributed connection to a parent candidate.
+    async fn connect_parent(&self, candidate: &Peer) -> Result<TcpStream> {
+        let addr = format!("{}:{}", candidate.ip, candidate.port);
+        let mut stream = tokio::time::timeout(
+            Duration::from_secs(PARENT_CONNECT_TIMEOUT_SECS),
+            TcpStream::connect(&addr),
+        )
+        .await
+        .map_err(|_| {
+            AppError::ServiceUnavailable(format!("Connection to {} timed out", candidate.username))
+        })?
+        .map_err(|e| {
+            AppError::ServiceUnavailable(format!(
+                "Failed to connect to {}: {}",
+                candidate.username, e
+            ))
+        })?;
+
+        let init = PeerConnectionMessage::PeerInit {
+            username: self.connector.our_username.clone(),
+            connection_type: ConnectionType::DistributedNetwork,
+            token: rand::thread_rng().gen(),
+        };
+        write_message(&mut stream, &init).await?;
+
+        Ok(stream)
+    }
+
+    /// Adopt `username` as our parent and handle its messages until it
+    /// disconnects.
+    async fn serve_parent(&self, username: &str, mut stream: TcpStream) -> Result<()> {
+        tracing::info!(username = %username, "Connected to distributed parent");
+
+        *self.parent.write().await = ParentState::Connected {
+            username: username.to_string(),
+            branch_level: 1,
+            branch_root: username.to_string(),
+        };
+        self.send_request(ServerRequest::NoParents(false)).await?;
+        self.send_request(ServerRequest::ChildDepth(0)).await?;
+        write_message(&mut stream, &DistributedMessage::ChildDepth(0)).await?;
+
+        let mut buffer = BytesMut::with_capacity(READ_BUFFER_SIZE);
+        loop {
+            while let Some(message) = try_parse_distributed_message(&mut buffer)? {
+                self.handle_parent_message(username, message).await?;
+            }
+
+            let n = stream
+                .read_buf(&mut buffer)
+                .await
+                .map_err(|e| AppError::Internal(format!("Failed to read from parent: {}", e)))?;
+            if n == 0 {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Handle a message from our parent.
+    async fn handle_parent_message(
+        &self,
+        username: &str,
+        message: DistributedMessage,
+    ) -> Result<()> {
+        match message {
+            DistributedMessage::BranchLevel(level) => {
+                let branch_level = level + 1;
+                if let ParentState::Connected {
+                    branch_level: ours, ..
+                } = &mut *self.parent.write().await
+                {
+                    *ours = branch_level;
+                }
+                self.send_request(ServerRequest::BranchLevel(branch_level))
+                    .await?;
+            }
+            DistributedMessage::BranchRoot(root) => {
+                if let ParentState::Connected { branch_root, .. } = &mut *self.parent.write().await
+                {
+                    *branch_root = root.clone();
+                }
+                self.send_request(ServerRequest::BranchRoot(root)).await?;
+            }
+            DistributedMessage::SearchRequest(request)
+            | DistributedMessage::ServerSearchRequest(request) => {
+                // Replying means connecting to the searcher, which may be slow
+                let client = self.clone();
+                tokio::spawn(async move { client.answer_search(request).await });
+            }
+            DistributedMessage::Ping => {
+                tracing::trace!(username = %username, "Distributed ping");
+            }
+            other => {
+                tracing::trace!(username = %username, message = ?other, "Unhandled distributed message");
+            }
+        }
+        Ok(())
+    }
+
+    /// Send the searcher the files in our share index that match its query.
+    pub async fn answer_search(&self, request: SearchRequest) {
+        if request.username == self.connector.our_username {
+            return;
+        }
+
+        let files = self
+            .share_index
+            .read()
+            .await
+            .search_reply_files(&request.query, MAX_SEARCH_RESULTS);
+        if files.is_empty() {
+            return;
+        }
+
+        let (slot_free, queue_length) = {
+            let queue = self.upload_queue.read().await;
+            (queue.has_free_slot(), queue.pending_count() as u32)
+        };
+
+        tracing::debug!(
+            username = %request.username,
+            ticket = request.ticket,
+            query = %request.query,
+            files = files.len(),
+            "Answering distributed search"
+        );
+
+        let reply = SearchReply {
+            username: self.connector.our_username.clone(),
+            ticket: request.ticket,
+            files,
+            slot_free,
+            average_speed: 0,
+            queue_length,
+            locked_results: vec![],
+        };
+
+        let result = async {
+            let (mut peer, _messages) = self.connector.connect(&request.username).await?;
+            peer.send(PeerRequest::SearchReply(reply)).await?;
+            peer.shutdown().await;
+            Ok::<_, AppError>(())
+        }
+        .await;
+
+        if let Err(e) = result {
+            tracing::debug!(
+                username = %request.username,
+                error = %e,
+                "Failed to send search results"
+            );
+        }
+    }
+
+    /// Send a request to the server.
+    async fn send_request(&self, request: ServerRequest) -> Result<()> {
+        let conn_guard = self.connector.connection.read().await;
+        let connection = conn_guard.as_ref().ok_or_else(|| {
+            AppError::ServiceUnavailable("Not connected to Soulseek server".to_string())
+        })?;
+        connection.send(request).await
+    }
+}
+
+/// Try to parse a complete distributed message from the buffer.
+fn try_parse_distributed_message(buffer: &mut BytesMut) -> Result<Option<DistributedMessage>> {
+    // Need at least 5 bytes for header (4 length + 1 code)
+    if buffer.len() < 5 {
+        return Ok(None);
+    }
+
+    let msg_len = u32::from_le_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]) as usize;
+    if buffer.len() < 4 + msg_len {
+        return Ok(None);
+    }
+
+    let msg_data = buffer.split_to(4 + msg_len);
+    let mut cursor = Cursor::new(msg_data.as_ref());
+    let header = DistributedMessage::check(&mut cursor)
+        .map_err(|e| AppError::Internal(format!("Invalid distributed message header: {}", e)))?;
+    DistributedMessage::parse(&mut cursor, &header)
+        .map(Some)
+        .map_err(|e| AppError::Internal(format!("Failed to parse distributed message: {}", e)))
+}
+
+/// Serialize a message and write it to the stream.
+async fn write_message(stream: &mut TcpStream, message: &(impl ToBytes + Sync)) -> Result<()> {
+    let mut writer = BufWriter::new(stream);
+    message
+        .write_to_buf(&mut writer)
+        .await
+        .map_err(|e| AppError::Internal(format!("Failed to write distributed message: {}", e)))?;
+    writer
+        .flush()
+        .await
+        .map_err(|e| AppError::Internal(format!("Failed to flush writer: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::soulseek::engine::SoulseekEngine;
+    use crate::services::soulseek::fake::{
+        engine_config, start_engine, FakePeer, FakeServer, PeerSession,
+    };
+    use soulseek_protocol::peers::p2p::response::PeerResponse;
+    use std::net::SocketAddr;
+    use tempfile::TempDir;
+
+    const HAVE_NO_PARENTS: u32 = 71;
+    const EMBEDDED_MESSAGE: u32 = 93;
+    const BRANCH_LEVEL: u32 = 126;
+    const BRANCH_ROOT: u32 = 127;
+
+    struct Harness {
+        server: FakeServer,
+        engine: Arc<SoulseekEngine>,
+        _share_dir: TempDir,
+        _download_dir: TempDir,
+    }
+
+    /// Start an engine sharing a single `artist - song.mp3`.
+    async fn harness() -> Harness {
+        let share_dir = TempDir::new().unwrap();
+        std::fs::write(share_dir.path().join("artist - song.mp3"), b"fake").unwrap();
+        let download_dir = TempDir::new().unwrap();
+
+        let server = FakeServer::start().await;
+        let mut config = engine_config(&server, download_dir.path());
+        config.sharing_enabled = true;
+        config.share_dirs = vec![share_dir.path().to_path_buf()];
+        let (engine, _) = start_engine(config).await;
+        engine.rebuild_share_index().await.unwrap();
+
+        Harness {
+            server,
+            engine,
+            _share_dir: share_dir,
+            _download_dir: download_dir,
+        }
+    }
+
+    fn search_request(username: &str, ticket: u32, query: &str) -> SearchRequest {
+        SearchRequest {
+            unknown: 0,
+            username: username.to_string(),
+            ticket,
+            query: query.to_string(),
+        }
+    }
+
+    /// Offer `parent` to the engine and accept its distributed connection.
+    async fn adopt(harness: &mut Harness, parent: &FakePeer) -> PeerSession {
+        harness.server.offer_parents(&[("parent", parent.addr())]);
+        let mut session = parent.accept().await;
+        assert_eq!(session.username, "lcars");
+        assert!(matches!(
+            session.connection_type,
+            ConnectionType::DistributedNetwork
+        ));
+        assert!(matches!(
+            session.recv_distributed().await,
+            DistributedMessage::ChildDepth(0)
+        ));
+        session
+    }
+
+    /// Wait for the searcher to receive our results.
+    async fn expect_reply(searcher: &FakePeer, ticket: u32) {
+        let mut session = searcher.accept().await;
+        assert_eq!(session.username, "lcars");
+        match session.recv().await {
+            PeerResponse::SearchReply(reply) => {
+                assert_eq!(reply.username, "lcars");
+                assert_eq!(reply.ticket, ticket);
+                assert_eq!(reply.files.len(), 1);
+                assert!(reply.files[0].name.ends_with("artist - song.mp3"));
+            }
+            other => panic!("expected SearchReply, got {:?}", other),
+        }
+    }
+
+    async fn searcher(server: &FakeServer) -> (FakePeer, SocketAddr) {
+        let searcher = FakePeer::bind().await;
+        let addr = searcher.addr();
+        server.add_peer("searcher", addr).await;
+        (searcher, addr)
+    }
+
+    #[tokio::test]
+    async fn test_login_announces_no_parent() {
+        let mut harness = harness().await;
+
+        assert_eq!(harness.server.next_request(HAVE_NO_PARENTS).await, [1]);
+        assert_eq!(
+            harness.server.next_request(BRANCH_LEVEL).await,
+            0u32.to_le_bytes()
+        );
+        assert_eq!(harness.engine.distributed_parent().await, ParentState::None);
+    }
+
+    #[tokio::test]
+    async fn test_reports_branch_from_parent() {
+        let mut harness = harness().await;
+        let parent = FakePeer::bind().await;
+        let mut session = adopt(&mut harness, &parent).await;
+
+        // The login announcement comes first
+        assert_eq!(harness.server.next_request(HAVE_NO_PARENTS).await, [1]);
+        assert_eq!(harness.server.next_request(HAVE_NO_PARENTS).await, [0]);
+
+        session
+            .send_distributed(DistributedMessage::BranchLevel(2))
+            .await;
+        session
+            .send_distributed(DistributedMessage::BranchRoot("root".to_string()))
+            .await;
+
+        assert_eq!(
+            harness.server.next_request(BRANCH_LEVEL).await,
+            3u32.to_le_bytes()
+        );
+        let root = harness.server.next_request(BRANCH_ROOT).await;
+        assert!(root.ends_with(b"root"));
+
+        assert_eq!(
+            harness.engine.distributed_parent().await,
+            ParentState::Connected {
+                username: "parent".to_string(),
+                branch_level: 3,
+                branch_root: "root".to_string(),
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_parent_disconnect_asks_for_new_parents() {
+        let mut harness = harness().await;
+        let parent = FakePeer::bind().await;
+        let session = adopt(&mut harness, &parent).await;
+
+        assert_eq!(harness.server.next_request(HAVE_NO_PARENTS).await, [1]);
+        assert_eq!(harness.server.next_request(HAVE_NO_PARENTS).await, [0]);
+
+        drop(session);
+
+        assert_eq!(harness.server.next_request(HAVE_NO_PARENTS).await, [1]);
+        assert_eq!(harness.engine.distributed_parent().await, ParentState::None);
+    }
+
+    #[tokio::test]
+    async fn test_unreachable_parents_ask_for_new_parents() {
+        let mut harness = harness().await;
+        let unreachable = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap()
+        };
+
+        harness.server.offer_parents(&[("gone", unreachable)]);
+
+        assert_eq!(harness.server.next_request(HAVE_NO_PARENTS).await, [1]);
+        assert_eq!(harness.server.next_request(HAVE_NO_PARENTS).await, [1]);
+    }
+
+    #[tokio::test]
+    async fn test_answers_search_from_parent() {
+        let mut harness = harness().await;
+        let (searcher, _) = searcher(&harness.server).await;
+        let parent = FakePeer::bind().await;
+        let mut session = adopt(&mut harness, &parent).await;
+
+        session
+            .send_distributed(DistributedMessage::SearchRequest(search_request(
+                "searcher", 7, "nothing",
+            )))
+            .await;
+        session
+            .send_distributed(DistributedMessage::SearchRequest(search_request(
+                "searcher", 42, "song",
+            )))
+            .await;
+
+        // Queries without matches get no reply
+        expect_reply(&searcher, 42).await;
+    }
+
+    #[tokio::test]
+    async fn test_answers_embedded_search() {
+        let harness = harness().await;
+        let (searcher, _) = searcher(&harness.server).await;
+
+        let request = search_request("searcher", 9, "artist song");
+        let mut body = vec![3];
+        body.extend_from_slice(&request.unknown.to_le_bytes());
+        body.extend_from_slice(&(request.username.len() as u32).to_le_bytes());
+        body.extend_from_slice(request.username.as_bytes());
+        body.extend_from_slice(&request.ticket.to_le_bytes());
+        body.extend_from_slice(&(request.query.len() as u32).to_le_bytes());
+        body.extend_from_slice(request.query.as_bytes());
+        harness.server.send(EMBEDDED_MESSAGE, &body);
+
+        expect_reply(&searcher, 9).await;
+    }
+
+    #[test]
+    fn test_parse_distributed_message_incomplete() {
+        let mut buffer = BytesMut::from(&[5u8, 0, 0, 0, 4, 1][..]);
+        assert!(try_parse_distributed_message(&mut buffer)
+            .unwrap()
+            .is_none());
+        assert_eq!(buffer.len(), 6);
+
+        buffer.extend_from_slice(&[0, 0, 0]);
+        assert!(matches!(
+            try_parse_distributed_message(&mut buffer).unwrap(),
+            Some(DistributedMessage::BranchLevel(1))
+        ));
+        assert!(buffer.is_empty());
+    }
+}
@@ -0,0 +1,881 @@
+//! Download handling for Soulseek file transfers.
+//!
+//! A download goes through three stages:
+//! 1. We connect to the uploader and send `QueueUpload`, then poll our place
+//!    in its queue with `PlaceInQueueRequest`.
+//! 2. When a slot frees up the uploader sends a `TransferRequest` (direction 1)
+//!    with a ticket, which we accept with a `TransferReply`.
+//! 3. The uploader opens a file (`F`) connection, sends the ticket, and we
+//!    answer with the offset to resume from before receiving the file bytes.
+//!
+//! The uploader may use our outgoing peer connection, open a new one to our
+//! listener, or have us connect back to it through the server, so transfer
+//! messages are routed to the waiting download task through [`TransferRoutes`].
+
+use bytes::BytesMut;
+use soulseek_protocol::peers::p2p::{
+    request::PeerRequest,
+    response::PeerResponse,
+    transfer::{PlaceInQueueRequest, QueueUpload, TransferReply},
+};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::sync::{broadcast, mpsc, RwLock};
+
+use crate::error::{AppError, Result};
+use crate::services::bandwidth::RateLimiter;
+
+use super::events::SoulseekEvent;
+use super::peer::PeerConnector;
+use super::types::{DownloadState, DownloadStatus};
+
+/// Interval between `PlaceInQueueRequest` polls while queued on the uploader.
+const QUEUE_POLL_INTERVAL_SECS: u64 = 60;
+
+/// Time the uploader has to open the file connection once we accepted a transfer.
+const FILE_CONNECTION_TIMEOUT_SECS: u64 = 60;
+
+/// Minimum interval between two progress events.
+const PROGRESS_INTERVAL_MS: u64 = 500;
+
+/// Buffer size for reading file data.
+const READ_BUFFER_SIZE: usize = 65536;
+
+/// Suffix of files that are still being downloaded.
+const PARTIAL_SUFFIX: &str = ".part";
+
+/// Reason sent back when the uploader offers a file we are not waiting for.
+const NOT_WAITING_REASON: &str = "Cancelled";
+
+/// A transfer update routed to a download task.
+#[derive(Debug)]
+pub enum TransferSignal {
+    /// The uploader is ready to send the file under this ticket.
+    Offered { ticket: u32, size: Option<u64> },
+    /// The uploader reported our place in its queue.
+    Queued(u32),
+    /// The uploader refused or aborted the transfer.
+    Failed(String),
+    /// The uploader opened the file connection for this transfer.
+    Connected(TcpStream, BytesMut),
+}
+
+/// Routes inbound transfer messages and file connections to download tasks.
+#[derive(Debug, Default)]
+pub struct TransferRoutes {
+    /// Download tasks waiting on a transfer, keyed by (username, filename).
+    waiting: HashMap<(String, String), mpsc::Sender<TransferSignal>>,
+    /// Tickets assigned by uploaders, mapped back to the remote filename.
+    tickets: HashMap<(String, u32), String>,
+}
+
+impl TransferRoutes {
+    /// Create an empty routing table.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register a download task waiting on `filename` from `username`.
+    pub fn register(&mut self, username: &str, filename: &str, tx: mpsc::Sender<TransferSignal>) {
+        self.waiting
+            .insert((username.to_string(), filename.to_string()), tx);
+    }
+
+    /// Remove a download task and any ticket assigned to it.
+    pub fn unregister(&mut self, username: &str, filename: &str) {
+        self.waiting
+            .remove(&(username.to_string(), filename.to_string()));
+        self.tickets
+            .retain(|(user, _), file| !(user == username && file == filename));
+    }
+
+    /// Get the task waiting on `filename` from `username`.
+    pub fn get(&self, username: &str, filename: &str) -> Option<mpsc::Sender<TransferSignal>> {
+        self.waiting
+            .get(&(username.to_string(), filename.to_string()))
+            .cloned()
+    }
+
+    /// Record the ticket the uploader assigned to `filename`.
+    ///
+    /// Returns the waiting task, or `None` if we are not downloading that file.
+    pub fn assign_ticket(
+        &mut self,
+        username: &str,
+        filename: &str,
+        ticket: u32,
+    ) -> Option<mpsc::Sender<TransferSignal>> {
+        let tx = self.get(username, filename)?;
+        self.tickets
+            .insert((username.to_string(), ticket), filename.to_string());
+        Some(tx)
+    }
+
+    /// Get the task expecting a file connection from `username` with `ticket`.
+    pub fn get_by_ticket(
+        &self,
+        username: &str,
+        ticket: u32,
+    ) -> Option<mpsc::Sender<TransferSignal>> {
+        let filename = self.tickets.get(&(username.to_string(), ticket))?;
+        self.get(username, filename)
+    }
+}
+
+/// Route a transfer-related peer message to the download it concerns.
+///
+/// Returns the reply to send back to the peer, if any. Messages that are not
+/// about downloads are ignored.
+pub async fn dispatch_peer_message(
+    routes: &RwLock<TransferRoutes>,
+    username: &str,
+    message: PeerResponse,
+) -> Option<PeerRequest> {
+    let (filename, signal) = match message {
+        PeerResponse::TransferRequest(request) if request.is_upload_request() => {
+            let ticket = request.ticket;
+            let tx = routes
+                .write()
+                .await
+                .assign_ticket(username, &request.filename, ticket);
+
+            let Some(tx) = tx else {
+                tracing::debug!(
+                    username = %username,
+                    filename = %request.filename,
+                    ticket = ticket,
+                    "Rejecting transfer for a file we are not downloading"
+                );
+                return Some(PeerRequest::TransferReply(
+                    TransferReply::TransferRejected {
+                        ticket,
+                        reason: NOT_WAITING_REASON.to_string(),
+                    },
+                ));
+            };
+
+            let _ = tx
+                .send(TransferSignal::Offered {
+                    ticket,
+                    size: request.file_size,
+                })
+                .await;
+            return Some(PeerRequest::TransferReply(TransferReply::TransferReplyOk {
+                ticket,
+                file_size: request.file_size.unwrap_or(0),
+            }));
+        }
+        PeerResponse::PlaceInQueueReply(reply) => {
+            (reply.filename, TransferSignal::Queued(reply.place))
+        }
+        PeerResponse::QueueFailed(failed) => {
+            (failed.filename, TransferSignal::Failed(failed.reason))
+        }
+        PeerResponse::UploadFailed(failed) => (
+            failed.filename,
+            TransferSignal::Failed("Upload failed on the remote peer".to_string()),
+        ),
+        _ => return None,
+    };
+
+    let tx = routes.read().await.get(username, &filename);
+    if let Some(tx) = tx {
+        let _ = tx.send(signal).await;
+    } else {
+        tracing::trace!(username = %username, filename = %filename, "Transfer message for unknown download");
+    }
+    None
+}
+
+/// Drives a single download from queueing to the completed file on disk.
+#[derive(Clone)]
+pub struct Downloader {
+    /// Directory where completed files are written.
+    pub download_dir: PathBuf,
+    /// Opens the peer connection to the uploader.
+    pub connector: PeerConnector,
+    /// Download states shared with the engine.
+    pub downloads: Arc<RwLock<HashMap<String, DownloadState>>>,
+    /// Routing table shared with the peer listener.
+    pub routes: Arc<RwLock<TransferRoutes>>,
+    /// Event broadcaster.
+    pub event_tx: broadcast::Sender<SoulseekEvent>,
+    /// Throttles downloads to the configured speed limit.
+    pub limiter: Arc<RateLimiter>,
+}
+
+impl Downloader {
+    /// Run the download identified by `id` to completion.
+    ///
+    /// This is a long-running task that should be spawned.
+    pub async fn run(self, id: String) {
+        let Some(download) = self.get(&id).await else {
+            return;
+        };
+
+        let (tx, rx) = mpsc::channel(8);
+        self.routes
+            .write()
+            .await
+            .register(&download.username, &download.filename, tx);
+
+        let result = self.transfer(&id, &download, rx).await;
+
+        self.routes
+            .write()
+            .await
+            .unregister(&download.username, &download.filename);
+
+        match result {
+            Ok(Some(path)) => {
+                tracing::info!(id = %id, path = ?path, "Soulseek download completed");
+                self.update(&id, |d| d.mark_completed(path.clone())).await;
+                let _ = self
+                    .event_tx
+                    .send(SoulseekEvent::DownloadComplete { id, path });
+            }
+            Ok(None) => {
+                tracing::debug!(id = %id, "Soulseek download stopped after cancellation");
+            }
+            Err(e) => {
+                if self.is_cancelled(&id).await {
+                    return;
+                }
+                tracing::warn!(id = %id, error = %e, "Soulseek download failed");
+                let error = e.to_string();
+                self.update(&id, |d| d.mark_failed(error.clone())).await;
+                let _ = self
+                    .event_tx
+                    .send(SoulseekEvent::DownloadFailed { id, error });
+            }
+        }
+    }
+
+    /// Negotiate the transfer and receive the file.
+    ///
+    /// Returns `None` if the download was cancelled along the way.
+    async fn transfer(
+        &self,
+        id: &str,
+        download: &DownloadState,
+        mut signals: mpsc::Receiver<TransferSignal>,
+    ) -> Result<Option<PathBuf>> {
+        let username = download.username.as_str();
+        let filename = download.filename.as_str();
+
+        let (mut peer, mut messages) = self.connector.connect(username).await?;
+
+        peer.send(PeerRequest::QueueUpload(QueueUpload::new(
+            filename.to_string(),
+        )))
+        .await?;
+        peer.send(PeerRequest::PlaceInQueueRequest(PlaceInQueueRequest::new(
+            filename.to_string(),
+        )))
+        .await?;
+        self.update(id, |d| d.status = DownloadStatus::Queued).await;
+
+        let mut peer_open = true;
+        let mut deadline: Option<tokio::time::Instant> = None;
+        let mut poll = tokio::time::interval(Duration::from_secs(QUEUE_POLL_INTERVAL_SECS));
+        poll.tick().await;
+
+        let (stream, buffered) = loop {
+            if self.is_cancelled(id).await {
+                peer.shutdown().await;
+                return Ok(None);
+            }
+
+            let connect_timeout = async {
+                match deadline {
+                    Some(deadline) => tokio::time::sleep_until(deadline).await,
+                    None => std::future::pending().await,
+                }
+            };
+
+            tokio::select! {
+                message = messages.recv(), if peer_open => match message {
+                    Some(message) => {
+                        if let Some(reply) =
+                            dispatch_peer_message(&self.routes, username, message).await
+                        {
+                            peer.send(reply).await?;
+                        }
+                    }
+                    None => {
+                        tracing::debug!(id = %id, username = %username, "Peer closed connection while queued");
+                        peer_open = false;
+                    }
+                },
+                signal = signals.recv() => match signal {
+                    Some(TransferSignal::Offered { ticket, size }) => {
+                        tracing::debug!(id = %id, ticket = ticket, "Peer is ready to upload");
+                        self.update(id, |d| {
+                            d.ticket = ticket;
+                            d.queue_position = None;
+                            if let Some(size) = size {
+                                d.size = size;
+                            }
+                        })
+                        .await;
+                        deadline = Some(
+                            tokio::time::Instant::now()
+                                + Duration::from_secs(FILE_CONNECTION_TIMEOUT_SECS),
+                        );
+                    }
+                    Some(TransferSignal::Queued(place)) => {
+                        tracing::debug!(id = %id, place = place, "Queued on peer");
+                        self.update(id, |d| d.mark_queued(place)).await;
+                    }
+                    Some(TransferSignal::Failed(reason)) => {
+                        peer.shutdown().await;
+                        return Err(AppError::ServiceUnavailable(format!(
+                            "{} refused the download: {}",
+                            username, reason
+                        )));
+                    }
+                    Some(TransferSignal::Connected(stream, buffered)) => break (stream, buffered),
+                    None => {
+                        return Err(AppError::Internal("Transfer route closed".to_string()));
+                    }
+                },
+                _ = poll.tick(), if peer_open && deadline.is_none() => {
+                    peer.send(PeerRequest::PlaceInQueueRequest(PlaceInQueueRequest::new(
+                        filename.to_string(),
+                    )))
+                    .await?;
+                }
+                _ = connect_timeout => {
+                    peer.shutdown().await;
+                    return Err(AppError::ServiceUnavailable(format!(
+                        "{} did not open the file connection",
+                        username
+                    )));
+                }
+            }
+        };
+
+        peer.shutdown().await;
+        self.receive(id, stream, buffered).await
+    }
+
+    /// Receive the file over an established file connection.
+    ///
+    /// Data is written to a `.part` file which is renamed once complete. An
+    /// existing `.part` file is resumed from its current length.
+    async fn receive(
+        &self,
+        id: &str,
+        mut stream: TcpStream,
+        buffered: BytesMut,
+    ) -> Result<Option<PathBuf>> {
+        let Some(download) = self.get(id).await else {
+            return Ok(None);
+        };
+        let size = download.size;
+        let path = self
+            .download_dir
+            .join(local_path(&download.username, &download.filename, id));
+        let partial = partial_path(&path);
+
+        tokio::fs::create_dir_all(path.parent().unwrap_or(&self.download_dir))
+            .await
+            .map_err(|e| {
+                AppError::Internal(format!("Failed to create download directory: {}", e))
+            })?;
+
+        let offset = match tokio::fs::metadata(&partial).await {
+            Ok(meta) if meta.len() <= size => meta.len(),
+            _ => 0,
+        };
+
+        let mut file = tokio::fs::OpenOptions::new()
+            .create(true)
+            .write(true)
+            .append(offset > 0)
+            .truncate(offset == 0)
+            .open(&partial)
+            .await
+            .map_err(|e| {
+                AppError::Internal(format!("Failed to open {}: {}", partial.display(), e))
+            })?;
+
+        stream
+            .write_u64_le(offset)
+            .await
+            .map_err(|e| AppError::Internal(format!("Failed to send file offset: {}", e)))?;
+
+        tracing::info!(id = %id, offset = offset, size = size, "Receiving Soulseek file");
+        self.update(id, |d| d.update_progress(offset, 0)).await;
+        let _ = self
+            .event_tx
+            .send(SoulseekEvent::DownloadStarted { id: id.to_string() });
+
+        let mut downloaded = offset;
+        let mut buf = buffered;
+        buf.reserve(READ_BUFFER_SIZE);
+        let mut last_report = Instant::now();
+        let mut last_reported = downloaded;
+
+        while downloaded < size {
+            if buf.is_empty() {
+                let n = stream
+                    .read_buf(&mut buf)
+                    .await
+                    .map_err(|e| AppError::Internal(format!("Failed to read file data: {}", e)))?;
+                if n == 0 {
+                    return Err(AppError::ServiceUnavailable(format!(
+                        "Peer closed the connection after {} of {} bytes",
+                        downloaded, size
+                    )));
+                }
+            }
+
+            let take = buf.len().min((size - downloaded) as usize);
+            self.limiter.acquire(take).await;
+            file.write_all(&buf[..take])
+                .await
+                .map_err(|e| AppError::Internal(format!("Failed to write file data: {}", e)))?;
+            buf.clear();
+            downloaded += take as u64;
+
+            let elapsed = last_report.elapsed();
+            if elapsed >= Duration::from_millis(PROGRESS_INTERVAL_MS) || downloaded == size {
+                if self.is_cancelled(id).await {
+                    return Ok(None);
+                }
+
+                let speed =
+                    ((downloaded - last_reported) as f64 / elapsed.as_secs_f64().max(0.001)) as u64;
+                self.update(id, |d| d.update_progress(downloaded, speed))
+                    .await;
+                let _ = self.event_tx.send(SoulseekEvent::DownloadProgress {
+                    id: id.to_string(),
+                    progress: downloaded,
+                    total: size,
+                    speed,
+                });
+                last_report = Instant::now();
+                last_reported = downloaded;
+            }
+        }
+
+        file.flush()
+            .await
+            .map_err(|e| AppError::Internal(format!("Failed to flush file: {}", e)))?;
+        drop(file);
+
+        tokio::fs::rename(&partial, &path)
+            .await
+            .map_err(|e| AppError::Internal(format!("Failed to finalize download: {}", e)))?;
+
+        Ok(Some(path))
+    }
+
+    /// Get a snapshot of a download.
+    async fn get(&self, id: &str) -> Option<DownloadState> {
+        self.downloads.read().await.get(id).cloned()
+    }
+
+    /// Apply a change to a download's state.
+    async fn update(&self, id: &str, f: impl FnOnce(&mut DownloadState)) {
+        if let Some(download) = self.downloads.write().await.get_mut(id) {
+            f(download);
+        }
+    }
+
+    /// Check whether the download was cancelled (or removed).
+    async fn is_cancelled(&self, id: &str) -> bool {
+        self.downloads
+            .read()
+            .await
+            .get(id)
+            .is_none_or(|d| d.status == DownloadStatus::Cancelled)
+    }
+}
+
+/// Local path of a remote file, relative to the download directory.
+///
+/// Files are kept under the uploader's name and the remote parent directory,
+/// so files with the same name (every album has a `01 - ...`) do not collide.
+/// Names that are unusable as a path component fall back to the download ID.
+/// Soulseek paths usually use Windows separators, so both are handled.
+fn local_path(username: &str, remote: &str, id: &str) -> PathBuf {
+    let mut parts = remote.rsplit(['/', '\\']);
+    let name = parts.next().filter(|p| is_plain_component(p)).unwrap_or(id);
+    let user = username.replace(['/', '\\'], "_");
+
+    let mut path = PathBuf::from(if is_plain_component(&user) { &user } else { id });
+    if let Some(parent) = parts.next().filter(|p| is_plain_component(p)) {
+        path.push(parent);
+    }
+    path.push(name);
+    path
+}
+
+fn is_plain_component(part: &str) -> bool {
+    !part.is_empty() && part != "." && part != ".."
+}
+
+/// Path of the in-progress file for `path`.
+fn partial_path(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(PARTIAL_SUFFIX);
+    PathBuf::from(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::soulseek::engine::SoulseekEngine;
+    use crate::services::soulseek::fake::{
+        logged_in_engine, open_file_connection, FakePeer, FakeServer, PeerSession, TIMEOUT,
+    };
+    use crate::services::soulseek::types::DownloadRequest;
+    use soulseek_protocol::message_common::ConnectionType;
+    use soulseek_protocol::peers::p2p::transfer::{
+        PlaceInQueueReply, QueueFailed, TransferRequest, UploadFailed,
+    };
+    use std::net::SocketAddr;
+
+    const UPLOADER: &str = "uploader";
+    const GET_PEER_ADDRESS: u32 = 3;
+    const FILENAME: &str = "@@music\\Artist\\Album\\01 - Song.flac";
+
+    struct Harness {
+        engine: Arc<SoulseekEngine>,
+        peer: FakePeer,
+        listen_addr: SocketAddr,
+        dir: tempfile::TempDir,
+        server: FakeServer,
+    }
+
+    async fn harness() -> Harness {
+        let dir = tempfile::tempdir().unwrap();
+        let server = FakeServer::start().await;
+        let peer = FakePeer::bind().await;
+        server.add_peer(UPLOADER, peer.addr()).await;
+
+        let (engine, listen_addr) = logged_in_engine(&server, dir.path()).await;
+
+        Harness {
+            engine,
+            peer,
+            listen_addr,
+            dir,
+            server,
+        }
+    }
+
+    async fn start_download(engine: &SoulseekEngine, size: u64) -> String {
+        engine
+            .download(DownloadRequest {
+                username: UPLOADER.to_string(),
+                filename: FILENAME.to_string(),
+                size,
+                media_type: None,
+                media_id: None,
+            })
+            .await
+            .unwrap()
+    }
+
+    /// Accept the engine's peer connection and read its queue request.
+    async fn accept_queue_request(peer: &FakePeer) -> PeerSession {
+        let mut session = peer.accept().await;
+        assert_eq!(session.username, "lcars");
+        assert_eq!(session.connection_type, ConnectionType::PeerToPeer);
+
+        match session.recv().await {
+            PeerResponse::QueueUpload(request) => assert_eq!(request.file_name, FILENAME),
+            other => panic!("expected QueueUpload, got {:?}", other),
+        }
+        match session.recv().await {
+            PeerResponse::PlaceInQueueRequest(request) => assert_eq!(request.file_name, FILENAME),
+            other => panic!("expected PlaceInQueueRequest, got {:?}", other),
+        }
+        session
+    }
+
+    fn offer(ticket: u32, size: u64) -> PeerRequest {
+        PeerRequest::TransferRequest(TransferRequest {
+            direction: 1,
+            ticket,
+            filename: FILENAME.to_string(),
+            file_size: Some(size),
+        })
+    }
+
+    async fn wait_for_state(
+        engine: &SoulseekEngine,
+        id: &str,
+        predicate: impl Fn(&DownloadState) -> bool,
+    ) -> DownloadState {
+        tokio::time::timeout(TIMEOUT, async {
+            loop {
+                if let Some(state) = engine.get_download(id).await {
+                    if predicate(&state) {
+                        return state;
+                    }
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("download did not reach the expected state")
+    }
+
+    async fn next_terminal_event(
+        events: &mut broadcast::Receiver<SoulseekEvent>,
+    ) -> (Vec<SoulseekEvent>, SoulseekEvent) {
+        let mut seen = Vec::new();
+        tokio::time::timeout(TIMEOUT, async {
+            loop {
+                let event = events.recv().await.unwrap();
+                match event {
+                    SoulseekEvent::DownloadComplete { .. }
+                    | SoulseekEvent::DownloadFailed { .. } => return (seen, event),
+                    other => seen.push(other),
+                }
+            }
+        })
+        .await
+        .expect("download did not finish")
+    }
+
+    #[tokio::test]
+    async fn test_download_resumes_partial_file() {
+        let h = harness().await;
+        let mut events = h.engine.subscribe();
+
+        let data: Vec<u8> = (0..300_000u32).map(|i| (i % 251) as u8).collect();
+        let resume_from = 1000;
+        let album_dir = h.dir.path().join(UPLOADER).join("Album");
+        std::fs::create_dir_all(&album_dir).unwrap();
+        std::fs::write(album_dir.join("01 - Song.flac.part"), &data[..resume_from]).unwrap();
+
+        let id = start_download(&h.engine, data.len() as u64).await;
+        let mut session = accept_queue_request(&h.peer).await;
+
+        // Queue position is reported back through the download state
+        session
+            .send(PeerRequest::PlaceInQueueReply(PlaceInQueueReply::new(
+                FILENAME.to_string(),
+                3,
+            )))
+            .await;
+        let queued = wait_for_state(&h.engine, &id, |d| d.queue_position == Some(3)).await;
+        assert_eq!(queued.status, DownloadStatus::Queued);
+
+        // A slot frees up: the uploader offers the file on the same connection
+        session.send(offer(42, data.len() as u64)).await;
+        match session.recv().await {
+            PeerResponse::TransferReply(TransferReply::TransferReplyOk { ticket, .. }) => {
+                assert_eq!(ticket, 42)
+            }
+            other => panic!("expected TransferReply, got {:?}", other),
+        }
+
+        let (mut stream, offset) = open_file_connection(h.listen_addr, UPLOADER, 42).await;
+        assert_eq!(offset, resume_from as u64);
+        stream.write_all(&data[resume_from..]).await.unwrap();
+
+        let (seen, last) = next_terminal_event(&mut events).await;
+        let path = match last {
+            SoulseekEvent::DownloadComplete { id: done, path } => {
+                assert_eq!(done, id);
+                path
+            }
+            other => panic!("expected DownloadComplete, got {:?}", other),
+        };
+        assert!(seen.iter().any(
+            |e| matches!(e, SoulseekEvent::DownloadStarted { id: started } if *started == id)
+        ));
+        assert!(seen.iter().any(|e| matches!(
+            e,
+            SoulseekEvent::DownloadProgress { progress, total, .. }
+                if *progress == data.len() as u64 && *total == data.len() as u64
+        )));
+
+        assert_eq!(path, album_dir.join("01 - Song.flac"));
+        assert_eq!(std::fs::read(&path).unwrap(), data);
+        assert!(!partial_path(&path).exists());
+
+        let state = h.engine.get_download(&id).await.unwrap();
+        assert_eq!(state.status, DownloadStatus::Completed);
+        assert_eq!(state.ticket, 42);
+        assert_eq!(state.local_path, Some(path));
+    }
+
+    #[tokio::test]
+    async fn test_download_queue_failed() {
+        let mut h = harness().await;
+        let mut events = h.engine.subscribe();
+
+        let id = start_download(&h.engine, 100).await;
+        let lookup = h.server.next_request(GET_PEER_ADDRESS).await;
+        assert_eq!(&lookup[4..], UPLOADER.as_bytes());
+        let mut session = accept_queue_request(&h.peer).await;
+        session
+            .send(PeerRequest::QueueFailed(QueueFailed::new(
+                FILENAME.to_string(),
+                "File not shared.".to_string(),
+            )))
+            .await;
+
+        let (_, last) = next_terminal_event(&mut events).await;
+        match last {
+            SoulseekEvent::DownloadFailed { id: failed, error } => {
+                assert_eq!(failed, id);
+                assert!(error.contains("File not shared."), "{}", error);
+            }
+            other => panic!("expected DownloadFailed, got {:?}", other),
+        }
+
+        let state = h.engine.get_download(&id).await.unwrap();
+        assert_eq!(state.status, DownloadStatus::Failed);
+        assert!(state.error.unwrap().contains("File not shared."));
+    }
+
+    #[tokio::test]
+    async fn test_download_upload_failed_over_new_connection() {
+        let h = harness().await;
+        let mut events = h.engine.subscribe();
+
+        let id = start_download(&h.engine, 100).await;
+        let _queue_session = accept_queue_request(&h.peer).await;
+
+        // The uploader reaches back through our listener instead
+        let mut session =
+            PeerSession::connect(h.listen_addr, UPLOADER, ConnectionType::PeerToPeer).await;
+        session.send(offer(7, 100)).await;
+        match session.recv().await {
+            PeerResponse::TransferReply(TransferReply::TransferReplyOk { ticket, .. }) => {
+                assert_eq!(ticket, 7)
+            }
+            other => panic!("expected TransferReply, got {:?}", other),
+        }
+        wait_for_state(&h.engine, &id, |d| d.ticket == 7).await;
+
+        // Files we are not downloading are turned down
+        session
+            .send(PeerRequest::TransferRequest(TransferRequest {
+                direction: 1,
+                ticket: 8,
+                filename: "other.flac".to_string(),
+                file_size: Some(10),
+            }))
+            .await;
+        match session.recv().await {
+            PeerResponse::TransferReply(TransferReply::TransferRejected { ticket, .. }) => {
+                assert_eq!(ticket, 8)
+            }
+            other => panic!("expected TransferReply, got {:?}", other),
+        }
+
+        session
+            .send(PeerRequest::UploadFailed(UploadFailed::new(
+                FILENAME.to_string(),
+            )))
+            .await;
+
+        let (_, last) = next_terminal_event(&mut events).await;
+        assert!(
+            matches!(last, SoulseekEvent::DownloadFailed { id: ref failed, .. } if *failed == id)
+        );
+        let state = h.engine.get_download(&id).await.unwrap();
+        assert_eq!(state.status, DownloadStatus::Failed);
+    }
+
+    #[tokio::test]
+    async fn test_download_file_connection_through_connect_back() {
+        let h = harness().await;
+        let mut events = h.engine.subscribe();
+        let data = b"firewalled uploader".to_vec();
+
+        let id = start_download(&h.engine, data.len() as u64).await;
+        let mut session = accept_queue_request(&h.peer).await;
+        session.send(offer(7, data.len() as u64)).await;
+        assert!(matches!(
+            session.recv().await,
+            PeerResponse::TransferReply(TransferReply::TransferReplyOk { ticket: 7, .. })
+        ));
+
+        // The uploader cannot reach our listener and asks us to connect back
+        h.server
+            .relay_connect_to_peer(UPLOADER, ConnectionType::FileTransfer, h.peer.addr(), 55);
+        let (file_session, token) = h.peer.accept_pierce().await;
+        assert_eq!(token, 55);
+
+        let mut stream = file_session.into_stream();
+        stream.write_u32_le(7).await.unwrap();
+        let offset = tokio::time::timeout(TIMEOUT, stream.read_u64_le())
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(offset, 0);
+        stream.write_all(&data).await.unwrap();
+
+        let (_, last) = next_terminal_event(&mut events).await;
+        let path = match last {
+            SoulseekEvent::DownloadComplete { id: done, path } => {
+                assert_eq!(done, id);
+                path
+            }
+            other => panic!("expected DownloadComplete, got {:?}", other),
+        };
+        assert_eq!(std::fs::read(path).unwrap(), data);
+    }
+
+    #[test]
+    fn test_local_path() {
+        assert_eq!(
+            local_path("alice", "@@music\\Artist\\Album\\01 - Song.flac", "id"),
+            PathBuf::from("alice/Album/01 - Song.flac")
+        );
+        assert_eq!(
+            local_path("alice", "music/Other Album/01 - Song.flac", "id"),
+            PathBuf::from("alice/Other Album/01 - Song.flac")
+        );
+        assert_eq!(
+            local_path("bob", "song.mp3", "id"),
+            PathBuf::from("bob/song.mp3")
+        );
+        assert_eq!(
+            local_path("a/b", "music\\..\\..", "id"),
+            PathBuf::from("a_b/id")
+        );
+        assert_eq!(
+            local_path("..", "music\\", "id"),
+            PathBuf::from("id/music/id")
+        );
+    }
+
+    #[test]
+    fn test_partial_path() {
+        assert_eq!(
+            partial_path(Path::new("/downloads/song.flac")),
+            PathBuf::from("/downloads/song.flac.part")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_routes_by_filename_and_ticket() {
+        let mut routes = TransferRoutes::new();
+        let (tx, _rx) = mpsc::channel(1);
+        routes.register("peer", "song.flac", tx);
+
+        assert!(routes.assign_ticket("peer", "other.flac", 1).is_none());
+        assert!(routes.assign_ticket("peer", "song.flac", 7).is_some());
+        assert!(routes.get_by_ticket("peer", 7).is_some());
+        assert!(routes.get_by_ticket("other", 7).is_none());
+
+        routes.unregister("peer", "song.flac");
+        assert!(routes.get("peer", "song.flac").is_none());
+        assert!(routes.get_by_ticket("peer", 7).is_none());
+    }
+}
@@ -3,6 +3,7 @@
 //! Main service struct that provides Soulseek network functionality
 //! for searching and downloading music files.
 
+use bytes::BytesMut;
 use chrono::{DateTime, Utc};
 
 // =============================================================================
@@ -20,13 +21,16 @@ const BROWSE_TIMEOUT_SECS: u64 = 30;
 
 use rand::Rng;
 use soulseek_protocol::{
-    message_common::ConnectionType,
     peers::p2p::{
         response::PeerResponse, search::SearchReply, shared_directories::SharedDirectories,
     },
     server::{
-        login::LoginRequest, peer::RequestConnectionToPeer, request::ServerRequest,
-        response::ServerResponse, search::SearchRequest, shares::SharedFolderAndFiles,
+        login::LoginRequest,
+        peer::{PeerConnectionRequest, PeerConnectionTicket},
+        request::ServerRequest,
+        response::ServerResponse,
+        search::SearchRequest,
+        shares::SharedFolderAndFiles,
     },
 };
 use std::collections::HashMap;
@@ -40,11 +44,14 @@ use uuid::Uuid;
 
 use crate::config::SoulseekConfig;
 use crate::error::{AppError, Result};
+use crate::services::bandwidth::{min_limit, BandwidthManager, RateLimiter, SpeedLimits};
 
 use super::connection::SoulseekConnection;
+use super::distributed::{DistributedClient, ParentState};
+use super::downloads::{Downloader, TransferRoutes};
 use super::events::SoulseekEvent;
-use super::listener::PeerListener;
-use super::peer::PeerConnection;
+use super::listener::{PeerContext, PeerListener};
+use super::peer::{connect_back, PeerConnector, PendingPierce};
 use super::shares::ShareIndex;
 use super::types::{
     BrowsedDirectory, BrowsedFile, ConnectionState, DownloadRequest, DownloadState, DownloadStatus,
@@ -53,7 +60,7 @@ use super::types::{
 use super::uploads::{UploadQueue, UploadState};
 
 /// Pending peer address request - waiting for server to provide IP/port.
-struct PendingPeerAddress {
+pub(super) struct PendingPeerAddress {
     /// Channel to send the result back.
     tx: oneshot::Sender<Result<(Ipv4Addr, u32)>>,
 }
@@ -77,6 +84,10 @@ pub struct SoulseekEngine {
     username: Arc<RwLock<Option<String>>>,
     /// Pending peer address requests indexed by username.
     pending_peer_addresses: Arc<RwLock<HashMap<String, PendingPeerAddress>>>,
+    /// Routes inbound transfer traffic to download tasks.
+    transfer_routes: Arc<RwLock<TransferRoutes>>,
+    /// Indirect peer connections waiting for a `PierceFireWall`, indexed by token.
+    pending_pierces: Arc<RwLock<HashMap<u32, PendingPierce>>>,
     // =========================================================================
     // Connection state management
     // =========================================================================
@@ -99,6 +110,20 @@ pub struct SoulseekEngine {
     upload_queue: Arc<RwLock<UploadQueue>>,
     /// Handle to the peer listener task.
     listener_handle: RwLock<Option<JoinHandle<()>>>,
+    // =========================================================================
+    // Distributed network fields
+    // =========================================================================
+    /// Our position in the distributed search network.
+    distributed_parent: Arc<RwLock<ParentState>>,
+    /// Handle to the task serving our distributed parent.
+    parent_handle: RwLock<Option<JoinHandle<()>>>,
+    // =========================================================================
+    // Bandwidth fields
+    // =========================================================================
+    /// Throttles file downloads.
+    download_limiter: Arc<RateLimiter>,
+    /// Throttles file uploads.
+    upload_limiter: Arc<RateLimiter>,
 }
 
 impl SoulseekEngine {
@@ -119,6 +144,7 @@ impl SoulseekEngine {
         let (event_tx, _) = broadcast::channel(MESSAGE_CHANNEL_SIZE);
 
         let upload_queue = UploadQueue::new(config.upload_slots);
+        let upload_limiter = Arc::new(RateLimiter::new(config.upload_speed_limit));
 
         Ok(Self {
             config,
@@ -128,6 +154,8 @@ impl SoulseekEngine {
             downloads: Arc::new(RwLock::new(HashMap::new())),
             username: Arc::new(RwLock::new(None)),
             pending_peer_addresses: Arc::new(RwLock::new(HashMap::new())),
+            transfer_routes: Arc::new(RwLock::new(TransferRoutes::new())),
+            pending_pierces: Arc::new(RwLock::new(HashMap::new())),
             connection_state: Arc::new(RwLock::new(ConnectionState::Disconnected)),
             connected_since: Arc::new(RwLock::new(None)),
             reconnect_attempts: AtomicU32::new(0),
@@ -136,6 +164,10 @@ impl SoulseekEngine {
             share_index: Arc::new(RwLock::new(ShareIndex::new())),
             upload_queue: Arc::new(RwLock::new(upload_queue)),
             listener_handle: RwLock::new(None),
+            distributed_parent: Arc::new(RwLock::new(ParentState::None)),
+            parent_handle: RwLock::new(None),
+            download_limiter: Arc::new(RateLimiter::new(None)),
+            upload_limiter,
         })
     }
 
@@ -144,6 +176,30 @@ impl SoulseekEngine {
         Ok(Arc::new(Self::new(config).await?))
     }
 
+    /// Follow the global bandwidth limits, including alt-speed switches.
+    ///
+    /// Uploads also stay within `upload_speed_limit` when it is lower.
+    pub fn apply_bandwidth_limits(self: &Arc<Self>, bandwidth: &BandwidthManager) {
+        let mut rx = bandwidth.subscribe();
+        let engine = Arc::clone(self);
+
+        let set_limits = move |engine: &Self, limits: SpeedLimits| {
+            engine.download_limiter.set_limit(limits.download);
+            engine
+                .upload_limiter
+                .set_limit(min_limit(limits.upload, engine.config.upload_speed_limit));
+        };
+
+        set_limits(&engine, *rx.borrow_and_update());
+        tokio::spawn(async move {
+            while rx.changed().await.is_ok() {
+                let limits = *rx.borrow_and_update();
+                set_limits(&engine, limits);
+                tracing::debug!(?limits, "Applied bandwidth limits to Soulseek transfers");
+            }
+        });
+    }
+
     /// Connect to the Soulseek server and authenticate.
     pub async fn connect(self: &Arc<Self>) -> Result<()> {
         // Check if already connected
@@ -256,6 +312,9 @@ impl SoulseekEngine {
             }
         }
 
+        // Leave the distributed network
+        self.stop_distributed().await;
+
         let mut conn_guard = self.connection.write().await;
         if let Some(mut connection) = conn_guard.take() {
             connection.shutdown().await;
@@ -439,6 +498,8 @@ impl SoulseekEngine {
             ));
         }
 
+        let connector = self.peer_connector().await?;
+
         // Generate unique download ID and ticket
         let id = Uuid::new_v4().to_string();
         let ticket: u32 = rand::thread_rng().gen();
@@ -475,11 +536,16 @@ impl SoulseekEngine {
             filename: request.filename.clone(),
         });
 
-        // Request peer address from server
-        let connect_req =
-            RequestConnectionToPeer::new(request.username.clone(), ConnectionType::PeerToPeer);
-        self.send_request(ServerRequest::ConnectToPeer(connect_req))
-            .await?;
+        // Negotiate and receive the file in the background
+        let downloader = Downloader {
+            download_dir: self.config.download_dir.clone(),
+            connector,
+            downloads: Arc::clone(&self.downloads),
+            routes: Arc::clone(&self.transfer_routes),
+            event_tx: self.event_tx.clone(),
+            limiter: Arc::clone(&self.download_limiter),
+        };
+        tokio::spawn(downloader.run(id.clone()));
 
         Ok(id)
     }
@@ -517,7 +583,7 @@ impl SoulseekEngine {
 
     /// Browse a user's shared files.
     ///
-    /// Connects directly to the peer and retrieves their shared file list.
+    /// Connects to the peer and retrieves their shared file list.
     pub async fn browse_user(&self, username: &str) -> Result<Vec<BrowsedDirectory>> {
         if !self.is_connected().await {
             return Err(AppError::ServiceUnavailable(
@@ -527,23 +593,9 @@ impl SoulseekEngine {
 
         tracing::info!(username = %username, "Browsing user's shares");
 
-        // Get peer address from server
-        let (ip, port) = self.get_peer_address(username).await?;
-
-        // Get our username
-        let our_username = {
-            let username_guard = self.username.read().await;
-            username_guard
-                .clone()
-                .ok_or_else(|| AppError::Internal("Not logged in".to_string()))?
-        };
-
-        // Generate connection token
-        let token: u32 = rand::thread_rng().gen();
-
-        // Connect to peer
+        // Connect to peer, directly or through the server
         let (mut peer_conn, mut message_rx) =
-            PeerConnection::connect(username, ip, port, &our_username, token).await?;
+            self.peer_connector().await?.connect(username).await?;
 
         // Request shares
         peer_conn.request_shares().await?;
@@ -692,25 +744,11 @@ impl SoulseekEngine {
     }
 
     /// Start the peer listener for incoming connections.
+    ///
+    /// The listener runs even when sharing is disabled, since uploaders open
+    /// file connections to us to deliver our downloads.
     pub async fn start_listener(self: &Arc<Self>) -> Result<()> {
-        if !self.config.sharing_enabled {
-            tracing::debug!("Sharing not enabled, not starting peer listener");
-            return Ok(());
-        }
-
-        let username = {
-            let guard = self.username.read().await;
-            guard.clone().unwrap_or_else(|| "anonymous".to_string())
-        };
-
-        let listener = PeerListener::bind(
-            self.config.listen_port,
-            Arc::clone(&self.share_index),
-            Arc::clone(&self.upload_queue),
-            username,
-            0, // TODO: Calculate from upload_speed_limit
-        )
-        .await?;
+        let listener = PeerListener::bind(self.config.listen_port, self.peer_context()).await?;
 
         let handle = tokio::spawn(async move {
             listener.run().await;
@@ -746,40 +784,6 @@ impl SoulseekEngine {
         }
     }
 
-    /// Get the address (IP and port) of a peer from the server.
-    async fn get_peer_address(&self, username: &str) -> Result<(Ipv4Addr, u32)> {
-        // Create a channel to receive the address
-        let (tx, rx) = oneshot::channel();
-
-        // Register the pending request
-        {
-            let mut pending = self.pending_peer_addresses.write().await;
-            pending.insert(username.to_string(), PendingPeerAddress { tx });
-        }
-
-        // Request the address from server
-        self.send_request(ServerRequest::GetPeerAddress(username.to_string()))
-            .await?;
-
-        // Wait for response with timeout
-        let result = tokio::time::timeout(Duration::from_secs(PEER_ADDRESS_TIMEOUT_SECS), rx).await;
-
-        // Clean up pending request on timeout or error
-        match &result {
-            Ok(Ok(_)) => {}
-            _ => {
-                let mut pending = self.pending_peer_addresses.write().await;
-                pending.remove(username);
-            }
-        }
-
-        result
-            .map_err(|_| {
-                AppError::Internal(format!("Timeout waiting for peer address for {}", username))
-            })?
-            .map_err(|_| AppError::Internal("Peer address request cancelled".to_string()))?
-    }
-
     /// Gracefully stop the engine.
     pub async fn stop(&self) {
         tracing::info!("Stopping Soulseek engine");
@@ -790,6 +794,60 @@ impl SoulseekEngine {
     // Private helpers
     // =========================================================================
 
+    /// Build a connector for outgoing peer connections.
+    async fn peer_connector(&self) -> Result<PeerConnector> {
+        let our_username = {
+            let username_guard = self.username.read().await;
+            username_guard
+                .clone()
+                .ok_or_else(|| AppError::Internal("Not logged in".to_string()))?
+        };
+
+        Ok(PeerConnector {
+            our_username,
+            connection: Arc::clone(&self.connection),
+            pending_peer_addresses: Arc::clone(&self.pending_peer_addresses),
+            pending_pierces: Arc::clone(&self.pending_pierces),
+        })
+    }
+
+    /// Build the client used to take part in the distributed network.
+    async fn distributed_client(&self) -> Result<DistributedClient> {
+        Ok(DistributedClient {
+            connector: self.peer_connector().await?,
+            share_index: Arc::clone(&self.share_index),
+            upload_queue: Arc::clone(&self.upload_queue),
+            parent: Arc::clone(&self.distributed_parent),
+        })
+    }
+
+    /// Our current position in the distributed network.
+    #[cfg(test)]
+    pub(super) async fn distributed_parent(&self) -> ParentState {
+        self.distributed_parent.read().await.clone()
+    }
+
+    /// Drop our distributed parent, if any.
+    async fn stop_distributed(&self) {
+        if let Some(h) = self.parent_handle.write().await.take() {
+            h.abort();
+        }
+        *self.distributed_parent.write().await = ParentState::None;
+    }
+
+    /// Build the state used to serve peer connections.
+    fn peer_context(&self) -> PeerContext {
+        PeerContext {
+            share_index: Arc::clone(&self.share_index),
+            upload_queue: Arc::clone(&self.upload_queue),
+            transfer_routes: Arc::clone(&self.transfer_routes),
+            pending_pierces: Arc::clone(&self.pending_pierces),
+            searches: Arc::clone(&self.searches),
+            event_tx: self.event_tx.clone(),
+            upload_limiter: Arc::clone(&self.upload_limiter),
+        }
+    }
+
     /// Send a request to the server.
     async fn send_request(&self, request: ServerRequest) -> Result<()> {
         let conn_guard = self.connection.read().await;
@@ -829,6 +887,9 @@ impl SoulseekEngine {
             matches!(*state, ConnectionState::Connected)
         };
 
+        // Our parent connection does not outlive the server connection
+        self.stop_distributed().await;
+
         if was_connected {
             // Clear connected_since
             {
@@ -990,6 +1051,15 @@ impl SoulseekEngine {
 
                         // Report share count to server
                         self.send_share_count().await?;
+
+                        // Join the distributed network as a leaf
+                        self.stop_distributed().await;
+                        self.send_request(ServerRequest::AcceptChildren(false))
+                            .await?;
+                        self.distributed_client()
+                            .await?
+                            .announce_no_parent()
+                            .await?;
                     }
                     soulseek_protocol::server::login::LoginResponse::Failure { reason } => {
                         tracing::warn!(reason = %reason, "Login failed");
@@ -1049,6 +1119,22 @@ impl SoulseekEngine {
                 self.handle_embedded_message(embedded).await?;
             }
 
+            ServerResponse::PossibleParents(candidates) => {
+                tracing::debug!(count = candidates.len(), "Received possible parents");
+
+                {
+                    let mut parent = self.distributed_parent.write().await;
+                    if *parent != ParentState::None {
+                        return Ok(());
+                    }
+                    *parent = ParentState::Connecting;
+                }
+
+                let client = self.distributed_client().await?;
+                let handle = tokio::spawn(client.run(candidates));
+                *self.parent_handle.write().await = Some(handle);
+            }
+
             ServerResponse::PeerAddress(peer_address) => {
                 tracing::debug!(
                     username = %peer_address.username,
@@ -1072,8 +1158,28 @@ impl SoulseekEngine {
                     token = connection_request.token,
                     "Received peer connection request"
                 );
-                // For now, we don't handle incoming connections
-                // This would be needed for users trying to download from us
+
+                // The peer could not reach us directly, so we connect back
+                let context = self.peer_context();
+                let connection = Arc::clone(&self.connection);
+                tokio::spawn(answer_connection_request(
+                    connection_request,
+                    context,
+                    connection,
+                ));
+            }
+
+            ServerResponse::CantConnectToPeer(ticket) => {
+                tracing::debug!(token = ticket.token, "Peer cannot connect to us");
+
+                // Fail the matching indirect connection right away
+                let pending = self.pending_pierces.write().await.remove(&ticket.token);
+                if let Some(pending) = pending {
+                    let _ = pending.tx.send(Err(AppError::ServiceUnavailable(format!(
+                        "Cannot connect to peer {}",
+                        pending.username
+                    ))));
+                }
             }
 
             ServerResponse::Unknown(len, code, _data) => {
@@ -1088,58 +1194,164 @@ impl SoulseekEngine {
         Ok(())
     }
 
-    /// Handle embedded distributed messages (search results from the network).
+    /// Handle embedded distributed messages (searches sent to branch roots).
     async fn handle_embedded_message(
         &self,
-        _embedded: soulseek_protocol::server::distributed::EmbeddedDistributedMessage,
+        embedded: soulseek_protocol::server::distributed::EmbeddedDistributedMessage,
     ) -> Result<()> {
-        // EmbeddedDistributedMessage contains code and raw message bytes
-        // For now we just log it - parsing the internal message would require
-        // additional work based on the code field
-        tracing::trace!("Received embedded distributed message");
-        // We don't share files yet, so we ignore incoming distributed messages
+        let Some(request) = embedded.search_request() else {
+            tracing::trace!(
+                code = embedded.code,
+                "Ignoring embedded distributed message"
+            );
+            return Ok(());
+        };
+
+        // Replying means connecting to the searcher, which may be slow
+        let client = self.distributed_client().await?;
+        tokio::spawn(async move { client.answer_search(request).await });
         Ok(())
     }
+}
 
-    /// Process search results from a peer.
-    ///
-    /// This is called when we receive search results either through the
-    /// distributed network or directly from a peer connection.
-    #[allow(dead_code)] // Will be used in Phase 2 (P2P connections)
-    pub(crate) async fn process_search_results(&self, reply: SearchReply) {
-        let ticket = reply.ticket;
-
-        let files: Vec<FileResult> = reply
-            .files
-            .iter()
-            .map(FileResult::from_protocol_file)
-            .collect();
-
-        // Update search state
-        {
-            let mut searches = self.searches.write().await;
-            if let Some(search_state) = searches.get_mut(&ticket) {
-                let result = SearchResult {
-                    username: reply.username.clone(),
-                    files: files.clone(),
-                    has_free_slot: reply.slot_free,
-                    average_speed: reply.average_speed,
-                    queue_length: reply.queue_length,
-                };
-                search_state.add_results(result);
-            }
-        }
-
-        // Emit event
-        let _ = self.event_tx.send(SoulseekEvent::SearchResult {
-            ticket,
-            username: reply.username,
-            files,
+/// Record search results sent by a peer.
+///
+/// Results for searches we no longer track (finished or cancelled) are dropped.
+pub(super) async fn process_search_results(
+    searches: &RwLock<HashMap<u32, SearchState>>,
+    event_tx: &broadcast::Sender<SoulseekEvent>,
+    reply: SearchReply,
+) {
+    let ticket = reply.ticket;
+
+    let files: Vec<FileResult> = reply
+        .files
+        .iter()
+        .map(FileResult::from_protocol_file)
+        .collect();
+
+    // Update search state
+    {
+        let mut searches = searches.write().await;
+        let Some(search_state) = searches.get_mut(&ticket) else {
+            tracing::trace!(ticket = ticket, username = %reply.username, "Search reply for unknown search");
+            return;
+        };
+        search_state.add_results(SearchResult {
+            username: reply.username.clone(),
+            files: files.clone(),
             has_free_slot: reply.slot_free,
             average_speed: reply.average_speed,
             queue_length: reply.queue_length,
         });
     }
+
+    tracing::debug!(
+        ticket = ticket,
+        username = %reply.username,
+        files = files.len(),
+        "Received search results"
+    );
+
+    // Emit event
+    let _ = event_tx.send(SoulseekEvent::SearchResult {
+        ticket,
+        username: reply.username,
+        files,
+        has_free_slot: reply.slot_free,
+        average_speed: reply.average_speed,
+        queue_length: reply.queue_length,
+    });
+}
+
+/// Ask the server for a peer's address (IP and port) and wait for the reply.
+pub(super) async fn resolve_peer_address(
+    connection: &RwLock<Option<SoulseekConnection>>,
+    pending_peer_addresses: &RwLock<HashMap<String, PendingPeerAddress>>,
+    username: &str,
+) -> Result<(Ipv4Addr, u32)> {
+    // Create a channel to receive the address
+    let (tx, rx) = oneshot::channel();
+
+    // Register the pending request
+    {
+        let mut pending = pending_peer_addresses.write().await;
+        pending.insert(username.to_string(), PendingPeerAddress { tx });
+    }
+
+    // Request the address from server
+    {
+        let conn_guard = connection.read().await;
+        let connection = conn_guard.as_ref().ok_or_else(|| {
+            AppError::ServiceUnavailable("Not connected to Soulseek server".to_string())
+        })?;
+        connection
+            .send(ServerRequest::GetPeerAddress(username.to_string()))
+            .await?;
+    }
+
+    // Wait for response with timeout
+    let result = tokio::time::timeout(Duration::from_secs(PEER_ADDRESS_TIMEOUT_SECS), rx).await;
+
+    // Clean up pending request on timeout or error
+    match &result {
+        Ok(Ok(_)) => {}
+        _ => {
+            let mut pending = pending_peer_addresses.write().await;
+            pending.remove(username);
+        }
+    }
+
+    result
+        .map_err(|_| {
+            AppError::Internal(format!("Timeout waiting for peer address for {}", username))
+        })?
+        .map_err(|_| AppError::Internal("Peer address request cancelled".to_string()))?
+}
+
+/// Connect back to a peer that asked the server to reach us.
+///
+/// If the peer cannot be reached either, we tell it through the server so it
+/// does not wait for the connection.
+async fn answer_connection_request(
+    request: PeerConnectionRequest,
+    context: PeerContext,
+    connection: Arc<RwLock<Option<SoulseekConnection>>>,
+) {
+    let stream =
+        match connect_back(&request.username, request.ip, request.port, request.token).await {
+            Ok(stream) => stream,
+            Err(e) => {
+                tracing::debug!(
+                    username = %request.username,
+                    token = request.token,
+                    error = %e,
+                    "Failed to connect back to peer"
+                );
+                let conn_guard = connection.read().await;
+                if let Some(connection) = conn_guard.as_ref() {
+                    let _ = connection
+                        .send(ServerRequest::CantConnectToPeer(PeerConnectionTicket {
+                            token: request.token,
+                            username: request.username,
+                        }))
+                        .await;
+                }
+                return;
+            }
+        };
+
+    if let Err(e) = PeerListener::serve(
+        stream,
+        BytesMut::new(),
+        request.username.clone(),
+        request.connection_type,
+        context,
+    )
+    .await
+    {
+        tracing::debug!(username = %request.username, error = %e, "Peer connection error");
+    }
 }
 
 /// Convert protocol SharedDirectories to our BrowsedDirectory format.
@@ -0,0 +1,461 @@
+//! In-process Soulseek server and peer used to exercise the engine in tests.
+//!
+//! The server answers logins and peer address lookups, records every request
+//! it receives and can push arbitrary frames to the client. The peer accepts
+//! connections and speaks the P2P protocol through the `soulseek-protocol`
+//! message types.
+
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::net::{Ipv4Addr, SocketAddr};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::{Buf, BytesMut};
+use soulseek_protocol::{
+    frame::ToBytes,
+    message_common::ConnectionType,
+    peers::{
+        connection::PeerConnectionMessage,
+        distributed::DistributedMessage,
+        p2p::{request::PeerRequest, response::PeerResponse},
+    },
+    ProtocolMessage,
+};
+use tokio::io::{AsyncReadExt, AsyncWriteExt, BufWriter};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, Mutex};
+
+use crate::config::SoulseekConfig;
+
+use super::engine::SoulseekEngine;
+
+/// How long test helpers wait for the engine before giving up.
+pub const TIMEOUT: Duration = Duration::from_secs(5);
+
+const LOGIN: u32 = 1;
+const GET_PEER_ADDRESS: u32 = 3;
+const CONNECT_TO_PEER: u32 = 18;
+const POSSIBLE_PARENTS: u32 = 102;
+
+/// A running fake server.
+pub struct FakeServer {
+    port: u16,
+    peers: Arc<Mutex<HashMap<String, SocketAddr>>>,
+    requests: mpsc::UnboundedReceiver<(u32, Vec<u8>)>,
+    replies: mpsc::UnboundedSender<Vec<u8>>,
+}
+
+impl FakeServer {
+    /// Start listening on an ephemeral localhost port.
+    ///
+    /// Only the first client connection is served.
+    pub async fn start() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let peers = Arc::new(Mutex::new(HashMap::new()));
+        let (request_tx, requests) = mpsc::unbounded_channel();
+        let (replies, mut outgoing_rx) = mpsc::unbounded_channel::<Vec<u8>>();
+
+        let known_peers = Arc::clone(&peers);
+        let outgoing = replies.clone();
+        tokio::spawn(async move {
+            let Ok((stream, _)) = listener.accept().await else {
+                return;
+            };
+            let (mut reader, mut writer) = stream.into_split();
+
+            tokio::spawn(async move {
+                while let Some(frame) = outgoing_rx.recv().await {
+                    if writer.write_all(&frame).await.is_err() {
+                        break;
+                    }
+                }
+            });
+
+            let mut buffer = BytesMut::new();
+            loop {
+                while buffer.len() >= 8 {
+                    let len = u32::from_le_bytes(buffer[..4].try_into().unwrap()) as usize;
+                    if buffer.len() < 4 + len {
+                        break;
+                    }
+                    let mut message = buffer.split_to(4 + len);
+                    message.advance(4);
+                    let code = message.get_u32_le();
+                    let body = message.to_vec();
+
+                    let _ = request_tx.send((code, body.clone()));
+                    match code {
+                        LOGIN => {
+                            let mut reply = vec![1u8];
+                            put_string(&mut reply, "Welcome");
+                            reply.extend_from_slice(&u32::from(Ipv4Addr::LOCALHOST).to_le_bytes());
+                            put_string(&mut reply, "");
+                            let _ = outgoing.send(frame(LOGIN, &reply));
+                        }
+                        GET_PEER_ADDRESS => {
+                            let username = read_string(&body);
+                            let addr = known_peers.lock().await.get(&username).copied();
+                            let (ip, port) = match addr {
+                                Some(SocketAddr::V4(addr)) => (*addr.ip(), addr.port() as u32),
+                                _ => (Ipv4Addr::UNSPECIFIED, 0),
+                            };
+                            let mut reply = Vec::new();
+                            put_string(&mut reply, &username);
+                            reply.extend_from_slice(&u32::from(ip).to_le_bytes());
+                            reply.extend_from_slice(&port.to_le_bytes());
+                            reply.push(0);
+                            reply.extend_from_slice(&0u32.to_le_bytes());
+                            let _ = outgoing.send(frame(GET_PEER_ADDRESS, &reply));
+                        }
+                        _ => {}
+                    }
+                }
+
+                match reader.read_buf(&mut buffer).await {
+                    Ok(0) | Err(_) => break,
+                    Ok(_) => {}
+                }
+            }
+        });
+
+        Self {
+            port,
+            peers,
+            requests,
+            replies,
+        }
+    }
+
+    /// Port the server listens on.
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+
+    /// Make `username` resolvable through `GetPeerAddress`.
+    pub async fn add_peer(&self, username: &str, addr: SocketAddr) {
+        self.peers.lock().await.insert(username.to_string(), addr);
+    }
+
+    /// Push a message with `code` and `body` to the client.
+    pub fn send(&self, code: u32, body: &[u8]) {
+        let _ = self.replies.send(frame(code, body));
+    }
+
+    /// Relay a `ConnectToPeer` request from `username`, reachable at `addr`.
+    pub fn relay_connect_to_peer(
+        &self,
+        username: &str,
+        connection_type: ConnectionType,
+        addr: SocketAddr,
+        token: u32,
+    ) {
+        let SocketAddr::V4(addr) = addr else {
+            panic!("fake peers listen on IPv4");
+        };
+        let mut body = Vec::new();
+        put_string(&mut body, username);
+        put_string(&mut body, connection_type.as_ref());
+        body.extend_from_slice(&u32::from(*addr.ip()).to_le_bytes());
+        body.extend_from_slice(&(addr.port() as u32).to_le_bytes());
+        body.extend_from_slice(&token.to_le_bytes());
+        body.push(0);
+        self.send(CONNECT_TO_PEER, &body);
+    }
+
+    /// Offer the client `parents` as distributed network parents.
+    pub fn offer_parents(&self, parents: &[(&str, SocketAddr)]) {
+        let mut body = Vec::new();
+        body.extend_from_slice(&(parents.len() as u32).to_le_bytes());
+        for (username, addr) in parents {
+            let ip = match addr.ip() {
+                std::net::IpAddr::V4(ip) => ip,
+                std::net::IpAddr::V6(_) => panic!("fake parents must be IPv4"),
+            };
+            put_string(&mut body, username);
+            body.extend_from_slice(&u32::from(ip).to_le_bytes());
+            body.extend_from_slice(&(addr.port() as u32).to_le_bytes());
+        }
+        self.send(POSSIBLE_PARENTS, &body);
+    }
+
+    /// Wait for the next request with `code`, skipping others.
+    pub async fn next_request(&mut self, code: u32) -> Vec<u8> {
+        tokio::time::timeout(TIMEOUT, async {
+            loop {
+                let (got, body) = self.requests.recv().await.expect("server stopped");
+                if got == code {
+                    return body;
+                }
+            }
+        })
+        .await
+        .unwrap_or_else(|_| panic!("no request with code {} received", code))
+    }
+}
+
+/// A fake peer accepting connections on an ephemeral localhost port.
+pub struct FakePeer {
+    listener: TcpListener,
+}
+
+impl FakePeer {
+    /// Bind a peer on an ephemeral port.
+    pub async fn bind() -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        Self { listener }
+    }
+
+    /// Address the peer listens on.
+    pub fn addr(&self) -> SocketAddr {
+        self.listener.local_addr().unwrap()
+    }
+
+    /// Accept the next connection and read its `PeerInit`.
+    pub async fn accept(&self) -> PeerSession {
+        let (mut session, init) = self.accept_connection().await;
+        match init {
+            PeerConnectionMessage::PeerInit {
+                username,
+                connection_type,
+                ..
+            } => {
+                session.username = username;
+                session.connection_type = connection_type;
+            }
+            other => panic!("expected PeerInit, got {:?}", other),
+        }
+        session
+    }
+
+    /// Accept the next connection and read its `PierceFireWall` token.
+    pub async fn accept_pierce(&self) -> (PeerSession, u32) {
+        let (session, init) = self.accept_connection().await;
+        match init {
+            PeerConnectionMessage::PierceFirewall(token) => (session, token),
+            other => panic!("expected PierceFirewall, got {:?}", other),
+        }
+    }
+
+    async fn accept_connection(&self) -> (PeerSession, PeerConnectionMessage) {
+        let (stream, _) = tokio::time::timeout(TIMEOUT, self.listener.accept())
+            .await
+            .expect("no connection to fake peer")
+            .unwrap();
+        let mut session = PeerSession {
+            stream,
+            buffer: BytesMut::new(),
+            username: String::new(),
+            connection_type: ConnectionType::HandShake,
+        };
+
+        let frame = session.read_frame(5).await;
+        let mut cursor = Cursor::new(frame.as_slice());
+        let header = PeerConnectionMessage::check(&mut cursor).unwrap();
+        let init = PeerConnectionMessage::parse(&mut cursor, &header).unwrap();
+        (session, init)
+    }
+}
+
+/// An accepted peer connection.
+pub struct PeerSession {
+    stream: TcpStream,
+    buffer: BytesMut,
+    /// Username announced by the remote side.
+    pub username: String,
+    /// Connection type announced by the remote side.
+    pub connection_type: ConnectionType,
+}
+
+impl PeerSession {
+    /// Connect to `addr` and announce ourselves as `username`.
+    pub async fn connect(
+        addr: SocketAddr,
+        username: &str,
+        connection_type: ConnectionType,
+    ) -> PeerSession {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        let init = PeerConnectionMessage::PeerInit {
+            username: username.to_string(),
+            connection_type,
+            token: 0,
+        };
+        write_message(&mut stream, &init).await;
+        PeerSession {
+            stream,
+            buffer: BytesMut::new(),
+            username: username.to_string(),
+            connection_type,
+        }
+    }
+
+    /// Connect to `addr` to answer the indirect connection request `token`.
+    pub async fn pierce(
+        addr: SocketAddr,
+        username: &str,
+        connection_type: ConnectionType,
+        token: u32,
+    ) -> PeerSession {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        write_message(&mut stream, &PeerConnectionMessage::PierceFirewall(token)).await;
+        PeerSession {
+            stream,
+            buffer: BytesMut::new(),
+            username: username.to_string(),
+            connection_type,
+        }
+    }
+
+    /// Receive the next peer message.
+    pub async fn recv(&mut self) -> PeerResponse {
+        let frame = self.read_frame(8).await;
+        let mut cursor = Cursor::new(frame.as_slice());
+        let header = PeerResponse::check(&mut cursor).unwrap();
+        PeerResponse::parse(&mut cursor, &header).unwrap()
+    }
+
+    /// Send a peer message.
+    pub async fn send(&mut self, request: PeerRequest) {
+        write_message(&mut self.stream, &request).await;
+    }
+
+    /// Receive the next distributed message.
+    pub async fn recv_distributed(&mut self) -> DistributedMessage {
+        let frame = self.read_frame(5).await;
+        let mut cursor = Cursor::new(frame.as_slice());
+        let header = DistributedMessage::check(&mut cursor).unwrap();
+        DistributedMessage::parse(&mut cursor, &header).unwrap()
+    }
+
+    /// Send a distributed message.
+    pub async fn send_distributed(&mut self, message: DistributedMessage) {
+        write_message(&mut self.stream, &message).await;
+    }
+
+    /// Take the underlying stream, e.g. to use the connection for file data.
+    pub fn into_stream(self) -> TcpStream {
+        self.stream
+    }
+
+    /// Read one length-prefixed frame, including its length prefix.
+    async fn read_frame(&mut self, header_len: usize) -> Vec<u8> {
+        tokio::time::timeout(TIMEOUT, async {
+            loop {
+                if self.buffer.len() >= header_len {
+                    let len = u32::from_le_bytes(self.buffer[..4].try_into().unwrap()) as usize;
+                    if self.buffer.len() >= 4 + len {
+                        return self.buffer.split_to(4 + len).to_vec();
+                    }
+                }
+                let n = self.stream.read_buf(&mut self.buffer).await.unwrap();
+                assert!(n > 0, "connection closed by engine");
+            }
+        })
+        .await
+        .expect("no message from engine")
+    }
+}
+
+/// Open a file connection to the downloader at `addr` as `username`.
+///
+/// Sends the transfer ticket and returns the stream along with the offset the
+/// downloader asked to resume from.
+pub async fn open_file_connection(
+    addr: SocketAddr,
+    username: &str,
+    ticket: u32,
+) -> (TcpStream, u64) {
+    let mut stream = PeerSession::connect(addr, username, ConnectionType::FileTransfer)
+        .await
+        .into_stream();
+    stream.write_u32_le(ticket).await.unwrap();
+
+    let offset = tokio::time::timeout(TIMEOUT, stream.read_u64_le())
+        .await
+        .expect("no offset from downloader")
+        .unwrap();
+    (stream, offset)
+}
+
+/// Start an engine logged in to `server` as `lcars`, with its listener running.
+///
+/// Returns the engine and the address of its peer listener.
+pub async fn logged_in_engine(
+    server: &FakeServer,
+    download_dir: &Path,
+) -> (Arc<SoulseekEngine>, SocketAddr) {
+    start_engine(engine_config(server, download_dir)).await
+}
+
+/// Configuration for an engine talking to `server` as `lcars`.
+pub fn engine_config(server: &FakeServer, download_dir: &Path) -> SoulseekConfig {
+    SoulseekConfig {
+        username: Some("lcars".to_string()),
+        password: Some("secret".to_string()),
+        server_host: "127.0.0.1".to_string(),
+        server_port: server.port(),
+        listen_port: free_port(),
+        download_dir: download_dir.to_path_buf(),
+        auto_reconnect: false,
+        keepalive_interval: 0,
+        ..Default::default()
+    }
+}
+
+/// Start an engine with `config`, log in and run its listener.
+///
+/// Returns the engine and the address of its peer listener.
+pub async fn start_engine(config: SoulseekConfig) -> (Arc<SoulseekEngine>, SocketAddr) {
+    let listen_port = config.listen_port;
+    let engine = SoulseekEngine::new_shared(config).await.unwrap();
+    engine.connect().await.unwrap();
+    engine.start_listener().await.unwrap();
+
+    tokio::time::timeout(TIMEOUT, async {
+        while !engine.is_connected().await {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("engine did not log in");
+
+    (engine, SocketAddr::from(([127, 0, 0, 1], listen_port)))
+}
+
+/// Find a free localhost port.
+pub fn free_port() -> u16 {
+    std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap()
+        .port()
+}
+
+async fn write_message(stream: &mut TcpStream, message: &(impl ToBytes + Sync)) {
+    let mut data = Vec::new();
+    let mut writer = BufWriter::new(&mut data);
+    message.write_to_buf(&mut writer).await.unwrap();
+    writer.flush().await.unwrap();
+    stream.write_all(&data).await.unwrap();
+}
+
+/// Build a server message frame.
+fn frame(code: u32, body: &[u8]) -> Vec<u8> {
+    let mut frame = Vec::with_capacity(body.len() + 8);
+    frame.extend_from_slice(&(body.len() as u32 + 4).to_le_bytes());
+    frame.extend_from_slice(&code.to_le_bytes());
+    frame.extend_from_slice(body);
+    frame
+}
+
+fn put_string(buf: &mut Vec<u8>, value: &str) {
+    buf.extend_from_slice(&(value.len() as u32).to_le_bytes());
+    buf.extend_from_slice(value.as_bytes());
+}
+
+fn read_string(body: &[u8]) -> String {
+    let len = u32::from_le_bytes(body[..4].try_into().unwrap()) as usize;
+    String::from_utf8_lossy(&body[4..4 + len]).to_string()
+}
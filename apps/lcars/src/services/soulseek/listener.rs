@@ -2,6 +2,8 @@
 //!
 //! This module listens for incoming P2P connections from other Soulseek users
 //! and handles requests to browse our shares, search our files, and download from us.
+//! Connections we open at a peer's request are served the same way, and peers
+//! answering our own indirect connection requests are handed to the waiting task.
 
 use bytes::{Buf, BytesMut};
 use soulseek_protocol::{
@@ -18,39 +20,54 @@ use soulseek_protocol::{
     },
     MessageCode, ProtocolHeader, ProtocolMessage,
 };
+use std::collections::HashMap;
 use std::io::Cursor;
 use std::net::SocketAddr;
 use std::sync::Arc;
 use tokio::io::{AsyncReadExt, AsyncWriteExt, BufWriter};
 use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::RwLock;
+use tokio::sync::{broadcast, RwLock};
 
 use crate::error::{AppError, Result};
+use crate::services::bandwidth::RateLimiter;
 
+use super::downloads::{dispatch_peer_message, TransferRoutes, TransferSignal};
+use super::engine::process_search_results;
+use super::events::SoulseekEvent;
+use super::peer::PendingPierce;
 use super::shares::ShareIndex;
+use super::types::SearchState;
 use super::uploads::UploadQueue;
 
 /// Buffer size for reading from peer connections.
 const READ_BUFFER_SIZE: usize = 65536;
 
+/// State needed to serve peer connections.
+///
+/// Shared by the listener and by connections we open at a peer's request.
+#[derive(Clone)]
+pub struct PeerContext {
+    pub share_index: Arc<RwLock<ShareIndex>>,
+    pub upload_queue: Arc<RwLock<UploadQueue>>,
+    pub transfer_routes: Arc<RwLock<TransferRoutes>>,
+    /// Indirect connections waiting for a `PierceFireWall`, indexed by token.
+    pub pending_pierces: Arc<RwLock<HashMap<u32, PendingPierce>>>,
+    /// Active searches, filled by the `SearchReply` messages peers send us.
+    pub searches: Arc<RwLock<HashMap<u32, SearchState>>>,
+    pub event_tx: broadcast::Sender<SoulseekEvent>,
+    /// Throttles uploads to the configured speed limit.
+    pub upload_limiter: Arc<RateLimiter>,
+}
+
 /// Handles incoming P2P connections for file sharing.
 pub struct PeerListener {
     listener: TcpListener,
-    share_index: Arc<RwLock<ShareIndex>>,
-    upload_queue: Arc<RwLock<UploadQueue>>,
-    our_username: String,
-    upload_speed: u32,
+    context: PeerContext,
 }
 
 impl PeerListener {
     /// Bind to the specified port and prepare to accept connections.
-    pub async fn bind(
-        port: u16,
-        share_index: Arc<RwLock<ShareIndex>>,
-        upload_queue: Arc<RwLock<UploadQueue>>,
-        our_username: String,
-        upload_speed: u32,
-    ) -> Result<Self> {
+    pub async fn bind(port: u16, context: PeerContext) -> Result<Self> {
         let addr = format!("0.0.0.0:{}", port);
         let listener = TcpListener::bind(&addr).await.map_err(|e| {
             AppError::Internal(format!("Failed to bind listener on {}: {}", addr, e))
@@ -58,13 +75,7 @@ impl PeerListener {
 
         tracing::info!(port = port, "Peer listener bound");
 
-        Ok(Self {
-            listener,
-            share_index,
-            upload_queue,
-            our_username,
-            upload_speed,
-        })
+        Ok(Self { listener, context })
     }
 
     /// Run the listener, accepting and handling connections.
@@ -76,22 +87,10 @@ impl PeerListener {
                 Ok((stream, addr)) => {
                     tracing::debug!(peer = %addr, "Incoming peer connection");
 
-                    let share_index = Arc::clone(&self.share_index);
-                    let upload_queue = Arc::clone(&self.upload_queue);
-                    let our_username = self.our_username.clone();
-                    let upload_speed = self.upload_speed;
+                    let context = self.context.clone();
 
                     tokio::spawn(async move {
-                        if let Err(e) = Self::handle_connection(
-                            stream,
-                            addr,
-                            share_index,
-                            upload_queue,
-                            our_username,
-                            upload_speed,
-                        )
-                        .await
-                        {
+                        if let Err(e) = Self::handle_connection(stream, addr, context).await {
                             tracing::debug!(peer = %addr, error = %e, "Peer connection error");
                         }
                     });
@@ -107,17 +106,14 @@ impl PeerListener {
     async fn handle_connection(
         mut stream: TcpStream,
         addr: SocketAddr,
-        share_index: Arc<RwLock<ShareIndex>>,
-        upload_queue: Arc<RwLock<UploadQueue>>,
-        our_username: String,
-        upload_speed: u32,
+        context: PeerContext,
     ) -> Result<()> {
         let mut buffer = BytesMut::with_capacity(READ_BUFFER_SIZE);
 
         // First, read the connection init message
         let init_msg = Self::read_connection_message(&mut stream, &mut buffer).await?;
 
-        let (peer_username, connection_type, _token) = match init_msg {
+        match init_msg {
             PeerConnectionMessage::PeerInit {
                 username,
                 connection_type,
@@ -130,41 +126,48 @@ impl PeerListener {
                     token = token,
                     "Peer init received"
                 );
-                (username, connection_type, token)
+                Self::serve(stream, buffer, username, connection_type, context).await
             }
             PeerConnectionMessage::PierceFirewall(token) => {
-                tracing::debug!(
-                    peer = %addr,
-                    token = token,
-                    "PierceFirewall received (not implemented)"
-                );
-                return Ok(());
+                tracing::debug!(peer = %addr, token = token, "PierceFirewall received");
+
+                // A peer answering our indirect connection request
+                let pending = context.pending_pierces.write().await.remove(&token);
+                match pending {
+                    Some(pending) => {
+                        let _ = pending.tx.send(Ok((stream, buffer)));
+                    }
+                    None => {
+                        tracing::debug!(peer = %addr, token = token, "No indirect connection pending for token");
+                    }
+                }
+                Ok(())
             }
-        };
+        }
+    }
 
+    /// Serve an established connection according to its type.
+    pub async fn serve(
+        stream: TcpStream,
+        buffer: BytesMut,
+        peer_username: String,
+        connection_type: ConnectionType,
+        context: PeerContext,
+    ) -> Result<()> {
         match connection_type {
             ConnectionType::PeerToPeer => {
-                Self::handle_p2p_connection(
-                    stream,
-                    buffer,
-                    peer_username,
-                    share_index,
-                    upload_queue,
-                    our_username,
-                    upload_speed,
-                )
-                .await
+                Self::handle_p2p_connection(stream, buffer, peer_username, &context).await
             }
             ConnectionType::FileTransfer => {
-                Self::handle_file_transfer(stream, buffer, peer_username, share_index, upload_queue)
-                    .await
+                Self::handle_file_transfer(stream, buffer, peer_username, &context).await
             }
             ConnectionType::DistributedNetwork => {
-                tracing::trace!(peer = %addr, "Distributed network connection (not implemented)");
+                // We join the distributed network as a leaf and do not accept children
+                tracing::trace!(username = %peer_username, "Refusing distributed child connection");
                 Ok(())
             }
             ConnectionType::HandShake => {
-                tracing::trace!(peer = %addr, "Handshake connection (not implemented)");
+                tracing::trace!(username = %peer_username, "Handshake connection (not implemented)");
                 Ok(())
             }
         }
@@ -216,14 +219,13 @@ impl PeerListener {
         mut stream: TcpStream,
         mut buffer: BytesMut,
         peer_username: String,
-        share_index: Arc<RwLock<ShareIndex>>,
-        upload_queue: Arc<RwLock<UploadQueue>>,
-        _our_username: String,
-        _upload_speed: u32,
+        context: &PeerContext,
     ) -> Result<()> {
+        let share_index = &context.share_index;
+        let upload_queue = &context.upload_queue;
         loop {
-            // Try to parse a message from the buffer
-            if let Some(msg_result) = Self::try_parse_peer_message(&mut buffer) {
+            // Handle every complete message in the buffer
+            while let Some(msg_result) = Self::try_parse_peer_message(&mut buffer) {
                 match msg_result {
                     Ok(msg) => {
                         tracing::debug!(
@@ -234,7 +236,12 @@ impl PeerListener {
 
                         match msg {
                             PeerResponse::SharesRequest => {
-                                Self::send_shares_reply(&mut stream, &share_index).await?;
+                                Self::send_shares_reply(&mut stream, share_index).await?;
+                            }
+                            PeerResponse::SearchReply(reply) => {
+                                // Results for one of our searches
+                                process_search_results(&context.searches, &context.event_tx, reply)
+                                    .await;
                             }
                             PeerResponse::UserInfoRequest => {
                                 // We don't implement user info for now
@@ -243,18 +250,33 @@ impl PeerListener {
                                     "UserInfoRequest not implemented"
                                 );
                             }
-                            PeerResponse::TransferRequest(transfer_req) => {
-                                if transfer_req.is_download_request() {
-                                    // Peer wants to download from us
-                                    Self::handle_transfer_request(
-                                        &mut stream,
-                                        &peer_username,
-                                        &transfer_req.filename,
-                                        transfer_req.ticket,
-                                        &share_index,
-                                        &upload_queue,
-                                    )
-                                    .await?;
+                            PeerResponse::TransferRequest(transfer_req)
+                                if transfer_req.is_download_request() =>
+                            {
+                                // Peer wants to download from us
+                                Self::handle_transfer_request(
+                                    &mut stream,
+                                    &peer_username,
+                                    &transfer_req.filename,
+                                    transfer_req.ticket,
+                                    share_index,
+                                    upload_queue,
+                                )
+                                .await?;
+                            }
+                            msg @ (PeerResponse::TransferRequest(_)
+                            | PeerResponse::PlaceInQueueReply(_)
+                            | PeerResponse::QueueFailed(_)
+                            | PeerResponse::UploadFailed(_)) => {
+                                // Updates about files we are downloading from the peer
+                                if let Some(reply) = dispatch_peer_message(
+                                    &context.transfer_routes,
+                                    &peer_username,
+                                    msg,
+                                )
+                                .await
+                                {
+                                    Self::send_peer_request(&mut stream, reply).await?;
                                 }
                             }
                             PeerResponse::QueueUpload(queue_upload) => {
@@ -263,8 +285,8 @@ impl PeerListener {
                                     &mut stream,
                                     &peer_username,
                                     &queue_upload.file_name,
-                                    &share_index,
-                                    &upload_queue,
+                                    share_index,
+                                    upload_queue,
                                 )
                                 .await?;
                             }
@@ -273,7 +295,7 @@ impl PeerListener {
                                     &mut stream,
                                     &peer_username,
                                     &place_req.file_name,
-                                    &upload_queue,
+                                    upload_queue,
                                 )
                                 .await?;
                             }
@@ -376,6 +398,29 @@ impl PeerListener {
         Ok(())
     }
 
+    /// Serialize and send a single peer message.
+    async fn send_peer_request(stream: &mut TcpStream, request: PeerRequest) -> Result<()> {
+        let mut data = Vec::new();
+        let mut writer = BufWriter::new(&mut data);
+
+        request
+            .write_to_buf(&mut writer)
+            .await
+            .map_err(|e| AppError::Internal(format!("Failed to serialize peer message: {}", e)))?;
+
+        writer
+            .flush()
+            .await
+            .map_err(|e| AppError::Internal(format!("Failed to flush buffer: {}", e)))?;
+
+        stream
+            .write_all(&data)
+            .await
+            .map_err(|e| AppError::Internal(format!("Failed to send peer message: {}", e)))?;
+
+        Ok(())
+    }
+
     /// Handle a transfer request (peer wants to download from us).
     async fn handle_transfer_request(
         stream: &mut TcpStream,
@@ -554,8 +599,7 @@ impl PeerListener {
         mut stream: TcpStream,
         mut buffer: BytesMut,
         peer_username: String,
-        _share_index: Arc<RwLock<ShareIndex>>,
-        upload_queue: Arc<RwLock<UploadQueue>>,
+        context: &PeerContext,
     ) -> Result<()> {
         // For file transfers, we need to wait for the peer to tell us which file
         // they want via a ticket. The file transfer protocol is:
@@ -583,13 +627,20 @@ impl PeerListener {
         tracing::debug!(username = %peer_username, ticket = ticket, "File transfer requested");
 
         // Find the upload by ticket
+        let upload_queue = &context.upload_queue;
         let queue = upload_queue.read().await;
         let upload = queue.find_by_ticket(&peer_username, ticket).cloned();
         drop(queue);
 
         if let Some(upload_state) = upload {
             // Stream the file
-            Self::stream_file(&mut stream, &upload_state.local_path, upload_state.size).await?;
+            Self::stream_file(
+                &mut stream,
+                &upload_state.local_path,
+                upload_state.size,
+                &context.upload_limiter,
+            )
+            .await?;
 
             // Mark as completed
             let mut queue = upload_queue.write().await;
@@ -601,18 +652,39 @@ impl PeerListener {
                 "File upload completed"
             );
         } else {
-            tracing::warn!(
-                username = %peer_username,
-                ticket = ticket,
-                "No upload found for ticket"
-            );
+            let download = context
+                .transfer_routes
+                .read()
+                .await
+                .get_by_ticket(&peer_username, ticket);
+
+            if let Some(tx) = download {
+                // The peer is uploading a file we are downloading
+                tracing::debug!(
+                    username = %peer_username,
+                    ticket = ticket,
+                    "Handing file connection to download"
+                );
+                let _ = tx.send(TransferSignal::Connected(stream, buffer)).await;
+            } else {
+                tracing::warn!(
+                    username = %peer_username,
+                    ticket = ticket,
+                    "No transfer found for ticket"
+                );
+            }
         }
 
         Ok(())
     }
 
-    /// Stream a file to the peer.
-    async fn stream_file(stream: &mut TcpStream, path: &std::path::Path, size: u64) -> Result<()> {
+    /// Stream a file to the peer, within the upload speed limit.
+    async fn stream_file(
+        stream: &mut TcpStream,
+        path: &std::path::Path,
+        size: u64,
+        limiter: &RateLimiter,
+    ) -> Result<()> {
         use tokio::fs::File;
 
         let mut file = File::open(path)
@@ -637,6 +709,7 @@ impl PeerListener {
                 break;
             }
 
+            limiter.acquire(n).await;
             stream
                 .write_all(&buf[..n])
                 .await
@@ -650,6 +723,86 @@ impl PeerListener {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::services::soulseek::fake::{logged_in_engine, FakeServer, PeerSession, TIMEOUT};
+    use soulseek_protocol::peers::p2p::{
+        search::SearchReply,
+        shared_directories::{Attribute, File},
+    };
+
+    fn search_reply(ticket: u32, name: &str) -> SearchReply {
+        SearchReply {
+            username: "sharer".to_string(),
+            ticket,
+            files: vec![File {
+                name: name.to_string(),
+                size: 1234,
+                extension: "flac".to_string(),
+                attributes: vec![Attribute {
+                    place: 1,
+                    attribute: 240,
+                }],
+            }],
+            slot_free: true,
+            average_speed: 500,
+            queue_length: 2,
+            locked_results: vec![],
+        }
+    }
+
+    #[tokio::test]
+    async fn test_search_reply_recorded() {
+        let dir = tempfile::tempdir().unwrap();
+        let server = FakeServer::start().await;
+        let (engine, listen_addr) = logged_in_engine(&server, dir.path()).await;
+        let mut events = engine.subscribe();
+
+        let ticket = engine.search("artist album").await.unwrap();
+
+        // A peer with matching files connects to us and sends its results,
+        // followed by a late reply to a search we never made
+        let mut session =
+            PeerSession::connect(listen_addr, "sharer", ConnectionType::PeerToPeer).await;
+        session
+            .send(PeerRequest::SearchReply(search_reply(
+                ticket.wrapping_add(1),
+                "@@music\\Other\\02.flac",
+            )))
+            .await;
+        session
+            .send(PeerRequest::SearchReply(search_reply(
+                ticket,
+                "@@music\\Artist\\Album\\01.flac",
+            )))
+            .await;
+
+        let event = tokio::time::timeout(TIMEOUT, events.recv())
+            .await
+            .expect("no search result event")
+            .unwrap();
+        match event {
+            SoulseekEvent::SearchResult {
+                ticket: got,
+                username,
+                files,
+                ..
+            } => {
+                assert_eq!(got, ticket);
+                assert_eq!(username, "sharer");
+                assert_eq!(files.len(), 1);
+            }
+            other => panic!("expected SearchResult, got {:?}", other),
+        }
+
+        let search = engine.get_search_results(ticket).await.unwrap();
+        assert_eq!(search.results.len(), 1);
+        let result = &search.results[0];
+        assert!(result.has_free_slot);
+        assert_eq!(result.average_speed, 500);
+        assert_eq!(result.queue_length, 2);
+        assert_eq!(result.files[0].filename, "@@music\\Artist\\Album\\01.flac");
+        assert_eq!(result.files[0].size, 1234);
+        assert_eq!(result.files[0].duration, Some(240));
+    }
 
     #[test]
     fn test_parse_peer_message_incomplete() {
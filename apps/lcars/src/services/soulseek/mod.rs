@@ -5,8 +5,12 @@
 //! connections for file transfers.
 
 mod connection;
+mod distributed;
+mod downloads;
 mod engine;
 mod events;
+#[cfg(test)]
+mod fake;
 mod listener;
 mod peer;
 mod shares;
@@ -1,9 +1,11 @@
 //! Peer-to-peer connection handling for Soulseek file transfers.
 //!
-//! This module manages direct connections to Soulseek peers for browsing
-//! shared directories and downloading files.
+//! This module manages connections to Soulseek peers for browsing shared
+//! directories and downloading files. Peers that cannot be reached directly
+//! are asked through the server to connect to us instead.
 
 use bytes::{Buf, BytesMut};
+use rand::Rng;
 use soulseek_protocol::{
     frame::ToBytes,
     message_common::ConnectionType,
@@ -14,22 +16,33 @@ use soulseek_protocol::{
             shared_directories::SharedDirectories, PeerMessageCode, PeerMessageHeader,
         },
     },
+    server::{peer::RequestConnectionToPeer, request::ServerRequest},
     ProtocolHeader, ProtocolMessage,
 };
+use std::collections::HashMap;
 use std::io::Cursor;
+use std::net::Ipv4Addr;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::io::{AsyncReadExt, AsyncWriteExt, BufWriter};
 use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
 use tokio::net::TcpStream;
-use tokio::sync::{mpsc, oneshot, Mutex};
+use tokio::sync::{mpsc, oneshot, Mutex, RwLock};
 
 use crate::error::{AppError, Result};
 
+use super::connection::SoulseekConnection;
+use super::engine::{resolve_peer_address, PendingPeerAddress};
+
 /// Buffer size for reading from peer connections.
 const READ_BUFFER_SIZE: usize = 65536;
 
-/// Connection timeout for peer connections.
-const CONNECT_TIMEOUT_SECS: u64 = 30;
+/// Connection timeout for direct peer connections, before falling back to an
+/// indirect connection.
+const CONNECT_TIMEOUT_SECS: u64 = 10;
+
+/// Time a peer has to connect to us after we asked for an indirect connection.
+const INDIRECT_CONNECT_TIMEOUT_SECS: u64 = 20;
 
 /// A connection to a Soulseek peer.
 ///
@@ -49,7 +62,7 @@ impl PeerConnection {
     /// This establishes a direct TCP connection to the peer.
     pub async fn connect(
         username: &str,
-        ip: std::net::Ipv4Addr,
+        ip: Ipv4Addr,
         port: u32,
         our_username: &str,
         token: u32,
@@ -62,19 +75,34 @@ impl PeerConnection {
             "Connecting to peer"
         );
 
-        // Connect with timeout
-        let stream = tokio::time::timeout(
-            std::time::Duration::from_secs(CONNECT_TIMEOUT_SECS),
-            TcpStream::connect(&addr),
-        )
-        .await
-        .map_err(|_| {
-            AppError::ServiceUnavailable(format!("Connection to peer {} timed out", username))
-        })?
-        .map_err(|e| {
-            AppError::ServiceUnavailable(format!("Failed to connect to peer {}: {}", username, e))
-        })?;
+        let stream = open_stream(username, &addr).await?;
+        let (connection, message_rx) =
+            Self::from_stream(stream, BytesMut::with_capacity(READ_BUFFER_SIZE), username);
 
+        // Send PeerInit message to identify ourselves
+        let init = PeerConnectionMessage::PeerInit {
+            username: our_username.to_string(),
+            connection_type: ConnectionType::PeerToPeer,
+            token,
+        };
+        connection
+            .write_message(&init)
+            .await
+            .map_err(|e| AppError::Internal(format!("Failed to send PeerInit: {}", e)))?;
+
+        tracing::info!(addr = %addr, username = %username, "Connected to peer");
+
+        Ok((connection, message_rx))
+    }
+
+    /// Wrap an already established peer connection.
+    ///
+    /// `buffer` holds any bytes read past the connection handshake.
+    pub fn from_stream(
+        stream: TcpStream,
+        buffer: BytesMut,
+        username: &str,
+    ) -> (Self, mpsc::Receiver<PeerResponse>) {
         let (reader, writer) = stream.into_split();
         let writer = Arc::new(Mutex::new(BufWriter::new(writer)));
 
@@ -84,55 +112,37 @@ impl PeerConnection {
         // Create shutdown signal
         let (shutdown_tx, shutdown_rx) = oneshot::channel();
 
-        // Send PeerInit message to identify ourselves
-        {
-            let mut w = writer.lock().await;
-            let init = PeerConnectionMessage::PeerInit {
-                username: our_username.to_string(),
-                connection_type: ConnectionType::PeerToPeer,
-                token,
-            };
-            init.write_to_buf(&mut *w)
-                .await
-                .map_err(|e| AppError::Internal(format!("Failed to send PeerInit: {}", e)))?;
-            w.flush()
-                .await
-                .map_err(|e| AppError::Internal(format!("Failed to flush writer: {}", e)))?;
-        }
-
         // Spawn the read loop
-        let username_clone = username.to_string();
         tokio::spawn(Self::read_loop(
             reader,
+            buffer,
             message_tx,
             shutdown_rx,
-            username_clone,
+            username.to_string(),
         ));
 
-        tracing::info!(addr = %addr, username = %username, "Connected to peer");
-
-        Ok((
+        (
             Self {
                 username: username.to_string(),
                 writer,
                 shutdown_tx: Some(shutdown_tx),
             },
             message_rx,
-        ))
+        )
     }
 
     /// Send a peer request.
     pub async fn send(&self, request: PeerRequest) -> Result<()> {
-        let mut writer = self.writer.lock().await;
-        request
-            .write_to_buf(&mut *writer)
+        self.write_message(&request)
             .await
-            .map_err(|e| AppError::Internal(format!("Failed to write peer request: {}", e)))?;
-        writer
-            .flush()
-            .await
-            .map_err(|e| AppError::Internal(format!("Failed to flush writer: {}", e)))?;
-        Ok(())
+            .map_err(|e| AppError::Internal(format!("Failed to write peer request: {}", e)))
+    }
+
+    /// Serialize a message and flush it to the peer.
+    async fn write_message(&self, message: &(impl ToBytes + Sync)) -> std::io::Result<()> {
+        let mut writer = self.writer.lock().await;
+        message.write_to_buf(&mut *writer).await?;
+        writer.flush().await
     }
 
     /// Request the peer's shared directories.
@@ -158,13 +168,32 @@ impl PeerConnection {
     /// Background task that reads from the peer and parses messages.
     async fn read_loop(
         mut reader: OwnedReadHalf,
+        mut buffer: BytesMut,
         message_tx: mpsc::Sender<PeerResponse>,
         mut shutdown_rx: oneshot::Receiver<()>,
         username: String,
     ) {
-        let mut buffer = BytesMut::with_capacity(READ_BUFFER_SIZE);
-
         loop {
+            // Try to parse messages from buffer
+            while let Some(response) = Self::try_parse_message(&mut buffer) {
+                match response {
+                    Ok(msg) => {
+                        tracing::trace!(username = %username, ?msg, "Parsed peer message");
+                        if message_tx.send(msg).await.is_err() {
+                            tracing::debug!(username = %username, "Message receiver dropped");
+                            return;
+                        }
+                    }
+                    Err(e) => {
+                        tracing::warn!(
+                            username = %username,
+                            error = %e,
+                            "Failed to parse peer message"
+                        );
+                    }
+                }
+            }
+
             tokio::select! {
                 _ = &mut shutdown_rx => {
                     tracing::debug!(username = %username, "Peer read loop received shutdown signal");
@@ -178,26 +207,6 @@ impl PeerConnection {
                         }
                         Ok(n) => {
                             tracing::trace!(username = %username, bytes = n, "Received data from peer");
-
-                            // Try to parse messages from buffer
-                            while let Some(response) = Self::try_parse_message(&mut buffer) {
-                                match response {
-                                    Ok(msg) => {
-                                        tracing::trace!(username = %username, ?msg, "Parsed peer message");
-                                        if message_tx.send(msg).await.is_err() {
-                                            tracing::debug!(username = %username, "Message receiver dropped");
-                                            return;
-                                        }
-                                    }
-                                    Err(e) => {
-                                        tracing::warn!(
-                                            username = %username,
-                                            error = %e,
-                                            "Failed to parse peer message"
-                                        );
-                                    }
-                                }
-                            }
                         }
                         Err(e) => {
                             tracing::error!(username = %username, error = %e, "Error reading from peer");
@@ -255,6 +264,163 @@ impl Drop for PeerConnection {
     }
 }
 
+/// An outgoing connection waiting for the peer to reach us with `PierceFireWall`.
+pub struct PendingPierce {
+    /// Peer we asked to connect to us.
+    pub username: String,
+    /// Channel to hand the pierced stream (and any bytes read past the
+    /// handshake) to the waiting task.
+    pub tx: oneshot::Sender<Result<(TcpStream, BytesMut)>>,
+}
+
+/// Opens P2P connections to peers.
+///
+/// A direct connection is tried first. When the peer cannot be reached (it is
+/// usually firewalled) we ask the server to relay a `ConnectToPeer` request and
+/// wait for the peer to connect to our listener with `PierceFireWall`.
+#[derive(Clone)]
+pub struct PeerConnector {
+    /// Username we are logged in with.
+    pub our_username: String,
+    /// Server connection, used to look up peers and relay connection requests.
+    pub connection: Arc<RwLock<Option<SoulseekConnection>>>,
+    /// Pending peer address requests shared with the engine.
+    pub pending_peer_addresses: Arc<RwLock<HashMap<String, PendingPeerAddress>>>,
+    /// Indirect connections waiting for the peer, indexed by token.
+    pub pending_pierces: Arc<RwLock<HashMap<u32, PendingPierce>>>,
+}
+
+impl PeerConnector {
+    /// Open a P2P connection to `username`.
+    pub async fn connect(
+        &self,
+        username: &str,
+    ) -> Result<(PeerConnection, mpsc::Receiver<PeerResponse>)> {
+        let (ip, port) =
+            resolve_peer_address(&self.connection, &self.pending_peer_addresses, username).await?;
+        if port == 0 {
+            return Err(AppError::ServiceUnavailable(format!(
+                "User {} is offline",
+                username
+            )));
+        }
+
+        let token: u32 = rand::thread_rng().gen();
+        match PeerConnection::connect(username, ip, port, &self.our_username, token).await {
+            Ok(connection) => Ok(connection),
+            Err(e) => {
+                tracing::debug!(
+                    username = %username,
+                    error = %e,
+                    "Direct peer connection failed, trying indirect connection"
+                );
+                self.connect_indirect(username, token).await
+            }
+        }
+    }
+
+    /// Ask the peer, through the server, to connect to us.
+    async fn connect_indirect(
+        &self,
+        username: &str,
+        token: u32,
+    ) -> Result<(PeerConnection, mpsc::Receiver<PeerResponse>)> {
+        let (tx, rx) = oneshot::channel();
+        {
+            let mut pending = self.pending_pierces.write().await;
+            pending.insert(
+                token,
+                PendingPierce {
+                    username: username.to_string(),
+                    tx,
+                },
+            );
+        }
+
+        let request = ServerRequest::ConnectToPeer(RequestConnectionToPeer {
+            token,
+            username: username.to_string(),
+            connection_type: ConnectionType::PeerToPeer,
+        });
+        let result = match self.send_request(request).await {
+            Ok(()) => tokio::time::timeout(Duration::from_secs(INDIRECT_CONNECT_TIMEOUT_SECS), rx)
+                .await
+                .map_err(|_| {
+                    AppError::ServiceUnavailable(format!(
+                        "Timed out waiting for peer {} to connect",
+                        username
+                    ))
+                })
+                .and_then(|result| {
+                    result.map_err(|_| {
+                        AppError::Internal("Indirect connection request cancelled".to_string())
+                    })?
+                }),
+            Err(e) => Err(e),
+        };
+
+        // Clean up the pending request unless the listener or server resolved it
+        self.pending_pierces.write().await.remove(&token);
+
+        let (stream, buffer) = result?;
+        tracing::info!(username = %username, token = token, "Indirect connection to peer established");
+        Ok(PeerConnection::from_stream(stream, buffer, username))
+    }
+
+    /// Send a request to the server.
+    async fn send_request(&self, request: ServerRequest) -> Result<()> {
+        let conn_guard = self.connection.read().await;
+        let connection = conn_guard.as_ref().ok_or_else(|| {
+            AppError::ServiceUnavailable("Not connected to Soulseek server".to_string())
+        })?;
+        connection.send(request).await
+    }
+}
+
+/// Answer a peer's connection request relayed by the server.
+///
+/// Connects to the peer and identifies the connection with `PierceFireWall`
+/// and the request token, after which the peer treats it like any connection
+/// it opened itself.
+pub async fn connect_back(
+    username: &str,
+    ip: Ipv4Addr,
+    port: u32,
+    token: u32,
+) -> Result<TcpStream> {
+    let addr = format!("{}:{}", ip, port);
+    tracing::debug!(addr = %addr, username = %username, token = token, "Connecting back to peer");
+
+    let mut stream = open_stream(username, &addr).await?;
+
+    let mut writer = BufWriter::new(&mut stream);
+    PeerConnectionMessage::PierceFirewall(token)
+        .write_to_buf(&mut writer)
+        .await
+        .map_err(|e| AppError::Internal(format!("Failed to send PierceFireWall: {}", e)))?;
+    writer
+        .flush()
+        .await
+        .map_err(|e| AppError::Internal(format!("Failed to flush writer: {}", e)))?;
+
+    Ok(stream)
+}
+
+/// Open a TCP connection to a peer with a timeout.
+async fn open_stream(username: &str, addr: &str) -> Result<TcpStream> {
+    tokio::time::timeout(
+        Duration::from_secs(CONNECT_TIMEOUT_SECS),
+        TcpStream::connect(addr),
+    )
+    .await
+    .map_err(|_| {
+        AppError::ServiceUnavailable(format!("Connection to peer {} timed out", username))
+    })?
+    .map_err(|e| {
+        AppError::ServiceUnavailable(format!("Failed to connect to peer {}: {}", username, e))
+    })
+}
+
 /// Result of browsing a peer's shared files.
 #[derive(Debug, Clone)]
 pub struct BrowseResult {
@@ -267,6 +433,128 @@ pub struct BrowseResult {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::services::soulseek::fake::{
+        free_port, logged_in_engine, FakePeer, FakeServer, PeerSession, TIMEOUT,
+    };
+    use std::net::SocketAddr;
+
+    const FIREWALLED: &str = "firewalled";
+    const CONNECT_TO_PEER: u32 = 18;
+    const CANT_CONNECT_TO_PEER: u32 = 1001;
+
+    /// Read the token and username of a `ConnectToPeer` request body.
+    fn parse_connect_to_peer(body: &[u8]) -> (u32, String, String) {
+        let mut cursor = Cursor::new(body);
+        let token = cursor.get_u32_le();
+        let username = take_string(&mut cursor);
+        let connection_type = take_string(&mut cursor);
+        (token, username, connection_type)
+    }
+
+    fn take_string(cursor: &mut Cursor<&[u8]>) -> String {
+        let len = cursor.get_u32_le() as usize;
+        let start = cursor.position() as usize;
+        cursor.advance(len);
+        String::from_utf8_lossy(&cursor.get_ref()[start..start + len]).to_string()
+    }
+
+    fn unreachable_addr() -> SocketAddr {
+        SocketAddr::from(([127, 0, 0, 1], free_port()))
+    }
+
+    #[tokio::test]
+    async fn test_indirect_connection_when_peer_unreachable() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut server = FakeServer::start().await;
+        server.add_peer(FIREWALLED, unreachable_addr()).await;
+        let (engine, listen_addr) = logged_in_engine(&server, dir.path()).await;
+
+        let browse = tokio::spawn(async move { engine.browse_user(FIREWALLED).await });
+
+        // The direct connection fails, so we ask the server to relay
+        let (token, username, connection_type) =
+            parse_connect_to_peer(&server.next_request(CONNECT_TO_PEER).await);
+        assert_eq!(username, FIREWALLED);
+        assert_eq!(connection_type, "P");
+
+        // The peer reaches our listener instead
+        let mut session =
+            PeerSession::pierce(listen_addr, FIREWALLED, ConnectionType::PeerToPeer, token).await;
+        assert!(matches!(session.recv().await, PeerResponse::SharesRequest));
+        session
+            .send(PeerRequest::SharesReply(SharedDirectories { dirs: vec![] }))
+            .await;
+
+        let result = tokio::time::timeout(TIMEOUT, browse)
+            .await
+            .unwrap()
+            .unwrap();
+        assert!(result.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_indirect_connection_fails_on_cant_connect() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut server = FakeServer::start().await;
+        server.add_peer(FIREWALLED, unreachable_addr()).await;
+        let (engine, _) = logged_in_engine(&server, dir.path()).await;
+
+        let browse = tokio::spawn(async move { engine.browse_user(FIREWALLED).await });
+
+        let (token, _, _) = parse_connect_to_peer(&server.next_request(CONNECT_TO_PEER).await);
+        server.send(CANT_CONNECT_TO_PEER, &token.to_le_bytes());
+
+        let result = tokio::time::timeout(TIMEOUT, browse)
+            .await
+            .unwrap()
+            .unwrap();
+        let error = result.unwrap_err().to_string();
+        assert!(
+            error.contains("Cannot connect to peer firewalled"),
+            "{}",
+            error
+        );
+    }
+
+    #[tokio::test]
+    async fn test_connect_back_on_peer_request() {
+        let dir = tempfile::tempdir().unwrap();
+        let server = FakeServer::start().await;
+        let peer = FakePeer::bind().await;
+        let (_engine, _) = logged_in_engine(&server, dir.path()).await;
+
+        server.relay_connect_to_peer(FIREWALLED, ConnectionType::PeerToPeer, peer.addr(), 99);
+
+        // We connect back and identify the connection with the token
+        let (mut session, token) = peer.accept_pierce().await;
+        assert_eq!(token, 99);
+
+        // The connection is then served like one accepted by the listener
+        session.send(PeerRequest::SharesRequest).await;
+        match session.recv().await {
+            PeerResponse::SharesReply(shares) => assert!(shares.dirs.is_empty()),
+            other => panic!("expected SharesReply, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_connect_back_failure_sends_cant_connect() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut server = FakeServer::start().await;
+        let (_engine, _) = logged_in_engine(&server, dir.path()).await;
+
+        server.relay_connect_to_peer(
+            FIREWALLED,
+            ConnectionType::PeerToPeer,
+            unreachable_addr(),
+            99,
+        );
+
+        let body = server.next_request(CANT_CONNECT_TO_PEER).await;
+        let mut cursor = Cursor::new(body.as_slice());
+        assert_eq!(cursor.get_u32_le(), 99);
+        assert_eq!(take_string(&mut cursor), FIREWALLED);
+    }
 
     #[test]
     fn test_try_parse_message_incomplete() {
@@ -332,6 +332,23 @@ impl ShareIndex {
             .collect()
     }
 
+    /// Search for files matching a query, as files for a search reply.
+    ///
+    /// Unlike shares replies, search results name files by their full virtual
+    /// path. At most `limit` files are returned.
+    pub fn search_reply_files(&self, query: &str, limit: usize) -> Vec<ProtocolFile> {
+        self.search(query)
+            .into_iter()
+            .take(limit)
+            .map(|f| ProtocolFile {
+                name: f.virtual_path.clone(),
+                size: f.size,
+                extension: f.extension.clone(),
+                attributes: f.attributes.to_protocol_attributes(),
+            })
+            .collect()
+    }
+
     /// Convert the index to protocol SharedDirectories format.
     pub fn to_protocol_directories(&self) -> SharedDirectories {
         let dirs: Vec<Directory> = self
@@ -436,6 +453,31 @@ mod tests {
         assert_eq!(results.len(), 0);
     }
 
+    #[tokio::test]
+    async fn test_search_reply_files() {
+        let temp_dir = TempDir::new().unwrap();
+
+        for track in 1..=3 {
+            fs::write(
+                temp_dir.path().join(format!("artist_{}.mp3", track)),
+                b"fake",
+            )
+            .unwrap();
+        }
+
+        let index = ShareIndex::scan(&[temp_dir.path().to_path_buf()], false)
+            .await
+            .unwrap();
+
+        let files = index.search_reply_files("artist", 2);
+        assert_eq!(files.len(), 2);
+        for file in &files {
+            assert!(file.name.contains('\\'), "{}", file.name);
+            assert_eq!(file.size, 4);
+            assert_eq!(file.extension, "mp3");
+        }
+    }
+
     #[tokio::test]
     async fn test_hidden_files() {
         let temp_dir = TempDir::new().unwrap();
@@ -8,9 +8,11 @@
 
 mod local;
 mod naming;
+mod smb;
 
 pub use local::LocalMount;
 pub use naming::NamingEngine;
+pub use smb::SmbMount;
 
 use async_trait::async_trait;
 use std::collections::HashMap;
@@ -94,6 +96,8 @@ pub struct ProcessedFile {
     pub mount_name: String,
     /// Size of the file in bytes.
     pub size: u64,
+    /// Copied for a move rule while the source is kept, see `keep_sources`.
+    pub move_pending: bool,
 }
 
 /// Video file extensions.
@@ -142,12 +146,26 @@ impl StorageManager {
                     Arc::new(LocalMount::new(mount_config.name.clone(), path))
                 }
                 MountType::Smb => {
-                    // SMB mount support is optional per the issue
-                    tracing::warn!(
-                        name = %mount_config.name,
-                        "SMB mounts not yet implemented, skipping"
-                    );
-                    continue;
+                    let host = mount_config.host.as_deref().ok_or_else(|| {
+                        AppError::Internal(format!(
+                            "SMB mount '{}' missing required 'host' field",
+                            mount_config.name
+                        ))
+                    })?;
+                    let share = mount_config.share.as_deref().ok_or_else(|| {
+                        AppError::Internal(format!(
+                            "SMB mount '{}' missing required 'share' field",
+                            mount_config.name
+                        ))
+                    })?;
+                    Arc::new(SmbMount::new(
+                        mount_config.name.clone(),
+                        host,
+                        share,
+                        mount_config.username.as_deref(),
+                        mount_config.password.as_deref(),
+                        mount_config.mount_point.clone(),
+                    ))
                 }
             };
 
@@ -189,6 +207,8 @@ impl StorageManager {
     ///
     /// * `download_path` - Path to the downloaded content (file or directory)
     /// * `media_info` - Metadata about the media for naming pattern expansion
+    /// * `keep_sources` - Copy instead of moving, for sources that are still in
+    ///   use (torrents that keep seeding). The caller deletes them later.
     ///
     /// # Returns
     ///
@@ -204,9 +224,11 @@ impl StorageManager {
         &self,
         download_path: &Path,
         media_info: &MediaInfo,
+        keep_sources: bool,
     ) -> Result<Vec<ProcessedFile>> {
         tracing::debug!(
             download_path = ?download_path,
+            keep_sources = keep_sources,
             "Processing completed download"
         );
 
@@ -218,7 +240,13 @@ impl StorageManager {
         };
 
         // Find media files
-        let files = find_media_files(download_path, media_type).await?;
+        let mut files = find_media_files(download_path, media_type).await?;
+
+        // Video downloads map to a single destination path: keep the largest file
+        // (the feature) and leave samples and extras behind
+        if matches!(media_type, MediaType::Movie | MediaType::Episode) {
+            files.truncate(1);
+        }
 
         if files.is_empty() {
             return Err(AppError::NotFound(format!(
@@ -285,7 +313,17 @@ impl StorageManager {
                     .unwrap_or(0);
 
                 // Execute action
+                let move_pending = keep_sources && rule.action == StorageAction::Move;
                 match rule.action {
+                    StorageAction::Move if keep_sources => {
+                        tracing::debug!(
+                            source = ?source_file,
+                            dest = ?dest_path,
+                            mount = %mount.name(),
+                            "Copying file, source is removed later"
+                        );
+                        mount.write_file(&source_file, &dest_path).await?;
+                    }
                     StorageAction::Move => {
                         tracing::debug!(
                             source = ?source_file,
@@ -321,6 +359,7 @@ impl StorageManager {
                     destination: full_dest,
                     mount_name: mount.name().to_string(),
                     size: file_size,
+                    move_pending,
                 });
 
                 // Only apply first matching rule per file
@@ -331,7 +370,7 @@ impl StorageManager {
         // Clean up empty directories in the download path
         // Use async metadata check instead of blocking is_dir()
         if let Ok(metadata) = tokio::fs::metadata(download_path).await {
-            if metadata.is_dir() {
+            if metadata.is_dir() && !keep_sources {
                 if let Err(e) = cleanup_empty_dirs(download_path).await {
                     tracing::warn!(
                         path = ?download_path,
@@ -364,6 +403,27 @@ impl StorageManager {
     pub fn naming(&self) -> &NamingEngine {
         &self.naming
     }
+
+    /// Deletes a library file given its full path, as recorded on the media.
+    ///
+    /// The file is removed through the mount it is stored on. Returns `false`
+    /// for files outside every mount, which were never moved into storage
+    /// (e.g. downloads no rule applied to) and are left alone.
+    pub async fn delete_library_file(&self, path: &Path) -> Result<bool> {
+        let mount = self
+            .mounts
+            .values()
+            .filter(|mount| path.starts_with(mount.root()))
+            .max_by_key(|mount| mount.root().components().count());
+
+        let Some(mount) = mount else {
+            return Ok(false);
+        };
+
+        let relative = path.strip_prefix(mount.root()).unwrap_or(path);
+        mount.delete_file(relative).await?;
+        Ok(true)
+    }
 }
 
 /// Finds media files in a directory or returns the file if it's a single file.
@@ -435,7 +495,7 @@ pub async fn find_media_files(path: &Path, media_type: MediaType) -> Result<Vec<
                     .unwrap_or(0);
                 files_with_size.push((file, size));
             }
-            files_with_size.sort_by(|a, b| b.1.cmp(&a.1));
+            files_with_size.sort_by_key(|(_, size)| std::cmp::Reverse(*size));
             Ok(files_with_size.into_iter().map(|(f, _)| f).collect())
         }
         MediaType::Album | MediaType::Track => {
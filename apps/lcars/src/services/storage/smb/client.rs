@@ -0,0 +1,741 @@
+//! Minimal SMB2/SMB3 client.
+//!
+//! Speaks dialects 2.0.2 through 3.0.2 over direct TCP, authenticates with
+//! NTLMv2 and signs messages when the server requires it. Requests are sent one
+//! at a time, so a single credit is all the client ever needs.
+
+use std::time::Duration;
+
+use aes::Aes128;
+use cmac::Cmac;
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use sha2::Sha256;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::error::AppError;
+
+use super::ntlm::{self, Credentials};
+
+// Commands
+pub const NEGOTIATE: u16 = 0x0000;
+pub const SESSION_SETUP: u16 = 0x0001;
+pub const TREE_CONNECT: u16 = 0x0003;
+pub const CREATE: u16 = 0x0005;
+pub const CLOSE: u16 = 0x0006;
+pub const WRITE: u16 = 0x0009;
+pub const ECHO: u16 = 0x000d;
+pub const QUERY_INFO: u16 = 0x0010;
+
+// Dialects
+pub const SMB_2_0_2: u16 = 0x0202;
+pub const SMB_2_1: u16 = 0x0210;
+pub const SMB_3_0: u16 = 0x0300;
+pub const SMB_3_0_2: u16 = 0x0302;
+const DIALECTS: [u16; 4] = [SMB_2_0_2, SMB_2_1, SMB_3_0, SMB_3_0_2];
+
+// Header flags
+pub const FLAGS_SERVER_TO_REDIR: u32 = 0x0000_0001;
+pub const FLAGS_ASYNC_COMMAND: u32 = 0x0000_0002;
+pub const FLAGS_SIGNED: u32 = 0x0000_0008;
+
+// Security mode
+pub const SIGNING_ENABLED: u16 = 0x0001;
+pub const SIGNING_REQUIRED: u16 = 0x0002;
+
+// Session flags
+const SESSION_FLAG_IS_GUEST: u16 = 0x0001;
+const SESSION_FLAG_IS_NULL: u16 = 0x0002;
+const SESSION_FLAG_ENCRYPT_DATA: u16 = 0x0004;
+
+// NTSTATUS values
+pub const STATUS_SUCCESS: u32 = 0x0000_0000;
+pub const STATUS_PENDING: u32 = 0x0000_0103;
+pub const STATUS_MORE_PROCESSING_REQUIRED: u32 = 0xc000_0016;
+pub const STATUS_ACCESS_DENIED: u32 = 0xc000_0022;
+pub const STATUS_OBJECT_NAME_NOT_FOUND: u32 = 0xc000_0034;
+pub const STATUS_OBJECT_NAME_COLLISION: u32 = 0xc000_0035;
+pub const STATUS_OBJECT_PATH_NOT_FOUND: u32 = 0xc000_003a;
+pub const STATUS_LOGON_FAILURE: u32 = 0xc000_006d;
+pub const STATUS_NOT_A_DIRECTORY: u32 = 0xc000_0103;
+pub const STATUS_FILE_IS_A_DIRECTORY: u32 = 0xc000_00ba;
+pub const STATUS_NETWORK_NAME_DELETED: u32 = 0xc000_00c9;
+pub const STATUS_BAD_NETWORK_NAME: u32 = 0xc000_00cc;
+pub const STATUS_INVALID_PARAMETER: u32 = 0xc000_000d;
+pub const STATUS_NOT_SUPPORTED: u32 = 0xc000_00bb;
+pub const STATUS_USER_SESSION_DELETED: u32 = 0xc000_0203;
+pub const STATUS_NETWORK_SESSION_EXPIRED: u32 = 0xc000_035c;
+
+// Access masks
+pub const FILE_READ_ATTRIBUTES: u32 = 0x0000_0080;
+pub const DELETE: u32 = 0x0001_0000;
+pub const GENERIC_WRITE: u32 = 0x4000_0000;
+
+// Share access
+pub const FILE_SHARE_ALL: u32 = 0x0000_0007;
+
+// Create dispositions
+pub const FILE_OPEN: u32 = 0x0000_0001;
+pub const FILE_CREATE: u32 = 0x0000_0002;
+pub const FILE_OPEN_IF: u32 = 0x0000_0003;
+pub const FILE_OVERWRITE_IF: u32 = 0x0000_0005;
+
+// Create options
+pub const FILE_DIRECTORY_FILE: u32 = 0x0000_0001;
+pub const FILE_NON_DIRECTORY_FILE: u32 = 0x0000_0040;
+pub const FILE_DELETE_ON_CLOSE: u32 = 0x0000_1000;
+
+// Query info
+const INFO_FILESYSTEM: u8 = 0x02;
+pub const FILE_FS_FULL_SIZE_INFORMATION: u8 = 7;
+
+/// Size of the SMB2 packet header.
+pub const HEADER_SIZE: usize = 64;
+
+/// Largest write issued in a single request, keeping every request at one credit.
+const MAX_WRITE_CHUNK: u32 = 64 * 1024;
+
+/// Timeout for establishing the TCP connection.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Timeout for a single request/response exchange.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// Errors raised by the SMB client.
+#[derive(Debug, thiserror::Error)]
+pub enum SmbError {
+    /// Transport failure, the connection is unusable.
+    #[error("SMB connection error: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// The server answered with a failure status.
+    #[error("SMB request failed with status 0x{0:08x}")]
+    Status(u32),
+
+    /// The server sent something we do not understand.
+    #[error("SMB protocol error: {0}")]
+    Protocol(String),
+
+    /// Reading the local file being uploaded failed.
+    #[error("Failed to read source file: {0}")]
+    Source(std::io::Error),
+}
+
+impl SmbError {
+    /// Whether the connection must be re-established after this error.
+    pub fn is_connection_error(&self) -> bool {
+        match self {
+            SmbError::Io(_) | SmbError::Protocol(_) => true,
+            SmbError::Status(status) => matches!(
+                *status,
+                STATUS_USER_SESSION_DELETED
+                    | STATUS_NETWORK_SESSION_EXPIRED
+                    | STATUS_NETWORK_NAME_DELETED
+            ),
+            SmbError::Source(_) => false,
+        }
+    }
+
+    /// Whether the error means the file or one of its parents does not exist.
+    pub fn is_not_found(&self) -> bool {
+        matches!(
+            self,
+            SmbError::Status(STATUS_OBJECT_NAME_NOT_FOUND | STATUS_OBJECT_PATH_NOT_FOUND)
+        )
+    }
+}
+
+impl From<SmbError> for AppError {
+    fn from(err: SmbError) -> Self {
+        AppError::Internal(err.to_string())
+    }
+}
+
+type SmbResult<T> = std::result::Result<T, SmbError>;
+
+/// Handle of an open file or directory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct FileId(pub [u8; 16]);
+
+/// Parameters of a CREATE request.
+#[derive(Debug, Clone, Copy)]
+pub struct CreateRequest {
+    pub desired_access: u32,
+    pub disposition: u32,
+    pub options: u32,
+}
+
+/// A parsed SMB2 response.
+#[derive(Debug)]
+pub struct Response {
+    pub status: u32,
+    pub tree_id: u32,
+    pub session_id: u64,
+    /// The full message, header included (offsets are relative to it).
+    pub message: Vec<u8>,
+}
+
+impl Response {
+    /// The message body following the header.
+    pub fn body(&self) -> &[u8] {
+        &self.message[HEADER_SIZE..]
+    }
+
+    /// Read a security or data buffer given its offset/length from the header start.
+    fn buffer(&self, offset: usize, len: usize) -> SmbResult<&[u8]> {
+        self.message
+            .get(offset..offset + len)
+            .ok_or_else(|| SmbError::Protocol("buffer outside message".to_string()))
+    }
+
+    fn u16_at(&self, body_offset: usize) -> SmbResult<u16> {
+        read_u16(self.body(), body_offset)
+    }
+
+    fn u32_at(&self, body_offset: usize) -> SmbResult<u32> {
+        read_u32(self.body(), body_offset)
+    }
+}
+
+/// Message signing algorithm negotiated for a session.
+#[derive(Clone)]
+pub enum Signer {
+    /// SMB 2.x: HMAC-SHA256 keyed with the session key.
+    HmacSha256([u8; 16]),
+    /// SMB 3.x: AES-128-CMAC keyed with the derived signing key.
+    AesCmac([u8; 16]),
+}
+
+impl Signer {
+    /// Create the signer for a dialect from the authentication session key.
+    pub fn new(dialect: u16, session_key: [u8; 16]) -> Self {
+        if dialect >= SMB_3_0 {
+            Signer::AesCmac(kdf(&session_key, b"SMB2AESCMAC\0", b"SmbSign\0"))
+        } else {
+            Signer::HmacSha256(session_key)
+        }
+    }
+
+    fn compute(&self, message: &[u8]) -> [u8; 16] {
+        let mut zeroed = message.to_vec();
+        zeroed[48..64].fill(0);
+
+        let mut signature = [0u8; 16];
+        match self {
+            Signer::HmacSha256(key) => {
+                let mut mac =
+                    Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+                mac.update(&zeroed);
+                signature.copy_from_slice(&mac.finalize().into_bytes()[..16]);
+            }
+            Signer::AesCmac(key) => {
+                let mut mac = <Cmac<Aes128> as Mac>::new_from_slice(key).expect("key is 16 bytes");
+                mac.update(&zeroed);
+                signature.copy_from_slice(&mac.finalize().into_bytes());
+            }
+        }
+        signature
+    }
+
+    /// Sign a message in place (the SIGNED flag must already be set).
+    pub fn sign(&self, message: &mut [u8]) {
+        let signature = self.compute(message);
+        message[48..64].copy_from_slice(&signature);
+    }
+
+    /// Check the signature of a received message.
+    pub fn verify(&self, message: &[u8]) -> bool {
+        message.len() >= HEADER_SIZE && self.compute(message) == message[48..64]
+    }
+}
+
+/// SP800-108 counter mode KDF with HMAC-SHA256, as used by SMB 3.0.
+fn kdf(key: &[u8; 16], label: &[u8], context: &[u8]) -> [u8; 16] {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(&1u32.to_be_bytes());
+    mac.update(label);
+    mac.update(&[0]);
+    mac.update(context);
+    mac.update(&128u32.to_be_bytes());
+
+    let mut out = [0u8; 16];
+    out.copy_from_slice(&mac.finalize().into_bytes()[..16]);
+    out
+}
+
+/// An authenticated connection to a single share.
+pub struct SmbClient {
+    stream: TcpStream,
+    dialect: u16,
+    message_id: u64,
+    session_id: u64,
+    tree_id: u32,
+    max_write_size: u32,
+    signer: Option<Signer>,
+}
+
+impl SmbClient {
+    /// Connect, authenticate and attach to a share.
+    pub async fn connect(
+        addr: &str,
+        host: &str,
+        share: &str,
+        creds: &Credentials,
+    ) -> SmbResult<Self> {
+        let stream = tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect(addr))
+            .await
+            .map_err(|_| {
+                SmbError::Io(std::io::Error::new(
+                    std::io::ErrorKind::TimedOut,
+                    format!("connection to {} timed out", addr),
+                ))
+            })??;
+        stream.set_nodelay(true)?;
+
+        let mut client = Self {
+            stream,
+            dialect: SMB_2_0_2,
+            message_id: 0,
+            session_id: 0,
+            tree_id: 0,
+            max_write_size: MAX_WRITE_CHUNK,
+            signer: None,
+        };
+
+        let require_signing = client.negotiate().await?;
+        client.session_setup(creds, require_signing).await?;
+        client.tree_connect(host, share).await?;
+
+        tracing::debug!(
+            addr = %addr,
+            share = %share,
+            dialect = format!("0x{:04x}", client.dialect),
+            signed = client.signer.is_some(),
+            "SMB session established"
+        );
+
+        Ok(client)
+    }
+
+    /// Maximum payload of a single WRITE request.
+    pub fn max_write_size(&self) -> usize {
+        self.max_write_size.min(MAX_WRITE_CHUNK) as usize
+    }
+
+    /// Negotiate the dialect, returning whether the server requires signing.
+    async fn negotiate(&mut self) -> SmbResult<bool> {
+        let mut body = Vec::with_capacity(36 + DIALECTS.len() * 2);
+        body.extend_from_slice(&36u16.to_le_bytes());
+        body.extend_from_slice(&(DIALECTS.len() as u16).to_le_bytes());
+        body.extend_from_slice(&SIGNING_ENABLED.to_le_bytes());
+        body.extend_from_slice(&0u16.to_le_bytes());
+        body.extend_from_slice(&0u32.to_le_bytes()); // Capabilities
+        body.extend_from_slice(&rand::thread_rng().gen::<[u8; 16]>()); // ClientGuid
+        body.extend_from_slice(&0u64.to_le_bytes()); // ClientStartTime
+        for dialect in DIALECTS {
+            body.extend_from_slice(&dialect.to_le_bytes());
+        }
+
+        let response = self.request(NEGOTIATE, body).await?;
+        check_status(&response, NEGOTIATE)?;
+
+        let security_mode = response.u16_at(2)?;
+        let dialect = response.u16_at(4)?;
+        if !DIALECTS.contains(&dialect) {
+            return Err(SmbError::Protocol(format!(
+                "server selected unsupported dialect 0x{:04x}",
+                dialect
+            )));
+        }
+
+        self.dialect = dialect;
+        self.max_write_size = response.u32_at(36)?;
+
+        Ok(security_mode & SIGNING_REQUIRED != 0)
+    }
+
+    /// Authenticate with NTLMv2 over SPNEGO.
+    async fn session_setup(&mut self, creds: &Credentials, require_signing: bool) -> SmbResult<()> {
+        // First leg: NEGOTIATE -> CHALLENGE
+        let token = ntlm::spnego_init(&ntlm::negotiate_message());
+        let response = self
+            .request(SESSION_SETUP, session_setup_body(&token))
+            .await?;
+        if response.status != STATUS_MORE_PROCESSING_REQUIRED {
+            return Err(SmbError::Status(response.status));
+        }
+        self.session_id = response.session_id;
+
+        let offset = response.u16_at(4)? as usize;
+        let len = response.u16_at(6)? as usize;
+        let blob = response.buffer(offset, len)?;
+        let challenge = ntlm::find_ntlmssp(blob)
+            .and_then(ntlm::parse_challenge)
+            .ok_or_else(|| SmbError::Protocol("invalid NTLM challenge".to_string()))?;
+
+        // Second leg: AUTHENTICATE
+        let auth =
+            ntlm::authenticate_message(creds, &challenge, rand::thread_rng().gen(), filetime_now());
+        let token = ntlm::spnego_response(&auth.message);
+        let body = session_setup_body(&token);
+
+        // The final response is already signed when signing is required
+        let signer = Signer::new(self.dialect, auth.session_key);
+        let response = self.request(SESSION_SETUP, body).await?;
+        check_status(&response, SESSION_SETUP)?;
+
+        let session_flags = response.u16_at(2)?;
+        if session_flags & SESSION_FLAG_ENCRYPT_DATA != 0 {
+            return Err(SmbError::Protocol(
+                "share requires SMB3 encryption, which is not supported".to_string(),
+            ));
+        }
+
+        let anonymous = session_flags & (SESSION_FLAG_IS_GUEST | SESSION_FLAG_IS_NULL) != 0;
+        if require_signing && !anonymous {
+            if !signer.verify(&response.message) {
+                return Err(SmbError::Protocol(
+                    "session setup response has an invalid signature".to_string(),
+                ));
+            }
+            self.signer = Some(signer);
+        }
+
+        Ok(())
+    }
+
+    /// Attach to `\\host\share`.
+    async fn tree_connect(&mut self, host: &str, share: &str) -> SmbResult<()> {
+        let path = ntlm::utf16le(&format!("\\\\{}\\{}", host, share));
+
+        let mut body = Vec::with_capacity(8 + path.len());
+        body.extend_from_slice(&9u16.to_le_bytes());
+        body.extend_from_slice(&0u16.to_le_bytes());
+        body.extend_from_slice(&((HEADER_SIZE + 8) as u16).to_le_bytes());
+        body.extend_from_slice(&(path.len() as u16).to_le_bytes());
+        body.extend_from_slice(&path);
+
+        let response = self.request(TREE_CONNECT, body).await?;
+        check_status(&response, TREE_CONNECT)?;
+        self.tree_id = response.tree_id;
+        Ok(())
+    }
+
+    /// Check the connection is alive.
+    pub async fn echo(&mut self) -> SmbResult<()> {
+        let body = vec![4, 0, 0, 0];
+        let response = self.request(ECHO, body).await?;
+        check_status(&response, ECHO)
+    }
+
+    /// Open or create a file or directory relative to the share root.
+    pub async fn create(&mut self, name: &str, req: CreateRequest) -> SmbResult<FileId> {
+        let name = ntlm::utf16le(name);
+
+        let mut body = Vec::with_capacity(56 + name.len().max(1));
+        body.extend_from_slice(&57u16.to_le_bytes());
+        body.push(0); // SecurityFlags
+        body.push(0); // RequestedOplockLevel: none
+        body.extend_from_slice(&2u32.to_le_bytes()); // ImpersonationLevel: impersonation
+        body.extend_from_slice(&[0u8; 16]); // SmbCreateFlags + Reserved
+        body.extend_from_slice(&req.desired_access.to_le_bytes());
+        body.extend_from_slice(&0u32.to_le_bytes()); // FileAttributes
+        body.extend_from_slice(&FILE_SHARE_ALL.to_le_bytes());
+        body.extend_from_slice(&req.disposition.to_le_bytes());
+        body.extend_from_slice(&req.options.to_le_bytes());
+        body.extend_from_slice(&((HEADER_SIZE + 56) as u16).to_le_bytes());
+        body.extend_from_slice(&(name.len() as u16).to_le_bytes());
+        body.extend_from_slice(&[0u8; 8]); // No create contexts
+        if name.is_empty() {
+            body.push(0);
+        } else {
+            body.extend_from_slice(&name);
+        }
+
+        let response = self.request(CREATE, body).await?;
+        if response.status != STATUS_SUCCESS {
+            return Err(SmbError::Status(response.status));
+        }
+
+        let id = response
+            .body()
+            .get(64..80)
+            .ok_or_else(|| SmbError::Protocol("truncated CREATE response".to_string()))?;
+        Ok(FileId(id.try_into().expect("slice is 16 bytes")))
+    }
+
+    /// Close an open handle.
+    pub async fn close(&mut self, file: FileId) -> SmbResult<()> {
+        let mut body = Vec::with_capacity(24);
+        body.extend_from_slice(&24u16.to_le_bytes());
+        body.extend_from_slice(&0u16.to_le_bytes()); // Flags
+        body.extend_from_slice(&0u32.to_le_bytes());
+        body.extend_from_slice(&file.0);
+
+        let response = self.request(CLOSE, body).await?;
+        check_status(&response, CLOSE)
+    }
+
+    /// Write a chunk at the given offset, returning the number of bytes written.
+    pub async fn write(&mut self, file: FileId, offset: u64, data: &[u8]) -> SmbResult<usize> {
+        let mut body = Vec::with_capacity(48 + data.len());
+        body.extend_from_slice(&49u16.to_le_bytes());
+        body.extend_from_slice(&((HEADER_SIZE + 48) as u16).to_le_bytes());
+        body.extend_from_slice(&(data.len() as u32).to_le_bytes());
+        body.extend_from_slice(&offset.to_le_bytes());
+        body.extend_from_slice(&file.0);
+        body.extend_from_slice(&[0u8; 16]); // Channel, RemainingBytes, channel info, Flags
+        body.extend_from_slice(data);
+
+        let response = self.request(WRITE, body).await?;
+        check_status(&response, WRITE)?;
+        Ok(response.u32_at(4)? as usize)
+    }
+
+    /// Free space available to the user on the share, in bytes.
+    pub async fn free_space(&mut self, file: FileId) -> SmbResult<u64> {
+        let mut body = Vec::with_capacity(40);
+        body.extend_from_slice(&41u16.to_le_bytes());
+        body.push(INFO_FILESYSTEM);
+        body.push(FILE_FS_FULL_SIZE_INFORMATION);
+        body.extend_from_slice(&32u32.to_le_bytes()); // OutputBufferLength
+        body.extend_from_slice(&[0u8; 16]); // Input buffer, AdditionalInformation, Flags
+        body.extend_from_slice(&file.0);
+        body.push(0);
+
+        let response = self.request(QUERY_INFO, body).await?;
+        check_status(&response, QUERY_INFO)?;
+
+        let offset = response.u16_at(2)? as usize;
+        let len = response.u32_at(4)? as usize;
+        let info = response.buffer(offset, len)?;
+        if info.len() < 32 {
+            return Err(SmbError::Protocol(
+                "truncated FileFsFullSizeInformation".to_string(),
+            ));
+        }
+
+        let caller_available = read_u64(info, 8)?;
+        let sectors_per_unit = read_u32(info, 24)? as u64;
+        let bytes_per_sector = read_u32(info, 28)? as u64;
+
+        caller_available
+            .checked_mul(sectors_per_unit * bytes_per_sector)
+            .ok_or_else(|| SmbError::Protocol("free space overflows".to_string()))
+    }
+
+    /// Send a request and wait for its final response.
+    async fn request(&mut self, command: u16, body: Vec<u8>) -> SmbResult<Response> {
+        let message_id = self.message_id;
+        self.message_id += 1;
+
+        let mut message = build_header(
+            command,
+            message_id,
+            self.session_id,
+            self.tree_id,
+            // SMB 2.0.2 predates credit charges
+            if self.dialect == SMB_2_0_2 { 0 } else { 1 },
+        );
+        message.extend_from_slice(&body);
+
+        if let Some(signer) = &self.signer {
+            message[16..20].copy_from_slice(&FLAGS_SIGNED.to_le_bytes());
+            signer.sign(&mut message);
+        }
+
+        tokio::time::timeout(REQUEST_TIMEOUT, async {
+            write_frame(&mut self.stream, &message).await?;
+
+            loop {
+                let message = read_frame(&mut self.stream).await?;
+                let response = parse_response(message)?;
+
+                let id = read_u64(&response.message, 24)?;
+                if id != message_id {
+                    return Err(SmbError::Protocol(format!(
+                        "unexpected response to message {} (expected {})",
+                        id, message_id
+                    )));
+                }
+
+                let flags = read_u32(&response.message, 16)?;
+                if response.status == STATUS_PENDING && flags & FLAGS_ASYNC_COMMAND != 0 {
+                    // Interim response, the real one follows
+                    continue;
+                }
+
+                if let Some(signer) = &self.signer {
+                    if flags & FLAGS_SIGNED != 0 && !signer.verify(&response.message) {
+                        return Err(SmbError::Protocol(
+                            "response has an invalid signature".to_string(),
+                        ));
+                    }
+                }
+
+                return Ok(response);
+            }
+        })
+        .await
+        .map_err(|_| {
+            SmbError::Io(std::io::Error::new(
+                std::io::ErrorKind::TimedOut,
+                "SMB request timed out",
+            ))
+        })?
+    }
+}
+
+/// Build a request header.
+pub fn build_header(
+    command: u16,
+    message_id: u64,
+    session_id: u64,
+    tree_id: u32,
+    credit_charge: u16,
+) -> Vec<u8> {
+    let mut header = Vec::with_capacity(HEADER_SIZE);
+    header.extend_from_slice(b"\xfeSMB");
+    header.extend_from_slice(&(HEADER_SIZE as u16).to_le_bytes());
+    header.extend_from_slice(&credit_charge.to_le_bytes());
+    header.extend_from_slice(&0u32.to_le_bytes()); // Status / ChannelSequence
+    header.extend_from_slice(&command.to_le_bytes());
+    header.extend_from_slice(&64u16.to_le_bytes()); // CreditRequest
+    header.extend_from_slice(&0u32.to_le_bytes()); // Flags
+    header.extend_from_slice(&0u32.to_le_bytes()); // NextCommand
+    header.extend_from_slice(&message_id.to_le_bytes());
+    header.extend_from_slice(&0u32.to_le_bytes()); // Reserved (ProcessId)
+    header.extend_from_slice(&tree_id.to_le_bytes());
+    header.extend_from_slice(&session_id.to_le_bytes());
+    header.extend_from_slice(&[0u8; 16]); // Signature
+    header
+}
+
+/// Validate a received message and extract the header fields.
+fn parse_response(message: Vec<u8>) -> SmbResult<Response> {
+    if message.len() < HEADER_SIZE || &message[..4] != b"\xfeSMB" {
+        return Err(SmbError::Protocol("not an SMB2 message".to_string()));
+    }
+
+    Ok(Response {
+        status: read_u32(&message, 8)?,
+        tree_id: read_u32(&message, 36)?,
+        session_id: read_u64(&message, 40)?,
+        message,
+    })
+}
+
+fn check_status(response: &Response, command: u16) -> SmbResult<()> {
+    if response.status == STATUS_SUCCESS {
+        Ok(())
+    } else {
+        tracing::trace!(
+            command = command,
+            status = format!("0x{:08x}", response.status),
+            "SMB request failed"
+        );
+        Err(SmbError::Status(response.status))
+    }
+}
+
+fn session_setup_body(token: &[u8]) -> Vec<u8> {
+    let mut body = Vec::with_capacity(24 + token.len());
+    body.extend_from_slice(&25u16.to_le_bytes());
+    body.push(0); // Flags
+    body.push(SIGNING_ENABLED as u8);
+    body.extend_from_slice(&0u32.to_le_bytes()); // Capabilities
+    body.extend_from_slice(&0u32.to_le_bytes()); // Channel
+    body.extend_from_slice(&((HEADER_SIZE + 24) as u16).to_le_bytes());
+    body.extend_from_slice(&(token.len() as u16).to_le_bytes());
+    body.extend_from_slice(&0u64.to_le_bytes()); // PreviousSessionId
+    body.extend_from_slice(token);
+    body
+}
+
+/// Write a message with its direct TCP transport header.
+pub async fn write_frame(stream: &mut TcpStream, message: &[u8]) -> std::io::Result<()> {
+    let len = message.len() as u32;
+    let mut frame = Vec::with_capacity(4 + message.len());
+    frame.extend_from_slice(&(len & 0x00ff_ffff).to_be_bytes());
+    frame.extend_from_slice(message);
+    stream.write_all(&frame).await
+}
+
+/// Read one message framed by the direct TCP transport header.
+pub async fn read_frame(stream: &mut TcpStream) -> std::io::Result<Vec<u8>> {
+    let mut len = [0u8; 4];
+    stream.read_exact(&mut len).await?;
+    let len = u32::from_be_bytes(len) & 0x00ff_ffff;
+
+    let mut message = vec![0u8; len as usize];
+    stream.read_exact(&mut message).await?;
+    Ok(message)
+}
+
+/// Current time as a Windows FILETIME (100ns intervals since 1601).
+fn filetime_now() -> u64 {
+    const EPOCH_DIFFERENCE_SECS: u64 = 11_644_473_600;
+    let since_unix = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap_or_default();
+    (since_unix.as_secs() + EPOCH_DIFFERENCE_SECS) * 10_000_000
+        + u64::from(since_unix.subsec_nanos()) / 100
+}
+
+pub fn read_u16(buf: &[u8], offset: usize) -> SmbResult<u16> {
+    buf.get(offset..offset + 2)
+        .map(|b| u16::from_le_bytes([b[0], b[1]]))
+        .ok_or_else(|| SmbError::Protocol("truncated message".to_string()))
+}
+
+pub fn read_u32(buf: &[u8], offset: usize) -> SmbResult<u32> {
+    buf.get(offset..offset + 4)
+        .map(|b| u32::from_le_bytes(b.try_into().expect("slice is 4 bytes")))
+        .ok_or_else(|| SmbError::Protocol("truncated message".to_string()))
+}
+
+pub fn read_u64(buf: &[u8], offset: usize) -> SmbResult<u64> {
+    buf.get(offset..offset + 8)
+        .map(|b| u64::from_le_bytes(b.try_into().expect("slice is 8 bytes")))
+        .ok_or_else(|| SmbError::Protocol("truncated message".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_header_layout() {
+        let header = build_header(CREATE, 7, 0x1122, 3, 1);
+        assert_eq!(header.len(), HEADER_SIZE);
+        assert_eq!(read_u16(&header, 12).unwrap(), CREATE);
+        assert_eq!(read_u64(&header, 24).unwrap(), 7);
+        assert_eq!(read_u32(&header, 36).unwrap(), 3);
+        assert_eq!(read_u64(&header, 40).unwrap(), 0x1122);
+    }
+
+    #[test]
+    fn test_signing_roundtrip() {
+        for dialect in [SMB_2_1, SMB_3_0_2] {
+            let signer = Signer::new(dialect, [7u8; 16]);
+            let mut message = build_header(ECHO, 1, 1, 0, 1);
+            message.extend_from_slice(&[4, 0, 0, 0]);
+            message[16..20].copy_from_slice(&FLAGS_SIGNED.to_le_bytes());
+
+            signer.sign(&mut message);
+            assert!(signer.verify(&message));
+
+            message[64] ^= 1;
+            assert!(!signer.verify(&message));
+        }
+    }
+
+    #[test]
+    fn test_error_classification() {
+        assert!(SmbError::Status(STATUS_OBJECT_PATH_NOT_FOUND).is_not_found());
+        assert!(!SmbError::Status(STATUS_ACCESS_DENIED).is_not_found());
+        assert!(SmbError::Status(STATUS_NETWORK_SESSION_EXPIRED).is_connection_error());
+        assert!(!SmbError::Status(STATUS_ACCESS_DENIED).is_connection_error());
+    }
+}
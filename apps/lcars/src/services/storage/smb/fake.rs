@@ -0,0 +1,435 @@
+//! In-process SMB2 server used to exercise the client in tests.
+//!
+//! Serves a single share backed by a local directory and implements just the
+//! commands the client issues: NTLMv2 session setup, tree connect, create,
+//! close, write, filesystem query and echo.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use tokio::io::{AsyncSeekExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+use super::client::*;
+use super::ntlm;
+
+/// Units reported as available by the fake FileFsFullSizeInformation.
+pub const FREE_UNITS: u64 = 500;
+pub const SECTORS_PER_UNIT: u32 = 8;
+pub const BYTES_PER_SECTOR: u32 = 512;
+
+const SESSION_ID: u64 = 0x0000_0400_0000_0011;
+const TREE_ID: u32 = 7;
+
+/// Server settings.
+#[derive(Debug, Clone)]
+pub struct FakeConfig {
+    pub share: String,
+    pub username: String,
+    pub password: String,
+    /// Highest dialect the server accepts.
+    pub max_dialect: u16,
+    pub require_signing: bool,
+}
+
+impl Default for FakeConfig {
+    fn default() -> Self {
+        Self {
+            share: "media".to_string(),
+            username: "lcars".to_string(),
+            password: "secret".to_string(),
+            max_dialect: SMB_3_0_2,
+            require_signing: false,
+        }
+    }
+}
+
+/// A running fake server.
+pub struct FakeSmbServer {
+    pub addr: String,
+    pub root: PathBuf,
+    connections: Arc<AtomicUsize>,
+    generation: Arc<AtomicU64>,
+}
+
+impl FakeSmbServer {
+    /// Start serving `root` on an ephemeral localhost port.
+    pub async fn start(root: PathBuf, config: FakeConfig) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap().to_string();
+        let connections = Arc::new(AtomicUsize::new(0));
+        let generation = Arc::new(AtomicU64::new(0));
+
+        let config = Arc::new(config);
+        let server_root = root.clone();
+        let counter = Arc::clone(&connections);
+        let server_generation = Arc::clone(&generation);
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                counter.fetch_add(1, Ordering::SeqCst);
+                let conn = Connection {
+                    root: server_root.clone(),
+                    config: Arc::clone(&config),
+                    generation: Arc::clone(&server_generation),
+                    started_at: server_generation.load(Ordering::SeqCst),
+                    dialect: SMB_2_0_2,
+                    challenge: [0x11; 8],
+                    signer: None,
+                    authenticated: false,
+                    handles: HashMap::new(),
+                    next_handle: 1,
+                };
+                tokio::spawn(conn.serve(stream));
+            }
+        });
+
+        Self {
+            addr,
+            root,
+            connections,
+            generation,
+        }
+    }
+
+    /// Number of TCP connections accepted so far.
+    pub fn connections(&self) -> usize {
+        self.connections.load(Ordering::SeqCst)
+    }
+
+    /// Drop every open connection before it handles its next request.
+    pub fn drop_connections(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+struct Handle {
+    path: PathBuf,
+    file: Option<tokio::fs::File>,
+    delete_on_close: bool,
+}
+
+struct Connection {
+    root: PathBuf,
+    config: Arc<FakeConfig>,
+    generation: Arc<AtomicU64>,
+    started_at: u64,
+    dialect: u16,
+    challenge: [u8; 8],
+    signer: Option<Signer>,
+    authenticated: bool,
+    handles: HashMap<[u8; 16], Handle>,
+    next_handle: u64,
+}
+
+impl Connection {
+    async fn serve(mut self, mut stream: TcpStream) {
+        while let Ok(request) = read_frame(&mut stream).await {
+            if self.generation.load(Ordering::SeqCst) != self.started_at {
+                return;
+            }
+
+            let command = read_u16(&request, 12).unwrap();
+            let message_id = read_u64(&request, 24).unwrap();
+            let flags = read_u32(&request, 16).unwrap();
+            let body = &request[HEADER_SIZE..];
+
+            let (status, response_body) = match &self.signer {
+                Some(signer) if flags & FLAGS_SIGNED == 0 || !signer.verify(&request) => {
+                    (STATUS_ACCESS_DENIED, error_body())
+                }
+                _ => self.handle(command, &request, body).await,
+            };
+
+            let mut message = build_header(command, message_id, SESSION_ID, TREE_ID, 0);
+            message[8..12].copy_from_slice(&status.to_le_bytes());
+            message[14..16].copy_from_slice(&1u16.to_le_bytes());
+            message.extend_from_slice(&response_body);
+
+            let mut flags = FLAGS_SERVER_TO_REDIR;
+            if let Some(signer) = &self.signer {
+                flags |= FLAGS_SIGNED;
+                message[16..20].copy_from_slice(&flags.to_le_bytes());
+                signer.sign(&mut message);
+            } else {
+                message[16..20].copy_from_slice(&flags.to_le_bytes());
+            }
+
+            if write_frame(&mut stream, &message).await.is_err() {
+                return;
+            }
+        }
+    }
+
+    async fn handle(&mut self, command: u16, request: &[u8], body: &[u8]) -> (u32, Vec<u8>) {
+        if command != NEGOTIATE && command != SESSION_SETUP && !self.authenticated {
+            return (STATUS_USER_SESSION_DELETED, error_body());
+        }
+
+        match command {
+            NEGOTIATE => self.negotiate(body),
+            SESSION_SETUP => self.session_setup(request, body),
+            TREE_CONNECT => self.tree_connect(request, body),
+            CREATE => self.create(request, body).await,
+            CLOSE => self.close(body).await,
+            WRITE => self.write(request, body).await,
+            QUERY_INFO => self.query_info(body),
+            ECHO => (STATUS_SUCCESS, vec![4, 0, 0, 0]),
+            _ => (STATUS_NOT_SUPPORTED, error_body()),
+        }
+    }
+
+    fn negotiate(&mut self, body: &[u8]) -> (u32, Vec<u8>) {
+        let count = read_u16(body, 2).unwrap() as usize;
+        let dialect = (0..count)
+            .map(|i| read_u16(body, 36 + i * 2).unwrap())
+            .filter(|d| *d <= self.config.max_dialect)
+            .max();
+        let Some(dialect) = dialect else {
+            return (STATUS_NOT_SUPPORTED, error_body());
+        };
+        self.dialect = dialect;
+
+        let mut security_mode = SIGNING_ENABLED;
+        if self.config.require_signing {
+            security_mode |= SIGNING_REQUIRED;
+        }
+
+        let mut out = Vec::with_capacity(64);
+        out.extend_from_slice(&65u16.to_le_bytes());
+        out.extend_from_slice(&security_mode.to_le_bytes());
+        out.extend_from_slice(&dialect.to_le_bytes());
+        out.extend_from_slice(&0u16.to_le_bytes());
+        out.extend_from_slice(&[0x42; 16]); // ServerGuid
+        out.extend_from_slice(&0u32.to_le_bytes()); // Capabilities
+        out.extend_from_slice(&(1u32 << 20).to_le_bytes()); // MaxTransactSize
+        out.extend_from_slice(&(1u32 << 20).to_le_bytes()); // MaxReadSize
+        out.extend_from_slice(&(1u32 << 20).to_le_bytes()); // MaxWriteSize
+        out.extend_from_slice(&[0u8; 16]); // SystemTime, ServerStartTime
+        out.extend_from_slice(&((HEADER_SIZE + 64) as u16).to_le_bytes());
+        out.extend_from_slice(&0u16.to_le_bytes());
+        out.extend_from_slice(&0u32.to_le_bytes());
+        (STATUS_SUCCESS, out)
+    }
+
+    fn session_setup(&mut self, request: &[u8], body: &[u8]) -> (u32, Vec<u8>) {
+        let offset = read_u16(body, 12).unwrap() as usize;
+        let len = read_u16(body, 14).unwrap() as usize;
+        let Some(token) = ntlm::find_ntlmssp(&request[offset..offset + len]) else {
+            return (STATUS_INVALID_PARAMETER, error_body());
+        };
+
+        match read_u32(token, 8).unwrap() {
+            1 => {
+                let challenge = ntlm::challenge_message(self.challenge, &[0, 0, 0, 0]);
+                (
+                    STATUS_MORE_PROCESSING_REQUIRED,
+                    session_setup_response(&challenge),
+                )
+            }
+            3 => {
+                let Some(session_key) = ntlm::verify_authenticate(
+                    token,
+                    &self.challenge,
+                    &self.config.username,
+                    &self.config.password,
+                ) else {
+                    return (STATUS_LOGON_FAILURE, error_body());
+                };
+
+                self.authenticated = true;
+                if self.config.require_signing {
+                    self.signer = Some(Signer::new(self.dialect, session_key));
+                }
+                (STATUS_SUCCESS, session_setup_response(&[]))
+            }
+            _ => (STATUS_INVALID_PARAMETER, error_body()),
+        }
+    }
+
+    fn tree_connect(&mut self, request: &[u8], body: &[u8]) -> (u32, Vec<u8>) {
+        let offset = read_u16(body, 4).unwrap() as usize;
+        let len = read_u16(body, 6).unwrap() as usize;
+        let path = utf16_string(&request[offset..offset + len]);
+
+        let share = path.rsplit('\\').next().unwrap_or_default();
+        if !share.eq_ignore_ascii_case(&self.config.share) {
+            return (STATUS_BAD_NETWORK_NAME, error_body());
+        }
+
+        let mut out = Vec::with_capacity(16);
+        out.extend_from_slice(&16u16.to_le_bytes());
+        out.push(1); // Disk share
+        out.push(0);
+        out.extend_from_slice(&0u32.to_le_bytes());
+        out.extend_from_slice(&0u32.to_le_bytes());
+        out.extend_from_slice(&0x001f_01ffu32.to_le_bytes());
+        (STATUS_SUCCESS, out)
+    }
+
+    async fn create(&mut self, request: &[u8], body: &[u8]) -> (u32, Vec<u8>) {
+        let disposition = read_u32(body, 36).unwrap();
+        let options = read_u32(body, 40).unwrap();
+        let offset = read_u16(body, 44).unwrap() as usize;
+        let len = read_u16(body, 46).unwrap() as usize;
+        let name = utf16_string(&request[offset..offset + len]);
+
+        let mut path = self.root.clone();
+        for part in name.split('\\').filter(|p| !p.is_empty()) {
+            if part == ".." || part.contains('/') {
+                return (STATUS_INVALID_PARAMETER, error_body());
+            }
+            path.push(part);
+        }
+
+        if !path.parent().is_some_and(|p| p.is_dir()) {
+            return (STATUS_OBJECT_PATH_NOT_FOUND, error_body());
+        }
+
+        let exists = path.exists();
+        let want_dir = options & FILE_DIRECTORY_FILE != 0;
+        if exists && want_dir && !path.is_dir() {
+            return (STATUS_NOT_A_DIRECTORY, error_body());
+        }
+        if exists && options & FILE_NON_DIRECTORY_FILE != 0 && path.is_dir() {
+            return (STATUS_FILE_IS_A_DIRECTORY, error_body());
+        }
+
+        let mut file = None;
+        match disposition {
+            FILE_OPEN if !exists => return (STATUS_OBJECT_NAME_NOT_FOUND, error_body()),
+            FILE_OPEN => {}
+            FILE_CREATE if exists => return (STATUS_OBJECT_NAME_COLLISION, error_body()),
+            FILE_CREATE | FILE_OPEN_IF if want_dir => {
+                if !exists {
+                    tokio::fs::create_dir(&path).await.unwrap();
+                }
+            }
+            FILE_CREATE | FILE_OPEN_IF => {
+                if !exists {
+                    tokio::fs::File::create(&path).await.unwrap();
+                }
+            }
+            FILE_OVERWRITE_IF if !want_dir => {
+                file = Some(tokio::fs::File::create(&path).await.unwrap());
+            }
+            _ => return (STATUS_INVALID_PARAMETER, error_body()),
+        }
+
+        if file.is_none() && path.is_file() {
+            file = tokio::fs::OpenOptions::new()
+                .write(true)
+                .open(&path)
+                .await
+                .ok();
+        }
+
+        let mut id = [0u8; 16];
+        id[..8].copy_from_slice(&self.next_handle.to_le_bytes());
+        self.next_handle += 1;
+        self.handles.insert(
+            id,
+            Handle {
+                path,
+                file,
+                delete_on_close: options & FILE_DELETE_ON_CLOSE != 0,
+            },
+        );
+
+        let mut out = vec![0u8; 88];
+        out[..2].copy_from_slice(&89u16.to_le_bytes());
+        out[4..8].copy_from_slice(&(if exists { 1u32 } else { 2u32 }).to_le_bytes());
+        out[64..80].copy_from_slice(&id);
+        (STATUS_SUCCESS, out)
+    }
+
+    async fn close(&mut self, body: &[u8]) -> (u32, Vec<u8>) {
+        let id: [u8; 16] = body[8..24].try_into().unwrap();
+        let Some(handle) = self.handles.remove(&id) else {
+            return (STATUS_INVALID_PARAMETER, error_body());
+        };
+
+        if let Some(mut file) = handle.file {
+            file.flush().await.unwrap();
+        }
+        if handle.delete_on_close {
+            if handle.path.is_dir() {
+                tokio::fs::remove_dir(&handle.path).await.unwrap();
+            } else {
+                tokio::fs::remove_file(&handle.path).await.unwrap();
+            }
+        }
+
+        let mut out = vec![0u8; 60];
+        out[..2].copy_from_slice(&60u16.to_le_bytes());
+        (STATUS_SUCCESS, out)
+    }
+
+    async fn write(&mut self, request: &[u8], body: &[u8]) -> (u32, Vec<u8>) {
+        let data_offset = read_u16(body, 2).unwrap() as usize;
+        let len = read_u32(body, 4).unwrap() as usize;
+        let offset = read_u64(body, 8).unwrap();
+        let id: [u8; 16] = body[16..32].try_into().unwrap();
+
+        let Some(file) = self.handles.get_mut(&id).and_then(|h| h.file.as_mut()) else {
+            return (STATUS_INVALID_PARAMETER, error_body());
+        };
+        file.seek(std::io::SeekFrom::Start(offset)).await.unwrap();
+        file.write_all(&request[data_offset..data_offset + len])
+            .await
+            .unwrap();
+
+        let mut out = vec![0u8; 16];
+        out[..2].copy_from_slice(&17u16.to_le_bytes());
+        out[4..8].copy_from_slice(&(len as u32).to_le_bytes());
+        (STATUS_SUCCESS, out)
+    }
+
+    fn query_info(&mut self, body: &[u8]) -> (u32, Vec<u8>) {
+        let id: [u8; 16] = body[24..40].try_into().unwrap();
+        if body[2] != 2
+            || body[3] != FILE_FS_FULL_SIZE_INFORMATION
+            || !self.handles.contains_key(&id)
+        {
+            return (STATUS_INVALID_PARAMETER, error_body());
+        }
+
+        let mut info = Vec::with_capacity(32);
+        info.extend_from_slice(&(FREE_UNITS * 4).to_le_bytes());
+        info.extend_from_slice(&FREE_UNITS.to_le_bytes());
+        info.extend_from_slice(&FREE_UNITS.to_le_bytes());
+        info.extend_from_slice(&SECTORS_PER_UNIT.to_le_bytes());
+        info.extend_from_slice(&BYTES_PER_SECTOR.to_le_bytes());
+
+        let mut out = Vec::with_capacity(8 + info.len());
+        out.extend_from_slice(&9u16.to_le_bytes());
+        out.extend_from_slice(&((HEADER_SIZE + 8) as u16).to_le_bytes());
+        out.extend_from_slice(&(info.len() as u32).to_le_bytes());
+        out.extend_from_slice(&info);
+        (STATUS_SUCCESS, out)
+    }
+}
+
+fn session_setup_response(token: &[u8]) -> Vec<u8> {
+    let mut out = Vec::with_capacity(8 + token.len());
+    out.extend_from_slice(&9u16.to_le_bytes());
+    out.extend_from_slice(&0u16.to_le_bytes());
+    out.extend_from_slice(&((HEADER_SIZE + 8) as u16).to_le_bytes());
+    out.extend_from_slice(&(token.len() as u16).to_le_bytes());
+    out.extend_from_slice(token);
+    out
+}
+
+fn error_body() -> Vec<u8> {
+    vec![9, 0, 0, 0, 0, 0, 0, 0, 0]
+}
+
+fn utf16_string(bytes: &[u8]) -> String {
+    String::from_utf16_lossy(
+        &bytes
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .collect::<Vec<_>>(),
+    )
+}
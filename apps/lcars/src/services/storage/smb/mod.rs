@@ -0,0 +1,582 @@
+//! SMB network share mount implementation.
+//!
+//! Stores media files on an SMB2/SMB3 share without relying on the share being
+//! mounted by the operating system. A single authenticated connection is kept
+//! open per mount and transparently re-established when it drops.
+
+mod client;
+#[cfg(test)]
+mod fake;
+mod ntlm;
+
+use async_trait::async_trait;
+use futures::future::BoxFuture;
+use std::path::{Component, Path, PathBuf};
+use tokio::io::AsyncReadExt;
+use tokio::sync::Mutex;
+
+use crate::error::{AppError, Result};
+
+use super::Mount;
+use client::{
+    CreateRequest, SmbClient, SmbError, DELETE, FILE_DELETE_ON_CLOSE, FILE_DIRECTORY_FILE,
+    FILE_NON_DIRECTORY_FILE, FILE_OPEN, FILE_OPEN_IF, FILE_OVERWRITE_IF, FILE_READ_ATTRIBUTES,
+    GENERIC_WRITE,
+};
+use ntlm::Credentials;
+
+/// Default SMB port (direct TCP transport).
+const DEFAULT_PORT: u16 = 445;
+
+/// SMB network share mount.
+///
+/// Paths are relative to the root of the share. The reported root is the
+/// configured `mount_point` (where the share is visible to media servers),
+/// falling back to `//host/share`.
+pub struct SmbMount {
+    name: String,
+    host: String,
+    addr: String,
+    share: String,
+    credentials: Credentials,
+    root: PathBuf,
+    client: Mutex<Option<SmbClient>>,
+}
+
+impl SmbMount {
+    /// Creates a new SMB mount.
+    ///
+    /// `host` may include a port (`nas.local:4445`). A `DOMAIN\user` username
+    /// authenticates against that domain. No connection is made until the
+    /// mount is first used.
+    pub fn new(
+        name: String,
+        host: &str,
+        share: &str,
+        username: Option<&str>,
+        password: Option<&str>,
+        mount_point: Option<PathBuf>,
+    ) -> Self {
+        let (host, port) = match host.rsplit_once(':') {
+            Some((h, p)) if !h.contains(':') => match p.parse::<u16>() {
+                Ok(port) => (h.to_string(), port),
+                Err(_) => (host.to_string(), DEFAULT_PORT),
+            },
+            _ => (host.to_string(), DEFAULT_PORT),
+        };
+        let addr = if host.contains(':') && !host.starts_with('[') {
+            format!("[{}]:{}", host, port)
+        } else {
+            format!("{}:{}", host, port)
+        };
+
+        let share = share.trim_matches(|c| c == '/' || c == '\\').to_string();
+        let root = mount_point.unwrap_or_else(|| PathBuf::from(format!("//{}/{}", host, share)));
+
+        Self {
+            name,
+            host,
+            addr,
+            share,
+            credentials: Credentials::new(
+                username.unwrap_or_default(),
+                password.unwrap_or_default(),
+            ),
+            root,
+            client: Mutex::new(None),
+        }
+    }
+
+    async fn connect(&self) -> std::result::Result<SmbClient, SmbError> {
+        SmbClient::connect(&self.addr, &self.host, &self.share, &self.credentials).await
+    }
+
+    /// Runs an operation on the shared connection, connecting if needed.
+    ///
+    /// If a reused connection turns out to be dead, it is re-established and
+    /// the operation retried once.
+    async fn with_client<T>(
+        &self,
+        op: impl for<'c> Fn(&'c mut SmbClient) -> BoxFuture<'c, std::result::Result<T, SmbError>>,
+    ) -> std::result::Result<T, SmbError> {
+        let mut guard = self.client.lock().await;
+        let reused = guard.is_some();
+        let client = match guard.as_mut() {
+            Some(client) => client,
+            None => guard.insert(self.connect().await?),
+        };
+
+        match op(client).await {
+            Err(e) if e.is_connection_error() => {
+                *guard = None;
+                if !reused {
+                    return Err(e);
+                }
+
+                tracing::debug!(name = %self.name, error = %e, "SMB connection lost, reconnecting");
+                let client = guard.insert(self.connect().await?);
+                let result = op(client).await;
+                if matches!(&result, Err(e) if e.is_connection_error()) {
+                    *guard = None;
+                }
+                result
+            }
+            result => result,
+        }
+    }
+
+    /// Wraps an SMB error with the mount name for context.
+    fn error(&self, action: &str, path: &Path, err: SmbError) -> AppError {
+        AppError::Internal(format!(
+            "SMB mount '{}': failed to {} {:?}: {}",
+            self.name, action, path, err
+        ))
+    }
+}
+
+/// Splits a relative path into share path components.
+///
+/// Rejects anything that could escape the share root.
+fn share_components(path: &Path) -> Result<Vec<String>> {
+    let mut parts = Vec::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(part) => {
+                let part = part.to_str().ok_or_else(|| {
+                    AppError::BadRequest(format!("Path is not valid UTF-8: {:?}", path))
+                })?;
+                if part.contains('\\') {
+                    return Err(AppError::BadRequest(format!(
+                        "Path component cannot contain a backslash: {:?}",
+                        part
+                    )));
+                }
+                parts.push(part.to_string());
+            }
+            Component::CurDir => {}
+            Component::ParentDir => {
+                return Err(AppError::BadRequest(
+                    "Path cannot contain parent directory references (..)".to_string(),
+                ));
+            }
+            _ => {
+                return Err(AppError::BadRequest(format!(
+                    "Invalid path component: {:?}",
+                    component
+                )));
+            }
+        }
+    }
+    Ok(parts)
+}
+
+/// Opens a file and closes it straight away.
+async fn open_close(
+    client: &mut SmbClient,
+    name: &str,
+    req: CreateRequest,
+) -> std::result::Result<(), SmbError> {
+    let file = client.create(name, req).await?;
+    client.close(file).await
+}
+
+/// Creates every directory leading to and including `dirs`.
+async fn create_dirs(client: &mut SmbClient, dirs: &[String]) -> std::result::Result<(), SmbError> {
+    let req = CreateRequest {
+        desired_access: FILE_READ_ATTRIBUTES,
+        disposition: FILE_OPEN_IF,
+        options: FILE_DIRECTORY_FILE,
+    };
+    for i in 1..=dirs.len() {
+        open_close(client, &dirs[..i].join("\\"), req).await?;
+    }
+    Ok(())
+}
+
+/// Uploads a local file, replacing any existing file. Returns the bytes written.
+async fn upload(
+    client: &mut SmbClient,
+    source: &Path,
+    name: &str,
+) -> std::result::Result<u64, SmbError> {
+    let mut local = tokio::fs::File::open(source)
+        .await
+        .map_err(SmbError::Source)?;
+
+    let file = client
+        .create(
+            name,
+            CreateRequest {
+                desired_access: GENERIC_WRITE,
+                disposition: FILE_OVERWRITE_IF,
+                options: FILE_NON_DIRECTORY_FILE,
+            },
+        )
+        .await?;
+
+    let mut buf = vec![0u8; client.max_write_size()];
+    let result = async {
+        let mut offset = 0u64;
+        loop {
+            let n = local.read(&mut buf).await.map_err(SmbError::Source)?;
+            if n == 0 {
+                return Ok(offset);
+            }
+
+            let mut chunk = &buf[..n];
+            while !chunk.is_empty() {
+                let written = client.write(file, offset, chunk).await?;
+                if written == 0 || written > chunk.len() {
+                    return Err(SmbError::Protocol(format!(
+                        "server reported {} bytes written for a {} byte chunk",
+                        written,
+                        chunk.len()
+                    )));
+                }
+                offset += written as u64;
+                chunk = &chunk[written..];
+            }
+        }
+    }
+    .await;
+
+    let closed = client.close(file).await;
+    let written = result?;
+    closed?;
+    Ok(written)
+}
+
+#[async_trait]
+impl Mount for SmbMount {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn mount_type(&self) -> &str {
+        "smb"
+    }
+
+    fn root(&self) -> &Path {
+        &self.root
+    }
+
+    async fn available(&self) -> bool {
+        match self.with_client(|c| Box::pin(c.echo())).await {
+            Ok(()) => true,
+            Err(e) => {
+                tracing::debug!(name = %self.name, error = %e, "SMB mount unavailable");
+                false
+            }
+        }
+    }
+
+    async fn free_space(&self) -> Result<u64> {
+        self.with_client(|c| {
+            Box::pin(async move {
+                let root = c
+                    .create(
+                        "",
+                        CreateRequest {
+                            desired_access: FILE_READ_ATTRIBUTES,
+                            disposition: FILE_OPEN,
+                            options: FILE_DIRECTORY_FILE,
+                        },
+                    )
+                    .await?;
+                let free = c.free_space(root).await;
+                c.close(root).await?;
+                free
+            })
+        })
+        .await
+        .map_err(|e| self.error("query free space of", Path::new(""), e))
+    }
+
+    async fn exists(&self, path: &Path) -> bool {
+        let Ok(parts) = share_components(path) else {
+            return false;
+        };
+        let name = parts.join("\\");
+
+        let result = self
+            .with_client(|c| {
+                let name = name.clone();
+                Box::pin(async move {
+                    let req = CreateRequest {
+                        desired_access: FILE_READ_ATTRIBUTES,
+                        disposition: FILE_OPEN,
+                        options: 0,
+                    };
+                    match open_close(c, &name, req).await {
+                        Ok(()) => Ok(true),
+                        Err(e) if e.is_not_found() => Ok(false),
+                        Err(e) => Err(e),
+                    }
+                })
+            })
+            .await;
+
+        result.unwrap_or_else(|e| {
+            tracing::warn!(name = %self.name, path = ?path, error = %e, "SMB existence check failed");
+            false
+        })
+    }
+
+    async fn write_file(&self, source: &Path, dest: &Path) -> Result<()> {
+        let parts = share_components(dest)?;
+        let Some((_, dirs)) = parts.split_last() else {
+            return Err(AppError::BadRequest(
+                "Destination path cannot be empty".to_string(),
+            ));
+        };
+        let name = parts.join("\\");
+
+        let written = self
+            .with_client(|c| {
+                let dirs = dirs.to_vec();
+                let name = name.clone();
+                let source = source.to_path_buf();
+                Box::pin(async move {
+                    create_dirs(c, &dirs).await?;
+                    upload(c, &source, &name).await
+                })
+            })
+            .await
+            .map_err(|e| self.error("write", dest, e))?;
+
+        tracing::debug!(
+            source = ?source,
+            dest = ?dest,
+            mount = %self.name,
+            bytes = written,
+            "File written to SMB share"
+        );
+
+        Ok(())
+    }
+
+    async fn delete_file(&self, path: &Path) -> Result<()> {
+        let name = share_components(path)?.join("\\");
+
+        self.with_client(|c| {
+            let name = name.clone();
+            Box::pin(async move {
+                let req = CreateRequest {
+                    desired_access: DELETE,
+                    disposition: FILE_OPEN,
+                    options: FILE_NON_DIRECTORY_FILE | FILE_DELETE_ON_CLOSE,
+                };
+                open_close(c, &name, req).await
+            })
+        })
+        .await
+        .map_err(|e| self.error("delete", path, e))?;
+
+        tracing::debug!(path = ?path, mount = %self.name, "File deleted from SMB share");
+        Ok(())
+    }
+
+    async fn create_dir_all(&self, path: &Path) -> Result<()> {
+        let parts = share_components(path)?;
+
+        self.with_client(|c| {
+            let parts = parts.clone();
+            Box::pin(async move { create_dirs(c, &parts).await })
+        })
+        .await
+        .map_err(|e| self.error("create directory", path, e))?;
+
+        tracing::debug!(path = ?path, mount = %self.name, "Directory created on SMB share");
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::client::{SMB_2_1, SMB_3_0_2};
+    use super::fake::{FakeConfig, FakeSmbServer, BYTES_PER_SECTOR, FREE_UNITS, SECTORS_PER_UNIT};
+    use super::*;
+    use std::fs;
+    use tempfile::TempDir;
+
+    async fn setup(config: FakeConfig) -> (TempDir, FakeSmbServer, SmbMount) {
+        let temp = TempDir::new().unwrap();
+        let server = FakeSmbServer::start(temp.path().to_path_buf(), config.clone()).await;
+        let mount = SmbMount::new(
+            "nas".to_string(),
+            &server.addr,
+            &config.share,
+            Some(&config.username),
+            Some(&config.password),
+            None,
+        );
+        (temp, server, mount)
+    }
+
+    fn source_file(dir: &TempDir, len: usize) -> (PathBuf, Vec<u8>) {
+        let data: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+        let path = dir.path().join("source.bin");
+        fs::write(&path, &data).unwrap();
+        (path, data)
+    }
+
+    #[test]
+    fn test_host_parsing() {
+        let mount = SmbMount::new("nas".to_string(), "nas.local", "/media/", None, None, None);
+        assert_eq!(mount.addr, "nas.local:445");
+        assert_eq!(mount.root(), Path::new("//nas.local/media"));
+
+        let mount = SmbMount::new(
+            "nas".to_string(),
+            "10.0.0.2:4445",
+            "media",
+            None,
+            None,
+            Some(PathBuf::from("/mnt/nas")),
+        );
+        assert_eq!(mount.addr, "10.0.0.2:4445");
+        assert_eq!(mount.root(), Path::new("/mnt/nas"));
+        assert_eq!(mount.mount_type(), "smb");
+    }
+
+    #[tokio::test]
+    async fn test_write_exists_and_delete() {
+        let (temp, _server, mount) = setup(FakeConfig::default()).await;
+        let source_dir = TempDir::new().unwrap();
+        // Larger than a single write chunk
+        let (source, data) = source_file(&source_dir, 200_000);
+
+        let dest = Path::new("movie/Heat (1995)/Heat (1995) - 1080p.mkv");
+        mount.write_file(&source, dest).await.unwrap();
+
+        assert_eq!(fs::read(temp.path().join(dest)).unwrap(), data);
+        assert!(mount.exists(dest).await);
+        assert!(mount.exists(Path::new("movie")).await);
+        assert!(!mount.exists(Path::new("movie/missing.mkv")).await);
+        assert!(!mount.exists(Path::new("nowhere/missing.mkv")).await);
+
+        // Overwrites replace the previous content
+        let (source, data) = source_file(&source_dir, 10);
+        mount.write_file(&source, dest).await.unwrap();
+        assert_eq!(fs::read(temp.path().join(dest)).unwrap(), data);
+
+        mount.delete_file(dest).await.unwrap();
+        assert!(!temp.path().join(dest).exists());
+        assert!(mount.delete_file(dest).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_create_dir_all_and_free_space() {
+        let (temp, _server, mount) = setup(FakeConfig::default()).await;
+
+        assert!(mount.available().await);
+        mount.create_dir_all(Path::new("a/b/c")).await.unwrap();
+        mount.create_dir_all(Path::new("a/b/c")).await.unwrap();
+        assert!(temp.path().join("a/b/c").is_dir());
+
+        let expected = FREE_UNITS * SECTORS_PER_UNIT as u64 * BYTES_PER_SECTOR as u64;
+        assert_eq!(mount.free_space().await.unwrap(), expected);
+    }
+
+    #[tokio::test]
+    async fn test_signed_sessions() {
+        for max_dialect in [SMB_2_1, SMB_3_0_2] {
+            let (temp, _server, mount) = setup(FakeConfig {
+                max_dialect,
+                require_signing: true,
+                ..Default::default()
+            })
+            .await;
+            let source_dir = TempDir::new().unwrap();
+            let (source, data) = source_file(&source_dir, 1_000);
+
+            mount
+                .write_file(&source, Path::new("music/track.flac"))
+                .await
+                .unwrap();
+            assert_eq!(
+                fs::read(temp.path().join("music/track.flac")).unwrap(),
+                data
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_domain_credentials() {
+        let (_temp, server, _) = setup(FakeConfig::default()).await;
+        let mount = SmbMount::new(
+            "nas".to_string(),
+            &server.addr,
+            "media",
+            Some("WORKGROUP\\lcars"),
+            Some("secret"),
+            None,
+        );
+        assert!(mount.available().await);
+    }
+
+    #[tokio::test]
+    async fn test_authentication_failure() {
+        let (_temp, server, _) = setup(FakeConfig::default()).await;
+        let mount = SmbMount::new(
+            "nas".to_string(),
+            &server.addr,
+            "media",
+            Some("lcars"),
+            Some("wrong"),
+            None,
+        );
+
+        assert!(!mount.available().await);
+        let err = mount.create_dir_all(Path::new("a")).await.unwrap_err();
+        assert!(err.to_string().contains("0xc000006d"));
+    }
+
+    #[tokio::test]
+    async fn test_unknown_share() {
+        let (_temp, server, _) = setup(FakeConfig::default()).await;
+        let mount = SmbMount::new(
+            "nas".to_string(),
+            &server.addr,
+            "other",
+            Some("lcars"),
+            Some("secret"),
+            None,
+        );
+        assert!(mount.free_space().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_reconnects_after_connection_loss() {
+        let (temp, server, mount) = setup(FakeConfig::default()).await;
+
+        mount.create_dir_all(Path::new("first")).await.unwrap();
+        assert_eq!(server.connections(), 1);
+
+        server.drop_connections();
+        mount.create_dir_all(Path::new("second")).await.unwrap();
+        assert_eq!(server.connections(), 2);
+        assert!(temp.path().join("second").is_dir());
+    }
+
+    #[tokio::test]
+    async fn test_path_traversal_prevention() {
+        let (_temp, server, mount) = setup(FakeConfig::default()).await;
+
+        let result = mount
+            .write_file(Path::new("/tmp/test"), Path::new("../escape"))
+            .await;
+        assert!(matches!(result, Err(AppError::BadRequest(_))));
+
+        let result = mount.delete_file(Path::new("foo/../../bar")).await;
+        assert!(matches!(result, Err(AppError::BadRequest(_))));
+
+        let result = mount.create_dir_all(Path::new("/etc")).await;
+        assert!(matches!(result, Err(AppError::BadRequest(_))));
+
+        assert!(!mount.exists(Path::new("../escape")).await);
+
+        // Rejected before any connection is made
+        assert_eq!(server.connections(), 0);
+    }
+}
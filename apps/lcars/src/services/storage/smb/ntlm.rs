@@ -0,0 +1,494 @@
+//! NTLMv2 authentication and the SPNEGO wrapping used by SMB2 session setup.
+//!
+//! Only what a client needs is implemented: building NEGOTIATE and AUTHENTICATE
+//! messages, parsing the server CHALLENGE and deriving the session key.
+
+use hmac::{Hmac, Mac};
+use md4::{Digest, Md4};
+use md5::Md5;
+
+type HmacMd5 = Hmac<Md5>;
+
+const SIGNATURE: &[u8; 8] = b"NTLMSSP\0";
+
+const NEGOTIATE_UNICODE: u32 = 0x0000_0001;
+const REQUEST_TARGET: u32 = 0x0000_0004;
+const NEGOTIATE_SIGN: u32 = 0x0000_0010;
+const NEGOTIATE_NTLM: u32 = 0x0000_0200;
+const NEGOTIATE_ALWAYS_SIGN: u32 = 0x0000_8000;
+const NEGOTIATE_EXTENDED_SESSIONSECURITY: u32 = 0x0008_0000;
+const NEGOTIATE_TARGET_INFO: u32 = 0x0080_0000;
+const NEGOTIATE_128: u32 = 0x2000_0000;
+const NEGOTIATE_56: u32 = 0x8000_0000;
+
+/// Flags sent in the NEGOTIATE message.
+///
+/// Key exchange is deliberately not offered, so the session key is the
+/// NTLMv2 session base key and no RC4 is involved.
+const CLIENT_FLAGS: u32 = NEGOTIATE_UNICODE
+    | REQUEST_TARGET
+    | NEGOTIATE_SIGN
+    | NEGOTIATE_NTLM
+    | NEGOTIATE_ALWAYS_SIGN
+    | NEGOTIATE_EXTENDED_SESSIONSECURITY
+    | NEGOTIATE_TARGET_INFO
+    | NEGOTIATE_128
+    | NEGOTIATE_56;
+
+/// AV pair carrying the server's FILETIME in the target info.
+const MSV_AV_TIMESTAMP: u16 = 7;
+const MSV_AV_EOL: u16 = 0;
+
+/// SPNEGO mechanism OID (1.3.6.1.5.5.2).
+const SPNEGO_OID: &[u8] = &[0x2b, 0x06, 0x01, 0x05, 0x05, 0x02];
+/// NTLMSSP mechanism OID (1.3.6.1.4.1.311.2.2.10).
+const NTLMSSP_OID: &[u8] = &[0x2b, 0x06, 0x01, 0x04, 0x01, 0x82, 0x37, 0x02, 0x02, 0x0a];
+
+/// Credentials used for NTLM authentication.
+#[derive(Clone)]
+pub struct Credentials {
+    pub domain: String,
+    pub username: String,
+    pub password: String,
+}
+
+impl Credentials {
+    /// Build credentials from a configured username, accepting `DOMAIN\user`.
+    pub fn new(username: &str, password: &str) -> Self {
+        let (domain, username) = match username.split_once('\\') {
+            Some((domain, user)) => (domain.to_string(), user.to_string()),
+            None => (String::new(), username.to_string()),
+        };
+
+        Self {
+            domain,
+            username,
+            password: password.to_string(),
+        }
+    }
+}
+
+// Custom Debug implementation to avoid exposing password
+impl std::fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Credentials")
+            .field("domain", &self.domain)
+            .field("username", &self.username)
+            .field("password", &"[REDACTED]")
+            .finish()
+    }
+}
+
+/// Server CHALLENGE message fields needed to answer it.
+#[derive(Debug, Clone)]
+pub struct Challenge {
+    pub flags: u32,
+    pub server_challenge: [u8; 8],
+    pub target_info: Vec<u8>,
+}
+
+impl Challenge {
+    /// Server FILETIME from the target info, if present.
+    fn timestamp(&self) -> Option<[u8; 8]> {
+        let mut info = self.target_info.as_slice();
+        while info.len() >= 4 {
+            let id = u16::from_le_bytes([info[0], info[1]]);
+            let len = u16::from_le_bytes([info[2], info[3]]) as usize;
+            if id == MSV_AV_EOL || info.len() < 4 + len {
+                break;
+            }
+            if id == MSV_AV_TIMESTAMP && len == 8 {
+                return info[4..12].try_into().ok();
+            }
+            info = &info[4 + len..];
+        }
+        None
+    }
+}
+
+/// A built AUTHENTICATE message and the session key it establishes.
+pub struct Authenticate {
+    pub message: Vec<u8>,
+    pub session_key: [u8; 16],
+}
+
+/// Build the NTLM NEGOTIATE message.
+pub fn negotiate_message() -> Vec<u8> {
+    let mut msg = Vec::with_capacity(32);
+    msg.extend_from_slice(SIGNATURE);
+    msg.extend_from_slice(&1u32.to_le_bytes());
+    msg.extend_from_slice(&CLIENT_FLAGS.to_le_bytes());
+    // Empty domain and workstation fields
+    msg.extend_from_slice(&[0u8; 16]);
+    msg
+}
+
+/// Parse an NTLM CHALLENGE message.
+pub fn parse_challenge(msg: &[u8]) -> Option<Challenge> {
+    if msg.len() < 48 || &msg[..8] != SIGNATURE || read_u32(msg, 8)? != 2 {
+        return None;
+    }
+
+    let flags = read_u32(msg, 20)?;
+    let server_challenge = msg[24..32].try_into().ok()?;
+    let target_info = read_field(msg, 40)?.to_vec();
+
+    Some(Challenge {
+        flags,
+        server_challenge,
+        target_info,
+    })
+}
+
+/// Build the NTLMv2 AUTHENTICATE message answering a challenge.
+///
+/// `client_challenge` must be random and `now` is the current time as a
+/// Windows FILETIME; the server timestamp is preferred when it sent one.
+pub fn authenticate_message(
+    creds: &Credentials,
+    challenge: &Challenge,
+    client_challenge: [u8; 8],
+    now: u64,
+) -> Authenticate {
+    let response_key = ntowf_v2(creds);
+    let server_timestamp = challenge.timestamp();
+    let timestamp = server_timestamp.unwrap_or_else(|| now.to_le_bytes());
+
+    let (nt_response, session_key) = nt_response_v2(
+        &response_key,
+        &challenge.server_challenge,
+        &client_challenge,
+        &timestamp,
+        &challenge.target_info,
+    );
+
+    // With a server timestamp the LM response must be all zeroes
+    let lm_response = if server_timestamp.is_some() {
+        vec![0u8; 24]
+    } else {
+        lm_response_v2(
+            &response_key,
+            &challenge.server_challenge,
+            &client_challenge,
+        )
+    };
+
+    let domain = utf16le(&creds.domain);
+    let user = utf16le(&creds.username);
+    let workstation: Vec<u8> = Vec::new();
+    let session_key_field: Vec<u8> = Vec::new();
+
+    const HEADER_LEN: usize = 64;
+    let fields = [
+        &lm_response,
+        &nt_response,
+        &domain,
+        &user,
+        &workstation,
+        &session_key_field,
+    ];
+
+    let mut msg = Vec::with_capacity(HEADER_LEN + fields.iter().map(|f| f.len()).sum::<usize>());
+    msg.extend_from_slice(SIGNATURE);
+    msg.extend_from_slice(&3u32.to_le_bytes());
+
+    let mut offset = HEADER_LEN;
+    for field in fields {
+        msg.extend_from_slice(&(field.len() as u16).to_le_bytes());
+        msg.extend_from_slice(&(field.len() as u16).to_le_bytes());
+        msg.extend_from_slice(&(offset as u32).to_le_bytes());
+        offset += field.len();
+    }
+
+    let flags = CLIENT_FLAGS & (challenge.flags | NEGOTIATE_UNICODE);
+    msg.extend_from_slice(&flags.to_le_bytes());
+
+    for field in fields {
+        msg.extend_from_slice(field);
+    }
+
+    Authenticate {
+        message: msg,
+        session_key,
+    }
+}
+
+/// NTOWFv2: HMAC-MD5 keyed with the NT hash over `UPPER(user) + domain`.
+fn ntowf_v2(creds: &Credentials) -> [u8; 16] {
+    let nt_hash = Md4::digest(utf16le(&creds.password));
+    let mut identity = utf16le(&creds.username.to_uppercase());
+    identity.extend_from_slice(&utf16le(&creds.domain));
+    hmac_md5(&nt_hash, &[&identity])
+}
+
+/// Compute the NTLMv2 response and the session base key.
+fn nt_response_v2(
+    response_key: &[u8; 16],
+    server_challenge: &[u8; 8],
+    client_challenge: &[u8; 8],
+    timestamp: &[u8; 8],
+    target_info: &[u8],
+) -> (Vec<u8>, [u8; 16]) {
+    let mut temp = Vec::with_capacity(32 + target_info.len());
+    temp.extend_from_slice(&[0x01, 0x01, 0, 0, 0, 0, 0, 0]);
+    temp.extend_from_slice(timestamp);
+    temp.extend_from_slice(client_challenge);
+    temp.extend_from_slice(&[0u8; 4]);
+    temp.extend_from_slice(target_info);
+    temp.extend_from_slice(&[0u8; 4]);
+
+    let proof = hmac_md5(response_key, &[server_challenge, &temp]);
+    let session_key = hmac_md5(response_key, &[&proof]);
+
+    let mut response = proof.to_vec();
+    response.extend_from_slice(&temp);
+    (response, session_key)
+}
+
+/// Compute the LMv2 response.
+fn lm_response_v2(
+    response_key: &[u8; 16],
+    server_challenge: &[u8; 8],
+    client_challenge: &[u8; 8],
+) -> Vec<u8> {
+    let mut response = hmac_md5(response_key, &[server_challenge, client_challenge]).to_vec();
+    response.extend_from_slice(client_challenge);
+    response
+}
+
+fn hmac_md5(key: &[u8], parts: &[&[u8]]) -> [u8; 16] {
+    let mut mac = HmacMd5::new_from_slice(key).expect("HMAC accepts any key length");
+    for part in parts {
+        mac.update(part);
+    }
+    mac.finalize().into_bytes().into()
+}
+
+/// Encode a string as UTF-16LE.
+pub fn utf16le(s: &str) -> Vec<u8> {
+    s.encode_utf16().flat_map(|c| c.to_le_bytes()).collect()
+}
+
+fn read_u32(buf: &[u8], offset: usize) -> Option<u32> {
+    Some(u32::from_le_bytes(
+        buf.get(offset..offset + 4)?.try_into().ok()?,
+    ))
+}
+
+/// Read a (length, max length, offset) payload field.
+fn read_field(msg: &[u8], field_offset: usize) -> Option<&[u8]> {
+    let len = u16::from_le_bytes(msg.get(field_offset..field_offset + 2)?.try_into().ok()?);
+    let offset = read_u32(msg, field_offset + 4)? as usize;
+    msg.get(offset..offset + len as usize)
+}
+
+// =============================================================================
+// SPNEGO
+// =============================================================================
+
+/// Wrap an NTLM NEGOTIATE message in a SPNEGO NegTokenInit.
+pub fn spnego_init(token: &[u8]) -> Vec<u8> {
+    let mech_types = der(0xa0, &der(0x30, &der(0x06, NTLMSSP_OID)));
+    let mech_token = der(0xa2, &der(0x04, token));
+    let neg_token_init = der(0xa0, &der(0x30, &[mech_types, mech_token].concat()));
+    der(0x60, &[der(0x06, SPNEGO_OID), neg_token_init].concat())
+}
+
+/// Wrap an NTLM AUTHENTICATE message in a SPNEGO NegTokenResp.
+pub fn spnego_response(token: &[u8]) -> Vec<u8> {
+    der(0xa1, &der(0x30, &der(0xa2, &der(0x04, token))))
+}
+
+/// Find the NTLMSSP message inside a (possibly SPNEGO wrapped) security blob.
+pub fn find_ntlmssp(blob: &[u8]) -> Option<&[u8]> {
+    blob.windows(SIGNATURE.len())
+        .position(|w| w == SIGNATURE)
+        .map(|pos| &blob[pos..])
+}
+
+/// Encode a DER tag-length-value.
+fn der(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    let len = content.len();
+    if len < 0x80 {
+        out.push(len as u8);
+    } else {
+        let bytes: Vec<u8> = len
+            .to_be_bytes()
+            .into_iter()
+            .skip_while(|b| *b == 0)
+            .collect();
+        out.push(0x80 | bytes.len() as u8);
+        out.extend_from_slice(&bytes);
+    }
+    out.extend_from_slice(content);
+    out
+}
+
+// =============================================================================
+// Server side (used by the fake server in tests)
+// =============================================================================
+
+/// Build a CHALLENGE message with the given target info.
+#[cfg(test)]
+pub fn challenge_message(server_challenge: [u8; 8], target_info: &[u8]) -> Vec<u8> {
+    let mut msg = Vec::with_capacity(48 + target_info.len());
+    msg.extend_from_slice(SIGNATURE);
+    msg.extend_from_slice(&2u32.to_le_bytes());
+    // Empty target name
+    msg.extend_from_slice(&[0, 0, 0, 0]);
+    msg.extend_from_slice(&48u32.to_le_bytes());
+    msg.extend_from_slice(&CLIENT_FLAGS.to_le_bytes());
+    msg.extend_from_slice(&server_challenge);
+    msg.extend_from_slice(&[0u8; 8]);
+    msg.extend_from_slice(&(target_info.len() as u16).to_le_bytes());
+    msg.extend_from_slice(&(target_info.len() as u16).to_le_bytes());
+    msg.extend_from_slice(&48u32.to_le_bytes());
+    msg.extend_from_slice(target_info);
+    msg
+}
+
+/// Check an AUTHENTICATE message against a password, returning the session key.
+#[cfg(test)]
+pub fn verify_authenticate(
+    msg: &[u8],
+    server_challenge: &[u8; 8],
+    username: &str,
+    password: &str,
+) -> Option<[u8; 16]> {
+    if msg.len() < 64 || &msg[..8] != SIGNATURE || read_u32(msg, 8)? != 3 {
+        return None;
+    }
+
+    let nt_response = read_field(msg, 20)?;
+    let domain = String::from_utf16_lossy(
+        &read_field(msg, 28)?
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .collect::<Vec<_>>(),
+    );
+    let user = String::from_utf16_lossy(
+        &read_field(msg, 36)?
+            .chunks_exact(2)
+            .map(|c| u16::from_le_bytes([c[0], c[1]]))
+            .collect::<Vec<_>>(),
+    );
+    if !user.eq_ignore_ascii_case(username) || nt_response.len() < 16 {
+        return None;
+    }
+
+    let creds = Credentials {
+        domain,
+        username: user,
+        password: password.to_string(),
+    };
+    let response_key = ntowf_v2(&creds);
+    let (proof, temp) = nt_response.split_at(16);
+    let expected = hmac_md5(&response_key, &[server_challenge, temp]);
+    if proof != expected {
+        return None;
+    }
+
+    Some(hmac_md5(&response_key, &[proof]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Test vectors from MS-NLMP 4.2.4 (NTLMv2 Authentication)
+    fn spec_credentials() -> Credentials {
+        Credentials {
+            domain: "Domain".to_string(),
+            username: "User".to_string(),
+            password: "Password".to_string(),
+        }
+    }
+
+    fn spec_target_info() -> Vec<u8> {
+        let mut info = Vec::new();
+        info.extend_from_slice(&[0x02, 0x00, 0x0c, 0x00]);
+        info.extend_from_slice(&utf16le("Domain"));
+        info.extend_from_slice(&[0x01, 0x00, 0x0c, 0x00]);
+        info.extend_from_slice(&utf16le("Server"));
+        info.extend_from_slice(&[0x00, 0x00, 0x00, 0x00]);
+        info
+    }
+
+    const SERVER_CHALLENGE: [u8; 8] = [0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef];
+    const CLIENT_CHALLENGE: [u8; 8] = [0xaa; 8];
+
+    #[test]
+    fn test_ntowf_v2() {
+        assert_eq!(
+            hex::encode(ntowf_v2(&spec_credentials())),
+            "0c868a403bfd7a93a3001ef22ef02e3f"
+        );
+    }
+
+    #[test]
+    fn test_nt_response_v2() {
+        let key = ntowf_v2(&spec_credentials());
+        let (response, session_key) = nt_response_v2(
+            &key,
+            &SERVER_CHALLENGE,
+            &CLIENT_CHALLENGE,
+            &[0u8; 8],
+            &spec_target_info(),
+        );
+
+        assert_eq!(
+            hex::encode(&response[..16]),
+            "68cd0ab851e51c96aabc927bebef6a1c"
+        );
+        assert_eq!(hex::encode(session_key), "8de40ccadbc14a82f15cb0ad0de95ca3");
+    }
+
+    #[test]
+    fn test_lm_response_v2() {
+        let key = ntowf_v2(&spec_credentials());
+        let response = lm_response_v2(&key, &SERVER_CHALLENGE, &CLIENT_CHALLENGE);
+        assert_eq!(
+            hex::encode(response),
+            "86c35097ac9cec102554764a57cccc19aaaaaaaaaaaaaaaa"
+        );
+    }
+
+    #[test]
+    fn test_authenticate_roundtrip() {
+        let creds = Credentials::new("WORKGROUP\\alice", "secret");
+        assert_eq!(creds.domain, "WORKGROUP");
+        assert_eq!(creds.username, "alice");
+
+        let challenge =
+            parse_challenge(&challenge_message(SERVER_CHALLENGE, &spec_target_info())).unwrap();
+        assert_eq!(challenge.server_challenge, SERVER_CHALLENGE);
+
+        let auth = authenticate_message(&creds, &challenge, CLIENT_CHALLENGE, 0);
+        let key = verify_authenticate(&auth.message, &SERVER_CHALLENGE, "alice", "secret");
+        assert_eq!(key, Some(auth.session_key));
+        assert!(verify_authenticate(&auth.message, &SERVER_CHALLENGE, "alice", "wrong").is_none());
+    }
+
+    #[test]
+    fn test_spnego_wrapping() {
+        let token = negotiate_message();
+        let wrapped = spnego_init(&token);
+        assert_eq!(wrapped[0], 0x60);
+        assert_eq!(find_ntlmssp(&wrapped), Some(token.as_slice()));
+
+        let big = vec![0x42u8; 300];
+        let wrapped = spnego_response(&big);
+        assert_eq!(&wrapped[..4], &[0xa1, 0x82, 0x01, 0x38]);
+    }
+
+    #[test]
+    fn test_challenge_timestamp() {
+        let mut info = vec![0x07, 0x00, 0x08, 0x00];
+        info.extend_from_slice(&42u64.to_le_bytes());
+        info.extend_from_slice(&[0, 0, 0, 0]);
+
+        let challenge = parse_challenge(&challenge_message(SERVER_CHALLENGE, &info)).unwrap();
+        assert_eq!(challenge.timestamp(), Some(42u64.to_le_bytes()));
+    }
+}
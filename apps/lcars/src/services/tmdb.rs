@@ -17,6 +17,7 @@ const REQUEST_TIMEOUT_SECS: u64 = 30;
 pub struct TmdbClient {
     client: Client,
     api_key: String,
+    base_url: String,
 }
 
 impl TmdbClient {
@@ -35,7 +36,17 @@ impl TmdbClient {
             .build()
             .map_err(|e| AppError::Internal(format!("Failed to create HTTP client: {}", e)))?;
 
-        Ok(Self { client, api_key })
+        Ok(Self {
+            client,
+            api_key,
+            base_url: TMDB_BASE_URL.to_string(),
+        })
+    }
+
+    /// Use a different API base URL (e.g. a local stub in tests).
+    pub fn with_base_url(mut self, base_url: impl Into<String>) -> Self {
+        self.base_url = base_url.into().trim_end_matches('/').to_string();
+        self
     }
 
     /// Create a new TMDB client wrapped in Arc for shared access.
@@ -90,7 +101,10 @@ impl TmdbClient {
     pub async fn get_tv(&self, id: i32) -> Result<TmdbTvDetails> {
         tracing::debug!(tv_id = %id, "Fetching TMDB TV show details");
 
-        let params = [("api_key", self.api_key.clone())];
+        let params = [
+            ("api_key", self.api_key.clone()),
+            ("append_to_response", "external_ids".to_string()),
+        ];
         self.get_with_params(&format!("/tv/{}", id), &params).await
     }
 
@@ -130,7 +144,7 @@ impl TmdbClient {
         T: for<'de> Deserialize<'de>,
         P: serde::Serialize,
     {
-        let url = format!("{}{}", TMDB_BASE_URL, path);
+        let url = format!("{}{}", self.base_url, path);
 
         let response = self
             .client
@@ -1,24 +1,48 @@
 //! Torrent download engine service.
 //!
 //! Provides BitTorrent client functionality using librqbit for downloading media.
-//! Supports VPN interface binding for traffic isolation.
+//! Supports VPN interface binding for traffic isolation: when an interface is
+//! configured, peer and tracker connections go through an [`InterfaceProxy`]
+//! bound to it, and the engine only starts if the rest of its traffic is
+//! routed through the interface.
 
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
 use librqbit::{
-    api::TorrentIdOrHash, dht::Id20, AddTorrent, AddTorrentOptions, AddTorrentResponse,
-    ManagedTorrent, Session, SessionOptions, TorrentStats, TorrentStatsState,
+    api::TorrentIdOrHash, dht::Id20, limits::LimitsConfig, AddTorrent, AddTorrentOptions,
+    AddTorrentResponse, ByteBufOwned, ManagedTorrent, Session, SessionOptions,
+    SessionPersistenceConfig, TorrentMetaV1Info, TorrentStats, TorrentStatsState,
 };
 use serde::Serialize;
 use std::collections::HashMap;
+use std::num::NonZeroU32;
+use std::path::PathBuf;
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::sync::{broadcast, RwLock};
+use tokio::task::JoinHandle;
 
 use crate::config::TorrentConfig;
 use crate::db::models::{DownloadStatus, MediaType};
 use crate::error::{AppError, Result};
 
+use super::bandwidth::{kib_to_bytes, BandwidthManager, SpeedLimits};
+use super::interface_proxy::{check_default_route, InterfaceProxy};
+use super::torrent_source::{torrent_file_path, torrent_info_hash, TorrentSource};
 use super::wireguard::{WireGuardEvent, WireGuardService};
 
+/// Folder inside the download directory where the session remembers its torrents.
+const SESSION_STATE_DIR: &str = ".lcars-session";
+
+/// How long to wait for peers to send a magnet link's metadata.
+const METADATA_TIMEOUT_SECS: u64 = 60;
+
+/// Timeout for downloading .torrent files.
+const FETCH_TIMEOUT_SECS: u64 = 30;
+
+/// Largest .torrent file accepted from a URL.
+const MAX_TORRENT_FILE_BYTES: u64 = 10 * 1024 * 1024;
+
 /// Convert an info_hash Id<20> to a hex string.
 fn info_hash_to_string(id: &Id20) -> String {
     hex::encode(id.0)
@@ -42,6 +66,61 @@ fn extract_live_stats(stats: &TorrentStats) -> (u64, u64, usize) {
         .unwrap_or((0, 0, 0))
 }
 
+/// Convert KiB/s caps to librqbit's limits.
+fn limits_config(limits: SpeedLimits) -> LimitsConfig {
+    let bps = |kib: Option<u64>| {
+        kib.and_then(|kib| NonZeroU32::new(kib_to_bytes(kib).min(u64::from(u32::MAX)) as u32))
+    };
+    LimitsConfig {
+        download_bps: bps(limits.download),
+        upload_bps: bps(limits.upload),
+    }
+}
+
+/// List the files of a torrent, keeping the indexes librqbit uses.
+///
+/// Padding files (BEP 47) are left out but still count towards the indexes.
+fn torrent_files(info: &TorrentMetaV1Info<ByteBufOwned>) -> Result<Vec<TorrentFile>> {
+    let details = info
+        .iter_file_details()
+        .map_err(|e| AppError::Internal(format!("Invalid torrent metadata: {}", e)))?;
+
+    let mut files = Vec::new();
+    for (index, file) in details.enumerate() {
+        if file.attrs().padding {
+            continue;
+        }
+        let path = file
+            .filename
+            .to_string()
+            .map_err(|e| AppError::Internal(format!("Invalid torrent file name: {}", e)))?;
+        files.push(TorrentFile {
+            index,
+            path,
+            size: file.len,
+        });
+    }
+    Ok(files)
+}
+
+/// Deepest path shared by all paths: the path itself when there is only one.
+fn common_path(paths: &[PathBuf]) -> PathBuf {
+    let Some((first, rest)) = paths.split_first() else {
+        return PathBuf::new();
+    };
+
+    let mut common: Vec<_> = first.components().collect();
+    for path in rest {
+        let shared = common
+            .iter()
+            .zip(path.components())
+            .take_while(|(a, b)| **a == *b)
+            .count();
+        common.truncate(shared);
+    }
+    common.iter().collect()
+}
+
 /// Event emitted by the torrent engine for progress tracking.
 #[derive(Debug, Clone, Serialize)]
 #[serde(tag = "type", rename_all = "snake_case")]
@@ -60,6 +139,8 @@ pub enum TorrentEvent {
     Completed { info_hash: String },
     /// A torrent has encountered an error.
     Error { info_hash: String, message: String },
+    /// A torrent made no progress for `minutes` minutes and stopped being monitored.
+    Stalled { info_hash: String, minutes: u64 },
     /// A torrent has been removed.
     Removed { info_hash: String },
     /// A torrent has been paused.
@@ -72,6 +153,11 @@ pub enum TorrentEvent {
     KillSwitchDeactivated,
 }
 
+/// Error message recorded for a torrent that stalled.
+pub fn stall_message(minutes: u64) -> String {
+    format!("No progress for {} minutes", minutes)
+}
+
 /// Reference to associated media for a torrent download.
 ///
 /// Links a torrent download to its corresponding media entry in the database.
@@ -101,6 +187,33 @@ pub struct TorrentStatus {
     pub error: Option<String>,
 }
 
+/// A file inside a torrent.
+#[derive(Debug, Clone, Serialize)]
+pub struct TorrentFile {
+    /// Index to pass when selecting files to download.
+    pub index: usize,
+    /// Path of the file relative to the torrent's folder.
+    pub path: String,
+    /// File size in bytes.
+    pub size: u64,
+}
+
+/// Metadata of a torrent resolved from a magnet link.
+#[derive(Debug, Clone, Serialize)]
+pub struct TorrentListing {
+    pub info_hash: String,
+    pub name: String,
+    pub files: Vec<TorrentFile>,
+}
+
+/// A torrent handed to the engine.
+#[derive(Debug, Clone)]
+pub struct AddedTorrent {
+    pub info_hash: String,
+    /// URI to record in `downloads.source_uri`, see [`TorrentSource::from_uri`].
+    pub source_uri: String,
+}
+
 /// Internal tracking info for each torrent.
 ///
 /// Stores metadata about each active torrent for internal use.
@@ -108,9 +221,13 @@ struct TorrentInfo {
     /// Associated media reference for database tracking.
     media_ref: MediaRef,
     /// When the torrent started seeding (for time-based seeding limits).
-    seeding_started_at: Option<std::time::Instant>,
+    seeding_started_at: Option<DateTime<Utc>>,
     /// Whether this torrent was paused by the VPN kill switch (not by user).
     paused_by_kill_switch: bool,
+    /// Whether the completion event was sent, so later monitors do not repeat it.
+    completion_reported: bool,
+    /// The task monitoring the torrent, there is only ever one per torrent.
+    monitor: Option<JoinHandle<()>>,
 }
 
 /// BitTorrent download engine using librqbit.
@@ -126,7 +243,11 @@ pub struct TorrentEngine {
     config: TorrentConfig,
     event_tx: broadcast::Sender<TorrentEvent>,
     /// Maps info_hash to torrent tracking info
-    torrents: RwLock<HashMap<String, TorrentInfo>>,
+    torrents: Arc<RwLock<HashMap<String, TorrentInfo>>>,
+    /// Proxy carrying torrent traffic when bound to an interface, stopped on drop
+    _interface_proxy: Option<InterfaceProxy>,
+    /// Client for .torrent URLs, going through the interface proxy if any
+    http: reqwest::Client,
 }
 
 impl TorrentEngine {
@@ -169,23 +290,40 @@ impl TorrentEngine {
             })?;
         }
 
-        // Log VPN interface binding configuration (requires system-level routing)
-        if !config.bind_interface.is_empty() {
-            tracing::info!(interface = %config.bind_interface, "Binding torrent traffic to interface");
-            // Note: librqbit doesn't directly support interface binding in SessionOptions
-            // This would require using socks_proxy_url or system-level routing
-            tracing::warn!(
-                "VPN interface binding ({}) requires system-level routing configuration",
-                config.bind_interface
-            );
-        }
-
-        let opts = SessionOptions {
+        // Fastresume only takes effect when the session is persisted
+        let mut opts = SessionOptions {
             listen_port_range: Some(config.port_range.0..config.port_range.1),
             fastresume: true,
+            persistence: Some(SessionPersistenceConfig::Json {
+                folder: Some(config.download_dir.join(SESSION_STATE_DIR)),
+            }),
             ..Default::default()
         };
 
+        // librqbit binds its sockets to all interfaces. Peer connections and
+        // HTTP trackers go through a proxy bound to the interface; DHT, UDP
+        // trackers and the incoming listener cannot be bound, so refuse to
+        // start unless the routing table already sends them through it.
+        let interface_proxy = if config.bind_interface.is_empty() {
+            None
+        } else {
+            let proxy = InterfaceProxy::start(&config.bind_interface).await?;
+            check_default_route(&config.bind_interface).await?;
+            opts.socks_proxy_url = Some(proxy.url());
+            tracing::info!(interface = %config.bind_interface, "Binding torrent traffic to interface");
+            Some(proxy)
+        };
+
+        let mut http = reqwest::Client::builder().timeout(Duration::from_secs(FETCH_TIMEOUT_SECS));
+        if let Some(proxy) = &interface_proxy {
+            let proxy = reqwest::Proxy::all(proxy.url())
+                .map_err(|e| AppError::Internal(format!("Invalid interface proxy: {}", e)))?;
+            http = http.proxy(proxy);
+        }
+        let http = http
+            .build()
+            .map_err(|e| AppError::Internal(format!("Failed to create HTTP client: {}", e)))?;
+
         let session = Session::new_with_opts(config.download_dir.clone(), opts)
             .await
             .map_err(|e| AppError::Internal(format!("Failed to create torrent session: {}", e)))?;
@@ -202,7 +340,9 @@ impl TorrentEngine {
             session,
             config,
             event_tx,
-            torrents: RwLock::new(HashMap::new()),
+            torrents: Arc::new(RwLock::new(HashMap::new())),
+            _interface_proxy: interface_proxy,
+            http,
         })
     }
 
@@ -218,62 +358,164 @@ impl TorrentEngine {
     pub async fn add_magnet(&self, magnet: &str, media_ref: MediaRef) -> Result<String> {
         tracing::debug!(magnet = %magnet, media_type = ?media_ref.media_type, media_id = %media_ref.media_id, "Adding magnet link");
 
-        let add_torrent = AddTorrent::from_url(magnet);
-        let opts = AddTorrentOptions::default();
+        self.add(
+            &TorrentSource::Magnet(magnet.to_string()),
+            media_ref,
+            None,
+            None,
+            false,
+            SpeedLimits::default(),
+        )
+        .await
+    }
 
-        let response = self
-            .session
-            .add_torrent(add_torrent, Some(opts))
-            .await
-            .map_err(|e| AppError::Internal(format!("Failed to add torrent: {}", e)))?;
+    /// Add a torrent from any source, downloading only the files at the given indexes.
+    ///
+    /// Indexes are those returned by [`TorrentEngine::list_files`]. `None`
+    /// downloads every file. .torrent URLs are fetched through the same
+    /// network path as torrent traffic, and uploaded .torrent files are kept
+    /// in the download directory so the torrent can be restored.
+    pub async fn add_source(
+        &self,
+        source: &TorrentSource,
+        media_ref: MediaRef,
+        files: Option<Vec<usize>>,
+    ) -> Result<AddedTorrent> {
+        tracing::debug!(kind = source.kind(), media_type = ?media_ref.media_type, media_id = %media_ref.media_id, files = ?files, "Adding torrent");
+
+        if files.as_ref().is_some_and(|f| f.is_empty()) {
+            return Err(AppError::BadRequest(
+                "At least one file must be selected".to_string(),
+            ));
+        }
 
-        let (info_hash, _name) = match response {
-            AddTorrentResponse::AlreadyManaged(id, handle) => {
-                let info_hash = info_hash_to_string(&handle.info_hash());
-                let name = handle.name().unwrap_or_else(|| format!("Torrent {}", id));
-                tracing::debug!(info_hash = %info_hash, "Torrent already managed");
-                (info_hash, name)
+        let source_uri = match source {
+            TorrentSource::Magnet(uri) | TorrentSource::Url(uri) => uri.clone(),
+            TorrentSource::File(bytes) => self.save_torrent_file(bytes).await?,
+        };
+
+        let info_hash = self
+            .add(
+                source,
+                media_ref,
+                files,
+                None,
+                false,
+                SpeedLimits::default(),
+            )
+            .await?;
+
+        Ok(AddedTorrent {
+            info_hash,
+            source_uri,
+        })
+    }
+
+    /// Resolve a torrent's metadata and list its files without downloading.
+    pub async fn list_files(&self, source: &TorrentSource) -> Result<TorrentListing> {
+        let opts = AddTorrentOptions {
+            list_only: true,
+            ..Default::default()
+        };
+        let add_torrent = self.add_torrent_request(source).await?;
+
+        let response = tokio::time::timeout(
+            Duration::from_secs(METADATA_TIMEOUT_SECS),
+            self.session.add_torrent(add_torrent, Some(opts)),
+        )
+        .await
+        .map_err(|_| AppError::Timeout("Timed out resolving torrent metadata".to_string()))?
+        .map_err(|e| AppError::Internal(format!("Failed to resolve torrent: {}", e)))?;
+
+        let (info_hash, info) = match response {
+            AddTorrentResponse::ListOnly(list) => (list.info_hash, list.info),
+            AddTorrentResponse::AlreadyManaged(_, handle)
+            | AddTorrentResponse::Added(_, handle) => {
+                let info = handle
+                    .with_metadata(|metadata| metadata.info.clone())
+                    .map_err(|e| AppError::Internal(format!("Failed to read torrent: {}", e)))?;
+                (handle.info_hash(), info)
             }
-            AddTorrentResponse::Added(id, handle) => {
-                let info_hash = info_hash_to_string(&handle.info_hash());
-                let name = handle.name().unwrap_or_else(|| format!("Torrent {}", id));
+        };
 
-                // Store tracking info
-                {
-                    let mut torrents = self.torrents.write().await;
-                    torrents.insert(
-                        info_hash.clone(),
-                        TorrentInfo {
-                            media_ref: media_ref.clone(),
-                            seeding_started_at: None,
-                            paused_by_kill_switch: false,
-                        },
-                    );
-                }
+        let name = info
+            .name
+            .as_ref()
+            .map(|n| String::from_utf8_lossy(n.as_ref()).to_string())
+            .unwrap_or_else(|| info_hash_to_string(&info_hash));
 
-                // Emit added event
-                let _ = self.event_tx.send(TorrentEvent::Added {
-                    info_hash: info_hash.clone(),
-                    name: name.clone(),
-                });
+        Ok(TorrentListing {
+            info_hash: info_hash_to_string(&info_hash),
+            name,
+            files: torrent_files(&info)?,
+        })
+    }
 
-                // Start monitoring task
-                self.spawn_monitor_task(handle);
+    /// Re-attach a torrent recorded in the database after a restart.
+    ///
+    /// Torrents the session remembered are picked up as they are, others are
+    /// added again from `source_uri` and checked against the files already on
+    /// disk. Returns the info_hash of the torrent.
+    pub async fn restore(
+        &self,
+        source_uri: &str,
+        media_ref: MediaRef,
+        files: Option<Vec<usize>>,
+        seeding_started_at: Option<DateTime<Utc>>,
+        paused: bool,
+        limits: SpeedLimits,
+    ) -> Result<String> {
+        tracing::debug!(source_uri = %source_uri, media_type = ?media_ref.media_type, media_id = %media_ref.media_id, "Restoring torrent");
+
+        let source = TorrentSource::from_uri(source_uri).await?;
+        self.add(
+            &source,
+            media_ref,
+            files,
+            seeding_started_at,
+            paused,
+            limits,
+        )
+        .await
+    }
 
-                tracing::info!(info_hash = %info_hash, name = %name, "Torrent added successfully");
-                (info_hash, name)
-            }
-            AddTorrentResponse::ListOnly(list_response) => {
-                // ListOnly mode returns torrent file list without downloading
-                let file_count = list_response.info.files.as_ref().map_or(1, |f| f.len());
-                return Err(AppError::BadRequest(format!(
-                    "Torrent contains {} files, use list-only mode to select files",
-                    file_count
-                )));
-            }
+    /// Set a torrent's own speed limits, on top of the global ones.
+    ///
+    /// librqbit only takes per-torrent limits when a torrent is added, so the
+    /// torrent is taken out of the session and added back, keeping its files.
+    pub async fn set_torrent_limits(&self, info_hash: &str, limits: SpeedLimits) -> Result<()> {
+        let handle = self.get_torrent_handle(info_hash)?;
+        let handle = self.readd(&handle, limits).await?;
+        self.spawn_monitor_task(handle).await;
+
+        tracing::info!(
+            info_hash = %info_hash,
+            download_kib = ?limits.download,
+            upload_kib = ?limits.upload,
+            "Torrent speed limits changed"
+        );
+        Ok(())
+    }
+
+    /// Follow the global bandwidth limits, including alt-speed switches.
+    pub fn apply_bandwidth_limits(self: &Arc<Self>, bandwidth: &BandwidthManager) {
+        let mut rx = bandwidth.subscribe();
+        let session = Arc::clone(&self.session);
+
+        let set_limits = move |session: &Session, limits: SpeedLimits| {
+            let config = limits_config(limits);
+            session.ratelimits.set_download_bps(config.download_bps);
+            session.ratelimits.set_upload_bps(config.upload_bps);
         };
 
-        Ok(info_hash)
+        set_limits(&session, *rx.borrow_and_update());
+        tokio::spawn(async move {
+            while rx.changed().await.is_ok() {
+                let limits = *rx.borrow_and_update();
+                set_limits(&session, limits);
+                tracing::debug!(?limits, "Applied bandwidth limits to torrent session");
+            }
+        });
     }
 
     /// Get the current status of a torrent by its info_hash.
@@ -335,6 +577,9 @@ impl TorrentEngine {
             info_hash: info_hash.to_string(),
         });
 
+        // The monitor stops when a torrent is paused
+        self.spawn_monitor_task(handle).await;
+
         tracing::debug!(info_hash = %info_hash, "Torrent resumed");
         Ok(())
     }
@@ -355,7 +600,9 @@ impl TorrentEngine {
         // Remove from tracking
         {
             let mut torrents = self.torrents.write().await;
-            torrents.remove(info_hash);
+            if let Some(monitor) = torrents.remove(info_hash).and_then(|info| info.monitor) {
+                monitor.abort();
+            }
         }
 
         let _ = self.event_tx.send(TorrentEvent::Removed {
@@ -422,10 +669,11 @@ impl TorrentEngine {
                 // Check time limit
                 if let Some(info) = torrents.get(&info_hash) {
                     if let Some(started_at) = info.seeding_started_at {
-                        if started_at.elapsed() >= time_limit {
+                        let elapsed = (Utc::now() - started_at).to_std().unwrap_or_default();
+                        if elapsed >= time_limit {
                             tracing::debug!(
                                 info_hash = %info_hash,
-                                elapsed = ?started_at.elapsed(),
+                                elapsed = ?elapsed,
                                 limit = ?time_limit,
                                 "Torrent reached time limit"
                             );
@@ -543,6 +791,7 @@ impl TorrentEngine {
                         let _ = self.event_tx.send(TorrentEvent::Resumed {
                             info_hash: info_hash.clone(),
                         });
+                        self.spawn_monitor_task(handle).await;
                     }
                 }
             }
@@ -629,6 +878,48 @@ impl TorrentEngine {
         self.session.stop().await;
     }
 
+    /// Get the path of a torrent's content on disk.
+    ///
+    /// Single-file torrents resolve to the file itself, multi-file torrents to
+    /// the directory holding their files.
+    ///
+    /// When only some files of a multi-file torrent are downloaded, this is
+    /// the deepest path holding all of them, so that skipped files (which are
+    /// created empty or partially written) are left out.
+    pub fn content_path(&self, info_hash: &str) -> Result<PathBuf> {
+        let handle = self.get_torrent_handle(info_hash)?;
+        let name = handle.name().ok_or_else(|| {
+            AppError::NotFound(format!("Torrent metadata not resolved: {}", info_hash))
+        })?;
+        let root = self.config.download_dir.join(name);
+
+        let Some(only_files) = handle.only_files() else {
+            return Ok(root);
+        };
+        let selected: Vec<PathBuf> = handle
+            .with_metadata(|metadata| {
+                if metadata.info.files.is_none() {
+                    return Vec::new();
+                }
+                only_files
+                    .iter()
+                    .filter_map(|&i| metadata.file_infos.get(i))
+                    .map(|f| f.relative_filename.clone())
+                    .collect()
+            })
+            .map_err(|e| AppError::Internal(format!("Failed to read torrent: {}", e)))?;
+
+        Ok(root.join(common_path(&selected)))
+    }
+
+    /// Get when a torrent started seeding, if it finished downloading.
+    pub async fn seeding_started_at(&self, info_hash: &str) -> Option<DateTime<Utc>> {
+        let torrents = self.torrents.read().await;
+        torrents
+            .get(info_hash)
+            .and_then(|info| info.seeding_started_at)
+    }
+
     /// Get the media reference associated with a torrent.
     pub async fn get_media_ref(&self, info_hash: &str) -> Option<MediaRef> {
         let torrents = self.torrents.read().await;
@@ -639,6 +930,224 @@ impl TorrentEngine {
     // Private helpers
     // =========================================================================
 
+    /// Add a torrent to the session and start tracking it.
+    ///
+    /// Torrents the session already manages are tracked too when nothing is
+    /// known about them yet, e.g. because the session restored them on startup.
+    async fn add(
+        &self,
+        source: &TorrentSource,
+        media_ref: MediaRef,
+        only_files: Option<Vec<usize>>,
+        seeding_started_at: Option<DateTime<Utc>>,
+        paused: bool,
+        limits: SpeedLimits,
+    ) -> Result<String> {
+        let add_torrent = self.add_torrent_request(source).await?;
+        let opts = AddTorrentOptions {
+            paused,
+            only_files,
+            ratelimits: limits_config(limits),
+            ..Default::default()
+        };
+
+        let response = self
+            .session
+            .add_torrent(add_torrent, Some(opts))
+            .await
+            .map_err(|e| {
+                let message = e.to_string();
+                if message.contains("out of range") {
+                    AppError::BadRequest(format!("Invalid file selection: {}", message))
+                } else {
+                    AppError::Internal(format!("Failed to add torrent: {}", message))
+                }
+            })?;
+
+        let (id, mut handle, added) = match response {
+            AddTorrentResponse::AlreadyManaged(id, handle) => (id, handle, false),
+            AddTorrentResponse::Added(id, handle) => (id, handle, true),
+            AddTorrentResponse::ListOnly(_) => {
+                return Err(AppError::Internal(
+                    "Torrent session only listed the torrent's files".to_string(),
+                ));
+            }
+        };
+
+        // The session does not remember per-torrent limits across restarts
+        if !added && !limits.is_unlimited() {
+            handle = self.readd(&handle, limits).await?;
+        }
+
+        let info_hash = info_hash_to_string(&handle.info_hash());
+        let name = handle.name().unwrap_or_else(|| format!("Torrent {}", id));
+
+        // Store tracking info
+        let tracked = {
+            let mut torrents = self.torrents.write().await;
+            if torrents.contains_key(&info_hash) {
+                false
+            } else {
+                torrents.insert(
+                    info_hash.clone(),
+                    TorrentInfo {
+                        media_ref,
+                        seeding_started_at,
+                        paused_by_kill_switch: false,
+                        completion_reported: false,
+                        monitor: None,
+                    },
+                );
+                true
+            }
+        };
+
+        if !tracked {
+            tracing::debug!(info_hash = %info_hash, "Torrent already managed");
+            return Ok(info_hash);
+        }
+
+        if added {
+            // Emit added event
+            let _ = self.event_tx.send(TorrentEvent::Added {
+                info_hash: info_hash.clone(),
+                name: name.clone(),
+            });
+        }
+
+        // Start monitoring task
+        self.spawn_monitor_task(handle).await;
+
+        tracing::info!(info_hash = %info_hash, name = %name, "Torrent added successfully");
+        Ok(info_hash)
+    }
+
+    /// Take a torrent out of the session and add it back with new limits.
+    ///
+    /// The torrent keeps its files, file selection, trackers and paused state,
+    /// and is rebuilt from its info dictionary so no source is needed.
+    async fn readd(
+        &self,
+        handle: &Arc<ManagedTorrent>,
+        limits: SpeedLimits,
+    ) -> Result<Arc<ManagedTorrent>> {
+        let info_bytes = handle
+            .with_metadata(|metadata| metadata.info_bytes.clone())
+            .map_err(|_| AppError::Conflict("Torrent metadata is not resolved yet".to_string()))?;
+        let mut torrent = b"d4:info".to_vec();
+        torrent.extend_from_slice(&info_bytes);
+        torrent.push(b'e');
+
+        let opts = AddTorrentOptions {
+            paused: handle.is_paused(),
+            only_files: handle.only_files(),
+            trackers: Some(
+                handle
+                    .shared
+                    .trackers
+                    .iter()
+                    .map(|t| t.to_string())
+                    .collect(),
+            ),
+            ratelimits: limits_config(limits),
+            ..Default::default()
+        };
+
+        self.session
+            .delete(TorrentIdOrHash::Id(handle.id()), false)
+            .await
+            .map_err(|e| AppError::Internal(format!("Failed to remove torrent: {}", e)))?;
+
+        let response = self
+            .session
+            .add_torrent(AddTorrent::from_bytes(torrent), Some(opts))
+            .await
+            .map_err(|e| AppError::Internal(format!("Failed to add torrent back: {}", e)))?;
+
+        match response {
+            AddTorrentResponse::AlreadyManaged(_, handle)
+            | AddTorrentResponse::Added(_, handle) => Ok(handle),
+            AddTorrentResponse::ListOnly(_) => Err(AppError::Internal(
+                "Torrent session only listed the torrent's files".to_string(),
+            )),
+        }
+    }
+
+    /// Build the session request for a torrent source.
+    ///
+    /// .torrent URLs are fetched here rather than by the session, so they go
+    /// through the same client (and interface proxy) as everything else.
+    async fn add_torrent_request(&self, source: &TorrentSource) -> Result<AddTorrent<'static>> {
+        let bytes = match source {
+            TorrentSource::Magnet(magnet) => return Ok(AddTorrent::from_url(magnet.clone())),
+            TorrentSource::Url(url) => self.fetch_torrent_file(url).await?,
+            TorrentSource::File(bytes) => bytes.clone(),
+        };
+
+        // Reject anything that is not a .torrent file before the session sees it
+        torrent_info_hash(&bytes)?;
+
+        Ok(AddTorrent::from_bytes(bytes))
+    }
+
+    /// Download a .torrent file.
+    async fn fetch_torrent_file(&self, url: &str) -> Result<Bytes> {
+        let mut response = self
+            .http
+            .get(url)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|e| {
+                AppError::BadRequest(format!("Failed to download torrent file {}: {}", url, e))
+            })?;
+
+        if response
+            .content_length()
+            .is_some_and(|len| len > MAX_TORRENT_FILE_BYTES)
+        {
+            return Err(AppError::BadRequest(format!(
+                "Torrent file {} is too large",
+                url
+            )));
+        }
+
+        // The length header is optional, so stop reading once over the limit
+        let mut body = Vec::new();
+        while let Some(chunk) = response.chunk().await.map_err(|e| {
+            AppError::BadRequest(format!("Failed to download torrent file {}: {}", url, e))
+        })? {
+            if (body.len() + chunk.len()) as u64 > MAX_TORRENT_FILE_BYTES {
+                return Err(AppError::BadRequest(format!(
+                    "Torrent file {} is too large",
+                    url
+                )));
+            }
+            body.extend_from_slice(&chunk);
+        }
+        let bytes = Bytes::from(body);
+
+        Ok(bytes)
+    }
+
+    /// Keep a copy of an uploaded .torrent file, returning its `file://` URI.
+    pub async fn save_torrent_file(&self, bytes: &Bytes) -> Result<String> {
+        let info_hash = torrent_info_hash(bytes)?;
+        let path = torrent_file_path(&self.config.download_dir, &info_hash);
+        let path = std::path::absolute(&path).unwrap_or(path);
+
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|e| AppError::Internal(format!("Failed to create {:?}: {}", parent, e)))?;
+        }
+        tokio::fs::write(&path, bytes)
+            .await
+            .map_err(|e| AppError::Internal(format!("Failed to save torrent file: {}", e)))?;
+
+        Ok(format!("file://{}", path.display()))
+    }
+
     /// Get a torrent handle by info_hash.
     fn get_torrent_handle(&self, info_hash: &str) -> Result<Arc<ManagedTorrent>> {
         use std::cell::RefCell;
@@ -705,14 +1214,21 @@ impl TorrentEngine {
 
     /// Spawn a background task to monitor torrent progress.
     ///
-    /// The task runs until the torrent is paused, errored, or removed.
-    /// It emits progress events every second and completion events when finished.
-    fn spawn_monitor_task(&self, handle: Arc<ManagedTorrent>) {
+    /// The task runs until the torrent is paused, errored, stalled, or removed.
+    /// It emits progress events every second and a completion event the first
+    /// time the torrent is finished. A monitor still running for the torrent is
+    /// stopped, so events are never sent twice.
+    async fn spawn_monitor_task(&self, handle: Arc<ManagedTorrent>) {
         let event_tx = self.event_tx.clone();
+        let torrents = Arc::clone(&self.torrents);
         let info_hash = info_hash_to_string(&handle.info_hash());
+        let stall_minutes = self.config.stall_timeout_minutes;
 
-        tokio::spawn(async move {
+        let monitor_info_hash = info_hash.clone();
+        let monitor = tokio::spawn(async move {
             let mut last_finished = false;
+            let mut last_progress_bytes = 0;
+            let mut last_progress_at = Instant::now();
 
             loop {
                 let stats = handle.stats();
@@ -742,16 +1258,28 @@ impl TorrentEngine {
 
                 // Check for completion
                 if stats.finished && !last_finished {
-                    if event_tx
-                        .send(TorrentEvent::Completed {
-                            info_hash: info_hash.clone(),
-                        })
-                        .is_err()
-                    {
-                        tracing::trace!("No subscribers for torrent completion event");
-                    }
-                    tracing::info!(info_hash = %info_hash, name = %name, "Torrent completed");
                     last_finished = true;
+
+                    // Seeding time counts from the first completion, across restarts
+                    let first = match torrents.write().await.get_mut(&info_hash) {
+                        Some(info) => {
+                            info.seeding_started_at.get_or_insert_with(Utc::now);
+                            !std::mem::replace(&mut info.completion_reported, true)
+                        }
+                        None => true,
+                    };
+
+                    if first {
+                        if event_tx
+                            .send(TorrentEvent::Completed {
+                                info_hash: info_hash.clone(),
+                            })
+                            .is_err()
+                        {
+                            tracing::trace!("No subscribers for torrent completion event");
+                        }
+                        tracing::info!(info_hash = %info_hash, name = %name, "Torrent completed");
+                    }
                 }
 
                 // Check for errors
@@ -764,6 +1292,22 @@ impl TorrentEngine {
                     break;
                 }
 
+                // Check for stalls, only counting time spent downloading
+                let downloading = matches!(stats.state, TorrentStatsState::Live) && !stats.finished;
+                if !downloading || stats.progress_bytes > last_progress_bytes {
+                    last_progress_bytes = stats.progress_bytes;
+                    last_progress_at = Instant::now();
+                } else if stall_minutes > 0
+                    && last_progress_at.elapsed() >= Duration::from_secs(stall_minutes * 60)
+                {
+                    let _ = event_tx.send(TorrentEvent::Stalled {
+                        info_hash: info_hash.clone(),
+                        minutes: stall_minutes,
+                    });
+                    tracing::warn!(info_hash = %info_hash, name = %name, peers, minutes = stall_minutes, "Torrent stalled");
+                    break;
+                }
+
                 // Check if torrent is paused or removed
                 if matches!(stats.state, TorrentStatsState::Paused) {
                     tracing::debug!(info_hash = %info_hash, "Monitoring paused torrent, stopping monitor");
@@ -773,20 +1317,30 @@ impl TorrentEngine {
                 tokio::time::sleep(Duration::from_secs(1)).await;
             }
         });
+
+        let mut torrents = self.torrents.write().await;
+        match torrents.get_mut(&monitor_info_hash) {
+            Some(info) => {
+                if let Some(previous) = info.monitor.replace(monitor) {
+                    previous.abort();
+                }
+            }
+            None => tracing::trace!(info_hash = %monitor_info_hash, "Monitoring untracked torrent"),
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use std::path::PathBuf;
 
-    #[allow(dead_code)]
     fn test_config() -> TorrentConfig {
         TorrentConfig {
             download_dir: PathBuf::from("/tmp/lcars-test-downloads"),
             bind_interface: String::new(),
             max_connections: 50,
+            max_active_downloads: 5,
+            stall_timeout_minutes: 60,
             port_range: (6881, 6889),
             seeding: crate::config::SeedingConfig {
                 enabled: true,
@@ -851,4 +1405,86 @@ mod tests {
         let json = serde_json::to_string(&event).unwrap();
         assert!(json.contains("\"type\":\"kill_switch_deactivated\""));
     }
+
+    #[test]
+    fn test_common_path() {
+        let paths = |p: &[&str]| p.iter().map(PathBuf::from).collect::<Vec<_>>();
+
+        assert_eq!(
+            common_path(&paths(&["Season 1/Show.S01E05.mkv"])),
+            PathBuf::from("Season 1/Show.S01E05.mkv")
+        );
+        assert_eq!(
+            common_path(&paths(&[
+                "2004 - Album/01.flac",
+                "2004 - Album/CD2/01.flac"
+            ])),
+            PathBuf::from("2004 - Album")
+        );
+        assert_eq!(
+            common_path(&paths(&["A/01.flac", "B/01.flac"])),
+            PathBuf::new()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_bind_interface_requires_route_through_it() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = test_config();
+        config.download_dir = dir.path().to_path_buf();
+        config.bind_interface = "lo".to_string();
+
+        // The internet is never reached through loopback
+        let result = TorrentEngine::new(config).await;
+        assert!(matches!(result, Err(AppError::Vpn(_))));
+    }
+
+    #[tokio::test]
+    async fn test_fetch_torrent_file_stops_past_limit() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        // Chunked response without a length, larger than the limit
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = [0u8; 1024];
+            let _ = stream.read(&mut request).await;
+            let header = "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n";
+            stream.write_all(header.as_bytes()).await.unwrap();
+            let chunk = vec![b'a'; 1024 * 1024];
+            for _ in 0..=MAX_TORRENT_FILE_BYTES / chunk.len() as u64 {
+                let size = format!("{:x}\r\n", chunk.len());
+                if stream.write_all(size.as_bytes()).await.is_err()
+                    || stream.write_all(&chunk).await.is_err()
+                    || stream.write_all(b"\r\n").await.is_err()
+                {
+                    return;
+                }
+            }
+            let _ = stream.write_all(b"0\r\n\r\n").await;
+        });
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = test_config();
+        config.download_dir = dir.path().to_path_buf();
+        let engine = TorrentEngine::new(config).await.unwrap();
+
+        let err = engine
+            .fetch_torrent_file(&format!("http://{}/big.torrent", addr))
+            .await
+            .unwrap_err();
+        assert!(err.to_string().contains("too large"));
+    }
+
+    #[tokio::test]
+    async fn test_missing_bind_interface_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = test_config();
+        config.download_dir = dir.path().to_path_buf();
+        config.bind_interface = "lcars-missing0".to_string();
+
+        let result = TorrentEngine::new(config).await;
+        assert!(matches!(result, Err(AppError::Vpn(_))));
+    }
 }
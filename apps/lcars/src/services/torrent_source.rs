@@ -0,0 +1,171 @@
+//! Torrent sources: magnet links, .torrent URLs and .torrent files.
+//!
+//! The kind of source a download came from is kept in `downloads.source_uri`:
+//! the magnet link, the `http(s)://` URL of the .torrent file, or a `file://`
+//! URI pointing at the copy of an uploaded .torrent file.
+
+use bytes::Bytes;
+use librqbit::{torrent_from_bytes, ByteBuf, Magnet};
+use std::path::PathBuf;
+
+use crate::error::{AppError, Result};
+
+/// Folder inside the download directory where uploaded .torrent files are kept.
+pub const TORRENT_FILES_DIR: &str = ".lcars-torrents";
+
+/// Where a torrent comes from.
+#[derive(Debug, Clone)]
+pub enum TorrentSource {
+    /// Magnet link.
+    Magnet(String),
+    /// HTTP(S) URL of a .torrent file.
+    Url(String),
+    /// Contents of a .torrent file.
+    File(Bytes),
+}
+
+impl TorrentSource {
+    /// Parse a link given by a user or an indexer: a magnet link or an HTTP(S) URL.
+    pub fn from_link(link: &str) -> Option<Self> {
+        if link.starts_with("magnet:?") {
+            Some(Self::Magnet(link.to_string()))
+        } else if link.starts_with("http://") || link.starts_with("https://") {
+            Some(Self::Url(link.to_string()))
+        } else {
+            None
+        }
+    }
+
+    /// Load the source recorded in `downloads.source_uri`.
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if the URI is of an unknown kind or a recorded
+    /// .torrent file cannot be read.
+    pub async fn from_uri(uri: &str) -> Result<Self> {
+        if let Some(path) = uri.strip_prefix("file://") {
+            let bytes = tokio::fs::read(path).await.map_err(|e| {
+                AppError::Internal(format!("Failed to read torrent file {}: {}", path, e))
+            })?;
+            return Ok(Self::File(Bytes::from(bytes)));
+        }
+
+        Self::from_link(uri)
+            .ok_or_else(|| AppError::BadRequest(format!("Unsupported torrent source: {}", uri)))
+    }
+
+    /// Kind of source, for logging.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Magnet(_) => "magnet",
+            Self::Url(_) => "url",
+            Self::File(_) => "file",
+        }
+    }
+}
+
+/// Info hash of a .torrent file.
+///
+/// # Errors
+///
+/// Returns a bad request error if the bytes are not a valid .torrent file.
+pub fn torrent_info_hash(torrent: &[u8]) -> Result<String> {
+    let meta = torrent_from_bytes::<ByteBuf>(torrent)
+        .map_err(|e| AppError::BadRequest(format!("Invalid .torrent file: {}", e)))?;
+    Ok(hex::encode(meta.info_hash.0))
+}
+
+/// Info hash of a magnet link, as lowercase hex.
+///
+/// Returns `None` for other links, whose info hash is only known once the
+/// .torrent file is fetched.
+pub fn magnet_info_hash(link: &str) -> Option<String> {
+    if !link.starts_with("magnet:?") {
+        return None;
+    }
+    let magnet = Magnet::parse(link).ok()?;
+    Some(hex::encode(magnet.as_id20()?.0))
+}
+
+/// Where the copy of an uploaded .torrent file is kept.
+pub fn torrent_file_path(download_dir: &std::path::Path, info_hash: &str) -> PathBuf {
+    download_dir
+        .join(TORRENT_FILES_DIR)
+        .join(format!("{}.torrent", info_hash))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A minimal single-file .torrent with the given tracker entries.
+    fn torrent(trackers: &str) -> Vec<u8> {
+        let info =
+            "d6:lengthi12e4:name8:file.txt12:piece lengthi16384e6:pieces20:aaaaaaaaaaaaaaaaaaaae";
+        format!("d{}4:info{}e", trackers, info).into_bytes()
+    }
+
+    #[test]
+    fn test_from_link() {
+        assert!(matches!(
+            TorrentSource::from_link("magnet:?xt=urn:btih:abc"),
+            Some(TorrentSource::Magnet(_))
+        ));
+        assert!(matches!(
+            TorrentSource::from_link("https://tracker.example/dl/1.torrent"),
+            Some(TorrentSource::Url(_))
+        ));
+        assert!(TorrentSource::from_link("file:///etc/passwd").is_none());
+        assert!(TorrentSource::from_link("not-a-link").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_from_uri_reads_torrent_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("a.torrent");
+        std::fs::write(&path, torrent("")).unwrap();
+
+        let source = TorrentSource::from_uri(&format!("file://{}", path.display()))
+            .await
+            .unwrap();
+        assert_eq!(source.kind(), "file");
+
+        let missing = format!("file://{}", dir.path().join("b.torrent").display());
+        assert!(TorrentSource::from_uri(&missing).await.is_err());
+    }
+
+    #[test]
+    fn test_torrent_info_hash() {
+        let plain = torrent_info_hash(&torrent("")).unwrap();
+        assert_eq!(plain.len(), 40);
+
+        // Trackers are outside the info dictionary
+        let with_tracker = torrent_info_hash(&torrent("8:announce9:udp://a:1")).unwrap();
+        assert_eq!(plain, with_tracker);
+
+        assert!(matches!(
+            torrent_info_hash(b"not a torrent"),
+            Err(AppError::BadRequest(_))
+        ));
+    }
+
+    #[test]
+    fn test_magnet_info_hash() {
+        let hex = "c12fe1c06bba254a9dc9f519b335aa7c1367a88a";
+        assert_eq!(
+            magnet_info_hash(&format!(
+                "magnet:?xt=urn:btih:{}&dn=Movie",
+                hex.to_uppercase()
+            ))
+            .as_deref(),
+            Some(hex)
+        );
+        // Base32 info hashes come out as hex too
+        assert_eq!(
+            magnet_info_hash("magnet:?xt=urn:btih:YEX6DQDLXISUVHOJ6UM3GNNKPQJWPKEK").as_deref(),
+            Some(hex)
+        );
+        assert!(magnet_info_hash("https://tracker.example/dl/1.torrent").is_none());
+        assert!(magnet_info_hash("magnet:?dn=no-hash").is_none());
+    }
+}
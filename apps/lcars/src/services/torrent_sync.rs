@@ -0,0 +1,618 @@
+//! Torrent state persistence.
+//!
+//! The torrent engine only keeps the media each torrent belongs to and when it
+//! started seeding in memory. [`TorrentSync`] checkpoints engine events into the
+//! `downloads` table and, on startup, hands every torrent recorded there back to
+//! the engine so downloads keep their media association and seeding clock
+//! across restarts.
+
+use std::collections::HashSet;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use rusqlite::Connection;
+use tokio::sync::{broadcast, Mutex};
+
+use crate::db::models::{DownloadStatus, MediaType};
+use crate::error::Result;
+use crate::services::bandwidth::SpeedLimits;
+use crate::services::torrent::{stall_message, MediaRef, TorrentEvent, TorrentStatus};
+use crate::services::TorrentEngine;
+
+/// How often progress is written to the database.
+const CHECKPOINT_INTERVAL_SECS: u64 = 10;
+
+/// Timestamp format used by SQLite's `datetime('now')`.
+const DB_TIMESTAMP_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// A torrent download recorded in the database.
+#[derive(Debug, Clone)]
+struct StoredTorrent {
+    source_id: String,
+    source_uri: String,
+    media_ref: MediaRef,
+    selected_files: Option<Vec<usize>>,
+    limits: SpeedLimits,
+    paused: bool,
+    seeding_started_at: Option<DateTime<Utc>>,
+}
+
+/// Keeps the `downloads` table in sync with the torrent engine.
+pub struct TorrentSync {
+    db: Arc<Mutex<Connection>>,
+    engine: Arc<TorrentEngine>,
+}
+
+impl TorrentSync {
+    /// Create a new torrent sync.
+    pub fn new(db: Arc<Mutex<Connection>>, engine: Arc<TorrentEngine>) -> Self {
+        Self { db, engine }
+    }
+
+    /// Create a new torrent sync wrapped in Arc for shared access.
+    pub fn new_shared(db: Arc<Mutex<Connection>>, engine: Arc<TorrentEngine>) -> Arc<Self> {
+        Arc::new(Self::new(db, engine))
+    }
+
+    /// Hand the torrents recorded in the database back to the engine.
+    ///
+    /// Each torrent is restored in the background, as resolving a magnet link
+    /// can take a while. Returns the number of torrents being restored.
+    pub async fn rehydrate(&self) -> Result<usize> {
+        let torrents = {
+            let db = self.db.lock().await;
+            load_torrents(&db)?
+        };
+
+        for torrent in &torrents {
+            let engine = Arc::clone(&self.engine);
+            let torrent = torrent.clone();
+            tokio::spawn(async move {
+                match engine
+                    .restore(
+                        &torrent.source_uri,
+                        torrent.media_ref,
+                        torrent.selected_files,
+                        torrent.seeding_started_at,
+                        torrent.paused,
+                        torrent.limits,
+                    )
+                    .await
+                {
+                    Ok(info_hash) if info_hash != torrent.source_id => {
+                        tracing::warn!(
+                            source_id = %torrent.source_id,
+                            info_hash = %info_hash,
+                            "Restored torrent does not match its download"
+                        );
+                    }
+                    Ok(_) => {
+                        tracing::debug!(source_id = %torrent.source_id, "Torrent restored");
+                    }
+                    Err(e) => {
+                        tracing::error!(
+                            source_id = %torrent.source_id,
+                            error = %e,
+                            "Failed to restore torrent"
+                        );
+                    }
+                }
+            });
+        }
+
+        tracing::info!(count = torrents.len(), "Restoring torrents");
+        Ok(torrents.len())
+    }
+
+    /// Record torrent events in the database as the engine emits them.
+    ///
+    /// Status changes are written right away, progress every
+    /// [`CHECKPOINT_INTERVAL_SECS`] seconds.
+    pub fn watch(self: &Arc<Self>) {
+        let sync = Arc::clone(self);
+        let mut rx = self.engine.subscribe();
+
+        tokio::spawn(async move {
+            tracing::info!("Torrent state sync enabled");
+
+            let mut dirty = HashSet::new();
+            let mut interval = tokio::time::interval(Duration::from_secs(CHECKPOINT_INTERVAL_SECS));
+
+            loop {
+                tokio::select! {
+                    event = rx.recv() => match event {
+                        Ok(TorrentEvent::Progress { info_hash, .. }) => {
+                            dirty.insert(info_hash);
+                        }
+                        Ok(event) => {
+                            if let TorrentEvent::Removed { info_hash } = &event {
+                                dirty.remove(info_hash);
+                            }
+                            if let Err(e) = sync.record_event(&event).await {
+                                tracing::error!(?event, error = %e, "Failed to record torrent event");
+                            }
+                        }
+                        Err(broadcast::error::RecvError::Lagged(n)) => {
+                            tracing::warn!(missed = n, "Torrent event receiver lagged, missed events");
+                        }
+                        Err(broadcast::error::RecvError::Closed) => {
+                            tracing::debug!("Torrent event channel closed, stopping state sync");
+                            break;
+                        }
+                    },
+                    _ = interval.tick() => {
+                        for info_hash in dirty.drain() {
+                            if let Err(e) = sync.checkpoint(&info_hash).await {
+                                tracing::debug!(info_hash = %info_hash, error = %e, "Failed to checkpoint torrent");
+                            }
+                        }
+                    }
+                }
+            }
+        });
+    }
+
+    /// Write the current stats of a torrent to its download row.
+    pub async fn checkpoint(&self, info_hash: &str) -> Result<()> {
+        let status = self.engine.get_status(info_hash).await?;
+        let db = self.db.lock().await;
+        write_status(&db, &status)
+    }
+
+    /// Record a status change of a torrent.
+    async fn record_event(&self, event: &TorrentEvent) -> Result<()> {
+        match event {
+            TorrentEvent::Completed { info_hash } => {
+                let started_at = self
+                    .engine
+                    .seeding_started_at(info_hash)
+                    .await
+                    .unwrap_or_else(Utc::now);
+                let db = self.db.lock().await;
+                mark_seeding(&db, info_hash, started_at)
+            }
+            TorrentEvent::Error { info_hash, message } => {
+                let db = self.db.lock().await;
+                mark_failed(&db, info_hash, message)
+            }
+            TorrentEvent::Stalled { info_hash, minutes } => {
+                let db = self.db.lock().await;
+                mark_failed(&db, info_hash, &stall_message(*minutes))
+            }
+            TorrentEvent::Paused { info_hash } => {
+                let db = self.db.lock().await;
+                set_status(&db, info_hash, DownloadStatus::Paused)
+            }
+            TorrentEvent::Resumed { info_hash } => {
+                let db = self.db.lock().await;
+                set_status(&db, info_hash, DownloadStatus::Downloading)
+            }
+            TorrentEvent::Removed { info_hash } => {
+                let db = self.db.lock().await;
+                mark_removed(&db, info_hash)
+            }
+            TorrentEvent::Added { .. }
+            | TorrentEvent::Progress { .. }
+            | TorrentEvent::KillSwitchActivated
+            | TorrentEvent::KillSwitchDeactivated => Ok(()),
+        }
+    }
+}
+
+/// Load the torrents that were still in the engine when LCARS stopped.
+///
+/// Queued downloads were never in the engine, the download queue starts them.
+/// Imported torrents whose files are gone are marked removed rather than
+/// downloaded again.
+fn load_torrents(conn: &Connection) -> Result<Vec<StoredTorrent>> {
+    let mut stmt = conn.prepare(
+        r#"
+        SELECT source_id, source_uri, media_type, media_id, status, seeding_started_at,
+               selected_files, download_limit, upload_limit, content_path
+        FROM downloads
+        WHERE source_type = 'torrent' AND removed_at IS NULL AND status NOT IN ('failed', 'queued')
+        ORDER BY added_at
+        "#,
+    )?;
+
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, String>(0)?,
+            row.get::<_, String>(1)?,
+            row.get::<_, String>(2)?,
+            row.get::<_, i64>(3)?,
+            row.get::<_, String>(4)?,
+            row.get::<_, Option<String>>(5)?,
+            row.get::<_, Option<String>>(6)?,
+            row.get::<_, Option<i64>>(7)?,
+            row.get::<_, Option<i64>>(8)?,
+            row.get::<_, Option<String>>(9)?,
+        ))
+    })?;
+
+    let mut torrents = Vec::new();
+    let mut gone = Vec::new();
+    for row in rows {
+        let (
+            source_id,
+            source_uri,
+            media_type,
+            media_id,
+            status,
+            seeding_started_at,
+            selected_files,
+            download_limit,
+            upload_limit,
+            content_path,
+        ) = row?;
+
+        if status == "completed" && !content_path.is_some_and(|p| Path::new(&p).exists()) {
+            tracing::info!(source_id = %source_id, "Files of imported torrent are gone, not restoring it");
+            gone.push(source_id);
+            continue;
+        }
+
+        let media_type = match media_type.as_str() {
+            "movie" => MediaType::Movie,
+            "episode" => MediaType::Episode,
+            "album" => MediaType::Album,
+            "track" => MediaType::Track,
+            other => {
+                tracing::warn!(source_id = %source_id, media_type = %other, "Skipping torrent with unknown media type");
+                continue;
+            }
+        };
+
+        torrents.push(StoredTorrent {
+            source_id,
+            source_uri,
+            media_ref: MediaRef {
+                media_type,
+                media_id,
+            },
+            selected_files: selected_files.and_then(|f| serde_json::from_str(&f).ok()),
+            limits: SpeedLimits {
+                download: download_limit.map(|l| l as u64),
+                upload: upload_limit.map(|l| l as u64),
+            },
+            paused: status == "paused",
+            seeding_started_at: seeding_started_at.as_deref().and_then(parse_timestamp),
+        });
+    }
+
+    for source_id in gone {
+        mark_removed(conn, &source_id)?;
+    }
+
+    Ok(torrents)
+}
+
+/// Write torrent stats, and its status while the engine still owns the download.
+fn write_status(conn: &Connection, status: &TorrentStatus) -> Result<()> {
+    // 'queued' is for downloads waiting in the download queue, an initializing
+    // torrent is already downloading
+    let download_status = match status.status {
+        DownloadStatus::Queued => DownloadStatus::Downloading,
+        other => other,
+    };
+    conn.execute(
+        r#"
+        UPDATE downloads SET
+            progress = ?1, download_speed = ?2, upload_speed = ?3, peers = ?4,
+            downloaded_bytes = ?5, uploaded_bytes = ?6, ratio = ?7,
+            size_bytes = COALESCE(?8, size_bytes),
+            status = CASE WHEN status IN ('queued', 'downloading', 'seeding', 'paused')
+                THEN ?9 ELSE status END
+        WHERE source_type = 'torrent' AND source_id = ?10
+        "#,
+        rusqlite::params![
+            status.progress.clamp(0.0, 100.0),
+            status.download_speed as i64,
+            status.upload_speed as i64,
+            status.peers as i64,
+            status.downloaded as i64,
+            status.uploaded as i64,
+            status.ratio.max(0.0),
+            (status.size > 0).then_some(status.size as i64),
+            download_status.to_string(),
+            status.info_hash,
+        ],
+    )?;
+    Ok(())
+}
+
+/// Record that a torrent finished downloading and started seeding.
+fn mark_seeding(conn: &Connection, info_hash: &str, started_at: DateTime<Utc>) -> Result<()> {
+    conn.execute(
+        r#"
+        UPDATE downloads SET
+            seeding_started_at = COALESCE(seeding_started_at, ?1),
+            status = CASE WHEN status IN ('queued', 'downloading', 'paused')
+                THEN 'seeding' ELSE status END
+        WHERE source_type = 'torrent' AND source_id = ?2
+        "#,
+        rusqlite::params![format_timestamp(started_at), info_hash],
+    )?;
+    Ok(())
+}
+
+/// Record a torrent error.
+fn mark_failed(conn: &Connection, info_hash: &str, message: &str) -> Result<()> {
+    conn.execute(
+        r#"
+        UPDATE downloads SET status = 'failed', error_message = ?1
+        WHERE source_type = 'torrent' AND source_id = ?2
+          AND status IN ('queued', 'downloading', 'seeding', 'paused')
+        "#,
+        rusqlite::params![message, info_hash],
+    )?;
+    Ok(())
+}
+
+/// Set the status of a torrent the engine still owns.
+fn set_status(conn: &Connection, info_hash: &str, status: DownloadStatus) -> Result<()> {
+    conn.execute(
+        r#"
+        UPDATE downloads SET status = ?1
+        WHERE source_type = 'torrent' AND source_id = ?2
+          AND status IN ('queued', 'downloading', 'seeding', 'paused')
+        "#,
+        rusqlite::params![status.to_string(), info_hash],
+    )?;
+    Ok(())
+}
+
+/// Record that a torrent left the engine, so it is not restored on startup.
+fn mark_removed(conn: &Connection, info_hash: &str) -> Result<()> {
+    conn.execute(
+        r#"
+        UPDATE downloads SET removed_at = COALESCE(removed_at, datetime('now'))
+        WHERE source_type = 'torrent' AND source_id = ?1
+        "#,
+        [info_hash],
+    )?;
+    Ok(())
+}
+
+fn format_timestamp(timestamp: DateTime<Utc>) -> String {
+    timestamp.format(DB_TIMESTAMP_FORMAT).to_string()
+}
+
+fn parse_timestamp(value: &str) -> Option<DateTime<Utc>> {
+    NaiveDateTime::parse_from_str(value, DB_TIMESTAMP_FORMAT)
+        .ok()
+        .map(|t| t.and_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn insert_download(conn: &Connection, source_id: &str, status: &str) {
+        let tmdb_id: i64 = conn
+            .query_row("SELECT COUNT(*) + 1 FROM movies", [], |row| row.get(0))
+            .unwrap();
+        conn.execute(
+            "INSERT INTO movies (tmdb_id, title, year, status) VALUES (?1, 'Movie', 2024, 'downloading')",
+            [tmdb_id],
+        )
+        .unwrap();
+        let movie_id = conn.last_insert_rowid();
+        conn.execute(
+            "INSERT INTO downloads (source_type, source_id, name, media_type, media_id, source_uri, status) VALUES ('torrent', ?1, 'Movie', 'movie', ?2, ?3, ?4)",
+            rusqlite::params![
+                source_id,
+                movie_id,
+                format!("magnet:?xt=urn:btih:{}", source_id),
+                status
+            ],
+        )
+        .unwrap();
+    }
+
+    fn column(conn: &Connection, column: &str, source_id: &str) -> Option<String> {
+        conn.query_row(
+            &format!(
+                "SELECT CAST({} AS TEXT) FROM downloads WHERE source_id = ?1",
+                column
+            ),
+            [source_id],
+            |row| row.get(0),
+        )
+        .unwrap()
+    }
+
+    fn status(info_hash: &str, status: DownloadStatus) -> TorrentStatus {
+        TorrentStatus {
+            info_hash: info_hash.to_string(),
+            name: "Movie".to_string(),
+            status,
+            progress: 42.5,
+            download_speed: 1000,
+            upload_speed: 500,
+            downloaded: 425,
+            uploaded: 100,
+            size: 1000,
+            ratio: 0.25,
+            peers: 7,
+            error: None,
+        }
+    }
+
+    #[test]
+    fn test_load_torrents_skips_removed_and_failed() {
+        let conn = crate::db::init_db_memory().unwrap();
+        insert_download(&conn, "active", "downloading");
+        insert_download(&conn, "paused", "paused");
+        insert_download(&conn, "failed", "failed");
+        insert_download(&conn, "queued", "queued");
+        insert_download(&conn, "removed", "seeding");
+        mark_removed(&conn, "removed").unwrap();
+        mark_seeding(
+            &conn,
+            "active",
+            parse_timestamp("2024-01-02 03:04:05").unwrap(),
+        )
+        .unwrap();
+
+        let torrents = load_torrents(&conn).unwrap();
+        let ids: Vec<_> = torrents.iter().map(|t| t.source_id.as_str()).collect();
+        assert_eq!(ids, ["active", "paused"]);
+
+        assert_eq!(torrents[0].source_uri, "magnet:?xt=urn:btih:active");
+        assert_eq!(torrents[0].media_ref.media_type, MediaType::Movie);
+        assert!(!torrents[0].paused);
+        assert_eq!(
+            torrents[0]
+                .seeding_started_at
+                .map(format_timestamp)
+                .as_deref(),
+            Some("2024-01-02 03:04:05")
+        );
+        assert!(torrents[1].paused);
+        assert!(torrents[1].seeding_started_at.is_none());
+    }
+
+    #[test]
+    fn test_load_torrents_skips_imported_without_files() {
+        let conn = crate::db::init_db_memory().unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        insert_download(&conn, "seeding", "completed");
+        insert_download(&conn, "moved", "completed");
+        conn.execute(
+            "UPDATE downloads SET content_path = ?1 WHERE source_id = 'seeding'",
+            [dir.path().to_string_lossy()],
+        )
+        .unwrap();
+        conn.execute(
+            "UPDATE downloads SET content_path = ?1 WHERE source_id = 'moved'",
+            [dir.path().join("gone").to_string_lossy()],
+        )
+        .unwrap();
+
+        let torrents = load_torrents(&conn).unwrap();
+        let ids: Vec<_> = torrents.iter().map(|t| t.source_id.as_str()).collect();
+        assert_eq!(ids, ["seeding"]);
+        assert!(column(&conn, "removed_at", "moved").is_some());
+        assert!(column(&conn, "removed_at", "seeding").is_none());
+    }
+
+    #[test]
+    fn test_load_torrents_keeps_file_selection() {
+        let conn = crate::db::init_db_memory().unwrap();
+        insert_download(&conn, "all", "downloading");
+        insert_download(&conn, "some", "downloading");
+        conn.execute(
+            "UPDATE downloads SET selected_files = '[1,3]' WHERE source_id = 'some'",
+            [],
+        )
+        .unwrap();
+
+        let torrents = load_torrents(&conn).unwrap();
+        assert_eq!(torrents[0].selected_files, None);
+        assert_eq!(torrents[1].selected_files, Some(vec![1, 3]));
+    }
+
+    #[test]
+    fn test_load_torrents_keeps_speed_limits() {
+        let conn = crate::db::init_db_memory().unwrap();
+        insert_download(&conn, "free", "downloading");
+        insert_download(&conn, "capped", "downloading");
+        conn.execute(
+            "UPDATE downloads SET download_limit = 512 WHERE source_id = 'capped'",
+            [],
+        )
+        .unwrap();
+
+        let torrents = load_torrents(&conn).unwrap();
+        let limits = |id: &str| {
+            torrents
+                .iter()
+                .find(|t| t.source_id == id)
+                .map(|t| t.limits)
+                .unwrap()
+        };
+        assert!(limits("free").is_unlimited());
+        assert_eq!(
+            limits("capped"),
+            SpeedLimits {
+                download: Some(512),
+                upload: None
+            }
+        );
+    }
+
+    #[test]
+    fn test_write_status() {
+        let conn = crate::db::init_db_memory().unwrap();
+        insert_download(&conn, "abc", "downloading");
+
+        // An initializing torrent is not waiting in the download queue
+        write_status(&conn, &status("abc", DownloadStatus::Queued)).unwrap();
+
+        assert_eq!(
+            column(&conn, "status", "abc").as_deref(),
+            Some("downloading")
+        );
+        assert_eq!(column(&conn, "progress", "abc").as_deref(), Some("42.5"));
+        assert_eq!(
+            column(&conn, "downloaded_bytes", "abc").as_deref(),
+            Some("425")
+        );
+        assert_eq!(column(&conn, "size_bytes", "abc").as_deref(), Some("1000"));
+        assert_eq!(column(&conn, "peers", "abc").as_deref(), Some("7"));
+        assert_eq!(column(&conn, "ratio", "abc").as_deref(), Some("0.25"));
+    }
+
+    #[test]
+    fn test_write_status_keeps_post_processing_status() {
+        let conn = crate::db::init_db_memory().unwrap();
+        insert_download(&conn, "abc", "processing");
+
+        write_status(&conn, &status("abc", DownloadStatus::Seeding)).unwrap();
+        set_status(&conn, "abc", DownloadStatus::Paused).unwrap();
+        mark_failed(&conn, "abc", "boom").unwrap();
+
+        assert_eq!(
+            column(&conn, "status", "abc").as_deref(),
+            Some("processing")
+        );
+        assert_eq!(
+            column(&conn, "uploaded_bytes", "abc").as_deref(),
+            Some("100")
+        );
+        assert!(column(&conn, "error_message", "abc").is_none());
+    }
+
+    #[test]
+    fn test_mark_seeding_keeps_first_start() {
+        let conn = crate::db::init_db_memory().unwrap();
+        insert_download(&conn, "abc", "downloading");
+
+        let first = parse_timestamp("2024-01-02 03:04:05").unwrap();
+        mark_seeding(&conn, "abc", first).unwrap();
+        mark_seeding(&conn, "abc", Utc::now()).unwrap();
+
+        assert_eq!(column(&conn, "status", "abc").as_deref(), Some("seeding"));
+        assert_eq!(
+            column(&conn, "seeding_started_at", "abc").as_deref(),
+            Some("2024-01-02 03:04:05")
+        );
+    }
+
+    #[test]
+    fn test_mark_failed() {
+        let conn = crate::db::init_db_memory().unwrap();
+        insert_download(&conn, "abc", "downloading");
+
+        mark_failed(&conn, "abc", "tracker error").unwrap();
+
+        assert_eq!(column(&conn, "status", "abc").as_deref(), Some("failed"));
+        assert_eq!(
+            column(&conn, "error_message", "abc").as_deref(),
+            Some("tracker error")
+        );
+    }
+}
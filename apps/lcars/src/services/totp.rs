@@ -0,0 +1,168 @@
+//! Time-based one-time passwords (RFC 6238).
+//!
+//! Uses the parameters every authenticator app supports: HMAC-SHA1, six
+//! digits and a 30 second step.
+
+use data_encoding::BASE32_NOPAD;
+use hmac::{Hmac, Mac};
+use qrcode::{render::svg, QrCode};
+use rand::RngCore;
+use sha1::Sha1;
+
+/// Seconds each code is valid for.
+pub const STEP_SECS: u64 = 30;
+
+/// Digits in a code.
+const DIGITS: u32 = 6;
+
+/// Secret length in bytes, the HMAC-SHA1 block output size.
+const SECRET_LEN: usize = 20;
+
+/// Steps either side of the current one that are still accepted, for clock drift.
+const ALLOWED_DRIFT: u64 = 1;
+
+/// Generates a new random secret, base32 encoded as authenticator apps expect.
+pub fn generate_secret() -> String {
+    let mut secret = [0u8; SECRET_LEN];
+    rand::thread_rng().fill_bytes(&mut secret);
+    BASE32_NOPAD.encode(&secret)
+}
+
+/// Time step containing `unix_secs`.
+pub fn step_at(unix_secs: u64) -> u64 {
+    unix_secs / STEP_SECS
+}
+
+/// The code for a time step (RFC 4226 HOTP over the step counter).
+pub fn code_at_step(secret: &[u8], step: u64) -> u32 {
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC accepts any key length");
+    mac.update(&step.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    // Dynamic truncation
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    binary % 10u32.pow(DIGITS)
+}
+
+/// Checks a code against a base32 secret at `unix_secs`.
+///
+/// Returns the matched time step, which must be newer than `last_step` so a
+/// code cannot be used twice.
+pub fn verify(secret: &str, code: &str, unix_secs: u64, last_step: Option<u64>) -> Option<u64> {
+    let code = code.trim().replace(' ', "");
+    if code.len() != DIGITS as usize || !code.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let code: u32 = code.parse().ok()?;
+    let secret = BASE32_NOPAD.decode(secret.as_bytes()).ok()?;
+
+    let current = step_at(unix_secs);
+    (current.saturating_sub(ALLOWED_DRIFT)..=current + ALLOWED_DRIFT)
+        .filter(|step| last_step.is_none_or(|last| *step > last))
+        .find(|step| code_at_step(&secret, *step) == code)
+}
+
+/// `otpauth://` URI that authenticator apps import, usually from a QR code.
+pub fn otpauth_uri(issuer: &str, account: &str, secret: &str) -> String {
+    format!(
+        "otpauth://totp/{}:{}?secret={}&issuer={}&algorithm=SHA1&digits={}&period={}",
+        urlencoding::encode(issuer),
+        urlencoding::encode(account),
+        secret,
+        urlencoding::encode(issuer),
+        DIGITS,
+        STEP_SECS
+    )
+}
+
+/// Renders a URI as an SVG QR code.
+pub fn qr_svg(uri: &str) -> Option<String> {
+    let code = QrCode::new(uri.as_bytes()).ok()?;
+    Some(
+        code.render::<svg::Color>()
+            .min_dimensions(200, 200)
+            .quiet_zone(true)
+            .build(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// RFC 6238 appendix B secret for SHA1.
+    const RFC_SECRET: &[u8] = b"12345678901234567890";
+
+    #[test]
+    fn test_rfc6238_vectors() {
+        // Appendix B, truncated to six digits
+        for (time, expected) in [
+            (59, 287082),
+            (1111111109, 81804),
+            (1111111111, 50471),
+            (1234567890, 5924),
+            (2000000000, 279037),
+            (20000000000, 353130),
+        ] {
+            assert_eq!(
+                code_at_step(RFC_SECRET, step_at(time)),
+                expected,
+                "time {}",
+                time
+            );
+        }
+    }
+
+    #[test]
+    fn test_verify() {
+        let secret = BASE32_NOPAD.encode(RFC_SECRET);
+
+        assert_eq!(verify(&secret, "287082", 59, None), Some(1));
+        assert_eq!(verify(&secret, " 287 082 ", 59, None), Some(1));
+
+        // Previous and next steps are accepted for clock drift
+        assert_eq!(verify(&secret, "287082", 89, None), Some(1));
+        assert_eq!(verify(&secret, "287082", 29, None), Some(1));
+        assert_eq!(verify(&secret, "287082", 120, None), None);
+
+        assert_eq!(verify(&secret, "000000", 59, None), None);
+        assert_eq!(verify(&secret, "28708", 59, None), None);
+        assert_eq!(verify(&secret, "abcdef", 59, None), None);
+    }
+
+    #[test]
+    fn test_verify_rejects_reuse() {
+        let secret = BASE32_NOPAD.encode(RFC_SECRET);
+
+        assert_eq!(verify(&secret, "287082", 59, Some(0)), Some(1));
+        assert_eq!(verify(&secret, "287082", 59, Some(1)), None);
+    }
+
+    #[test]
+    fn test_generate_secret() {
+        let secret = generate_secret();
+        assert_eq!(
+            BASE32_NOPAD.decode(secret.as_bytes()).unwrap().len(),
+            SECRET_LEN
+        );
+        assert_ne!(secret, generate_secret());
+    }
+
+    #[test]
+    fn test_otpauth_uri() {
+        let uri = otpauth_uri("LCARS", "jean luc", "JBSWY3DPEHPK3PXP");
+        assert_eq!(
+            uri,
+            "otpauth://totp/LCARS:jean%20luc?secret=JBSWY3DPEHPK3PXP&issuer=LCARS&algorithm=SHA1&digits=6&period=30"
+        );
+
+        let svg = qr_svg(&uri).unwrap();
+        assert!(svg.contains("<svg"));
+    }
+}
@@ -0,0 +1,398 @@
+//! Two-factor authentication state of users.
+//!
+//! Enrolment stores a TOTP secret that only takes effect once the user proves
+//! their authenticator works by entering a code, at which point one-time
+//! recovery codes are issued. Admins can require 2FA for every admin account.
+
+use rand::Rng;
+use rusqlite::{Connection, OptionalExtension};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+
+use crate::error::{AppError, Result};
+use crate::services::totp;
+
+/// Setting key for requiring 2FA on admin accounts.
+pub const REQUIRE_ADMIN_SETTING: &str = "require_admin_two_factor";
+
+/// Recovery codes issued at a time.
+const RECOVERY_CODE_COUNT: usize = 10;
+
+/// Characters in a recovery code, split in two halves for reading.
+const RECOVERY_CODE_LEN: usize = 10;
+
+/// Alphabet of recovery codes, without look-alike characters.
+const RECOVERY_ALPHABET: &[u8] = b"abcdefghjkmnpqrstuvwxyz23456789";
+
+/// 2FA state of a user.
+#[derive(Debug, Clone, Serialize)]
+pub struct TwoFactorStatus {
+    pub enabled: bool,
+    /// Unused recovery codes left
+    pub recovery_codes_remaining: i64,
+    /// Whether the user's role requires 2FA
+    pub required: bool,
+}
+
+/// Current Unix time in seconds.
+pub fn unix_now() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Whether admin accounts must use 2FA.
+pub fn admin_two_factor_required(conn: &Connection) -> Result<bool> {
+    let value: Option<String> = conn
+        .query_row(
+            "SELECT value FROM settings WHERE key = ?1",
+            [REQUIRE_ADMIN_SETTING],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(value.as_deref() == Some("true"))
+}
+
+/// Sets whether admin accounts must use 2FA.
+pub fn set_admin_two_factor_required(conn: &Connection, required: bool) -> Result<()> {
+    conn.execute(
+        r#"
+        INSERT INTO settings (key, value) VALUES (?1, ?2)
+        ON CONFLICT(key) DO UPDATE SET value = excluded.value, updated_at = datetime('now')
+        "#,
+        rusqlite::params![REQUIRE_ADMIN_SETTING, required.to_string()],
+    )?;
+    Ok(())
+}
+
+/// Whether a user has 2FA turned on.
+pub fn is_enabled(conn: &Connection, user_id: i64) -> Result<bool> {
+    let enabled: Option<bool> = conn
+        .query_row(
+            "SELECT totp_enabled FROM users WHERE id = ?1",
+            [user_id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    enabled.ok_or_else(|| AppError::NotFound("User not found".to_string()))
+}
+
+/// Whether a user must enrol before doing anything else: their role requires
+/// 2FA and they have not turned it on.
+pub fn setup_required(conn: &Connection, user_id: i64, role: &str) -> Result<bool> {
+    if role != "admin" || !admin_two_factor_required(conn)? {
+        return Ok(false);
+    }
+    Ok(!is_enabled(conn, user_id)?)
+}
+
+/// 2FA state of a user.
+pub fn status(conn: &Connection, user_id: i64) -> Result<TwoFactorStatus> {
+    let (enabled, role): (bool, String) = conn
+        .query_row(
+            "SELECT totp_enabled, role FROM users WHERE id = ?1",
+            [user_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+
+    let recovery_codes_remaining: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM recovery_codes WHERE user_id = ?1 AND used_at IS NULL",
+        [user_id],
+        |row| row.get(0),
+    )?;
+
+    Ok(TwoFactorStatus {
+        enabled,
+        recovery_codes_remaining,
+        required: role == "admin" && admin_two_factor_required(conn)?,
+    })
+}
+
+/// Starts enrolment with a new secret, replacing any unconfirmed one.
+///
+/// Returns the base32 secret to show to the user.
+pub fn begin_enrolment(conn: &Connection, user_id: i64) -> Result<String> {
+    if is_enabled(conn, user_id)? {
+        return Err(AppError::Conflict(
+            "Two-factor authentication is already enabled".to_string(),
+        ));
+    }
+
+    let secret = totp::generate_secret();
+    conn.execute(
+        "UPDATE users SET totp_secret = ?1, totp_last_step = NULL WHERE id = ?2",
+        rusqlite::params![secret, user_id],
+    )?;
+    Ok(secret)
+}
+
+/// Turns 2FA on once the user entered a valid code for the pending secret.
+///
+/// Returns the recovery codes, which are only shown this once.
+pub fn confirm_enrolment(
+    conn: &Connection,
+    user_id: i64,
+    code: &str,
+    unix_secs: u64,
+) -> Result<Vec<String>> {
+    let (secret, enabled): (Option<String>, bool) = conn
+        .query_row(
+            "SELECT totp_secret, totp_enabled FROM users WHERE id = ?1",
+            [user_id],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .optional()?
+        .ok_or_else(|| AppError::NotFound("User not found".to_string()))?;
+
+    if enabled {
+        return Err(AppError::Conflict(
+            "Two-factor authentication is already enabled".to_string(),
+        ));
+    }
+    let secret = secret.ok_or_else(|| {
+        AppError::BadRequest("Start two-factor setup before confirming it".to_string())
+    })?;
+
+    let step = totp::verify(&secret, code, unix_secs, None)
+        .ok_or_else(|| AppError::BadRequest("Invalid verification code".to_string()))?;
+
+    conn.execute(
+        "UPDATE users SET totp_enabled = 1, totp_last_step = ?1 WHERE id = ?2",
+        rusqlite::params![step as i64, user_id],
+    )?;
+
+    regenerate_recovery_codes(conn, user_id)
+}
+
+/// Checks a second-factor code: a TOTP code or an unused recovery code.
+///
+/// Accepted codes are used up, so the same code never works twice.
+pub fn verify_code(conn: &Connection, user_id: i64, code: &str, unix_secs: u64) -> Result<bool> {
+    let row: Option<(Option<String>, bool, Option<i64>)> = conn
+        .query_row(
+            "SELECT totp_secret, totp_enabled, totp_last_step FROM users WHERE id = ?1",
+            [user_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+        )
+        .optional()?;
+    let Some((Some(secret), true, last_step)) = row else {
+        return Ok(false);
+    };
+
+    let normalized = normalize_code(code);
+    if normalized.len() == 6 && normalized.bytes().all(|b| b.is_ascii_digit()) {
+        let last_step = last_step.map(|step| step as u64);
+        return match totp::verify(&secret, &normalized, unix_secs, last_step) {
+            Some(step) => {
+                conn.execute(
+                    "UPDATE users SET totp_last_step = ?1 WHERE id = ?2",
+                    rusqlite::params![step as i64, user_id],
+                )?;
+                Ok(true)
+            }
+            None => Ok(false),
+        };
+    }
+
+    let used = conn.execute(
+        r#"
+        UPDATE recovery_codes SET used_at = datetime('now')
+        WHERE id = (
+            SELECT id FROM recovery_codes
+            WHERE user_id = ?1 AND code_hash = ?2 AND used_at IS NULL
+            LIMIT 1
+        )
+        "#,
+        rusqlite::params![user_id, hash_recovery_code(&normalized)],
+    )?;
+    if used > 0 {
+        tracing::info!(user_id = user_id, "Recovery code used");
+    }
+    Ok(used > 0)
+}
+
+/// Turns 2FA off and forgets the secret and recovery codes.
+pub fn disable(conn: &Connection, user_id: i64) -> Result<()> {
+    conn.execute(
+        "UPDATE users SET totp_secret = NULL, totp_enabled = 0, totp_last_step = NULL WHERE id = ?1",
+        [user_id],
+    )?;
+    conn.execute("DELETE FROM recovery_codes WHERE user_id = ?1", [user_id])?;
+    Ok(())
+}
+
+/// Replaces a user's recovery codes with new ones and returns them.
+pub fn regenerate_recovery_codes(conn: &Connection, user_id: i64) -> Result<Vec<String>> {
+    conn.execute("DELETE FROM recovery_codes WHERE user_id = ?1", [user_id])?;
+
+    let mut rng = rand::thread_rng();
+    let codes: Vec<String> = (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let chars: String = (0..RECOVERY_CODE_LEN)
+                .map(|_| RECOVERY_ALPHABET[rng.gen_range(0..RECOVERY_ALPHABET.len())] as char)
+                .collect();
+            let (first, second) = chars.split_at(RECOVERY_CODE_LEN / 2);
+            format!("{}-{}", first, second)
+        })
+        .collect();
+
+    for code in &codes {
+        conn.execute(
+            "INSERT INTO recovery_codes (user_id, code_hash) VALUES (?1, ?2)",
+            rusqlite::params![user_id, hash_recovery_code(&normalize_code(code))],
+        )?;
+    }
+
+    Ok(codes)
+}
+
+/// Lowercases a code and drops separators, so codes can be typed loosely.
+fn normalize_code(code: &str) -> String {
+    code.chars()
+        .filter(|c| !c.is_whitespace() && *c != '-')
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+fn hash_recovery_code(normalized: &str) -> String {
+    hex::encode(Sha256::digest(normalized.as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use data_encoding::BASE32_NOPAD;
+
+    fn test_db(role: &str) -> (Connection, i64) {
+        let conn = crate::db::init_db_memory().unwrap();
+        conn.execute(
+            "INSERT INTO users (username, password_hash, role) VALUES ('alice', 'x', ?1)",
+            [role],
+        )
+        .unwrap();
+        let user_id = conn.last_insert_rowid();
+        (conn, user_id)
+    }
+
+    fn code_for(secret: &str, unix_secs: u64) -> String {
+        let secret = BASE32_NOPAD.decode(secret.as_bytes()).unwrap();
+        format!(
+            "{:06}",
+            totp::code_at_step(&secret, totp::step_at(unix_secs))
+        )
+    }
+
+    #[test]
+    fn test_enrolment() {
+        let (conn, user_id) = test_db("user");
+        let now = 1_700_000_000;
+
+        let secret = begin_enrolment(&conn, user_id).unwrap();
+        // Not active until confirmed
+        assert!(!is_enabled(&conn, user_id).unwrap());
+        assert!(!verify_code(&conn, user_id, &code_for(&secret, now), now).unwrap());
+
+        assert!(matches!(
+            confirm_enrolment(&conn, user_id, "000000", now),
+            Err(AppError::BadRequest(_))
+        ));
+
+        let codes = confirm_enrolment(&conn, user_id, &code_for(&secret, now), now).unwrap();
+        assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+        assert!(is_enabled(&conn, user_id).unwrap());
+
+        let status = status(&conn, user_id).unwrap();
+        assert!(status.enabled);
+        assert_eq!(status.recovery_codes_remaining, RECOVERY_CODE_COUNT as i64);
+
+        // Cannot enrol twice
+        assert!(matches!(
+            begin_enrolment(&conn, user_id),
+            Err(AppError::Conflict(_))
+        ));
+    }
+
+    #[test]
+    fn test_totp_codes_are_single_use() {
+        let (conn, user_id) = test_db("user");
+        let now = 1_700_000_000;
+
+        let secret = begin_enrolment(&conn, user_id).unwrap();
+        confirm_enrolment(&conn, user_id, &code_for(&secret, now), now).unwrap();
+
+        // The code used to confirm cannot log in
+        assert!(!verify_code(&conn, user_id, &code_for(&secret, now), now).unwrap());
+
+        let later = now + totp::STEP_SECS;
+        assert!(verify_code(&conn, user_id, &code_for(&secret, later), later).unwrap());
+        assert!(!verify_code(&conn, user_id, &code_for(&secret, later), later).unwrap());
+    }
+
+    #[test]
+    fn test_recovery_codes() {
+        let (conn, user_id) = test_db("user");
+        let now = 1_700_000_000;
+
+        let secret = begin_enrolment(&conn, user_id).unwrap();
+        let codes = confirm_enrolment(&conn, user_id, &code_for(&secret, now), now).unwrap();
+
+        // Typed loosely, once only
+        let typed = codes[0].to_uppercase().replace('-', " ");
+        assert!(verify_code(&conn, user_id, &typed, now).unwrap());
+        assert!(!verify_code(&conn, user_id, &codes[0], now).unwrap());
+        assert_eq!(status(&conn, user_id).unwrap().recovery_codes_remaining, 9);
+
+        assert!(!verify_code(&conn, user_id, "aaaaa-aaaaa", now).unwrap());
+
+        // Regenerating invalidates the old codes
+        let new_codes = regenerate_recovery_codes(&conn, user_id).unwrap();
+        assert!(!verify_code(&conn, user_id, &codes[1], now).unwrap());
+        assert!(verify_code(&conn, user_id, &new_codes[1], now).unwrap());
+    }
+
+    #[test]
+    fn test_disable() {
+        let (conn, user_id) = test_db("user");
+        let now = 1_700_000_000;
+
+        let secret = begin_enrolment(&conn, user_id).unwrap();
+        let codes = confirm_enrolment(&conn, user_id, &code_for(&secret, now), now).unwrap();
+
+        disable(&conn, user_id).unwrap();
+        assert!(!is_enabled(&conn, user_id).unwrap());
+        assert!(!verify_code(&conn, user_id, &codes[0], now).unwrap());
+        assert_eq!(status(&conn, user_id).unwrap().recovery_codes_remaining, 0);
+    }
+
+    #[test]
+    fn test_admin_requirement() {
+        let (conn, admin_id) = test_db("admin");
+        conn.execute(
+            "INSERT INTO users (username, password_hash, role) VALUES ('bob', 'x', 'user')",
+            [],
+        )
+        .unwrap();
+        let user_id = conn.last_insert_rowid();
+
+        assert!(!admin_two_factor_required(&conn).unwrap());
+        assert!(!setup_required(&conn, admin_id, "admin").unwrap());
+
+        set_admin_two_factor_required(&conn, true).unwrap();
+        assert!(admin_two_factor_required(&conn).unwrap());
+        assert!(setup_required(&conn, admin_id, "admin").unwrap());
+        assert!(status(&conn, admin_id).unwrap().required);
+        assert!(!setup_required(&conn, user_id, "user").unwrap());
+        assert!(!status(&conn, user_id).unwrap().required);
+
+        let now = 1_700_000_000;
+        let secret = begin_enrolment(&conn, admin_id).unwrap();
+        confirm_enrolment(&conn, admin_id, &code_for(&secret, now), now).unwrap();
+        assert!(!setup_required(&conn, admin_id, "admin").unwrap());
+
+        set_admin_two_factor_required(&conn, false).unwrap();
+        assert!(!admin_two_factor_required(&conn).unwrap());
+    }
+}
@@ -1,22 +1,40 @@
 //! Authentication views
 
+use std::net::SocketAddr;
+
 use askama::Template;
 use axum::{
-    extract::State,
-    http::{header, HeaderMap, StatusCode},
-    response::{IntoResponse, Redirect},
+    body::Body,
+    extract::{ConnectInfo, State},
+    http::{header, HeaderMap, Request, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Redirect, Response},
     Form,
 };
 use axum_extra::extract::cookie::{Cookie, CookieJar};
 use serde::Deserialize;
 
-use crate::services::auth::Claims;
+use crate::error::AppError;
+use crate::middleware::client_ip;
+use crate::services::auth::{self, Claims};
+use crate::services::two_factor;
 use crate::AppState;
 
 #[derive(Template)]
 #[template(path = "login.html")]
 pub struct LoginTemplate {
     pub error: Option<String>,
+    /// Challenge token while waiting for the two-factor code
+    pub challenge: Option<String>,
+}
+
+impl LoginTemplate {
+    fn error(message: impl Into<String>) -> Self {
+        Self {
+            error: Some(message.into()),
+            challenge: None,
+        }
+    }
 }
 
 #[derive(Deserialize)]
@@ -25,20 +43,39 @@ pub struct LoginForm {
     pub password: String,
 }
 
+#[derive(Deserialize)]
+pub struct TwoFactorLoginForm {
+    pub challenge: String,
+    pub code: String,
+}
+
 /// Render the login page
 pub async fn login_page() -> impl IntoResponse {
-    LoginTemplate { error: None }
+    LoginTemplate {
+        error: None,
+        challenge: None,
+    }
 }
 
 /// Handle login form submission
 pub async fn login_submit(
     State(state): State<AppState>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
     headers: HeaderMap,
     cookies: CookieJar,
     Form(form): Form<LoginForm>,
 ) -> impl IntoResponse {
-    // Check if this is an HTMX request
-    let is_htmx = headers.contains_key("hx-request");
+    // Refuse attempts while failures are being throttled
+    let ip = client_ip(
+        connect_info.map(|ConnectInfo(addr)| addr.ip()),
+        &headers,
+        &state.config.server.trusted_proxies,
+    );
+    let throttle = state.login_throttle();
+    let attempt = match throttle.begin_attempt(ip, &form.username) {
+        Ok(attempt) => attempt,
+        Err(e) => return throttled(e).into_response(),
+    };
 
     // Query user from database
     let db = state.db.lock().await;
@@ -49,68 +86,172 @@ pub async fn login_submit(
     );
     drop(db);
 
-    match user_result {
-        Ok((user_id, password_hash, role)) => {
-            // Verify password
-            match state
+    let verified = user_result.ok().filter(|(_, password_hash, _)| {
+        matches!(
+            state
                 .auth_service()
-                .verify_password(&form.password, &password_hash)
-            {
-                Ok(true) => {
-                    // Create JWT token
-                    match state.auth_service().create_token(user_id, &role) {
-                        Ok(token) => {
-                            // Set session cookie (7 days)
-                            let cookie = Cookie::build(("session", token))
-                                .path("/")
-                                .http_only(true)
-                                .secure(state.config.server.secure_cookies)
-                                .same_site(axum_extra::extract::cookie::SameSite::Lax)
-                                .max_age(::time::Duration::days(7))
-                                .build();
-
-                            if is_htmx {
-                                // HTMX request: return HX-Redirect header
-                                (
-                                    cookies.add(cookie),
-                                    [(header::HeaderName::from_static("hx-redirect"), "/")],
-                                    StatusCode::OK,
-                                )
-                                    .into_response()
-                            } else {
-                                // Standard form submission: HTTP redirect
-                                (cookies.add(cookie), Redirect::to("/")).into_response()
-                            }
-                        }
-                        Err(_) => LoginTemplate {
-                            error: Some("Authentication failed".to_string()),
-                        }
-                        .into_response(),
-                    }
-                }
-                _ => {
-                    // Invalid password
-                    LoginTemplate {
-                        error: Some("Invalid username or password".to_string()),
-                    }
-                    .into_response()
-                }
+                .verify_password(&form.password, password_hash),
+            Ok(true)
+        )
+    });
+
+    let Some((user_id, _, role)) = verified else {
+        // Unknown user or invalid password
+        let db = state.db.lock().await;
+        throttle.login_failed(&db, ip, &form.username, attempt);
+        return LoginTemplate::error("Invalid username or password").into_response();
+    };
+
+    // Ask for the second factor before starting a session
+    let db = state.db.lock().await;
+    let two_factor = two_factor::is_enabled(&db, user_id);
+    drop(db);
+    match two_factor {
+        Ok(true) => match state.auth_service().create_challenge(user_id) {
+            Ok(challenge) => LoginTemplate {
+                error: None,
+                challenge: Some(challenge),
             }
+            .into_response(),
+            Err(_) => LoginTemplate::error("Authentication failed").into_response(),
+        },
+        Ok(false) => {
+            throttle.record_success(ip, &form.username);
+            start_session(&state, &headers, cookies, user_id, &role).await
         }
-        Err(_) => {
-            // User not found
-            LoginTemplate {
-                error: Some("Invalid username or password".to_string()),
-            }
-            .into_response()
+        Err(_) => LoginTemplate::error("Authentication failed").into_response(),
+    }
+}
+
+/// Handle the two-factor code form of a login
+pub async fn login_two_factor_submit(
+    State(state): State<AppState>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+    cookies: CookieJar,
+    Form(form): Form<TwoFactorLoginForm>,
+) -> impl IntoResponse {
+    let Ok(user_id) = state.auth_service().verify_challenge(&form.challenge) else {
+        return LoginTemplate::error("Login expired, please sign in again").into_response();
+    };
+
+    let db = state.db.lock().await;
+    let user: Result<(String, String), rusqlite::Error> = db.query_row(
+        "SELECT username, role FROM users WHERE id = ?1",
+        [user_id],
+        |row| Ok((row.get(0)?, row.get(1)?)),
+    );
+    drop(db);
+    let Ok((username, role)) = user else {
+        return LoginTemplate::error("Login expired, please sign in again").into_response();
+    };
+
+    let ip = client_ip(
+        connect_info.map(|ConnectInfo(addr)| addr.ip()),
+        &headers,
+        &state.config.server.trusted_proxies,
+    );
+    let throttle = state.login_throttle();
+    let attempt = match throttle.begin_attempt(ip, &username) {
+        Ok(attempt) => attempt,
+        Err(e) => return throttled(e).into_response(),
+    };
+
+    let db = state.db.lock().await;
+    let verified = two_factor::verify_code(&db, user_id, &form.code, two_factor::unix_now());
+    if !matches!(verified, Ok(true)) {
+        throttle.login_failed(&db, ip, &username, attempt);
+        return LoginTemplate {
+            error: Some("Invalid verification code".to_string()),
+            challenge: Some(form.challenge),
         }
+        .into_response();
+    }
+    drop(db);
+
+    throttle.record_success(ip, &username);
+    start_session(&state, &headers, cookies, user_id, &role).await
+}
+
+/// Login page for an attempt refused by the login throttle.
+fn throttled(error: AppError) -> LoginTemplate {
+    match error {
+        AppError::RateLimited(secs) => LoginTemplate::error(format!(
+            "Too many failed login attempts. Try again in {} seconds.",
+            secs
+        )),
+        _ => LoginTemplate::error("Authentication failed"),
+    }
+}
+
+/// Creates a session for a logged in user, sets its cookie and redirects.
+///
+/// Users who must enrol in two-factor authentication are sent to settings.
+async fn start_session(
+    state: &AppState,
+    headers: &HeaderMap,
+    cookies: CookieJar,
+    user_id: i64,
+    role: &str,
+) -> axum::response::Response {
+    // Check if this is an HTMX request
+    let is_htmx = headers.contains_key("hx-request");
+
+    // Create JWT token and its session
+    let user_agent = headers
+        .get(header::USER_AGENT)
+        .and_then(|v| v.to_str().ok());
+    let db = state.db.lock().await;
+    let token = state
+        .auth_service()
+        .create_token(&db, user_id, role, user_agent);
+    let setup_required = two_factor::setup_required(&db, user_id, role).unwrap_or(false);
+    drop(db);
+
+    let Ok(token) = token else {
+        return LoginTemplate::error("Authentication failed").into_response();
+    };
+
+    // Set session cookie (7 days)
+    let cookie = Cookie::build(("session", token))
+        .path("/")
+        .http_only(true)
+        .secure(state.config.server.secure_cookies)
+        .same_site(axum_extra::extract::cookie::SameSite::Lax)
+        .max_age(::time::Duration::days(7))
+        .build();
+
+    let target = if setup_required { "/settings" } else { "/" };
+    if is_htmx {
+        // HTMX request: return HX-Redirect header
+        (
+            cookies.add(cookie),
+            [(header::HeaderName::from_static("hx-redirect"), target)],
+            StatusCode::OK,
+        )
+            .into_response()
+    } else {
+        // Standard form submission: HTTP redirect
+        (cookies.add(cookie), Redirect::to(target)).into_response()
     }
 }
 
 /// Handle logout
-pub async fn logout(headers: HeaderMap, cookies: CookieJar) -> impl IntoResponse {
+pub async fn logout(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    cookies: CookieJar,
+) -> impl IntoResponse {
     let is_htmx = headers.contains_key("hx-request");
 
+    // End the session so the token stops working even if it was copied
+    if let Some(claims) = get_current_user(&state, &cookies).await {
+        let db = state.db.lock().await;
+        if let Err(e) = auth::revoke_session(&db, &claims.jti) {
+            tracing::warn!(user_id = claims.sub, error = %e, "Failed to revoke session");
+        }
+    }
+
     let cookie = Cookie::build(("session", ""))
         .path("/")
         .max_age(::time::Duration::ZERO)
@@ -128,8 +269,59 @@ pub async fn logout(headers: HeaderMap, cookies: CookieJar) -> impl IntoResponse
     }
 }
 
-/// Extract session token from cookies and validate
+/// Extract session token from cookies and validate it and its session
 pub async fn get_current_user(state: &AppState, cookies: &CookieJar) -> Option<Claims> {
     let session = cookies.get("session")?;
-    state.auth_service().verify_token(session.value()).ok()
+    let db = state.db.lock().await;
+    state
+        .auth_service()
+        .verify_session(&db, session.value())
+        .ok()
+}
+
+/// Pages an admin who still has to set up 2FA can reach: logging in and out,
+/// and the settings page with the enrolment handlers.
+const TWO_FACTOR_SETUP_PATHS: &[&str] = &[
+    "/login",
+    "/login/2fa",
+    "/logout",
+    "/settings",
+    "/settings/2fa/setup",
+    "/settings/2fa/enable",
+];
+
+/// Middleware sending admins who must use 2FA but have not enrolled to the
+/// settings page, as `auth_middleware` does for the API.
+pub async fn require_two_factor_setup(
+    State(state): State<AppState>,
+    cookies: CookieJar,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    if TWO_FACTOR_SETUP_PATHS.contains(&request.uri().path()) {
+        return next.run(request).await;
+    }
+
+    let Some(claims) = get_current_user(&state, &cookies).await else {
+        return next.run(request).await;
+    };
+
+    let db = state.db.lock().await;
+    let setup_required = two_factor::setup_required(&db, claims.sub, &claims.role).unwrap_or(false);
+    drop(db);
+
+    if !setup_required {
+        return next.run(request).await;
+    }
+
+    tracing::debug!(user_id = claims.sub, "Admin has not set up required 2FA");
+    if request.headers().contains_key("hx-request") {
+        (
+            [(header::HeaderName::from_static("hx-redirect"), "/settings")],
+            StatusCode::OK,
+        )
+            .into_response()
+    } else {
+        Redirect::to("/settings").into_response()
+    }
 }
@@ -316,6 +316,7 @@ pub async fn cancel(
         Path(id),
         axum::extract::Query(DeleteDownloadQuery {
             delete_files: Some(false),
+            blocklist: None,
         }),
     )
     .await;
@@ -42,10 +42,14 @@ pub async fn not_found(uri: Uri) -> impl IntoResponse {
 }
 
 /// Build the HTML routes for the frontend
-pub fn routes() -> Router<AppState> {
+pub fn routes(state: AppState) -> Router<AppState> {
     Router::new()
         // Public routes
         .route("/login", get(auth::login_page).post(auth::login_submit))
+        .route(
+            "/login/2fa",
+            axum::routing::post(auth::login_two_factor_submit),
+        )
         // Protected routes (auth checked in handlers for now)
         .route("/", get(dashboard::page))
         .route("/logout", axum::routing::post(auth::logout))
@@ -81,6 +85,34 @@ pub fn routes() -> Router<AppState> {
         )
         .route("/downloads/:id", axum::routing::delete(downloads::cancel))
         .route("/settings", get(settings::page))
+        .route(
+            "/settings/api-keys",
+            axum::routing::post(settings::create_api_key_submit),
+        )
+        .route(
+            "/settings/api-keys/:id",
+            axum::routing::delete(settings::delete_api_key_submit),
+        )
+        .route(
+            "/settings/2fa/setup",
+            axum::routing::post(settings::two_factor_setup_submit),
+        )
+        .route(
+            "/settings/2fa/enable",
+            axum::routing::post(settings::two_factor_enable_submit),
+        )
+        .route(
+            "/settings/2fa/disable",
+            axum::routing::post(settings::two_factor_disable_submit),
+        )
+        .route(
+            "/settings/2fa/recovery-codes",
+            axum::routing::post(settings::regenerate_recovery_codes_submit),
+        )
+        .route(
+            "/settings/2fa/require",
+            axum::routing::post(settings::require_two_factor_submit),
+        )
         // VPN routes
         .route("/vpn/status", get(settings::vpn_status_partial))
         .route("/vpn/connect", axum::routing::post(settings::vpn_connect))
@@ -98,4 +130,8 @@ pub fn routes() -> Router<AppState> {
         // SSE endpoints
         .route("/sse/downloads", get(sse::downloads_stream))
         .route("/sse/status", get(sse::status_stream))
+        .route_layer(axum::middleware::from_fn_with_state(
+            state,
+            auth::require_two_factor_setup,
+        ))
 }
@@ -363,6 +363,7 @@ pub async fn add_movie(
             tmdb_id: form.tmdb_id,
             monitored: Some(true),
             quality_limit: None,
+            quality_profile_id: None,
         }),
     )
     .await;
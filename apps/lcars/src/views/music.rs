@@ -501,6 +501,7 @@ pub async fn add_artist(
             mbid: form.mbid,
             monitored: Some(true),
             quality_limit: None,
+            quality_profile_id: None,
         }),
     )
     .await;
@@ -2,11 +2,19 @@
 
 use askama::Template;
 use axum::{
-    extract::State,
+    extract::{Path, State},
     response::{Html, IntoResponse, Redirect},
 };
-use axum_extra::extract::CookieJar;
-
+use axum_extra::extract::{CookieJar, Form};
+use serde::Deserialize;
+
+use crate::db::models::{ApiKey, ApiKeyScope};
+use crate::error::AppError;
+use crate::services::activity::{ActivityBuilder, EventType};
+use crate::services::api_keys::{create_api_key, delete_api_key, list_api_keys};
+use crate::services::auth::Claims;
+use crate::services::totp;
+use crate::services::two_factor::{self, TwoFactorStatus};
 use crate::services::wireguard::ConnectionStatus;
 use crate::AppState;
 
@@ -24,6 +32,29 @@ pub struct SettingsTemplate {
     pub soulseek_status: ServiceStatus,
     pub storage_mounts: Vec<StorageMount>,
     pub indexers: Vec<IndexerInfo>,
+    pub api_keys: Vec<ApiKey>,
+    pub new_key: Option<String>,
+    pub api_key_error: Option<String>,
+    pub two_factor: TwoFactorView,
+}
+
+/// Two-factor authentication section of the settings page
+pub struct TwoFactorView {
+    pub status: TwoFactorStatus,
+    pub is_admin: bool,
+    /// Whether 2FA is required for admins, shown to admins
+    pub require_admin: bool,
+    /// Pending enrolment waiting for a code
+    pub setup: Option<TwoFactorSetupView>,
+    /// Recovery codes just issued, shown once
+    pub recovery_codes: Vec<String>,
+    pub error: Option<String>,
+}
+
+/// Secret being enrolled, as text and QR code
+pub struct TwoFactorSetupView {
+    pub secret: String,
+    pub qr_svg: Option<String>,
 }
 
 /// VPN status view model for templates
@@ -69,9 +100,9 @@ pub struct IndexerInfo {
 
 /// Settings page
 pub async fn page(State(state): State<AppState>, cookies: CookieJar) -> impl IntoResponse {
-    if auth::get_current_user(&state, &cookies).await.is_none() {
+    let Some(user) = auth::get_current_user(&state, &cookies).await else {
         return Redirect::to("/login").into_response();
-    }
+    };
 
     let uptime = state.start_time().elapsed();
     let uptime_str = format_duration(uptime.as_secs());
@@ -98,6 +129,9 @@ pub async fn page(State(state): State<AppState>, cookies: CookieJar) -> impl Int
         .collect::<Result<Vec<_>, _>>()
         .unwrap_or_default()
     };
+
+    let api_keys = list_api_keys(&db, user.sub).unwrap_or_default();
+    let two_factor = two_factor_view(&db, &user, None);
     drop(db);
 
     // Check VPN status from WireGuard service
@@ -180,6 +214,349 @@ pub async fn page(State(state): State<AppState>, cookies: CookieJar) -> impl Int
         soulseek_status,
         storage_mounts,
         indexers,
+        api_keys,
+        new_key: None,
+        api_key_error: None,
+        two_factor,
+    }
+    .into_response()
+}
+
+/// API keys section of the settings page, for HTMX updates
+#[derive(Template)]
+#[template(path = "partials/api_keys.html")]
+pub struct ApiKeysPartial {
+    pub api_keys: Vec<ApiKey>,
+    /// Key just created, shown once
+    pub new_key: Option<String>,
+    pub api_key_error: Option<String>,
+}
+
+/// Form data for creating an API key
+#[derive(Debug, Deserialize)]
+pub struct CreateApiKeyForm {
+    pub name: String,
+    pub scope: ApiKeyScope,
+}
+
+/// POST /settings/api-keys - Create an API key for the current user
+pub async fn create_api_key_submit(
+    State(state): State<AppState>,
+    cookies: CookieJar,
+    Form(form): Form<CreateApiKeyForm>,
+) -> impl IntoResponse {
+    let Some(user) = auth::get_current_user(&state, &cookies).await else {
+        return Html("<div class='lcars-error'>Unauthorized</div>").into_response();
+    };
+
+    let db = state.db.lock().await;
+    let (new_key, api_key_error) = match create_api_key(&db, user.sub, &form.name, form.scope) {
+        Ok((key, secret)) => {
+            ActivityBuilder::new(
+                EventType::ApiKeyCreated,
+                format!("API key '{}' created", key.name),
+            )
+            .user(user.sub)
+            .metadata(&serde_json::json!({
+                "api_key_id": key.id,
+                "api_key": key.name,
+                "scope": key.scope,
+                "created_by": user.sub,
+            }))
+            .log_sync(&db);
+            (Some(secret), None)
+        }
+        Err(AppError::BadRequest(msg) | AppError::Conflict(msg)) => (None, Some(msg)),
+        Err(e) => {
+            tracing::error!(user_id = user.sub, error = %e, "Failed to create API key");
+            (None, Some("Failed to create API key".to_string()))
+        }
+    };
+
+    ApiKeysPartial {
+        api_keys: list_api_keys(&db, user.sub).unwrap_or_default(),
+        new_key,
+        api_key_error,
+    }
+    .into_response()
+}
+
+/// DELETE /settings/api-keys/:id - Delete one of the current user's API keys
+pub async fn delete_api_key_submit(
+    State(state): State<AppState>,
+    cookies: CookieJar,
+    Path(key_id): Path<i64>,
+) -> impl IntoResponse {
+    let Some(user) = auth::get_current_user(&state, &cookies).await else {
+        return Html("<div class='lcars-error'>Unauthorized</div>").into_response();
+    };
+
+    let db = state.db.lock().await;
+    let api_keys = list_api_keys(&db, user.sub).unwrap_or_default();
+    let api_key_error = match api_keys.iter().find(|k| k.id == key_id) {
+        Some(key) => match delete_api_key(&db, user.sub, key_id) {
+            Ok(_) => {
+                ActivityBuilder::new(
+                    EventType::ApiKeyDeleted,
+                    format!("API key '{}' deleted", key.name),
+                )
+                .user(user.sub)
+                .metadata(&serde_json::json!({
+                    "api_key_id": key.id,
+                    "api_key": key.name,
+                    "deleted_by": user.sub,
+                }))
+                .log_sync(&db);
+                None
+            }
+            Err(e) => {
+                tracing::error!(user_id = user.sub, error = %e, "Failed to delete API key");
+                Some("Failed to delete API key".to_string())
+            }
+        },
+        None => Some("API key not found".to_string()),
+    };
+
+    ApiKeysPartial {
+        api_keys: list_api_keys(&db, user.sub).unwrap_or_default(),
+        new_key: None,
+        api_key_error,
+    }
+    .into_response()
+}
+
+/// Two-factor section of the settings page, for HTMX updates
+#[derive(Template)]
+#[template(path = "partials/two_factor.html")]
+pub struct TwoFactorPartial {
+    pub two_factor: TwoFactorView,
+}
+
+/// Form data carrying a TOTP or recovery code
+#[derive(Debug, Deserialize)]
+pub struct TwoFactorCodeForm {
+    pub code: String,
+}
+
+/// Form data for the admin 2FA requirement; the checkbox is absent when off
+#[derive(Debug, Deserialize)]
+pub struct RequireTwoFactorForm {
+    pub required: Option<String>,
+}
+
+/// Loads the two-factor section for a user.
+///
+/// A pending enrolment is shown with its QR code so a failed confirmation
+/// can be retried.
+fn two_factor_view(
+    conn: &rusqlite::Connection,
+    user: &Claims,
+    error: Option<String>,
+) -> TwoFactorView {
+    let status = two_factor::status(conn, user.sub).unwrap_or(TwoFactorStatus {
+        enabled: false,
+        recovery_codes_remaining: 0,
+        required: false,
+    });
+
+    let setup = if status.enabled {
+        None
+    } else {
+        conn.query_row(
+            "SELECT username, totp_secret FROM users WHERE id = ?1 AND totp_secret IS NOT NULL",
+            [user.sub],
+            |row| Ok((row.get::<_, String>(0)?, row.get::<_, String>(1)?)),
+        )
+        .ok()
+        .map(|(username, secret)| TwoFactorSetupView {
+            qr_svg: totp::qr_svg(&totp::otpauth_uri("LCARS", &username, &secret)),
+            secret,
+        })
+    };
+
+    TwoFactorView {
+        status,
+        is_admin: user.role == "admin",
+        require_admin: two_factor::admin_two_factor_required(conn).unwrap_or(false),
+        setup,
+        recovery_codes: Vec::new(),
+        error,
+    }
+}
+
+/// Message to show for a failed 2FA action.
+fn two_factor_error(user_id: i64, e: AppError) -> String {
+    match e {
+        AppError::BadRequest(msg) | AppError::Conflict(msg) => msg,
+        e => {
+            tracing::error!(user_id = user_id, error = %e, "Two-factor update failed");
+            "Failed to update two-factor authentication".to_string()
+        }
+    }
+}
+
+/// POST /settings/2fa/setup - Start enrolment with a new secret
+pub async fn two_factor_setup_submit(
+    State(state): State<AppState>,
+    cookies: CookieJar,
+) -> impl IntoResponse {
+    let Some(user) = auth::get_current_user(&state, &cookies).await else {
+        return Html("<div class='lcars-error'>Unauthorized</div>").into_response();
+    };
+
+    let db = state.db.lock().await;
+    let error = two_factor::begin_enrolment(&db, user.sub)
+        .err()
+        .map(|e| two_factor_error(user.sub, e));
+
+    TwoFactorPartial {
+        two_factor: two_factor_view(&db, &user, error),
+    }
+    .into_response()
+}
+
+/// POST /settings/2fa/enable - Confirm enrolment with a code
+pub async fn two_factor_enable_submit(
+    State(state): State<AppState>,
+    cookies: CookieJar,
+    Form(form): Form<TwoFactorCodeForm>,
+) -> impl IntoResponse {
+    let Some(user) = auth::get_current_user(&state, &cookies).await else {
+        return Html("<div class='lcars-error'>Unauthorized</div>").into_response();
+    };
+
+    let db = state.db.lock().await;
+    match two_factor::confirm_enrolment(&db, user.sub, &form.code, two_factor::unix_now()) {
+        Ok(recovery_codes) => {
+            ActivityBuilder::new(
+                EventType::TwoFactorEnabled,
+                "Two-factor authentication enabled",
+            )
+            .user(user.sub)
+            .log_sync(&db);
+
+            let mut view = two_factor_view(&db, &user, None);
+            view.recovery_codes = recovery_codes;
+            TwoFactorPartial { two_factor: view }.into_response()
+        }
+        Err(e) => TwoFactorPartial {
+            two_factor: two_factor_view(&db, &user, Some(two_factor_error(user.sub, e))),
+        }
+        .into_response(),
+    }
+}
+
+/// POST /settings/2fa/disable - Turn 2FA off after checking a code
+pub async fn two_factor_disable_submit(
+    State(state): State<AppState>,
+    cookies: CookieJar,
+    Form(form): Form<TwoFactorCodeForm>,
+) -> impl IntoResponse {
+    let Some(user) = auth::get_current_user(&state, &cookies).await else {
+        return Html("<div class='lcars-error'>Unauthorized</div>").into_response();
+    };
+
+    let db = state.db.lock().await;
+    let result = two_factor::status(&db, user.sub).and_then(|status| {
+        if status.required {
+            return Err(AppError::BadRequest(
+                "Two-factor authentication is required for admins".to_string(),
+            ));
+        }
+        if !two_factor::verify_code(&db, user.sub, &form.code, two_factor::unix_now())? {
+            return Err(AppError::BadRequest(
+                "Invalid verification code".to_string(),
+            ));
+        }
+        two_factor::disable(&db, user.sub)
+    });
+
+    let error = match result {
+        Ok(()) => {
+            ActivityBuilder::new(
+                EventType::TwoFactorDisabled,
+                "Two-factor authentication disabled",
+            )
+            .user(user.sub)
+            .log_sync(&db);
+            None
+        }
+        Err(e) => Some(two_factor_error(user.sub, e)),
+    };
+
+    TwoFactorPartial {
+        two_factor: two_factor_view(&db, &user, error),
+    }
+    .into_response()
+}
+
+/// POST /settings/2fa/recovery-codes - Replace the recovery codes after checking a code
+pub async fn regenerate_recovery_codes_submit(
+    State(state): State<AppState>,
+    cookies: CookieJar,
+    Form(form): Form<TwoFactorCodeForm>,
+) -> impl IntoResponse {
+    let Some(user) = auth::get_current_user(&state, &cookies).await else {
+        return Html("<div class='lcars-error'>Unauthorized</div>").into_response();
+    };
+
+    let db = state.db.lock().await;
+    let result = match two_factor::verify_code(&db, user.sub, &form.code, two_factor::unix_now()) {
+        Ok(true) => two_factor::regenerate_recovery_codes(&db, user.sub),
+        Ok(false) => Err(AppError::BadRequest(
+            "Invalid verification code".to_string(),
+        )),
+        Err(e) => Err(e),
+    };
+
+    let view = match result {
+        Ok(recovery_codes) => {
+            let mut view = two_factor_view(&db, &user, None);
+            view.recovery_codes = recovery_codes;
+            view
+        }
+        Err(e) => two_factor_view(&db, &user, Some(two_factor_error(user.sub, e))),
+    };
+
+    TwoFactorPartial { two_factor: view }.into_response()
+}
+
+/// POST /settings/2fa/require - Set whether admins must use 2FA (admin only)
+pub async fn require_two_factor_submit(
+    State(state): State<AppState>,
+    cookies: CookieJar,
+    Form(form): Form<RequireTwoFactorForm>,
+) -> impl IntoResponse {
+    let Some(user) = auth::get_current_user(&state, &cookies).await else {
+        return Html("<div class='lcars-error'>Unauthorized</div>").into_response();
+    };
+
+    let db = state.db.lock().await;
+    let error = if user.role != "admin" {
+        Some("Only admins can change this setting".to_string())
+    } else {
+        let required = form.required.is_some();
+        match two_factor::set_admin_two_factor_required(&db, required) {
+            Ok(()) => {
+                ActivityBuilder::new(
+                    EventType::ConfigChanged,
+                    if required {
+                        "Two-factor authentication required for admins"
+                    } else {
+                        "Two-factor authentication no longer required for admins"
+                    },
+                )
+                .user(user.sub)
+                .metadata(&serde_json::json!({ "require_admin_two_factor": required }))
+                .log_sync(&db);
+                None
+            }
+            Err(e) => Some(two_factor_error(user.sub, e)),
+        }
+    };
+
+    TwoFactorPartial {
+        two_factor: two_factor_view(&db, &user, error),
     }
     .into_response()
 }
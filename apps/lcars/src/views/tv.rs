@@ -348,6 +348,7 @@ pub async fn add_show(
             tmdb_id: form.tmdb_id,
             monitored: Some(true),
             quality_limit: None,
+            quality_profile_id: None,
         }),
     )
     .await;
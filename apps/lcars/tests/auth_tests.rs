@@ -2,6 +2,8 @@
 
 mod common;
 
+use std::future::IntoFuture;
+
 use common::TestApp;
 
 #[tokio::test]
@@ -67,6 +69,113 @@ async fn test_login_nonexistent_user() {
     response.assert_status_unauthorized();
 }
 
+#[tokio::test]
+async fn test_repeated_login_failures_are_throttled() {
+    let app = TestApp::new().await;
+
+    let _user_id = app
+        .create_test_user("testuser", "password123", "user")
+        .await;
+
+    // The first failures after the free attempts start the backoff
+    for _ in 0..4 {
+        app.server()
+            .post("/api/auth/login")
+            .json(&serde_json::json!({
+                "username": "testuser",
+                "password": "wrongpassword"
+            }))
+            .await
+            .assert_status_unauthorized();
+    }
+
+    // Even the right password is refused until the backoff passes
+    let response = app
+        .server()
+        .post("/api/auth/login")
+        .json(&serde_json::json!({
+            "username": "testuser",
+            "password": "password123"
+        }))
+        .await;
+
+    response.assert_status(axum::http::StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(response.header("retry-after"), "1");
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["error"], "rate_limited");
+
+    // Other usernames are not affected
+    let _other_id = app
+        .create_test_user("otheruser", "password123", "user")
+        .await;
+    app.server()
+        .post("/api/auth/login")
+        .json(&serde_json::json!({
+            "username": "otheruser",
+            "password": "password123"
+        }))
+        .await
+        .assert_status_ok();
+}
+
+#[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+async fn test_concurrent_login_attempts_are_throttled() {
+    let app = TestApp::new_http().await;
+
+    let _user_id = app
+        .create_test_user("testuser", "password123", "user")
+        .await;
+
+    // Parallel guesses cannot all get past the throttle before the first
+    // failure is counted
+    let requests = (0..10).map(|_| {
+        app.server()
+            .post("/api/auth/login")
+            .json(&serde_json::json!({
+                "username": "testuser",
+                "password": "wrongpassword"
+            }))
+            .into_future()
+    });
+    let responses = futures::future::join_all(requests).await;
+
+    let count = |status| {
+        responses
+            .iter()
+            .filter(|response| response.status_code() == status)
+            .count()
+    };
+    assert_eq!(count(axum::http::StatusCode::UNAUTHORIZED), 4);
+    assert_eq!(count(axum::http::StatusCode::TOO_MANY_REQUESTS), 6);
+}
+
+#[tokio::test]
+async fn test_login_failures_for_unknown_users_are_throttled() {
+    let app = TestApp::new().await;
+
+    for _ in 0..4 {
+        app.server()
+            .post("/api/auth/login")
+            .json(&serde_json::json!({
+                "username": "nonexistent",
+                "password": "password123"
+            }))
+            .await
+            .assert_status_unauthorized();
+    }
+
+    let response = app
+        .server()
+        .post("/api/auth/login")
+        .json(&serde_json::json!({
+            "username": "nonexistent",
+            "password": "password123"
+        }))
+        .await;
+
+    response.assert_status(axum::http::StatusCode::TOO_MANY_REQUESTS);
+}
+
 #[tokio::test]
 async fn test_me_endpoint_authenticated() {
     let app = TestApp::new().await;
@@ -132,6 +241,16 @@ async fn test_logout_success() {
     response.assert_status_ok();
     let body: serde_json::Value = response.json();
     assert_eq!(body["message"], "Logged out successfully");
+
+    // The token no longer works
+    let (name, value) = app.auth_header(&token);
+    let response = app
+        .server()
+        .get("/api/auth/me")
+        .add_header(name, value)
+        .await;
+
+    response.assert_status_unauthorized();
 }
 
 #[tokio::test]
@@ -143,3 +262,502 @@ async fn test_logout_unauthenticated() {
 
     response.assert_status_unauthorized();
 }
+
+// =============================================================================
+// Session tests
+// =============================================================================
+
+/// Log in through the API and return the token.
+async fn login(app: &TestApp, username: &str, password: &str) -> String {
+    let response = app
+        .server()
+        .post("/api/auth/login")
+        .json(&serde_json::json!({
+            "username": username,
+            "password": password
+        }))
+        .await;
+
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    body["token"].as_str().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn test_list_sessions() {
+    let app = TestApp::new().await;
+    app.create_test_user("testuser", "password123", "user")
+        .await;
+    let first = login(&app, "testuser", "password123").await;
+    let _second = login(&app, "testuser", "password123").await;
+
+    let (name, value) = app.auth_header(&first);
+    let response = app
+        .server()
+        .get("/api/auth/sessions")
+        .add_header(name, value)
+        .await;
+
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    let sessions = body.as_array().unwrap();
+    assert_eq!(sessions.len(), 2);
+    assert_eq!(sessions.iter().filter(|s| s["current"] == true).count(), 1);
+    // Token hashes stay on the server
+    assert!(sessions[0].get("token_hash").is_none());
+}
+
+#[tokio::test]
+async fn test_logout_all() {
+    let app = TestApp::new().await;
+    app.create_test_user("testuser", "password123", "user")
+        .await;
+    let first = login(&app, "testuser", "password123").await;
+    let second = login(&app, "testuser", "password123").await;
+
+    let (name, value) = app.auth_header(&first);
+    let response = app
+        .server()
+        .post("/api/auth/logout-all")
+        .add_header(name, value)
+        .await;
+
+    response.assert_status_ok();
+
+    for token in [first, second] {
+        let (name, value) = app.auth_header(&token);
+        let response = app
+            .server()
+            .get("/api/auth/me")
+            .add_header(name, value)
+            .await;
+        response.assert_status_unauthorized();
+    }
+}
+
+#[tokio::test]
+async fn test_delete_session() {
+    let app = TestApp::new().await;
+    app.create_test_user("testuser", "password123", "user")
+        .await;
+    let first = login(&app, "testuser", "password123").await;
+    let second = login(&app, "testuser", "password123").await;
+    let (name, value) = app.auth_header(&first);
+
+    let response = app
+        .server()
+        .get("/api/auth/sessions")
+        .add_header(name.clone(), value.clone())
+        .await;
+    let body: serde_json::Value = response.json();
+    let other = body
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|s| s["current"] == false)
+        .unwrap()["id"]
+        .as_i64()
+        .unwrap();
+
+    let response = app
+        .server()
+        .delete(&format!("/api/auth/sessions/{}", other))
+        .add_header(name.clone(), value.clone())
+        .await;
+    response.assert_status_ok();
+
+    // Only the revoked session is logged out
+    let (other_name, other_value) = app.auth_header(&second);
+    let response = app
+        .server()
+        .get("/api/auth/me")
+        .add_header(other_name, other_value)
+        .await;
+    response.assert_status_unauthorized();
+
+    let response = app
+        .server()
+        .get("/api/auth/me")
+        .add_header(name.clone(), value.clone())
+        .await;
+    response.assert_status_ok();
+
+    // Sessions of other users cannot be revoked
+    let response = app
+        .server()
+        .delete("/api/auth/sessions/9999")
+        .add_header(name, value)
+        .await;
+    response.assert_status_not_found();
+}
+
+#[tokio::test]
+async fn test_token_without_session_rejected() {
+    let app = TestApp::new().await;
+    let (user_id, token) = app.create_user().await;
+
+    let db = app.db().lock().await;
+    db.execute("DELETE FROM sessions WHERE user_id = ?1", [user_id])
+        .unwrap();
+    drop(db);
+
+    let (name, value) = app.auth_header(&token);
+    let response = app
+        .server()
+        .get("/api/auth/me")
+        .add_header(name, value)
+        .await;
+
+    response.assert_status_unauthorized();
+}
+
+// =============================================================================
+// Two-factor authentication tests
+// =============================================================================
+
+/// TOTP code for a base32 secret, `offset` steps from now.
+fn totp_code(secret: &str, offset: i64) -> String {
+    use lcars::services::{totp, two_factor};
+
+    let secret = data_encoding::BASE32_NOPAD
+        .decode(secret.as_bytes())
+        .unwrap();
+    let step = totp::step_at(two_factor::unix_now()) as i64 + offset;
+    format!("{:06}", totp::code_at_step(&secret, step as u64))
+}
+
+/// Enrol a user in 2FA through the API. Returns the secret and recovery codes.
+async fn enable_two_factor(app: &TestApp, token: &str) -> (String, Vec<String>) {
+    let (name, value) = app.auth_header(token);
+    let response = app
+        .server()
+        .post("/api/auth/2fa/setup")
+        .add_header(name.clone(), value.clone())
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    let secret = body["secret"].as_str().unwrap().to_string();
+    assert!(body["otpauth_uri"]
+        .as_str()
+        .unwrap()
+        .starts_with("otpauth://totp/"));
+    assert!(body["qr_svg"].as_str().unwrap().contains("<svg"));
+
+    let response = app
+        .server()
+        .post("/api/auth/2fa/enable")
+        .add_header(name, value)
+        .json(&serde_json::json!({ "code": totp_code(&secret, -1) }))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    let codes = body["recovery_codes"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|c| c.as_str().unwrap().to_string())
+        .collect();
+
+    (secret, codes)
+}
+
+/// Password step of a login that must ask for the second factor.
+async fn login_challenge(app: &TestApp, username: &str, password: &str) -> String {
+    let response = app
+        .server()
+        .post("/api/auth/login")
+        .json(&serde_json::json!({
+            "username": username,
+            "password": password
+        }))
+        .await;
+
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["two_factor_required"], true);
+    assert!(body.get("token").is_none());
+    body["challenge_token"].as_str().unwrap().to_string()
+}
+
+#[tokio::test]
+async fn test_two_factor_login() {
+    let app = TestApp::new().await;
+    app.create_test_user("testuser", "password123", "user")
+        .await;
+    let token = login(&app, "testuser", "password123").await;
+    let (secret, _) = enable_two_factor(&app, &token).await;
+
+    let challenge = login_challenge(&app, "testuser", "password123").await;
+
+    // The challenge is not a session token
+    let (name, value) = app.auth_header(&challenge);
+    app.server()
+        .get("/api/auth/me")
+        .add_header(name, value)
+        .await
+        .assert_status_unauthorized();
+
+    let code = totp_code(&secret, 0);
+    let response = app
+        .server()
+        .post("/api/auth/login/2fa")
+        .json(&serde_json::json!({
+            "challenge_token": challenge,
+            "code": code
+        }))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    let token = body["token"].as_str().unwrap();
+
+    let (name, value) = app.auth_header(token);
+    app.server()
+        .get("/api/auth/me")
+        .add_header(name, value)
+        .await
+        .assert_status_ok();
+
+    // The same code cannot be used again
+    let challenge = login_challenge(&app, "testuser", "password123").await;
+    app.server()
+        .post("/api/auth/login/2fa")
+        .json(&serde_json::json!({
+            "challenge_token": challenge,
+            "code": code
+        }))
+        .await
+        .assert_status_unauthorized();
+}
+
+#[tokio::test]
+async fn test_two_factor_login_with_recovery_code() {
+    let app = TestApp::new().await;
+    app.create_test_user("testuser", "password123", "user")
+        .await;
+    let token = login(&app, "testuser", "password123").await;
+    let (_, recovery_codes) = enable_two_factor(&app, &token).await;
+    assert_eq!(recovery_codes.len(), 10);
+
+    let challenge = login_challenge(&app, "testuser", "password123").await;
+    app.server()
+        .post("/api/auth/login/2fa")
+        .json(&serde_json::json!({
+            "challenge_token": challenge,
+            "code": recovery_codes[0]
+        }))
+        .await
+        .assert_status_ok();
+
+    // Recovery codes work once
+    let challenge = login_challenge(&app, "testuser", "password123").await;
+    app.server()
+        .post("/api/auth/login/2fa")
+        .json(&serde_json::json!({
+            "challenge_token": challenge,
+            "code": recovery_codes[0]
+        }))
+        .await
+        .assert_status_unauthorized();
+
+    let (name, value) = app.auth_header(&token);
+    let response = app
+        .server()
+        .get("/api/auth/2fa")
+        .add_header(name, value)
+        .await;
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["enabled"], true);
+    assert_eq!(body["recovery_codes_remaining"], 9);
+}
+
+#[tokio::test]
+async fn test_two_factor_login_rejects_bad_code() {
+    let app = TestApp::new().await;
+    app.create_test_user("testuser", "password123", "user")
+        .await;
+    let token = login(&app, "testuser", "password123").await;
+    enable_two_factor(&app, &token).await;
+
+    let challenge = login_challenge(&app, "testuser", "password123").await;
+    app.server()
+        .post("/api/auth/login/2fa")
+        .json(&serde_json::json!({
+            "challenge_token": challenge,
+            "code": "not-a-code"
+        }))
+        .await
+        .assert_status_unauthorized();
+
+    app.server()
+        .post("/api/auth/login/2fa")
+        .json(&serde_json::json!({
+            "challenge_token": "garbage",
+            "code": "123456"
+        }))
+        .await
+        .assert_status_unauthorized();
+}
+
+#[tokio::test]
+async fn test_two_factor_disable() {
+    let app = TestApp::new().await;
+    app.create_test_user("testuser", "password123", "user")
+        .await;
+    let token = login(&app, "testuser", "password123").await;
+    let (secret, _) = enable_two_factor(&app, &token).await;
+
+    let (name, value) = app.auth_header(&token);
+    app.server()
+        .post("/api/auth/2fa/disable")
+        .add_header(name.clone(), value.clone())
+        .json(&serde_json::json!({ "code": "000000" }))
+        .await
+        .assert_status_bad_request();
+
+    app.server()
+        .post("/api/auth/2fa/disable")
+        .add_header(name, value)
+        .json(&serde_json::json!({ "code": totp_code(&secret, 0) }))
+        .await
+        .assert_status_ok();
+
+    // Password alone is enough again
+    login(&app, "testuser", "password123").await;
+}
+
+#[tokio::test]
+async fn test_admin_two_factor_requirement() {
+    let app = TestApp::new().await;
+    app.create_test_user("captain", "password123", "admin")
+        .await;
+    let token = login(&app, "captain", "password123").await;
+    let (name, value) = app.auth_header(&token);
+
+    app.server()
+        .put("/api/system/security")
+        .add_header(name.clone(), value.clone())
+        .json(&serde_json::json!({ "require_admin_two_factor": true }))
+        .await
+        .assert_status_ok();
+
+    // Admin APIs are blocked until the admin enrols
+    app.server()
+        .get("/api/users")
+        .add_header(name.clone(), value.clone())
+        .await
+        .assert_status_forbidden();
+
+    let response = app
+        .server()
+        .post("/api/auth/login")
+        .json(&serde_json::json!({
+            "username": "captain",
+            "password": "password123"
+        }))
+        .await;
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["two_factor_setup_required"], true);
+
+    let (secret, _) = enable_two_factor(&app, &token).await;
+    app.server()
+        .get("/api/users")
+        .add_header(name.clone(), value.clone())
+        .await
+        .assert_status_ok();
+
+    // Cannot be turned off while required
+    app.server()
+        .post("/api/auth/2fa/disable")
+        .add_header(name, value)
+        .json(&serde_json::json!({ "code": totp_code(&secret, 0) }))
+        .await
+        .assert_status_bad_request();
+}
+
+/// Session cookie header for the HTMX views.
+fn session_cookie(token: &str) -> (axum::http::HeaderName, axum::http::HeaderValue) {
+    (
+        axum::http::header::COOKIE,
+        axum::http::HeaderValue::from_str(&format!("session={}", token)).unwrap(),
+    )
+}
+
+#[tokio::test]
+async fn test_admin_two_factor_requirement_redirects_views() {
+    let app = TestApp::new().await;
+    let (_, admin_token) = app.create_admin().await;
+    let (_, user_token) = app.create_user().await;
+    let (name, value) = app.auth_header(&admin_token);
+
+    app.server()
+        .put("/api/system/security")
+        .add_header(name, value)
+        .json(&serde_json::json!({ "require_admin_two_factor": true }))
+        .await
+        .assert_status_ok();
+
+    // Every page but the settings page redirects there until the admin enrols
+    let (cookie, session) = session_cookie(&admin_token);
+    let response = app
+        .server()
+        .get("/movies")
+        .add_header(cookie.clone(), session.clone())
+        .await;
+    response.assert_status(axum::http::StatusCode::SEE_OTHER);
+    assert_eq!(response.header("location"), "/settings");
+
+    let response = app
+        .server()
+        .get("/downloads")
+        .add_header(cookie.clone(), session.clone())
+        .add_header("hx-request", "true")
+        .await;
+    response.assert_status_ok();
+    assert_eq!(response.header("hx-redirect"), "/settings");
+
+    app.server()
+        .get("/settings")
+        .add_header(cookie.clone(), session.clone())
+        .await
+        .assert_status_ok();
+    app.server()
+        .post("/settings/2fa/setup")
+        .add_header(cookie.clone(), session.clone())
+        .await
+        .assert_status_ok();
+
+    // Users are not affected
+    let (user_cookie, user_session) = session_cookie(&user_token);
+    app.server()
+        .get("/movies")
+        .add_header(user_cookie, user_session)
+        .await
+        .assert_status_ok();
+
+    enable_two_factor(&app, &admin_token).await;
+    app.server()
+        .get("/movies")
+        .add_header(cookie, session)
+        .await
+        .assert_status_ok();
+}
+
+#[tokio::test]
+async fn test_admin_resets_two_factor() {
+    let app = TestApp::new().await;
+    let user_id = app
+        .create_test_user("testuser", "password123", "user")
+        .await;
+    let token = login(&app, "testuser", "password123").await;
+    enable_two_factor(&app, &token).await;
+
+    let (_, admin_token) = app.create_admin().await;
+    let (name, value) = app.auth_header(&admin_token);
+    app.server()
+        .delete(&format!("/api/users/{}/two-factor", user_id))
+        .add_header(name, value)
+        .await
+        .assert_status_ok();
+
+    login(&app, "testuser", "password123").await;
+}
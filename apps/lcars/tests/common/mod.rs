@@ -13,7 +13,9 @@ use rusqlite::Connection;
 use std::sync::Arc;
 use tokio::sync::Mutex;
 
-use lcars::services::{AuthService, IndexerManager};
+use lcars::services::{
+    AuthService, BandwidthManager, DownloadQueue, IndexerManager, LoginThrottle,
+};
 use lcars::{config::Config, db, AppState};
 
 /// Test application wrapper around axum_test::TestServer.
@@ -24,6 +26,7 @@ pub struct TestApp {
     server: TestServer,
     db: Arc<Mutex<Connection>>,
     auth_service: Arc<AuthService>,
+    indexer_manager: Arc<IndexerManager>,
 }
 
 impl TestApp {
@@ -38,6 +41,17 @@ impl TestApp {
     /// Optional services (TMDB, MusicBrainz, torrent engine, scheduler, storage)
     /// are initialized as None for test isolation.
     pub async fn new() -> Self {
+        Self::build(false).await
+    }
+
+    /// Like [`TestApp::new`], but served over a real socket so that
+    /// concurrent requests are handled in parallel.
+    #[allow(dead_code)]
+    pub async fn new_http() -> Self {
+        Self::build(true).await
+    }
+
+    async fn build(http_transport: bool) -> Self {
         // Initialize in-memory database
         let conn = db::init_db_memory().expect("Failed to initialize test database");
         let db = Arc::new(Mutex::new(conn));
@@ -50,6 +64,7 @@ impl TestApp {
                 jwt_secret: Some("test-jwt-secret-for-integration-tests".to_string()),
                 secure_cookies: false,
                 cors_origins: Vec::new(),
+                trusted_proxies: Vec::new(),
             },
             database: lcars::config::DatabaseConfig {
                 path: ":memory:".into(),
@@ -58,8 +73,11 @@ impl TestApp {
             musicbrainz: Default::default(),
             torrent: Default::default(),
             soulseek: Default::default(),
+            bandwidth: Default::default(),
+            login: Default::default(),
             storage: Default::default(),
             scheduler: Default::default(),
+            search: Default::default(),
             music: Default::default(),
             wireguard: None,
         };
@@ -69,9 +87,16 @@ impl TestApp {
             "test-jwt-secret-for-integration-tests".to_string(),
         ));
 
-        // Create indexer manager
+        // Create indexer manager. Unlike main.rs the indexers table is not
+        // loaded at startup, so searches never reach real sites unless a test
+        // changes the indexers.
         let indexer_manager = IndexerManager::new_shared();
 
+        // Create download queue (downloads stay queued without engines)
+        let download_queue = DownloadQueue::new_shared(Arc::clone(&db), &config, None, None);
+
+        let login_throttle = LoginThrottle::new_shared(config.login.clone());
+
         // Create application state (without optional services for test isolation)
         let state = AppState {
             config: Arc::new(config),
@@ -79,8 +104,11 @@ impl TestApp {
             auth_service: Arc::clone(&auth_service),
             tmdb_client: None,
             musicbrainz_client: None,
-            indexer_manager,
+            indexer_manager: Arc::clone(&indexer_manager),
             torrent_engine: None,
+            bandwidth: BandwidthManager::new_shared(Default::default()),
+            download_queue,
+            login_throttle,
             soulseek_engine: None,
             scheduler: None,
             start_time: std::time::Instant::now(),
@@ -92,12 +120,17 @@ impl TestApp {
         let app = Self::build_router(state);
 
         // Create test server
-        let server = TestServer::new(app).expect("Failed to create test server");
+        let mut builder = TestServer::builder();
+        if http_transport {
+            builder = builder.http_transport();
+        }
+        let server = builder.build(app).expect("Failed to create test server");
 
         Self {
             server,
             db,
             auth_service,
+            indexer_manager,
         }
     }
 
@@ -110,25 +143,34 @@ impl TestApp {
     /// with axum-test. Both syntaxes are valid in Axum 0.7, but axum-test requires
     /// the colon syntax for proper route matching in test environments.
     fn build_router(state: AppState) -> Router {
-        // Build auth routes (public)
+        // Build auth routes: login is public, the rest manage the user's own
+        // credentials and are not open to API keys
         let auth_routes = Router::new()
             .route("/login", post(lcars::api::auth::login))
-            .route(
-                "/logout",
-                post(lcars::api::auth::logout).layer(axum_mw::from_fn_with_state(
-                    state.clone(),
-                    lcars::middleware::auth_middleware,
-                )),
-            )
-            .route(
-                "/me",
-                get(lcars::api::auth::me).layer(axum_mw::from_fn_with_state(
-                    state.clone(),
-                    lcars::middleware::auth_middleware,
-                )),
+            .route("/login/2fa", post(lcars::api::auth::login_two_factor))
+            .merge(
+                Router::new()
+                    .route("/logout", post(lcars::api::auth::logout))
+                    .route("/me", get(lcars::api::auth::me))
+                    .route("/logout-all", post(lcars::api::auth::logout_all))
+                    .route("/sessions", get(lcars::api::auth::list_sessions))
+                    .route("/sessions/:id", delete(lcars::api::auth::delete_session))
+                    .route("/2fa", get(lcars::api::auth::two_factor_status))
+                    .route("/2fa/setup", post(lcars::api::auth::two_factor_setup))
+                    .route("/2fa/enable", post(lcars::api::auth::two_factor_enable))
+                    .route("/2fa/disable", post(lcars::api::auth::two_factor_disable))
+                    .route(
+                        "/2fa/recovery-codes",
+                        post(lcars::api::auth::regenerate_recovery_codes),
+                    )
+                    .layer(axum_mw::from_fn(lcars::middleware::reject_api_keys))
+                    .layer(axum_mw::from_fn_with_state(
+                        state.clone(),
+                        lcars::middleware::auth_middleware,
+                    )),
             );
 
-        // Build user routes (admin only)
+        // Build user routes (admin only, not open to API keys)
         let user_routes = Router::new()
             .route(
                 "/",
@@ -138,7 +180,25 @@ impl TestApp {
                 "/:id",
                 put(lcars::api::users::update_user).delete(lcars::api::users::delete_user),
             )
+            .route(
+                "/:id/sessions",
+                delete(lcars::api::users::delete_user_sessions),
+            )
+            .route(
+                "/:id/api-keys",
+                get(lcars::api::users::list_user_api_keys)
+                    .post(lcars::api::users::create_user_api_key),
+            )
+            .route(
+                "/:id/api-keys/:key_id",
+                delete(lcars::api::users::delete_user_api_key),
+            )
+            .route(
+                "/:id/two-factor",
+                delete(lcars::api::users::delete_user_two_factor),
+            )
             .layer(axum_mw::from_fn(lcars::middleware::require_admin))
+            .layer(axum_mw::from_fn(lcars::middleware::reject_api_keys))
             .layer(axum_mw::from_fn_with_state(
                 state.clone(),
                 lcars::middleware::auth_middleware,
@@ -148,6 +208,15 @@ impl TestApp {
         let system_auth_routes = Router::new()
             .route("/status", get(lcars::api::system::get_system_status))
             .route("/activity", get(lcars::api::system::get_activity))
+            .route(
+                "/quality-profiles",
+                get(lcars::api::quality_profiles::list_quality_profiles),
+            )
+            .route(
+                "/quality-profiles/:id",
+                get(lcars::api::quality_profiles::get_quality_profile),
+            )
+            .route("/blocklist", get(lcars::api::blocklist::list_blocklist))
             .layer(axum_mw::from_fn_with_state(
                 state.clone(),
                 lcars::middleware::auth_middleware,
@@ -165,6 +234,24 @@ impl TestApp {
                 put(lcars::api::system::update_indexer).delete(lcars::api::system::delete_indexer),
             )
             .route("/indexers/:id/test", post(lcars::api::system::test_indexer))
+            .route(
+                "/quality-profiles",
+                post(lcars::api::quality_profiles::create_quality_profile),
+            )
+            .route(
+                "/quality-profiles/:id",
+                put(lcars::api::quality_profiles::update_quality_profile)
+                    .delete(lcars::api::quality_profiles::delete_quality_profile),
+            )
+            .route("/blocklist", delete(lcars::api::blocklist::clear_blocklist))
+            .route(
+                "/blocklist/:id",
+                delete(lcars::api::blocklist::delete_blocklist_entry),
+            )
+            .route(
+                "/security",
+                get(lcars::api::system::get_security).put(lcars::api::system::update_security),
+            )
             .route("/storage/mounts", get(lcars::api::system::list_mounts))
             .route(
                 "/storage/mounts/:name/test",
@@ -239,7 +326,27 @@ impl TestApp {
         let music_routes = lcars::api::music::router(state.clone());
 
         // Build downloads routes (authenticated)
-        let downloads_routes = lcars::api::downloads::router(state.clone());
+        let downloads_routes = Router::new()
+            .route("/", get(lcars::api::downloads::list_downloads))
+            .route("/files", post(lcars::api::downloads::list_torrent_files))
+            .route("/queue", get(lcars::api::downloads::list_queue))
+            .route(
+                "/:id",
+                get(lcars::api::downloads::get_download)
+                    .delete(lcars::api::downloads::delete_download),
+            )
+            .route("/:id/pause", post(lcars::api::downloads::pause_download))
+            .route("/:id/resume", post(lcars::api::downloads::resume_download))
+            .route("/:id/retry", post(lcars::api::downloads::retry_download))
+            .route(
+                "/:id/limits",
+                put(lcars::api::downloads::set_download_limits),
+            )
+            .route("/:id/position", put(lcars::api::downloads::move_download))
+            .layer(axum_mw::from_fn_with_state(
+                state.clone(),
+                lcars::middleware::auth_middleware,
+            ));
 
         // Build soulseek routes (authenticated)
         // Note: Using :param syntax instead of {param} for axum-test compatibility
@@ -286,6 +393,8 @@ impl TestApp {
         // Build main router with state
         Router::new()
             .route("/health", get(lcars::health_check))
+            // HTMX HTML routes (served at root)
+            .merge(lcars::views::routes(state.clone()))
             .nest("/api/auth", auth_routes)
             .nest("/api/users", user_routes)
             .nest("/api/movies", movies_routes)
@@ -325,6 +434,14 @@ impl TestApp {
         &self.auth_service
     }
 
+    /// Get a reference to the indexer manager.
+    ///
+    /// Useful for checking which providers searches would use.
+    #[allow(dead_code)]
+    pub fn indexer_manager(&self) -> &Arc<IndexerManager> {
+        &self.indexer_manager
+    }
+
     /// Create a test user in the database.
     ///
     /// Returns the user_id of the created user.
@@ -354,7 +471,7 @@ impl TestApp {
         db.last_insert_rowid()
     }
 
-    /// Generate a JWT token for the given user.
+    /// Generate a JWT token for the given user, recording its session.
     ///
     /// # Arguments
     /// * `user_id` - The user ID to encode in the token
@@ -362,11 +479,12 @@ impl TestApp {
     ///
     /// # Example
     /// ```ignore
-    /// let token = app.get_auth_token(1, "admin");
+    /// let token = app.get_auth_token(1, "admin").await;
     /// ```
-    pub fn get_auth_token(&self, user_id: i64, role: &str) -> String {
+    pub async fn get_auth_token(&self, user_id: i64, role: &str) -> String {
+        let db = self.db.lock().await;
         self.auth_service
-            .create_token(user_id, role)
+            .create_token(&db, user_id, role, None)
             .expect("Failed to create token")
     }
 
@@ -380,7 +498,7 @@ impl TestApp {
     ///
     /// # Example
     /// ```ignore
-    /// let token = app.get_auth_token(user_id, "user");
+    /// let token = app.get_auth_token(user_id, "user").await;
     /// let (name, value) = app.auth_header(&token);
     /// let response = app.server().get("/api/movies").add_header(name, value).await;
     /// ```
@@ -402,7 +520,7 @@ impl TestApp {
     /// ```
     pub async fn create_admin(&self) -> (i64, String) {
         let user_id = self.create_test_user("admin", "adminpass", "admin").await;
-        let token = self.get_auth_token(user_id, "admin");
+        let token = self.get_auth_token(user_id, "admin").await;
         (user_id, token)
     }
 
@@ -416,7 +534,7 @@ impl TestApp {
     /// ```
     pub async fn create_user(&self) -> (i64, String) {
         let user_id = self.create_test_user("testuser", "userpass", "user").await;
-        let token = self.get_auth_token(user_id, "user");
+        let token = self.get_auth_token(user_id, "user").await;
         (user_id, token)
     }
 }
@@ -452,22 +570,24 @@ mod tests {
     #[tokio::test]
     async fn test_get_auth_token() {
         let app = TestApp::new().await;
-        let token = app.get_auth_token(1, "admin");
+        let user_id = app.create_test_user("admin", "adminpass", "admin").await;
+        let token = app.get_auth_token(user_id, "admin").await;
         assert!(!token.is_empty());
 
-        // Verify token is valid
+        // Verify token and its session are valid
         let claims = app
             .auth_service
-            .verify_token(&token)
+            .verify_session(&*app.db.lock().await, &token)
             .expect("Token should be valid");
-        assert_eq!(claims.sub, 1);
+        assert_eq!(claims.sub, user_id);
         assert_eq!(claims.role, "admin");
     }
 
     #[tokio::test]
     async fn test_auth_header() {
         let app = TestApp::new().await;
-        let token = app.get_auth_token(1, "user");
+        let user_id = app.create_test_user("testuser", "userpass", "user").await;
+        let token = app.get_auth_token(user_id, "user").await;
         let (name, value) = app.auth_header(&token);
 
         assert_eq!(name, axum::http::header::AUTHORIZATION);
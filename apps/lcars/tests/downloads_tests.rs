@@ -0,0 +1,283 @@
+//! Integration tests for the downloads API endpoints.
+//!
+//! The test environment has no torrent engine, so these cover validation and
+//! what gets recorded in the database.
+
+mod common;
+
+use common::TestApp;
+
+/// Insert a download row and return its id.
+async fn insert_download(app: &TestApp, source_type: &str, source_id: &str) -> i64 {
+    let db = app.db().lock().await;
+    db.execute(
+        r#"
+        INSERT INTO downloads (source_type, source_id, name, media_type, media_id, source_uri, status)
+        VALUES (?1, ?2, 'Test Download', 'movie', 1, 'magnet:?xt=urn:btih:abc', 'downloading')
+        "#,
+        rusqlite::params![source_type, source_id],
+    )
+    .expect("Failed to insert test download");
+    db.last_insert_rowid()
+}
+
+// =============================================================================
+// Speed limit tests
+// =============================================================================
+
+#[tokio::test]
+async fn test_set_download_limits() {
+    let app = TestApp::new().await;
+    let (_user_id, token) = app.create_user().await;
+    let (name, value) = app.auth_header(&token);
+    let download_id = insert_download(&app, "torrent", "abc").await;
+
+    let response = app
+        .server()
+        .put(&format!("/api/downloads/{}/limits", download_id))
+        .add_header(name.clone(), value.clone())
+        .json(&serde_json::json!({
+            "download_limit": 512,
+            "upload_limit": 0
+        }))
+        .await;
+
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["download_limit"], 512);
+    // Zero removes the cap
+    assert!(body.get("upload_limit").is_none());
+
+    // Limits show up on the download
+    let response = app
+        .server()
+        .get(&format!("/api/downloads/{}", download_id))
+        .add_header(name, value)
+        .await;
+
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["download_limit"], 512);
+}
+
+#[tokio::test]
+async fn test_set_download_limits_soulseek_unsupported() {
+    let app = TestApp::new().await;
+    let (_user_id, token) = app.create_user().await;
+    let (name, value) = app.auth_header(&token);
+    let download_id = insert_download(&app, "soulseek", "transfer-1").await;
+
+    let response = app
+        .server()
+        .put(&format!("/api/downloads/{}/limits", download_id))
+        .add_header(name, value)
+        .json(&serde_json::json!({ "upload_limit": 100 }))
+        .await;
+
+    response.assert_status(axum::http::StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn test_set_download_limits_not_found() {
+    let app = TestApp::new().await;
+    let (_user_id, token) = app.create_user().await;
+    let (name, value) = app.auth_header(&token);
+
+    let response = app
+        .server()
+        .put("/api/downloads/999/limits")
+        .add_header(name, value)
+        .json(&serde_json::json!({ "download_limit": 100 }))
+        .await;
+
+    response.assert_status_not_found();
+}
+
+#[tokio::test]
+async fn test_set_download_limits_unauthenticated() {
+    let app = TestApp::new().await;
+
+    let response = app
+        .server()
+        .put("/api/downloads/1/limits")
+        .json(&serde_json::json!({ "download_limit": 100 }))
+        .await;
+
+    response.assert_status_unauthorized();
+}
+
+// =============================================================================
+// Download queue tests
+// =============================================================================
+
+/// Insert a queued download at a queue position and return its id.
+async fn insert_queued(app: &TestApp, source_id: &str, queue_order: i64) -> i64 {
+    let db = app.db().lock().await;
+    db.execute(
+        r#"
+        INSERT INTO downloads (source_type, source_id, name, media_type, media_id, source_uri, status, queue_order)
+        VALUES ('torrent', ?1, ?1, 'movie', 1, 'magnet:?xt=urn:btih:abc', 'queued', ?2)
+        "#,
+        rusqlite::params![source_id, queue_order],
+    )
+    .expect("Failed to insert queued download");
+    db.last_insert_rowid()
+}
+
+fn queue_ids(body: &serde_json::Value) -> Vec<i64> {
+    body.as_array()
+        .unwrap()
+        .iter()
+        .map(|d| d["id"].as_i64().unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn test_list_queue() {
+    let app = TestApp::new().await;
+    let (_user_id, token) = app.create_user().await;
+    let (name, value) = app.auth_header(&token);
+    let second = insert_queued(&app, "queued:b", 2).await;
+    let first = insert_queued(&app, "queued:a", 1).await;
+    insert_download(&app, "torrent", "running").await;
+
+    let response = app
+        .server()
+        .get("/api/downloads/queue")
+        .add_header(name, value)
+        .await;
+
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(queue_ids(&body), [first, second]);
+    assert_eq!(body[0]["queue_order"], 1);
+}
+
+#[tokio::test]
+async fn test_move_download_in_queue() {
+    let app = TestApp::new().await;
+    let (_user_id, token) = app.create_user().await;
+    let (name, value) = app.auth_header(&token);
+    let first = insert_queued(&app, "queued:a", 1).await;
+    let second = insert_queued(&app, "queued:b", 2).await;
+    let third = insert_queued(&app, "queued:c", 3).await;
+
+    let response = app
+        .server()
+        .put(&format!("/api/downloads/{}/position", third))
+        .add_header(name.clone(), value.clone())
+        .json(&serde_json::json!({ "position": 1 }))
+        .await;
+
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(queue_ids(&body), [third, first, second]);
+
+    // The new order is what the queue lists
+    let response = app
+        .server()
+        .get("/api/downloads/queue")
+        .add_header(name, value)
+        .await;
+
+    let body: serde_json::Value = response.json();
+    assert_eq!(queue_ids(&body), [third, first, second]);
+}
+
+#[tokio::test]
+async fn test_move_download_not_queued() {
+    let app = TestApp::new().await;
+    let (_user_id, token) = app.create_user().await;
+    let (name, value) = app.auth_header(&token);
+    let download_id = insert_download(&app, "torrent", "abc").await;
+
+    let response = app
+        .server()
+        .put(&format!("/api/downloads/{}/position", download_id))
+        .add_header(name.clone(), value.clone())
+        .json(&serde_json::json!({ "position": 1 }))
+        .await;
+
+    response.assert_status(axum::http::StatusCode::CONFLICT);
+
+    let response = app
+        .server()
+        .put(&format!("/api/downloads/{}/position", download_id))
+        .add_header(name, value)
+        .json(&serde_json::json!({ "position": 0 }))
+        .await;
+
+    response.assert_status_bad_request();
+}
+
+#[tokio::test]
+async fn test_move_download_unauthenticated() {
+    let app = TestApp::new().await;
+
+    let response = app
+        .server()
+        .put("/api/downloads/1/position")
+        .json(&serde_json::json!({ "position": 1 }))
+        .await;
+
+    response.assert_status_unauthorized();
+}
+
+// =============================================================================
+// Blocklist tests
+// =============================================================================
+
+#[tokio::test]
+async fn test_delete_download_with_blocklist() {
+    let app = TestApp::new().await;
+    let (_user_id, token) = app.create_user().await;
+    let (name, value) = app.auth_header(&token);
+    let download_id = insert_download(&app, "torrent", "abc").await;
+    {
+        let db = app.db().lock().await;
+        db.execute(
+            "UPDATE downloads SET release_title = 'Test.Download.2024.1080p.WEB-DL' WHERE id = ?1",
+            [download_id],
+        )
+        .unwrap();
+    }
+
+    let response = app
+        .server()
+        .delete(&format!("/api/downloads/{}?blocklist=true", download_id))
+        .add_header(name.clone(), value.clone())
+        .await;
+
+    response.assert_status_ok();
+
+    let response = app
+        .server()
+        .get("/api/system/blocklist")
+        .add_header(name, value)
+        .await;
+
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    let entries = body.as_array().unwrap();
+    assert_eq!(entries.len(), 1);
+    assert_eq!(entries[0]["info_hash"], "abc");
+    assert_eq!(entries[0]["title"], "Test.Download.2024.1080p.WEB-DL");
+    assert_eq!(entries[0]["reason"], "manual");
+    assert_eq!(entries[0]["media_type"], "movie");
+}
+
+#[tokio::test]
+async fn test_delete_download_with_blocklist_soulseek_unsupported() {
+    let app = TestApp::new().await;
+    let (_user_id, token) = app.create_user().await;
+    let (name, value) = app.auth_header(&token);
+    let download_id = insert_download(&app, "soulseek", "transfer-1").await;
+
+    let response = app
+        .server()
+        .delete(&format!("/api/downloads/{}?blocklist=true", download_id))
+        .add_header(name, value)
+        .await;
+
+    response.assert_status_bad_request();
+}
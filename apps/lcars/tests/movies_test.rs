@@ -752,6 +752,51 @@ async fn test_download_release_invalid_magnet() {
     response.assert_status(axum::http::StatusCode::BAD_REQUEST);
 }
 
+#[tokio::test]
+async fn test_download_release_invalid_torrent_source() {
+    let app = TestApp::new().await;
+    let (user_id, token) = app.create_user().await;
+    let (name, value) = app.auth_header(&token);
+
+    // Insert a test movie
+    let db = app.db().lock().await;
+    db.execute(
+        r#"
+        INSERT INTO movies (tmdb_id, title, year, status, monitored, quality_limit, added_by)
+        VALUES (550, 'Fight Club', 1999, 'missing', 1, '1080p', ?1)
+        "#,
+        rusqlite::params![user_id],
+    )
+    .expect("Failed to insert test movie");
+    let movie_id = db.last_insert_rowid();
+    drop(db);
+
+    let invalid = [
+        // No source at all
+        serde_json::json!({}),
+        // Several sources
+        serde_json::json!({
+            "magnet": "magnet:?xt=urn:btih:abc123",
+            "torrent_url": "https://tracker.example/1.torrent"
+        }),
+        // Not an HTTP(S) URL
+        serde_json::json!({ "torrent_url": "file:///etc/passwd" }),
+        // Not base64
+        serde_json::json!({ "torrent_file": "not base64!" }),
+    ];
+
+    for body in invalid {
+        let response = app
+            .server()
+            .post(&format!("/api/movies/{}/download", movie_id))
+            .add_header(name.clone(), value.clone())
+            .json(&body)
+            .await;
+
+        response.assert_status(axum::http::StatusCode::BAD_REQUEST);
+    }
+}
+
 #[tokio::test]
 async fn test_download_release_movie_not_found() {
     let app = TestApp::new().await;
@@ -879,3 +924,57 @@ async fn test_refresh_metadata_no_tmdb_client() {
     // Should return internal server error when TMDB client is not configured
     response.assert_status(axum::http::StatusCode::INTERNAL_SERVER_ERROR);
 }
+
+#[tokio::test]
+async fn test_update_movie_quality_profile() {
+    let app = TestApp::new().await;
+    let (user_id, token) = app.create_user().await;
+    let (name, value) = app.auth_header(&token);
+
+    let db = app.db().lock().await;
+    db.execute(
+        r#"
+        INSERT INTO movies (tmdb_id, title, year, status, monitored, quality_limit, added_by)
+        VALUES (550, 'Fight Club', 1999, 'missing', 1, '1080p', ?1)
+        "#,
+        rusqlite::params![user_id],
+    )
+    .expect("Failed to insert test movie");
+    let movie_id = db.last_insert_rowid();
+    let (video, music): (i64, i64) = db
+        .query_row(
+            "SELECT (SELECT id FROM quality_profiles WHERE name = 'HD'), (SELECT id FROM quality_profiles WHERE name = 'Lossless')",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .unwrap();
+    drop(db);
+
+    // Music profiles do not apply to movies
+    app.server()
+        .put(&format!("/api/movies/{}", movie_id))
+        .add_header(name.clone(), value.clone())
+        .json(&serde_json::json!({ "quality_profile_id": music }))
+        .await
+        .assert_status_bad_request();
+
+    let response = app
+        .server()
+        .put(&format!("/api/movies/{}", movie_id))
+        .add_header(name.clone(), value.clone())
+        .json(&serde_json::json!({ "quality_profile_id": video }))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["quality_profile_id"], video);
+
+    let response = app
+        .server()
+        .put(&format!("/api/movies/{}", movie_id))
+        .add_header(name, value)
+        .json(&serde_json::json!({ "clear_quality_profile": true }))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert!(body["quality_profile_id"].is_null());
+}
@@ -0,0 +1,117 @@
+//! SMB mount tests against a real Samba server.
+//!
+//! The unit tests in `services/storage/smb` run against an in-process fake
+//! server; these check the same client against Samba. They are ignored by
+//! default and need a server:
+//!
+//! ```bash
+//! docker run -d --name lcars-samba -p 4445:445 \
+//!     -e NAME=media -e USER=lcars -e PASS=lcars dockurr/samba
+//! cargo test --test smb_tests -- --ignored
+//! ```
+//!
+//! Another server can be used through `LCARS_TEST_SMB_HOST`,
+//! `LCARS_TEST_SMB_SHARE`, `LCARS_TEST_SMB_USERNAME` and
+//! `LCARS_TEST_SMB_PASSWORD`. Each test works in its own directory, which is
+//! left behind (empty) since mounts cannot remove directories.
+
+use std::path::{Path, PathBuf};
+
+use lcars::services::storage::{Mount, SmbMount};
+use tempfile::TempDir;
+
+fn env_or(name: &str, default: &str) -> String {
+    std::env::var(name).unwrap_or_else(|_| default.to_string())
+}
+
+fn mount_with(share: &str, password: &str) -> SmbMount {
+    SmbMount::new(
+        "samba".to_string(),
+        &env_or("LCARS_TEST_SMB_HOST", "127.0.0.1:4445"),
+        share,
+        Some(&env_or("LCARS_TEST_SMB_USERNAME", "lcars")),
+        Some(password),
+        None,
+    )
+}
+
+fn mount() -> SmbMount {
+    mount_with(
+        &env_or("LCARS_TEST_SMB_SHARE", "media"),
+        &env_or("LCARS_TEST_SMB_PASSWORD", "lcars"),
+    )
+}
+
+/// A directory on the share no other test run uses.
+fn test_dir() -> PathBuf {
+    PathBuf::from(format!("lcars-test-{}", uuid::Uuid::new_v4()))
+}
+
+fn source_file(dir: &TempDir, len: usize) -> (PathBuf, Vec<u8>) {
+    let data: Vec<u8> = (0..len).map(|i| (i % 251) as u8).collect();
+    let path = dir.path().join(format!("source-{}", len));
+    std::fs::write(&path, &data).unwrap();
+    (path, data)
+}
+
+#[tokio::test]
+#[ignore = "needs a Samba server, see the module docs"]
+async fn test_samba_write_exists_and_delete() {
+    let mount = mount();
+    assert!(mount.available().await);
+
+    let source_dir = TempDir::new().unwrap();
+    // Several megabytes, larger than the server's maximum write size
+    let (source, _) = source_file(&source_dir, 20 * 1024 * 1024 + 123);
+
+    let dir = test_dir();
+    let dest = dir.join("Heat (1995)/Heat (1995) - 1080p.mkv");
+    mount.write_file(&source, &dest).await.unwrap();
+    assert!(mount.exists(&dest).await);
+    assert!(mount.exists(&dir.join("Heat (1995)")).await);
+    assert!(!mount.exists(&dir.join("missing.mkv")).await);
+
+    // Overwrites replace the previous content
+    let (source, _) = source_file(&source_dir, 10);
+    mount.write_file(&source, &dest).await.unwrap();
+    assert!(mount.exists(&dest).await);
+
+    mount.delete_file(&dest).await.unwrap();
+    assert!(!mount.exists(&dest).await);
+    assert!(mount.delete_file(&dest).await.is_err());
+}
+
+#[tokio::test]
+#[ignore = "needs a Samba server, see the module docs"]
+async fn test_samba_create_dir_all_and_free_space() {
+    let mount = mount();
+
+    let dir = test_dir().join("music/Artist/Album");
+    mount.create_dir_all(&dir).await.unwrap();
+    assert!(mount.exists(&dir).await);
+    // Creating existing directories is not an error
+    mount.create_dir_all(&dir).await.unwrap();
+
+    assert!(mount.free_space().await.unwrap() > 0);
+}
+
+#[tokio::test]
+#[ignore = "needs a Samba server, see the module docs"]
+async fn test_samba_rejects_bad_credentials_and_shares() {
+    // Failures below only count if the server is there
+    assert!(mount().available().await);
+
+    let share = env_or("LCARS_TEST_SMB_SHARE", "media");
+
+    let wrong_password = mount_with(&share, "not-the-password");
+    assert!(!wrong_password.available().await);
+    assert!(wrong_password.free_space().await.is_err());
+
+    let password = env_or("LCARS_TEST_SMB_PASSWORD", "lcars");
+    let unknown_share = mount_with("lcars-no-such-share", &password);
+    assert!(!unknown_share.available().await);
+    assert!(unknown_share
+        .create_dir_all(Path::new("anything"))
+        .await
+        .is_err());
+}
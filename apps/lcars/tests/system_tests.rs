@@ -0,0 +1,295 @@
+//! Integration tests for system endpoints.
+
+mod common;
+
+use common::TestApp;
+
+// =============================================================================
+// Indexer tests
+// =============================================================================
+
+fn provider_names(app: &TestApp) -> Vec<String> {
+    app.indexer_manager()
+        .providers()
+        .iter()
+        .map(|p| p.name().to_string())
+        .collect()
+}
+
+#[tokio::test]
+async fn test_indexer_changes_reload_providers() {
+    let app = TestApp::new().await;
+    let (_, token) = app.create_admin().await;
+    let (name, value) = app.auth_header(&token);
+
+    let response = app
+        .server()
+        .post("/api/system/indexers")
+        .add_header(name.clone(), value.clone())
+        .json(&serde_json::json!({
+            "name": "YTS mirror",
+            "indexer_type": "yts",
+            "url": "https://yts.example",
+            "priority": 90,
+            "categories": "[\"movies\"]"
+        }))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    let id = body["id"].as_i64().unwrap();
+
+    // The default indexers plus the new one, highest priority first
+    assert_eq!(
+        provider_names(&app),
+        vec!["YTS", "Rutracker", "EZTV", "1337x", "YTS"]
+    );
+
+    app.server()
+        .put("/api/system/indexers/1")
+        .add_header(name.clone(), value.clone())
+        .json(&serde_json::json!({ "enabled": false }))
+        .await
+        .assert_status_ok();
+    assert_eq!(
+        provider_names(&app),
+        vec!["YTS", "Rutracker", "EZTV", "YTS"]
+    );
+
+    app.server()
+        .delete(&format!("/api/system/indexers/{}", id))
+        .add_header(name, value)
+        .await
+        .assert_status_ok();
+    assert_eq!(provider_names(&app), vec!["Rutracker", "EZTV", "YTS"]);
+}
+
+#[tokio::test]
+async fn test_torznab_indexer_uses_row_name() {
+    let app = TestApp::new().await;
+    let (_, token) = app.create_admin().await;
+    let (name, value) = app.auth_header(&token);
+
+    app.server()
+        .post("/api/system/indexers")
+        .add_header(name, value)
+        .json(&serde_json::json!({
+            "name": "Jackett",
+            "indexer_type": "torznab",
+            "url": "http://jackett.example:9117/api/v2.0/indexers/all/results/torznab",
+            "api_key": "secret",
+            "priority": 80
+        }))
+        .await
+        .assert_status_ok();
+
+    assert_eq!(provider_names(&app)[0], "Jackett");
+}
+
+#[tokio::test]
+async fn test_create_indexer_validates_type_and_categories() {
+    let app = TestApp::new().await;
+    let (_, token) = app.create_admin().await;
+    let (name, value) = app.auth_header(&token);
+
+    app.server()
+        .post("/api/system/indexers")
+        .add_header(name.clone(), value.clone())
+        .json(&serde_json::json!({
+            "name": "Mystery",
+            "indexer_type": "public",
+            "url": "https://example.com"
+        }))
+        .await
+        .assert_status_bad_request();
+
+    app.server()
+        .post("/api/system/indexers")
+        .add_header(name.clone(), value.clone())
+        .json(&serde_json::json!({
+            "name": "1337x mirror",
+            "indexer_type": "1337x",
+            "url": "https://1337x.example",
+            "categories": "movies"
+        }))
+        .await
+        .assert_status_bad_request();
+
+    app.server()
+        .put("/api/system/indexers/1")
+        .add_header(name, value)
+        .json(&serde_json::json!({ "indexer_type": "public" }))
+        .await
+        .assert_status_bad_request();
+
+    assert!(provider_names(&app).is_empty());
+}
+
+#[tokio::test]
+async fn test_quality_profile_crud() {
+    let app = TestApp::new().await;
+    let (_, admin_token) = app.create_admin().await;
+    let (admin_name, admin_value) = app.auth_header(&admin_token);
+    let (_, user_token) = app.create_user().await;
+    let (user_name, user_value) = app.auth_header(&user_token);
+
+    // Default profiles are visible to every user
+    let response = app
+        .server()
+        .get("/api/system/quality-profiles")
+        .add_header(user_name.clone(), user_value.clone())
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body.as_array().unwrap().len(), 3);
+
+    // Only admins manage them
+    let profile = serde_json::json!({
+        "name": "Compact",
+        "media_type": "video",
+        "allowed": ["720p WEB-DL", "720p HDTV"],
+        "cutoff": "720p WEB-DL",
+        "rejected_words": ["CAM"],
+        "max_size_mb_per_minute": 15
+    });
+    app.server()
+        .post("/api/system/quality-profiles")
+        .add_header(user_name, user_value)
+        .json(&profile)
+        .await
+        .assert_status_forbidden();
+
+    let response = app
+        .server()
+        .post("/api/system/quality-profiles")
+        .add_header(admin_name.clone(), admin_value.clone())
+        .json(&profile)
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(
+        body["allowed"],
+        serde_json::json!(["720p WEB-DL", "720p HDTV"])
+    );
+    assert_eq!(body["rejected_words"], serde_json::json!(["CAM"]));
+    let id = body["id"].as_i64().unwrap();
+
+    app.server()
+        .post("/api/system/quality-profiles")
+        .add_header(admin_name.clone(), admin_value.clone())
+        .json(&profile)
+        .await
+        .assert_status(axum::http::StatusCode::CONFLICT);
+
+    // The cutoff must be allowed, and qualities must fit the media type
+    app.server()
+        .put(&format!("/api/system/quality-profiles/{}", id))
+        .add_header(admin_name.clone(), admin_value.clone())
+        .json(&serde_json::json!({ "cutoff": "1080p BluRay" }))
+        .await
+        .assert_status_bad_request();
+    app.server()
+        .put(&format!("/api/system/quality-profiles/{}", id))
+        .add_header(admin_name.clone(), admin_value.clone())
+        .json(&serde_json::json!({ "allowed": ["FLAC"], "cutoff": "FLAC" }))
+        .await
+        .assert_status_bad_request();
+
+    let response = app
+        .server()
+        .put(&format!("/api/system/quality-profiles/{}", id))
+        .add_header(admin_name.clone(), admin_value.clone())
+        .json(&serde_json::json!({ "preferred_words": ["NTb"], "clear_sizes": true }))
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body["preferred_words"], serde_json::json!(["NTb"]));
+    assert!(body["max_size_mb_per_minute"].is_null());
+
+    app.server()
+        .delete(&format!("/api/system/quality-profiles/{}", id))
+        .add_header(admin_name.clone(), admin_value.clone())
+        .await
+        .assert_status_ok();
+    app.server()
+        .get(&format!("/api/system/quality-profiles/{}", id))
+        .add_header(admin_name, admin_value)
+        .await
+        .assert_status_not_found();
+}
+
+#[tokio::test]
+async fn test_blocklist_management() {
+    let app = TestApp::new().await;
+    let (_, admin_token) = app.create_admin().await;
+    let (admin_name, admin_value) = app.auth_header(&admin_token);
+    let (_, user_token) = app.create_user().await;
+    let (user_name, user_value) = app.auth_header(&user_token);
+
+    let first_id = {
+        let db = app.db().lock().await;
+        db.execute(
+            "INSERT INTO blocklist (info_hash, title, reason, message) VALUES ('abc', 'Movie.2024.1080p.BluRay', 'failed', 'tracker error')",
+            [],
+        )
+        .unwrap();
+        let id = db.last_insert_rowid();
+        db.execute(
+            "INSERT INTO blocklist (title, reason) VALUES ('Movie.2024.720p.HDTV', 'stalled')",
+            [],
+        )
+        .unwrap();
+        id
+    };
+
+    // The blocklist is visible to every user
+    let response = app
+        .server()
+        .get("/api/system/blocklist")
+        .add_header(user_name.clone(), user_value.clone())
+        .await;
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    assert_eq!(body.as_array().unwrap().len(), 2);
+
+    // Only admins remove entries
+    app.server()
+        .delete(&format!("/api/system/blocklist/{}", first_id))
+        .add_header(user_name, user_value)
+        .await
+        .assert_status_forbidden();
+
+    app.server()
+        .delete(&format!("/api/system/blocklist/{}", first_id))
+        .add_header(admin_name.clone(), admin_value.clone())
+        .await
+        .assert_status_ok();
+
+    app.server()
+        .delete(&format!("/api/system/blocklist/{}", first_id))
+        .add_header(admin_name.clone(), admin_value.clone())
+        .await
+        .assert_status_not_found();
+
+    let response = app
+        .server()
+        .get("/api/system/blocklist")
+        .add_header(admin_name.clone(), admin_value.clone())
+        .await;
+    let body: serde_json::Value = response.json();
+    assert_eq!(body[0]["title"], "Movie.2024.720p.HDTV");
+    assert_eq!(body[0]["reason"], "stalled");
+
+    app.server()
+        .delete("/api/system/blocklist")
+        .add_header(admin_name.clone(), admin_value.clone())
+        .await
+        .assert_status_ok();
+
+    let response = app
+        .server()
+        .get("/api/system/blocklist")
+        .add_header(admin_name, admin_value)
+        .await;
+    let body: serde_json::Value = response.json();
+    assert!(body.as_array().unwrap().is_empty());
+}
@@ -2,6 +2,10 @@
 
 mod common;
 
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use axum::extract::Query;
 use common::TestApp;
 
 // =============================================================================
@@ -861,6 +865,101 @@ async fn test_search_episode_without_indexer() {
     assert_eq!(body.as_array().unwrap().len(), 0);
 }
 
+/// Torznab server that supports TV searches by id and records the query of
+/// each request.
+async fn torznab_server() -> (String, Arc<Mutex<Vec<HashMap<String, String>>>>) {
+    const CAPS: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<caps>
+  <searching>
+    <search available="yes" supportedParams="q"/>
+    <tv-search available="yes" supportedParams="q,season,ep,imdbid,tvdbid"/>
+  </searching>
+  <categories>
+    <category id="5000" name="TV"/>
+  </categories>
+</caps>"#;
+    const EMPTY: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0"><channel><title>Stub</title></channel></rss>"#;
+
+    let requests = Arc::new(Mutex::new(Vec::new()));
+    let recorded = Arc::clone(&requests);
+    let app = axum::Router::new().route(
+        "/api",
+        axum::routing::get(move |Query(params): Query<HashMap<String, String>>| {
+            let recorded = Arc::clone(&recorded);
+            async move {
+                let body = match params.get("t").map(String::as_str) {
+                    Some("caps") => CAPS,
+                    _ => EMPTY,
+                };
+                recorded.lock().unwrap().push(params);
+                body
+            }
+        }),
+    );
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        axum::serve(listener, app).await.unwrap();
+    });
+
+    (format!("http://{}", addr), requests)
+}
+
+#[tokio::test]
+async fn test_search_episode_sends_tvdb_id() {
+    let app = TestApp::new().await;
+    let (_user_id, user_token) = app.create_user().await;
+    let (url, requests) = torznab_server().await;
+
+    // Only the stub indexer is searched
+    let db = app.db().lock().await;
+    db.execute("UPDATE indexers SET enabled = 0", []).unwrap();
+    db.execute(
+        "INSERT INTO indexers (name, indexer_type, url, priority) VALUES ('Stub', 'torznab', ?1, 50)",
+        [&url],
+    )
+    .unwrap();
+    app.indexer_manager().reload(&db).unwrap();
+
+    db.execute(
+        r#"
+        INSERT INTO tv_shows (tmdb_id, tvdb_id, title, status, monitored, quality_limit, added_by)
+        VALUES (1399, 121361, 'Game of Thrones', 'ended', 1, '1080p', 1)
+        "#,
+        [],
+    )
+    .unwrap();
+    let show_id = db.last_insert_rowid();
+    db.execute(
+        r#"
+        INSERT INTO episodes (show_id, season_number, episode_number, title, status, monitored)
+        VALUES (?, 1, 1, 'Winter Is Coming', 'missing', 1)
+        "#,
+        [show_id],
+    )
+    .unwrap();
+    drop(db);
+
+    let (name, value) = app.auth_header(&user_token);
+    app.server()
+        .post(&format!("/api/tv/{}/season/1/episode/1/search", show_id))
+        .add_header(name, value)
+        .await
+        .assert_status_ok();
+
+    let requests = requests.lock().unwrap();
+    let search = requests
+        .iter()
+        .find(|r| r.get("t").map(String::as_str) != Some("caps"))
+        .expect("no search request");
+    assert_eq!(search["t"], "tvsearch");
+    assert_eq!(search["tvdbid"], "121361");
+    assert_eq!(search["season"], "1");
+    assert_eq!(search["ep"], "1");
+}
+
 #[tokio::test]
 async fn test_download_episode_without_torrent_engine() {
     let app = TestApp::new().await;
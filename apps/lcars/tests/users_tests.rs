@@ -2,6 +2,7 @@
 
 mod common;
 
+use axum::http::Method;
 use common::TestApp;
 
 #[tokio::test]
@@ -173,3 +174,372 @@ async fn test_delete_user_as_regular_user() {
 
     response.assert_status_forbidden();
 }
+
+#[tokio::test]
+async fn test_demoting_user_logs_them_out() {
+    let app = TestApp::new().await;
+
+    let (_admin_id, admin_token) = app.create_admin().await;
+    let other_id = app
+        .create_test_user("otheradmin", "pass12345", "admin")
+        .await;
+    let other_token = app.get_auth_token(other_id, "admin").await;
+
+    let (name, value) = app.auth_header(&admin_token);
+    let response = app
+        .server()
+        .put(&format!("/api/users/{}", other_id))
+        .add_header(name, value)
+        .json(&serde_json::json!({ "role": "user" }))
+        .await;
+
+    response.assert_status_ok();
+
+    // The admin token issued before the demotion is revoked
+    let (name, value) = app.auth_header(&other_token);
+    let response = app.server().get("/api/users").add_header(name, value).await;
+
+    response.assert_status_unauthorized();
+}
+
+#[tokio::test]
+async fn test_force_logout_user() {
+    let app = TestApp::new().await;
+
+    let (_admin_id, admin_token) = app.create_admin().await;
+    let (user_id, user_token) = app.create_user().await;
+
+    let (name, value) = app.auth_header(&admin_token);
+    let response = app
+        .server()
+        .delete(&format!("/api/users/{}/sessions", user_id))
+        .add_header(name.clone(), value.clone())
+        .await;
+
+    response.assert_status_ok();
+
+    let (user_name, user_value) = app.auth_header(&user_token);
+    let response = app
+        .server()
+        .get("/api/auth/me")
+        .add_header(user_name, user_value)
+        .await;
+
+    response.assert_status_unauthorized();
+
+    // Admin sessions are untouched
+    let response = app
+        .server()
+        .get("/api/auth/me")
+        .add_header(name.clone(), value.clone())
+        .await;
+
+    response.assert_status_ok();
+
+    let response = app
+        .server()
+        .delete("/api/users/9999/sessions")
+        .add_header(name, value)
+        .await;
+
+    response.assert_status_not_found();
+}
+
+#[tokio::test]
+async fn test_force_logout_as_regular_user() {
+    let app = TestApp::new().await;
+
+    let (user_id, user_token) = app.create_user().await;
+    let (name, value) = app.auth_header(&user_token);
+
+    let response = app
+        .server()
+        .delete(&format!("/api/users/{}/sessions", user_id))
+        .add_header(name, value)
+        .await;
+
+    response.assert_status_forbidden();
+}
+
+/// Creates an API key for a user as admin and returns (key id, key).
+async fn create_api_key(
+    app: &TestApp,
+    admin_token: &str,
+    user_id: i64,
+    scope: &str,
+) -> (i64, String) {
+    let (name, value) = app.auth_header(admin_token);
+    let response = app
+        .server()
+        .post(&format!("/api/users/{}/api-keys", user_id))
+        .add_header(name, value)
+        .json(&serde_json::json!({ "name": "script", "scope": scope }))
+        .await;
+
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    (
+        body["id"].as_i64().unwrap(),
+        body["key"].as_str().unwrap().to_string(),
+    )
+}
+
+#[tokio::test]
+async fn test_create_and_list_api_keys() {
+    let app = TestApp::new().await;
+
+    let (_admin_id, admin_token) = app.create_admin().await;
+    let user_id = app.create_test_user("scripter", "pass12345", "user").await;
+
+    let (key_id, key) = create_api_key(&app, &admin_token, user_id, "read").await;
+    assert!(key.starts_with("lcars_"));
+
+    let (name, value) = app.auth_header(&admin_token);
+    let response = app
+        .server()
+        .get(&format!("/api/users/{}/api-keys", user_id))
+        .add_header(name, value)
+        .await;
+
+    response.assert_status_ok();
+    let body: serde_json::Value = response.json();
+    let keys = body.as_array().unwrap();
+    assert_eq!(keys.len(), 1);
+    assert_eq!(keys[0]["id"], key_id);
+    assert_eq!(keys[0]["name"], "script");
+    assert_eq!(keys[0]["scope"], "read");
+    // The key itself is never listed
+    assert!(keys[0].get("key").is_none());
+    assert!(keys[0].get("key_hash").is_none());
+    assert!(key.starts_with(keys[0]["key_prefix"].as_str().unwrap()));
+}
+
+#[tokio::test]
+async fn test_api_key_duplicate_name() {
+    let app = TestApp::new().await;
+
+    let (admin_id, admin_token) = app.create_admin().await;
+    create_api_key(&app, &admin_token, admin_id, "full").await;
+
+    let (name, value) = app.auth_header(&admin_token);
+    let response = app
+        .server()
+        .post(&format!("/api/users/{}/api-keys", admin_id))
+        .add_header(name, value)
+        .json(&serde_json::json!({ "name": "script" }))
+        .await;
+
+    response.assert_status(axum::http::StatusCode::CONFLICT);
+}
+
+#[tokio::test]
+async fn test_api_keys_as_regular_user() {
+    let app = TestApp::new().await;
+
+    let (user_id, user_token) = app.create_user().await;
+    let (name, value) = app.auth_header(&user_token);
+
+    let response = app
+        .server()
+        .post(&format!("/api/users/{}/api-keys", user_id))
+        .add_header(name, value)
+        .json(&serde_json::json!({ "name": "script" }))
+        .await;
+
+    response.assert_status_forbidden();
+}
+
+#[tokio::test]
+async fn test_api_key_authenticates_requests() {
+    let app = TestApp::new().await;
+
+    let (_admin_id, admin_token) = app.create_admin().await;
+    let user_id = app.create_test_user("scripter", "pass12345", "user").await;
+    let (_key_id, key) = create_api_key(&app, &admin_token, user_id, "read").await;
+
+    // Header
+    let response = app
+        .server()
+        .get("/api/movies")
+        .add_header(
+            axum::http::HeaderName::from_static("x-api-key"),
+            axum::http::HeaderValue::from_str(&key).unwrap(),
+        )
+        .await;
+    response.assert_status_ok();
+
+    // Query parameter
+    let response = app
+        .server()
+        .get(&format!("/api/movies?apikey={}", key))
+        .await;
+    response.assert_status_ok();
+
+    // The key acts with its user's role
+    let response = app
+        .server()
+        .get(&format!("/api/system/jobs?apikey={}", key))
+        .await;
+    response.assert_status_forbidden();
+
+    // Unknown keys are rejected
+    let response = app.server().get("/api/movies?apikey=lcars_bogus").await;
+    response.assert_status_unauthorized();
+}
+
+#[tokio::test]
+async fn test_read_only_api_key_cannot_write() {
+    let app = TestApp::new().await;
+
+    let (admin_id, admin_token) = app.create_admin().await;
+    let (_key_id, read_key) = create_api_key(&app, &admin_token, admin_id, "read").await;
+
+    let response = app
+        .server()
+        .delete(&format!("/api/movies/9999?apikey={}", read_key))
+        .await;
+    response.assert_status_forbidden();
+
+    let (name, value) = app.auth_header(&admin_token);
+    let response = app
+        .server()
+        .post(&format!("/api/users/{}/api-keys", admin_id))
+        .add_header(name, value)
+        .json(&serde_json::json!({ "name": "writer", "scope": "full" }))
+        .await;
+    let full_key = response.json::<serde_json::Value>()["key"]
+        .as_str()
+        .unwrap()
+        .to_string();
+
+    // Gets past auth and reaches the handler
+    let response = app
+        .server()
+        .delete(&format!("/api/movies/9999?apikey={}", full_key))
+        .await;
+    response.assert_status_not_found();
+}
+
+#[tokio::test]
+async fn test_api_key_cannot_manage_credentials() {
+    let app = TestApp::new().await;
+
+    let (admin_id, admin_token) = app.create_admin().await;
+    let other_admin = app.create_test_user("other", "pass12345", "admin").await;
+    let (_key_id, key) = create_api_key(&app, &admin_token, admin_id, "full").await;
+
+    let requests = [
+        (Method::GET, "/api/auth/me".to_string()),
+        (Method::GET, "/api/auth/sessions".to_string()),
+        (Method::POST, "/api/auth/logout-all".to_string()),
+        (Method::POST, "/api/auth/2fa/setup".to_string()),
+        (Method::POST, "/api/auth/2fa/disable".to_string()),
+        (Method::POST, "/api/auth/2fa/recovery-codes".to_string()),
+        (Method::GET, "/api/users".to_string()),
+        (Method::PUT, format!("/api/users/{}", admin_id)),
+        (Method::POST, format!("/api/users/{}/api-keys", admin_id)),
+        (
+            Method::DELETE,
+            format!("/api/users/{}/two-factor", other_admin),
+        ),
+    ];
+    for (method, path) in requests {
+        let response = app
+            .server()
+            .method(method.clone(), &path)
+            .add_header(
+                axum::http::HeaderName::from_static("x-api-key"),
+                axum::http::HeaderValue::from_str(&key).unwrap(),
+            )
+            .json(&serde_json::json!({ "name": "minted", "password": "changed123" }))
+            .await;
+        assert_eq!(
+            response.status_code(),
+            axum::http::StatusCode::FORBIDDEN,
+            "{} {}",
+            method,
+            path
+        );
+    }
+
+    // Nothing was changed and no key was minted
+    let (name, value) = app.auth_header(&admin_token);
+    let response = app
+        .server()
+        .get(&format!("/api/users/{}/api-keys", admin_id))
+        .add_header(name.clone(), value.clone())
+        .await;
+    response.assert_status_ok();
+    assert_eq!(
+        response
+            .json::<serde_json::Value>()
+            .as_array()
+            .unwrap()
+            .len(),
+        1
+    );
+
+    // The same routes still work with a session
+    app.server()
+        .get("/api/auth/me")
+        .add_header(name, value)
+        .await
+        .assert_status_ok();
+}
+
+#[tokio::test]
+async fn test_api_key_use_is_logged() {
+    let app = TestApp::new().await;
+
+    let (admin_id, admin_token) = app.create_admin().await;
+    let (_key_id, key) = create_api_key(&app, &admin_token, admin_id, "full").await;
+
+    app.server()
+        .get(&format!("/api/movies?apikey={}", key))
+        .await
+        .assert_status_ok();
+
+    let db = app.db().lock().await;
+    let (message, metadata): (String, String) = db
+        .query_row(
+            "SELECT message, metadata FROM activity WHERE event_type = 'api_key_used'",
+            [],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+        .unwrap();
+    assert!(message.contains("'script'"));
+    let metadata: serde_json::Value = serde_json::from_str(&metadata).unwrap();
+    assert_eq!(metadata["api_key"], "script");
+    assert_eq!(metadata["path"], "/api/movies");
+}
+
+#[tokio::test]
+async fn test_delete_api_key() {
+    let app = TestApp::new().await;
+
+    let (_admin_id, admin_token) = app.create_admin().await;
+    let user_id = app.create_test_user("scripter", "pass12345", "user").await;
+    let (key_id, key) = create_api_key(&app, &admin_token, user_id, "read").await;
+
+    let (name, value) = app.auth_header(&admin_token);
+    let response = app
+        .server()
+        .delete(&format!("/api/users/{}/api-keys/{}", user_id, key_id))
+        .add_header(name.clone(), value.clone())
+        .await;
+    response.assert_status_ok();
+
+    let response = app
+        .server()
+        .get(&format!("/api/movies?apikey={}", key))
+        .await;
+    response.assert_status_unauthorized();
+
+    // Already gone
+    let response = app
+        .server()
+        .delete(&format!("/api/users/{}/api-keys/{}", user_id, key_id))
+        .add_header(name, value)
+        .await;
+    response.assert_status_not_found();
+}
@@ -74,7 +74,7 @@ impl ToBytes for PeerConnectionMessage {
     ) -> tokio::io::Result<()> {
         match self {
             PeerConnectionMessage::PierceFirewall(token) => {
-                buffer.write_u32_le(8).await?;
+                buffer.write_u32_le(5).await?;
                 buffer
                     .write_u8(ConnectionMessageCode::PierceFireWall as u8)
                     .await?;
@@ -131,3 +131,53 @@ impl ProtocolMessage for PeerConnectionMessage {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio_test::block_on;
+
+    fn roundtrip(message: PeerConnectionMessage) -> (Vec<u8>, PeerConnectionMessage) {
+        let mut data = Vec::new();
+        let mut buffer = BufWriter::new(&mut data);
+        block_on(async {
+            message.write_to_buf(&mut buffer).await.unwrap();
+            buffer.flush().await.unwrap();
+        });
+
+        let mut cursor = Cursor::new(data.as_slice());
+        let header = PeerConnectionMessage::check(&mut cursor).unwrap();
+        let parsed = PeerConnectionMessage::parse(&mut cursor, &header).unwrap();
+        (data, parsed)
+    }
+
+    #[test]
+    fn pierce_firewall() {
+        let (data, parsed) = roundtrip(PeerConnectionMessage::PierceFirewall(42));
+
+        assert_eq!(data, [5, 0, 0, 0, 0, 42, 0, 0, 0]);
+        assert!(matches!(parsed, PeerConnectionMessage::PierceFirewall(42)));
+    }
+
+    #[test]
+    fn peer_init() {
+        let (_, parsed) = roundtrip(PeerConnectionMessage::PeerInit {
+            username: "test".to_string(),
+            connection_type: ConnectionType::FileTransfer,
+            token: 7,
+        });
+
+        match parsed {
+            PeerConnectionMessage::PeerInit {
+                username,
+                connection_type,
+                token,
+            } => {
+                assert_eq!(username, "test");
+                assert_eq!(connection_type, ConnectionType::FileTransfer);
+                assert_eq!(token, 7);
+            }
+            other => panic!("expected PeerInit, got {:?}", other),
+        }
+    }
+}
@@ -9,8 +9,7 @@ use tokio::io::{AsyncWrite, AsyncWriteExt, BufWriter};
 
 use self::search::SearchRequest;
 
-mod branch;
-mod search;
+pub mod search;
 
 #[derive(Debug)]
 pub struct DistributedMessageHeader {
@@ -73,7 +72,9 @@ pub enum DistributedMessage {
     BranchLevel(u32),
     BranchRoot(String),
     ChildDepth(u32),
-    ServerSearchRequest,
+    /// A search request from the server, forwarded by branch roots to their
+    /// children.
+    ServerSearchRequest(SearchRequest),
     Unknown,
 }
 
@@ -89,21 +90,11 @@ impl ToBytes for DistributedMessage {
             }
             DistributedMessage::SearchRequest(req) => {
                 // Layout: unknown (4) + username string + ticket (4) + query string
-                let len = 1
-                    + 4
-                    + STR_LENGTH_PREFIX
-                    + req.username.len() as u32
-                    + 4
-                    + STR_LENGTH_PREFIX
-                    + req.query.len() as u32;
-                buffer.write_u32_le(len).await?;
+                buffer.write_u32_le(1 + req.body_len()).await?;
                 buffer
                     .write_u8(DistributedMessageCode::SearchRequest as u8)
                     .await?;
-                buffer.write_u32_le(req.unknown).await?;
-                write_string(&req.username, buffer).await?;
-                buffer.write_u32_le(req.ticket).await?;
-                write_string(&req.query, buffer).await?;
+                req.write_body(buffer).await?;
             }
             DistributedMessage::BranchLevel(level) => {
                 // Layout: code (1) + level (4)
@@ -130,9 +121,16 @@ impl ToBytes for DistributedMessage {
                     .await?;
                 buffer.write_u32_le(*depth).await?;
             }
-            DistributedMessage::ServerSearchRequest => {
-                write_empty_distributed_msg(DistributedMessageCode::ServerSearchRequest, buffer)
-                    .await?
+            DistributedMessage::ServerSearchRequest(req) => {
+                // Layout: code (1) + embedded code (1) + search request
+                buffer.write_u32_le(2 + req.body_len()).await?;
+                buffer
+                    .write_u8(DistributedMessageCode::ServerSearchRequest as u8)
+                    .await?;
+                buffer
+                    .write_u8(DistributedMessageCode::SearchRequest as u8)
+                    .await?;
+                req.write_body(buffer).await?;
             }
             DistributedMessage::Unknown => {
                 // Cannot serialize unknown messages
@@ -162,7 +160,15 @@ impl ProtocolMessage for DistributedMessage {
                 Ok(DistributedMessage::ChildDepth(src.get_u32_le()))
             }
             DistributedMessageCode::ServerSearchRequest => {
-                Ok(DistributedMessage::ServerSearchRequest)
+                match DistributedMessageCode::read(src) {
+                    DistributedMessageCode::SearchRequest => {
+                        SearchRequest::parse(src).map(DistributedMessage::ServerSearchRequest)
+                    }
+                    code => {
+                        error!("Unknown embedded distributed message type {:?}", code);
+                        Ok(DistributedMessage::Unknown)
+                    }
+                }
             }
             _ => {
                 error!("Unknown distributed message type {:?}", src);
@@ -180,3 +186,74 @@ async fn write_empty_distributed_msg(
     buffer.write_u8(code as u8).await?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio_test::block_on;
+
+    fn roundtrip(message: DistributedMessage) -> DistributedMessage {
+        let mut data = Vec::new();
+        let mut buffer = BufWriter::new(&mut data);
+        block_on(async {
+            message.write_to_buf(&mut buffer).await.unwrap();
+            buffer.flush().await.unwrap();
+        });
+
+        let mut cursor = Cursor::new(data.as_slice());
+        let header = DistributedMessage::check(&mut cursor).unwrap();
+        let parsed = DistributedMessage::parse(&mut cursor, &header).unwrap();
+        assert!(!cursor.has_remaining());
+        parsed
+    }
+
+    fn search_request() -> SearchRequest {
+        SearchRequest {
+            unknown: 49,
+            username: "searcher".to_string(),
+            ticket: 1234,
+            query: "artist album".to_string(),
+        }
+    }
+
+    #[test]
+    fn search_request_roundtrip() {
+        match roundtrip(DistributedMessage::SearchRequest(search_request())) {
+            DistributedMessage::SearchRequest(req) => {
+                assert_eq!(req.unknown, 49);
+                assert_eq!(req.username, "searcher");
+                assert_eq!(req.ticket, 1234);
+                assert_eq!(req.query, "artist album");
+            }
+            other => panic!("expected SearchRequest, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn server_search_request_roundtrip() {
+        match roundtrip(DistributedMessage::ServerSearchRequest(search_request())) {
+            DistributedMessage::ServerSearchRequest(req) => {
+                assert_eq!(req.username, "searcher");
+                assert_eq!(req.ticket, 1234);
+                assert_eq!(req.query, "artist album");
+            }
+            other => panic!("expected ServerSearchRequest, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn branch_roundtrip() {
+        assert!(matches!(
+            roundtrip(DistributedMessage::BranchLevel(3)),
+            DistributedMessage::BranchLevel(3)
+        ));
+        assert!(matches!(
+            roundtrip(DistributedMessage::BranchRoot("root".to_string())),
+            DistributedMessage::BranchRoot(root) if root == "root"
+        ));
+        assert!(matches!(
+            roundtrip(DistributedMessage::ChildDepth(0)),
+            DistributedMessage::ChildDepth(0)
+        ));
+    }
+}
@@ -1,6 +1,7 @@
-use crate::frame::{read_string, ParseBytes};
+use crate::frame::{read_string, write_string, ParseBytes, STR_LENGTH_PREFIX};
 use bytes::Buf;
 use std::io::Cursor;
+use tokio::io::{AsyncWrite, AsyncWriteExt, BufWriter};
 
 #[derive(Debug)]
 pub struct SearchRequest {
@@ -10,6 +11,27 @@ pub struct SearchRequest {
     pub query: String,
 }
 
+impl SearchRequest {
+    /// Length of the message body, without length prefix and code.
+    pub(crate) fn body_len(&self) -> u32 {
+        4 + STR_LENGTH_PREFIX
+            + self.username.len() as u32
+            + 4
+            + STR_LENGTH_PREFIX
+            + self.query.len() as u32
+    }
+
+    pub(crate) async fn write_body(
+        &self,
+        buffer: &mut BufWriter<impl AsyncWrite + Unpin + Send>,
+    ) -> tokio::io::Result<()> {
+        buffer.write_u32_le(self.unknown).await?;
+        write_string(&self.username, buffer).await?;
+        buffer.write_u32_le(self.ticket).await?;
+        write_string(&self.query, buffer).await
+    }
+}
+
 impl ParseBytes for SearchRequest {
     fn parse(src: &mut Cursor<&[u8]>) -> std::io::Result<Self> {
         let unknown = src.get_u32_le();
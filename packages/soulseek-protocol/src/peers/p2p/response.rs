@@ -58,10 +58,14 @@ impl ProtocolMessage for PeerResponse {
             PeerMessageCode::TransferRequest => {
                 TransferRequest::parse(src).map(PeerResponse::TransferRequest)
             }
-            PeerMessageCode::TransferReply => todo!(),
+            PeerMessageCode::TransferReply => {
+                TransferReply::parse(src).map(PeerResponse::TransferReply)
+            }
             PeerMessageCode::UploadPlacehold => todo!(),
             PeerMessageCode::QueueUpload => QueueUpload::parse(src).map(PeerResponse::QueueUpload),
-            PeerMessageCode::PlaceInQueueReply => todo!(),
+            PeerMessageCode::PlaceInQueueReply => {
+                PlaceInQueueReply::parse(src).map(PeerResponse::PlaceInQueueReply)
+            }
             PeerMessageCode::UploadFailed => {
                 UploadFailed::parse(src).map(PeerResponse::UploadFailed)
             }
@@ -69,7 +73,7 @@ impl ProtocolMessage for PeerResponse {
             PeerMessageCode::PlaceInQueueRequest => {
                 PlaceInQueueRequest::parse(src).map(PeerResponse::PlaceInQueueRequest)
             }
-            PeerMessageCode::UploadQueueNotification => todo!(),
+            PeerMessageCode::UploadQueueNotification => Ok(PeerResponse::UploadQueueNotification),
             PeerMessageCode::Unknown => {
                 warn!("Unknown message from peer : \n{:?}", src);
                 Ok(PeerResponse::Unknown)
@@ -162,8 +162,8 @@ impl ToBytes for Attribute {
         &self,
         buffer: &mut BufWriter<impl AsyncWrite + Unpin + Send>,
     ) -> tokio::io::Result<()> {
-        buffer.write_u32_le(self.attribute).await?;
         buffer.write_u32_le(self.place).await?;
+        buffer.write_u32_le(self.attribute).await?;
         Ok(())
     }
 }
@@ -182,7 +182,7 @@ mod test {
     use crate::{
         frame::{ParseBytes, ToBytes},
         peers::p2p::{
-            shared_directories::{Directory, File, SharedDirectories},
+            shared_directories::{Attribute, Directory, File, SharedDirectories},
             PeerMessageCode,
         },
     };
@@ -209,7 +209,7 @@ mod test {
         block_on(shared_dirs.write_to_buf(&mut buff)).unwrap();
         let mut cursor = std::io::Cursor::new(buff.buffer());
 
-        let len = cursor.get_u32_le();
+        let _len = cursor.get_u32_le();
         let code = cursor.get_u32_le();
         assert_eq!(code, PeerMessageCode::SharesReply as u32);
         assert_eq!(cursor.position(), 8);
@@ -218,4 +218,20 @@ mod test {
 
         assert_eq!(parse_result, shared_dirs);
     }
+
+    #[test]
+    fn attribute_roundtrip() {
+        let attribute = Attribute {
+            place: 1,
+            attribute: 240,
+        };
+
+        let mut vec = vec![];
+        let mut buff = BufWriter::new(&mut vec);
+        block_on(attribute.write_to_buf(&mut buff)).unwrap();
+        assert_eq!(buff.buffer(), [1, 0, 0, 0, 240, 0, 0, 0]);
+
+        let mut cursor = std::io::Cursor::new(buff.buffer());
+        assert_eq!(Attribute::parse(&mut cursor).unwrap(), attribute);
+    }
 }
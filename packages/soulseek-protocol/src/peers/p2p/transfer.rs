@@ -118,15 +118,21 @@ impl ToBytes for TransferRequest {
 #[derive(Debug, Serialize)]
 pub struct PlaceInQueueReply {
     pub filename: String,
-    pub place: String,
+    pub place: u32,
 }
 
 impl PlaceInQueueReply {
     pub fn new(filename: String, place: u32) -> Self {
-        Self {
-            filename,
-            place: place.to_string(),
-        }
+        Self { filename, place }
+    }
+}
+
+impl ParseBytes for PlaceInQueueReply {
+    fn parse(src: &mut Cursor<&[u8]>) -> std::io::Result<Self> {
+        let filename = read_string(src)?;
+        let place = src.get_u32_le();
+
+        Ok(Self { filename, place })
     }
 }
 
@@ -136,20 +142,14 @@ impl ToBytes for PlaceInQueueReply {
         &self,
         buffer: &mut BufWriter<impl AsyncWrite + Unpin + Send>,
     ) -> tokio::io::Result<()> {
-        // Note: According to protocol spec, 'place' is typically a u32, but the struct
-        // uses String. We serialize as two strings for now matching struct definition.
-        let len = 4
-            + STR_LENGTH_PREFIX
-            + self.filename.len() as u32
-            + STR_LENGTH_PREFIX
-            + self.place.len() as u32;
+        let len = 4 + STR_LENGTH_PREFIX + self.filename.len() as u32 + 4;
 
         buffer.write_u32_le(len).await?;
         buffer
             .write_u32_le(PeerMessageCode::PlaceInQueueReply as u32)
             .await?;
         write_string(&self.filename, buffer).await?;
-        write_string(&self.place, buffer).await?;
+        buffer.write_u32_le(self.place).await?;
 
         Ok(())
     }
@@ -157,7 +157,13 @@ impl ToBytes for PlaceInQueueReply {
 
 #[derive(Debug, Serialize)]
 pub struct UploadFailed {
-    filename: String,
+    pub filename: String,
+}
+
+impl UploadFailed {
+    pub fn new(filename: String) -> Self {
+        Self { filename }
+    }
 }
 
 impl ParseBytes for UploadFailed {
@@ -235,6 +241,12 @@ pub struct PlaceInQueueRequest {
     pub file_name: String,
 }
 
+impl PlaceInQueueRequest {
+    pub fn new(file_name: String) -> Self {
+        Self { file_name }
+    }
+}
+
 impl ParseBytes for PlaceInQueueRequest {
     fn parse(src: &mut Cursor<&[u8]>) -> std::io::Result<Self> {
         let file_name = read_string(src)?;
@@ -266,6 +278,30 @@ pub enum TransferReply {
     TransferRejected { ticket: u32, reason: String },
 }
 
+impl ParseBytes for TransferReply {
+    fn parse(src: &mut Cursor<&[u8]>) -> std::io::Result<Self> {
+        let ticket = src.get_u32_le();
+        let allowed = src.get_u8() == 1;
+
+        if allowed {
+            // The file size is only sent when replying to a download request
+            let file_size = if src.remaining() >= 8 {
+                src.get_u64_le()
+            } else {
+                0
+            };
+            Ok(TransferReply::TransferReplyOk { ticket, file_size })
+        } else {
+            let reason = if src.has_remaining() {
+                read_string(src)?
+            } else {
+                String::new()
+            };
+            Ok(TransferReply::TransferRejected { ticket, reason })
+        }
+    }
+}
+
 #[async_trait]
 impl ToBytes for TransferReply {
     async fn write_to_buf(
@@ -298,3 +334,96 @@ impl ToBytes for TransferReply {
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        peers::p2p::{request::PeerRequest, response::PeerResponse},
+        ProtocolMessage,
+    };
+    use tokio_test::block_on;
+
+    fn roundtrip(request: PeerRequest) -> PeerResponse {
+        let mut data = Vec::new();
+        let mut buffer = BufWriter::new(&mut data);
+        block_on(async {
+            request.write_to_buf(&mut buffer).await.unwrap();
+            buffer.flush().await.unwrap();
+        });
+
+        let mut cursor = Cursor::new(data.as_slice());
+        let header = PeerResponse::check(&mut cursor).unwrap();
+        assert_eq!(cursor.remaining(), header.message_len);
+        PeerResponse::parse(&mut cursor, &header).unwrap()
+    }
+
+    #[test]
+    fn transfer_reply_roundtrip() {
+        let ok = roundtrip(PeerRequest::TransferReply(TransferReply::TransferReplyOk {
+            ticket: 7,
+            file_size: 1024,
+        }));
+        assert!(matches!(
+            ok,
+            PeerResponse::TransferReply(TransferReply::TransferReplyOk {
+                ticket: 7,
+                file_size: 1024
+            })
+        ));
+
+        let rejected = roundtrip(PeerRequest::TransferReply(
+            TransferReply::TransferRejected {
+                ticket: 8,
+                reason: "Queued".to_string(),
+            },
+        ));
+        match rejected {
+            PeerResponse::TransferReply(TransferReply::TransferRejected { ticket, reason }) => {
+                assert_eq!(ticket, 8);
+                assert_eq!(reason, "Queued");
+            }
+            other => panic!("unexpected message: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn place_in_queue_reply_roundtrip() {
+        let reply = roundtrip(PeerRequest::PlaceInQueueReply(PlaceInQueueReply::new(
+            "Music\\song.flac".to_string(),
+            12,
+        )));
+        match reply {
+            PeerResponse::PlaceInQueueReply(reply) => {
+                assert_eq!(reply.filename, "Music\\song.flac");
+                assert_eq!(reply.place, 12);
+            }
+            other => panic!("unexpected message: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn upload_failed_roundtrip() {
+        let failed = roundtrip(PeerRequest::UploadFailed(UploadFailed::new(
+            "song.flac".to_string(),
+        )));
+        assert!(
+            matches!(failed, PeerResponse::UploadFailed(UploadFailed { filename }) if filename == "song.flac")
+        );
+    }
+
+    #[test]
+    fn queue_failed_roundtrip() {
+        let failed = roundtrip(PeerRequest::QueueFailed(QueueFailed::new(
+            "song.flac".to_string(),
+            "File not shared.".to_string(),
+        )));
+        match failed {
+            PeerResponse::QueueFailed(failed) => {
+                assert_eq!(failed.filename, "song.flac");
+                assert_eq!(failed.reason, "File not shared.");
+            }
+            other => panic!("unexpected message: {:?}", other),
+        }
+    }
+}
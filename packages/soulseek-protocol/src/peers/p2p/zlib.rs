@@ -1,23 +1,30 @@
 use crate::{debug, error};
 use bytes::Buf;
-use flate2::{write::ZlibEncoder, Compression, Decompress, FlushDecompress};
-use std::io::{Cursor, Write};
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use std::io::{Cursor, Read, Write};
+
+/// Upper bound on a decompressed peer message, so a small compressed payload
+/// cannot expand without limit (a zlib bomb).
+pub(crate) const MAX_DECOMPRESSED_BYTES: u64 = 64 * 1024 * 1024;
 
 pub(crate) fn decompress(src: &mut Cursor<&[u8]>) -> std::io::Result<Vec<u8>> {
-    // FIXME : 1032 is zlib max ratio
-    let mut data = Vec::with_capacity(src.remaining() * 4096);
-    let decompress_result =
-        Decompress::new(true).decompress_vec(src.chunk(), &mut data, FlushDecompress::Sync);
-
-    match decompress_result {
-        Ok(status) => {
-            debug!("Data successfully decompressed : {:?}", status)
-        }
-        Err(e) => {
-            error!("Decompress error: {}", e);
-            return Err(std::io::Error::from(std::io::ErrorKind::InvalidData));
-        }
-    };
+    // Decode into a growing buffer: compressed peer messages (search and
+    // shares replies) are often many times larger than their input.
+    let mut data = Vec::with_capacity(src.remaining() * 4);
+    let mut decoder = ZlibDecoder::new(src.chunk()).take(MAX_DECOMPRESSED_BYTES + 1);
+    if let Err(e) = decoder.read_to_end(&mut data) {
+        error!("Decompress error: {}", e);
+        return Err(std::io::Error::from(std::io::ErrorKind::InvalidData));
+    }
+    if data.len() as u64 > MAX_DECOMPRESSED_BYTES {
+        error!(
+            "Decompressed message exceeds {} bytes",
+            MAX_DECOMPRESSED_BYTES
+        );
+        return Err(std::io::Error::from(std::io::ErrorKind::InvalidData));
+    }
+    debug!("Data successfully decompressed : {} bytes", data.len());
+    src.advance(src.remaining());
 
     Ok(data)
 }
@@ -36,7 +43,7 @@ mod test {
         frame::ToBytes,
         peers::p2p::{
             shared_directories::{Directory, File, SharedDirectories},
-            zlib::{compress, decompress},
+            zlib::{compress, decompress, MAX_DECOMPRESSED_BYTES},
             PeerMessageCode,
         },
     };
@@ -49,7 +56,7 @@ mod test {
     fn should_decompress() {
         let data = vec![1, 2, 3, 4];
         let out = &mut vec![];
-        let cursor = Cursor::new(data.as_slice());
+        let _cursor = Cursor::new(data.as_slice());
         let mut message_buffer = BufWriter::new(out);
 
         block_on(async { message_buffer.write_all(data.as_slice()).await.unwrap() });
@@ -99,4 +106,16 @@ mod test {
             ]
         );
     }
+
+    #[test]
+    fn should_reject_oversized_output() {
+        let data = vec![0; MAX_DECOMPRESSED_BYTES as usize + 1];
+        let compressed_data = compress(&data).unwrap();
+        assert!(compressed_data.len() < 1024 * 1024);
+
+        let mut cursor = Cursor::new(compressed_data.as_slice());
+        let err = decompress(&mut cursor).unwrap_err();
+
+        assert_eq!(err.kind(), std::io::ErrorKind::InvalidData);
+    }
 }
@@ -1,11 +1,29 @@
-use crate::{frame::ParseBytes, Deserialize, Serialize};
+use crate::{
+    frame::ParseBytes,
+    peers::distributed::{search::SearchRequest, DistributedMessageCode},
+    Deserialize, Serialize,
+};
 use bytes::Buf;
 use std::io::Cursor;
 
+/// A distributed message the server sends to branch roots, to be answered and
+/// forwarded down the distributed network.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct EmbeddedDistributedMessage {
-    code: u8,
-    message: Vec<u8>,
+    pub code: u8,
+    pub message: Vec<u8>,
+}
+
+impl EmbeddedDistributedMessage {
+    /// Parse the embedded message as a search request, if it is one.
+    pub fn search_request(&self) -> Option<SearchRequest> {
+        match DistributedMessageCode::from(self.code) {
+            DistributedMessageCode::SearchRequest => {
+                SearchRequest::parse(&mut Cursor::new(self.message.as_slice())).ok()
+            }
+            _ => None,
+        }
+    }
 }
 
 impl ParseBytes for EmbeddedDistributedMessage {
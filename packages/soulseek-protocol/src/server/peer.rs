@@ -183,8 +183,8 @@ impl ToBytes for PeerConnectionTicket {
         buffer
             .write_u32_le(MessageCode::CantConnectToPeer as u32)
             .await?;
-        write_string(&self.username, buffer).await?;
         buffer.write_u32_le(self.token).await?;
+        write_string(&self.username, buffer).await?;
 
         Ok(())
     }
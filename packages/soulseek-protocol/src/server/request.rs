@@ -529,8 +529,13 @@ pub(crate) async fn write_u32_msg(
 mod tests {
     use crate::{
         frame::ToBytes,
+        message_common::ConnectionType,
         server::{
-            chat::SayInChat, login::LoginRequest, request::ServerRequest, room::UserRoomEvent,
+            chat::SayInChat,
+            login::LoginRequest,
+            peer::{PeerConnectionTicket, RequestConnectionToPeer},
+            request::ServerRequest,
+            room::UserRoomEvent,
             shares::SharedFolderAndFiles,
         },
     };
@@ -751,18 +756,47 @@ mod tests {
     }
 
     #[test]
-    #[ignore = "Assertion values may be incorrect in upstream"]
     fn shared_folders() {
         let shared_folders =
             ServerRequest::SharedFolderAndFiles(SharedFolderAndFiles { dirs: 2, files: 3 });
 
         let data = write_to_buff_blocking(shared_folders);
 
-        assert_eq!(&data[8..], [4, 0, 0, 0, 47, 0, 0, 0]);
+        assert_eq!(&data[..4], [12, 0, 0, 0]);
+        assert_eq!(&data[8..], [2, 0, 0, 0, 3, 0, 0, 0]);
+    }
+
+    #[test]
+    fn connect_to_peer() {
+        let connect_to_peer = ServerRequest::ConnectToPeer(RequestConnectionToPeer {
+            token: 7,
+            username: "test".to_string(),
+            connection_type: ConnectionType::PeerToPeer,
+        });
+
+        let data = write_to_buff_blocking(connect_to_peer);
+
+        assert_eq!(&data[..4], [21, 0, 0, 0]);
+        assert_eq!(
+            &data[8..],
+            b"\x07\x00\x00\x00\x04\x00\x00\x00test\x01\x00\x00\x00P"
+        );
+    }
+
+    #[test]
+    fn cant_connect_to_peer() {
+        let cant_connect = ServerRequest::CantConnectToPeer(PeerConnectionTicket {
+            token: 7,
+            username: "test".to_string(),
+        });
+
+        let data = write_to_buff_blocking(cant_connect);
+
+        assert_eq!(&data[..4], [16, 0, 0, 0]);
+        assert_eq!(&data[8..], b"\x07\x00\x00\x00\x04\x00\x00\x00test");
     }
 
     #[test]
-    #[ignore = "Not yet implemented"]
     fn branch_level() {
         let branch_level = ServerRequest::BranchLevel(0);
 
@@ -772,7 +806,6 @@ mod tests {
     }
 
     #[test]
-    #[ignore = "Not yet implemented"]
     fn branch_root() {
         let branch_root = ServerRequest::BranchRoot("oknozor".to_string());
 
@@ -781,6 +814,15 @@ mod tests {
         assert_eq!(&data[8..], [7, 0, 0, 0, 111, 107, 110, 111, 122, 111, 114]);
     }
 
+    #[test]
+    fn child_depth() {
+        let child_depth = ServerRequest::ChildDepth(2);
+
+        let data = write_to_buff_blocking(child_depth);
+
+        assert_eq!(&data[8..], [2, 0, 0, 0]);
+    }
+
     #[test]
     #[ignore = "Not yet implemented"]
     fn toggle_private_rooms() {
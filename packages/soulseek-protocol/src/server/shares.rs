@@ -19,7 +19,7 @@ impl ToBytes for SharedFolderAndFiles {
         &self,
         buffer: &mut BufWriter<impl AsyncWrite + Unpin + Send>,
     ) -> tokio::io::Result<()> {
-        buffer.write_u32_le(8).await?;
+        buffer.write_u32_le(12).await?;
         buffer
             .write_u32_le(MessageCode::SharedFoldersAndFiles as u32)
             .await?;